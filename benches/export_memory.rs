@@ -0,0 +1,141 @@
+//! Peak heap use of streamed exports. Run with
+//! `cargo bench --bench export_memory`; exits non-zero if exporting 10M rows
+//! needs noticeably more memory than 100k rows.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use vscode_latency_monitor::export::{collect_metadata_keys, CsvExporter, ExportRow, JsonExporter};
+
+/// Tracks live and peak heap bytes.
+struct PeakAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+const SMALL: u64 = 100_000;
+const LARGE: u64 = 10_000_000;
+
+/// Allowed growth in peak heap between the small and large export
+const SLACK_BYTES: usize = 1024 * 1024;
+
+const METADATA: &str = r#"{"pid":4242,"process":{"name":"code","cpu":12.5},"args":["--type=renderer"]}"#;
+
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Json,
+    Csv,
+    CsvGzip,
+}
+
+/// Export `rows` synthetic events and return the peak heap bytes above the
+/// baseline at the start.
+fn run_export(format: Format, rows: u64) -> usize {
+    let baseline = LIVE.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let started = Instant::now();
+
+    let sink = io::BufWriter::new(io::sink());
+    let written = match format {
+        Format::Json => {
+            let mut json = JsonExporter::new(sink).unwrap();
+            for_each_row(rows, |row| json.write_row(row).unwrap());
+            json.finish().unwrap()
+        }
+        Format::Csv => export_csv(sink, rows),
+        Format::CsvGzip => {
+            let mut encoder = flate2::write::GzEncoder::new(sink, flate2::Compression::default());
+            let written = export_csv(&mut encoder, rows);
+            encoder.finish().unwrap().flush().unwrap();
+            written
+        }
+    };
+    assert_eq!(written, rows);
+
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(baseline);
+    let elapsed = started.elapsed();
+    println!(
+        "{:<8} {:>10} rows  {:>8.2?}  {:>10.0} rows/s  peak heap {:>8} KiB",
+        format!("{:?}", format),
+        rows,
+        elapsed,
+        rows as f64 / elapsed.as_secs_f64(),
+        peak / 1024
+    );
+    peak
+}
+
+fn export_csv<W: Write>(writer: W, rows: u64) -> u64 {
+    let mut keys = BTreeSet::new();
+    collect_metadata_keys(Some(METADATA), &mut keys);
+    let mut csv = CsvExporter::new(writer, b',', keys).unwrap();
+    for_each_row(rows, |row| csv.write_row(row).unwrap());
+    csv.finish().unwrap()
+}
+
+/// Feed `rows` events through `on_row`, reusing one description buffer the
+/// way database rows are borrowed in place.
+fn for_each_row(rows: u64, mut on_row: impl FnMut(&ExportRow)) {
+    let mut description = String::new();
+    for id in 0..rows as i64 {
+        description.clear();
+        std::fmt::Write::write_fmt(&mut description, format_args!("Command execution #{}", id)).unwrap();
+        on_row(&ExportRow {
+            id,
+            uid: Some("0f3a9c2b7d8e4f6a1b2c3d4e5f6a7b8c"),
+            timestamp: "2025-10-15T12:34:56.789+00:00",
+            component_type: "VSCode",
+            event_source: "CommandExecution",
+            event_kind: Some("command_run"),
+            duration_us: 1_000 + id % 250_000,
+            description: &description,
+            metadata: Some(METADATA),
+            created_at: Some("2025-10-15 12:34:57"),
+        });
+    }
+}
+
+fn main() {
+    let mut failed = false;
+    for format in [Format::Json, Format::Csv, Format::CsvGzip] {
+        let small = run_export(format, SMALL);
+        let large = run_export(format, LARGE);
+        if large > small + SLACK_BYTES {
+            eprintln!(
+                "{:?}: peak heap grew from {} KiB to {} KiB between {} and {} rows",
+                format,
+                small / 1024,
+                large / 1024,
+                SMALL,
+                LARGE
+            );
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
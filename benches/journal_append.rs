@@ -0,0 +1,36 @@
+//! Spill journal append throughput per I/O backend. Run with
+//! `cargo bench --features io-uring --bench journal_append` to include the
+//! io_uring path.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use vscode_latency_monitor::file_io::{Appender, IoBackend};
+
+/// Roughly one spill of serialized events
+const BATCH_SIZES: &[usize] = &[4 * 1024, 64 * 1024, 1024 * 1024];
+
+fn journal_append(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("vlm-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut group = c.benchmark_group("journal_append");
+    for &size in BATCH_SIZES {
+        let batch = vec![b'x'; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        for backend in [IoBackend::Std, IoBackend::IoUring] {
+            let path = dir.join(format!("{:?}-{}.jsonl", backend, size));
+            let mut appender = Appender::create(&path, backend).unwrap();
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", backend), size), &batch, |b, batch| {
+                b.iter(|| appender.write_all(batch).unwrap())
+            });
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, journal_append);
+criterion_main!(benches);
@@ -0,0 +1,188 @@
+//! Throughput of each stage of the event pipeline, from building an event to
+//! exporting it. Run with `cargo bench --bench pipeline`; compare runs with
+//! `--save-baseline` / `--baseline` to get before/after numbers for a change.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+use vscode_latency_monitor::config::WriteBufferConfig;
+use vscode_latency_monitor::event_queue::{EventQueue, LoadSheddingConfig, MemoryBudgetConfig, OverflowPolicy};
+use vscode_latency_monitor::file_io::IoBackend;
+use vscode_latency_monitor::progress::Progress;
+use vscode_latency_monitor::{ComponentType, EventSource, LatencyEvent, MetricsStorage, TimeRange};
+
+const BATCH_SIZES: &[usize] = &[100, 1_000, 10_000];
+
+/// Events in the database used by the query and export benchmarks
+const SEEDED_EVENTS: usize = 50_000;
+
+fn sample_event(i: usize) -> LatencyEvent {
+    let (component, source) = match i % 4 {
+        0 => (ComponentType::VSCode, EventSource::CommandExecution),
+        1 => (ComponentType::GitHubCopilot, EventSource::NetworkRequest),
+        2 => (ComponentType::LocalModel, EventSource::ModelProcess),
+        _ => (ComponentType::System, EventSource::ProcessMonitor),
+    };
+    LatencyEvent::new(
+        component,
+        source,
+        Duration::from_micros(500 + (i as u64 * 7919) % 250_000),
+        format!("Bench event #{}", i),
+    )
+    .with_metadata(serde_json::json!({ "pid": 4242, "process": { "name": "code", "cpu": 12.5 } }))
+}
+
+fn bench_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vlm-pipeline-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A fresh database with the monitor's batching writer enabled.
+fn open_storage(rt: &Runtime, path: PathBuf) -> MetricsStorage {
+    let _ = std::fs::remove_file(&path);
+    rt.block_on(async {
+        MetricsStorage::new(&path)
+            .await
+            .unwrap()
+            .with_write_buffer(&WriteBufferConfig::default())
+    })
+}
+
+fn event_creation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("event_creation");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("new_with_metadata", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            sample_event(i)
+        })
+    });
+    group.finish();
+}
+
+fn channel_throughput(c: &mut Criterion) {
+    let dir = bench_dir();
+    let mut group = c.benchmark_group("channel_throughput");
+
+    for &size in BATCH_SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+        let queue = EventQueue::new(
+            &MemoryBudgetConfig::default(),
+            &LoadSheddingConfig::default(),
+            size,
+            OverflowPolicy::default(),
+            dir.join(format!("spill-{}.jsonl", size)),
+            IoBackend::Std,
+        )
+        .unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched(
+                || (0..size).map(sample_event).collect::<Vec<_>>(),
+                |events| {
+                    for event in events {
+                        queue.send(event).unwrap();
+                    }
+                    while queue.try_recv().is_ok() {}
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn batch_insert(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = bench_dir();
+    let mut group = c.benchmark_group("batch_insert");
+    group.sample_size(20);
+
+    for &size in BATCH_SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+        let storage = open_storage(&rt, dir.join(format!("insert-{}.db", size)));
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched(
+                || (0..size).map(sample_event).collect::<Vec<_>>(),
+                |events| {
+                    rt.block_on(async {
+                        for event in &events {
+                            storage.store_event(event).await.unwrap();
+                        }
+                        storage.flush().await.unwrap();
+                    })
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Storage holding `SEEDED_EVENTS` events, for the read-side benchmarks.
+fn seeded_storage(rt: &Runtime, dir: &std::path::Path) -> MetricsStorage {
+    let storage = open_storage(rt, dir.join("seeded.db"));
+    rt.block_on(async {
+        for i in 0..SEEDED_EVENTS {
+            storage.store_event(&sample_event(i)).await.unwrap();
+        }
+        storage.flush().await.unwrap();
+    });
+    storage
+}
+
+fn aggregate_query(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = bench_dir();
+    let storage = seeded_storage(&rt, &dir);
+
+    let mut group = c.benchmark_group("aggregate_query");
+    group.sample_size(20);
+    group.bench_function("performance_metrics", |b| {
+        b.iter(|| rt.block_on(storage.get_performance_metrics_in(&TimeRange::default())).unwrap())
+    });
+    group.bench_function("count_by_kind", |b| {
+        b.iter(|| rt.block_on(storage.count_events_by_kind(&TimeRange::default())).unwrap())
+    });
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn export_throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = bench_dir();
+    let storage = seeded_storage(&rt, &dir);
+
+    let mut group = c.benchmark_group("export_throughput");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(SEEDED_EVENTS as u64));
+    group.bench_function("json", |b| {
+        b.iter(|| rt.block_on(storage.export_json(std::io::sink(), &TimeRange::default(), &Progress::hidden())).unwrap())
+    });
+    group.bench_function("csv", |b| {
+        b.iter(|| rt.block_on(storage.export_csv(std::io::sink(), &TimeRange::default(), b',', &Progress::hidden())).unwrap())
+    });
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+criterion_group!(
+    benches,
+    event_creation,
+    channel_throughput,
+    batch_insert,
+    aggregate_query,
+    export_throughput
+);
+criterion_main!(benches);
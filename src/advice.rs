@@ -0,0 +1,117 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::filter::EventFilter;
+use crate::models::{ComponentType, EventKind};
+use crate::storage::MetricsStorage;
+use crate::timerange::TimeRange;
+
+/// When background jobs should hold off, configured as `[advice]` and
+/// answered by `GET /api/advice/defer`. A job is told to defer while you
+/// are using the component (a command ran within `active_within_secs`) or
+/// while its interactive latency is already over `threshold_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdviceConfig {
+    /// p95 of interactive events over `window_secs` above which jobs defer
+    pub threshold_ms: u64,
+    pub window_secs: u64,
+    /// Defer while a command ran this recently, however fast it was
+    pub defer_when_active: bool,
+    pub active_within_secs: u64,
+    /// Suggested wait before asking again
+    pub retry_after_secs: u64,
+}
+
+impl Default for AdviceConfig {
+    fn default() -> Self {
+        Self {
+            threshold_ms: 200,
+            window_secs: 300,
+            defer_when_active: true,
+            active_within_secs: 120,
+            retry_after_secs: 120,
+        }
+    }
+}
+
+/// Whether a heavy job should wait, and why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeferAdvice {
+    pub defer: bool,
+    pub component: ComponentType,
+    /// Why the job should wait; empty when it can go ahead
+    pub reasons: Vec<String>,
+    /// Interactive events in the window, excluding process samples
+    pub events: u64,
+    pub p95_ms: Option<u64>,
+    pub threshold_ms: u64,
+    pub last_activity: Option<DateTime<Utc>>,
+    /// Set when deferring: how long to wait before asking again
+    pub retry_after_secs: Option<u64>,
+}
+
+/// Judge from `component`'s recent events whether now is a bad time for
+/// heavy background work. `threshold_ms` overrides the configured one.
+pub async fn defer_advice(
+    storage: &MetricsStorage,
+    config: &AdviceConfig,
+    component: ComponentType,
+    threshold_ms: Option<u64>,
+) -> Result<DeferAdvice> {
+    let threshold_ms = threshold_ms.unwrap_or(config.threshold_ms);
+    let now = Utc::now();
+
+    // Process samples time the sampler, not the editor
+    let interactive = EventFilter::parse(&format!(
+        "component = {} AND kind != {}",
+        component.as_str(),
+        EventKind::ProcessSample.as_str()
+    ))?;
+    let range = TimeRange {
+        since: Some(now - chrono::Duration::seconds(config.window_secs.max(1) as i64)),
+        until: None,
+        working_hours: None,
+        filter: Some(interactive),
+        include_test_data: false,
+    };
+    let metrics = storage
+        .get_performance_metrics_in(&range)
+        .await?
+        .into_iter()
+        .find(|metrics| metrics.component == component);
+
+    let commands = EventFilter::parse(&format!(
+        "component = {} AND kind = {}",
+        component.as_str(),
+        EventKind::CommandRun.as_str()
+    ))?
+    .to_sql()?;
+    let last_activity = storage.query_events(Some(&commands), 1).await?.first().map(|event| event.timestamp);
+
+    let mut reasons = Vec::new();
+    let p95_ms = metrics.as_ref().filter(|m| m.total_events > 0).map(|m| m.p95_duration_ms);
+    if let Some(p95) = p95_ms.filter(|p95| *p95 > threshold_ms) {
+        reasons.push(format!(
+            "{} p95 is {}ms over the last {}s, above {}ms",
+            component, p95, config.window_secs, threshold_ms
+        ));
+    }
+    let active_since = now - chrono::Duration::seconds(config.active_within_secs as i64);
+    if let Some(at) = last_activity.filter(|at| config.defer_when_active && *at >= active_since) {
+        reasons.push(format!("{} was used {}s ago", component, (now - at).num_seconds().max(0)));
+    }
+
+    let defer = !reasons.is_empty();
+    Ok(DeferAdvice {
+        defer,
+        component,
+        reasons,
+        events: metrics.map(|m| m.total_events).unwrap_or(0),
+        p95_ms,
+        threshold_ms,
+        last_activity,
+        retry_after_secs: defer.then_some(config.retry_after_secs),
+    })
+}
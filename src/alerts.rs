@@ -0,0 +1,1165 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::email::{self, EmailMessage};
+use crate::filter::EventFilter;
+use crate::lifecycle::LifecycleKind;
+use crate::models::{AlertHistoryEntry, ComponentType, EventKind, LatencyEvent, MaintenanceWindow};
+use crate::notifications::{self, NotificationChannel, Notifier};
+use crate::report;
+use crate::secrets::Secrets;
+use crate::storage::{self, MetricsStorage};
+use crate::timerange::TimeRange;
+use crate::webhook;
+
+/// How often rules are re-evaluated without new events, so alerts resolve
+/// once their window drains.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Events examined by `alerts test`.
+const TEST_EVENT_LIMIT: u32 = 100_000;
+
+/// Built-in rule extension host crash loops are alerted and recorded under.
+pub const CRASH_LOOP_RULE: &str = "extension_host_crash_loop";
+
+/// A threshold rule, configured as `[[alerts]]`, e.g. "GitHubCopilot p95_ms
+/// > 2000 over 300s".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    /// Only events from this component count toward the rule
+    #[serde(default)]
+    pub component: Option<ComponentType>,
+    /// Narrow the events further, in the `--filter` syntax
+    #[serde(default)]
+    pub filter: Option<String>,
+    pub metric: AlertMetric,
+    #[serde(default)]
+    pub condition: Comparison,
+    pub threshold: f64,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Events needed in the window before the rule can fire
+    #[serde(default = "default_min_events")]
+    pub min_events: usize,
+    /// Minimum time between two firings of the same rule
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    #[serde(default = "default_actions")]
+    pub actions: Vec<AlertAction>,
+}
+
+fn default_window_secs() -> u64 {
+    300
+}
+
+fn default_min_events() -> usize {
+    1
+}
+
+fn default_cooldown_secs() -> u64 {
+    600
+}
+
+fn default_actions() -> Vec<AlertAction> {
+    vec![AlertAction::Log]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    AvgMs,
+    P50Ms,
+    P95Ms,
+    P99Ms,
+    MaxMs,
+    /// Events in the window
+    Count,
+    /// Fraction of events that failed, 0.0..=1.0
+    ErrorRate,
+    /// Average `metadata.cpu_percent` of process samples
+    CpuPercent,
+    /// Average `metadata.memory_mb` of process samples
+    MemoryMb,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[default]
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertAction {
+    /// Warning in the monitor log
+    Log,
+    /// Broadcast to logged-in terminals with `wall`
+    Wall,
+    /// POST to webhooks subscribed to `alert.firing` / `alert.resolved`
+    Webhook,
+    /// Desktop notification (notify-send / osascript)
+    Desktop,
+    /// Mail to `email.alert_recipients`
+    Email,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// A rule changing state, as delivered to actions.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertNotification {
+    pub rule: String,
+    pub state: AlertState,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+    pub at: DateTime<Utc>,
+    /// Set when this message sums up several held-back notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<DigestSummary>,
+}
+
+/// Digest windows per channel in seconds, configured as `[alert_digest]`.
+/// While a rule's window is open on a channel, only its first notification
+/// goes out at once; the rest are summed up in one message when the window
+/// closes, so a flapping threshold doesn't flood the channel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertDigestConfig {
+    pub log: Option<u64>,
+    pub wall: Option<u64>,
+    pub webhook: Option<u64>,
+    pub desktop: Option<u64>,
+    pub email: Option<u64>,
+}
+
+impl AlertDigestConfig {
+    fn window(&self, action: AlertAction) -> Option<chrono::Duration> {
+        let secs = match action {
+            AlertAction::Log => self.log,
+            AlertAction::Wall => self.wall,
+            AlertAction::Webhook => self.webhook,
+            AlertAction::Desktop => self.desktop,
+            AlertAction::Email => self.email,
+        }?;
+        (secs > 0).then(|| chrono::Duration::seconds(secs as i64))
+    }
+}
+
+/// What a digest held back: how often the rule fired and resolved, and the
+/// range of metric values it reported.
+#[derive(Debug, Clone, Serialize)]
+pub struct DigestSummary {
+    pub since: DateTime<Utc>,
+    pub firings: u32,
+    pub resolutions: u32,
+    pub min_value: f64,
+    pub max_value: f64,
+}
+
+/// An open digest window for one rule on one channel.
+struct PendingDigest {
+    rule: AlertRule,
+    closes_at: DateTime<Utc>,
+    summary: DigestSummary,
+    /// Latest held-back notification; `None` if nothing was held back
+    last: Option<AlertNotification>,
+}
+
+impl PendingDigest {
+    fn hold(&mut self, notification: &AlertNotification) {
+        let summary = &mut self.summary;
+        match notification.state {
+            AlertState::Firing => summary.firings += 1,
+            AlertState::Resolved => summary.resolutions += 1,
+        }
+        summary.min_value = summary.min_value.min(notification.value);
+        summary.max_value = summary.max_value.max(notification.value);
+        self.last = Some(notification.clone());
+    }
+
+    fn into_notification(self) -> Option<(AlertRule, AlertNotification)> {
+        let mut notification = self.last?;
+        let summary = self.summary;
+        notification.message = format!(
+            "Alert '{}': fired {} and resolved {} time(s) since {}, {} between {:.1} and {:.1}; now {}",
+            self.rule.name,
+            summary.firings,
+            summary.resolutions,
+            summary.since.format("%H:%M UTC"),
+            self.rule.metric.as_str(),
+            summary.min_value,
+            summary.max_value,
+            match notification.state {
+                AlertState::Firing => "firing",
+                AlertState::Resolved => "resolved",
+            }
+        );
+        notification.digest = Some(summary);
+        Some((self.rule, notification))
+    }
+}
+
+/// Open digest windows, keyed by rule name and channel.
+#[derive(Default)]
+struct Digests {
+    pending: HashMap<(String, AlertAction), PendingDigest>,
+}
+
+impl Digests {
+    /// Whether `notification` goes out on `action` now. If not, it is held
+    /// for the digest.
+    fn admit(
+        &mut self,
+        config: &AlertDigestConfig,
+        rule: &AlertRule,
+        action: AlertAction,
+        notification: &AlertNotification,
+    ) -> bool {
+        let Some(window) = config.window(action) else {
+            return true;
+        };
+
+        match self.pending.get_mut(&(rule.name.clone(), action)) {
+            Some(pending) => {
+                pending.hold(notification);
+                false
+            }
+            None => {
+                self.pending.insert(
+                    (rule.name.clone(), action),
+                    PendingDigest {
+                        rule: rule.clone(),
+                        closes_at: notification.at + window,
+                        summary: DigestSummary {
+                            since: notification.at,
+                            firings: 0,
+                            resolutions: 0,
+                            min_value: f64::INFINITY,
+                            max_value: f64::NEG_INFINITY,
+                        },
+                        last: None,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Close windows that have run out, returning the digests to send.
+    fn close_due(&mut self, now: DateTime<Utc>) -> Vec<(AlertAction, AlertRule, AlertNotification)> {
+        let due: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.closes_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        due.into_iter()
+            .filter_map(|key| {
+                let pending = self.pending.remove(&key)?;
+                let (rule, notification) = pending.into_notification()?;
+                Some((key.1, rule, notification))
+            })
+            .collect()
+    }
+}
+
+impl Comparison {
+    pub fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+        }
+    }
+}
+
+impl AlertMetric {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlertMetric::AvgMs => "avg_ms",
+            AlertMetric::P50Ms => "p50_ms",
+            AlertMetric::P95Ms => "p95_ms",
+            AlertMetric::P99Ms => "p99_ms",
+            AlertMetric::MaxMs => "max_ms",
+            AlertMetric::Count => "count",
+            AlertMetric::ErrorRate => "error_rate",
+            AlertMetric::CpuPercent => "cpu_percent",
+            AlertMetric::MemoryMb => "memory_mb",
+        }
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(component) = self.component {
+            write!(f, "{:?} ", component)?;
+        }
+        write!(
+            f,
+            "{} {} {} over {}s",
+            self.metric.as_str(),
+            self.condition.as_str(),
+            self.threshold,
+            self.window_secs
+        )?;
+        if let Some(filter) = &self.filter {
+            write!(f, " where {}", filter)?;
+        }
+        Ok(())
+    }
+}
+
+/// The parts of an event a rule aggregates over.
+#[derive(Debug, Clone)]
+struct Sample {
+    at: DateTime<Utc>,
+    duration_us: u64,
+    failed: bool,
+    cpu_percent: Option<f64>,
+    memory_mb: Option<f64>,
+}
+
+impl Sample {
+    fn from_event(event: &LatencyEvent) -> Self {
+        let metadata = &event.metadata;
+        Self {
+            at: event.timestamp,
+            duration_us: event.duration_us(),
+            failed: metadata.get("success").and_then(|s| s.as_bool()) == Some(false)
+                || metadata.get("error").is_some_and(|e| !e.is_null()),
+            cpu_percent: metadata.get("cpu_percent").and_then(|v| v.as_f64()),
+            memory_mb: metadata.get("memory_mb").and_then(|v| v.as_f64()),
+        }
+    }
+}
+
+/// Why an evaluation did or didn't change a rule's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Fewer events in the window than `min_events`
+    TooFewEvents,
+    /// No event in the window carries the metric, e.g. no CPU samples
+    NoValue,
+    /// The condition doesn't hold
+    WithinThreshold,
+    /// The condition holds, but the rule fired less than `cooldown_secs` ago
+    CoolingDown,
+    Fired,
+    /// The condition still holds and the rule is already firing
+    StillFiring,
+    Resolved,
+}
+
+/// One evaluation of a rule.
+struct Evaluation {
+    value: Option<f64>,
+    outcome: Outcome,
+    /// Set when the rule changed state
+    notification: Option<AlertNotification>,
+}
+
+/// A rule plus its sliding window and firing state.
+struct RuleState {
+    rule: AlertRule,
+    filter: Option<EventFilter>,
+    samples: VecDeque<Sample>,
+    firing: bool,
+    last_fired: Option<DateTime<Utc>>,
+}
+
+impl RuleState {
+    fn new(rule: AlertRule) -> Result<Self> {
+        let filter = rule
+            .filter
+            .as_deref()
+            .map(EventFilter::parse)
+            .transpose()
+            .map_err(|e| anyhow!("Alert '{}' has an invalid filter: {}", rule.name, e))?;
+
+        Ok(Self {
+            rule,
+            filter,
+            samples: VecDeque::new(),
+            firing: false,
+            last_fired: None,
+        })
+    }
+
+    fn applies_to(&self, event: &LatencyEvent) -> bool {
+        !event.is_test_data()
+            && self.rule.component.is_none_or(|c| c == event.component_type)
+            && self.filter.as_ref().is_none_or(|f| f.matches(event))
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - chrono::Duration::seconds(self.rule.window_secs as i64);
+        while self.samples.front().is_some_and(|s| s.at < cutoff) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Current metric value, or `None` when the window can't support one.
+    fn value(&self) -> Option<f64> {
+        if self.samples.len() < self.rule.min_events.max(1) {
+            return None;
+        }
+
+        let mut durations: Vec<u64> = self.samples.iter().map(|s| s.duration_us).collect();
+        durations.sort_unstable();
+        let ms = |us: u64| us as f64 / 1000.0;
+        let average = |values: Vec<f64>| {
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+
+        match self.rule.metric {
+            AlertMetric::AvgMs => average(durations.iter().map(|d| ms(*d)).collect()),
+            AlertMetric::P50Ms => Some(ms(storage::percentile(&durations, 0.50))),
+            AlertMetric::P95Ms => Some(ms(storage::percentile(&durations, 0.95))),
+            AlertMetric::P99Ms => Some(ms(storage::percentile(&durations, 0.99))),
+            AlertMetric::MaxMs => durations.last().map(|d| ms(*d)),
+            AlertMetric::Count => Some(self.samples.len() as f64),
+            AlertMetric::ErrorRate => {
+                let failed = self.samples.iter().filter(|s| s.failed).count();
+                Some(failed as f64 / self.samples.len() as f64)
+            }
+            AlertMetric::CpuPercent => average(self.samples.iter().filter_map(|s| s.cpu_percent).collect()),
+            AlertMetric::MemoryMb => average(self.samples.iter().filter_map(|s| s.memory_mb).collect()),
+        }
+    }
+
+    /// Re-evaluate and return a notification if the rule changed state.
+    fn evaluate(&mut self, now: DateTime<Utc>) -> Option<AlertNotification> {
+        self.step(now).notification
+    }
+
+    /// Re-evaluate, keeping why the rule did or didn't change state.
+    fn step(&mut self, now: DateTime<Utc>) -> Evaluation {
+        self.prune(now);
+        let value = self.value();
+        let breached = value.is_some_and(|v| self.rule.condition.holds(v, self.rule.threshold));
+        let quiet = |outcome| Evaluation { value, outcome, notification: None };
+
+        if breached && !self.firing {
+            let cooldown = chrono::Duration::seconds(self.rule.cooldown_secs as i64);
+            if self.last_fired.is_some_and(|last| now - last < cooldown) {
+                return quiet(Outcome::CoolingDown);
+            }
+            self.firing = true;
+            self.last_fired = Some(now);
+            let notification = self.notification(AlertState::Firing, value.unwrap_or_default(), now);
+            Evaluation { value, outcome: Outcome::Fired, notification: Some(notification) }
+        } else if !breached && self.firing {
+            self.firing = false;
+            let notification = self.notification(AlertState::Resolved, value.unwrap_or_default(), now);
+            Evaluation { value, outcome: Outcome::Resolved, notification: Some(notification) }
+        } else if breached {
+            quiet(Outcome::StillFiring)
+        } else if value.is_some() {
+            quiet(Outcome::WithinThreshold)
+        } else if self.samples.len() < self.rule.min_events.max(1) {
+            quiet(Outcome::TooFewEvents)
+        } else {
+            quiet(Outcome::NoValue)
+        }
+    }
+
+    /// Why `evaluation`, made at `at`, came out the way it did.
+    fn explain(&self, evaluation: &Evaluation, at: DateTime<Utc>) -> String {
+        let rule = &self.rule;
+        let compared = |value: f64, holds: bool| {
+            let not = if holds { "" } else { "not " };
+            format!("{} = {:.1}, {}{} {}", rule.metric.as_str(), value, not, rule.condition.as_str(), rule.threshold)
+        };
+        let value = evaluation.value.unwrap_or_default();
+        match evaluation.outcome {
+            Outcome::TooFewEvents => format!(
+                "{} event(s) in the last {}s, {} needed",
+                self.samples.len(),
+                rule.window_secs,
+                rule.min_events.max(1)
+            ),
+            Outcome::NoValue => {
+                format!("No event in the last {}s has a {} value", rule.window_secs, rule.metric.as_str())
+            }
+            Outcome::WithinThreshold => compared(value, false),
+            Outcome::CoolingDown => format!(
+                "{}, but the rule fired {}s ago (cooldown {}s)",
+                compared(value, true),
+                self.last_fired.map_or(0, |last| (at - last).num_seconds()),
+                rule.cooldown_secs
+            ),
+            Outcome::Fired => format!("Fired: {}", compared(value, true)),
+            Outcome::StillFiring => format!("Still firing: {}", compared(value, true)),
+            Outcome::Resolved if evaluation.value.is_some() => format!("Resolved: {}", compared(value, false)),
+            Outcome::Resolved => format!("Resolved: {} event(s) in the window leave no value", self.samples.len()),
+        }
+    }
+
+    fn notification(&self, state: AlertState, value: f64, at: DateTime<Utc>) -> AlertNotification {
+        let message = match state {
+            AlertState::Firing => format!(
+                "Alert '{}' firing: {} = {:.1} ({})",
+                self.rule.name,
+                self.rule.metric.as_str(),
+                value,
+                self.rule
+            ),
+            AlertState::Resolved => format!("Alert '{}' resolved: {} = {:.1}", self.rule.name, self.rule.metric.as_str(), value),
+        };
+
+        AlertNotification {
+            rule: self.rule.name.clone(),
+            state,
+            value,
+            threshold: self.rule.threshold,
+            message,
+            at,
+            digest: None,
+        }
+    }
+}
+
+/// Evaluates every configured rule against a stream of events.
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: &[AlertRule]) -> Result<Self> {
+        Ok(Self {
+            rules: rules.iter().cloned().map(RuleState::new).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Replace the rules after a config reload. A rule keeping its name keeps
+    /// its samples and firing state, so changing a threshold doesn't re-fire
+    /// an alert that is already firing.
+    pub fn update_rules(&mut self, rules: &[AlertRule]) -> Result<()> {
+        let mut previous: HashMap<_, _> = self
+            .rules
+            .drain(..)
+            .map(|state| (state.rule.name.clone(), state))
+            .collect();
+        let mut updated = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let mut state = RuleState::new(rule.clone())?;
+            if let Some(old) = previous.remove(&rule.name) {
+                state.samples = old.samples;
+                state.firing = old.firing;
+                state.last_fired = old.last_fired;
+            }
+            updated.push(state);
+        }
+        self.rules = updated;
+        Ok(())
+    }
+
+    /// Feed one event; returns the notifications for rules it changed.
+    pub fn observe(&mut self, event: &LatencyEvent) -> Vec<(AlertRule, AlertNotification)> {
+        let now = Utc::now();
+        let mut changes = Vec::new();
+
+        for state in &mut self.rules {
+            if !state.applies_to(event) {
+                continue;
+            }
+            state.samples.push_back(Sample::from_event(event));
+            if let Some(notification) = state.evaluate(now) {
+                changes.push((state.rule.clone(), notification));
+            }
+        }
+
+        changes
+    }
+
+    /// Re-check all rules without new input, resolving drained windows.
+    pub fn sweep(&mut self) -> Vec<(AlertRule, AlertNotification)> {
+        let now = Utc::now();
+        self.rules
+            .iter_mut()
+            .filter_map(|state| state.evaluate(now).map(|n| (state.rule.clone(), n)))
+            .collect()
+    }
+}
+
+/// Evaluate rules continuously against stored events, switching to the
+/// rules of each config published on `settings`. With a `notifier`, every
+/// rule is also delivered on the notification system's channels and
+/// recorded in its history.
+pub async fn run_alerts(mut settings: watch::Receiver<Config>, storage: MetricsStorage, notifier: Option<Notifier>) {
+    let mut config = settings.borrow_and_update().clone();
+    let mut engine = match AlertEngine::new(&config.alerts) {
+        Ok(engine) => engine,
+        Err(e) => {
+            warn!("Alerting disabled: {}", e);
+            return;
+        }
+    };
+    let mut events = storage.subscribe();
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    let mut digests = Digests::default();
+    let mut windows = Vec::new();
+    let mut following = true;
+    if !config.alerts.is_empty() {
+        info!("Evaluating {} alert rule(s)", config.alerts.len());
+    }
+
+    loop {
+        let changes = tokio::select! {
+            changed = settings.changed(), if following => {
+                if changed.is_err() {
+                    following = false;
+                    continue;
+                }
+                let reloaded = settings.borrow_and_update().clone();
+                match engine.update_rules(&reloaded.alerts) {
+                    Ok(()) => {
+                        info!("Evaluating {} alert rule(s) from the reloaded config", reloaded.alerts.len());
+                        config = reloaded;
+                    }
+                    Err(e) => warn!("Keeping the previous alert rules: {}", e),
+                }
+                continue;
+            },
+            received = events.recv() => match received {
+                Ok(event) => {
+                    let mut changes = engine.observe(&event);
+                    changes.extend(crash_loop_alert(&config, &event));
+                    changes
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Alert evaluation skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = sweep.tick() => {
+                // Picks up windows added from the CLI or API since the last sweep
+                match storage.list_maintenance_windows().await {
+                    Ok(loaded) => windows = loaded,
+                    Err(e) => warn!("Failed to load maintenance windows: {}", e),
+                }
+                engine.sweep()
+            }
+        };
+
+        let mut sends = Vec::new();
+        for (rule, notification) in changes {
+            let silenced_by = silencing_window(&windows, &rule.name, notification.at);
+            if let Err(e) = storage.record_alert(&notification, silenced_by).await {
+                warn!("Failed to record alert '{}': {}", rule.name, e);
+            }
+            if let Some(window) = silenced_by {
+                info!(
+                    "Alert '{}' {} during maintenance window '{}'; notifications suppressed",
+                    rule.name, notification.state.as_str(), window
+                );
+                continue;
+            }
+
+            let mut actions = rule.actions.clone();
+            for channel in notifier.iter().flat_map(|notifier| notifier.channels()) {
+                if !actions.contains(&channel.action()) {
+                    actions.push(channel.action());
+                }
+            }
+            actions.retain(|action| digests.admit(&config.alert_digest, &rule, *action, &notification));
+            sends.push((actions, rule, notification));
+        }
+        let now = Utc::now();
+        for (action, rule, notification) in digests.close_due(now) {
+            if silencing_window(&windows, &rule.name, now).is_none() {
+                sends.push((vec![action], rule, notification));
+            }
+        }
+
+        for (actions, rule, notification) in sends {
+            if actions.is_empty() {
+                continue;
+            }
+            if let Some(notifier) = &notifier {
+                let channels: Vec<_> = [NotificationChannel::Wall, NotificationChannel::Desktop]
+                    .into_iter()
+                    .filter(|channel| actions.contains(&channel.action()))
+                    .collect();
+                if !channels.is_empty() {
+                    notifier.record_alert(&notification, channels);
+                }
+            }
+            let (config, storage) = (config.clone(), storage.clone());
+            // Webhook retries can take minutes; don't stall evaluation
+            tokio::spawn(async move {
+                for action in actions {
+                    notify_action(&config, &storage, &rule, action, &notification).await;
+                }
+            });
+        }
+    }
+}
+
+/// The crash-loop rule firing for a crash loop lifecycle event. It goes to
+/// the log and the notification system's channels like a configured rule,
+/// but never resolves: each loop is its own alert.
+fn crash_loop_alert(config: &Config, event: &LatencyEvent) -> Option<(AlertRule, AlertNotification)> {
+    if event.kind != EventKind::Lifecycle
+        || event.metadata["lifecycle"].as_str() != Some(LifecycleKind::CrashLoop.as_str())
+    {
+        return None;
+    }
+
+    let rule = AlertRule {
+        name: CRASH_LOOP_RULE.to_string(),
+        component: Some(ComponentType::VSCodeExtension),
+        filter: None,
+        metric: AlertMetric::Count,
+        condition: Comparison::Ge,
+        threshold: config.monitoring.crash_loop_threshold as f64,
+        window_secs: config.monitoring.crash_loop_window_secs,
+        min_events: 1,
+        cooldown_secs: 0,
+        actions: default_actions(),
+    };
+    let notification = AlertNotification {
+        rule: rule.name.clone(),
+        state: AlertState::Firing,
+        value: event.metadata["failures"].as_f64().unwrap_or(rule.threshold),
+        threshold: rule.threshold,
+        message: event.description.clone(),
+        at: event.timestamp,
+        digest: None,
+    };
+    Some((rule, notification))
+}
+
+/// Name of the first maintenance window holding back `rule`'s
+/// notifications at `at`.
+fn silencing_window<'a>(windows: &'a [MaintenanceWindow], rule: &str, at: DateTime<Utc>) -> Option<&'a str> {
+    windows
+        .iter()
+        .find(|window| window.silences(rule, at))
+        .map(|window| window.name.as_str())
+}
+
+/// Run a rule's actions for a notification. Failures are logged per action.
+pub async fn notify(config: &Config, storage: &MetricsStorage, rule: &AlertRule, notification: &AlertNotification) {
+    for action in &rule.actions {
+        notify_action(config, storage, rule, *action, notification).await;
+    }
+}
+
+async fn notify_action(
+    config: &Config,
+    storage: &MetricsStorage,
+    rule: &AlertRule,
+    action: AlertAction,
+    notification: &AlertNotification,
+) {
+    let result = match action {
+        AlertAction::Log => {
+            match notification.state {
+                AlertState::Firing => warn!("{}", notification.message),
+                AlertState::Resolved => info!("{}", notification.message),
+            }
+            Ok(())
+        }
+        AlertAction::Wall => notifications::wall(&notification.message).await,
+        AlertAction::Desktop => {
+            notifications::desktop(notifications::alert_title(notification.state), &notification.message).await
+        }
+        AlertAction::Webhook => {
+            let event_type = match notification.state {
+                AlertState::Firing => "alert.firing",
+                AlertState::Resolved => "alert.resolved",
+            };
+            webhook::broadcast(storage, &config.webhooks, event_type, &json!(notification)).await
+        }
+        AlertAction::Email => email_alert(config, notification).await,
+    };
+
+    if let Err(e) = result {
+        warn!("Alert '{}' action {:?} failed: {}", rule.name, action, e);
+    }
+}
+
+/// Mail a notification to `email.alert_recipients`. A digest is sent with
+/// its own subject so it isn't mistaken for a single firing.
+async fn email_alert(config: &Config, notification: &AlertNotification) -> Result<()> {
+    let subject = match notification.digest {
+        Some(_) => format!("Alert digest for '{}'", notification.rule),
+        None => format!("Alert '{}' {}", notification.rule, notification.state.as_str()),
+    };
+    let message = EmailMessage {
+        subject,
+        html_body: format!(
+            "<html><body style=\"font-family: sans-serif\"><p>{}</p></body></html>",
+            report::html_escape(&notification.message)
+        ),
+        attachments: Vec::new(),
+    };
+    email::send_email(&config.email, &Secrets::load()?, &config.email.alert_recipients, message).await
+}
+
+/// Evaluate a rule against the events stored over its window, as `alerts
+/// test` does. Returns the metric value (if any) and the event count.
+pub async fn evaluate_stored(storage: &MetricsStorage, rule: &AlertRule) -> Result<(Option<f64>, usize)> {
+    let mut state = RuleState::new(rule.clone())?;
+    let range = TimeRange::last(chrono::Duration::seconds(rule.window_secs as i64));
+
+    let mut events = storage.query_events(Some(&range.to_sql()), TEST_EVENT_LIMIT).await?;
+    events.retain(|event| state.applies_to(event));
+    events.reverse();
+    state.samples = events.iter().map(Sample::from_event).collect();
+
+    Ok((state.value(), state.samples.len()))
+}
+
+/// Most evaluations one `debug_rule` replay may make.
+const MAX_DEBUG_TICKS: i64 = 2000;
+
+/// Input events a `debug_rule` replay returns, the most recent ones.
+const DEBUG_INPUT_LIMIT: usize = 1000;
+
+/// An event a rule aggregated over.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleInput {
+    pub id: Option<i64>,
+    pub at: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub failed: bool,
+    pub cpu_percent: Option<f64>,
+    pub memory_mb: Option<f64>,
+}
+
+/// One evaluation of a replayed rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTick {
+    pub at: DateTime<Utc>,
+    /// Events in the window
+    pub events: usize,
+    pub value: Option<f64>,
+    pub outcome: Outcome,
+    /// Whether the rule is firing after this evaluation
+    pub firing: bool,
+    pub reason: String,
+}
+
+/// A rule replayed over stored events, for `/api/alerts/rules/:name/debug`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleDebug {
+    pub rule: AlertRule,
+    /// The rule as text, e.g. `GitHubCopilot p95_ms > 2000 over 300s`
+    pub summary: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub tick_secs: i64,
+    /// Matching events from one window before `since` up to `until`
+    pub input_count: usize,
+    /// The most recent of them, oldest first
+    pub inputs: Vec<RuleInput>,
+    pub ticks: Vec<RuleTick>,
+    /// State changes the monitor recorded for the rule over the same time
+    pub history: Vec<AlertHistoryEntry>,
+}
+
+/// Replay `rule` over the events stored between `since` and `until`,
+/// evaluating it every `tick` as the sweep does. The replay starts quiet
+/// one window before `since`, so a rule already firing then shows up as
+/// firing at its first breached tick; evaluations on each new event in the
+/// monitor can also fire between ticks. `history` has what really fired.
+pub async fn debug_rule(
+    storage: &MetricsStorage,
+    rule: &AlertRule,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    tick: chrono::Duration,
+) -> Result<RuleDebug> {
+    if tick <= chrono::Duration::zero() || since >= until {
+        return Err(anyhow!("Replay needs a positive tick and since before until"));
+    }
+    // In microseconds, so sub-second ticks are counted in full
+    let count = match ((until - since).num_microseconds(), tick.num_microseconds()) {
+        (Some(span), Some(tick)) if tick > 0 => span / tick,
+        _ => i64::MAX,
+    };
+    if count > MAX_DEBUG_TICKS {
+        return Err(anyhow!("{} evaluations requested; at most {} are allowed", count, MAX_DEBUG_TICKS));
+    }
+
+    let mut state = RuleState::new(rule.clone())?;
+    let range = TimeRange {
+        since: Some(since - chrono::Duration::seconds(rule.window_secs as i64)),
+        until: Some(until),
+        ..TimeRange::default()
+    };
+    let mut events = storage.query_events(Some(&range.to_sql()), TEST_EVENT_LIMIT).await?;
+    events.retain(|event| state.applies_to(event));
+    events.reverse();
+
+    let mut ticks = Vec::new();
+    let mut pending = events.iter().peekable();
+    let mut at = since;
+    while at <= until {
+        while let Some(event) = pending.next_if(|event| event.timestamp <= at) {
+            state.samples.push_back(Sample::from_event(event));
+        }
+        let evaluation = state.step(at);
+        ticks.push(RuleTick {
+            at,
+            events: state.samples.len(),
+            value: evaluation.value,
+            outcome: evaluation.outcome,
+            firing: state.firing,
+            reason: state.explain(&evaluation, at),
+        });
+        at += tick;
+    }
+
+    let inputs = events[events.len().saturating_sub(DEBUG_INPUT_LIMIT)..]
+        .iter()
+        .map(|event| {
+            let sample = Sample::from_event(event);
+            RuleInput {
+                id: event.id,
+                at: sample.at,
+                duration_ms: sample.duration_us as f64 / 1000.0,
+                failed: sample.failed,
+                cpu_percent: sample.cpu_percent,
+                memory_mb: sample.memory_mb,
+            }
+        })
+        .collect();
+
+    Ok(RuleDebug {
+        rule: rule.clone(),
+        summary: rule.to_string(),
+        since,
+        until,
+        tick_secs: tick.num_seconds(),
+        input_count: events.len(),
+        inputs,
+        ticks,
+        history: storage.get_rule_alert_history(&rule.name, since).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EventSource;
+
+    fn rule(metric: AlertMetric, threshold: f64, min_events: usize) -> AlertRule {
+        AlertRule {
+            name: "slow".to_string(),
+            component: None,
+            filter: None,
+            metric,
+            condition: Comparison::Gt,
+            threshold,
+            window_secs: 60,
+            min_events,
+            cooldown_secs: 600,
+            actions: default_actions(),
+        }
+    }
+
+    fn sample(at: DateTime<Utc>, duration_ms: u64) -> Sample {
+        Sample {
+            at,
+            duration_us: duration_ms * 1000,
+            failed: false,
+            cpu_percent: None,
+            memory_mb: None,
+        }
+    }
+
+    /// Evaluate at `at`, returning the outcome and its explanation.
+    fn step(state: &mut RuleState, at: DateTime<Utc>) -> (Outcome, String) {
+        let evaluation = state.step(at);
+        (evaluation.outcome, state.explain(&evaluation, at))
+    }
+
+    #[test]
+    fn rules_fire_resolve_and_cool_down() {
+        let t0 = Utc::now();
+        let secs = |secs| t0 + chrono::Duration::seconds(secs);
+        let mut state = RuleState::new(rule(AlertMetric::P95Ms, 100.0, 2)).unwrap();
+
+        state.samples.push_back(sample(t0, 50));
+        assert_eq!(step(&mut state, t0), (Outcome::TooFewEvents, "1 event(s) in the last 60s, 2 needed".to_string()));
+
+        state.samples.push_back(sample(t0, 60));
+        assert_eq!(step(&mut state, t0), (Outcome::WithinThreshold, "p95_ms = 60.0, not > 100".to_string()));
+
+        state.samples.push_back(sample(secs(1), 500));
+        let evaluation = state.step(secs(1));
+        assert_eq!(evaluation.outcome, Outcome::Fired);
+        assert_eq!(state.explain(&evaluation, secs(1)), "Fired: p95_ms = 500.0, > 100");
+        let notification = evaluation.notification.unwrap();
+        assert_eq!(notification.state, AlertState::Firing);
+        assert_eq!(notification.message, "Alert 'slow' firing: p95_ms = 500.0 (p95_ms > 100 over 60s)");
+
+        assert_eq!(step(&mut state, secs(2)).0, Outcome::StillFiring);
+
+        // Every sample has left the window
+        assert_eq!(
+            step(&mut state, secs(62)),
+            (Outcome::Resolved, "Resolved: 0 event(s) in the window leave no value".to_string())
+        );
+        assert!(!state.firing);
+
+        state.samples.extend([sample(secs(70), 900), sample(secs(70), 900)]);
+        assert_eq!(
+            step(&mut state, secs(70)),
+            (Outcome::CoolingDown, "p95_ms = 900.0, > 100, but the rule fired 69s ago (cooldown 600s)".to_string())
+        );
+
+        state.samples.extend([sample(secs(620), 900), sample(secs(620), 900)]);
+        assert_eq!(step(&mut state, secs(620)).0, Outcome::Fired);
+    }
+
+    #[test]
+    fn windows_without_the_metric_have_no_value() {
+        let t0 = Utc::now();
+        let mut state = RuleState::new(rule(AlertMetric::CpuPercent, 80.0, 1)).unwrap();
+        assert_eq!(step(&mut state, t0).0, Outcome::TooFewEvents);
+
+        state.samples.push_back(sample(t0, 10));
+        let (outcome, reason) = step(&mut state, t0);
+        assert_eq!(outcome, Outcome::NoValue);
+        assert_eq!(reason, "No event in the last 60s has a cpu_percent value");
+
+        state.samples.push_back(Sample {
+            cpu_percent: Some(95.0),
+            ..sample(t0, 10)
+        });
+        assert_eq!(step(&mut state, t0).0, Outcome::Fired);
+    }
+
+    #[test]
+    fn failures_count_towards_the_error_rate() {
+        let mut state = RuleState::new(rule(AlertMetric::ErrorRate, 0.25, 1)).unwrap();
+        for metadata in [
+            json!({"success": true}),
+            json!({"success": false}),
+            json!({"error": "timed out"}),
+            json!({"error": null}),
+        ] {
+            let event = LatencyEvent::new(
+                ComponentType::Network,
+                EventSource::NetworkRequest,
+                Duration::from_millis(5),
+                "GET /".to_string(),
+            )
+            .with_metadata(metadata);
+            state.samples.push_back(Sample::from_event(&event));
+        }
+        assert_eq!(state.value(), Some(0.5));
+    }
+
+    #[test]
+    fn rules_only_see_their_component_and_filter() {
+        let mut scoped = rule(AlertMetric::Count, 0.0, 1);
+        scoped.component = Some(ComponentType::Terminal);
+        scoped.filter = Some("description ~ cargo".to_string());
+        let state = RuleState::new(scoped.clone()).unwrap();
+        assert_eq!(scoped.to_string(), "Terminal count > 0 over 60s where description ~ cargo");
+
+        let event = |component, description: &str| {
+            let duration = Duration::from_millis(5);
+            LatencyEvent::new(component, EventSource::CommandExecution, duration, description.to_string())
+        };
+        assert!(state.applies_to(&event(ComponentType::Terminal, "cargo build")));
+        assert!(!state.applies_to(&event(ComponentType::Terminal, "ls")));
+        assert!(!state.applies_to(&event(ComponentType::Editor, "cargo build")));
+        assert!(!state.applies_to(&event(ComponentType::Terminal, "cargo build").as_test_data()));
+
+        scoped.filter = Some("colour = red".to_string());
+        assert!(RuleState::new(scoped).is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_evaluate_stored_events_tick_by_tick() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-alert-debug-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db")).await.unwrap();
+
+        let since = Utc::now() - chrono::Duration::minutes(10);
+        let slow = LatencyEvent::builder(ComponentType::Terminal, EventSource::CommandExecution)
+            .duration(Duration::from_millis(500))
+            .description("cargo build".to_string())
+            .timestamp(since + chrono::Duration::seconds(30))
+            .build();
+        storage.import_events(&[slow]).await.unwrap();
+
+        let rule = rule(AlertMetric::P95Ms, 100.0, 1);
+        let minute = chrono::Duration::minutes(1);
+        let debug = debug_rule(&storage, &rule, since, since + minute * 5, minute).await.unwrap();
+        let outcomes: Vec<Outcome> = debug.ticks.iter().map(|tick| tick.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                Outcome::TooFewEvents,
+                Outcome::Fired,
+                Outcome::Resolved,
+                Outcome::TooFewEvents,
+                Outcome::TooFewEvents,
+                Outcome::TooFewEvents,
+            ]
+        );
+        assert_eq!(debug.input_count, 1);
+        assert_eq!(debug.inputs[0].duration_ms, 500.0);
+        assert_eq!(debug.summary, "p95_ms > 100 over 60s");
+        assert!(debug.history.is_empty());
+
+        assert!(debug_rule(&storage, &rule, since, since + minute, chrono::Duration::zero()).await.is_err());
+        let too_many = chrono::Duration::milliseconds(10);
+        assert!(debug_rule(&storage, &rule, since, since + minute, too_many).await.is_err());
+
+        drop(storage);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Prefix that makes leaked tokens easy to recognise (and grep for).
+const TOKEN_PREFIX: &str = "vlm_";
+
+/// What an API token may do. `Admin` implies every other scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "read:metrics")]
+    ReadMetrics,
+    #[serde(rename = "write:events")]
+    WriteEvents,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl ApiScope {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "read:metrics" => Ok(ApiScope::ReadMetrics),
+            "write:events" => Ok(ApiScope::WriteEvents),
+            "admin" => Ok(ApiScope::Admin),
+            _ => Err(anyhow!(
+                "Unknown scope '{}' (read:metrics, write:events, admin)",
+                value
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::ReadMetrics => "read:metrics",
+            ApiScope::WriteEvents => "write:events",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    pub fn allows(granted: &[ApiScope], required: ApiScope) -> bool {
+        granted.iter().any(|s| *s == required || *s == ApiScope::Admin)
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A new random bearer token. Only its hash is ever stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+}
+
+/// Tokens are 256-bit random values, so an unsalted SHA-256 is sufficient.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::config::StorageConfig;
+use crate::models::{EventDeletion, LatencyEvent, PerformanceMetrics};
+use crate::postgres::{PostgresConfig, PostgresStorage};
+use crate::secrets::Secrets;
+use crate::storage::MetricsStorage;
+use crate::timerange::TimeRange;
+
+/// Events copied to the central backend per round trip.
+const REPLICATION_BATCH: u32 = 1000;
+
+/// Database selected by `storage.backend`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Only the per-machine database at `storage.database_path`
+    #[default]
+    Sqlite,
+    /// A shared Postgres database many monitors write to
+    Postgres,
+}
+
+impl StorageBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageBackend::Sqlite => "sqlite",
+            StorageBackend::Postgres => "postgres",
+        }
+    }
+}
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Event storage every backend provides: writing events and the reads the
+/// dashboard needs to show them. Everything else (tags, saved queries,
+/// alerts, tokens, the audit log) stays in the local [`MetricsStorage`].
+pub trait MetricsBackend: Send + Sync {
+    /// Name for logs and `/api/sources`
+    fn name(&self) -> &'static str;
+
+    /// Insert `events`, skipping ones already stored; returns how many were new.
+    fn store_events<'a>(&'a self, events: &'a [LatencyEvent]) -> BackendFuture<'a, u64>;
+
+    /// Mark stored events deleted or restored, matched by uid; returns how
+    /// many changed.
+    fn apply_deletions<'a>(&'a self, deletions: &'a [EventDeletion]) -> BackendFuture<'a, u64>;
+
+    fn get_recent_events(&self, limit: u32) -> BackendFuture<'_, Vec<LatencyEvent>>;
+
+    fn get_performance_metrics_in<'a>(&'a self, range: &'a TimeRange) -> BackendFuture<'a, Vec<PerformanceMetrics>>;
+
+    fn count_events(&self) -> BackendFuture<'_, u64>;
+}
+
+impl MetricsBackend for MetricsStorage {
+    fn name(&self) -> &'static str {
+        StorageBackend::Sqlite.as_str()
+    }
+
+    fn store_events<'a>(&'a self, events: &'a [LatencyEvent]) -> BackendFuture<'a, u64> {
+        Box::pin(self.import_events(events))
+    }
+
+    fn apply_deletions<'a>(&'a self, deletions: &'a [EventDeletion]) -> BackendFuture<'a, u64> {
+        Box::pin(MetricsStorage::apply_deletions(self, deletions))
+    }
+
+    fn get_recent_events(&self, limit: u32) -> BackendFuture<'_, Vec<LatencyEvent>> {
+        Box::pin(MetricsStorage::get_recent_events(self, limit))
+    }
+
+    fn get_performance_metrics_in<'a>(&'a self, range: &'a TimeRange) -> BackendFuture<'a, Vec<PerformanceMetrics>> {
+        Box::pin(MetricsStorage::get_performance_metrics_in(self, range))
+    }
+
+    fn count_events(&self) -> BackendFuture<'_, u64> {
+        Box::pin(MetricsStorage::count_events(self))
+    }
+}
+
+/// The backend `storage.backend` selects: `local` itself for SQLite, or a
+/// connection to the shared database. The shared database is connected to
+/// on first use rather than here, so starting while it is down still
+/// records locally, and the replicator retries until it is back.
+pub fn open(config: &StorageConfig, local: MetricsStorage, secrets: &Secrets) -> Arc<dyn MetricsBackend> {
+    match config.backend {
+        StorageBackend::Sqlite => Arc::new(local),
+        StorageBackend::Postgres => Arc::new(Reconnecting {
+            config: config.postgres.clone(),
+            secrets: secrets.clone(),
+            connected: Mutex::new(None),
+        }),
+    }
+}
+
+/// A Postgres backend that connects on first use and retries on every use
+/// until a connection succeeds. The pool reconnects by itself after that.
+struct Reconnecting {
+    config: PostgresConfig,
+    secrets: Secrets,
+    connected: Mutex<Option<Arc<PostgresStorage>>>,
+}
+
+impl Reconnecting {
+    async fn connected(&self) -> Result<Arc<PostgresStorage>> {
+        let mut connected = self.connected.lock().await;
+        if let Some(storage) = connected.as_ref() {
+            return Ok(storage.clone());
+        }
+        let storage = Arc::new(PostgresStorage::connect(&self.config, &self.secrets).await?);
+        *connected = Some(storage.clone());
+        Ok(storage)
+    }
+}
+
+impl MetricsBackend for Reconnecting {
+    fn name(&self) -> &'static str {
+        StorageBackend::Postgres.as_str()
+    }
+
+    fn store_events<'a>(&'a self, events: &'a [LatencyEvent]) -> BackendFuture<'a, u64> {
+        Box::pin(async move { self.connected().await?.store_events(events).await })
+    }
+
+    fn apply_deletions<'a>(&'a self, deletions: &'a [EventDeletion]) -> BackendFuture<'a, u64> {
+        Box::pin(async move { self.connected().await?.apply_deletions(deletions).await })
+    }
+
+    fn get_recent_events(&self, limit: u32) -> BackendFuture<'_, Vec<LatencyEvent>> {
+        Box::pin(async move { self.connected().await?.get_recent_events(limit).await })
+    }
+
+    fn get_performance_metrics_in<'a>(&'a self, range: &'a TimeRange) -> BackendFuture<'a, Vec<PerformanceMetrics>> {
+        Box::pin(async move { self.connected().await?.get_performance_metrics_in(range).await })
+    }
+
+    fn count_events(&self) -> BackendFuture<'_, u64> {
+        Box::pin(async move { self.connected().await?.count_events().await })
+    }
+}
+
+/// Copy every event the monitor stores locally to `backend`, oldest first,
+/// then the deletions and restores made since. Progress is kept as a sync
+/// watermark in the local database, so events stored while the backend was
+/// unreachable are sent once it is back.
+pub async fn run_replication(storage: MetricsStorage, backend: Arc<dyn MetricsBackend>, interval: Duration) {
+    let source = format!("backend:{}", backend.name());
+    let mut ticker = tokio::time::interval(interval);
+    info!("Replicating events to the {} backend", backend.name());
+
+    loop {
+        ticker.tick().await;
+        match replicate(&storage, backend.as_ref(), &source).await {
+            Ok(0) => {}
+            Ok(sent) => debug!("Replicated {} events to the {} backend", sent, backend.name()),
+            Err(e) => {
+                warn!("Replicating events to the {} backend failed: {}", backend.name(), e);
+                continue;
+            }
+        }
+        match replicate_deletions(&storage, backend.as_ref()).await {
+            Ok(0) => {}
+            Ok(changed) => debug!("Replicated {} deletions to the {} backend", changed, backend.name()),
+            Err(e) => warn!("Replicating deletions to the {} backend failed: {}", backend.name(), e),
+        }
+    }
+}
+
+async fn replicate(storage: &MetricsStorage, backend: &dyn MetricsBackend, source: &str) -> Result<u64> {
+    let mut after_id = storage.get_sync_watermark(source).await?.map_or(0, |(_, id)| id);
+    let mut sent = 0;
+
+    loop {
+        let events = storage.get_events_after(after_id, REPLICATION_BATCH).await?;
+        let Some((last_id, last_timestamp)) = events.last().and_then(|event| Some((event.id?, event.timestamp))) else {
+            return Ok(sent);
+        };
+
+        backend.store_events(&events).await?;
+        sent += events.len() as u64;
+        after_id = last_id;
+        storage
+            .set_sync_watermark(source, &last_timestamp.to_rfc3339(), after_id)
+            .await?;
+
+        if events.len() < REPLICATION_BATCH as usize {
+            return Ok(sent);
+        }
+    }
+}
+
+/// Replay local deletions and restores on `backend`. A restored event is
+/// sent again first, in case it was deleted before it was ever copied.
+async fn replicate_deletions(storage: &MetricsStorage, backend: &dyn MetricsBackend) -> Result<u64> {
+    let mut changed = 0;
+
+    loop {
+        let pending = storage.get_pending_deletions(REPLICATION_BATCH).await?;
+        let Some(&(last_seq, _)) = pending.last() else {
+            return Ok(changed);
+        };
+
+        let mut restored = Vec::new();
+        for (_, deletion) in pending.iter().filter(|(_, deletion)| deletion.deleted_at.is_none()) {
+            restored.extend(storage.get_event_by_uid(&deletion.uid).await?);
+        }
+        backend.store_events(&restored).await?;
+
+        let deletions: Vec<EventDeletion> = pending.into_iter().map(|(_, deletion)| deletion).collect();
+        changed += backend.apply_deletions(&deletions).await?;
+        storage.clear_pending_deletions(last_seq).await?;
+
+        if deletions.len() < REPLICATION_BATCH as usize {
+            return Ok(changed);
+        }
+    }
+}
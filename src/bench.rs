@@ -0,0 +1,430 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tokio::task::JoinHandle;
+
+use crate::backend::StorageBackend;
+use crate::config::Config;
+use crate::dashboard::DashboardServer;
+use crate::models::{ComponentType, EventSource, LatencyEvent};
+use crate::platform;
+use crate::storage::{self, MetricsStorage};
+
+/// One of the monitor's hot paths, timed by `bench`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// The process-table pass every collector makes
+    ProcessScan,
+    /// Storing one event in SQLite
+    DbInsert,
+    /// `GET /api/status` against a dashboard on loopback
+    ApiRoundtrip,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 3] = [Workload::ProcessScan, Workload::DbInsert, Workload::ApiRoundtrip];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|workload| workload.as_str() == value)
+            .ok_or_else(|| anyhow!("Unknown workload '{}' (process-scan, db-insert, api-roundtrip)", value))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Workload::ProcessScan => "process-scan",
+            Workload::DbInsert => "db-insert",
+            Workload::ApiRoundtrip => "api-roundtrip",
+        }
+    }
+}
+
+/// The statistic compared with a baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchMetric {
+    Mean,
+    P50,
+    P95,
+    P99,
+}
+
+impl BenchMetric {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "mean" => Ok(BenchMetric::Mean),
+            "p50" => Ok(BenchMetric::P50),
+            "p95" => Ok(BenchMetric::P95),
+            "p99" => Ok(BenchMetric::P99),
+            _ => Err(anyhow!("Unknown metric '{}' (mean, p50, p95, p99)", value)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BenchMetric::Mean => "mean",
+            BenchMetric::P50 => "p50",
+            BenchMetric::P95 => "p95",
+            BenchMetric::P99 => "p99",
+        }
+    }
+
+    fn of(&self, result: &BenchResult) -> f64 {
+        match self {
+            BenchMetric::Mean => result.mean_ms,
+            BenchMetric::P50 => result.p50_ms,
+            BenchMetric::P95 => result.p95_ms,
+            BenchMetric::P99 => result.p99_ms,
+        }
+    }
+}
+
+pub struct BenchOptions {
+    pub workloads: Vec<Workload>,
+    pub iterations: usize,
+    /// Untimed runs first, to warm caches and connections
+    pub warmup: usize,
+}
+
+/// Timings of one workload across its iterations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub workload: String,
+    pub iterations: u64,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Results saved with `bench --save-baseline`, for later runs to compare
+/// against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub recorded_at: DateTime<Utc>,
+    /// Version of the monitor that recorded it
+    pub version: String,
+    pub results: Vec<BenchResult>,
+}
+
+impl Baseline {
+    pub fn new(results: Vec<BenchResult>) -> Self {
+        Self {
+            recorded_at: Utc::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            results,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Can't read baseline {}: {}", path.display(), e))?;
+        serde_json::from_str(&content).map_err(|e| anyhow!("Invalid baseline {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// One workload measured against its baseline.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub workload: String,
+    pub metric: &'static str,
+    pub baseline_ms: f64,
+    pub current_ms: f64,
+    /// Positive when slower than the baseline
+    pub change_percent: f64,
+    pub regressed: bool,
+}
+
+/// Compare `results` with `baseline` on `metric`; a workload regressed when
+/// it is more than `threshold_percent` slower. Workloads the baseline
+/// doesn't have are left out.
+pub fn compare(
+    baseline: &Baseline,
+    results: &[BenchResult],
+    metric: BenchMetric,
+    threshold_percent: f64,
+) -> Vec<Comparison> {
+    results
+        .iter()
+        .filter_map(|result| {
+            let before = baseline.results.iter().find(|before| before.workload == result.workload)?;
+            let (baseline_ms, current_ms) = (metric.of(before), metric.of(result));
+            let change_percent = if baseline_ms > 0.0 { (current_ms - baseline_ms) / baseline_ms * 100.0 } else { 0.0 };
+            Some(Comparison {
+                workload: result.workload.clone(),
+                metric: metric.as_str(),
+                baseline_ms,
+                current_ms,
+                change_percent,
+                regressed: change_percent > threshold_percent,
+            })
+        })
+        .collect()
+}
+
+/// Run each workload `warmup + iterations` times against a scratch
+/// database, timing the last `iterations`. The user's database is never
+/// touched.
+pub async fn run(config: &Config, options: &BenchOptions) -> Result<Vec<BenchResult>> {
+    if options.iterations == 0 {
+        return Err(anyhow!("bench needs at least one iteration"));
+    }
+    let scratch = Scratch::create()?;
+    let storage = MetricsStorage::new(&scratch.path.join("bench.db")).await?;
+    let mut api = None;
+
+    let mut results = Vec::new();
+    for workload in &options.workloads {
+        if *workload == Workload::ApiRoundtrip && api.is_none() {
+            api = Some(ApiServer::start(config, &scratch.path, storage.clone()).await?);
+        }
+
+        let mut samples_us = Vec::with_capacity(options.iterations);
+        for i in 0..options.warmup + options.iterations {
+            let start = Instant::now();
+            match workload {
+                Workload::ProcessScan => {
+                    let mut system = System::new_all();
+                    system.refresh_processes();
+                    let _ = system.processes().values().filter(|process| platform::is_vscode(process)).count();
+                }
+                Workload::DbInsert => storage.store_event(&bench_event(i)).await?,
+                Workload::ApiRoundtrip => {
+                    if let Some(api) = &api {
+                        api.get("/api/status").await?;
+                    }
+                }
+            }
+            if i >= options.warmup {
+                samples_us.push(start.elapsed().as_micros() as u64);
+            }
+        }
+        results.push(summarize(*workload, samples_us));
+    }
+
+    Ok(results)
+}
+
+fn bench_event(i: usize) -> LatencyEvent {
+    LatencyEvent::new(
+        ComponentType::VSCode,
+        EventSource::TestCommand,
+        Duration::from_micros(1000 + (i % 500) as u64),
+        format!("Bench insert #{}", i + 1),
+    )
+    .as_test_data()
+}
+
+fn summarize(workload: Workload, mut samples_us: Vec<u64>) -> BenchResult {
+    samples_us.sort_unstable();
+    let ms = |us: u64| us as f64 / 1000.0;
+    let count = samples_us.len() as f64;
+    let mean_ms = samples_us.iter().map(|&us| ms(us)).sum::<f64>() / count;
+    let variance = samples_us.iter().map(|&us| (ms(us) - mean_ms).powi(2)).sum::<f64>() / count;
+
+    BenchResult {
+        workload: workload.as_str().to_string(),
+        iterations: samples_us.len() as u64,
+        mean_ms,
+        stddev_ms: variance.sqrt(),
+        min_ms: ms(samples_us.first().copied().unwrap_or(0)),
+        p50_ms: ms(storage::percentile(&samples_us, 0.50)),
+        p95_ms: ms(storage::percentile(&samples_us, 0.95)),
+        p99_ms: ms(storage::percentile(&samples_us, 0.99)),
+        max_ms: ms(samples_us.last().copied().unwrap_or(0)),
+    }
+}
+
+/// Temporary directory for the scratch database, removed on drop.
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("vscode-latency-monitor-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A dashboard serving the scratch database on a free port,
+/// stopped on drop.
+struct ApiServer {
+    client: reqwest::Client,
+    base_url: String,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ApiServer {
+    async fn start(config: &Config, scratch: &Path, storage: MetricsStorage) -> Result<Self> {
+        // Only the local database, whatever the real dashboard is set up with
+        let mut config = config.clone();
+        config.storage.database_path = scratch.join("bench.db");
+        config.storage.backend = StorageBackend::Sqlite;
+        config.dashboard.sources.clear();
+        config.dashboard.federation.enabled = false;
+        config.dashboard.tenancy.enabled = false;
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let server = DashboardServer::new(config, storage, false).await?;
+        let task = tokio::spawn(server.serve(port));
+        let api = Self {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            base_url: format!("http://127.0.0.1:{}", port),
+            task,
+        };
+
+        for _ in 0..100 {
+            if api.get("/health").await.is_ok() {
+                return Ok(api);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        Err(anyhow!("Benchmark dashboard on port {} didn't start", port))
+    }
+
+    async fn get(&self, path: &str) -> Result<()> {
+        self.client
+            .get(format!("{}{}", self.base_url, path))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(())
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(workload: &str, mean_ms: f64, p95_ms: f64) -> BenchResult {
+        BenchResult {
+            workload: workload.to_string(),
+            iterations: 100,
+            mean_ms,
+            stddev_ms: 0.0,
+            min_ms: 0.0,
+            p50_ms: mean_ms,
+            p95_ms,
+            p99_ms: p95_ms,
+            max_ms: p95_ms,
+        }
+    }
+
+    #[test]
+    fn regressions_are_changes_beyond_the_threshold() {
+        let baseline = Baseline::new(vec![result("db-insert", 1.0, 2.0), result("process-scan", 10.0, 20.0)]);
+        let current = [
+            result("db-insert", 1.05, 2.5),
+            result("process-scan", 8.0, 20.0),
+            result("api-roundtrip", 3.0, 6.0),
+        ];
+
+        let by_mean = compare(&baseline, &current, BenchMetric::Mean, 10.0);
+        // Workloads the baseline didn't run aren't compared
+        assert_eq!(by_mean.len(), 2);
+        assert_eq!(by_mean[0].workload, "db-insert");
+        assert_eq!(by_mean[0].metric, "mean");
+        assert!((by_mean[0].change_percent - 5.0).abs() < 1e-9);
+        assert!(!by_mean[0].regressed);
+        assert!((by_mean[1].change_percent + 20.0).abs() < 1e-9);
+        assert!(!by_mean[1].regressed);
+
+        let by_p95 = compare(&baseline, &current, BenchMetric::P95, 10.0);
+        assert!((by_p95[0].change_percent - 25.0).abs() < 1e-9);
+        assert!(by_p95[0].regressed);
+        assert!(!by_p95[1].regressed);
+    }
+
+    #[test]
+    fn a_zero_baseline_never_regresses() {
+        let baseline = Baseline::new(vec![result("db-insert", 0.0, 0.0)]);
+        let comparison = compare(&baseline, &[result("db-insert", 5.0, 5.0)], BenchMetric::P99, 0.0);
+        assert_eq!(comparison[0].change_percent, 0.0);
+        assert!(!comparison[0].regressed);
+    }
+
+    #[test]
+    fn summary_covers_every_sample() {
+        let summary = summarize(Workload::DbInsert, vec![4000, 1000, 3000, 2000]);
+        assert_eq!(summary.workload, "db-insert");
+        assert_eq!(summary.iterations, 4);
+        assert_eq!(summary.mean_ms, 2.5);
+        assert!((summary.stddev_ms - 1.25f64.sqrt()).abs() < 1e-9);
+        assert_eq!(summary.min_ms, 1.0);
+        assert_eq!(summary.max_ms, 4.0);
+        assert!(summary.p50_ms >= 2.0 && summary.p50_ms <= 3.0);
+    }
+
+    #[test]
+    fn baselines_survive_a_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-baseline-{}", std::process::id()));
+        let path = dir.join("nested").join("baseline.json");
+        Baseline::new(vec![result("api-roundtrip", 1.5, 3.0)]).save(&path).unwrap();
+
+        let loaded = Baseline::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(loaded.results.len(), 1);
+        assert_eq!(loaded.results[0].p95_ms, 3.0);
+        assert!(Baseline::load(&path).is_err());
+    }
+
+    #[test]
+    fn names_parse_back_to_their_values() {
+        for workload in Workload::ALL {
+            assert_eq!(Workload::parse(workload.as_str()).unwrap(), workload);
+        }
+        for metric in [BenchMetric::Mean, BenchMetric::P50, BenchMetric::P95, BenchMetric::P99] {
+            assert_eq!(BenchMetric::parse(metric.as_str()).unwrap(), metric);
+        }
+        assert!(Workload::parse("disk").is_err());
+        assert!(BenchMetric::parse("p90").is_err());
+    }
+
+    #[tokio::test]
+    async fn database_inserts_are_timed_in_a_scratch_database() {
+        let options = BenchOptions {
+            workloads: vec![Workload::DbInsert],
+            iterations: 5,
+            warmup: 2,
+        };
+        let results = run(&Config::default(), &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].iterations, 5);
+        assert!(results[0].min_ms <= results[0].max_ms);
+        assert!(!std::env::temp_dir().join(format!("vscode-latency-monitor-bench-{}", std::process::id())).exists());
+
+        let options = BenchOptions { iterations: 0, ..options };
+        assert!(run(&Config::default(), &options).await.is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! `cargo latency <command>`: run a cargo command and record how long it
+//! and each rustc it started took. Built with `--features cargo-latency`.
+
+fn main() {
+    match vscode_latency_monitor::cargo_latency::main(std::env::args_os().skip(1).collect()) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("cargo-latency: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tracing::info;
+
+use crate::models::ComponentType;
+use crate::platform;
+use crate::storage;
+
+/// How reports treat latency within a machine's idle noise profile.
+/// Configured as `reports.noise`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseHandling {
+    /// Report latency as measured
+    #[default]
+    Off,
+    /// Mark components whose median is indistinguishable from idle noise
+    Flag,
+    /// Take the idle median off every percentile
+    Subtract,
+}
+
+impl NoiseHandling {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "off" => Ok(NoiseHandling::Off),
+            "flag" => Ok(NoiseHandling::Flag),
+            "subtract" => Ok(NoiseHandling::Subtract),
+            _ => Err(anyhow!("Unknown noise handling '{}' (off, flag, subtract)", value)),
+        }
+    }
+}
+
+/// What a sampling pass costs on this machine with VS Code closed: every
+/// process-sample latency includes this, so differences inside its spread
+/// are the machine, not the editor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoiseProfile {
+    pub measured_at: DateTime<Utc>,
+    pub interval_ms: u64,
+    pub samples: u64,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// How late the sampling timer fired, at the 95th percentile
+    pub timer_jitter_p95_ms: f64,
+    /// Components whose latency includes the calibrated pass; the profile
+    /// only applies to these
+    #[serde(default = "sampled_components")]
+    pub components: Vec<ComponentType>,
+}
+
+/// Components recorded from process samples, whose latency is the
+/// process-table pass `measure` times.
+pub const SAMPLED_COMPONENTS: [ComponentType; 7] = [
+    ComponentType::VSCode,
+    ComponentType::VSCodeExtension,
+    ComponentType::GitHubCopilot,
+    ComponentType::LocalModel,
+    ComponentType::Terminal,
+    ComponentType::LanguageServer,
+    ComponentType::Editor,
+];
+
+fn sampled_components() -> Vec<ComponentType> {
+    SAMPLED_COMPONENTS.to_vec()
+}
+
+impl NoiseProfile {
+    /// Spread of idle measurements around their median; latency within
+    /// this of the idle median, or changes smaller than it, are noise.
+    pub fn band_ms(&self) -> f64 {
+        self.p95_ms - self.p50_ms
+    }
+
+    /// Whether a median of `p50_ms` can't be told apart from idle sampling.
+    pub fn contains(&self, p50_ms: f64) -> bool {
+        p50_ms <= self.p95_ms
+    }
+
+    /// `ms` with the idle median taken off, never below zero.
+    pub fn subtract(&self, ms: f64) -> f64 {
+        (ms - self.p50_ms).max(0.0)
+    }
+
+    /// Whether `component`'s latency includes the calibrated pass; probes,
+    /// commands and model requests don't.
+    pub fn applies_to(&self, component: ComponentType) -> bool {
+        self.components.contains(&component)
+    }
+}
+
+/// VS Code processes currently running; calibrating with any of them open
+/// would measure the editor instead of the machine.
+pub fn running_vscode_processes() -> usize {
+    let mut system = System::new();
+    system.refresh_processes();
+    system.processes().values().filter(|process| platform::is_vscode(process)).count()
+}
+
+/// Time the same process-table pass the monitors make, every `interval_ms`
+/// for `duration`, and summarize how long it takes.
+pub async fn measure(interval_ms: u64, duration: Duration) -> Result<NoiseProfile> {
+    let interval = Duration::from_millis(interval_ms.max(1));
+    let deadline = Instant::now() + duration;
+    let mut passes_us = Vec::new();
+    let mut jitter_us = Vec::new();
+
+    info!("Measuring idle noise every {} ms for {:?}", interval_ms, duration);
+    while Instant::now() < deadline {
+        let asleep = Instant::now();
+        tokio::time::sleep(interval).await;
+        jitter_us.push(asleep.elapsed().saturating_sub(interval).as_micros() as u64);
+
+        let start = Instant::now();
+        let mut system = System::new_all();
+        system.refresh_processes();
+        let _ = system.processes().values().filter(|process| platform::is_vscode(process)).count();
+        passes_us.push(start.elapsed().as_micros() as u64);
+    }
+
+    if passes_us.is_empty() {
+        return Err(anyhow!("Calibration took no samples; use a duration longer than the interval"));
+    }
+    passes_us.sort_unstable();
+    jitter_us.sort_unstable();
+
+    let ms = |us: u64| us as f64 / 1000.0;
+    let samples = passes_us.len() as f64;
+    let mean_ms = passes_us.iter().map(|&us| ms(us)).sum::<f64>() / samples;
+    let variance = passes_us.iter().map(|&us| (ms(us) - mean_ms).powi(2)).sum::<f64>() / samples;
+
+    Ok(NoiseProfile {
+        measured_at: Utc::now(),
+        interval_ms,
+        samples: passes_us.len() as u64,
+        mean_ms,
+        stddev_ms: variance.sqrt(),
+        p50_ms: ms(storage::percentile(&passes_us, 0.50)),
+        p95_ms: ms(storage::percentile(&passes_us, 0.95)),
+        p99_ms: ms(storage::percentile(&passes_us, 0.99)),
+        timer_jitter_p95_ms: ms(storage::percentile(&jitter_us, 0.95)),
+        components: sampled_components(),
+    })
+}
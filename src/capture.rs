@@ -0,0 +1,442 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::connectivity::ConnectivityTracker;
+use crate::daemon;
+use crate::environment::Environment;
+use crate::filter::EventFilter;
+use crate::ipc;
+use crate::models::{AuditEntry, ComponentType};
+use crate::monitor::LatencyMonitor;
+use crate::progress::Progress;
+use crate::report;
+use crate::stats::{self, RankTest};
+use crate::storage::{self, MetricsStorage};
+use crate::timerange::TimeRange;
+use crate::units;
+use crate::workspace::Workspaces;
+
+/// Sampling interval while capturing, unless `--interval` says otherwise.
+pub const CAPTURE_INTERVAL_MS: u64 = 25;
+
+/// Tag set on every event recorded during a capture, with its label as value.
+pub const CAPTURE_TAG: &str = "capture";
+
+/// Tag naming the A/B capture phase (`a` or `b`) an event was recorded in.
+pub const PHASE_TAG: &str = "capture_phase";
+
+/// p-value below which an A/B difference is reported as real.
+const SIGNIFICANCE: f64 = 0.05;
+
+/// Events tagged per transaction once a capture ends.
+const TAG_BATCH_SIZE: u32 = 1000;
+
+/// A finished capture: every monitor ran at `interval_ms` from `started`
+/// to `ended`, and the events recorded are tagged `capture=<label>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capture {
+    pub label: String,
+    pub started: DateTime<Utc>,
+    pub ended: DateTime<Utc>,
+    pub interval_ms: u64,
+    /// Ctrl+C ended the capture before its duration was up
+    pub stopped_early: bool,
+    pub events: u64,
+}
+
+impl Capture {
+    /// The captured events: the session's window, narrowed to its tag.
+    pub fn range(&self) -> Result<TimeRange> {
+        let window = TimeRange {
+            since: Some(self.started),
+            until: Some(self.ended),
+            ..TimeRange::default()
+        };
+        Ok(window.matching(EventFilter::parse(&format!("tag.{} = '{}'", CAPTURE_TAG, self.label))?))
+    }
+
+    /// `capture-<label>-<start time>`, for the report and bundle names.
+    pub fn file_stem(&self) -> String {
+        format!("capture-{}-{}", self.label, self.started.format("%Y%m%dT%H%M%SZ"))
+    }
+}
+
+/// Labels end up in file names and filter expressions, so keep them plain.
+pub fn validate_label(label: &str) -> Result<()> {
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid capture label '{}': use letters, digits, '-', '_' and '.'",
+            label
+        ))
+    }
+}
+
+/// Start every monitor at `interval_ms`, storing nothing until the
+/// monitor's `run_until`. Refuses to run beside a monitor daemon, which
+/// would record the same activity twice.
+async fn start_monitors(config: &Config, interval_ms: u64) -> Result<(MetricsStorage, LatencyMonitor)> {
+    if let Some(pid) = daemon::running_pid(config) {
+        return Err(anyhow!(
+            "The monitor daemon is running (PID {}); stop it before capturing so events aren't recorded twice",
+            pid
+        ));
+    }
+
+    let mut config = config.clone();
+    config.monitoring.enabled_components = vec!["all".to_string()];
+
+    let mut storage = MetricsStorage::new(&config.storage.database_path)
+        .await?
+        .with_latency_histograms(&config.histograms);
+    if config.monitoring.connectivity.enabled {
+        let tracker = ConnectivityTracker::new(&config.monitoring.connectivity);
+        tokio::spawn(tracker.clone().run());
+        storage = storage.with_connectivity(&tracker);
+    }
+    let storage = storage.with_write_buffer(&config.storage.write_buffer);
+    let workspaces = Workspaces::default();
+    if config.ipc.enabled {
+        let (config, storage, workspaces) = (config.clone(), storage.clone(), workspaces.clone());
+        tokio::spawn(async move {
+            // Extension and shell timings are only captured while we own the socket
+            if let Err(e) = ipc::serve(config, storage, workspaces).await {
+                warn!("Extension IPC server stopped: {}", e);
+            }
+        });
+    }
+
+    let mut monitor = LatencyMonitor::new(config.clone(), storage.clone()).await?;
+    monitor.follow_workspaces(&workspaces);
+    monitor.start_all_monitoring(Some(interval_ms)).await?;
+    monitor.start_self_monitoring();
+    monitor.start_load_watchdog();
+    Ok((storage, monitor))
+}
+
+/// Run every monitor at `interval_ms` for `duration` (or until Ctrl+C),
+/// store what they record and tag it with `label`.
+pub async fn run(config: &Config, label: &str, duration: Duration, interval_ms: u64) -> Result<(MetricsStorage, Capture)> {
+    validate_label(label)?;
+    let duration = duration.to_std().map_err(|_| anyhow!("Capture duration must be positive"))?;
+    let (storage, mut monitor) = start_monitors(config, interval_ms).await?;
+
+    info!("Capturing '{}' for {:?} at {} ms intervals", label, duration, interval_ms);
+    let started = Utc::now();
+    let mut stopped_early = false;
+    monitor
+        .run_until(async {
+            tokio::select! {
+                _ = tokio::time::sleep(duration) => {}
+                _ = tokio::signal::ctrl_c() => stopped_early = true,
+            }
+            Ok(())
+        })
+        .await?;
+    let ended = Utc::now();
+
+    let window = TimeRange {
+        since: Some(started),
+        until: Some(ended),
+        ..TimeRange::default()
+    };
+    let tags = [(CAPTURE_TAG.to_string(), label.to_string())];
+    let events = storage
+        .tag_matching_events(&window.to_sql(), &tags, TAG_BATCH_SIZE, &Progress::hidden())
+        .await?;
+
+    let capture = Capture {
+        label: label.to_string(),
+        started,
+        ended,
+        interval_ms,
+        stopped_early,
+        events,
+    };
+    storage
+        .record_audit(
+            &AuditEntry::new(&AuditEntry::cli_actor(), "capture.run", Some(label))
+                .with_change(None, Some(serde_json::to_value(&capture)?)),
+        )
+        .await?;
+    info!("Captured {} events for '{}'", events, label);
+
+    Ok((storage, capture))
+}
+
+/// The capture's report in `format`, over the whole session whatever
+/// `reports.working_hours_only` says.
+pub async fn report(storage: &MetricsStorage, config: &Config, capture: &Capture, format: &str) -> Result<String> {
+    let mut config = config.clone();
+    config.reports.working_hours_only = false;
+    report::generate_report(storage, &config, &capture.range()?, format).await
+}
+
+/// Write an incident bundle into `dir`: the report, a SQLite snapshot of
+/// the captured events, the configuration in effect and `capture.json`
+/// describing the session, ready to attach to a bug report.
+pub async fn write_bundle(
+    storage: &MetricsStorage,
+    config: &Config,
+    capture: &Capture,
+    report: &str,
+    format: &str,
+    dir: &Path,
+) -> Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    tokio::fs::write(dir.join(format!("report.{}", format)), report).await?;
+    storage
+        .export_sqlite(&dir.join("events.db"), &capture.range()?, &Progress::hidden())
+        .await?;
+    tokio::fs::write(dir.join("config.toml"), toml::to_string_pretty(&config.scrubbed())?).await?;
+
+    let summary = serde_json::json!({
+        "capture": capture,
+        "environment": Environment::collect(config)?,
+    });
+    tokio::fs::write(dir.join("capture.json"), serde_json::to_string_pretty(&summary)?).await?;
+
+    info!("Wrote incident bundle to {}", dir.display());
+    Ok(())
+}
+
+/// One stretch of an A/B capture during which setup `phase` was active.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseWindow {
+    pub phase: &'static str,
+    pub round: u32,
+    pub started: DateTime<Utc>,
+    pub ended: DateTime<Utc>,
+    pub events: u64,
+}
+
+/// Latency of one component over every window of one phase.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseStats {
+    pub events: u64,
+    pub avg_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl PhaseStats {
+    fn new(sorted: &[u64]) -> Self {
+        let ms = |us: u64| us as f64 / 1000.0;
+        Self {
+            events: sorted.len() as u64,
+            avg_ms: if sorted.is_empty() {
+                0.0
+            } else {
+                ms(sorted.iter().sum::<u64>()) / sorted.len() as f64
+            },
+            p50_ms: ms(storage::percentile(sorted, 0.50)),
+            p95_ms: ms(storage::percentile(sorted, 0.95)),
+        }
+    }
+}
+
+/// How one component's latency differed between phase A and phase B.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentComparison {
+    pub component: String,
+    pub a: PhaseStats,
+    pub b: PhaseStats,
+    /// Change in the median from A to B; `None` when A has no events
+    pub p50_change_percent: Option<f64>,
+    /// `None` when either phase has too few events to test
+    pub test: Option<RankTest>,
+    /// The medians differ by less than the calibrated idle noise band
+    pub within_noise: bool,
+}
+
+impl ComponentComparison {
+    pub fn verdict(&self) -> &'static str {
+        match self.test {
+            None => "too few events",
+            Some(test) if test.p_value >= SIGNIFICANCE => "no significant difference",
+            Some(_) if self.within_noise => "within idle noise",
+            Some(test) if test.b_greater_probability > 0.5 => "B slower",
+            Some(_) => "B faster",
+        }
+    }
+}
+
+/// A finished A/B capture: the windows recorded for each setup, and how
+/// every component's latency compared between them.
+#[derive(Debug, Clone, Serialize)]
+pub struct AbCapture {
+    pub label: String,
+    pub a: String,
+    pub b: String,
+    pub interval_ms: u64,
+    pub windows: Vec<PhaseWindow>,
+    /// Ctrl+C ended the capture before every phase had run
+    pub stopped_early: bool,
+    pub comparisons: Vec<ComponentComparison>,
+}
+
+/// Capture setup `a` then setup `b` for `duration` each, `rounds` times,
+/// prompting on stdout before each phase so the user can switch setups
+/// (with stdin closed, phases follow each other without waiting). Events
+/// are tagged with `label` and their phase, then compared per component.
+pub async fn run_ab(
+    config: &Config,
+    label: &str,
+    (a, b): (&str, &str),
+    duration: Duration,
+    rounds: u32,
+    interval_ms: u64,
+) -> Result<(MetricsStorage, AbCapture)> {
+    validate_label(label)?;
+    let duration = duration.to_std().map_err(|_| anyhow!("Capture duration must be positive"))?;
+    let (storage, mut monitor) = start_monitors(config, interval_ms).await?;
+
+    // The monitors keep sampling while we wait for the user; only the
+    // events inside a phase's window are tagged and compared
+    let mut windows = Vec::new();
+    let mut stopped_early = false;
+    monitor
+        .run_until(async {
+            let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+            for round in 1..=rounds.max(1) {
+                for (phase, setup) in [("a", a), ("b", b)] {
+                    println!(
+                        "Phase {} ({}), round {}/{}: press Enter to capture for {}",
+                        phase.to_uppercase(),
+                        setup,
+                        round,
+                        rounds.max(1),
+                        units::format_duration(duration)
+                    );
+                    let ready = tokio::select! {
+                        line = stdin.next_line() => line.is_ok(),
+                        _ = tokio::signal::ctrl_c() => false,
+                    };
+                    if !ready {
+                        stopped_early = true;
+                        return Ok(());
+                    }
+
+                    let started = Utc::now();
+                    let interrupted = tokio::select! {
+                        _ = tokio::time::sleep(duration) => false,
+                        _ = tokio::signal::ctrl_c() => true,
+                    };
+                    windows.push(PhaseWindow {
+                        phase,
+                        round,
+                        started,
+                        ended: Utc::now(),
+                        events: 0,
+                    });
+                    if interrupted {
+                        stopped_early = true;
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+    for window in &mut windows {
+        let range = TimeRange {
+            since: Some(window.started),
+            until: Some(window.ended),
+            ..TimeRange::default()
+        };
+        let tags = [
+            (CAPTURE_TAG.to_string(), label.to_string()),
+            (PHASE_TAG.to_string(), window.phase.to_string()),
+        ];
+        window.events = storage
+            .tag_matching_events(&range.to_sql(), &tags, TAG_BATCH_SIZE, &Progress::hidden())
+            .await?;
+    }
+
+    let mut capture = AbCapture {
+        label: label.to_string(),
+        a: a.to_string(),
+        b: b.to_string(),
+        interval_ms,
+        windows,
+        stopped_early,
+        comparisons: Vec::new(),
+    };
+    capture.comparisons = compare(&storage, &capture).await?;
+
+    storage
+        .record_audit(&AuditEntry::new(&AuditEntry::cli_actor(), "capture.ab", Some(label)).with_change(
+            None,
+            Some(serde_json::json!({
+                "a": capture.a,
+                "b": capture.b,
+                "interval_ms": capture.interval_ms,
+                "windows": capture.windows,
+                "stopped_early": capture.stopped_early,
+            })),
+        ))
+        .await?;
+
+    Ok((storage, capture))
+}
+
+/// Compare each component's latency across every A window against every
+/// B window, most events first. Once `calibrate` has run, differences
+/// smaller than the machine's idle noise aren't called significant.
+async fn compare(storage: &MetricsStorage, capture: &AbCapture) -> Result<Vec<ComponentComparison>> {
+    let (Some(first), Some(last)) = (capture.windows.first(), capture.windows.last()) else {
+        return Ok(Vec::new());
+    };
+    let phase_range = |phase: &str| -> Result<TimeRange> {
+        let window = TimeRange {
+            since: Some(first.started),
+            until: Some(last.ended),
+            ..TimeRange::default()
+        };
+        Ok(window.matching(EventFilter::parse(&format!(
+            "tag.{} = '{}' and tag.{} = '{}'",
+            CAPTURE_TAG, capture.label, PHASE_TAG, phase
+        ))?))
+    };
+    let a = storage.get_durations_by_component(&phase_range("a")?).await?;
+    let b = storage.get_durations_by_component(&phase_range("b")?).await?;
+
+    let noise = storage.latest_noise_profile().await?;
+
+    let components: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    let mut comparisons: Vec<ComponentComparison> = components
+        .into_iter()
+        .map(|component| {
+            let a = a.get(component).map(Vec::as_slice).unwrap_or_default();
+            let b = b.get(component).map(Vec::as_slice).unwrap_or_default();
+            let (a_stats, b_stats) = (PhaseStats::new(a), PhaseStats::new(b));
+            ComponentComparison {
+                component: component.clone(),
+                p50_change_percent: (a_stats.p50_ms > 0.0)
+                    .then(|| (b_stats.p50_ms - a_stats.p50_ms) / a_stats.p50_ms * 100.0),
+                test: stats::mann_whitney(a, b),
+                within_noise: noise.as_ref().is_some_and(|profile| {
+                    ComponentType::parse(component).is_some_and(|component| profile.applies_to(component))
+                        && (b_stats.p50_ms - a_stats.p50_ms).abs() < profile.band_ms()
+                }),
+                a: a_stats,
+                b: b_stats,
+            }
+        })
+        .collect();
+    comparisons.sort_by_key(|c| std::cmp::Reverse(c.a.events + c.b.events));
+    Ok(comparisons)
+}
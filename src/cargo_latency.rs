@@ -0,0 +1,415 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::ids;
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::storage::MetricsStorage;
+
+/// File the rustc wrapper appends one timing per line to; set by the
+/// `cargo latency` process for the cargo it runs.
+const TIMINGS_ENV: &str = "VSCODE_LATENCY_MONITOR_RUSTC_TIMINGS";
+/// `RUSTC_WRAPPER` from before `cargo latency` replaced it (e.g. sccache),
+/// which the wrapper runs rustc through in turn.
+const INNER_WRAPPER_ENV: &str = "VSCODE_LATENCY_MONITOR_RUSTC_WRAPPER";
+/// Config file to read instead of the default one.
+const CONFIG_ENV: &str = "VSCODE_LATENCY_MONITOR_CONFIG";
+/// Options cargo accepts before the subcommand whose value is the next
+/// argument.
+const VALUE_OPTIONS: &[&str] = &["--manifest-path", "--color", "--config", "--explain", "-Z", "-C"];
+
+/// One rustc run, timed by the wrapper.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RustcTiming {
+    crate_name: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    wall_ms: f64,
+    /// Compiled with `-C incremental`, i.e. a workspace crate in a profile
+    /// with incremental compilation on
+    incremental: bool,
+    /// A workspace member rather than a dependency
+    primary: bool,
+    /// Built with `--test`
+    test: bool,
+    success: bool,
+    user_cpu_ms: Option<f64>,
+    system_cpu_ms: Option<f64>,
+    max_rss_kb: Option<u64>,
+}
+
+/// Entry point of the `cargo-latency` binary, given its arguments without
+/// the program name. Cargo runs it as `cargo-latency latency <args>` for
+/// `cargo latency <args>`, and the cargo it starts runs it again as
+/// `RUSTC_WRAPPER` for every rustc. Returns the exit code to exit with.
+pub fn main(args: Vec<OsString>) -> Result<i32> {
+    if std::env::var_os(TIMINGS_ENV).is_some() && args.first().is_some_and(|program| is_compiler(program)) {
+        return wrap_rustc(&args);
+    }
+
+    let args = match args.split_first() {
+        Some((first, rest)) if first == "latency" => rest.to_vec(),
+        _ => args,
+    };
+    if args.is_empty() {
+        eprintln!("Usage: cargo latency <cargo command> [args...]");
+        eprintln!("       e.g. cargo latency build --release");
+        return Ok(2);
+    }
+    run_cargo(&args)
+}
+
+/// `rustc` or `clippy-driver` (which `cargo clippy` sets as the workspace
+/// wrapper), as cargo passes it to `RUSTC_WRAPPER`.
+fn is_compiler(program: &OsStr) -> bool {
+    Path::new(program)
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .is_some_and(|stem| stem.starts_with("rustc") || stem.starts_with("clippy-driver"))
+}
+
+/// Run the compiler cargo asked for, appending its timing to the timings
+/// file. Failing to record never fails the build.
+fn wrap_rustc(args: &[OsString]) -> Result<i32> {
+    let mut command = match std::env::var_os(INNER_WRAPPER_ENV).filter(|wrapper| !wrapper.is_empty()) {
+        Some(wrapper) => {
+            let mut command = Command::new(wrapper);
+            command.args(args);
+            command
+        }
+        None => {
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            command
+        }
+    };
+
+    let start = Utc::now();
+    let clock = Instant::now();
+    let status = command
+        .env_remove(TIMINGS_ENV)
+        .status()
+        .map_err(|e| anyhow!("Can't run {}: {}", args[0].to_string_lossy(), e))?;
+    let wall = clock.elapsed();
+
+    // Cargo also asks rustc for its version and target info, under the
+    // crate name `___`; only real compilations are worth keeping
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    if let Some(crate_name) = option_value(&args, "--crate-name").filter(|name| *name != "___") {
+        let (user_cpu_ms, system_cpu_ms, max_rss_kb) = child_usage();
+        let timing = RustcTiming {
+            crate_name: crate_name.to_string(),
+            start,
+            end: Utc::now(),
+            wall_ms: wall.as_secs_f64() * 1000.0,
+            incremental: args
+                .windows(2)
+                .any(|pair| pair[0] == "-C" && pair[1].starts_with("incremental="))
+                || args.iter().any(|arg| arg.starts_with("-Cincremental=")),
+            primary: std::env::var_os("CARGO_PRIMARY_PACKAGE").is_some(),
+            test: args.iter().any(|arg| arg == "--test"),
+            success: status.success(),
+            user_cpu_ms,
+            system_cpu_ms,
+            max_rss_kb,
+        };
+        if let Some(path) = std::env::var_os(TIMINGS_ENV) {
+            let _ = append_timing(Path::new(&path), &timing);
+        }
+    }
+
+    Ok(status.code().unwrap_or(1))
+}
+
+fn option_value<'a>(args: &'a [String], option: &str) -> Option<&'a str> {
+    args.windows(2).find(|pair| pair[0] == option).map(|pair| pair[1].as_str())
+}
+
+/// One line per timing; rustc runs in parallel, so each is written in a
+/// single append.
+fn append_timing(path: &Path, timing: &RustcTiming) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{}\n", serde_json::to_string(timing)?).as_bytes())?;
+    Ok(())
+}
+
+/// CPU time and peak memory of the compiler just waited for, which is this
+/// wrapper's only child.
+#[cfg(unix)]
+fn child_usage() -> (Option<f64>, Option<f64>, Option<u64>) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return (None, None, None);
+    }
+    let ms = |time: libc::timeval| time.tv_sec as f64 * 1000.0 + time.tv_usec as f64 / 1000.0;
+    // Bytes on macOS, kilobytes elsewhere
+    let max_rss_kb = if cfg!(target_os = "macos") { usage.ru_maxrss as u64 / 1024 } else { usage.ru_maxrss as u64 };
+    (Some(ms(usage.ru_utime)), Some(ms(usage.ru_stime)), Some(max_rss_kb))
+}
+
+#[cfg(not(unix))]
+fn child_usage() -> (Option<f64>, Option<f64>, Option<u64>) {
+    (None, None, None)
+}
+
+/// Run cargo with `args`, this binary as its rustc wrapper, and store the
+/// build and each rustc run as `BuildTool` events.
+fn run_cargo(args: &[OsString]) -> Result<i32> {
+    // Loaded first so event ids follow `storage.event_ids`; a broken config
+    // still lets the build run
+    let config_path = std::env::var_os(CONFIG_ENV).map(PathBuf::from).unwrap_or_else(Config::default_path);
+    let config = Config::load(&config_path);
+    if let Ok(config) = &config {
+        ids::configure(config.storage.event_ids);
+    }
+
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let timings = std::env::temp_dir().join(format!("cargo-latency-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&timings);
+
+    let start = Utc::now();
+    let clock = Instant::now();
+    let status = Command::new(&cargo)
+        .args(args)
+        .env("RUSTC_WRAPPER", std::env::current_exe()?)
+        .env(INNER_WRAPPER_ENV, std::env::var_os("RUSTC_WRAPPER").unwrap_or_default())
+        .env(TIMINGS_ENV, &timings)
+        .status()
+        .map_err(|e| anyhow!("Can't run {}: {}", cargo.to_string_lossy(), e))?;
+    let wall = clock.elapsed();
+
+    let rustc = read_timings(&timings);
+    let _ = fs::remove_file(&timings);
+
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    let events = build_events(&args, start, wall, status.code(), &rustc);
+    if let Err(e) = config.and_then(|config| store(&config, &events)) {
+        eprintln!("cargo-latency: build timings not recorded: {:#}", e);
+    }
+
+    Ok(status.code().unwrap_or(1))
+}
+
+fn read_timings(path: &Path) -> Vec<RustcTiming> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The cargo command first, then its rustc runs, which share its
+/// `build_id`.
+fn build_events(
+    args: &[String],
+    start: DateTime<Utc>,
+    wall: Duration,
+    exit_code: Option<i32>,
+    rustc: &[RustcTiming],
+) -> Vec<LatencyEvent> {
+    let subcommand = subcommand(args).unwrap_or_default().to_string();
+    let incremental: Vec<&RustcTiming> = rustc.iter().filter(|timing| timing.incremental).collect();
+    let cpu_ms: f64 = rustc
+        .iter()
+        .map(|timing| timing.user_cpu_ms.unwrap_or(0.0) + timing.system_cpu_ms.unwrap_or(0.0))
+        .sum();
+    let success = exit_code == Some(0);
+    let build_id = format!("{}-{}", start.timestamp_millis(), std::process::id());
+
+    let mut build = LatencyEvent::builder(ComponentType::BuildTool, EventSource::CommandExecution)
+        .kind(EventKind::CommandRun)
+        .duration(wall)
+        .description(format!("cargo {}", args.join(" ")))
+        .timestamp(start + chrono::Duration::from_std(wall).unwrap_or_default())
+        .metadata(json!({
+            "build_id": build_id,
+            "cargo_command": subcommand,
+            "args": args,
+            "cwd": std::env::current_dir().ok(),
+            "exit_code": exit_code,
+            "success": success,
+            // Nothing was recompiled
+            "fresh": rustc.is_empty(),
+            "rustc_invocations": rustc.len(),
+            "incremental_invocations": incremental.len(),
+            "incremental_ms": incremental.iter().map(|timing| timing.wall_ms).sum::<f64>(),
+            "rustc_cpu_ms": cpu_ms,
+            "peak_memory_kb": rustc.iter().filter_map(|timing| timing.max_rss_kb).max(),
+        }))
+        .build();
+    if !success {
+        build.metadata["error"] = json!(format!("cargo exited with {:?}", exit_code));
+    }
+
+    let mut events = vec![build];
+    for timing in rustc {
+        let mut description = format!("rustc {}", timing.crate_name);
+        if timing.test {
+            description.push_str(" (test)");
+        }
+        if timing.incremental {
+            description.push_str(" (incremental)");
+        }
+        events.push(
+            LatencyEvent::builder(ComponentType::BuildTool, EventSource::CommandExecution)
+                .kind(EventKind::CommandRun)
+                .duration(Duration::from_secs_f64(timing.wall_ms.max(0.0) / 1000.0))
+                .description(description)
+                .timestamp(timing.end)
+                .metadata(json!({
+                    "build_id": build_id,
+                    "cargo_command": subcommand,
+                    "crate_name": timing.crate_name,
+                    "incremental": timing.incremental,
+                    "primary": timing.primary,
+                    "test": timing.test,
+                    "success": timing.success,
+                    "start": timing.start,
+                    "user_cpu_ms": timing.user_cpu_ms,
+                    "system_cpu_ms": timing.system_cpu_ms,
+                    "memory_kb": timing.max_rss_kb,
+                }))
+                .build(),
+        );
+    }
+    events
+}
+
+/// The cargo subcommand; `+nightly` and options, with their values, may
+/// come before it.
+fn subcommand(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if VALUE_OPTIONS.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('+') && !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+fn store(config: &Config, events: &[LatencyEvent]) -> Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let storage = MetricsStorage::new(&config.storage.database_path).await?;
+        for event in events {
+            storage.store_event(event).await?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn timing(crate_name: &str, wall_ms: f64, incremental: bool, test: bool) -> RustcTiming {
+        let end = Utc::now();
+        RustcTiming {
+            crate_name: crate_name.to_string(),
+            start: end - chrono::Duration::milliseconds(wall_ms as i64),
+            end,
+            wall_ms,
+            incremental,
+            primary: incremental,
+            test,
+            success: true,
+            user_cpu_ms: Some(wall_ms * 2.0),
+            system_cpu_ms: Some(10.0),
+            max_rss_kb: Some(wall_ms as u64 * 100),
+        }
+    }
+
+    #[test]
+    fn subcommand_skips_toolchains_and_options() {
+        assert_eq!(subcommand(&args("build --release")), Some("build"));
+        assert_eq!(subcommand(&args("+nightly -v test")), Some("test"));
+        assert_eq!(subcommand(&args("--manifest-path a/Cargo.toml -Z unstable-options check")), Some("check"));
+        assert_eq!(subcommand(&args("-C sub clippy")), Some("clippy"));
+        assert_eq!(subcommand(&args("--version")), None);
+    }
+
+    #[test]
+    fn compilers_are_recognised_by_file_stem() {
+        assert!(is_compiler(OsStr::new("/home/me/.rustup/toolchains/stable/bin/rustc")));
+        assert!(is_compiler(OsStr::new("clippy-driver")));
+        assert!(is_compiler(OsStr::new("rustc.exe")));
+        assert!(!is_compiler(OsStr::new("latency")));
+        assert!(!is_compiler(OsStr::new("sccache")));
+    }
+
+    #[test]
+    fn option_values_follow_their_option() {
+        let rustc = args("--crate-name demo --edition=2021 src/lib.rs --crate-type");
+        assert_eq!(option_value(&rustc, "--crate-name"), Some("demo"));
+        assert_eq!(option_value(&rustc, "--crate-type"), None);
+        assert_eq!(option_value(&rustc, "--edition"), None);
+    }
+
+    #[test]
+    fn build_event_sums_the_rustc_runs_it_started() {
+        let start = Utc::now();
+        let rustc = [timing("serde", 800.0, false, false), timing("app", 300.0, true, true)];
+        let events = build_events(&args("build --release"), start, Duration::from_secs(2), Some(0), &rustc);
+        assert_eq!(events.len(), 3);
+
+        let build = &events[0];
+        assert_eq!(build.description, "cargo build --release");
+        assert_eq!(build.duration, Duration::from_secs(2));
+        assert_eq!(build.timestamp, start + chrono::Duration::seconds(2));
+        assert_eq!(build.metadata["cargo_command"], "build");
+        assert_eq!(build.metadata["success"], true);
+        assert_eq!(build.metadata["fresh"], false);
+        assert_eq!(build.metadata["rustc_invocations"], 2);
+        assert_eq!(build.metadata["incremental_invocations"], 1);
+        assert_eq!(build.metadata["incremental_ms"], 300.0);
+        assert_eq!(build.metadata["rustc_cpu_ms"], 1600.0 + 600.0 + 20.0);
+        assert_eq!(build.metadata["peak_memory_kb"], 80_000);
+        assert!(build.metadata.get("error").is_none());
+
+        assert_eq!(events[1].description, "rustc serde");
+        assert_eq!(events[2].description, "rustc app (test) (incremental)");
+        assert_eq!(events[2].duration, Duration::from_millis(300));
+        for event in &events[1..] {
+            assert_eq!(event.metadata["build_id"], build.metadata["build_id"]);
+            assert_eq!(event.metadata["cargo_command"], "build");
+        }
+    }
+
+    #[test]
+    fn failed_and_fresh_builds_are_marked() {
+        let events = build_events(&args("test"), Utc::now(), Duration::from_millis(150), Some(101), &[]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].metadata["success"], false);
+        assert_eq!(events[0].metadata["fresh"], true);
+        assert_eq!(events[0].metadata["error"], "cargo exited with Some(101)");
+        assert!(events[0].metadata["peak_memory_kb"].is_null());
+    }
+
+    #[test]
+    fn timings_file_skips_lines_it_cannot_read() {
+        let path = std::env::temp_dir().join(format!("vscode-latency-monitor-cargo-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        append_timing(&path, &timing("first", 100.0, false, false)).unwrap();
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"truncated\n").unwrap();
+        append_timing(&path, &timing("second", 200.0, true, false)).unwrap();
+
+        let timings = read_timings(&path);
+        fs::remove_file(&path).unwrap();
+        let names: Vec<&str> = timings.iter().map(|timing| timing.crate_name.as_str()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert!(read_timings(&path).is_empty());
+    }
+}
@@ -1,14 +1,109 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::fs;
 
+use crate::advice::AdviceConfig;
+use crate::alerts::{AlertAction, AlertDigestConfig, AlertRule};
+use crate::backend::StorageBackend;
+use crate::capture;
+use crate::cmdline::CmdlineConfig;
+use crate::connectivity::ConnectivityConfig;
+use crate::cron::CronSchedule;
+use crate::derived::DerivedMetricsConfig;
+use crate::editors::EditorsConfig;
+use crate::email::EmailConfig;
+use crate::extensions::ExtensionAttributionConfig;
+use crate::federation::FederationConfig;
+use crate::histogram::HistogramConfig;
+use crate::ids::EventIds;
+use crate::event_queue::{LoadSheddingConfig, MemoryBudgetConfig, OverflowPolicy};
+use crate::file_io::IoBackend;
+use crate::ipc::IpcConfig;
+use crate::loopback::LoopbackConfig;
+use crate::model_proxy::ModelProxyConfig;
+use crate::netprobe::NetworkProbeConfig;
+use crate::notifications::NotificationsConfig;
+use crate::object_store::ObjectStorageConfig;
+use crate::otel::OtelConfig;
+use crate::postgres::PostgresConfig;
+use crate::report::ReportsConfig;
+use crate::scheduling::SchedulingConfig;
+use crate::sources::DataSourceConfig;
+use crate::tenancy::TenancyConfig;
+use crate::timerange::{parse_duration, WorkingHoursConfig};
+use crate::webhook::WebhookConfig;
+
+/// Stands in for secret values in [`Config::scrubbed`].
+const REDACTED: &str = "<redacted>";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub monitoring: MonitoringConfig,
     pub dashboard: DashboardConfig,
     pub storage: StorageConfig,
     pub integrations: IntegrationsConfig,
+    #[serde(default)]
+    pub reports: ReportsConfig,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub schedules: Vec<ScheduleConfig>,
+    #[serde(default)]
+    pub object_storage: ObjectStorageConfig,
+    #[serde(default)]
+    pub ipc: IpcConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    #[serde(default)]
+    pub alert_digest: AlertDigestConfig,
+    #[serde(default)]
+    pub working_hours: WorkingHoursConfig,
+    #[serde(default)]
+    pub derived_metrics: DerivedMetricsConfig,
+    #[serde(default)]
+    pub histograms: HistogramConfig,
+    #[serde(default)]
+    pub advice: AdviceConfig,
+    #[serde(default)]
+    pub model_proxy: ModelProxyConfig,
+}
+
+/// A named report delivery target.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    pub name: String,
+    /// Email recipients for this schedule
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// Time range covered by each delivered report
+    #[serde(default = "default_schedule_since")]
+    pub since: String,
+    /// When the running monitor delivers this report by itself: a cron
+    /// expression in local time (`0 8 * * 1-5`) or `@daily`, `@weekly`, ...
+    /// Without one, reports are only delivered by `report --deliver`.
+    #[serde(default)]
+    pub cron: Option<String>,
+    /// Directory each delivered report is also written to
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+    /// Format of the written report (json, csv, html)
+    #[serde(default = "default_schedule_format")]
+    pub format: String,
+    /// Webhooks (by name) the report is posted to; when empty, every webhook
+    /// subscribed to `report`
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+}
+
+fn default_schedule_since() -> String {
+    "24h".to_string()
+}
+
+fn default_schedule_format() -> String {
+    "html".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +112,135 @@ pub struct MonitoringConfig {
     pub precision: String,
     pub buffer_size: usize,
     pub enabled_components: Vec<String>,
+    /// Extension host restarts/crashes within the window that count as a crash loop
+    #[serde(default = "default_crash_loop_threshold")]
+    pub crash_loop_threshold: usize,
+    #[serde(default = "default_crash_loop_window_secs")]
+    pub crash_loop_window_secs: u64,
+    #[serde(default)]
+    pub ollama_probe: OllamaProbeConfig,
+    #[serde(default)]
+    pub copilot_proxy: CopilotProxyConfig,
+    #[serde(default)]
+    pub loopback: LoopbackConfig,
+    #[serde(default)]
+    pub disk: DiskConfig,
+    #[serde(default)]
+    pub network: NetworkProbeConfig,
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    #[serde(default)]
+    pub memory_budget: MemoryBudgetConfig,
+    #[serde(default)]
+    pub load_shedding: LoadSheddingConfig,
+    /// What a full event channel lane (`buffer_size` events) does with new
+    /// events: `drop`, `oldest` or `block`
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    #[serde(default)]
+    pub cmdline: CmdlineConfig,
+    #[serde(default)]
+    pub extensions: ExtensionAttributionConfig,
+    #[serde(default)]
+    pub editors: EditorsConfig,
+    #[serde(default)]
+    pub connectivity: ConnectivityConfig,
+}
+
+/// Active latency probe against a local Ollama server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaProbeConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub model: String,
+    /// Kept tiny so the probe itself doesn't load the machine
+    pub prompt: String,
+    pub max_tokens: u32,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for OllamaProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://localhost:11434".to_string(),
+            model: "llama3.2".to_string(),
+            prompt: "Say OK.".to_string(),
+            max_tokens: 4,
+            interval_secs: 60,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Disk I/O monitoring, the `disk` component: per-process reads, writes and
+/// time blocked on I/O for VS Code, plus a write + fsync probe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// Time a small write and fsync every `probe_interval_secs`; off by
+    /// default since it writes to your disk
+    pub probe_enabled: bool,
+    /// Directory the probe writes in, on the disk your workspaces live on
+    /// (default: home directory)
+    pub probe_dir: Option<PathBuf>,
+    pub probe_interval_secs: u64,
+    pub probe_bytes: usize,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            probe_enabled: false,
+            probe_dir: None,
+            probe_interval_secs: 10,
+            probe_bytes: 4096,
+        }
+    }
+}
+
+/// Local HTTP CONNECT proxy that times Copilot's HTTPS traffic. Point VS
+/// Code's `http.proxy` at `listen`; tunnels are passed through untouched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CopilotProxyConfig {
+    pub enabled: bool,
+    pub listen: String,
+    /// Tunnels to these hosts (or their subdomains) are measured; all
+    /// others are forwarded without recording anything
+    pub hosts: Vec<String>,
+    pub connect_timeout_secs: u64,
+    /// A response is considered finished after this long without data
+    pub idle_timeout_ms: u64,
+    /// Client writes smaller than this (HTTP/2 window updates, pings) don't
+    /// start a new request
+    pub min_request_bytes: usize,
+}
+
+impl Default for CopilotProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:8899".to_string(),
+            hosts: vec![
+                "api.githubcopilot.com".to_string(),
+                "copilot-proxy.githubusercontent.com".to_string(),
+            ],
+            connect_timeout_secs: 10,
+            idle_timeout_ms: 2000,
+            min_request_bytes: 256,
+        }
+    }
+}
+
+fn default_crash_loop_threshold() -> usize {
+    3
+}
+
+fn default_crash_loop_window_secs() -> u64 {
+    300
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +249,56 @@ pub struct DashboardConfig {
     pub auto_refresh_ms: u64,
     pub theme: String,
     pub enable_websocket: bool,
+    /// Extra databases or remote dashboards selectable in the UI
+    #[serde(default)]
+    pub sources: Vec<DataSourceConfig>,
+    /// Merged read queries across this instance and `sources`
+    #[serde(default)]
+    pub federation: FederationConfig,
+    /// Per-team databases selected by the caller's API token
+    #[serde(default)]
+    pub tenancy: TenancyConfig,
+    /// Time ranges offered by the dashboard, bound in order to keys 1-9
+    #[serde(default = "default_dashboard_ranges")]
+    pub ranges: Vec<DashboardRange>,
+    /// `since` of the range the dashboard opens with when its URL names none
+    #[serde(default = "default_dashboard_range")]
+    pub default_range: String,
+}
+
+/// One entry of the dashboard's range switcher, e.g. the last 24 hours in
+/// 15 minute buckets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardRange {
+    /// How far back the range starts (`1h`, `7d`, ...); also its name in URLs
+    pub since: String,
+    /// Bucket width of the trend chart
+    pub bucket: String,
+    pub label: String,
+}
+
+impl DashboardRange {
+    fn new(since: &str, bucket: &str, label: &str) -> Self {
+        Self {
+            since: since.to_string(),
+            bucket: bucket.to_string(),
+            label: label.to_string(),
+        }
+    }
+}
+
+fn default_dashboard_ranges() -> Vec<DashboardRange> {
+    vec![
+        DashboardRange::new("1h", "1m", "Last hour"),
+        DashboardRange::new("6h", "5m", "Last 6 hours"),
+        DashboardRange::new("24h", "15m", "Last 24 hours"),
+        DashboardRange::new("7d", "1h", "Last 7 days"),
+        DashboardRange::new("30d", "6h", "Last 30 days"),
+    ]
+}
+
+fn default_dashboard_range() -> String {
+    "1h".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +307,48 @@ pub struct StorageConfig {
     pub retention_days: u32,
     pub archive_threshold: u64,
     pub compression_enabled: bool,
+    #[serde(default)]
+    pub write_buffer: WriteBufferConfig,
+    /// Backend for the spill journal and restored archives (`std` or
+    /// `io_uring`)
+    #[serde(default)]
+    pub io_backend: IoBackend,
+    /// Database events are centralized in (`sqlite` keeps them local only)
+    #[serde(default)]
+    pub backend: StorageBackend,
+    #[serde(default)]
+    pub postgres: PostgresConfig,
+    /// How events are identified across databases (`ulid` or `hash`)
+    #[serde(default)]
+    pub event_ids: EventIds,
+    /// Days deleted events stay restorable before they are purged for good
+    #[serde(default = "default_deleted_retention_days")]
+    pub deleted_retention_days: u32,
+}
+
+fn default_deleted_retention_days() -> u32 {
+    7
+}
+
+/// Batching of monitor writes into one transaction per flush.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteBufferConfig {
+    pub enabled: bool,
+    /// Longest an event waits in the buffer before being written
+    pub flush_interval_ms: u64,
+    /// Events written per transaction; a full buffer flushes immediately
+    pub max_batch_size: usize,
+}
+
+impl Default for WriteBufferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            flush_interval_ms: 250,
+            max_batch_size: 500,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +357,10 @@ pub struct IntegrationsConfig {
     pub enhanced_logging: bool,
     pub copilot_tracking: bool,
     pub export_prometheus: bool,
+    #[serde(default)]
+    pub otel: OtelConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
 }
 
 impl Default for Config {
@@ -56,13 +376,35 @@ impl Default for Config {
                     "vscode".to_string(),
                     "models".to_string(),
                     "terminal".to_string(),
+                    "lsp".to_string(),
+                    "disk".to_string(),
                 ],
+                crash_loop_threshold: default_crash_loop_threshold(),
+                crash_loop_window_secs: default_crash_loop_window_secs(),
+                ollama_probe: OllamaProbeConfig::default(),
+                copilot_proxy: CopilotProxyConfig::default(),
+                loopback: LoopbackConfig::default(),
+                disk: DiskConfig::default(),
+                network: NetworkProbeConfig::default(),
+                scheduling: SchedulingConfig::default(),
+                memory_budget: MemoryBudgetConfig::default(),
+                load_shedding: LoadSheddingConfig::default(),
+                overflow_policy: OverflowPolicy::default(),
+                cmdline: CmdlineConfig::default(),
+                extensions: ExtensionAttributionConfig::default(),
+                editors: EditorsConfig::default(),
+                connectivity: ConnectivityConfig::default(),
             },
             dashboard: DashboardConfig {
                 port: 3030,
                 auto_refresh_ms: 1000,
                 theme: "dark".to_string(),
                 enable_websocket: true,
+                sources: Vec::new(),
+                federation: FederationConfig::default(),
+                tenancy: TenancyConfig::default(),
+                ranges: default_dashboard_ranges(),
+                default_range: default_dashboard_range(),
             },
             storage: StorageConfig {
                 database_path: home_dir
@@ -70,50 +412,161 @@ impl Default for Config {
                 retention_days: 30,
                 archive_threshold: 100000,
                 compression_enabled: true,
+                write_buffer: WriteBufferConfig::default(),
+                io_backend: IoBackend::default(),
+                backend: StorageBackend::default(),
+                postgres: PostgresConfig::default(),
+                event_ids: EventIds::default(),
+                deleted_retention_days: default_deleted_retention_days(),
             },
             integrations: IntegrationsConfig {
-                wall_notification_system: true,
+                wall_notification_system: false,
                 enhanced_logging: true,
                 copilot_tracking: true,
                 export_prometheus: false,
+                otel: OtelConfig::default(),
+                notifications: NotificationsConfig::default(),
             },
+            reports: ReportsConfig::default(),
+            email: EmailConfig::default(),
+            schedules: Vec::new(),
+            object_storage: ObjectStorageConfig::default(),
+            ipc: IpcConfig::default(),
+            webhooks: Vec::new(),
+            alerts: Vec::new(),
+            alert_digest: AlertDigestConfig::default(),
+            working_hours: WorkingHoursConfig::default(),
+            derived_metrics: DerivedMetricsConfig::default(),
+            histograms: HistogramConfig::default(),
+            advice: AdviceConfig::default(),
+            model_proxy: ModelProxyConfig::default(),
         }
     }
 }
 
 impl Config {
-    pub fn load(config_path: Option<PathBuf>) -> Result<Self> {
-        let config_file = match config_path {
-            Some(path) => path,
-            None => {
-                let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-                home_dir.join(".config/vscode-latency-monitor/config.toml")
-            }
-        };
+    /// `~/.config/vscode-latency-monitor/config.toml`
+    pub fn default_path() -> PathBuf {
+        let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        home_dir.join(".config/vscode-latency-monitor/config.toml")
+    }
 
+    pub fn load(config_file: &Path) -> Result<Self> {
         if config_file.exists() {
-            let content = fs::read_to_string(&config_file)?;
+            let content = fs::read_to_string(config_file)?;
             let config: Config = toml::from_str(&content)?;
+            config.validate()?;
             Ok(config)
         } else {
             // Create default config file
             let config = Config::default();
-            config.save(&config_file)?;
+            config.save(config_file)?;
             Ok(config)
         }
     }
 
+    /// Write the config through a temporary file and rename it into place, so
+    /// a crash mid-write never leaves a truncated config behind.
     pub fn save(&self, config_path: &Path) -> Result<()> {
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
         let content = toml::to_string_pretty(self)?;
-        fs::write(config_path, content)?;
-        
+        let mut temp_path = config_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, config_path)?;
+
         Ok(())
     }
 
+    /// A copy safe to share in bug reports and report bundles. Credentials
+    /// live in secrets.toml, but webhook URLs (Slack's, for one) and
+    /// collector headers are bearer tokens themselves.
+    pub fn scrubbed(&self) -> Config {
+        let mut shared = self.clone();
+        for webhook in &mut shared.webhooks {
+            webhook.url = REDACTED.to_string();
+        }
+        for value in shared.integrations.otel.headers.values_mut() {
+            *value = REDACTED.to_string();
+        }
+        shared
+    }
+
+    /// Value at a dotted key such as `monitoring.interval_ms`; array elements
+    /// are addressed by index (`webhooks.0.url`).
+    pub fn get(&self, key: &str) -> Result<toml::Value> {
+        let root = toml::Value::try_from(self)?;
+        lookup(&root, key)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown config key '{}'", key))
+    }
+
+    /// Set a dotted key from its command-line text, parsed according to the
+    /// type of the current value. Returns the previous value, if any. The
+    /// result must still deserialize and pass `validate`.
+    pub fn set(&mut self, key: &str, raw: &str) -> Result<Option<toml::Value>> {
+        let mut root = toml::Value::try_from(&*self)?;
+        let (parent_key, field) = match key.rsplit_once('.') {
+            Some((parent, field)) => (Some(parent), field),
+            None => (None, key),
+        };
+
+        let parent = match parent_key {
+            Some(parent_key) => lookup_mut(&mut root, parent_key)
+                .ok_or_else(|| anyhow!("Unknown config key '{}'", key))?,
+            None => &mut root,
+        };
+        let slot = match parent {
+            toml::Value::Table(table) => {
+                // Unset optional fields are absent from the serialized table
+                if !table.contains_key(field) {
+                    table.insert(field.to_string(), parse_untyped(raw));
+                    None
+                } else {
+                    table.get_mut(field)
+                }
+            }
+            toml::Value::Array(items) => field.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+            _ => None,
+        };
+
+        let previous = match slot {
+            Some(slot) => {
+                let parsed = parse_like(slot, raw)
+                    .map_err(|e| anyhow!("Invalid value for '{}': {}", key, e))?;
+                Some(std::mem::replace(slot, parsed))
+            }
+            None => None,
+        };
+        if previous.is_none() && lookup(&root, key).is_none() {
+            return Err(anyhow!("Unknown config key '{}'", key));
+        }
+
+        let updated: Config = root
+            .try_into()
+            .map_err(|e| anyhow!("Invalid value for '{}': {}", key, e))?;
+        // Unknown fields are dropped on deserialize rather than rejected
+        if lookup(&toml::Value::try_from(&updated)?, key).is_none() {
+            return Err(anyhow!("Unknown config key '{}'", key));
+        }
+        updated.validate()?;
+        *self = updated;
+        Ok(previous)
+    }
+
+    /// Every leaf value of the effective config (file merged over defaults)
+    /// as `(dotted key, value)` pairs.
+    pub fn entries(&self) -> Result<Vec<(String, toml::Value)>> {
+        let mut entries = Vec::new();
+        flatten(&toml::Value::try_from(self)?, String::new(), &mut entries);
+        Ok(entries)
+    }
+
     pub fn validate(&self) -> Result<()> {
         // Validate configuration values
         if self.monitoring.interval_ms == 0 {
@@ -132,6 +585,258 @@ impl Config {
             return Err(anyhow::anyhow!("Retention days must be greater than 0"));
         }
 
+        if self.dashboard.ranges.is_empty() || self.dashboard.ranges.len() > 9 {
+            return Err(anyhow!("dashboard.ranges needs between 1 and 9 ranges"));
+        }
+        for range in &self.dashboard.ranges {
+            if parse_duration(&range.since).is_none() || parse_duration(&range.bucket).is_none() {
+                return Err(anyhow!(
+                    "Dashboard range '{}': since and bucket must be durations like 24h or 15m",
+                    range.label
+                ));
+            }
+        }
+        if !self.dashboard.ranges.iter().any(|range| range.since == self.dashboard.default_range) {
+            return Err(anyhow!(
+                "dashboard.default_range '{}' isn't the since of any dashboard range",
+                self.dashboard.default_range
+            ));
+        }
+
+        for schedule in &self.schedules {
+            // The name is part of the report file names in `output_dir`
+            capture::validate_label(&schedule.name).map_err(|_| {
+                anyhow!("Schedule '{}': use letters, digits, '-', '_' and '.' in the name", schedule.name)
+            })?;
+            if let Some(cron) = &schedule.cron {
+                CronSchedule::parse(cron).map_err(|e| anyhow!("Schedule '{}': {}", schedule.name, e))?;
+            }
+            if !matches!(schedule.format.as_str(), "json" | "csv" | "html") {
+                return Err(anyhow!(
+                    "Schedule '{}': unknown format '{}' (json, csv, html)",
+                    schedule.name,
+                    schedule.format
+                ));
+            }
+            if let Some(name) = schedule.webhooks.iter().find(|name| !self.webhooks.iter().any(|w| &w.name == *name)) {
+                return Err(anyhow!("Schedule '{}': no webhook named '{}'", schedule.name, name));
+            }
+        }
+
+        if let Some(rule) = self.alerts.iter().find(|rule| rule.actions.contains(&AlertAction::Email)) {
+            if !self.email.enabled || self.email.alert_recipients.is_empty() {
+                return Err(anyhow!(
+                    "Alert '{}' has the email action, which needs email.enabled and email.alert_recipients",
+                    rule.name
+                ));
+            }
+        }
+
+        self.working_hours.resolve()?;
+        self.derived_metrics.compile()?;
+        self.histograms.validate()?;
+        self.monitoring.editors.validate()?;
+        self.integrations.notifications.validate()?;
+        self.model_proxy.validate()?;
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn lookup<'a>(value: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.').try_fold(value, |value, part| match value {
+        toml::Value::Table(table) => table.get(part),
+        toml::Value::Array(items) => items.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn lookup_mut<'a>(value: &'a mut toml::Value, key: &str) -> Option<&'a mut toml::Value> {
+    key.split('.').try_fold(value, |value, part| match value {
+        toml::Value::Table(table) => table.get_mut(part),
+        toml::Value::Array(items) => items.get_mut(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Parse `raw` as the same TOML type as `current`. Strings are taken
+/// verbatim; arrays and tables use inline TOML syntax (`["a", "b"]`).
+fn parse_like(current: &toml::Value, raw: &str) -> Result<toml::Value> {
+    let raw = raw.trim();
+    Ok(match current {
+        toml::Value::String(_) => toml::Value::String(raw.to_string()),
+        toml::Value::Integer(_) => toml::Value::Integer(
+            raw.parse().map_err(|_| anyhow!("expected an integer, got '{}'", raw))?,
+        ),
+        toml::Value::Float(_) => toml::Value::Float(
+            raw.parse().map_err(|_| anyhow!("expected a number, got '{}'", raw))?,
+        ),
+        toml::Value::Boolean(_) => toml::Value::Boolean(match raw.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => true,
+            "false" | "no" | "off" | "0" => false,
+            _ => return Err(anyhow!("expected true or false, got '{}'", raw)),
+        }),
+        toml::Value::Datetime(_) | toml::Value::Array(_) | toml::Value::Table(_) => {
+            let parsed = parse_inline(raw)?;
+            if std::mem::discriminant(&parsed) != std::mem::discriminant(current) {
+                return Err(anyhow!("expected {}, got '{}'", current.type_str(), raw));
+            }
+            parsed
+        }
+    })
+}
+
+/// A value for a field with no current value to take the type from: TOML
+/// syntax if it parses, otherwise a plain string.
+fn parse_untyped(raw: &str) -> toml::Value {
+    parse_inline(raw.trim()).unwrap_or_else(|_| toml::Value::String(raw.trim().to_string()))
+}
+
+fn parse_inline(raw: &str) -> Result<toml::Value> {
+    let mut table: toml::Table = toml::from_str(&format!("value = {}", raw))?;
+    table
+        .remove("value")
+        .ok_or_else(|| anyhow!("expected a TOML value, got '{}'", raw))
+}
+
+fn flatten(value: &toml::Value, prefix: String, entries: &mut Vec<(String, toml::Value)>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten(value, join(key), entries);
+            }
+        }
+        // Arrays of tables (webhooks, alerts, ...) are listed per element
+        toml::Value::Array(items) if items.iter().any(|item| item.is_table()) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(item, join(&index.to_string()), entries);
+            }
+        }
+        _ => entries.push((prefix, value.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_parses_the_type_of_the_current_value() {
+        let mut config = Config::default();
+        let previous = config.set("monitoring.interval_ms", "250").unwrap();
+        assert_eq!(previous, Some(toml::Value::Integer(Config::default().monitoring.interval_ms as i64)));
+        assert_eq!(config.monitoring.interval_ms, 250);
+
+        config.set("email.enabled", "on").unwrap();
+        assert!(config.email.enabled);
+        config.set("email.enabled", "no").unwrap();
+        assert!(!config.email.enabled);
+
+        config.set("monitoring.enabled_components", r#"["vscode", "terminal"]"#).unwrap();
+        assert_eq!(config.monitoring.enabled_components, ["vscode", "terminal"]);
+    }
+
+    #[test]
+    fn set_rejects_values_of_the_wrong_type() {
+        let mut config = Config::default();
+        let err = config.set("monitoring.interval_ms", "fast").unwrap_err();
+        assert!(err.to_string().contains("Invalid value for 'monitoring.interval_ms'"), "{}", err);
+        assert!(config.set("email.enabled", "maybe").is_err());
+        assert!(config.set("monitoring.enabled_components", "vscode").is_err());
+        assert_eq!(config.monitoring.interval_ms, Config::default().monitoring.interval_ms);
+    }
+
+    #[test]
+    fn set_rejects_unknown_keys() {
+        let mut config = Config::default();
+        for key in ["monitoring.no_such_field", "no_such_section.field", "no_such_key", "monitoring.enabled_components.99"] {
+            let err = config.set(key, "1").unwrap_err();
+            assert_eq!(err.to_string(), format!("Unknown config key '{}'", key));
+        }
+    }
+
+    #[test]
+    fn set_validates_the_updated_config() {
+        let mut config = Config::default();
+        assert!(config.set("monitoring.interval_ms", "0").is_err());
+        assert_eq!(config.monitoring.interval_ms, Config::default().monitoring.interval_ms);
+    }
+
+    #[test]
+    fn set_fills_in_unset_optional_fields() {
+        let mut config = Config::default();
+        assert!(config.monitoring.memory_budget.spill_path.is_none());
+        let previous = config.set("monitoring.memory_budget.spill_path", "/tmp/spill.jsonl").unwrap();
+        assert_eq!(previous, None);
+        assert_eq!(config.monitoring.memory_budget.spill_path, Some(PathBuf::from("/tmp/spill.jsonl")));
+    }
+
+    #[test]
+    fn set_indexes_into_arrays() {
+        let mut config = Config::default();
+        config.set("monitoring.enabled_components.0", "terminal").unwrap();
+        assert_eq!(config.monitoring.enabled_components[0], "terminal");
+    }
+
+    #[test]
+    fn lookup_follows_tables_and_array_indices() {
+        let value: toml::Value = toml::from_str(
+            r#"
+            [monitoring]
+            interval_ms = 100
+            components = ["vscode", "terminal"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(lookup(&value, "monitoring.interval_ms"), Some(&toml::Value::Integer(100)));
+        assert_eq!(
+            lookup(&value, "monitoring.components.1"),
+            Some(&toml::Value::String("terminal".to_string()))
+        );
+        assert!(lookup(&value, "monitoring").unwrap().is_table());
+        assert_eq!(lookup(&value, "monitoring.missing"), None);
+        assert_eq!(lookup(&value, "monitoring.components.2"), None);
+        assert_eq!(lookup(&value, "monitoring.components.first"), None);
+        assert_eq!(lookup(&value, "monitoring.interval_ms.value"), None);
+    }
+
+    #[test]
+    fn scrubbed_copies_hide_tokens_but_keep_the_rest() {
+        let webhook = toml::from_str(
+            r#"
+            name = "slack"
+            url = "https://hooks.slack.com/services/T000/B000/XXXX"
+            signing_secret = "slack_signing"
+            "#,
+        )
+        .unwrap();
+        let mut config = Config {
+            webhooks: vec![webhook],
+            ..Default::default()
+        };
+        config.integrations.otel.headers.insert("authorization".to_string(), "Bearer abc123".to_string());
+        config.monitoring.interval_ms = 250;
+
+        let shared = config.scrubbed();
+        assert_eq!(shared.webhooks[0].url, REDACTED);
+        assert_eq!(shared.webhooks[0].name, "slack");
+        // Only the secret's name, which is safe to share
+        assert_eq!(shared.webhooks[0].signing_secret.as_deref(), Some("slack_signing"));
+        assert_eq!(shared.integrations.otel.headers["authorization"], REDACTED);
+        assert_eq!(shared.monitoring.interval_ms, 250);
+        assert_eq!(config.webhooks[0].url, "https://hooks.slack.com/services/T000/B000/XXXX");
+
+        let written = toml::to_string_pretty(&shared).unwrap();
+        assert!(!written.contains("hooks.slack.com") && !written.contains("abc123"));
+        assert!(toml::from_str::<Config>(&written).is_ok());
+    }
+}
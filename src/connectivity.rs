@@ -0,0 +1,460 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::models::{ComponentType, LatencyEvent};
+
+/// Network context recorded with network and model events, configured as
+/// `[monitoring.connectivity]`: the interface of the default route, whether
+/// it is Wi-Fi, Ethernet, cellular or a VPN, and on Wi-Fi the network name
+/// and signal strength. Slow Copilot responses on a weak hotel Wi-Fi can
+/// then be told apart from real regressions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectivityConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Components whose events are tagged
+    pub components: Vec<ComponentType>,
+    /// Record the Wi-Fi network's name, which says where you are; off by
+    /// default
+    pub record_ssid: bool,
+}
+
+impl Default for ConnectivityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 30,
+            components: vec![
+                ComponentType::Network,
+                ComponentType::GitHubCopilot,
+                ComponentType::LocalModel,
+                ComponentType::CloudModel,
+            ],
+            record_ssid: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkType {
+    Ethernet,
+    Wifi,
+    Cellular,
+    Vpn,
+    /// A default route over an interface of another or unknown kind
+    Other,
+    /// No default route
+    Offline,
+}
+
+impl LinkType {
+    /// Value stored in `metadata.link_type`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkType::Ethernet => "ethernet",
+            LinkType::Wifi => "wifi",
+            LinkType::Cellular => "cellular",
+            LinkType::Vpn => "vpn",
+            LinkType::Other => "other",
+            LinkType::Offline => "offline",
+        }
+    }
+}
+
+/// One sample of the machine's connectivity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connectivity {
+    /// Interface of the default route, e.g. `wlan0` or `en0`
+    pub interface: Option<String>,
+    pub link: LinkType,
+    pub ssid: Option<String>,
+    /// Wi-Fi signal strength
+    pub signal_dbm: Option<i32>,
+}
+
+impl Connectivity {
+    fn offline() -> Self {
+        Self {
+            interface: None,
+            link: LinkType::Offline,
+            ssid: None,
+            signal_dbm: None,
+        }
+    }
+
+    /// Record the sample in an event's metadata, as `network_interface`,
+    /// `link_type`, `ssid` and `signal_dbm`.
+    pub fn apply(&self, metadata: &mut serde_json::Value) {
+        if !metadata.is_object() {
+            *metadata = serde_json::json!({});
+        }
+        metadata["link_type"] = serde_json::json!(self.link.as_str());
+        if let Some(interface) = &self.interface {
+            metadata["network_interface"] = serde_json::json!(interface);
+        }
+        if let Some(ssid) = &self.ssid {
+            metadata["ssid"] = serde_json::json!(ssid);
+        }
+        if let Some(signal_dbm) = self.signal_dbm {
+            metadata["signal_dbm"] = serde_json::json!(signal_dbm);
+        }
+    }
+
+    /// Whether the machine moved to another network, as opposed to the
+    /// signal changing.
+    fn same_network(&self, other: &Connectivity) -> bool {
+        self.interface == other.interface && self.link == other.link && self.ssid == other.ssid
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.link.as_str())?;
+        if let Some(interface) = &self.interface {
+            write!(f, " on {}", interface)?;
+        }
+        if let Some(ssid) = &self.ssid {
+            write!(f, " ({})", ssid)?;
+        }
+        if let Some(signal_dbm) = self.signal_dbm {
+            write!(f, ", {} dBm", signal_dbm)?;
+        }
+        Ok(())
+    }
+}
+
+/// The latest sample, shared with the storage that tags events with it.
+/// Clones share one sample.
+#[derive(Debug, Clone)]
+pub struct ConnectivityTracker {
+    config: ConnectivityConfig,
+    current: Arc<RwLock<Option<Connectivity>>>,
+}
+
+impl ConnectivityTracker {
+    pub fn new(config: &ConnectivityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            current: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn current(&self) -> Option<Connectivity> {
+        self.current.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// A copy of `event` tagged with the latest sample, or `None` when its
+    /// component isn't tagged, nothing was sampled yet, or it carries a
+    /// sample already (e.g. one recorded on another machine).
+    pub fn tag(&self, event: &LatencyEvent) -> Option<LatencyEvent> {
+        if !self.config.components.contains(&event.component_type) || event.metadata.get("link_type").is_some() {
+            return None;
+        }
+        let connectivity = self.current()?;
+        let mut tagged = event.clone();
+        connectivity.apply(&mut tagged.metadata);
+        Some(tagged)
+    }
+
+    /// Sample every `interval_secs` until the task is dropped.
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+        let record_ssid = self.config.record_ssid;
+        loop {
+            ticker.tick().await;
+            let sampled = match tokio::task::spawn_blocking(move || sample(record_ssid)).await {
+                Ok(sampled) => sampled,
+                Err(e) => {
+                    warn!("Connectivity sampling failed: {}", e);
+                    continue;
+                }
+            };
+
+            let mut current = self.current.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            match current.as_ref() {
+                Some(previous) if previous.same_network(&sampled) => debug!("Network: {}", sampled),
+                _ => info!("Network: {}", sampled),
+            }
+            *current = Some(sampled);
+        }
+    }
+}
+
+/// Run `program` and return its output if it succeeded.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Value of the first `key: value` line of `text` with this key.
+fn field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim()).filter(|value| !value.is_empty())
+    })
+}
+
+/// The default route's interface from `/proc/net/route`, the Wi-Fi details
+/// from `/proc/net/wireless` and `iw` (or `iwgetid`).
+#[cfg(target_os = "linux")]
+fn sample(record_ssid: bool) -> Connectivity {
+    let Some(interface) = linux_default_interface() else {
+        return Connectivity::offline();
+    };
+    let sys = std::path::Path::new("/sys/class/net").join(&interface);
+    let arp_type = std::fs::read_to_string(sys.join("type")).ok().and_then(|t| t.trim().parse::<u32>().ok());
+    let link = if sys.join("wireless").exists() || sys.join("phy80211").exists() {
+        LinkType::Wifi
+    } else if ["wwan", "rmnet", "ww"].iter().any(|prefix| interface.starts_with(prefix)) {
+        LinkType::Cellular
+    } else if ["tun", "tap", "wg", "vpn"].iter().any(|prefix| interface.starts_with(prefix))
+        // ARPHRD_NONE: WireGuard and other point-to-point tunnels
+        || arp_type == Some(65534)
+    {
+        LinkType::Vpn
+    } else if arp_type == Some(1) {
+        LinkType::Ethernet
+    } else {
+        LinkType::Other
+    };
+
+    let mut connectivity = Connectivity {
+        interface: Some(interface.clone()),
+        link,
+        ssid: None,
+        signal_dbm: None,
+    };
+    if link == LinkType::Wifi {
+        let iw = output("iw", &["dev", &interface, "link"]);
+        connectivity.signal_dbm = linux_wireless_signal(&interface).or_else(|| {
+            let signal = field(iw.as_deref()?, "signal")?;
+            signal.trim_end_matches("dBm").trim().parse().ok()
+        });
+        if record_ssid {
+            connectivity.ssid = iw
+                .as_deref()
+                .and_then(|iw| field(iw, "SSID"))
+                .map(str::to_string)
+                .or_else(|| Some(output("iwgetid", &[&interface, "-r"])?.trim().to_string()).filter(|s| !s.is_empty()));
+        }
+    }
+    connectivity
+}
+
+#[cfg(target_os = "linux")]
+fn linux_default_interface() -> Option<String> {
+    default_route_interface(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+/// Interface of the IPv4 default route with the lowest metric, from the
+/// contents of `/proc/net/route`.
+#[cfg(target_os = "linux")]
+fn default_route_interface(routes: &str) -> Option<String> {
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (destination, metric, mask) = (fields.get(1)?, fields.get(6)?, fields.get(7)?);
+            if *destination != "00000000" || *mask != "00000000" {
+                return None;
+            }
+            Some((metric.parse::<u64>().ok()?, fields[0].to_string()))
+        })
+        .min()
+        .map(|(_, interface)| interface)
+}
+
+#[cfg(target_os = "linux")]
+fn linux_wireless_signal(interface: &str) -> Option<i32> {
+    wireless_signal(&std::fs::read_to_string("/proc/net/wireless").ok()?, interface)
+}
+
+/// Signal level of `interface` in the contents of `/proc/net/wireless`,
+/// e.g. `-56.`.
+#[cfg(target_os = "linux")]
+fn wireless_signal(wireless: &str, interface: &str) -> Option<i32> {
+    wireless.lines().skip(2).find_map(|line| {
+        let (name, values) = line.split_once(':')?;
+        if name.trim() != interface {
+            return None;
+        }
+        // status link level noise ...
+        let level: f64 = values.split_whitespace().nth(2)?.trim_end_matches('.').parse().ok()?;
+        (level < 0.0).then_some(level as i32)
+    })
+}
+
+/// The default route's interface from `route get`, the Wi-Fi network from
+/// `networksetup` and its signal from `airport -I` where macOS still ships
+/// it. Recent releases only reveal the network name to apps allowed to
+/// use location services.
+#[cfg(target_os = "macos")]
+fn sample(record_ssid: bool) -> Connectivity {
+    const AIRPORT: &str =
+        "/System/Library/PrivateFrameworks/Apple80211.framework/Versions/Current/Resources/airport";
+
+    let Some(interface) = output("route", &["-n", "get", "default"])
+        .and_then(|route| field(&route, "interface").map(str::to_string))
+    else {
+        return Connectivity::offline();
+    };
+    let airport_network = output("networksetup", &["-getairportnetwork", &interface]).unwrap_or_default();
+    let link = if !airport_network.is_empty() && !airport_network.contains("not a Wi-Fi interface") {
+        LinkType::Wifi
+    } else if ["utun", "ipsec", "ppp", "tun", "tap"].iter().any(|prefix| interface.starts_with(prefix)) {
+        LinkType::Vpn
+    } else if interface.starts_with("en") {
+        LinkType::Ethernet
+    } else {
+        LinkType::Other
+    };
+
+    let mut connectivity = Connectivity {
+        interface: Some(interface),
+        link,
+        ssid: None,
+        signal_dbm: None,
+    };
+    if link == LinkType::Wifi {
+        if record_ssid {
+            connectivity.ssid = field(&airport_network, "Current Wi-Fi Network").map(str::to_string);
+        }
+        connectivity.signal_dbm =
+            output(AIRPORT, &["-I"]).and_then(|info| field(&info, "agrCtlRSSI")?.parse().ok()).filter(|dbm| *dbm < 0);
+    }
+    connectivity
+}
+
+/// The connected wireless interface from `netsh wlan show interfaces`.
+/// Other links can't be told apart without more tooling, so they are
+/// `other`.
+#[cfg(windows)]
+fn sample(record_ssid: bool) -> Connectivity {
+    let interfaces = output("netsh", &["wlan", "show", "interfaces"]).unwrap_or_default();
+    if field(&interfaces, "State") != Some("connected") {
+        return Connectivity {
+            interface: None,
+            link: LinkType::Other,
+            ssid: None,
+            signal_dbm: None,
+        };
+    }
+    Connectivity {
+        interface: field(&interfaces, "Name").map(str::to_string),
+        link: LinkType::Wifi,
+        ssid: field(&interfaces, "SSID").filter(|_| record_ssid).map(str::to_string),
+        // Windows maps -100..-50 dBm linearly onto 0..100%
+        signal_dbm: field(&interfaces, "Signal")
+            .and_then(|signal| signal.trim_end_matches('%').trim().parse::<i32>().ok())
+            .map(|percent| percent / 2 - 100),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn sample(_record_ssid: bool) -> Connectivity {
+    Connectivity {
+        interface: None,
+        link: LinkType::Other,
+        ssid: None,
+        signal_dbm: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EventSource;
+
+    fn wifi(ssid: Option<&str>, signal_dbm: Option<i32>) -> Connectivity {
+        Connectivity {
+            interface: Some("wlan0".to_string()),
+            link: LinkType::Wifi,
+            ssid: ssid.map(str::to_string),
+            signal_dbm,
+        }
+    }
+
+    #[test]
+    fn fields_are_read_from_key_value_lines() {
+        let iw = "Connected to 00:11:22:33:44:55 (on wlan0)\n\tSSID: Hotel Lobby\n\tsignal: -67 dBm\n\tfreq:\n";
+        assert_eq!(field(iw, "SSID"), Some("Hotel Lobby"));
+        assert_eq!(field(iw, "signal"), Some("-67 dBm"));
+        assert_eq!(field(iw, "freq"), None);
+        assert_eq!(field(iw, "tx bitrate"), None);
+        // Only the part before the first colon is the key
+        assert_eq!(field("BSSID: 00:11:22:33:44:55", "BSSID"), Some("00:11:22:33:44:55"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn default_route_with_the_lowest_metric_wins() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                      eth0\t00000000\t0102A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+                      wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+                      wg0\t0000000A\t00000000\t0001\t0\t0\t0\t000000FF\t0\t0\t0\n";
+        assert_eq!(default_route_interface(routes).as_deref(), Some("eth0"));
+        let header = routes.lines().next().unwrap();
+        assert_eq!(default_route_interface(header), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn wireless_signal_is_the_level_column() {
+        let wireless = "Inter-| sta-|   Quality        |   Discarded packets\n \
+                        face | tus | link level noise |  nwid  crypt   frag\n\
+                        wlan0: 0000   54.  -56.  -256        0      0      0\n\
+                        wlan1: 0000    0.    0.  -256        0      0      0\n";
+        assert_eq!(wireless_signal(wireless, "wlan0"), Some(-56));
+        // A zero level means the driver doesn't report one
+        assert_eq!(wireless_signal(wireless, "wlan1"), None);
+        assert_eq!(wireless_signal(wireless, "wlan2"), None);
+    }
+
+    #[test]
+    fn samples_are_recorded_and_described() {
+        let mut metadata = serde_json::Value::Null;
+        wifi(Some("Office"), Some(-60)).apply(&mut metadata);
+        assert_eq!(
+            metadata,
+            serde_json::json!({"link_type": "wifi", "network_interface": "wlan0", "ssid": "Office", "signal_dbm": -60})
+        );
+        assert_eq!(wifi(Some("Office"), Some(-60)).to_string(), "wifi on wlan0 (Office), -60 dBm");
+        assert_eq!(Connectivity::offline().to_string(), "offline");
+
+        let mut metadata = serde_json::json!({"pid": 1});
+        Connectivity::offline().apply(&mut metadata);
+        assert_eq!(metadata, serde_json::json!({"pid": 1, "link_type": "offline"}));
+    }
+
+    #[test]
+    fn signal_changes_stay_on_the_same_network() {
+        assert!(wifi(Some("Office"), Some(-60)).same_network(&wifi(Some("Office"), Some(-80))));
+        assert!(!wifi(Some("Office"), None).same_network(&wifi(Some("Hotel"), None)));
+        assert!(!wifi(None, None).same_network(&Connectivity::offline()));
+    }
+
+    #[test]
+    fn only_configured_components_are_tagged() {
+        let tracker = ConnectivityTracker::new(&ConnectivityConfig::default());
+        let event = |component| {
+            LatencyEvent::new(component, EventSource::NetworkRequest, Duration::from_millis(40), "GET /".to_string())
+        };
+        assert!(tracker.tag(&event(ComponentType::Network)).is_none());
+
+        *tracker.clone().current.write().unwrap() = Some(wifi(None, Some(-70)));
+        let tagged = tracker.tag(&event(ComponentType::Network)).unwrap();
+        assert_eq!(tagged.metadata["link_type"], "wifi");
+        assert_eq!(tagged.metadata["signal_dbm"], -70);
+        assert!(tracker.tag(&event(ComponentType::Terminal)).is_none());
+        // An event sampled elsewhere keeps its own connectivity
+        assert!(tracker.tag(&tagged).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+/// A five-field cron expression (`minute hour day-of-month month
+/// day-of-week`) evaluated in local time. Fields take `*`, numbers, ranges
+/// (`1-5`), steps (`*/15`, `8-18/2`) and comma lists; day-of-week 0 and 7
+/// are both Sunday. As in cron, when both day fields are restricted a day
+/// matching either one is due. `@hourly`, `@daily`, `@weekly`, `@monthly`
+/// and `@yearly` are accepted as shorthands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Far enough ahead to reach any valid expression, e.g. `0 0 29 2 *`.
+const SEARCH_YEARS: i32 = 8;
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = expression.trim();
+        let expanded = match expression {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!(
+                "Invalid cron expression '{}': expected 5 fields (minute hour day month weekday)",
+                expression
+            ));
+        };
+        let invalid = |e: anyhow::Error| anyhow!("Invalid cron expression '{}': {}", expression, e);
+
+        let weekdays = parse_field(weekday, 0, 7).map_err(invalid)?;
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).map_err(invalid)?,
+            hours: parse_field(hour, 0, 23).map_err(invalid)? as u32,
+            days: parse_field(day, 1, 31).map_err(invalid)? as u32,
+            months: parse_field(month, 1, 12).map_err(invalid)? as u16,
+            // 7 is Sunday too
+            weekdays: ((weekdays | (weekdays >> 7)) & 0x7f) as u8,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// The first minute strictly after `after` the schedule is due, or
+    /// `None` if it never is (e.g. February 30th). Minutes skipped by a
+    /// daylight saving change are never due; repeated ones are due once.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(366 * SEARCH_YEARS as i64);
+        let mut at = start;
+        while at < limit {
+            if self.months & (1 << at.month()) == 0 {
+                at = first_of_next_month(at.date())?;
+            } else if !self.day_matches(at.date()) {
+                at = (at.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << at.hour()) == 0 {
+                at = at.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << at.minute()) == 0 {
+                at += Duration::minutes(1);
+            } else {
+                match Local.from_local_datetime(&at) {
+                    LocalResult::Single(due) | LocalResult::Ambiguous(due, _) if due > after => return Some(due),
+                    _ => at += Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn first_of_next_month(date: NaiveDate) -> Option<NaiveDateTime> {
+    let (year, month) = if date.month() == 12 { (date.year() + 1, 1) } else { (date.year(), date.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+/// One field as a bit set of the values it allows, between `min` and `max`.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut allowed = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| anyhow!("invalid step '{}'", step))?;
+                if step == 0 {
+                    return Err(anyhow!("step must be at least 1 in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => {
+                let value = |text: &str| -> Result<u32> {
+                    let value: u32 = text.parse().map_err(|_| anyhow!("invalid value '{}'", text))?;
+                    if value < min || value > max {
+                        return Err(anyhow!("{} is outside {}-{}", value, min, max));
+                    }
+                    Ok(value)
+                };
+                match range.split_once('-') {
+                    Some((first, last)) => (value(first)?, value(last)?),
+                    // `5/15` means from 5 to the end in steps of 15
+                    None if step > 1 => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                }
+            }
+        };
+        if first > last {
+            return Err(anyhow!("range '{}' runs backwards", range));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            allowed |= 1 << value;
+        }
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local time on days without a daylight saving change in either
+    /// hemisphere; 2026-06-10 is a Wednesday.
+    fn local(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap()
+    }
+
+    fn next(expression: &str, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        CronSchedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn next_run_is_strictly_after_the_given_time() {
+        assert_eq!(next("*/15 * * * *", local(2026, 6, 10, 10, 7, 30)), Some(local(2026, 6, 10, 10, 15, 0)));
+        assert_eq!(next("*/15 * * * *", local(2026, 6, 10, 10, 15, 0)), Some(local(2026, 6, 10, 10, 30, 0)));
+        assert_eq!(next("30 8-18/2 * * *", local(2026, 6, 10, 18, 31, 0)), Some(local(2026, 6, 11, 8, 30, 0)));
+    }
+
+    #[test]
+    fn weekday_ranges_skip_the_weekend() {
+        // Friday evening to Monday morning
+        assert_eq!(next("0 9 * * 1-5", local(2026, 6, 12, 10, 0, 0)), Some(local(2026, 6, 15, 9, 0, 0)));
+        // 0 and 7 are both Sunday
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap(), CronSchedule::parse("0 0 * * 0").unwrap());
+        assert_eq!(next("0 0 * * 7", local(2026, 6, 10, 0, 0, 0)), Some(local(2026, 6, 14, 0, 0, 0)));
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either_one() {
+        // The 13th or any Friday
+        assert_eq!(next("0 0 13 * 5", local(2026, 6, 10, 0, 0, 0)), Some(local(2026, 6, 12, 0, 0, 0)));
+        assert_eq!(next("0 0 13 * 5", local(2026, 6, 12, 0, 0, 0)), Some(local(2026, 6, 13, 0, 0, 0)));
+    }
+
+    #[test]
+    fn rare_and_impossible_dates() {
+        assert_eq!(next("0 0 29 2 *", local(2026, 6, 10, 0, 0, 0)), Some(local(2028, 2, 29, 0, 0, 0)));
+        assert_eq!(next("0 0 30 2 *", local(2026, 6, 10, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn shorthands_expand_to_their_expressions() {
+        for (shorthand, expression) in [
+            ("@hourly", "0 * * * *"),
+            ("@daily", "0 0 * * *"),
+            ("@midnight", "0 0 * * *"),
+            ("@weekly", "0 0 * * 0"),
+            ("@monthly", "0 0 1 * *"),
+            ("@yearly", "0 0 1 1 *"),
+        ] {
+            assert_eq!(CronSchedule::parse(shorthand).unwrap(), CronSchedule::parse(expression).unwrap());
+        }
+        assert_eq!(next("@monthly", local(2026, 12, 15, 0, 0, 0)), Some(local(2027, 1, 1, 0, 0, 0)));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "@reboot",
+        ] {
+            assert!(CronSchedule::parse(expression).is_err(), "{}", expression);
+        }
+    }
+}
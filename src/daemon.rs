@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// Set in the environment of the re-executed background process so it
+/// doesn't detach a second time, and by the service units for the same
+/// reason.
+pub const DAEMONIZED_ENV: &str = "VSCODE_LATENCY_MONITOR_DAEMONIZED";
+
+/// How long `stop` waits for the daemon to flush and exit.
+const STOP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Directory holding the PID file and daemon log (next to the database).
+pub fn runtime_dir(config: &Config) -> PathBuf {
+    config
+        .storage
+        .database_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn pid_file_path(config: &Config) -> PathBuf {
+    runtime_dir(config).join("monitor.pid")
+}
+
+pub fn log_file_path(config: &Config) -> PathBuf {
+    runtime_dir(config).join("monitor.log")
+}
+
+/// Restored archives attached read-only for querying (`query --archive`).
+pub fn archives_dir(config: &Config) -> PathBuf {
+    runtime_dir(config).join("archives")
+}
+
+pub fn is_daemonized() -> bool {
+    std::env::var_os(DAEMONIZED_ENV).is_some()
+}
+
+/// Re-run the current command line in a new session with output redirected
+/// to the daemon log, returning the child's PID.
+pub fn spawn_detached(config: &Config) -> Result<u32> {
+    if let Some(pid) = running_pid(config) {
+        return Err(anyhow!("Monitor daemon already running with PID {}", pid));
+    }
+
+    fs::create_dir_all(runtime_dir(config))?;
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file_path(config))?;
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(DAEMONIZED_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Own process group so the daemon survives the terminal closing
+        command.process_group(0);
+    }
+
+    let child = command.spawn()?;
+    Ok(child.id())
+}
+
+/// PID file owned by the running daemon; removed again on drop.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(config: &Config) -> Result<Self> {
+        let path = pid_file_path(config);
+
+        if let Some(pid) = read_pid(&path) {
+            if pid != std::process::id() && process_alive(pid) {
+                return Err(anyhow!("Monitor daemon already running with PID {}", pid));
+            }
+            warn!("Removing stale PID file for PID {}", pid);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, std::process::id().to_string())?;
+        info!("Wrote PID file {}", path.display());
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// PID of a live daemon, if the PID file points at a running process.
+pub fn running_pid(config: &Config) -> Option<u32> {
+    read_pid(&pid_file_path(config)).filter(|pid| process_alive(*pid))
+}
+
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> bool {
+    // Signal 0 performs the permission/existence check only
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+/// Ask the daemon to shut down (SIGTERM, or SIGKILL when forced) and wait
+/// for it to exit. Returns the stopped PID, or `None` if nothing was running.
+#[cfg(unix)]
+pub async fn stop_daemon(config: &Config, force: bool) -> Result<Option<u32>> {
+    let path = pid_file_path(config);
+    let Some(pid) = read_pid(&path) else {
+        return Ok(None);
+    };
+
+    if !process_alive(pid) {
+        warn!("PID file refers to PID {} which is not running; removing it", pid);
+        let _ = fs::remove_file(&path);
+        return Ok(None);
+    }
+
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+        return Err(anyhow!(
+            "Failed to signal PID {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    let started = Instant::now();
+    while process_alive(pid) {
+        if started.elapsed() > STOP_TIMEOUT {
+            return Err(anyhow!(
+                "Daemon PID {} did not exit within {}s (try --force)",
+                pid,
+                STOP_TIMEOUT.as_secs()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    if force {
+        // SIGKILL skips the daemon's own cleanup
+        let _ = fs::remove_file(&path);
+    }
+
+    Ok(Some(pid))
+}
+
+#[cfg(not(unix))]
+pub async fn stop_daemon(_config: &Config, _force: bool) -> Result<Option<u32>> {
+    Err(anyhow!("Stopping the daemon is only supported on Unix platforms"))
+}
+
+/// Resolves when the process is asked to terminate (Ctrl+C or SIGTERM).
+pub async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
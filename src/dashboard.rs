@@ -1,18 +1,48 @@
 use anyhow::Result;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::{Html, Json},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Response},
     routing::get,
-    Router,
+    Extension, Router,
 };
+use serde::Deserialize;
 use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tower::ServiceExt;
 use tower_http::cors::CorsLayer;
-use tracing::info;
+use tracing::{debug, info, warn};
 
+use crate::advice;
+use crate::alerts;
 use crate::config::Config;
-use crate::storage::MetricsStorage;
+use crate::storage::{self, MetricsStorage};
+use crate::filter::EventFilter;
+use crate::auth::{self, ApiScope};
+use crate::backend::{self, StorageBackend};
+use crate::federation::{self, Federated};
+use crate::ids;
+use crate::prometheus;
+use crate::otel::TraceContext;
+use crate::models::{AuditEntry, ComponentType, LatencyEvent, MaintenanceWindow, SavedQuery};
+use crate::secrets::Secrets;
+use crate::tenancy::Tenants;
+use crate::timerange::TimeRange;
+use crate::units::{self, UnitsQuery};
+use crate::sources::{CalendarQuery, DataSource, DerivedQuery, DataSources, TimeseriesQuery, LOCAL_SOURCE};
+
+/// Who made an API request, for the audit log: `api:<token name>`, or
+/// `api:anonymous` while no tokens exist.
+#[derive(Clone)]
+struct ApiActor(String);
 
 pub struct DashboardServer {
     config: Config,
@@ -23,9 +53,53 @@ pub struct DashboardServer {
 #[derive(Clone)]
 struct AppState {
     storage: MetricsStorage,
+    /// Database API tokens are checked against: always the dashboard's own,
+    /// even when `storage` is a tenant's
+    tokens: MetricsStorage,
+    /// Tenant whose database `storage` is; only its tokens are accepted
+    tenant: Option<String>,
+    sources: Arc<DataSources>,
     config: Config,
 }
 
+/// API routers under `dashboard.tenancy`: one for the dashboard's own
+/// database and one per tenant, built the first time a tenant's token is
+/// seen. Every route of a tenant's router serves only its database.
+struct TenantRouters {
+    main: Router,
+    base: AppState,
+    tenants: Tenants,
+    realtime: bool,
+    routers: tokio::sync::Mutex<HashMap<String, Router>>,
+}
+
+impl TenantRouters {
+    async fn router(&self, tenant: &str) -> Result<Router> {
+        let mut routers = self.routers.lock().await;
+        if let Some(router) = routers.get(tenant) {
+            return Ok(router.clone());
+        }
+
+        let storage = self.tenants.storage(tenant).await?;
+        // Other sources and federation peers are the operator's, not the tenant's
+        let mut config = self.base.config.clone();
+        config.dashboard.sources.clear();
+        config.dashboard.federation.enabled = false;
+        let sources = DataSources::open(&[], storage.clone(), None, &Secrets::default()).await?;
+        let state = AppState {
+            storage,
+            tokens: self.base.tokens.clone(),
+            tenant: Some(tenant.to_string()),
+            sources: Arc::new(sources),
+            config,
+        };
+
+        let router = api_router(state, self.realtime);
+        routers.insert(tenant.to_string(), router.clone());
+        Ok(router)
+    }
+}
+
 impl DashboardServer {
     pub async fn new(
         config: Config,
@@ -40,21 +114,64 @@ impl DashboardServer {
     }
 
     pub async fn serve(self, port: u16) -> Result<()> {
+        if self.realtime_enabled {
+            let interval = Duration::from_millis(self.config.dashboard.auto_refresh_ms.max(100));
+            tokio::spawn(tail_new_events(self.storage.clone(), interval));
+            info!("Real-time WebSocket updates enabled at /ws");
+        }
+
+        let secrets = Secrets::load()?;
+        let central = match self.config.storage.backend {
+            StorageBackend::Sqlite => None,
+            _ => Some(backend::open(&self.config.storage, self.storage.clone(), &secrets)),
+        };
+        let sources = DataSources::open(&self.config.dashboard.sources, self.storage.clone(), central, &secrets).await?;
         let state = AppState {
+            tokens: self.storage.clone(),
             storage: self.storage,
+            tenant: None,
+            sources: Arc::new(sources),
             config: self.config,
         };
 
+        // Without tokens the API is open to anyone who can reach it, so it
+        // only listens on loopback until one is created
+        let open_api = !state.config.dashboard.tenancy.enabled && state.tokens.count_api_tokens().await? == 0;
+
+        let view = view_config(&state.config);
         let app = Router::new()
             .route("/", get(dashboard_html))
-            .route("/api/status", get(api_status))
-            .route("/api/events", get(api_events))
-            .route("/api/metrics", get(api_metrics))
-            .route("/health", get(health_check))
-            .layer(CorsLayer::permissive())
-            .with_state(state);
-
-        let addr = format!("0.0.0.0:{}", port);
+            .route("/dashboard/config", get(move || async move { Json(view) }))
+            .route("/events/:id", get(event_html))
+            .route("/health", get(health_check));
+        let app = if state.config.dashboard.tenancy.enabled {
+            let tenants = Tenants::new(&state.config.dashboard.tenancy, &state.config.storage);
+            info!("Tenancy enabled; tenant databases in {}", tenants.data_dir().display());
+            let routers = TenantRouters {
+                main: api_router(state.clone(), self.realtime_enabled),
+                base: state,
+                tenants,
+                realtime: self.realtime_enabled,
+                routers: tokio::sync::Mutex::new(HashMap::new()),
+            };
+            app.merge(Router::new().fallback(route_to_tenant).with_state(Arc::new(routers)))
+        } else {
+            app.merge(api_router(state, self.realtime_enabled))
+        };
+        let app = app
+            .layer(middleware::from_fn(units::formatted_units))
+            .layer(CorsLayer::permissive());
+
+        let host = if open_api {
+            warn!(
+                "No API tokens exist, so the dashboard only listens on 127.0.0.1. \
+                 Create one with `tokens create` and restart to serve other machines."
+            );
+            "127.0.0.1"
+        } else {
+            "0.0.0.0"
+        };
+        let addr = format!("{}:{}", host, port);
         info!("Starting dashboard server on http://{}", addr);
 
         let listener = TcpListener::bind(&addr).await?;
@@ -64,10 +181,276 @@ impl DashboardServer {
     }
 }
 
+/// Every API route, each behind the scope it needs, serving `state`'s
+/// database.
+fn api_router(state: AppState, realtime: bool) -> Router {
+    let mut read = Router::new()
+        .route("/api/status", get(api_status))
+        .route("/api/events", get(api_events))
+        .route("/api/events/:id", get(api_get_event))
+        .route("/api/metrics", get(api_metrics))
+        .route("/api/timeseries", get(api_timeseries))
+        .route("/api/calendar", get(api_calendar))
+        .route("/api/metrics/grouped", get(api_grouped_metrics))
+        .route("/api/advice/defer", get(api_defer_advice))
+        .route("/api/histograms", get(api_histograms))
+        .route("/api/processes", get(api_processes))
+        .route("/api/processes/:pid", get(api_process))
+        .route("/api/derived", get(api_derived))
+        .route("/api/models/throughput", get(api_model_throughput))
+        .route("/api/sync/events", get(api_sync_events))
+        .route("/api/queries", get(api_list_queries))
+        .route("/api/queries/:name", get(api_get_query))
+        .route("/api/queries/:name/events", get(api_run_query))
+        .route("/api/maintenance", get(api_list_maintenance))
+        .route("/api/alerts/history", get(api_alert_history))
+        .route("/api/alerts/rules/:name/debug", get(api_alert_rule_debug))
+        .route("/api/sources", get(api_sources))
+        .route("/api/sources/:source/status", get(api_source_status))
+        .route("/api/sources/:source/events", get(api_source_events))
+        .route("/api/sources/:source/metrics", get(api_source_metrics))
+        .route("/api/sources/:source/timeseries", get(api_source_timeseries))
+        .route("/api/sources/:source/calendar", get(api_source_calendar))
+        .route("/api/sources/:source/derived", get(api_source_derived))
+        .route("/api/sources/:source/queries", get(api_source_queries))
+        .route("/api/sources/:source/queries/:name/events", get(api_source_run_query));
+
+    if realtime {
+        read = read.route("/ws", get(ws_events));
+    }
+    if state.config.dashboard.federation.enabled {
+        read = read
+            .route("/api/federated/status", get(api_federated_status))
+            .route("/api/federated/events", get(api_federated_events))
+            .route("/api/federated/metrics", get(api_federated_metrics))
+            .route("/api/federated/timeseries", get(api_federated_timeseries))
+            .route("/api/federated/queries/:name/events", get(api_federated_run_query));
+        info!(
+            "Federated queries across {} sources enabled at /api/federated",
+            state.config.dashboard.sources.len() + 1
+        );
+    }
+    if state.config.integrations.export_prometheus {
+        read = read.route("/metrics", get(prometheus_metrics));
+        info!("Prometheus metrics (OpenMetrics with exemplars) enabled at /metrics");
+    }
+
+    let write = Router::new().route("/api/events", axum::routing::post(api_ingest_event));
+
+    let admin = Router::new()
+        .route("/api/audit", get(api_audit))
+        .route("/api/events/:id", axum::routing::delete(api_delete_event))
+        .route("/api/events/:id/restore", axum::routing::post(api_restore_event))
+        .route("/api/queries", axum::routing::post(api_save_query))
+        .route("/api/queries/:name", axum::routing::delete(api_delete_query))
+        .route("/api/maintenance", axum::routing::post(api_save_maintenance))
+        .route("/api/maintenance/:name", axum::routing::delete(api_delete_maintenance));
+
+    Router::new()
+        .merge(read.route_layer(middleware::from_fn_with_state(
+            (state.clone(), ApiScope::ReadMetrics),
+            require_scope,
+        )))
+        .merge(write.route_layer(middleware::from_fn_with_state(
+            (state.clone(), ApiScope::WriteEvents),
+            require_scope,
+        )))
+        .merge(admin.route_layer(middleware::from_fn_with_state(
+            (state.clone(), ApiScope::Admin),
+            require_scope,
+        )))
+        .with_state(state)
+}
+
+/// Pass an API request to the router of the presented token's tenant.
+/// Requests without a known token go to the dashboard's own router, whose
+/// scope check rejects them.
+async fn route_to_tenant(State(routers): State<Arc<TenantRouters>>, request: Request) -> Response {
+    let tenant = match presented_token(&request) {
+        Some(token) => match routers.base.tokens.api_token_tenant(&auth::hash_token(&token)).await {
+            Ok(tenant) => tenant.flatten(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        None => None,
+    };
+
+    let router = match tenant {
+        Some(tenant) => match routers.router(&tenant).await {
+            Ok(router) => router,
+            Err(e) => {
+                warn!("Can't open database for tenant '{}': {}", tenant, e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        None => routers.main.clone(),
+    };
+    match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+/// Enforce the route's scope. The API stays open until the first token is
+/// created; after that every request needs a bearer token (or `?token=` on
+/// the WebSocket feed, which browsers can't send headers to) carrying the
+/// scope.
+async fn require_scope(
+    State((state, scope)): State<(AppState, ApiScope)>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let tenancy = state.config.dashboard.tenancy.enabled;
+    let actor = match presented_token(&request) {
+        Some(token) => match state.tokens.authenticate_api_token(&auth::hash_token(&token)).await {
+            Ok(Some(token)) if tenancy && token.tenant != state.tenant => {
+                debug!("Token '{}' belongs to another tenant", token.name);
+                return Err(StatusCode::FORBIDDEN);
+            }
+            Ok(Some(token)) if ApiScope::allows(&token.scopes, scope) => format!("api:{}", token.name),
+            Ok(Some(token)) => {
+                debug!("Token '{}' lacks scope {}", token.name, scope);
+                return Err(StatusCode::FORBIDDEN);
+            }
+            Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        },
+        // Tenancy relies on tokens to tell tenants apart, so it never allows
+        // anonymous access
+        None if tenancy => return Err(StatusCode::UNAUTHORIZED),
+        None => match state.tokens.count_api_tokens().await {
+            Ok(0) => "api:anonymous".to_string(),
+            Ok(_) => return Err(StatusCode::UNAUTHORIZED),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        },
+    };
+
+    request.extensions_mut().insert(ApiActor(actor));
+    Ok(next.run(request).await)
+}
+
+/// Routes that accept the token as `?token=`: WebSocket upgrades can't carry
+/// an Authorization header from a browser.
+const QUERY_TOKEN_PATHS: &[&str] = &["/ws"];
+
+fn presented_token(request: &Request) -> Option<String> {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    // Query strings end up in logs and browser history, so only the routes
+    // that can't take a header accept one
+    if bearer.is_some() || !QUERY_TOKEN_PATHS.contains(&request.uri().path()) {
+        return bearer;
+    }
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(str::to_string)
+}
+
+/// Publish rows written by other processes (e.g. a monitor daemon sharing
+/// the database) to local WebSocket subscribers.
+async fn tail_new_events(storage: MetricsStorage, interval: Duration) {
+    let mut last_id = storage.get_max_event_id().await.unwrap_or(0);
+    let mut local = storage.subscribe();
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        // Events stored in this process (and our own re-broadcasts) were
+        // already delivered
+        while let Ok(event) = local.try_recv() {
+            last_id = last_id.max(event.id.unwrap_or(0));
+        }
+
+        match storage.get_events_after(last_id, 500).await {
+            Ok(events) => {
+                for event in events {
+                    last_id = last_id.max(event.id.unwrap_or(0));
+                    storage.publish(event);
+                }
+            }
+            Err(e) => warn!("Failed to tail new events: {}", e),
+        }
+    }
+}
+
+async fn ws_events(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(query): Query<UnitsQuery>,
+) -> Response {
+    let receiver = state.storage.subscribe();
+    ws.on_upgrade(move |socket| stream_events(socket, receiver, query.units))
+}
+
+/// Forward stored events to a WebSocket client, with `_formatted` strings
+/// when it connected with `?units=true`.
+async fn stream_events(
+    mut socket: WebSocket,
+    mut receiver: tokio::sync::broadcast::Receiver<crate::models::LatencyEvent>,
+    units: bool,
+) {
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("WebSocket client lagged, skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let payload = match serde_json::to_value(&event).and_then(|mut value| {
+            if units {
+                units::annotate(&mut value);
+            }
+            serde_json::to_string(&value)
+        }) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize event for WebSocket: {}", e);
+                continue;
+            }
+        };
+
+        if socket.send(Message::Text(payload)).await.is_err() {
+            // Client went away
+            break;
+        }
+    }
+}
+
 async fn dashboard_html() -> Html<&'static str> {
     Html(include_str!("../static/dashboard.html"))
 }
 
+/// What the dashboard page needs before its first query: the ranges it
+/// offers, the one it opens with, how often it polls and the components it
+/// can filter by. Public like the page itself, as it holds no metrics.
+fn view_config(config: &Config) -> serde_json::Value {
+    json!({
+        "ranges": config.dashboard.ranges,
+        "default_range": config.dashboard.default_range,
+        "refresh_ms": config.dashboard.auto_refresh_ms.max(500),
+        "components": ComponentType::ALL
+            .iter()
+            .map(|component| json!({ "name": component.as_str(), "label": component.to_string() }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Permalink page for one event; it loads `/api/events/:id` client-side so the
+/// API token rules still apply.
+async fn event_html() -> Html<&'static str> {
+    Html(include_str!("../static/event.html"))
+}
+
 async fn api_status(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
     match state.storage.get_system_status().await {
         Ok(status) => Ok(Json(json!(status))),
@@ -82,6 +465,31 @@ async fn api_events(State(state): State<AppState>) -> Result<Json<serde_json::Va
     }
 }
 
+#[derive(Deserialize)]
+struct SyncParams {
+    #[serde(default)]
+    after_timestamp: String,
+    #[serde(default)]
+    after_id: i64,
+    limit: Option<u32>,
+}
+
+/// Watermark-ordered event feed consumed by `sync --from http://...`.
+async fn api_sync_events(
+    State(state): State<AppState>,
+    Query(params): Query<SyncParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let limit = params.limit.unwrap_or(1000).min(10_000);
+    match state
+        .storage
+        .get_events_after_watermark(&params.after_timestamp, params.after_id, limit)
+        .await
+    {
+        Ok(events) => Ok(Json(json!(events))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
 async fn api_metrics(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
     match state.storage.get_performance_metrics().await {
         Ok(metrics) => Ok(Json(json!(metrics))),
@@ -89,6 +497,727 @@ async fn api_metrics(State(state): State<AppState>) -> Result<Json<serde_json::V
     }
 }
 
+/// Latency over time, aggregated per bucket in SQL for charting.
+async fn api_timeseries(
+    state: State<AppState>,
+    query: Query<TimeseriesQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    api_source_timeseries(state, Path(LOCAL_SOURCE.to_string()), query).await
+}
+
+/// Daily p95 per component over months, for the heat calendar.
+async fn api_calendar(
+    state: State<AppState>,
+    query: Query<CalendarQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    api_source_calendar(state, Path(LOCAL_SOURCE.to_string()), query).await
+}
+
+/// `?by=vscode_build&since=24h` on `/api/metrics/grouped`.
+#[derive(Deserialize)]
+struct GroupedParams {
+    by: String,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+/// `?component=vscode&threshold_ms=300` on `/api/advice/defer`.
+#[derive(Deserialize)]
+struct AdviceParams {
+    component: Option<String>,
+    threshold_ms: Option<u64>,
+}
+
+/// Whether a heavy background job should wait because the component is in
+/// use or already slow. Deferring answers carry `Retry-After`.
+async fn api_defer_advice(
+    State(state): State<AppState>,
+    Query(params): Query<AdviceParams>,
+) -> Result<Response, StatusCode> {
+    let component = match params.component.as_deref() {
+        Some(name) => ComponentType::parse(name).ok_or(StatusCode::BAD_REQUEST)?,
+        None => ComponentType::VSCode,
+    };
+    let advice = advice::defer_advice(&state.storage, &state.config.advice, component, params.threshold_ms)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut response = Json(json!(advice)).into_response();
+    if let Some(secs) = advice.retry_after_secs {
+        response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+    }
+    Ok(response)
+}
+
+/// Latency per component and group, e.g. per VS Code build or profile.
+async fn api_grouped_metrics(
+    State(state): State<AppState>,
+    Query(params): Query<GroupedParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let range = TimeRange::parse(Some(params.since.as_deref().unwrap_or("24h")), params.until.as_deref())
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !storage::group_by_columns().any(|column| column == params.by) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match state.storage.get_grouped_metrics(&range, &params.by).await {
+        Ok(groups) => Ok(Json(json!(groups))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// All-time latency histogram per component, bucketed by `[histograms]`.
+async fn api_histograms(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state.storage.get_latency_histograms(&state.config.histograms).await {
+        Ok(histograms) => Ok(Json(json!(histograms))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Values of the `[derived_metrics]` expressions, one series per metric.
+async fn api_derived(
+    state: State<AppState>,
+    query: Query<DerivedQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    api_source_derived(state, Path(LOCAL_SOURCE.to_string()), query).await
+}
+
+/// `?since=24h&model=llama3:8b&limit=50` on `/api/models/throughput`.
+#[derive(Deserialize)]
+struct ThroughputParams {
+    since: Option<String>,
+    until: Option<String>,
+    model: Option<String>,
+    /// Recent responses listed beside the aggregates
+    limit: Option<u32>,
+}
+
+/// Token throughput and time to first token of streamed model responses,
+/// per source and model, with the most recent responses.
+async fn api_model_throughput(
+    State(state): State<AppState>,
+    Query(params): Query<ThroughputParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let range = TimeRange::parse(Some(params.since.as_deref().unwrap_or("24h")), params.until.as_deref())
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let model = params.model.as_deref();
+    let models = state
+        .storage
+        .get_model_throughput(range.since, range.until, model)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let recent = state
+        .storage
+        .get_token_metrics(range.since, range.until, model, params.limit.unwrap_or(50).min(1000))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "since": range.since,
+        "until": range.until,
+        "models": models,
+        "recent": recent,
+    })))
+}
+
+async fn api_list_queries(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state.storage.list_saved_queries().await {
+        Ok(queries) => Ok(Json(json!(queries))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Ingest one event, e.g. from the VS Code extension when IPC isn't available.
+/// A W3C `traceparent` header links the event to the caller's distributed
+/// trace; malformed headers are ignored, as the spec asks.
+async fn api_ingest_event(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut event): Json<LatencyEvent>,
+) -> Result<StatusCode, StatusCode> {
+    event.id = None;
+    // Senders may pass their own `uid` so a retried post is stored once
+    if event.uid.is_none() {
+        event.uid = ids::new_event_id();
+    }
+    let traceparent = headers.get(TRACEPARENT).and_then(|v| v.to_str().ok());
+    if let Some(context) = traceparent.and_then(TraceContext::parse) {
+        context.apply(&mut event).map_err(|_| StatusCode::BAD_REQUEST)?;
+    } else if let Some(value) = traceparent {
+        debug!("Ignoring malformed traceparent '{}'", value);
+    }
+    match state.storage.store_event(&event).await {
+        Ok(()) => Ok(StatusCode::CREATED),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn api_save_query(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Json(query): Json<SavedQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if EventFilter::parse(&query.filter).and_then(|f| f.to_sql()).is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let previous = state
+        .storage
+        .get_saved_query(&query.name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .storage
+        .save_query(&query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let entry = AuditEntry::new(&actor, "queries.save", Some(&query.name))
+        .with_change(previous.map(|q| json!(q)), Some(json!(query)));
+    if let Err(e) = state.storage.record_audit(&entry).await {
+        warn!("Failed to record audit entry: {}", e);
+    }
+
+    Ok(Json(json!(query)))
+}
+
+/// W3C trace context header accepted on event ingestion.
+const TRACEPARENT: &str = "traceparent";
+
+/// Neighbors shown either side of an event on its permalink page.
+const EVENT_NEIGHBORS: u32 = 5;
+/// Process samples within this many seconds of an event count as related.
+const RELATED_SAMPLE_WINDOW_SECS: i64 = 30;
+
+/// One event by its local row id or by its uid.
+async fn api_get_event(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let storage = &state.storage;
+    let found = match id.parse::<i64>() {
+        Ok(id) => storage.get_event(id).await,
+        Err(_) => storage.get_event_by_uid(&id).await,
+    };
+    let event = match found {
+        Ok(Some(event)) => event,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    let id = event.id.unwrap_or_default();
+
+    let details = async {
+        let tags = storage.get_event_tags(id).await?;
+        let (before, after) = storage.get_neighboring_events(&event, EVENT_NEIGHBORS).await?;
+        let samples = storage
+            .get_process_samples_near(
+                event.timestamp,
+                chrono::Duration::seconds(RELATED_SAMPLE_WINDOW_SECS),
+                50,
+            )
+            .await?
+            .into_iter()
+            .filter(|sample| sample.id != event.id && sample.component_type == event.component_type)
+            .collect::<Vec<_>>();
+
+        anyhow::Ok(json!({
+            "event": event,
+            "uid": storage::event_uid(&event),
+            "permalink": format!("/events/{}", id),
+            "tags": tags,
+            "process_samples": samples,
+            "before": before,
+            "after": after,
+        }))
+    };
+
+    details.await.map(Json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn api_get_query(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state.storage.get_saved_query(&name).await {
+        Ok(Some(query)) => Ok(Json(json!(query))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn api_delete_query(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let previous = match state.storage.get_saved_query(&name).await {
+        Ok(Some(query)) => query,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    match state.storage.delete_saved_query(&name).await {
+        Ok(true) => {
+            let entry = AuditEntry::new(&actor, "queries.delete", Some(&name))
+                .with_change(Some(json!(previous)), None);
+            if let Err(e) = state.storage.record_audit(&entry).await {
+                warn!("Failed to record audit entry: {}", e);
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Remove a corrupt or test event; the deleted event is kept in the audit log.
+async fn api_delete_event(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let previous = match state.storage.get_event(id).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    match state.storage.delete_event(id).await {
+        Ok(true) => {
+            let entry = AuditEntry::new(&actor, "events.delete", Some(&id.to_string()))
+                .with_change(Some(json!(previous)), None);
+            if let Err(e) = state.storage.record_audit(&entry).await {
+                warn!("Failed to record audit entry: {}", e);
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Undo a deletion that hasn't been purged yet.
+async fn api_restore_event(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    match state.storage.restore_events(&[id]).await {
+        Ok(0) => Err(StatusCode::NOT_FOUND),
+        Ok(_) => {
+            let entry = AuditEntry::new(&actor, "events.restore", Some(&id.to_string()));
+            if let Err(e) = state.storage.record_audit(&entry).await {
+                warn!("Failed to record audit entry: {}", e);
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Maintenance windows, each with whether it is silencing alerts now.
+async fn api_list_maintenance(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    let windows = state
+        .storage
+        .list_maintenance_windows()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let now = chrono::Utc::now();
+    let windows: Vec<_> = windows
+        .into_iter()
+        .map(|window| {
+            let active = window.is_active(now);
+            let mut value = json!(window);
+            value["active"] = json!(active);
+            value
+        })
+        .collect();
+    Ok(Json(json!(windows)))
+}
+
+async fn api_save_maintenance(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Json(window): Json<MaintenanceWindow>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if window.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let previous = state
+        .storage
+        .get_maintenance_window(&window.name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state
+        .storage
+        .save_maintenance_window(&window)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let entry = AuditEntry::new(&actor, "maintenance.save", Some(&window.name))
+        .with_change(previous.map(|w| json!(w)), Some(json!(window)));
+    if let Err(e) = state.storage.record_audit(&entry).await {
+        warn!("Failed to record audit entry: {}", e);
+    }
+
+    Ok(Json(json!(window)))
+}
+
+async fn api_delete_maintenance(
+    State(state): State<AppState>,
+    Extension(ApiActor(actor)): Extension<ApiActor>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let previous = match state.storage.get_maintenance_window(&name).await {
+        Ok(Some(window)) => window,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    match state.storage.delete_maintenance_window(&name).await {
+        Ok(true) => {
+            let entry = AuditEntry::new(&actor, "maintenance.delete", Some(&name))
+                .with_change(Some(json!(previous)), None);
+            if let Err(e) = state.storage.record_audit(&entry).await {
+                warn!("Failed to record audit entry: {}", e);
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[derive(Deserialize)]
+struct AlertHistoryParams {
+    limit: Option<u32>,
+}
+
+async fn api_alert_history(
+    State(state): State<AppState>,
+    Query(params): Query<AlertHistoryParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state.storage.get_alert_history(params.limit.unwrap_or(100).min(1000)).await {
+        Ok(entries) => Ok(Json(json!(entries))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// `?since=1h&until=...&tick=15s` on `/api/alerts/rules/:name/debug`.
+#[derive(Deserialize)]
+struct RuleDebugParams {
+    since: Option<String>,
+    until: Option<String>,
+    tick: Option<String>,
+}
+
+/// A configured rule replayed over stored events: its inputs, the value and
+/// outcome of each evaluation with the reason it did or didn't fire, and
+/// the state changes the monitor recorded.
+async fn api_alert_rule_debug(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<RuleDebugParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let rule = state.config.alerts.iter().find(|rule| rule.name == name).ok_or(StatusCode::NOT_FOUND)?;
+    let range = TimeRange::parse(Some(params.since.as_deref().unwrap_or("1h")), params.until.as_deref())
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let tick = match params.tick.as_deref() {
+        Some(tick) => crate::timerange::parse_duration(tick).ok_or(StatusCode::BAD_REQUEST)?,
+        None => chrono::Duration::from_std(alerts::SWEEP_INTERVAL).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    let (since, until) = (range.since.unwrap_or_default(), range.until.unwrap_or_else(chrono::Utc::now));
+
+    match alerts::debug_rule(&state.storage, rule, since, until, tick).await {
+        Ok(debug) => Ok(Json(json!(debug))),
+        Err(e) => {
+            debug!("Rejected alert rule debug request: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// `?since=1h&limit=100` on the process views.
+#[derive(Deserialize)]
+struct ProcessParams {
+    since: Option<String>,
+    limit: Option<u32>,
+}
+
+impl ProcessParams {
+    fn since(&self) -> Result<chrono::DateTime<chrono::Utc>, StatusCode> {
+        crate::timerange::parse_time_expr(self.since.as_deref().unwrap_or("1h"), chrono::Utc::now())
+            .map_err(|_| StatusCode::BAD_REQUEST)
+    }
+}
+
+/// Processes sampled in the window, with their CPU and memory peaks.
+async fn api_processes(
+    State(state): State<AppState>,
+    Query(params): Query<ProcessParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let since = params.since()?;
+    match state.storage.get_processes(since, params.limit.unwrap_or(100).min(1000)).await {
+        Ok(processes) => Ok(Json(json!(processes))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Process detail: CPU and memory timeline of one PID.
+async fn api_process(
+    State(state): State<AppState>,
+    Path(pid): Path<u32>,
+    Query(params): Query<ProcessParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let since = params.since()?;
+    let timeline = state
+        .storage
+        .get_process_timeline(pid, since, params.limit.unwrap_or(1000).min(10_000))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if timeline.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let cpu: Vec<f64> = timeline.iter().map(|point| point.cpu_percent).collect();
+    Ok(Json(json!({
+        "pid": pid,
+        "since": since,
+        "process_name": timeline.last().map(|point| point.process_name.clone()),
+        "samples": timeline.len(),
+        "avg_cpu_percent": cpu.iter().sum::<f64>() / cpu.len() as f64,
+        "max_cpu_percent": cpu.iter().copied().fold(0.0, f64::max),
+        "max_memory_kb": timeline.iter().map(|point| point.memory_kb).max(),
+        "timeline": timeline,
+    })))
+}
+
+#[derive(Deserialize)]
+struct AuditParams {
+    action: Option<String>,
+    limit: Option<u32>,
+}
+
+async fn api_audit(
+    State(state): State<AppState>,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state
+        .storage
+        .get_audit_log(params.action.as_deref(), params.limit.unwrap_or(100).min(1000))
+        .await
+    {
+        Ok(entries) => Ok(Json(json!(entries))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn api_run_query(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let query = match state.storage.get_saved_query(&name).await {
+        Ok(Some(query)) => query,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let filter = EventFilter::parse(&query.filter)
+        .and_then(|f| f.to_sql())
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    match state.storage.query_events(Some(&filter), query.limit.unwrap_or(50)).await {
+        Ok(events) => Ok(Json(json!(events))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn api_sources(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(state.sources.list())
+}
+
+fn find_source<'a>(state: &'a AppState, name: &str) -> Result<&'a DataSource, StatusCode> {
+    state.sources.get(name).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// A remote source failing is the upstream's fault, not ours.
+fn source_error(source: &DataSource, e: anyhow::Error) -> StatusCode {
+    debug!("Dashboard source request failed: {}", e);
+    match source {
+        DataSource::Remote { .. } | DataSource::Backend(_) => StatusCode::BAD_GATEWAY,
+        DataSource::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn api_source_status(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    source.status().await.map(Json).map_err(|e| source_error(source, e))
+}
+
+async fn api_source_events(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    source.recent_events().await.map(Json).map_err(|e| source_error(source, e))
+}
+
+async fn api_source_metrics(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    source.metrics().await.map(Json).map_err(|e| source_error(source, e))
+}
+
+async fn api_source_timeseries(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<TimeseriesQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    if let Err(e) = query.resolve(&state.config.working_hours) {
+        debug!("Rejected timeseries request: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    source
+        .timeseries(&query, &state.config.working_hours)
+        .await
+        .map(Json)
+        .map_err(|e| source_error(source, e))
+}
+
+async fn api_source_calendar(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<CalendarQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    source
+        .calendar(&query, &state.config.working_hours, &state.config.histograms)
+        .await
+        .map(Json)
+        .map_err(|e| source_error(source, e))
+}
+
+async fn api_source_derived(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<DerivedQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    if let Err(e) = query.since() {
+        debug!("Rejected derived metrics request: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    source.derived(&query).await.map(Json).map_err(|e| source_error(source, e))
+}
+
+async fn api_source_queries(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    source.saved_queries().await.map(Json).map_err(|e| source_error(source, e))
+}
+
+async fn api_source_run_query(
+    State(state): State<AppState>,
+    Path((name, query)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_source(&state, &name)?;
+    match source.run_query(&query).await {
+        Ok(Some(events)) => Ok(Json(events)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Err(source_error(source, e)),
+    }
+}
+
+async fn federate<F, Fut>(state: &AppState, read: F) -> Federated
+where
+    F: Fn(Arc<DataSource>) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<Option<serde_json::Value>>> + Send + 'static,
+{
+    federation::federate(&state.config.dashboard.federation, &state.sources, read).await
+}
+
+async fn api_federated_status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let federated = federate(&state, |source| async move {
+        source.status().await.map(Some)
+    })
+    .await;
+    Json(json!(federated))
+}
+
+async fn api_federated_events(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let mut federated = federate(&state, |source| async move {
+        source.recent_events().await.map(Some)
+    })
+    .await;
+    federated.sort_by_field("timestamp", true);
+    Json(json!(federated))
+}
+
+async fn api_federated_metrics(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let federated = federate(&state, |source| async move {
+        source.metrics().await.map(Some)
+    })
+    .await;
+    Json(json!(federated))
+}
+
+async fn api_federated_timeseries(
+    State(state): State<AppState>,
+    Query(query): Query<TimeseriesQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if let Err(e) = query.resolve(&state.config.working_hours) {
+        debug!("Rejected timeseries request: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let working_hours = state.config.working_hours.clone();
+    let mut federated = federate(&state, |source| {
+        let (query, working_hours) = (query.clone(), working_hours.clone());
+        async move {
+            let mut series = source.timeseries(&query, &working_hours).await?;
+            Ok(series.get_mut("points").map(serde_json::Value::take))
+        }
+    })
+    .await;
+    federated.sort_by_field("bucket_start", false);
+    Ok(Json(json!(federated)))
+}
+
+/// Run a saved query on every host that has one by this name.
+async fn api_federated_run_query(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Json<serde_json::Value> {
+    let mut federated = federate(&state, |source| {
+        let name = name.clone();
+        async move { source.run_query(&name).await }
+    })
+    .await;
+    federated.sort_by_field("timestamp", true);
+    Json(json!(federated))
+}
+
+async fn prometheus_metrics(State(state): State<AppState>) -> Result<Response, StatusCode> {
+    let body = prometheus::render_openmetrics(&state.storage, &state.config.histograms)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, prometheus::OPENMETRICS_CONTENT_TYPE)
+        .body(body.into())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
 async fn health_check() -> Json<serde_json::Value> {
     Json(json!({
         "status": "healthy",
@@ -0,0 +1,517 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::backend::StorageBackend;
+use crate::config::Config;
+use crate::daemon;
+use crate::dashboard::DashboardServer;
+use crate::lifecycle::LifecycleKind;
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::storage::MetricsStorage;
+
+/// Seeded events are written this many to a transaction.
+const BATCH_SIZE: usize = 5000;
+/// How often the live generator writes what "happened" since its last round.
+const LIVE_INTERVAL: Duration = Duration::from_secs(2);
+/// Length of the slowdown planted in the history, in minutes.
+const INCIDENT_MINUTES: i64 = 90;
+
+/// Extensions and their usual CPU share, in percent.
+const EXTENSIONS: &[(&str, f64)] = &[
+    ("rust-lang.rust-analyzer", 6.0),
+    ("github.copilot", 4.0),
+    ("ms-python.python", 3.0),
+    ("eamodio.gitlens", 2.5),
+    ("dbaeumer.vscode-eslint", 1.5),
+    ("esbenp.prettier-vscode", 1.0),
+];
+const LANGUAGE_SERVERS: &[&str] = &["rust-analyzer", "pyright", "typescript-language-server"];
+/// LSP methods and their median latency in milliseconds.
+const LSP_METHODS: &[(&str, f64)] = &[
+    ("textDocument/completion", 45.0),
+    ("textDocument/hover", 25.0),
+    ("textDocument/definition", 30.0),
+    ("textDocument/codeAction", 60.0),
+    ("textDocument/semanticTokens/full", 120.0),
+];
+/// Shell commands and their median latency in milliseconds.
+const TERMINAL_COMMANDS: &[(&str, f64)] = &[
+    ("git status", 40.0),
+    ("git diff", 60.0),
+    ("ls", 8.0),
+    ("rg TODO", 90.0),
+    ("npm run lint", 4500.0),
+    ("cargo test", 14000.0),
+];
+const CRATES: &[&str] = &["demo-core", "demo-api", "demo-cli", "serde_json", "tokio"];
+const NETWORK_TARGETS: &[(&str, &str)] = &[("copilot", "api.githubcopilot.com"), ("github", "github.com")];
+
+pub struct DemoOptions {
+    /// Days of history to generate
+    pub days: u32,
+    pub seed: u64,
+    /// Keep the database here instead of in a temporary directory
+    pub database: Option<PathBuf>,
+    /// Keep generating events while the dashboard runs
+    pub live: bool,
+}
+
+/// Seed a fresh database with `days` of generated history and serve it on
+/// `port` until interrupted. The user's own database and config files are
+/// never touched.
+pub async fn run(config: &Config, options: &DemoOptions, port: u16) -> Result<()> {
+    let database = DemoDatabase::create(options.database.clone())?;
+    let storage = MetricsStorage::new(&database.path).await?;
+
+    info!("Generating {} days of demo history in {}", options.days, database.path.display());
+    let inserted = seed(&storage, options.days, options.seed).await?;
+    info!("Generated {} demo events", inserted);
+
+    // Only the demo database, whatever the real dashboard is set up with
+    let mut config = config.clone();
+    config.storage.database_path = database.path.clone();
+    config.storage.backend = StorageBackend::Sqlite;
+    config.dashboard.sources.clear();
+    config.dashboard.federation.enabled = false;
+    config.dashboard.tenancy.enabled = false;
+
+    if options.live {
+        let storage = storage.clone();
+        let seed = options.seed;
+        tokio::spawn(async move {
+            if let Err(e) = run_live(storage, seed).await {
+                warn!("Demo event generator stopped: {}", e);
+            }
+        });
+    }
+
+    println!("Demo dashboard: http://localhost:{} (Ctrl-C to stop)", port);
+    let server = DashboardServer::new(config, storage, options.live).await?;
+    tokio::select! {
+        result = server.serve(port) => result?,
+        result = daemon::shutdown_signal() => result?,
+    }
+
+    if !database.temporary {
+        println!("Demo database kept at {}", database.path.display());
+    }
+    Ok(())
+}
+
+/// Write `days` of history ending now to `storage`, returning how many
+/// events were stored. The same `seed` gives the same mix of events.
+pub async fn seed(storage: &MetricsStorage, days: u32, seed: u64) -> Result<u64> {
+    let now = Utc::now();
+    let start = now - chrono::Duration::days(days as i64);
+    let mut generator = Generator::new(seed, Some(incident_start(now, days)));
+    let minute = chrono::Duration::minutes(1);
+
+    let mut inserted = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut at = start;
+    while at < now {
+        generator.span(at, minute, activity(at), &mut batch);
+        if batch.len() >= BATCH_SIZE {
+            inserted += storage.import_events(&batch).await?;
+            batch.clear();
+        }
+        at += minute;
+    }
+    inserted += storage.import_events(&batch).await?;
+    Ok(inserted)
+}
+
+/// Keep writing events as they "happen", so the real-time views have
+/// something to show. Runs until the task is dropped.
+pub async fn run_live(storage: MetricsStorage, seed: u64) -> Result<()> {
+    let mut generator = Generator::new(seed.wrapping_add(1), None);
+    let mut last = Utc::now();
+    loop {
+        tokio::time::sleep(LIVE_INTERVAL).await;
+        let now = Utc::now();
+        let mut events = Vec::new();
+        // A demo shown at night should still look busy
+        generator.span(last, now - last, activity(last).max(0.6), &mut events);
+        storage.import_events(&events).await?;
+        last = now;
+    }
+}
+
+/// How busy the made-up developer is at `at`, from 0 (editor closed) to 1:
+/// office hours on weekdays with a lunch dip, a little evening and weekend
+/// work, nothing at night.
+fn activity(at: DateTime<Utc>) -> f64 {
+    let local = at.with_timezone(&Local);
+    let hour = local.hour() as f64 + local.minute() as f64 / 60.0;
+    let level = match hour {
+        h if (9.0..12.0).contains(&h) => 1.0,
+        h if (12.0..13.0).contains(&h) => 0.3,
+        h if (13.0..18.0).contains(&h) => 0.9,
+        h if (8.0..9.0).contains(&h) || (18.0..20.0).contains(&h) => 0.35,
+        h if (20.0..23.0).contains(&h) => 0.1,
+        _ => 0.0,
+    };
+    match local.weekday() {
+        Weekday::Sat | Weekday::Sun => level * 0.15,
+        _ => level,
+    }
+}
+
+/// 14:00 local time about halfway through the history, when everything
+/// gets slower for a while and the extension host crash-loops, so the
+/// anomaly and lifecycle views have something to find.
+fn incident_start(now: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    let day = (now - chrono::Duration::days(days as i64 / 2 + 1)).with_timezone(&Local).date_naive();
+    let afternoon = day.and_time(NaiveTime::from_hms_opt(14, 0, 0).unwrap_or_default());
+    Local
+        .from_local_datetime(&afternoon)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+        .unwrap_or(now - chrono::Duration::days(days as i64 / 2 + 1))
+}
+
+/// Produces plausible events for any stretch of time: per-minute rates
+/// scaled by activity, latencies log-normally distributed around each
+/// operation's median.
+struct Generator {
+    rng: StdRng,
+    incident: Option<DateTime<Utc>>,
+    /// Latency multiplier for the span being generated
+    slowdown: f64,
+    crash_loop_reported: bool,
+}
+
+impl Generator {
+    fn new(seed: u64, incident: Option<DateTime<Utc>>) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            incident,
+            slowdown: 1.0,
+            crash_loop_reported: false,
+        }
+    }
+
+    /// Events for `length` of time from `start`; `activity` is how busy the
+    /// developer is, with 0 meaning the editor is closed.
+    fn span(
+        &mut self,
+        start: DateTime<Utc>,
+        length: chrono::Duration,
+        activity: f64,
+        events: &mut Vec<LatencyEvent>,
+    ) {
+        if activity <= 0.0 || length <= chrono::Duration::zero() {
+            return;
+        }
+        let minutes = length.num_milliseconds() as f64 / 60_000.0;
+        let in_incident = self.incident.is_some_and(|incident| {
+            start >= incident && start < incident + chrono::Duration::minutes(INCIDENT_MINUTES)
+        });
+        self.slowdown = if in_incident { 3.5 } else { 1.0 };
+
+        // Collectors sample on a timer while the editor is open
+        for _ in 0..self.count(1.0, minutes) {
+            let at = self.at(start, length);
+            events.push(self.vscode_sample(at, in_incident));
+        }
+        for _ in 0..self.count(1.0, minutes) {
+            let at = self.at(start, length);
+            events.push(self.extension_sample(at, in_incident));
+        }
+        for _ in 0..self.count(1.0, minutes) {
+            let at = self.at(start, length);
+            events.push(self.disk_probe(at));
+        }
+        for _ in 0..self.count(1.0, minutes) {
+            let at = self.at(start, length);
+            events.push(self.network_probe(at));
+        }
+        for _ in 0..self.count(0.2, minutes) {
+            let at = self.at(start, length);
+            events.push(self.local_model_probe(at));
+        }
+
+        // Everything else follows what the developer is doing
+        for _ in 0..self.count(4.0 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(self.copilot_request(at));
+        }
+        for _ in 0..self.count(5.0 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(self.lsp_request(at));
+        }
+        for _ in 0..self.count(1.0 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(self.terminal_command(at));
+        }
+        for _ in 0..self.count(0.2 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(self.editor_log(at));
+        }
+        for _ in 0..self.count(0.05 * activity, minutes) {
+            let at = self.at(start, length);
+            self.cargo_build(at, events);
+        }
+
+        if in_incident {
+            for _ in 0..self.count(0.08, minutes) {
+                let at = self.at(start, length);
+                events.push(lifecycle_event(at, LifecycleKind::ExtensionHostCrash));
+                if !self.crash_loop_reported {
+                    self.crash_loop_reported = true;
+                    events.push(lifecycle_event(at, LifecycleKind::CrashLoop));
+                }
+            }
+        }
+        for _ in 0..self.count(0.003 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(lifecycle_event(at, LifecycleKind::ExtensionHostRestart));
+        }
+        for _ in 0..self.count(0.002 * activity, minutes) {
+            let at = self.at(start, length);
+            events.push(lifecycle_event(at, LifecycleKind::RendererReload));
+        }
+    }
+
+    /// How many times something happening `per_minute` times a minute
+    /// happened over `minutes`.
+    fn count(&mut self, per_minute: f64, minutes: f64) -> usize {
+        let expected = (per_minute * minutes).max(0.0);
+        expected.floor() as usize + usize::from(self.rng.gen_bool(expected.fract()))
+    }
+
+    fn at(&mut self, start: DateTime<Utc>, length: chrono::Duration) -> DateTime<Utc> {
+        start + chrono::Duration::milliseconds(self.rng.gen_range(0..length.num_milliseconds().max(1)))
+    }
+
+    /// A latency around `median_ms`: most close to it, with a long tail of
+    /// slow ones, `spread` setting how long.
+    fn latency(&mut self, median_ms: f64, spread: f64) -> Duration {
+        let u1: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let u2: f64 = self.rng.gen();
+        let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        Duration::from_secs_f64(median_ms * (spread * normal).exp() * self.slowdown / 1000.0)
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.rng.gen_range(0..items.len())]
+    }
+
+    fn vscode_sample(&mut self, at: DateTime<Utc>, in_incident: bool) -> LatencyEvent {
+        let cpu_percent = self.rng.gen_range(2.0..18.0) * if in_incident { 3.0 } else { 1.0 };
+        let memory_mb = self.rng.gen_range(650.0..900.0);
+        let build = if self.rng.gen_bool(0.1) { "insiders" } else { "stable" };
+        LatencyEvent::builder(ComponentType::VSCode, EventSource::ProcessMonitor)
+            .duration(self.latency(12.0, 0.4))
+            .description(format!("VS Code 4242 - CPU: {:.1}%", cpu_percent))
+            .timestamp(at)
+            .metadata(json!({
+                "pid": 4242,
+                "process_name": "code",
+                "cpu_percent": cpu_percent,
+                "memory_mb": memory_mb,
+                "memory_kb": (memory_mb * 1024.0) as u64,
+                "vscode_build": build,
+                "vscode_window": "main.rs - demo",
+            }))
+            .build()
+    }
+
+    fn extension_sample(&mut self, at: DateTime<Utc>, in_incident: bool) -> LatencyEvent {
+        let (extension, usual_cpu) = *self.pick(EXTENSIONS);
+        let cpu_percent = if in_incident && extension == "rust-lang.rust-analyzer" {
+            self.rng.gen_range(70.0..95.0)
+        } else {
+            usual_cpu * self.rng.gen_range(0.3..2.0)
+        };
+        LatencyEvent::builder(ComponentType::VSCodeExtension, EventSource::ExtensionHost)
+            .duration(self.latency(15.0, 0.4))
+            .description(format!("Extension {} - CPU: {:.1}%", extension, cpu_percent))
+            .timestamp(at)
+            .metadata(json!({ "pid": 4250, "extension_id": extension, "cpu_percent": cpu_percent }))
+            .build()
+    }
+
+    fn copilot_request(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        // Now and then the service has a bad moment
+        let duration = if self.rng.gen_bool(0.01) { self.latency(4000.0, 0.4) } else { self.latency(350.0, 0.5) };
+        let tokens = self.rng.gen_range(20..400);
+        LatencyEvent::builder(ComponentType::GitHubCopilot, EventSource::NetworkRequest)
+            .kind(EventKind::ModelRequest)
+            .duration(duration)
+            .description(format!("Copilot completion - {} tokens", tokens))
+            .timestamp(at)
+            .metadata(json!({ "estimated_tokens": tokens, "success": true }))
+            .build()
+    }
+
+    fn local_model_probe(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        let duration = self.latency(900.0, 0.3);
+        let ttft = duration.as_millis() as u64 / 4;
+        LatencyEvent::builder(ComponentType::LocalModel, EventSource::NetworkRequest)
+            .kind(EventKind::ModelRequest)
+            .duration(duration)
+            .description(format!("Ollama probe (llama3:8b) ttft {}ms", ttft))
+            .timestamp(at)
+            .metadata(json!({ "model": "llama3:8b", "time_to_first_token_ms": ttft, "estimated_tokens": 32 }))
+            .build()
+    }
+
+    fn lsp_request(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        let server = *self.pick(LANGUAGE_SERVERS);
+        let (method, median) = *self.pick(LSP_METHODS);
+        LatencyEvent::builder(ComponentType::LanguageServer, EventSource::ExtensionHost)
+            .kind(EventKind::CommandRun)
+            .duration(self.latency(median, 0.6))
+            .description(format!("{} {}", server, method))
+            .timestamp(at)
+            .metadata(json!({ "language_server": server, "lsp_method": method, "success": true }))
+            .build()
+    }
+
+    fn terminal_command(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        let (command, median) = *self.pick(TERMINAL_COMMANDS);
+        let exit_code = if self.rng.gen_bool(0.05) { 1 } else { 0 };
+        LatencyEvent::builder(ComponentType::Terminal, EventSource::CommandExecution)
+            .duration(self.latency(median, 0.3))
+            .description(command)
+            .timestamp(at)
+            .metadata(json!({ "command": command, "exit_code": exit_code, "success": exit_code == 0 }))
+            .build()
+    }
+
+    fn disk_probe(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        let duration = self.latency(3.0, 0.5);
+        LatencyEvent::builder(ComponentType::FileSystem, EventSource::FileOperation)
+            .kind(EventKind::Probe)
+            .duration(duration)
+            .description(format!("Disk probe write+fsync 64KB - fsync {:.1}ms", duration.as_secs_f64() * 800.0))
+            .timestamp(at)
+            .build()
+    }
+
+    fn network_probe(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        let (target, host) = *self.pick(NETWORK_TARGETS);
+        LatencyEvent::builder(ComponentType::Network, EventSource::NetworkRequest)
+            .duration(self.latency(45.0, 0.4))
+            .description(format!("HTTPS HEAD {} 200", target))
+            .timestamp(at)
+            .metadata(json!({ "probe": "https", "target": target, "host": host, "status": 200, "success": true }))
+            .build()
+    }
+
+    fn editor_log(&mut self, at: DateTime<Utc>) -> LatencyEvent {
+        LatencyEvent::builder(ComponentType::Editor, EventSource::UserInteraction)
+            .duration(self.latency(30.0, 0.7))
+            .description("Zed: project search")
+            .timestamp(at)
+            .metadata(json!({ "editor": "zed", "operation": "project search" }))
+            .build()
+    }
+
+    /// A `cargo latency` build: the cargo command and one event per crate
+    /// rustc compiled, as the real wrapper records them.
+    fn cargo_build(&mut self, at: DateTime<Utc>, events: &mut Vec<LatencyEvent>) {
+        let build_id = format!("{}-demo", at.timestamp_millis());
+        let compiled = &CRATES[..self.rng.gen_range(1..=3)];
+        let mut total = Duration::ZERO;
+        for crate_name in compiled {
+            let duration = self.latency(2500.0, 0.5);
+            total += duration;
+            events.push(
+                LatencyEvent::builder(ComponentType::BuildTool, EventSource::CommandExecution)
+                    .kind(EventKind::CommandRun)
+                    .duration(duration)
+                    .description(format!("rustc {} (incremental)", crate_name))
+                    .timestamp(at + chrono::Duration::from_std(total).unwrap_or_default())
+                    .metadata(json!({
+                        "build_id": build_id,
+                        "cargo_command": "build",
+                        "crate_name": crate_name,
+                        "incremental": true,
+                        "primary": true,
+                        "success": true,
+                    }))
+                    .build(),
+            );
+        }
+        let wall = total + self.latency(400.0, 0.3);
+        events.push(
+            LatencyEvent::builder(ComponentType::BuildTool, EventSource::CommandExecution)
+                .kind(EventKind::CommandRun)
+                .duration(wall)
+                .description("cargo build")
+                .timestamp(at + chrono::Duration::from_std(wall).unwrap_or_default())
+                .metadata(json!({
+                    "build_id": build_id,
+                    "cargo_command": "build",
+                    "exit_code": 0,
+                    "success": true,
+                    "rustc_invocations": compiled.len(),
+                    "incremental_invocations": compiled.len(),
+                }))
+                .build(),
+        );
+    }
+}
+
+fn lifecycle_event(at: DateTime<Utc>, lifecycle: LifecycleKind) -> LatencyEvent {
+    let (component, process_kind, description) = match lifecycle {
+        LifecycleKind::ExtensionHostRestart => {
+            (ComponentType::VSCodeExtension, "extension_host", "Extension Host restarted")
+        }
+        LifecycleKind::ExtensionHostCrash => {
+            (ComponentType::VSCodeExtension, "extension_host", "Extension Host exited while window was still open")
+        }
+        LifecycleKind::RendererReload => (ComponentType::VSCode, "renderer", "Renderer reloaded"),
+        LifecycleKind::CrashLoop => {
+            (ComponentType::VSCodeExtension, "extension_host", "Extension Host crash loop detected")
+        }
+    };
+    LatencyEvent::builder(component, EventSource::ExtensionHost)
+        .kind(EventKind::Lifecycle)
+        .description(description)
+        .timestamp(at)
+        .metadata(json!({ "lifecycle": lifecycle, "process_kind": process_kind, "detected_at": at }))
+        .build()
+}
+
+/// Where the demo database lives: a temporary directory removed on drop,
+/// or a path the user asked to keep.
+struct DemoDatabase {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl DemoDatabase {
+    fn create(keep_at: Option<PathBuf>) -> Result<Self> {
+        if let Some(path) = keep_at {
+            // Never mix generated events into a real database
+            if path.exists() {
+                return Err(anyhow!("{} already exists; the demo only writes to a new database", path.display()));
+            }
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            return Ok(Self { path, temporary: false });
+        }
+
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-demo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { path: dir.join("demo.db"), temporary: true })
+    }
+}
+
+impl Drop for DemoDatabase {
+    fn drop(&mut self) {
+        if let (true, Some(dir)) = (self.temporary, self.path.parent()) {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::models::{ComponentType, DerivedMetricSample, PerformanceMetrics};
+use crate::storage::MetricsStorage;
+use crate::timerange::TimeRange;
+
+/// Metrics computed from the per-component rollups, configured as
+/// `[derived_metrics.expressions]`, e.g.
+/// `copilot_overhead = "GitHubCopilot.p95 - Network.p95"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DerivedMetricsConfig {
+    /// How often the expressions are evaluated and stored
+    pub interval_secs: u64,
+    /// Span of events the rollups cover at each evaluation
+    pub window_secs: u64,
+    /// Metric name to expression
+    pub expressions: BTreeMap<String, String>,
+}
+
+impl Default for DerivedMetricsConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            window_secs: 300,
+            expressions: BTreeMap::new(),
+        }
+    }
+}
+
+impl DerivedMetricsConfig {
+    /// Parse every expression, failing on the first invalid one.
+    pub fn compile(&self) -> Result<Vec<DerivedMetric>> {
+        self.expressions
+            .iter()
+            .map(|(name, expression)| DerivedMetric::parse(name, expression))
+            .collect()
+    }
+}
+
+/// A named expression over rollup statistics. Operands are `Component.stat`
+/// (`GitHubCopilot.p95`) or numbers, combined with `+ - * /` and
+/// parentheses.
+#[derive(Debug, Clone)]
+pub struct DerivedMetric {
+    pub name: String,
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Stat(ComponentType, Stat),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+/// Rollup statistic an operand reads; latencies are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stat {
+    Avg,
+    Min,
+    Max,
+    P50,
+    P95,
+    P99,
+    Count,
+    Rate,
+    ErrorRate,
+}
+
+impl Stat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "avg" => Some(Stat::Avg),
+            "min" => Some(Stat::Min),
+            "max" => Some(Stat::Max),
+            "p50" => Some(Stat::P50),
+            "p95" => Some(Stat::P95),
+            "p99" => Some(Stat::P99),
+            "count" => Some(Stat::Count),
+            "rate" => Some(Stat::Rate),
+            "error_rate" => Some(Stat::ErrorRate),
+            _ => None,
+        }
+    }
+
+    fn value(&self, metrics: &PerformanceMetrics) -> f64 {
+        match self {
+            Stat::Avg => metrics.avg_duration_ms,
+            Stat::Min => metrics.min_duration_ms as f64,
+            Stat::Max => metrics.max_duration_ms as f64,
+            Stat::P50 => metrics.p50_duration_ms as f64,
+            Stat::P95 => metrics.p95_duration_ms as f64,
+            Stat::P99 => metrics.p99_duration_ms as f64,
+            Stat::Count => metrics.total_events as f64,
+            Stat::Rate => metrics.events_per_second,
+            Stat::ErrorRate => metrics.error_rate,
+        }
+    }
+}
+
+impl DerivedMetric {
+    pub fn parse(name: &str, source: &str) -> Result<Self> {
+        let valid_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow!(
+                "Invalid derived metric name '{}' (use letters, digits and underscores)",
+                name
+            ));
+        }
+
+        let tokens = tokenize(source).map_err(|e| anyhow!("Derived metric '{}': {}", name, e))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr().map_err(|e| anyhow!("Derived metric '{}': {}", name, e))?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(anyhow!("Derived metric '{}': unexpected '{}'", name, token));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Value over `rollups`, or `None` when an operand's component has no
+    /// events or the result isn't a finite number (division by zero).
+    pub fn evaluate(&self, rollups: &[PerformanceMetrics]) -> Option<f64> {
+        eval(&self.expr, rollups).filter(|value| value.is_finite())
+    }
+}
+
+impl fmt::Display for DerivedMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.name, self.source)
+    }
+}
+
+fn eval(expr: &Expr, rollups: &[PerformanceMetrics]) -> Option<f64> {
+    match expr {
+        Expr::Number(value) => Some(*value),
+        Expr::Stat(component, stat) => match rollups.iter().find(|m| m.component == *component) {
+            Some(metrics) => Some(stat.value(metrics)),
+            // A component with no events has seen none of them at any rate
+            None if matches!(stat, Stat::Count | Stat::Rate) => Some(0.0),
+            None => None,
+        },
+        Expr::Neg(inner) => eval(inner, rollups).map(|value| -value),
+        Expr::Binary(left, op, right) => {
+            let (left, right) = (eval(left, rollups)?, eval(right, rollups)?);
+            Some(match op {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                _ => left / right,
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            let value = number.parse().map_err(|_| anyhow!("invalid number '{}'", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '.') {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else {
+            return Err(anyhow!("unexpected character '{}'", c));
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(anyhow!("empty expression"));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent with the usual precedence: `* /` bind tighter than
+/// `+ -`, unary minus tightest.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_op(&mut self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(*op) => {
+                self.pos += 1;
+                Some(*op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut left = self.term()?;
+        while let Some(op) = self.next_op("+-") {
+            left = Expr::Binary(Box::new(left), op, Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.next_op("*/") {
+            left = Expr::Binary(Box::new(left), op, Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.next_op("-").is_some() {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| anyhow!("expression ends early"))?;
+        self.pos += 1;
+
+        match token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Ident(ident) => operand(&ident),
+            Token::Op('(') => {
+                let inner = self.expr()?;
+                if self.next_op(")").is_none() {
+                    return Err(anyhow!("missing ')'"));
+                }
+                Ok(inner)
+            }
+            Token::Op(op) => Err(anyhow!("unexpected '{}'", op)),
+        }
+    }
+}
+
+/// `Component.stat`, e.g. `GitHubCopilot.p95`.
+fn operand(ident: &str) -> Result<Expr> {
+    let (component, stat) = ident
+        .split_once('.')
+        .ok_or_else(|| anyhow!("'{}' should be Component.stat, e.g. GitHubCopilot.p95", ident))?;
+    let component: ComponentType = serde_json::from_value(serde_json::json!(component))
+        .map_err(|_| anyhow!("unknown component '{}'", component))?;
+    let stat = Stat::parse(stat).ok_or_else(|| {
+        anyhow!(
+            "unknown statistic '{}' (expected avg, min, max, p50, p95, p99, count, rate or error_rate)",
+            stat
+        )
+    })?;
+    Ok(Expr::Stat(component, stat))
+}
+
+/// Evaluate the configured expressions every `interval_secs` and store the
+/// results until the task is dropped.
+pub async fn run_derived_metrics(config: DerivedMetricsConfig, storage: MetricsStorage) {
+    let metrics = match config.compile() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            warn!("Derived metrics disabled: {}", e);
+            return;
+        }
+    };
+    let window = chrono::Duration::seconds(config.window_secs.max(1) as i64);
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    info!("Evaluating {} derived metric(s)", metrics.len());
+
+    loop {
+        interval.tick().await;
+
+        let rollups = match storage.get_performance_metrics_in(&TimeRange::last(window)).await {
+            Ok(rollups) => rollups,
+            Err(e) => {
+                warn!("Failed to compute rollups for derived metrics: {}", e);
+                continue;
+            }
+        };
+
+        let at = Utc::now();
+        let samples: Vec<_> = metrics
+            .iter()
+            .filter_map(|metric| match metric.evaluate(&rollups) {
+                Some(value) => Some(DerivedMetricSample {
+                    name: metric.name.clone(),
+                    value,
+                    at,
+                }),
+                None => {
+                    debug!("Derived metric '{}' has no value this interval", metric.name);
+                    None
+                }
+            })
+            .collect();
+
+        if let Err(e) = storage.store_derived_metrics(&samples).await {
+            warn!("Failed to store derived metrics: {}", e);
+        }
+    }
+}
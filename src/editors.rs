@@ -0,0 +1,461 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use sysinfo::Process;
+
+use crate::platform;
+
+/// The built-in VS Code profile, served by the VS Code, extension and
+/// Copilot collectors rather than by the generic editor collector.
+pub const VSCODE: &str = "vscode";
+
+/// Longest stretch of a log read in one round; a log that grew more than
+/// this since the last round is skipped ahead rather than parsed in full.
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// Which editors are monitored, configured as `[monitoring.editors]`.
+/// `vscode` is the default and uses the dedicated VS Code collectors; every
+/// other profile is sampled and its logs parsed by the generic editor
+/// collector.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorsConfig {
+    /// Profiles to monitor, by name
+    pub active: Vec<String>,
+    /// Profiles added to the built-in ones; one with a built-in's name
+    /// replaces it
+    pub profiles: Vec<EditorProfile>,
+}
+
+impl Default for EditorsConfig {
+    fn default() -> Self {
+        Self {
+            active: vec![VSCODE.to_string()],
+            profiles: Vec::new(),
+        }
+    }
+}
+
+impl EditorsConfig {
+    pub fn is_active(&self, name: &str) -> bool {
+        self.active.iter().any(|active| active == name)
+    }
+
+    /// Every known profile: the built-in ones, overridden or extended by
+    /// `profiles`.
+    pub fn all_profiles(&self) -> Vec<EditorProfile> {
+        let mut profiles: Vec<EditorProfile> = builtin_profiles()
+            .into_iter()
+            .filter(|builtin| !self.profiles.iter().any(|profile| profile.name == builtin.name))
+            .collect();
+        profiles.extend(self.profiles.iter().cloned());
+        profiles
+    }
+
+    /// Active profiles the generic collector handles, i.e. all but VS Code.
+    pub fn generic_profiles(&self) -> Vec<EditorProfile> {
+        self.all_profiles()
+            .into_iter()
+            .filter(|profile| profile.name != VSCODE && self.is_active(&profile.name))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let profiles = self.all_profiles();
+        for name in &self.active {
+            if !profiles.iter().any(|profile| &profile.name == name) {
+                let known: Vec<&str> = profiles.iter().map(|profile| profile.name.as_str()).collect();
+                return Err(anyhow!("monitoring.editors.active: unknown editor '{}' ({})", name, known.join(", ")));
+            }
+        }
+        for profile in &self.profiles {
+            if profile.name.is_empty() {
+                return Err(anyhow!("monitoring.editors.profiles: every profile needs a name"));
+            }
+            for log in &profile.logs {
+                log.compile().map_err(|e| anyhow!("Editor '{}': {}", profile.name, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How to recognize one editor's processes and where to read its latency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorProfile {
+    /// Recorded as `metadata.editor` on its events
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+    /// Executable names, matched without case or a `.exe` suffix
+    #[serde(default)]
+    pub process_names: Vec<String>,
+    /// Command-line fragments that identify the editor when its executable
+    /// doesn't, e.g. a JetBrains IDE running as `java`
+    #[serde(default)]
+    pub cmdline_contains: Vec<String>,
+    /// Logs whose lines report how long operations took
+    #[serde(default)]
+    pub logs: Vec<EditorLog>,
+}
+
+impl EditorProfile {
+    pub fn label(&self) -> &str {
+        if self.label.is_empty() {
+            &self.name
+        } else {
+            &self.label
+        }
+    }
+
+    pub fn matches(&self, process: &Process) -> bool {
+        let stem = platform::process_stem(process);
+        self.process_names.iter().any(|name| name.eq_ignore_ascii_case(&stem))
+            || (!self.cmdline_contains.is_empty()
+                && process
+                    .cmd()
+                    .iter()
+                    .any(|arg| self.cmdline_contains.iter().any(|marker| arg.contains(marker.as_str()))))
+    }
+}
+
+/// A log file and the pattern that picks latencies out of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorLog {
+    /// Path to the log; `~` is the home directory, and `*` wildcards pick
+    /// the most recently modified match
+    pub path: String,
+    /// Regular expression with a `duration` group and optionally `unit`
+    /// (`ns`, `us`, `µs`, `ms` or `s`; milliseconds when absent) and
+    /// `operation`
+    pub pattern: String,
+}
+
+impl EditorLog {
+    fn compile(&self) -> Result<Regex> {
+        let regex = Regex::new(&self.pattern).map_err(|e| anyhow!("invalid log pattern '{}': {}", self.pattern, e))?;
+        if !regex.capture_names().any(|name| name == Some("duration")) {
+            return Err(anyhow!("log pattern '{}' has no (?P<duration>...) group", self.pattern));
+        }
+        Ok(regex)
+    }
+
+    /// The file `path` currently refers to.
+    fn resolve(&self) -> Option<PathBuf> {
+        let path = match self.path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()?.join(rest),
+            None => PathBuf::from(&self.path),
+        };
+        if !self.path.contains('*') {
+            return Some(path);
+        }
+        glob::glob(&path.to_string_lossy())
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|path| Some((path.metadata().ok()?.modified().unwrap_or(SystemTime::UNIX_EPOCH), path)))
+            .max_by_key(|(modified, _)| *modified)
+            .map(|(_, path)| path)
+    }
+}
+
+/// One latency read from an editor's log.
+#[derive(Debug, Clone)]
+pub struct LogLatency {
+    pub duration: Duration,
+    pub operation: Option<String>,
+    pub line: String,
+    pub path: PathBuf,
+}
+
+/// Reads lines appended to editor logs since the previous call. Logs are
+/// picked up from their current end, so history from before the monitor
+/// started isn't imported.
+#[derive(Default)]
+pub struct LogTailer {
+    offsets: HashMap<PathBuf, u64>,
+    patterns: HashMap<String, Option<Regex>>,
+}
+
+impl LogTailer {
+    pub fn poll(&mut self, log: &EditorLog) -> Vec<LogLatency> {
+        let Some(regex) = self.patterns.entry(log.pattern.clone()).or_insert_with(|| log.compile().ok()).clone() else {
+            return Vec::new();
+        };
+        let Some(path) = log.resolve() else {
+            return Vec::new();
+        };
+        let Ok(text) = self.read_new(&path) else {
+            return Vec::new();
+        };
+
+        text.lines()
+            .filter_map(|line| {
+                let captures = regex.captures(line)?;
+                let value: f64 = captures.name("duration")?.as_str().replace(',', ".").parse().ok()?;
+                let unit = captures.name("unit").map(|unit| unit.as_str()).unwrap_or("ms");
+                let ms = match unit {
+                    "ns" => value / 1_000_000.0,
+                    "us" | "µs" => value / 1000.0,
+                    "s" => value * 1000.0,
+                    _ => value,
+                };
+                if !ms.is_finite() || ms < 0.0 {
+                    return None;
+                }
+                Some(LogLatency {
+                    duration: Duration::from_secs_f64(ms / 1000.0),
+                    operation: captures.name("operation").map(|operation| operation.as_str().trim().to_string()),
+                    line: line.to_string(),
+                    path: path.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Whole lines appended to `path` since the last read. A log that
+    /// shrank was rotated and is read again from the start.
+    fn read_new(&mut self, path: &Path) -> std::io::Result<String> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let offset = match self.offsets.get(path) {
+            Some(&offset) if offset <= len => offset,
+            Some(_) => 0,
+            None => {
+                self.offsets.insert(path.to_path_buf(), len);
+                return Ok(String::new());
+            }
+        };
+        // Skipping ahead can land mid-line: read from one byte early and
+        // start after the first line break
+        let skip_to = len.saturating_sub(MAX_READ_BYTES);
+        let (start, skipped) = if offset < skip_to { (skip_to - 1, true) } else { (offset, false) };
+
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        file.take(len - start).read_to_end(&mut bytes)?;
+        let first = if skipped {
+            bytes.iter().position(|&byte| byte == b'\n').map_or(bytes.len(), |end| end + 1)
+        } else {
+            0
+        };
+        // Leave a line still being written for the next round
+        let complete = bytes.iter().rposition(|&byte| byte == b'\n').map_or(0, |end| end + 1).max(first);
+        self.offsets.insert(path.to_path_buf(), start + complete as u64);
+        Ok(String::from_utf8_lossy(&bytes[first..complete]).into_owned())
+    }
+}
+
+/// Profiles for editors other than VS Code. Their log patterns are starting
+/// points: what editors log changes between versions, so check them against
+/// your own logs.
+pub fn builtin_profiles() -> Vec<EditorProfile> {
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    vec![
+        EditorProfile {
+            name: VSCODE.to_string(),
+            label: "VS Code".to_string(),
+            process_names: names(platform::VSCODE_NAMES),
+            cmdline_contains: Vec::new(),
+            logs: Vec::new(),
+        },
+        EditorProfile {
+            name: "neovim".to_string(),
+            label: "Neovim".to_string(),
+            process_names: names(&["nvim", "nvim-qt", "neovide"]),
+            cmdline_contains: Vec::new(),
+            // Neovim has no latency log; a plugin reports over IPC instead
+            logs: Vec::new(),
+        },
+        EditorProfile {
+            name: "jetbrains".to_string(),
+            label: "JetBrains".to_string(),
+            process_names: names(&[
+                "idea", "idea64", "pycharm", "pycharm64", "clion", "clion64", "goland", "goland64", "webstorm",
+                "webstorm64", "rider", "rider64", "rubymine", "phpstorm", "datagrip", "rustrover", "studio",
+            ]),
+            cmdline_contains: vec!["com.intellij.idea.Main".to_string()],
+            logs: vec![
+                EditorLog {
+                    path: "~/.cache/JetBrains/*/log/idea.log".to_string(),
+                    pattern: r"UI was frozen for (?P<duration>\d+)\s*(?P<unit>ms)".to_string(),
+                },
+                EditorLog {
+                    path: "~/Library/Logs/JetBrains/*/idea.log".to_string(),
+                    pattern: r"UI was frozen for (?P<duration>\d+)\s*(?P<unit>ms)".to_string(),
+                },
+            ],
+        },
+        EditorProfile {
+            name: "zed".to_string(),
+            label: "Zed".to_string(),
+            process_names: names(&["zed", "zed-editor", "zeditor", "zed-preview"]),
+            cmdline_contains: Vec::new(),
+            logs: vec![
+                EditorLog {
+                    path: "~/.local/share/zed/logs/Zed.log".to_string(),
+                    pattern: r"(?P<operation>[\w:. -]+?) took (?P<duration>[\d.]+)\s*(?P<unit>ns|µs|us|ms|s)\b"
+                        .to_string(),
+                },
+                EditorLog {
+                    path: "~/Library/Logs/Zed/Zed.log".to_string(),
+                    pattern: r"(?P<operation>[\w:. -]+?) took (?P<duration>[\d.]+)\s*(?P<unit>ns|µs|us|ms|s)\b"
+                        .to_string(),
+                },
+            ],
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const PATTERN: &str = r"op=(?P<operation>\S+) took (?P<duration>[\d.,]+)(?P<unit>ns|us|µs|ms|s)";
+
+    fn log_in(dir: &Path, name: &str) -> EditorLog {
+        EditorLog {
+            path: dir.join(name).to_string_lossy().to_string(),
+            pattern: PATTERN.to_string(),
+        }
+    }
+
+    fn append(log: &EditorLog, text: &str) {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&log.path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn logs_are_tailed_from_where_they_ended() {
+        let dir = scratch("tail");
+        let log = log_in(&dir, "editor.log");
+        append(&log, "op=history took 5ms\n");
+
+        let mut tailer = LogTailer::default();
+        assert!(tailer.poll(&log).is_empty());
+
+        append(&log, "op=open took 12.5ms\nnot a timing\nop=save took 1,5s\nop=format took 250us\nop=half took 3");
+        let latencies = tailer.poll(&log);
+        let seen: Vec<(&str, Duration)> =
+            latencies.iter().map(|latency| (latency.operation.as_deref().unwrap(), latency.duration)).collect();
+        assert_eq!(
+            seen,
+            [
+                ("open", Duration::from_micros(12_500)),
+                ("save", Duration::from_millis(1500)),
+                ("format", Duration::from_micros(250)),
+            ]
+        );
+
+        // The line being written is read once it is finished
+        append(&log, "ms\n");
+        let latencies = tailer.poll(&log);
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies[0].line, "op=half took 3ms");
+        assert!(tailer.poll(&log).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotated_logs_are_read_from_the_start() {
+        let dir = scratch("rotate");
+        let log = log_in(&dir, "editor.log");
+        append(&log, "op=old took 1ms\nop=old took 2ms\n");
+        let mut tailer = LogTailer::default();
+        tailer.poll(&log);
+
+        std::fs::write(&log.path, "op=new took 3ms\n").unwrap();
+        let latencies = tailer.poll(&log);
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies[0].operation.as_deref(), Some("new"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn large_growth_skips_ahead_to_a_line_start() {
+        let dir = scratch("skip");
+        let line = "op=abc took 5ms\n";
+        let cut = "op=cut_in_half_xyzw took 7.25ms\n";
+        assert_eq!(MAX_READ_BYTES % line.len() as u64, 0);
+        assert_eq!(cut.len(), 2 * line.len());
+        let lines = (MAX_READ_BYTES / line.len() as u64) as usize;
+
+        // The read window starts inside the cut line, whose tail is dropped
+        let mid_line = log_in(&dir, "mid.log");
+        append(&mid_line, "");
+        let mut tailer = LogTailer::default();
+        tailer.poll(&mid_line);
+        append(&mid_line, &format!("op=filler took 9ms\n{}{}", cut, line.repeat(lines - 1)));
+        let latencies = tailer.poll(&mid_line);
+        assert_eq!(latencies.len(), lines - 1);
+        assert!(latencies.iter().all(|latency| latency.operation.as_deref() == Some("abc")));
+
+        // The read window starts right at the cut line, which is kept
+        let at_line = log_in(&dir, "at.log");
+        append(&at_line, "");
+        tailer.poll(&at_line);
+        append(&at_line, &format!("op=filler took 9ms\n{}{}", cut, line.repeat(lines - 2)));
+        let latencies = tailer.poll(&at_line);
+        assert_eq!(latencies.len(), lines - 1);
+        assert_eq!(latencies[0].operation.as_deref(), Some("cut_in_half_xyzw"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn wildcard_paths_follow_the_newest_log() {
+        let dir = scratch("wildcard");
+        let older = log_in(&dir, "session-1.log");
+        append(&older, "");
+        std::thread::sleep(Duration::from_millis(20));
+        let newer = log_in(&dir, "session-2.log");
+        append(&newer, "");
+
+        let log = log_in(&dir, "session-*.log");
+        assert_eq!(log.resolve(), Some(PathBuf::from(&newer.path)));
+        assert_eq!(log_in(&dir, "none-*.log").resolve(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profiles_are_validated() {
+        let config = EditorsConfig {
+            active: vec!["no-such-editor".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let profile = EditorProfile {
+            name: "mine".to_string(),
+            label: String::new(),
+            process_names: vec!["mine".to_string()],
+            cmdline_contains: Vec::new(),
+            logs: vec![EditorLog {
+                path: "~/mine.log".to_string(),
+                pattern: r"took (\d+)ms".to_string(),
+            }],
+        };
+        let config = EditorsConfig {
+            active: vec!["mine".to_string()],
+            profiles: vec![profile],
+        };
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("no (?P<duration>...) group"), "{}", error);
+        assert_eq!(config.generic_profiles()[0].label(), "mine");
+        assert!(EditorsConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::secrets::Secrets;
+
+/// SMTP settings. Credentials are referenced by secret name rather than
+/// stored inline; see `Secrets`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub enabled: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// "starttls", "tls" (implicit TLS) or "none" (local relays only)
+    pub security: String,
+    pub from: String,
+    pub username_secret: String,
+    pub password_secret: String,
+    /// Recipients of alert rules with the `email` action
+    #[serde(default)]
+    pub alert_recipients: Vec<String>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            security: "starttls".to_string(),
+            from: "VS Code Latency Monitor <latency-monitor@localhost>".to_string(),
+            username_secret: "smtp_username".to_string(),
+            password_secret: "smtp_password".to_string(),
+            alert_recipients: Vec::new(),
+        }
+    }
+}
+
+/// A message with an HTML body and optional attachments.
+pub struct EmailMessage {
+    pub subject: String,
+    pub html_body: String,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+pub async fn send_email(
+    config: &EmailConfig,
+    secrets: &Secrets,
+    recipients: &[String],
+    message: EmailMessage,
+) -> Result<()> {
+    if !config.enabled {
+        return Err(anyhow!("Email delivery is disabled (set email.enabled = true)"));
+    }
+    if recipients.is_empty() {
+        return Err(anyhow!("No recipients configured"));
+    }
+
+    let mut builder = Message::builder()
+        .from(config.from.parse::<Mailbox>()?)
+        .subject(&message.subject);
+    for recipient in recipients {
+        builder = builder.to(recipient.parse::<Mailbox>()?);
+    }
+
+    let mut body = MultiPart::mixed().singlepart(
+        SinglePart::builder()
+            .header(ContentType::TEXT_HTML)
+            .body(message.html_body),
+    );
+    for attachment in message.attachments {
+        let content_type = ContentType::parse(&attachment.content_type)
+            .map_err(|e| anyhow!("Invalid attachment content type: {}", e))?;
+        body = body.singlepart(Attachment::new(attachment.filename).body(attachment.content, content_type));
+    }
+
+    let email = builder.multipart(body)?;
+
+    let transport = match config.security.as_str() {
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+        "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?,
+        "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+        other => return Err(anyhow!("Unknown email security mode: {}", other)),
+    };
+
+    let mut transport = transport.port(config.smtp_port);
+    if let (Some(username), Some(password)) = (
+        secrets.get(&config.username_secret),
+        secrets.get(&config.password_secret),
+    ) {
+        transport = transport.credentials(Credentials::new(username, password));
+    }
+
+    transport.build().send(email).await?;
+    info!("Sent '{}' to {} recipient(s)", message.subject, recipients.len());
+
+    Ok(())
+}
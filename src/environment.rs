@@ -0,0 +1,47 @@
+use anyhow::Result;
+use serde::Serialize;
+use sysinfo::System;
+
+use crate::config::Config;
+
+/// Cargo features this build was compiled with.
+const FEATURES: &[(&str, bool)] = &[
+    ("io-uring", cfg!(feature = "io-uring")),
+    ("cargo-latency", cfg!(feature = "cargo-latency")),
+];
+
+/// What produced a report: the monitor's version and build, the machine it
+/// ran on and the configuration in effect, so results from different
+/// machines or versions can be told apart and reproduced.
+#[derive(Debug, Clone, Serialize)]
+pub struct Environment {
+    pub version: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// e.g. `Linux 24.04 Ubuntu`, `macOS 14.5 Sonoma`
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub cpus: usize,
+    /// Cargo features compiled in
+    pub features: Vec<&'static str>,
+    /// `monitoring.enabled_components`
+    pub components: Vec<String>,
+    /// The configuration, with webhook URLs and other secrets redacted
+    pub config: serde_json::Value,
+}
+
+impl Environment {
+    pub fn collect(config: &Config) -> Result<Self> {
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            os_version: System::long_os_version(),
+            kernel_version: System::kernel_version(),
+            cpus: std::thread::available_parallelism().map(usize::from).unwrap_or(1),
+            features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect(),
+            components: config.monitoring.enabled_components.clone(),
+            config: serde_json::to_value(config.scrubbed())?,
+        })
+    }
+}
@@ -0,0 +1,667 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{bounded, Receiver, SendTimeoutError, Sender, TryRecvError, TrySendError};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::RuntimeFlavor;
+use tracing::{info, warn};
+
+use crate::file_io::{Appender, IoBackend};
+use crate::models::{EventKind, EventPriority, LatencyEvent};
+
+/// Caps memory held by events waiting to be stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryBudgetConfig {
+    /// Buffered events beyond this spill to the on-disk journal
+    pub max_buffered_mb: u64,
+    /// Journal file (defaults to `spill.jsonl` next to the database)
+    pub spill_path: Option<PathBuf>,
+    /// How often the monitor records a self-check event with its RSS and
+    /// budget usage; 0 disables
+    pub self_report_interval_secs: u64,
+}
+
+impl Default for MemoryBudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_buffered_mb: 64,
+            spill_path: None,
+            self_report_interval_secs: 60,
+        }
+    }
+}
+
+/// Degrades collection gracefully when storage can't keep up, so the
+/// monitor never becomes the thing slowing the machine down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadSheddingConfig {
+    pub enabled: bool,
+    /// Queued events (buffered plus spilled) that count as overload
+    pub queue_depth_threshold: usize,
+    /// How long overload must last before each escalation
+    pub sustain_secs: u64,
+    /// How long depth must stay under half the threshold to recover
+    pub recover_secs: u64,
+    /// Sampling intervals are multiplied by this while degraded
+    pub interval_multiplier: u32,
+    /// Kinds dropped while degraded, lowest priority first; every further
+    /// sustained period of overload sheds the next one
+    pub shed_kinds: Vec<EventKind>,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            queue_depth_threshold: 5000,
+            sustain_secs: 10,
+            recover_secs: 30,
+            interval_multiplier: 4,
+            shed_kinds: vec![EventKind::ProcessSample, EventKind::Probe],
+        }
+    }
+}
+
+/// What a full lane does with a new event, configured as
+/// `monitoring.overflow_policy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Discard the new event
+    Drop,
+    /// Discard the lane's oldest event to make room
+    #[default]
+    Oldest,
+    /// Wait for room, up to `BLOCK_TIMEOUT`, then discard the new event
+    Block,
+}
+
+/// Longest a producer waits for room under [`OverflowPolicy::Block`]. The
+/// producers are sampling tasks, so an unbounded wait would stall them.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Degraded-mode snapshot, carried in self-check events for `status` and
+/// the dashboard.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadSheddingStatus {
+    pub degraded: bool,
+    pub queue_depth: u64,
+    /// Kinds currently being dropped
+    pub shedding: Vec<EventKind>,
+    pub interval_multiplier: u32,
+    pub dropped_total: u64,
+    pub degraded_since: Option<DateTime<Utc>>,
+}
+
+/// Events read back from the journal per refill.
+const RESTORE_BATCH: usize = 500;
+
+/// Fixed per-event overhead on top of the variable-length fields.
+const EVENT_OVERHEAD_BYTES: usize = std::mem::size_of::<LatencyEvent>() + 64;
+
+/// Snapshot of queue and budget usage, for self-monitoring.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub buffered_events: usize,
+    pub lanes: LaneDepths,
+    pub buffered_bytes: usize,
+    pub budget_bytes: usize,
+    pub spilled_total: u64,
+    pub journal_pending: u64,
+    /// Events each lane holds before `overflow_policy` applies
+    #[serde(default)]
+    pub lane_capacity: usize,
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    /// Events discarded because their lane was full
+    #[serde(default)]
+    pub overflow_dropped: u64,
+}
+
+/// Buffered events per priority lane.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaneDepths {
+    pub high: usize,
+    pub normal: usize,
+    pub low: usize,
+}
+
+/// Multi-producer event channel with a memory budget. Events travel in one
+/// bounded lane per [`EventPriority`]; the consumer drains higher lanes
+/// first. Only while load is being shed are events sorted into lanes by
+/// priority: otherwise they all share the normal lane, so they are stored
+/// and streamed in the order they were recorded. When buffered events exceed the budget the oldest low-priority
+/// ones are moved to an append-only journal and fed back once the consumer
+/// catches up, so a slow database grows a file rather than the monitor's
+/// RSS. A lane that fills up anyway applies the [`OverflowPolicy`].
+#[derive(Clone)]
+pub struct EventQueue {
+    /// Indexed by `lane_index`, highest priority first
+    lanes: [Lane; 3],
+    shared: Arc<Shared>,
+}
+
+#[derive(Clone)]
+struct Lane {
+    sender: Sender<(LatencyEvent, usize)>,
+    receiver: Receiver<(LatencyEvent, usize)>,
+}
+
+impl Lane {
+    fn new(capacity: usize) -> Self {
+        let (sender, receiver) = bounded(capacity);
+        Self { sender, receiver }
+    }
+}
+
+fn lane_index(priority: EventPriority) -> usize {
+    match priority {
+        EventPriority::High => 0,
+        EventPriority::Normal => 1,
+        EventPriority::Low => 2,
+    }
+}
+
+struct Shared {
+    budget_bytes: usize,
+    used_bytes: AtomicUsize,
+    lane_capacity: usize,
+    overflow: OverflowPolicy,
+    overflow_dropped: AtomicU64,
+    spilled_total: AtomicU64,
+    journal: Mutex<SpillJournal>,
+    /// Mirror of the journal's pending count, readable while it is busy
+    journal_pending: AtomicU64,
+    /// Set while spilled events are on their way to the journal
+    spilling: AtomicBool,
+    load: LoadShedder,
+}
+
+struct LoadShedder {
+    config: LoadSheddingConfig,
+    /// How many of `shed_kinds` are dropped; 0 when not degraded
+    level: AtomicUsize,
+    dropped_total: AtomicU64,
+    window: Mutex<LoadWindow>,
+}
+
+#[derive(Default)]
+struct LoadWindow {
+    over_since: Option<Instant>,
+    under_since: Option<Instant>,
+    degraded_since: Option<DateTime<Utc>>,
+}
+
+impl LoadShedder {
+    fn sheds(&self, kind: EventKind) -> bool {
+        let level = self.level.load(Ordering::Relaxed);
+        level > 0 && self.config.shed_kinds.iter().take(level).any(|k| *k == kind)
+    }
+
+    /// Escalation stops once every shed kind is dropped; with none
+    /// configured there is still one level, which only slows sampling.
+    fn max_level(&self) -> usize {
+        self.config.shed_kinds.len().max(1)
+    }
+}
+
+impl EventQueue {
+    /// Each lane holds up to `capacity` events (`monitoring.buffer_size`).
+    pub fn new(
+        config: &MemoryBudgetConfig,
+        shedding: &LoadSheddingConfig,
+        capacity: usize,
+        overflow: OverflowPolicy,
+        spill_path: PathBuf,
+        backend: IoBackend,
+    ) -> Result<Self> {
+        let journal = SpillJournal::open(spill_path, backend)?;
+        if journal.pending > 0 {
+            info!(
+                "Replaying {} spilled events left in {}",
+                journal.pending,
+                journal.path.display()
+            );
+        }
+
+        Ok(Self {
+            lanes: [Lane::new(capacity.max(1)), Lane::new(capacity.max(1)), Lane::new(capacity.max(1))],
+            shared: Arc::new(Shared {
+                budget_bytes: (config.max_buffered_mb.max(1) * 1024 * 1024) as usize,
+                used_bytes: AtomicUsize::new(0),
+                lane_capacity: capacity.max(1),
+                overflow,
+                overflow_dropped: AtomicU64::new(0),
+                spilled_total: AtomicU64::new(0),
+                journal_pending: AtomicU64::new(journal.pending),
+                journal: Mutex::new(journal),
+                spilling: AtomicBool::new(false),
+                load: LoadShedder {
+                    config: shedding.clone(),
+                    level: AtomicUsize::new(0),
+                    dropped_total: AtomicU64::new(0),
+                    window: Mutex::new(LoadWindow::default()),
+                },
+            }),
+        })
+    }
+
+    /// Queue an event. While degraded, events of shed kinds are counted and
+    /// dropped instead.
+    pub fn send(&self, event: LatencyEvent) -> Result<()> {
+        if self.shared.load.sheds(event.kind) {
+            self.shared.load.dropped_total.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.send_exempt(event)
+    }
+
+    /// Queue an event even while its kind is being shed, for the monitor's
+    /// own health reports.
+    pub fn send_exempt(&self, event: LatencyEvent) -> Result<()> {
+        let size = estimate_size(&event);
+        let lane = self.lane_for(&event);
+        self.shared.used_bytes.fetch_add(size, Ordering::Relaxed);
+        self.push(lane, (event, size))?;
+
+        if self.shared.used_bytes.load(Ordering::Relaxed) > self.shared.budget_bytes {
+            self.spill_oldest();
+        }
+        Ok(())
+    }
+
+    /// The lane for `event`: its priority's while degraded, else the
+    /// normal lane.
+    fn lane_for(&self, event: &LatencyEvent) -> &Lane {
+        let priority = if self.shared.load.level.load(Ordering::Relaxed) > 0 {
+            event.kind.priority()
+        } else {
+            EventPriority::Normal
+        };
+        &self.lanes[lane_index(priority)]
+    }
+
+    /// Put an event on `lane`, applying the overflow policy if it is full.
+    fn push(&self, lane: &Lane, item: (LatencyEvent, usize)) -> Result<()> {
+        let mut item = match lane.sender.try_send(item) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(item)) => item,
+            Err(TrySendError::Disconnected(_)) => return Err(anyhow!("Event queue is closed")),
+        };
+
+        match self.shared.overflow {
+            OverflowPolicy::Drop => self.discard(item.1),
+            OverflowPolicy::Oldest => loop {
+                if let Ok((_, size)) = lane.receiver.try_recv() {
+                    self.discard(size);
+                }
+                match lane.sender.try_send(item) {
+                    Ok(()) => break,
+                    // Another producer took the freed slot
+                    Err(TrySendError::Full(back)) => item = back,
+                    Err(TrySendError::Disconnected(_)) => return Err(anyhow!("Event queue is closed")),
+                }
+            },
+            OverflowPolicy::Block => wait_for_room(|timeout| match lane.sender.send_timeout(item, timeout) {
+                Ok(()) => Ok(()),
+                Err(SendTimeoutError::Timeout((_, size))) => {
+                    self.discard(size);
+                    Ok(())
+                }
+                Err(SendTimeoutError::Disconnected(_)) => Err(anyhow!("Event queue is closed")),
+            })?,
+        }
+        Ok(())
+    }
+
+    /// Count an event lost to a full lane and release its budget.
+    fn discard(&self, size: usize) {
+        self.shared.used_bytes.fetch_sub(size, Ordering::Relaxed);
+        let dropped = self.shared.overflow_dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        if dropped == 1 || dropped.is_multiple_of(1000) {
+            warn!(
+                "Event queue lane full ({} events); {} events dropped so far ({:?} policy)",
+                self.shared.lane_capacity, dropped, self.shared.overflow
+            );
+        }
+    }
+
+    /// Take the next event from the highest non-empty lane. Spilled events
+    /// aren't read back here; call [`refill`](Self::refill) once every lane
+    /// is empty.
+    pub fn try_recv(&self) -> Result<LatencyEvent, TryRecvError> {
+        for lane in &self.lanes {
+            if let Ok((event, size)) = lane.receiver.try_recv() {
+                self.shared.used_bytes.fetch_sub(size, Ordering::Relaxed);
+                return Ok(event);
+            }
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let [high, normal, low] = &self.lanes;
+        let lanes = LaneDepths {
+            high: high.receiver.len(),
+            normal: normal.receiver.len(),
+            low: low.receiver.len(),
+        };
+        QueueStats {
+            buffered_events: lanes.high + lanes.normal + lanes.low,
+            lanes,
+            buffered_bytes: self.shared.used_bytes.load(Ordering::Relaxed),
+            budget_bytes: self.shared.budget_bytes,
+            spilled_total: self.shared.spilled_total.load(Ordering::Relaxed),
+            journal_pending: self.shared.journal_pending.load(Ordering::Relaxed),
+            lane_capacity: self.shared.lane_capacity,
+            overflow_policy: self.shared.overflow,
+            overflow_dropped: self.shared.overflow_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Compare queue depth with the load-shedding thresholds, escalating
+    /// after sustained overload and recovering once the queue has drained.
+    /// Call periodically; returns the new status when the level changed.
+    pub fn evaluate_load(&self) -> Option<LoadSheddingStatus> {
+        let load = &self.shared.load;
+        if !load.config.enabled {
+            return None;
+        }
+
+        let stats = self.stats();
+        let depth = stats.buffered_events as u64 + stats.journal_pending;
+        let threshold = load.config.queue_depth_threshold as u64;
+        let level = load.level.load(Ordering::Relaxed);
+        let now = Instant::now();
+        let mut window = load.window.lock().ok()?;
+
+        if depth > threshold {
+            window.under_since = None;
+            let over_since = *window.over_since.get_or_insert(now);
+            if level < load.max_level()
+                && now.duration_since(over_since) >= Duration::from_secs(load.config.sustain_secs)
+            {
+                load.level.store(level + 1, Ordering::Relaxed);
+                // The next escalation needs another full period of overload
+                window.over_since = Some(now);
+                window.degraded_since.get_or_insert_with(Utc::now);
+                return Some(self.load_status_with(depth, window.degraded_since));
+            }
+        } else if level > 0 && depth <= threshold / 2 {
+            window.over_since = None;
+            let under_since = *window.under_since.get_or_insert(now);
+            if now.duration_since(under_since) >= Duration::from_secs(load.config.recover_secs) {
+                load.level.store(0, Ordering::Relaxed);
+                *window = LoadWindow::default();
+                return Some(self.load_status_with(depth, None));
+            }
+        } else {
+            window.over_since = None;
+            window.under_since = None;
+        }
+
+        None
+    }
+
+    pub fn load_status(&self) -> LoadSheddingStatus {
+        let stats = self.stats();
+        let degraded_since = self
+            .shared
+            .load
+            .window
+            .lock()
+            .map(|w| w.degraded_since)
+            .unwrap_or_default();
+        self.load_status_with(stats.buffered_events as u64 + stats.journal_pending, degraded_since)
+    }
+
+    fn load_status_with(&self, queue_depth: u64, degraded_since: Option<DateTime<Utc>>) -> LoadSheddingStatus {
+        let load = &self.shared.load;
+        let level = load.level.load(Ordering::Relaxed);
+        LoadSheddingStatus {
+            degraded: level > 0,
+            queue_depth,
+            shedding: load.config.shed_kinds.iter().take(level).copied().collect(),
+            interval_multiplier: if level > 0 { load.config.interval_multiplier.max(1) } else { 1 },
+            dropped_total: load.dropped_total.load(Ordering::Relaxed),
+            degraded_since,
+        }
+    }
+
+    /// `interval` stretched by the configured multiplier while degraded.
+    pub fn sampling_interval(&self, interval: Duration) -> Duration {
+        let load = &self.shared.load;
+        if load.level.load(Ordering::Relaxed) > 0 {
+            interval * load.config.interval_multiplier.max(1)
+        } else {
+            interval
+        }
+    }
+
+    /// Move the oldest buffered events to the journal until usage is back
+    /// under three quarters of the budget, emptying the low-priority lane
+    /// before touching the ones above it. The file is written on a blocking
+    /// thread, so producers on the runtime never wait for the disk.
+    fn spill_oldest(&self) {
+        // Another producer is already spilling
+        if self.shared.spilling.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let target = self.shared.budget_bytes / 4 * 3;
+        let mut spilled = Vec::new();
+        for lane in self.lanes.iter().rev() {
+            while self.shared.used_bytes.load(Ordering::Relaxed) > target {
+                match lane.receiver.try_recv() {
+                    Ok((event, size)) => {
+                        self.shared.used_bytes.fetch_sub(size, Ordering::Relaxed);
+                        spilled.push(event);
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+
+        if spilled.is_empty() {
+            self.shared.spilling.store(false, Ordering::Release);
+            return;
+        }
+        let shared = self.shared.clone();
+        run_blocking(move || {
+            shared.write_spilled(&spilled);
+            shared.spilling.store(false, Ordering::Release);
+        });
+    }
+
+    /// Whether spilled events are still being written to the journal, and
+    /// so not yet visible to [`refill`](Self::refill).
+    pub fn is_spilling(&self) -> bool {
+        self.shared.spilling.load(Ordering::Acquire)
+    }
+
+    /// Load a batch of spilled events back into the channel, reading the
+    /// journal on a blocking thread. Returns how many were restored.
+    pub async fn refill(&self) -> usize {
+        if self.shared.journal_pending.load(Ordering::Relaxed) == 0 {
+            return 0;
+        }
+
+        // Lanes are empty when refilling, so a batch no larger than one
+        // lane always fits unless producers race it
+        let max = RESTORE_BATCH.min(self.shared.lane_capacity);
+        let shared = self.shared.clone();
+        let restored = tokio::task::spawn_blocking(move || shared.restore_spilled(max))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|restored| restored);
+        match restored {
+            Ok(events) => {
+                let restored = events.len();
+                for event in events {
+                    let size = estimate_size(&event);
+                    self.shared.used_bytes.fetch_add(size, Ordering::Relaxed);
+                    if self.lane_for(&event).sender.try_send((event, size)).is_err() {
+                        self.discard(size);
+                    }
+                }
+                restored
+            }
+            Err(e) => {
+                warn!("Failed to read spill journal: {}", e);
+                0
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn write_spilled(&self, spilled: &[LatencyEvent]) {
+        let mut journal = self.journal.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match journal.append(spilled) {
+            Ok(()) => {
+                self.spilled_total.fetch_add(spilled.len() as u64, Ordering::Relaxed);
+                warn!(
+                    "Event buffer over its {}MB budget; spilled {} oldest events to {}",
+                    self.budget_bytes / (1024 * 1024),
+                    spilled.len(),
+                    journal.path.display()
+                );
+            }
+            Err(e) => warn!("Failed to spill {} events to journal, dropping them: {}", spilled.len(), e),
+        }
+        self.journal_pending.store(journal.pending, Ordering::Relaxed);
+    }
+
+    fn restore_spilled(&self, max: usize) -> Result<Vec<LatencyEvent>> {
+        let mut journal = self.journal.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if journal.pending == 0 {
+            return Ok(Vec::new());
+        }
+        let restored = journal.restore(max);
+        self.journal_pending.store(journal.pending, Ordering::Relaxed);
+        restored
+    }
+}
+
+/// Call `send` with how long it may block. On a multi-threaded runtime the
+/// worker first hands its other tasks to another thread (`block_in_place`);
+/// a current-thread runtime's only thread also drains the queue, so there
+/// it doesn't wait at all.
+fn wait_for_room<T>(send: impl FnOnce(Duration) -> T) -> T {
+    match tokio::runtime::Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(|| send(BLOCK_TIMEOUT)),
+        Ok(_) => send(Duration::ZERO),
+        Err(_) => send(BLOCK_TIMEOUT),
+    }
+}
+
+/// Run `task` on tokio's blocking pool, or right here outside a runtime.
+fn run_blocking(task: impl FnOnce() + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(task);
+        }
+        Err(_) => task(),
+    }
+}
+
+/// Rough heap + inline footprint of an event while queued.
+fn estimate_size(event: &LatencyEvent) -> usize {
+    let metadata = if event.metadata.is_null() {
+        0
+    } else {
+        serde_json::to_vec(&event.metadata).map(|v| v.len()).unwrap_or_default() * 2
+    };
+    EVENT_OVERHEAD_BYTES + event.description.len() + metadata
+}
+
+/// Append-only JSONL file of spilled events, consumed from `read_offset`.
+/// Truncated once fully replayed.
+struct SpillJournal {
+    path: PathBuf,
+    backend: IoBackend,
+    /// Opened on first spill, dropped whenever the file is truncated
+    writer: Option<Appender>,
+    read_offset: u64,
+    pending: u64,
+}
+
+impl SpillJournal {
+    fn open(path: PathBuf, backend: IoBackend) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let pending = match File::open(&path) {
+            Ok(file) => BufReader::new(file).lines().count() as u64,
+            Err(_) => 0,
+        };
+
+        Ok(Self {
+            path,
+            backend,
+            writer: None,
+            read_offset: 0,
+            pending,
+        })
+    }
+
+    fn append(&mut self, events: &[LatencyEvent]) -> Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert(Appender::open(&self.path, self.backend)?),
+        };
+        let mut buffer = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut buffer, event)?;
+            buffer.push(b'\n');
+        }
+        writer.write_all(&buffer)?;
+        self.pending += events.len() as u64;
+        Ok(())
+    }
+
+    fn restore(&mut self, max: usize) -> Result<Vec<LatencyEvent>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.read_offset))?;
+        let mut reader = BufReader::new(file);
+
+        let mut events = Vec::new();
+        let mut line = String::new();
+        while events.len() < max {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                // Fewer lines than counted (e.g. a torn final write)
+                self.pending = 0;
+                break;
+            }
+            self.read_offset += read as u64;
+            self.pending = self.pending.saturating_sub(1);
+
+            match serde_json::from_str(line.trim_end()) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!("Skipping unreadable spilled event: {}", e),
+            }
+        }
+
+        if self.pending == 0 {
+            self.writer = None;
+            truncate(&self.path)?;
+            self.read_offset = 0;
+        }
+        Ok(events)
+    }
+}
+
+fn truncate(path: &Path) -> Result<()> {
+    OpenOptions::new().write(true).truncate(true).open(path)?;
+    Ok(())
+}
@@ -0,0 +1,397 @@
+use anyhow::Result;
+use arrow::array::{ArrayRef, Float64Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Serialize, Serializer};
+use serde_json::value::RawValue;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::models::{MetadataColumn, METADATA_COLUMNS};
+
+/// Fixed columns of a CSV export; flattened `metadata.*` columns follow.
+pub const EXPORT_COLUMNS: &[&str] = &[
+    "id",
+    "uid",
+    "timestamp",
+    "component_type",
+    "event_source",
+    "event_kind",
+    "duration_us",
+    "duration_ms",
+    "description",
+    "created_at",
+];
+
+/// One exported event, borrowed from the database row it was read from so
+/// serializing it allocates nothing per field.
+#[derive(Debug, Serialize)]
+pub struct ExportRow<'a> {
+    pub id: i64,
+    pub uid: Option<&'a str>,
+    pub timestamp: &'a str,
+    pub component_type: &'a str,
+    pub event_source: &'a str,
+    pub event_kind: Option<&'a str>,
+    pub duration_us: i64,
+    pub description: &'a str,
+    /// Stored JSON text, embedded as-is rather than re-parsed
+    #[serde(serialize_with = "serialize_raw_json")]
+    pub metadata: Option<&'a str>,
+    pub created_at: Option<&'a str>,
+}
+
+fn serialize_raw_json<S: Serializer>(value: &Option<&str>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value.map(serde_json::from_str::<&RawValue>) {
+        Some(Ok(raw)) => raw.serialize(serializer),
+        // Not valid JSON; keep the text rather than dropping it
+        Some(Err(_)) => value.serialize(serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Writes rows as one JSON array, element by element.
+pub struct JsonExporter<W: Write> {
+    writer: W,
+    rows: u64,
+}
+
+impl<W: Write> JsonExporter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"[")?;
+        Ok(Self { writer, rows: 0 })
+    }
+
+    pub fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        self.writer.write_all(if self.rows == 0 { b"\n" } else { b",\n" })?;
+        serde_json::to_writer(&mut self.writer, row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Close the array and return the number of rows written.
+    pub fn finish(mut self) -> Result<u64> {
+        self.writer.write_all(b"\n]\n")?;
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+/// Writes rows as CSV with one column per metadata key. The record, the
+/// scratch buffer and the per-row metadata slots are reused across rows.
+pub struct CsvExporter<W: Write> {
+    csv: csv::Writer<W>,
+    metadata_keys: Vec<String>,
+    metadata_values: Vec<Option<String>>,
+    record: csv::ByteRecord,
+    scratch: Vec<u8>,
+    rows: u64,
+}
+
+impl<W: Write> CsvExporter<W> {
+    /// Write the header; `metadata_keys` are the dotted paths collected with
+    /// `collect_metadata_keys` over every exported row.
+    pub fn new(writer: W, delimiter: u8, metadata_keys: BTreeSet<String>) -> Result<Self> {
+        let mut csv = csv::WriterBuilder::new().delimiter(delimiter).from_writer(writer);
+        let mut record = csv::ByteRecord::new();
+        for column in EXPORT_COLUMNS {
+            record.push_field(column.as_bytes());
+        }
+        for key in &metadata_keys {
+            record.push_field(format!("metadata.{}", key).as_bytes());
+        }
+        csv.write_byte_record(&record)?;
+
+        Ok(Self {
+            csv,
+            metadata_values: vec![None; metadata_keys.len()],
+            metadata_keys: metadata_keys.into_iter().collect(),
+            record,
+            scratch: Vec::new(),
+            rows: 0,
+        })
+    }
+
+    pub fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        let record = &mut self.record;
+        let scratch = &mut self.scratch;
+        record.clear();
+
+        push_display(record, scratch, row.id)?;
+        record.push_field(row.uid.unwrap_or_default().as_bytes());
+        record.push_field(row.timestamp.as_bytes());
+        record.push_field(row.component_type.as_bytes());
+        record.push_field(row.event_source.as_bytes());
+        record.push_field(row.event_kind.unwrap_or_default().as_bytes());
+        push_display(record, scratch, row.duration_us)?;
+        push_display(record, scratch, format_args!("{:.3}", row.duration_us as f64 / 1000.0))?;
+        record.push_field(row.description.as_bytes());
+        record.push_field(row.created_at.unwrap_or_default().as_bytes());
+
+        if !self.metadata_keys.is_empty() {
+            self.metadata_values.iter_mut().for_each(|v| *v = None);
+            if let Some(value) = row.metadata.and_then(|m| serde_json::from_str(m).ok()) {
+                let keys = &self.metadata_keys;
+                let values = &mut self.metadata_values;
+                flatten_metadata(&value, "", &mut |key, value| {
+                    if let Ok(index) = keys.binary_search(&key) {
+                        values[index] = Some(value);
+                    }
+                });
+            }
+            for value in &self.metadata_values {
+                record.push_field(value.as_deref().unwrap_or_default().as_bytes());
+            }
+        }
+
+        self.csv.write_byte_record(record)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Flush buffered records and return the number of rows written.
+    pub fn finish(mut self) -> Result<u64> {
+        self.csv.flush()?;
+        Ok(self.rows)
+    }
+}
+
+fn push_display(record: &mut csv::ByteRecord, scratch: &mut Vec<u8>, value: impl std::fmt::Display) -> Result<()> {
+    scratch.clear();
+    write!(scratch, "{}", value)?;
+    record.push_field(scratch);
+    Ok(())
+}
+
+/// Add the dotted metadata paths of one row to `keys`.
+pub fn collect_metadata_keys(metadata: Option<&str>, keys: &mut BTreeSet<String>) {
+    if let Some(value) = metadata.and_then(|m| serde_json::from_str(m).ok()) {
+        flatten_metadata(&value, "", &mut |key, _| {
+            keys.insert(key);
+        });
+    }
+}
+
+/// Call `emit` with a dotted path and string value for every scalar in
+/// `value`. Arrays are emitted whole as JSON.
+fn flatten_metadata(value: &serde_json::Value, prefix: &str, emit: &mut impl FnMut(String, String)) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_metadata(value, &path, emit);
+            }
+        }
+        serde_json::Value::Null => {}
+        _ if prefix.is_empty() => {}
+        serde_json::Value::String(text) => emit(prefix.to_string(), text.clone()),
+        other => emit(prefix.to_string(), other.to_string()),
+    }
+}
+
+/// Rows gathered into each Arrow record batch before it is encoded.
+const PARQUET_BATCH_ROWS: usize = 8192;
+
+/// Rows per Parquet row group, which the writer holds in memory until full.
+const PARQUET_ROW_GROUP_ROWS: usize = 128 * 1024;
+
+/// Builder for one promoted metadata column, typed by its SQL type.
+enum MetadataBuilder {
+    Integer(Int64Builder),
+    Real(Float64Builder),
+    Text(StringBuilder),
+}
+
+impl MetadataBuilder {
+    fn new(column: &MetadataColumn) -> Self {
+        match column.sql_type {
+            "INTEGER" => MetadataBuilder::Integer(Int64Builder::new()),
+            "REAL" => MetadataBuilder::Real(Float64Builder::new()),
+            _ => MetadataBuilder::Text(StringBuilder::new()),
+        }
+    }
+
+    fn data_type(column: &MetadataColumn) -> DataType {
+        match column.sql_type {
+            "INTEGER" => DataType::Int64,
+            "REAL" => DataType::Float64,
+            _ => DataType::Utf8,
+        }
+    }
+
+    /// Append the value at the column's key, converted the way SQLite's
+    /// generated column would (`true` is 1, numbers in text columns are text).
+    fn append(&mut self, value: Option<&serde_json::Value>) {
+        let value = value.filter(|v| !v.is_null());
+        match self {
+            MetadataBuilder::Integer(builder) => builder.append_option(value.and_then(|v| {
+                v.as_i64()
+                    .or_else(|| v.as_f64().map(|f| f as i64))
+                    .or_else(|| v.as_bool().map(i64::from))
+            })),
+            MetadataBuilder::Real(builder) => builder.append_option(value.and_then(serde_json::Value::as_f64)),
+            MetadataBuilder::Text(builder) => match value {
+                Some(serde_json::Value::String(text)) => builder.append_value(text),
+                Some(other) => builder.append_value(other.to_string()),
+                None => builder.append_null(),
+            },
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            MetadataBuilder::Integer(builder) => Arc::new(builder.finish()),
+            MetadataBuilder::Real(builder) => Arc::new(builder.finish()),
+            MetadataBuilder::Text(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// Writes rows as a Snappy-compressed Parquet file with typed columns:
+/// timestamps as UTC microseconds, durations as integers, and each
+/// promoted metadata column (`pid`, `process_name`, ...) with its own type.
+/// The full metadata is kept as JSON text in `metadata`.
+pub struct ParquetExporter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    id: Int64Builder,
+    uid: StringBuilder,
+    timestamp: TimestampMicrosecondBuilder,
+    component_type: StringBuilder,
+    event_source: StringBuilder,
+    event_kind: StringBuilder,
+    duration_us: Int64Builder,
+    duration_ms: Float64Builder,
+    description: StringBuilder,
+    columns: Vec<MetadataBuilder>,
+    metadata: StringBuilder,
+    created_at: TimestampMicrosecondBuilder,
+    buffered: usize,
+    rows: u64,
+}
+
+impl<W: Write + Send> ParquetExporter<W> {
+    pub fn new(writer: W) -> Result<Self> {
+        let utc = || DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        let mut fields = vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("uid", DataType::Utf8, true),
+            Field::new("timestamp", utc(), true),
+            Field::new("component_type", DataType::Utf8, false),
+            Field::new("event_source", DataType::Utf8, false),
+            Field::new("event_kind", DataType::Utf8, true),
+            Field::new("duration_us", DataType::Int64, false),
+            Field::new("duration_ms", DataType::Float64, false),
+            Field::new("description", DataType::Utf8, false),
+        ];
+        fields.extend(
+            METADATA_COLUMNS
+                .iter()
+                .map(|column| Field::new(column.name, MetadataBuilder::data_type(column), true)),
+        );
+        fields.push(Field::new("metadata", DataType::Utf8, true));
+        fields.push(Field::new("created_at", utc(), true));
+        let schema = Arc::new(Schema::new(fields));
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+            .build();
+        let timestamps = || TimestampMicrosecondBuilder::new().with_timezone("UTC");
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(writer, schema.clone(), Some(properties))?,
+            schema,
+            id: Int64Builder::new(),
+            uid: StringBuilder::new(),
+            timestamp: timestamps(),
+            component_type: StringBuilder::new(),
+            event_source: StringBuilder::new(),
+            event_kind: StringBuilder::new(),
+            duration_us: Int64Builder::new(),
+            duration_ms: Float64Builder::new(),
+            description: StringBuilder::new(),
+            columns: METADATA_COLUMNS.iter().map(MetadataBuilder::new).collect(),
+            metadata: StringBuilder::new(),
+            created_at: timestamps(),
+            buffered: 0,
+            rows: 0,
+        })
+    }
+
+    pub fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        self.id.append_value(row.id);
+        self.uid.append_option(row.uid);
+        self.timestamp.append_option(timestamp_micros(row.timestamp));
+        self.component_type.append_value(row.component_type);
+        self.event_source.append_value(row.event_source);
+        self.event_kind.append_option(row.event_kind);
+        self.duration_us.append_value(row.duration_us);
+        self.duration_ms.append_value(row.duration_us as f64 / 1000.0);
+        self.description.append_value(row.description);
+
+        let metadata: Option<serde_json::Value> = row.metadata.and_then(|m| serde_json::from_str(m).ok());
+        for (column, builder) in METADATA_COLUMNS.iter().zip(&mut self.columns) {
+            builder.append(metadata.as_ref().and_then(|m| m.get(column.key)));
+        }
+        self.metadata.append_option(row.metadata);
+        self.created_at.append_option(row.created_at.and_then(timestamp_micros));
+
+        self.buffered += 1;
+        self.rows += 1;
+        if self.buffered >= PARQUET_BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.id.finish()),
+            Arc::new(self.uid.finish()),
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.component_type.finish()),
+            Arc::new(self.event_source.finish()),
+            Arc::new(self.event_kind.finish()),
+            Arc::new(self.duration_us.finish()),
+            Arc::new(self.duration_ms.finish()),
+            Arc::new(self.description.finish()),
+        ];
+        columns.extend(self.columns.iter_mut().map(MetadataBuilder::finish));
+        columns.push(Arc::new(self.metadata.finish()));
+        columns.push(Arc::new(self.created_at.finish()));
+
+        self.writer.write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Write the last batch and the file footer; returns the number of rows.
+    pub fn finish(mut self) -> Result<u64> {
+        if self.buffered > 0 {
+            self.write_batch()?;
+        }
+        let mut writer = self.writer.into_inner()?;
+        writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+/// Microseconds since the epoch of a stored timestamp: RFC 3339, or
+/// SQLite's `CURRENT_TIMESTAMP` format (UTC) for `created_at`.
+fn timestamp_micros(text: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(text)
+        .map(|t| t.timestamp_micros())
+        .or_else(|_| {
+            chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc().timestamp_micros())
+        })
+        .ok()
+}
@@ -0,0 +1,401 @@
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use sysinfo::{Pid, Process, System};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::debug;
+
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::platform::{self, VSCodeRole};
+
+/// Attribution of extension host CPU to the extensions causing it,
+/// configured as `[monitoring.extensions]`. Every round, processes an
+/// extension started (language servers, linters, tools) are charged to the
+/// extension whose directory their command line runs from. With `inspector`
+/// on, extension hosts started with `--inspect-extensions=<port>` are also
+/// CPU-profiled through that inspector, splitting the JavaScript thread's
+/// time by the extension whose code was running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionAttributionConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Profile extension hosts that expose an inspector
+    pub inspector: bool,
+    /// Length of each inspector CPU profile; the extension host runs
+    /// slightly slower while it is profiled
+    pub profile_ms: u64,
+}
+
+impl Default for ExtensionAttributionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 60,
+            inspector: false,
+            profile_ms: 2000,
+        }
+    }
+}
+
+/// Upper bound on one inspector session beyond the profile itself.
+const INSPECTOR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sample every extension host in `system`, returning one event per
+/// extension found using CPU.
+pub async fn attribute(system: &System, config: &ExtensionAttributionConfig) -> Vec<LatencyEvent> {
+    let mut events = Vec::new();
+
+    let mut by_process: BTreeMap<(Pid, String), (f32, Vec<u32>)> = BTreeMap::new();
+    for (pid, process) in system.processes() {
+        let Some(host) = platform::extension_host_of(system, process) else {
+            continue;
+        };
+        if let Some(extension) = extension_in(process) {
+            let entry = by_process.entry((host, extension)).or_default();
+            entry.0 += process.cpu_usage();
+            entry.1.push(pid.as_u32());
+        }
+    }
+    for ((host, extension), (cpu_percent, pids)) in by_process {
+        events.push(extension_event(
+            &extension,
+            cpu_percent as f64,
+            json!({ "attribution": "process", "extension_host_pid": host.as_u32(), "pids": pids }),
+        ));
+    }
+
+    let inspected = system
+        .processes()
+        .iter()
+        .filter(|_| config.inspector)
+        .filter(|(_, process)| platform::vscode_role(process) == Some(VSCodeRole::ExtensionHost))
+        .filter_map(|(pid, process)| Some((*pid, inspector_port(process.cmd())?)));
+    for (host, port) in inspected {
+        let window = Duration::from_millis(config.profile_ms.max(100));
+        let threads_before = platform::thread_cpu_times(host.as_u32());
+        let profile = match tokio::time::timeout(window + INSPECTOR_TIMEOUT, profile_cpu(port, window)).await {
+            Ok(Ok(profile)) => profile,
+            Ok(Err(e)) => {
+                debug!("CPU profile of extension host {} on port {} failed: {}", host, port, e);
+                continue;
+            }
+            Err(_) => {
+                debug!("CPU profile of extension host {} on port {} timed out", host, port);
+                continue;
+            }
+        };
+        let threads_after = platform::thread_cpu_times(host.as_u32());
+
+        // The profile says which extension ran in each sample; the main
+        // thread's CPU time, where /proc has it, says how busy it really was
+        let thread_percent = |main: bool| {
+            let (before, after) = (threads_before.as_ref()?, threads_after.as_ref()?);
+            let used: Duration = after
+                .iter()
+                .filter(|(tid, _)| (**tid == host.as_u32()) == main)
+                .map(|(tid, cpu)| cpu.saturating_sub(before.get(tid).copied().unwrap_or_default()))
+                .sum();
+            Some(used.as_secs_f64() / profile.wall.as_secs_f64() * 100.0)
+        };
+        let main_thread_percent = thread_percent(true);
+        let other_threads_percent = thread_percent(false);
+        let busy_percent = profile.busy.as_secs_f64() / profile.wall.as_secs_f64() * 100.0;
+
+        for (extension, time) in &profile.by_extension {
+            let share = time.as_secs_f64() / profile.busy.as_secs_f64().max(f64::EPSILON);
+            events.push(extension_event(
+                extension,
+                share * main_thread_percent.unwrap_or(busy_percent),
+                json!({
+                    "attribution": "inspector",
+                    "extension_host_pid": host.as_u32(),
+                    "profile_ms": profile.wall.as_millis() as u64,
+                    "share_percent": share * 100.0,
+                    "main_thread_cpu_percent": main_thread_percent,
+                    "other_threads_cpu_percent": other_threads_percent,
+                }),
+            ));
+        }
+    }
+
+    events
+}
+
+/// A CPU share carries no latency, so the event has no duration.
+fn extension_event(extension: &str, cpu_percent: f64, mut metadata: serde_json::Value) -> LatencyEvent {
+    metadata["extension_id"] = json!(extension);
+    metadata["cpu_percent"] = json!(cpu_percent);
+    LatencyEvent::new(
+        ComponentType::VSCodeExtension,
+        EventSource::ExtensionHost,
+        Duration::ZERO,
+        format!("Extension {} - CPU: {:.1}%", extension, cpu_percent),
+    )
+    .with_kind(EventKind::Attribution)
+    .with_metadata(metadata)
+}
+
+/// The extension a process runs from, judged by its executable and
+/// arguments.
+fn extension_in(process: &Process) -> Option<String> {
+    let exe = process.exe().map(|path| path.to_string_lossy().into_owned());
+    exe.iter().chain(process.cmd()).find_map(|arg| extension_id(arg))
+}
+
+/// Id of the extension a path or `file://` URL lies in: `publisher.name`
+/// for installed extensions (`.../extensions/ms-python.python-2024.2.1/...`,
+/// version and platform suffix dropped) and `vscode.name` for built-in ones
+/// (`.../resources/app/extensions/git/...`).
+pub fn extension_id(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    path.match_indices("/extensions/").find_map(|(at, marker)| {
+        let directory = path[at + marker.len()..].split('/').next().filter(|directory| !directory.is_empty())?;
+        if path[..at].ends_with("/resources/app") {
+            return Some(format!("vscode.{}", directory.to_lowercase()));
+        }
+
+        // `publisher.name-1.2.3` or `publisher.name-1.2.3-linux-x64`
+        let version = directory
+            .match_indices('-')
+            .map(|(i, _)| i)
+            .find(|&i| directory[i + 1..].starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(directory.len());
+        let id = &directory[..version];
+        match id.split_once('.') {
+            Some((publisher, name)) if !publisher.is_empty() && !name.is_empty() => Some(id.to_lowercase()),
+            _ => None,
+        }
+    })
+}
+
+/// Inspector port of an extension host started with `--inspect-extensions`
+/// (which VS Code passes on as `--inspect=<port>`), from its arguments.
+pub fn inspector_port(args: &[String]) -> Option<u16> {
+    const OPTIONS: [&str; 5] =
+        ["--inspect-extensions=", "--inspect-brk-extensions=", "--inspect=", "--inspect-brk=", "--inspect-port="];
+    args.iter().find_map(|arg| {
+        let value = OPTIONS.iter().find_map(|option| arg.strip_prefix(option))?;
+        // `[host:]port`
+        value.rsplit(':').next()?.parse().ok().filter(|port| *port != 0)
+    })
+}
+
+/// CPU profile of one extension host, reduced to time per extension.
+struct ExtensionProfile {
+    /// How long the profiler ran
+    wall: Duration,
+    /// Sampled time the JavaScript thread wasn't idle
+    busy: Duration,
+    /// Busy time spent in each extension's code, or in code it called
+    by_extension: BTreeMap<String, Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    nodes: Vec<ProfileNode>,
+    #[serde(default)]
+    samples: Vec<u64>,
+    #[serde(default)]
+    time_deltas: Vec<i64>,
+    start_time: i64,
+    end_time: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileNode {
+    id: u64,
+    call_frame: CallFrame,
+    #[serde(default)]
+    children: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    function_name: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InspectorTarget {
+    web_socket_debugger_url: Option<String>,
+}
+
+/// Record a CPU profile of the Node process whose inspector listens on
+/// `port` for `window`, over the DevTools protocol.
+async fn profile_cpu(port: u16, window: Duration) -> Result<ExtensionProfile> {
+    let targets: Vec<InspectorTarget> = reqwest::get(format!("http://127.0.0.1:{}/json/list", port))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let url = targets
+        .into_iter()
+        .find_map(|target| target.web_socket_debugger_url)
+        .ok_or_else(|| anyhow!("inspector lists no debuggable target"))?;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+    let mut next_id = 0;
+    let mut call = |method: &'static str, params: serde_json::Value| {
+        next_id += 1;
+        (next_id, json!({ "id": next_id, "method": method, "params": params }).to_string())
+    };
+
+    let mut result = serde_json::Value::Null;
+    for (method, params, wait) in [
+        ("Profiler.enable", json!({}), Duration::ZERO),
+        ("Profiler.setSamplingInterval", json!({ "interval": 1000 }), Duration::ZERO),
+        ("Profiler.start", json!({}), window),
+        ("Profiler.stop", json!({}), Duration::ZERO),
+        ("Profiler.disable", json!({}), Duration::ZERO),
+    ] {
+        let (id, request) = call(method, params);
+        socket.send(Message::Text(request)).await?;
+        // Wait for the reply, skipping events
+        let mut reply = loop {
+            let Some(message) = socket.next().await else {
+                return Err(anyhow!("inspector closed the connection during {}", method));
+            };
+            if let Message::Text(text) = message? {
+                let reply: serde_json::Value = serde_json::from_str(&text)?;
+                if reply["id"] == json!(id) {
+                    break reply;
+                }
+            }
+        };
+        if let Some(error) = reply.get("error") {
+            return Err(anyhow!("{} failed: {}", method, error["message"].as_str().unwrap_or_default()));
+        }
+        if method == "Profiler.stop" {
+            result = reply["result"]["profile"].take();
+        }
+        tokio::time::sleep(wait).await;
+    }
+    let _ = socket.close(None).await;
+
+    Ok(summarize(serde_json::from_value(result)?))
+}
+
+/// Charge each sample to the innermost frame on its stack that lies in an
+/// extension, so time in Node built-ins counts for the extension calling
+/// them.
+fn summarize(profile: Profile) -> ExtensionProfile {
+    let mut parents = HashMap::new();
+    for node in &profile.nodes {
+        for child in &node.children {
+            parents.insert(*child, node.id);
+        }
+    }
+    let frames: HashMap<u64, &CallFrame> = profile.nodes.iter().map(|node| (node.id, &node.call_frame)).collect();
+    let mut owners: HashMap<u64, Option<String>> = HashMap::new();
+    let mut owner = |id: u64| -> Option<String> {
+        if let Some(owner) = owners.get(&id) {
+            return owner.clone();
+        }
+        let mut current = Some(id);
+        let mut found = None;
+        while let Some(node) = current {
+            if let Some(extension) = frames.get(&node).and_then(|frame| extension_id(&frame.url)) {
+                found = Some(extension);
+                break;
+            }
+            current = parents.get(&node).copied();
+        }
+        owners.insert(id, found.clone());
+        found
+    };
+
+    let mut busy = Duration::ZERO;
+    let mut by_extension: BTreeMap<String, Duration> = BTreeMap::new();
+    for (sample, delta) in profile.samples.iter().zip(&profile.time_deltas) {
+        let idle = frames.get(sample).is_some_and(|frame| frame.function_name == "(idle)");
+        if idle {
+            continue;
+        }
+        let time = Duration::from_micros((*delta).max(0) as u64);
+        busy += time;
+        if let Some(extension) = owner(*sample) {
+            *by_extension.entry(extension).or_default() += time;
+        }
+    }
+
+    ExtensionProfile {
+        wall: Duration::from_micros((profile.end_time - profile.start_time).max(1) as u64),
+        busy,
+        by_extension,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u64, function_name: &str, url: &str, children: &[u64]) -> ProfileNode {
+        ProfileNode {
+            id,
+            call_frame: CallFrame {
+                function_name: function_name.to_string(),
+                url: url.to_string(),
+            },
+            children: children.to_vec(),
+        }
+    }
+
+    #[test]
+    fn extension_id_from_installed_and_builtin_paths() {
+        assert_eq!(
+            extension_id("/home/me/.vscode/extensions/ms-python.python-2024.2.1/out/client.js").as_deref(),
+            Some("ms-python.python")
+        );
+        assert_eq!(
+            extension_id("file:///home/me/.vscode/extensions/rust-lang.rust-analyzer-0.3.1-linux-x64/server")
+                .as_deref(),
+            Some("rust-lang.rust-analyzer")
+        );
+        assert_eq!(
+            extension_id("C:\\Program Files\\VS Code\\resources\\app\\extensions\\Git\\dist\\main.js").as_deref(),
+            Some("vscode.git")
+        );
+        assert_eq!(extension_id("/home/me/project/extensions/helpers/index.js"), None);
+        assert_eq!(extension_id("/usr/lib/node_modules/typescript/lib/tsserver.js"), None);
+    }
+
+    #[test]
+    fn inspector_port_from_arguments() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(inspector_port(&args(&["--type=extensionHost", "--inspect=9333"])), Some(9333));
+        assert_eq!(inspector_port(&args(&["--inspect-extensions=127.0.0.1:9229"])), Some(9229));
+        assert_eq!(inspector_port(&args(&["--inspect-brk=0"])), None);
+        assert_eq!(inspector_port(&args(&["--inspect"])), None);
+    }
+
+    #[test]
+    fn summarize_charges_samples_to_the_innermost_extension_frame() {
+        let profile = Profile {
+            nodes: vec![
+                node(1, "(root)", "", &[2, 3, 5]),
+                node(2, "(idle)", "", &[]),
+                node(3, "activate", "/x/extensions/acme.linter-1.0.0/main.js", &[4]),
+                node(4, "readFileSync", "node:fs", &[]),
+                node(5, "tick", "/x/resources/app/out/host.js", &[]),
+            ],
+            samples: vec![2, 4, 3, 5],
+            time_deltas: vec![1000, 2000, 3000, 4000],
+            start_time: 0,
+            end_time: 20_000,
+        };
+
+        let summary = summarize(profile);
+        assert_eq!(summary.wall, Duration::from_millis(20));
+        assert_eq!(summary.busy, Duration::from_millis(9));
+        assert_eq!(summary.by_extension.len(), 1);
+        assert_eq!(summary.by_extension["acme.linter"], Duration::from_millis(5));
+    }
+}
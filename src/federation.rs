@@ -0,0 +1,134 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::sources::{DataSource, DataSources, LOCAL_SOURCE};
+
+/// Read queries answered by this instance together with every configured
+/// dashboard source, so one dashboard covers several machines without
+/// copying their events into one database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FederationConfig {
+    pub enabled: bool,
+    /// Peers slower than this are reported as errors and left out
+    pub timeout_secs: u64,
+    /// Host label for this instance's own results (defaults to the hostname)
+    pub local_host: Option<String>,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: 10,
+            local_host: None,
+        }
+    }
+}
+
+/// Items from every host that answered, each labelled with a `host` field,
+/// plus the hosts that didn't.
+#[derive(Debug, Default, Serialize)]
+pub struct Federated {
+    pub items: Vec<serde_json::Value>,
+    pub errors: Vec<HostError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostError {
+    pub host: String,
+    pub error: String,
+}
+
+impl Federated {
+    /// Order items by a string field, e.g. RFC 3339 timestamps.
+    pub fn sort_by_field(&mut self, field: &str, descending: bool) {
+        self.items.sort_by(|a, b| {
+            let ordering = a[field].as_str().cmp(&b[field].as_str());
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+}
+
+/// Run `read` against every source concurrently and merge the results. A
+/// JSON array contributes its elements; any other value is one item.
+/// `read` returning `Ok(None)` means the host has nothing to contribute
+/// (e.g. no saved query by that name) and is not an error.
+pub async fn federate<F, Fut>(config: &FederationConfig, sources: &DataSources, read: F) -> Federated
+where
+    F: Fn(Arc<DataSource>) -> Fut,
+    Fut: Future<Output = Result<Option<serde_json::Value>>> + Send + 'static,
+{
+    let timeout = Duration::from_secs(config.timeout_secs.max(1));
+    let mut tasks = JoinSet::new();
+    for (name, source) in sources.iter() {
+        // The central backend holds copies of the hosts' own events
+        if matches!(source.as_ref(), DataSource::Backend(_)) {
+            continue;
+        }
+        let host = if name == LOCAL_SOURCE {
+            local_host(config)
+        } else {
+            name.to_string()
+        };
+        let read = read(source.clone());
+        tasks.spawn(async move {
+            let result = match tokio::time::timeout(timeout, read).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("No answer within {}s", timeout.as_secs())),
+            };
+            (host, result)
+        });
+    }
+
+    let mut federated = Federated::default();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((host, result)) = joined else { continue };
+        match result {
+            Ok(Some(value)) => {
+                let items = match value {
+                    serde_json::Value::Array(items) => items,
+                    other => vec![other],
+                };
+                federated.items.extend(items.into_iter().map(|item| label(item, &host)));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                debug!("Federated read from {} failed: {}", host, e);
+                federated.errors.push(HostError {
+                    host,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    federated.errors.sort_by(|a, b| a.host.cmp(&b.host));
+    federated
+}
+
+fn local_host(config: &FederationConfig) -> String {
+    config
+        .local_host
+        .clone()
+        .or_else(sysinfo::System::host_name)
+        .unwrap_or_else(|| LOCAL_SOURCE.to_string())
+}
+
+fn label(item: serde_json::Value, host: &str) -> serde_json::Value {
+    match item {
+        serde_json::Value::Object(mut fields) => {
+            fields.insert("host".to_string(), serde_json::json!(host));
+            serde_json::Value::Object(fields)
+        }
+        other => serde_json::json!({ "host": host, "value": other }),
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// How the spill journal and restored archives are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoBackend {
+    /// Plain buffered `write(2)` calls
+    #[default]
+    Std,
+    /// io_uring submissions; needs a Linux build with the `io-uring` feature
+    /// and falls back to `std` when the ring can't be set up (e.g. blocked
+    /// by a container seccomp profile)
+    IoUring,
+}
+
+/// Append-only file handle over the selected backend.
+pub enum Appender {
+    Std(File),
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    Uring(Box<uring::UringAppender>),
+}
+
+impl Appender {
+    /// Open `path` for appending, creating it if missing.
+    pub fn open(path: &Path, backend: IoBackend) -> Result<Self> {
+        Self::with_options(path, backend, false)
+    }
+
+    /// Create or truncate `path`.
+    pub fn create(path: &Path, backend: IoBackend) -> Result<Self> {
+        Self::with_options(path, backend, true)
+    }
+
+    fn with_options(path: &Path, backend: IoBackend, truncate: bool) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true);
+        if truncate {
+            options.write(true).truncate(true);
+        } else {
+            options.append(true);
+        }
+
+        match backend {
+            IoBackend::Std => Ok(Self::Std(options.open(path)?)),
+            IoBackend::IoUring => Self::open_uring(path, options, truncate),
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn open_uring(path: &Path, mut options: OpenOptions, truncate: bool) -> Result<Self> {
+        // The ring writes at explicit offsets, which O_APPEND would override
+        let file = options.append(false).write(true).open(path)?;
+        match uring::UringAppender::new(file) {
+            Ok(appender) => Ok(Self::Uring(Box::new(appender))),
+            Err(e) => {
+                tracing::warn!("io_uring unavailable ({}), using standard file writes", e);
+                Self::with_options(path, IoBackend::Std, truncate)
+            }
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    fn open_uring(path: &Path, _options: OpenOptions, truncate: bool) -> Result<Self> {
+        tracing::warn!("Built without the io-uring feature, using standard file writes");
+        Self::with_options(path, IoBackend::Std, truncate)
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        match self {
+            Self::Std(file) => file.write_all(data)?,
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Self::Uring(appender) => appender.write_all(data)?,
+        }
+        Ok(())
+    }
+}
+
+/// Write `data` to `path`, replacing any existing file.
+pub fn write_file(path: &Path, data: &[u8], backend: IoBackend) -> Result<()> {
+    Appender::create(path, backend)?.write_all(data)
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring {
+    use anyhow::{anyhow, Result};
+    use io_uring::{opcode, types, IoUring};
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    const RING_ENTRIES: u32 = 8;
+
+    pub struct UringAppender {
+        ring: IoUring,
+        file: File,
+        offset: u64,
+    }
+
+    impl UringAppender {
+        pub fn new(file: File) -> Result<Self> {
+            let offset = file.metadata()?.len();
+            Ok(Self {
+                ring: IoUring::new(RING_ENTRIES)?,
+                file,
+                offset,
+            })
+        }
+
+        pub fn write_all(&mut self, mut data: &[u8]) -> Result<()> {
+            while !data.is_empty() {
+                let len = data.len().min(u32::MAX as usize) as u32;
+                let entry = opcode::Write::new(types::Fd(self.file.as_raw_fd()), data.as_ptr(), len)
+                    .offset(self.offset)
+                    .build();
+
+                // Safety: `data` outlives the submission since we wait for
+                // its completion before returning or touching the buffer.
+                unsafe {
+                    self.ring
+                        .submission()
+                        .push(&entry)
+                        .map_err(|_| anyhow!("io_uring submission queue is full"))?;
+                }
+                self.ring.submit_and_wait(1)?;
+
+                let written = self
+                    .ring
+                    .completion()
+                    .next()
+                    .ok_or_else(|| anyhow!("io_uring returned no completion"))?
+                    .result();
+                if written < 0 {
+                    return Err(std::io::Error::from_raw_os_error(-written).into());
+                }
+                if written == 0 {
+                    return Err(anyhow!("io_uring write made no progress"));
+                }
+
+                self.offset += written as u64;
+                data = &data[written as usize..];
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::fmt;
+
+use crate::models::{LatencyEvent, MetadataColumn, METADATA_COLUMNS};
+
+/// Event filter expressions used by the CLI and API, e.g.
+/// `component = Terminal and description ~ 'cargo'`.
+///
+/// Terms are `field op value`, joined with `and`/`or` (`and` binds tighter).
+/// Supported fields: `component`, `source`, `kind`, `description`,
+/// `duration_ms`, `duration_us`, the promoted metadata columns (`pid`,
+/// `process_name`, `cmdline`, `cpu_percent`, `memory_mb`, `memory_kb`,
+/// `tokens`, `trace_id`, `parent_span_id`, `workspace`, ...), `tag.<key>` and
+/// `metadata.<path>`.
+/// Operators: `=`, `!=`, `~` (contains), `!~`, `>`, `>=`, `<`, `<=`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+    source: String,
+    // Disjunction of conjunctions
+    groups: Vec<Vec<Condition>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Component,
+    Source,
+    Kind,
+    Description,
+    DurationMs,
+    DurationUs,
+    Column(&'static MetadataColumn),
+    Tag(String),
+    Metadata(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    NotContains,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// Value bound into a compiled filter's placeholders.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    Number(f64),
+}
+
+/// A filter compiled to a SQL boolean expression over `latency_events`.
+#[derive(Debug, Clone, Default)]
+pub struct SqlFilter {
+    pub clause: String,
+    pub binds: Vec<FilterValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(String),
+}
+
+impl EventFilter {
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(anyhow!("Empty filter expression"));
+        }
+
+        let mut groups = vec![Vec::new()];
+        let mut iter = tokens.into_iter().peekable();
+
+        loop {
+            let field = match iter.next() {
+                Some(Token::Word(word)) => parse_field(&word)?,
+                other => return Err(anyhow!("Expected field name, found {:?}", other)),
+            };
+            let op = match iter.next() {
+                Some(Token::Op(op)) => parse_op(&op)?,
+                other => return Err(anyhow!("Expected operator after field, found {:?}", other)),
+            };
+            let value = match iter.next() {
+                Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+                other => return Err(anyhow!("Expected value after operator, found {:?}", other)),
+            };
+
+            if let Some(last) = groups.last_mut() {
+                last.push(Condition { field, op, value });
+            }
+
+            match iter.next() {
+                None => break,
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("and") => {}
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("or") => groups.push(Vec::new()),
+                Some(other) => return Err(anyhow!("Expected 'and' or 'or', found {:?}", other)),
+            }
+        }
+
+        let filter = Self {
+            source: input.trim().to_string(),
+            groups,
+        };
+        // Reject values that can't compile (e.g. non-numeric durations) up front
+        filter.to_sql()?;
+        Ok(filter)
+    }
+
+    /// Events whose `field` equals `value`, for options such as
+    /// `--workspace` that narrow down by one field.
+    pub fn equals(field: &str, value: &str) -> Result<Self> {
+        let filter = Self {
+            source: format!("{} = {:?}", field, value),
+            groups: vec![vec![Condition {
+                field: parse_field(field)?,
+                op: Op::Eq,
+                value: value.to_string(),
+            }]],
+        };
+        filter.to_sql()?;
+        Ok(filter)
+    }
+
+    /// Events matching both filters.
+    pub fn and(&self, other: &EventFilter) -> EventFilter {
+        let groups = self
+            .groups
+            .iter()
+            .flat_map(|left| other.groups.iter().map(move |right| left.iter().chain(right).cloned().collect()))
+            .collect();
+        EventFilter {
+            source: format!("({}) and ({})", self.source, other.source),
+            groups,
+        }
+    }
+
+    /// Compile into a SQL expression suitable for a `WHERE` clause.
+    pub fn to_sql(&self) -> Result<SqlFilter> {
+        let mut binds = Vec::new();
+        let mut ors = Vec::new();
+
+        for group in &self.groups {
+            let mut ands = Vec::new();
+            for condition in group {
+                ands.push(condition.to_sql(&mut binds)?);
+            }
+            ors.push(format!("({})", ands.join(" AND ")));
+        }
+
+        Ok(SqlFilter {
+            clause: format!("({})", ors.join(" OR ")),
+            binds,
+        })
+    }
+
+    /// Evaluate against an in-memory event, mirroring the SQL semantics.
+    /// Tags aren't carried on events, so `tag.*` terms only satisfy `!~`.
+    pub fn matches(&self, event: &LatencyEvent) -> bool {
+        self.groups
+            .iter()
+            .any(|group| group.iter().all(|condition| condition.matches(event)))
+    }
+}
+
+impl SqlFilter {
+    /// Conjunction of two compiled filters; binds keep placeholder order.
+    pub fn and(&self, other: &SqlFilter) -> SqlFilter {
+        SqlFilter {
+            clause: format!("{} AND {}", self.clause, other.clause),
+            binds: self.binds.iter().chain(&other.binds).cloned().collect(),
+        }
+    }
+}
+
+impl fmt::Display for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Condition {
+    fn to_sql(&self, binds: &mut Vec<FilterValue>) -> Result<String> {
+        let column = match &self.field {
+            Field::Component => "component_type".to_string(),
+            Field::Source => "event_source".to_string(),
+            Field::Kind => "event_kind".to_string(),
+            Field::Description => "description".to_string(),
+            Field::DurationMs => "(duration_us / 1000.0)".to_string(),
+            Field::DurationUs => "duration_us".to_string(),
+            Field::Column(column) => column.name.to_string(),
+            Field::Metadata(path) => {
+                binds.push(FilterValue::Text(format!("$.{}", path)));
+                "json_extract(metadata, ?)".to_string()
+            }
+            Field::Tag(key) => {
+                binds.push(FilterValue::Text(key.clone()));
+                "(SELECT value FROM event_tags WHERE event_tags.event_id = latency_events.id AND event_tags.key = ?)"
+                    .to_string()
+            }
+        };
+
+        let numeric = match &self.field {
+            Field::DurationMs | Field::DurationUs => true,
+            Field::Column(column) => column.is_numeric(),
+            _ => false,
+        };
+        let sql = match self.op {
+            Op::Contains | Op::NotContains => {
+                binds.push(FilterValue::Text(format!("%{}%", self.value)));
+                let not = if self.op == Op::NotContains { "NOT " } else { "" };
+                format!("COALESCE({}, '') {}LIKE ?", column, not)
+            }
+            _ => {
+                let value = if numeric {
+                    let number: f64 = self.value.parse().map_err(|_| {
+                        anyhow!("Expected a number to compare with, got '{}'", self.value)
+                    })?;
+                    FilterValue::Number(number)
+                } else {
+                    FilterValue::Text(self.value.clone())
+                };
+                binds.push(value);
+                format!("{} {} ?", column, self.op.as_sql())
+            }
+        };
+
+        Ok(sql)
+    }
+
+    fn matches(&self, event: &LatencyEvent) -> bool {
+        let actual = match &self.field {
+            Field::Component => Some(json!(event.component_type.as_str())),
+            Field::Source => Some(json!(event.event_source.as_str())),
+            Field::Kind => Some(json!(event.kind.as_str())),
+            Field::Description => Some(json!(event.description)),
+            Field::DurationMs => Some(json!(event.duration_us() as f64 / 1000.0)),
+            Field::DurationUs => Some(json!(event.duration_us())),
+            Field::Column(column) => event.metadata.get(column.key).filter(|value| !value.is_null()).cloned(),
+            Field::Metadata(path) => path
+                .split('.')
+                .try_fold(&event.metadata, |value, key| value.get(key))
+                .filter(|value| !value.is_null())
+                .cloned(),
+            Field::Tag(_) => None,
+        };
+
+        match (self.op, actual) {
+            (Op::Contains, actual) | (Op::NotContains, actual) => {
+                let text = actual.map(|v| json_text(&v)).unwrap_or_default().to_lowercase();
+                text.contains(&self.value.to_lowercase()) == (self.op == Op::Contains)
+            }
+            (_, None) => false,
+            (op, Some(actual)) => {
+                let ordering = match (actual.as_f64(), self.value.parse::<f64>()) {
+                    (Some(actual), Ok(expected)) => actual.partial_cmp(&expected),
+                    _ => Some(json_text(&actual).as_str().cmp(self.value.as_str())),
+                };
+                let Some(ordering) = ordering else {
+                    return false;
+                };
+                match op {
+                    Op::Eq => ordering.is_eq(),
+                    Op::Ne => ordering.is_ne(),
+                    Op::Gt => ordering.is_gt(),
+                    Op::Ge => ordering.is_ge(),
+                    Op::Lt => ordering.is_lt(),
+                    Op::Le => ordering.is_le(),
+                    Op::Contains | Op::NotContains => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl Op {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Contains => "LIKE",
+            Op::NotContains => "NOT LIKE",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        }
+    }
+}
+
+fn parse_field(word: &str) -> Result<Field> {
+    if let Some(key) = word.strip_prefix("tag.") {
+        return Ok(Field::Tag(key.to_string()));
+    }
+    if let Some(path) = word.strip_prefix("metadata.") {
+        return Ok(Field::Metadata(path.to_string()));
+    }
+
+    match word.to_lowercase().as_str() {
+        "component" | "component_type" => Ok(Field::Component),
+        "source" | "event_source" => Ok(Field::Source),
+        "kind" | "event_kind" => Ok(Field::Kind),
+        "description" => Ok(Field::Description),
+        "duration_ms" | "duration" => Ok(Field::DurationMs),
+        "duration_us" => Ok(Field::DurationUs),
+        name => METADATA_COLUMNS
+            .iter()
+            .find(|column| column.name == name)
+            .map(Field::Column)
+            .ok_or_else(|| anyhow!("Unknown filter field: {}", word)),
+    }
+}
+
+fn parse_op(op: &str) -> Result<Op> {
+    match op {
+        "=" | "==" => Ok(Op::Eq),
+        "!=" => Ok(Op::Ne),
+        "~" => Ok(Op::Contains),
+        "!~" => Ok(Op::NotContains),
+        ">" => Ok(Op::Gt),
+        ">=" => Ok(Op::Ge),
+        "<" => Ok(Op::Lt),
+        "<=" => Ok(Op::Le),
+        _ => Err(anyhow!("Unknown filter operator: {}", op)),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some(ch) if ch == c => break,
+                    Some(ch) => value.push(ch),
+                    None => return Err(anyhow!("Unterminated quoted value in filter")),
+                }
+            }
+            tokens.push(Token::Quoted(value));
+        } else if "=!~<>".contains(c) {
+            let mut op = String::new();
+            while let Some(&ch) = chars.peek() {
+                if "=!~<>".contains(ch) {
+                    op.push(ch);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Op(op));
+        } else {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() || "=!~<>'\"".contains(ch) {
+                    break;
+                }
+                word.push(ch);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ComponentType, EventSource};
+    use std::time::Duration;
+
+    fn event(component: ComponentType, description: &str, duration_ms: u64) -> LatencyEvent {
+        LatencyEvent::new(
+            component,
+            EventSource::CommandExecution,
+            Duration::from_millis(duration_ms),
+            description.to_string(),
+        )
+    }
+
+    #[test]
+    fn compiles_terms_to_bound_sql() {
+        let sql = EventFilter::parse("component = Terminal and description ~ 'cargo build'")
+            .unwrap()
+            .to_sql()
+            .unwrap();
+        assert_eq!(sql.clause, "((component_type = ? AND COALESCE(description, '') LIKE ?))");
+        assert_eq!(
+            sql.binds,
+            vec![
+                FilterValue::Text("Terminal".to_string()),
+                FilterValue::Text("%cargo build%".to_string())
+            ]
+        );
+
+        let sql = EventFilter::parse("duration_ms>=250 or tag.team = \"editor\"")
+            .unwrap()
+            .to_sql()
+            .unwrap();
+        assert!(sql.clause.starts_with("(((duration_us / 1000.0) >= ?) OR ("));
+        assert_eq!(sql.binds[0], FilterValue::Number(250.0));
+        assert_eq!(sql.binds[1], FilterValue::Text("team".to_string()));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let filter = EventFilter::parse("component = Terminal and duration_ms > 100 or component = Editor").unwrap();
+        assert!(filter.matches(&event(ComponentType::Terminal, "ls", 200)));
+        assert!(!filter.matches(&event(ComponentType::Terminal, "ls", 50)));
+        assert!(filter.matches(&event(ComponentType::Editor, "save", 50)));
+    }
+
+    #[test]
+    fn matches_like_the_sql() {
+        let slow_build = event(ComponentType::Terminal, "Cargo Build", 1500);
+        assert!(EventFilter::parse("description ~ cargo").unwrap().matches(&slow_build));
+        assert!(EventFilter::parse("description !~ test").unwrap().matches(&slow_build));
+        assert!(EventFilter::parse("duration_us = 1500000").unwrap().matches(&slow_build));
+        assert!(!EventFilter::parse("duration < 1000").unwrap().matches(&slow_build));
+
+        let with_metadata = slow_build.with_metadata(serde_json::json!({ "pid": 42, "git": { "branch": "main" } }));
+        assert!(EventFilter::parse("pid = 42").unwrap().matches(&with_metadata));
+        assert!(EventFilter::parse("metadata.git.branch = main").unwrap().matches(&with_metadata));
+        assert!(!EventFilter::parse("metadata.git.branch != main").unwrap().matches(&with_metadata));
+        // Tags aren't carried on events
+        assert!(!EventFilter::parse("tag.team = editor").unwrap().matches(&with_metadata));
+        assert!(EventFilter::parse("tag.team !~ editor").unwrap().matches(&with_metadata));
+    }
+
+    #[test]
+    fn and_requires_both_filters() {
+        let either = EventFilter::parse("component = Terminal or component = Editor").unwrap();
+        let workspace = EventFilter::parse("workspace = api").unwrap();
+        let both = either.and(&workspace);
+        assert_eq!(both.to_string(), "(component = Terminal or component = Editor) and (workspace = api)");
+
+        let in_workspace = |component, workspace: &str| {
+            event(component, "open", 10).with_metadata(serde_json::json!({ "workspace": workspace }))
+        };
+        assert!(both.matches(&in_workspace(ComponentType::Terminal, "api")));
+        assert!(both.matches(&in_workspace(ComponentType::Editor, "api")));
+        assert!(!both.matches(&in_workspace(ComponentType::Editor, "web")));
+        assert!(!both.matches(&in_workspace(ComponentType::Network, "api")));
+
+        // Each side's alternatives are kept apart in the SQL too
+        let sql = both.to_sql().unwrap();
+        assert_eq!(sql.clause.matches(" OR ").count(), 1);
+        assert_eq!(sql.binds.len(), 4);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(EventFilter::parse("").is_err());
+        assert!(EventFilter::parse("colour = red").is_err());
+        assert!(EventFilter::parse("component =< Terminal").is_err());
+        assert!(EventFilter::parse("component = Terminal and").is_err());
+        assert!(EventFilter::parse("component Terminal").is_err());
+        assert!(EventFilter::parse("description ~ 'unterminated").is_err());
+        assert!(EventFilter::parse("duration_ms > fast").is_err());
+        assert!(EventFilter::parse("component = Terminal nor source = Api").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::ComponentType;
+
+/// Default bucket upper bounds in milliseconds (5ms .. 10s).
+pub const DEFAULT_BUCKETS_MS: [f64; 11] = [
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1_000.0, 2_500.0, 5_000.0, 10_000.0,
+];
+
+/// Each bucket is a column in the histogram query, so keep the count sane.
+const MAX_BUCKETS: usize = 50;
+
+/// Leading bits of a duration an [`HdrHistogram`] keeps; 7 bits bound the
+/// error of a recorded value to under 1%.
+const HDR_SIGNIFICANT_BITS: u32 = 7;
+const HDR_SUB_BUCKETS: u64 = 1 << HDR_SIGNIFICANT_BITS;
+const HDR_HALF_SUB_BUCKETS: u64 = HDR_SUB_BUCKETS / 2;
+
+/// Latency bucket boundaries, configured as `[histograms]`. The same
+/// boundaries back the Prometheus histograms and their exemplars, the
+/// `/api/histograms` table and the dashboard's heat calendar.
+///
+/// Separately, the monitor keeps an [`HdrHistogram`] per component and
+/// writes it to the `latency_histograms` table every `flush_interval_secs`,
+/// so percentiles over long ranges don't need every raw duration.
+///
+/// ```toml
+/// [histograms]
+/// buckets_ms = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000]
+/// store = true
+/// flush_interval_secs = 60
+///
+/// [histograms.components]
+/// GitHubCopilot = [100, 250, 500, 1000, 2500, 5000, 10000]
+/// VSCode = [1, 2, 5, 10, 25, 50, 100]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistogramConfig {
+    /// Upper bounds for components without their own, in milliseconds
+    pub buckets_ms: Vec<f64>,
+    /// Per-component upper bounds in milliseconds, keyed by component name
+    pub components: BTreeMap<String, Vec<f64>>,
+    /// Record stored events into per-component HDR histograms
+    pub store: bool,
+    /// How often recorded histograms are written to the database
+    pub flush_interval_secs: u64,
+}
+
+impl Default for HistogramConfig {
+    fn default() -> Self {
+        Self {
+            buckets_ms: DEFAULT_BUCKETS_MS.to_vec(),
+            components: BTreeMap::new(),
+            store: true,
+            flush_interval_secs: 60,
+        }
+    }
+}
+
+impl HistogramConfig {
+    pub fn validate(&self) -> Result<()> {
+        validate_buckets("histograms.buckets_ms", &self.buckets_ms)?;
+        for (name, buckets) in &self.components {
+            if ComponentType::parse(name).is_none() {
+                return Err(anyhow!("Unknown component '{}' in histograms.components", name));
+            }
+            validate_buckets(&format!("histograms.components.{}", name), buckets)?;
+        }
+        if self.flush_interval_secs == 0 {
+            return Err(anyhow!("histograms.flush_interval_secs must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Upper bounds in milliseconds for `component`, or the defaults when
+    /// `None` (all components together).
+    pub fn buckets_ms(&self, component: Option<ComponentType>) -> &[f64] {
+        self.components
+            .iter()
+            .find(|(name, _)| component.is_some() && ComponentType::parse(name) == component)
+            .map(|(_, buckets)| buckets.as_slice())
+            .unwrap_or(&self.buckets_ms)
+    }
+
+    /// Upper bounds in microseconds for `component`, ascending.
+    pub fn bounds_us(&self, component: ComponentType) -> Vec<u64> {
+        self.buckets_ms(Some(component))
+            .iter()
+            .map(|ms| (ms * 1000.0).round() as u64)
+            .collect()
+    }
+
+    /// Every bound any component uses, ascending and deduplicated, so one
+    /// query can count all of them.
+    pub fn all_bounds_us(&self) -> Vec<u64> {
+        let mut bounds: Vec<u64> = ComponentType::ALL.into_iter().flat_map(|c| self.bounds_us(c)).collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds
+    }
+}
+
+/// Index of the bucket `duration_us` falls into; `bounds_us.len()` is the
+/// +Inf bucket.
+pub fn bucket_index(bounds_us: &[u64], duration_us: u64) -> usize {
+    bounds_us
+        .iter()
+        .position(|bound| duration_us <= *bound)
+        .unwrap_or(bounds_us.len())
+}
+
+/// Latency distribution with buckets whose width grows with the value (as in
+/// HdrHistogram): durations below 128µs are counted exactly, larger ones by
+/// their 7 leading bits. Only non-empty buckets are kept, so a histogram
+/// stays small whatever the event rate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HdrHistogram {
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    max_us: u64,
+}
+
+impl HdrHistogram {
+    /// Rebuild a histogram from stored non-empty bucket counts.
+    pub fn from_buckets(buckets: BTreeMap<u32, u64>, max_us: u64) -> Self {
+        let count = buckets.values().sum();
+        Self { buckets, count, max_us }
+    }
+
+    pub fn buckets(&self) -> &BTreeMap<u32, u64> {
+        &self.buckets
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max_us(&self) -> u64 {
+        self.max_us
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn record(&mut self, duration_us: u64) {
+        *self.buckets.entry(hdr_index(duration_us)).or_default() += 1;
+        self.count += 1;
+        self.max_us = self.max_us.max(duration_us);
+    }
+
+    pub fn merge(&mut self, other: &HdrHistogram) {
+        for (index, count) in &other.buckets {
+            *self.buckets.entry(*index).or_default() += count;
+        }
+        self.count += other.count;
+        self.max_us = self.max_us.max(other.max_us);
+    }
+
+    /// Nearest-rank percentile (`q` in 0.0..=1.0), like
+    /// [`crate::storage::percentile`] on the raw durations but reporting the
+    /// upper end of the bucket the rank falls in.
+    pub fn percentile(&self, q: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = ((q * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return hdr_bucket_max(*index).min(self.max_us);
+            }
+        }
+        self.max_us
+    }
+}
+
+/// Bucket of `duration_us`: the value itself below `HDR_SUB_BUCKETS`, then
+/// `HDR_HALF_SUB_BUCKETS` buckets per power of two.
+fn hdr_index(duration_us: u64) -> u32 {
+    if duration_us < HDR_SUB_BUCKETS {
+        return duration_us as u32;
+    }
+    let shift = (63 - duration_us.leading_zeros()) - (HDR_SIGNIFICANT_BITS - 1);
+    let leading = duration_us >> shift;
+    (HDR_SUB_BUCKETS + (shift as u64 - 1) * HDR_HALF_SUB_BUCKETS + (leading - HDR_HALF_SUB_BUCKETS)) as u32
+}
+
+/// Largest duration counted in bucket `index`.
+fn hdr_bucket_max(index: u32) -> u64 {
+    let index = index as u64;
+    if index < HDR_SUB_BUCKETS {
+        return index;
+    }
+    let offset = index - HDR_SUB_BUCKETS;
+    let shift = offset / HDR_HALF_SUB_BUCKETS + 1;
+    let leading = offset % HDR_HALF_SUB_BUCKETS + HDR_HALF_SUB_BUCKETS;
+    (leading << shift) + ((1 << shift) - 1)
+}
+
+fn validate_buckets(key: &str, buckets: &[f64]) -> Result<()> {
+    if buckets.is_empty() || buckets.len() > MAX_BUCKETS {
+        return Err(anyhow!("{} needs between 1 and {} buckets", key, MAX_BUCKETS));
+    }
+    if buckets.iter().any(|ms| !ms.is_finite() || *ms <= 0.0) {
+        return Err(anyhow!("{} bounds must be positive numbers of milliseconds", key));
+    }
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(anyhow!("{} bounds must be strictly ascending", key));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_durations_get_exact_buckets() {
+        for duration_us in [0, 1, 64, 127] {
+            assert_eq!(hdr_index(duration_us), duration_us as u32);
+            assert_eq!(hdr_bucket_max(hdr_index(duration_us)), duration_us);
+        }
+        assert_eq!(hdr_index(128), 128);
+        assert_eq!(hdr_bucket_max(128), 129);
+    }
+
+    #[test]
+    fn every_duration_falls_within_one_percent_of_its_bucket_max() {
+        let mut duration_us = 1;
+        while duration_us < u64::MAX / 3 {
+            for value in [duration_us, duration_us * 3 / 2, duration_us * 2 - 1] {
+                let max = hdr_bucket_max(hdr_index(value));
+                assert!(max >= value, "{} counted in a bucket ending at {}", value, max);
+                assert!((max - value) as f64 <= value as f64 / 64.0, "{} vs {}", value, max);
+            }
+            duration_us *= 2;
+        }
+    }
+
+    #[test]
+    fn bucket_indexes_grow_with_the_duration() {
+        let indexes: Vec<u32> = (0..100_000).step_by(7).map(hdr_index).collect();
+        assert!(indexes.windows(2).all(|pair| pair[0] <= pair[1]));
+        // The bucket after a bucket's max starts right after it
+        for index in 0..1000 {
+            assert_eq!(hdr_index(hdr_bucket_max(index)), index);
+            assert_eq!(hdr_index(hdr_bucket_max(index) + 1), index + 1);
+        }
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut histogram = HdrHistogram::default();
+        assert_eq!(histogram.percentile(0.5), 0);
+        for duration_us in 1..=100 {
+            histogram.record(duration_us);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(0.5), 50);
+        assert_eq!(histogram.percentile(0.99), 99);
+        assert_eq!(histogram.percentile(1.0), 100);
+        assert_eq!(histogram.percentile(0.0), 1);
+
+        // Upper bucket ends are capped at the largest value seen
+        let mut slow = HdrHistogram::default();
+        slow.record(1_000_001);
+        assert_eq!(slow.percentile(0.5), 1_000_001);
+    }
+
+    #[test]
+    fn merged_and_rebuilt_histograms_agree() {
+        let mut left = HdrHistogram::default();
+        let mut right = HdrHistogram::default();
+        let mut both = HdrHistogram::default();
+        for duration_us in [5, 300, 70_000] {
+            left.record(duration_us);
+            both.record(duration_us);
+        }
+        for duration_us in [300, 2_000_000] {
+            right.record(duration_us);
+            both.record(duration_us);
+        }
+        left.merge(&right);
+        assert_eq!(left, both);
+        assert_eq!(HdrHistogram::from_buckets(both.buckets().clone(), both.max_us()), both);
+    }
+
+    #[test]
+    fn configured_buckets_must_ascend() {
+        assert!(validate_buckets("buckets_ms", &[5.0, 10.0]).is_ok());
+        assert!(validate_buckets("buckets_ms", &[]).is_err());
+        assert!(validate_buckets("buckets_ms", &[10.0, 5.0]).is_err());
+        assert!(validate_buckets("buckets_ms", &[0.0, 5.0]).is_err());
+        assert!(validate_buckets("buckets_ms", &[5.0, f64::NAN]).is_err());
+
+        let bounds = [5_000, 10_000];
+        assert_eq!(bucket_index(&bounds, 5_000), 0);
+        assert_eq!(bucket_index(&bounds, 5_001), 1);
+        assert_eq!(bucket_index(&bounds, 10_001), 2);
+    }
+}
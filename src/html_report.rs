@@ -0,0 +1,420 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::models::ComponentType;
+use crate::report::html_escape;
+use crate::units::{format_duration, format_ms};
+
+/// Points per component on the timeline and in sparklines.
+const TIMELINE_BUCKETS: usize = 60;
+
+const TIMELINE_WIDTH: f64 = 860.0;
+const TIMELINE_HEIGHT: f64 = 240.0;
+const TIMELINE_PADDING: f64 = 40.0;
+
+const SPARKLINE_WIDTH: f64 = 120.0;
+const SPARKLINE_HEIGHT: f64 = 28.0;
+
+const PALETTE: [&str; 8] = [
+    "#4f9dff", "#ff9f43", "#2ed573", "#ff4757", "#a55eea", "#1abc9c", "#f368e0", "#c8d6e5",
+];
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; margin: 2rem auto; max-width: 960px; color: #222; }
+h1 { margin-bottom: 0.2rem; }
+.meta { color: #666; margin-bottom: 2rem; }
+table { border-collapse: collapse; width: 100%; margin: 0.5rem 0 1.5rem; font-size: 0.9rem; }
+th, td { border-bottom: 1px solid #e5e5e5; padding: 0.35rem 0.5rem; text-align: left; }
+th { background: #f6f8fa; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+.legend span { display: inline-block; margin-right: 1rem; font-size: 0.85rem; }
+.legend i { display: inline-block; width: 0.8rem; height: 0.8rem; margin-right: 0.3rem; vertical-align: middle; }
+.ok { color: #2e7d32; font-weight: 600; }
+.fail { color: #c62828; font-weight: 600; }
+pre { background: #f6f8fa; padding: 0.75rem; overflow-x: auto; }
+"#;
+
+/// Average latency (ms) per time bucket for one component; `None` where the
+/// component had no events in the bucket.
+struct Series {
+    component: ComponentType,
+    points: Vec<Option<f64>>,
+}
+
+/// Render a composed report (see `report::build_report`) as a standalone HTML
+/// page. Charts are inline SVG, so the file works offline and can be mailed.
+pub fn render(report: &Value, samples: &[(DateTime<Utc>, ComponentType, u64)]) -> String {
+    let series = bucket_samples(samples);
+    let mut html = String::new();
+
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>VS Code Latency Report</title><style>{}</style></head><body>",
+        STYLE
+    );
+    let _ = write!(
+        html,
+        "<h1>VS Code Latency Report</h1><div class=\"meta\">Generated {} &middot; covering {}</div>",
+        html_escape(report["generated_at"].as_str().unwrap_or_default()),
+        html_escape(report["range"].as_str().unwrap_or_default())
+    );
+    let noise = &report["noise"];
+    if noise.is_object() {
+        let subtracted = noise["handling"] == "subtract";
+        let _ = write!(
+            html,
+            "<div class=\"meta\">Idle noise (calibrated {}): p50 {}, p95 {}{}</div>",
+            html_escape(noise["profile"]["measured_at"].as_str().unwrap_or_default()),
+            format_ms(noise["profile"]["p50_ms"].as_f64().unwrap_or_default()),
+            format_ms(noise["profile"]["p95_ms"].as_f64().unwrap_or_default()),
+            if subtracted { " &middot; idle p50 subtracted from percentiles" } else { "" }
+        );
+    }
+
+    html.push_str("<h2>Latency Timeline</h2>");
+    match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => {
+            html.push_str(&timeline_svg(&series, first.0, last.0));
+            html.push_str("<div class=\"legend\">");
+            for (index, s) in series.iter().enumerate() {
+                let _ = write!(
+                    html,
+                    "<span><i style=\"background:{}\"></i>{}</span>",
+                    PALETTE[index % PALETTE.len()],
+                    html_escape(&s.component.to_string())
+                );
+            }
+            html.push_str("</div>");
+        }
+        _ => html.push_str("<p>No latency events recorded.</p>"),
+    }
+
+    for section in report["sections"].as_array().into_iter().flatten() {
+        let _ = write!(
+            html,
+            "<h2>{}</h2>",
+            html_escape(section["title"].as_str().unwrap_or_default())
+        );
+        let data = &section["data"];
+
+        match section["type"].as_str().unwrap_or_default() {
+            "summary" => html.push_str(&summary_table(data)),
+            "percentiles" => html.push_str(&percentile_table(data, &series)),
+            "slowest-events" | "annotations" => html.push_str(&event_table(data)),
+            _ => html.push_str(&object_table(data)),
+        }
+    }
+
+    let environment = &report["environment"];
+    if environment.is_object() {
+        let features: Vec<&str> = environment["features"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let _ = write!(
+            html,
+            "<h2>Environment</h2>\
+             <div class=\"meta\">Monitor {} on {} {} ({}, kernel {}, {} CPUs) &middot; features: {}</div>",
+            html_escape(environment["version"].as_str().unwrap_or_default()),
+            html_escape(environment["os"].as_str().unwrap_or_default()),
+            html_escape(environment["arch"].as_str().unwrap_or_default()),
+            html_escape(environment["os_version"].as_str().unwrap_or("unknown version")),
+            html_escape(environment["kernel_version"].as_str().unwrap_or("unknown")),
+            environment["cpus"],
+            if features.is_empty() { "none".to_string() } else { html_escape(&features.join(", ")) }
+        );
+        let _ = write!(
+            html,
+            "<details><summary>Configuration</summary><pre>{}</pre></details>",
+            html_escape(&serde_json::to_string_pretty(&environment["config"]).unwrap_or_default())
+        );
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+fn bucket_samples(samples: &[(DateTime<Utc>, ComponentType, u64)]) -> Vec<Series> {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Vec::new();
+    };
+    let start = first.0.timestamp_millis();
+    let span = (last.0.timestamp_millis() - start).max(1) as f64;
+
+    // Keyed by display name for a stable legend order
+    let mut sums: BTreeMap<String, (ComponentType, Vec<(f64, u32)>)> = BTreeMap::new();
+    for (timestamp, component, duration_us) in samples {
+        let offset = (timestamp.timestamp_millis() - start) as f64 / span;
+        let bucket = ((offset * TIMELINE_BUCKETS as f64) as usize).min(TIMELINE_BUCKETS - 1);
+        let entry = sums
+            .entry(component.to_string())
+            .or_insert_with(|| (*component, vec![(0.0, 0); TIMELINE_BUCKETS]));
+        entry.1[bucket].0 += *duration_us as f64 / 1000.0;
+        entry.1[bucket].1 += 1;
+    }
+
+    sums.into_values()
+        .map(|(component, buckets)| Series {
+            component,
+            points: buckets
+                .into_iter()
+                .map(|(sum, count)| (count > 0).then(|| sum / count as f64))
+                .collect(),
+        })
+        .collect()
+}
+
+fn timeline_svg(series: &[Series], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let max = series
+        .iter()
+        .flat_map(|s| s.points.iter().flatten())
+        .fold(0.0_f64, |a, b| a.max(*b))
+        .max(1.0);
+    let plot_width = TIMELINE_WIDTH - 2.0 * TIMELINE_PADDING;
+    let plot_height = TIMELINE_HEIGHT - 2.0 * TIMELINE_PADDING;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" role=\"img\">",
+        w = TIMELINE_WIDTH,
+        h = TIMELINE_HEIGHT
+    );
+    let _ = write!(
+        svg,
+        "<rect x=\"{p}\" y=\"{p}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ddd\"/>",
+        plot_width,
+        plot_height,
+        p = TIMELINE_PADDING
+    );
+    let _ = write!(
+        svg,
+        "<text x=\"4\" y=\"{}\" font-size=\"11\" fill=\"#666\">{}</text>\
+         <text x=\"4\" y=\"{}\" font-size=\"11\" fill=\"#666\">0</text>\
+         <text x=\"{}\" y=\"{}\" font-size=\"11\" fill=\"#666\">{}</text>\
+         <text x=\"{}\" y=\"{}\" font-size=\"11\" fill=\"#666\" text-anchor=\"end\">{}</text>",
+        TIMELINE_PADDING + 4.0,
+        format_ms(max),
+        TIMELINE_HEIGHT - TIMELINE_PADDING,
+        TIMELINE_PADDING,
+        TIMELINE_HEIGHT - 12.0,
+        start.format("%Y-%m-%d %H:%M"),
+        TIMELINE_WIDTH - TIMELINE_PADDING,
+        TIMELINE_HEIGHT - 12.0,
+        end.format("%Y-%m-%d %H:%M")
+    );
+
+    for (index, s) in series.iter().enumerate() {
+        let color = PALETTE[index % PALETTE.len()];
+        for segment in segments(&s.points) {
+            let points = segment
+                .iter()
+                .map(|(i, value)| {
+                    let x = TIMELINE_PADDING + plot_width * (*i as f64 + 0.5) / TIMELINE_BUCKETS as f64;
+                    let y = TIMELINE_PADDING + plot_height * (1.0 - value / max);
+                    format!("{:.1},{:.1}", x, y)
+                })
+                .collect::<Vec<_>>();
+
+            if points.len() == 1 {
+                let (x, y) = points[0].split_once(',').unwrap_or_default();
+                let _ = write!(svg, "<circle cx=\"{}\" cy=\"{}\" r=\"2\" fill=\"{}\"/>", x, y, color);
+            } else {
+                let _ = write!(
+                    svg,
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"><title>{}</title></polyline>",
+                    points.join(" "),
+                    color,
+                    html_escape(&s.component.to_string())
+                );
+            }
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn sparkline_svg(points: &[Option<f64>]) -> String {
+    let max = points.iter().flatten().fold(0.0_f64, |a, b| a.max(*b)).max(1.0);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = SPARKLINE_WIDTH,
+        h = SPARKLINE_HEIGHT
+    );
+
+    for segment in segments(points) {
+        let coords = segment
+            .iter()
+            .map(|(i, value)| {
+                let x = SPARKLINE_WIDTH * (*i as f64 + 0.5) / points.len().max(1) as f64;
+                let y = 2.0 + (SPARKLINE_HEIGHT - 4.0) * (1.0 - value / max);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#4f9dff\" stroke-width=\"1.2\"/>",
+            coords
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Split a series into runs of consecutive present points.
+fn segments(points: &[Option<f64>]) -> Vec<Vec<(usize, f64)>> {
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+
+    for (index, point) in points.iter().enumerate() {
+        match point {
+            Some(value) => current.push((index, *value)),
+            None if !current.is_empty() => segments.push(std::mem::take(&mut current)),
+            None => {}
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments
+}
+
+fn summary_table(data: &Value) -> String {
+    let mut html = String::from("<table>");
+    let _ = write!(
+        html,
+        "<tr><th>Total events</th><td class=\"num\">{}</td></tr><tr><th>Last event</th><td>{}</td></tr>",
+        data["total_events"],
+        html_escape(data["last_event"].as_str().unwrap_or("-"))
+    );
+    for (kind, count) in data["events_by_kind"].as_object().into_iter().flatten() {
+        let _ = write!(
+            html,
+            "<tr><th>{} events</th><td class=\"num\">{}</td></tr>",
+            html_escape(kind),
+            count
+        );
+    }
+    for (key, value) in data["lifecycle"].as_object().into_iter().flatten() {
+        let _ = write!(
+            html,
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>",
+            html_escape(&key.replace('_', " ")),
+            html_escape(&cell(value))
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn percentile_table(data: &Value, series: &[Series]) -> String {
+    let mut html = String::from(
+        "<table><tr><th>Component</th><th>Events</th><th>Avg</th><th>p50</th><th>p95</th><th>p99</th><th>Max</th><th>Trend</th></tr>",
+    );
+
+    for row in data.as_array().into_iter().flatten() {
+        let trend = series
+            .iter()
+            .find(|s| serde_json::to_value(s.component).ok().as_ref() == Some(&row["component"]))
+            .map(|s| sparkline_svg(&s.points))
+            .unwrap_or_default();
+
+        let _ = write!(
+            html,
+            "<tr><td>{}{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+            html_escape(&cell(&row["component"])),
+            if row["within_noise"] == true { " <small>(within idle noise)</small>" } else { "" },
+            row["events"],
+            format_ms(row["avg_ms"].as_f64().unwrap_or_default()),
+            format_ms(row["p50_ms"].as_f64().unwrap_or_default()),
+            format_ms(row["p95_ms"].as_f64().unwrap_or_default()),
+            format_ms(row["p99_ms"].as_f64().unwrap_or_default()),
+            format_ms(row["max_ms"].as_f64().unwrap_or_default()),
+            trend
+        );
+    }
+
+    html.push_str("</table>");
+    html
+}
+
+fn event_table(data: &Value) -> String {
+    let events = data.as_array().map(Vec::as_slice).unwrap_or_default();
+    if events.is_empty() {
+        return "<p>None.</p>".to_string();
+    }
+
+    let mut html = String::from("<table><tr><th>Time</th><th>Component</th><th>Duration</th><th>Description</th></tr>");
+    for event in events {
+        let duration = serde_json::from_value(event["duration"].clone())
+            .map(format_duration)
+            .unwrap_or_else(|_| "-".to_string());
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+            html_escape(event["timestamp"].as_str().unwrap_or_default()),
+            html_escape(&cell(&event["component_type"])),
+            duration,
+            html_escape(event["description"].as_str().unwrap_or_default())
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Generic table for an array of flat objects; anything else is shown as JSON.
+fn object_table(data: &Value) -> String {
+    let Some(rows) = data.as_array().filter(|rows| rows.iter().all(Value::is_object)) else {
+        return format!(
+            "<pre>{}</pre>",
+            html_escape(&serde_json::to_string_pretty(data).unwrap_or_default())
+        );
+    };
+    let Some(first) = rows.first().and_then(Value::as_object) else {
+        return "<p>None.</p>".to_string();
+    };
+
+    let columns: Vec<&String> = first.keys().collect();
+    let mut html = String::from("<table><tr>");
+    for column in &columns {
+        let _ = write!(html, "<th>{}</th>", html_escape(&column.replace('_', " ")));
+    }
+    html.push_str("</tr>");
+
+    for row in rows {
+        html.push_str("<tr>");
+        for column in &columns {
+            let value = &row[column.as_str()];
+            let class = match value {
+                Value::Bool(true) => " class=\"ok\"",
+                Value::Bool(false) => " class=\"fail\"",
+                Value::Number(_) => " class=\"num\"",
+                _ => "",
+            };
+            let _ = write!(html, "<td{}>{}</td>", class, html_escape(&cell(value)));
+        }
+        html.push_str("</tr>");
+    }
+
+    html.push_str("</table>");
+    html
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => match number.as_f64() {
+            Some(f) if number.is_f64() => format!("{:.2}", f),
+            _ => number.to_string(),
+        },
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+
+/// How events get the identifier that follows them through exports, syncs
+/// and merges, configured as `storage.event_ids`. The numeric row id stays
+/// local to each database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventIds {
+    /// A ULID assigned when the event is created: unique across agents and
+    /// ordered by creation time
+    #[default]
+    Ulid,
+    /// A hash of the event's timestamp, component, duration and description,
+    /// assigned when it is stored; identical samples share one
+    Hash,
+}
+
+impl EventIds {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "ulid" => Ok(EventIds::Ulid),
+            "hash" => Ok(EventIds::Hash),
+            _ => Err(anyhow!("Unknown event id scheme '{}' (ulid, hash)", value)),
+        }
+    }
+}
+
+static SCHEME: OnceLock<EventIds> = OnceLock::new();
+
+/// Last ULID handed out, so ids created within one millisecond still sort in
+/// creation order.
+static LAST: Mutex<(u64, u128)> = Mutex::new((0, 0));
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Choose the id scheme for the rest of the process. Only the first call
+/// counts, so it must run before any event is created.
+pub fn configure(scheme: EventIds) {
+    let _ = SCHEME.set(scheme);
+}
+
+/// Id for an event being created now, or `None` when ids are content hashes
+/// assigned at insert.
+pub fn new_event_id() -> Option<String> {
+    match SCHEME.get().copied().unwrap_or_default() {
+        EventIds::Ulid => Some(ulid()),
+        EventIds::Hash => None,
+    }
+}
+
+/// A new ULID: 48 bits of Unix milliseconds then 80 random bits, in
+/// Crockford base32. Within one millisecond the random part is incremented
+/// instead of redrawn.
+pub fn ulid() -> String {
+    let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+    let random_mask = (1u128 << 80) - 1;
+    let (ms, random) = if now_ms > last.0 {
+        (now_ms, rand::thread_rng().gen::<u128>() & random_mask)
+    } else if last.1 < random_mask {
+        // Same millisecond, or the clock stepped back
+        (last.0, last.1 + 1)
+    } else {
+        (last.0 + 1, rand::thread_rng().gen::<u128>() & random_mask)
+    };
+    *last = (ms, random);
+    encode(((ms as u128) << 80) | random)
+}
+
+fn encode(value: u128) -> String {
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// The 128 bits of a ULID, for exporters wanting binary ids; `None` if
+/// `id` isn't one.
+pub fn ulid_bytes(id: &str) -> Option<[u8; 16]> {
+    if id.len() != 26 || id.as_bytes()[0] > b'7' {
+        return None;
+    }
+    let mut value = 0u128;
+    for c in id.bytes() {
+        let digit = CROCKFORD.iter().position(|&d| d == c.to_ascii_uppercase())?;
+        value = (value << 5) | digit as u128;
+    }
+    Some(value.to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulids_are_26_crockford_characters_and_sort_in_creation_order() {
+        let ids: Vec<String> = (0..1000).map(|_| ulid()).collect();
+        for id in &ids {
+            assert_eq!(id.len(), 26);
+            assert!(id.bytes().all(|c| CROCKFORD.contains(&c)), "{}", id);
+        }
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn ulids_start_with_the_creation_time() {
+        let before = chrono::Utc::now().timestamp_millis() as u128;
+        let bytes = ulid_bytes(&ulid()).unwrap();
+        let after = chrono::Utc::now().timestamp_millis() as u128;
+
+        // Ids created in a burst may run slightly ahead of the clock
+        let ms = u128::from_be_bytes(bytes) >> 80;
+        assert!(ms >= before && ms <= after + 1000, "{} not in {}..{}", ms, before, after);
+    }
+
+    #[test]
+    fn ulid_bytes_round_trip_and_ignore_case() {
+        let value = 0x0123_4567_89ab_cdef_0011_2233_4455_6677u128;
+        let id = encode(value);
+        assert_eq!(ulid_bytes(&id), Some(value.to_be_bytes()));
+        assert_eq!(ulid_bytes(&id.to_lowercase()), Some(value.to_be_bytes()));
+        assert_eq!(encode(u128::MAX), "7ZZZZZZZZZZZZZZZZZZZZZZZZZ");
+    }
+
+    #[test]
+    fn other_strings_are_not_ulids() {
+        assert_eq!(ulid_bytes(""), None);
+        assert_eq!(ulid_bytes("01ARZ3NDEKTSV4RRFFQ69G5FA"), None);
+        // Above 128 bits
+        assert_eq!(ulid_bytes("8ZZZZZZZZZZZZZZZZZZZZZZZZZ"), None);
+        // I, L, O and U aren't Crockford digits
+        assert_eq!(ulid_bytes("01ARZ3NDEKTSV4RRFFQ69G5FAU"), None);
+        assert_eq!(ulid_bytes(&"x".repeat(26)), None);
+    }
+
+    #[test]
+    fn schemes_parse_by_name() {
+        assert_eq!(EventIds::parse("ulid").unwrap(), EventIds::Ulid);
+        assert_eq!(EventIds::parse("hash").unwrap(), EventIds::Hash);
+        assert!(EventIds::parse("uuid").is_err());
+    }
+}
@@ -0,0 +1,572 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::progress::Progress;
+use crate::storage::MetricsStorage;
+
+/// Events stored per transaction.
+const IMPORT_BATCH_SIZE: usize = 1000;
+/// Bad lines reported by line number; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 10;
+/// Local times without a zone are tried in these formats before giving up.
+const NAIVE_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y/%m/%d %H:%M:%S%.f"];
+
+/// Words in a component value that give the component away, for logs that
+/// label events freely ("copilot completion", "tsc build").
+const COMPONENT_KEYWORDS: &[(&str, ComponentType)] = &[
+    ("copilot", ComponentType::GitHubCopilot),
+    ("extension", ComponentType::VSCodeExtension),
+    ("openai", ComponentType::CloudModel),
+    ("anthropic", ComponentType::CloudModel),
+    ("ollama", ComponentType::LocalModel),
+    ("llm", ComponentType::LocalModel),
+    ("model", ComponentType::LocalModel),
+    ("terminal", ComponentType::Terminal),
+    ("shell", ComponentType::Terminal),
+    ("lsp", ComponentType::LanguageServer),
+    ("language", ComponentType::LanguageServer),
+    ("analyzer", ComponentType::LanguageServer),
+    ("disk", ComponentType::FileSystem),
+    ("file", ComponentType::FileSystem),
+    ("network", ComponentType::Network),
+    ("http", ComponentType::Network),
+    ("ping", ComponentType::Network),
+    ("cargo", ComponentType::BuildTool),
+    ("build", ComponentType::BuildTool),
+    ("compile", ComponentType::BuildTool),
+    ("vscode", ComponentType::VSCode),
+    ("vs code", ComponentType::VSCode),
+];
+
+/// An event field a record's field can be mapped to.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Timestamp,
+    Duration(DurationUnit),
+    Description,
+    Component,
+    Source,
+    Kind,
+    /// `metadata.<key>`
+    Metadata(String),
+}
+
+impl Target {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "timestamp" => Ok(Target::Timestamp),
+            "duration_s" => Ok(Target::Duration(DurationUnit::Seconds)),
+            "duration_ms" => Ok(Target::Duration(DurationUnit::Milliseconds)),
+            "duration_us" => Ok(Target::Duration(DurationUnit::Microseconds)),
+            "duration_ns" => Ok(Target::Duration(DurationUnit::Nanoseconds)),
+            "description" => Ok(Target::Description),
+            "component" => Ok(Target::Component),
+            "source" => Ok(Target::Source),
+            "kind" => Ok(Target::Kind),
+            _ => match value.strip_prefix("metadata.") {
+                Some(key) if !key.is_empty() => Ok(Target::Metadata(key.to_string())),
+                _ => Err(anyhow!(
+                    "Unknown import target '{}' (timestamp, duration_s, duration_ms, duration_us, duration_ns, \
+                     description, component, source, kind, metadata.<key>)",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DurationUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl DurationUnit {
+    /// Negative, NaN and overlong values are errors for the line they're on.
+    fn to_duration(self, value: f64) -> Result<Duration> {
+        let secs = match self {
+            DurationUnit::Seconds => value,
+            DurationUnit::Milliseconds => value / 1000.0,
+            DurationUnit::Microseconds => value / 1_000_000.0,
+            DurationUnit::Nanoseconds => value / 1_000_000_000.0,
+        };
+        Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("duration {} is out of range", value))
+    }
+}
+
+/// `import generic` options, as given on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct GenericOptions {
+    /// `TARGET=SOURCE` pairs
+    pub maps: Vec<String>,
+    /// Field whose value names the component
+    pub component_from: Option<String>,
+    /// `VALUE=Component` pairs for values that don't name one themselves
+    pub component_names: Vec<String>,
+    /// Component when no field names one [default: System]
+    pub component: Option<String>,
+    /// chrono format for timestamps that aren't RFC 3339 or epoch numbers
+    pub timestamp_format: Option<String>,
+    /// Keep the fields nothing is mapped from as metadata
+    pub keep_unmapped: bool,
+}
+
+/// How `import generic` turns one JSON record into an event. Fields are
+/// named by dotted paths into the record (`timing.total`).
+#[derive(Debug, Clone)]
+pub struct GenericMapping {
+    fields: Vec<(Target, String)>,
+    component_names: Vec<(String, ComponentType)>,
+    default_component: ComponentType,
+    timestamp_format: Option<String>,
+    keep_unmapped: bool,
+}
+
+impl GenericMapping {
+    /// Unmapped, the timestamp is read from `timestamp`, the duration from
+    /// `duration_ms` and the description from `description`.
+    pub fn new(options: &GenericOptions) -> Result<Self> {
+        let mut fields = Vec::new();
+        for pair in &options.maps {
+            let (target, source) = pair
+                .split_once('=')
+                .map(|(target, source)| (target.trim(), source.trim()))
+                .filter(|(target, source)| !target.is_empty() && !source.is_empty())
+                .ok_or_else(|| anyhow!("Invalid mapping '{}', expected TARGET=SOURCE", pair))?;
+            fields.push((Target::parse(target)?, source.to_string()));
+        }
+        if let Some(field) = &options.component_from {
+            fields.push((Target::Component, field.clone()));
+        }
+
+        if !fields.iter().any(|(target, _)| *target == Target::Timestamp) {
+            fields.push((Target::Timestamp, "timestamp".to_string()));
+        }
+        if !fields.iter().any(|(target, _)| matches!(target, Target::Duration(_))) {
+            fields.push((Target::Duration(DurationUnit::Milliseconds), "duration_ms".to_string()));
+        }
+        if !fields.iter().any(|(target, _)| *target == Target::Description) {
+            fields.push((Target::Description, "description".to_string()));
+        }
+        for unique in [Target::Timestamp, Target::Description, Target::Component, Target::Source, Target::Kind] {
+            if fields.iter().filter(|(target, _)| *target == unique).count() > 1 {
+                return Err(anyhow!("{:?} is mapped more than once", unique));
+            }
+        }
+        if fields.iter().filter(|(target, _)| matches!(target, Target::Duration(_))).count() > 1 {
+            return Err(anyhow!("Only one duration_* target can be mapped"));
+        }
+
+        let component_names = options
+            .component_names
+            .iter()
+            .map(|pair| {
+                let (value, component) = pair
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Invalid component name '{}', expected VALUE=Component", pair))?;
+                Ok((value.trim().to_lowercase(), parse_component(component)?))
+            })
+            .collect::<Result<_>>()?;
+        let default_component = match &options.component {
+            Some(component) => parse_component(component)?,
+            None => ComponentType::System,
+        };
+
+        Ok(Self {
+            fields,
+            component_names,
+            default_component,
+            timestamp_format: options.timestamp_format.clone(),
+            keep_unmapped: options.keep_unmapped,
+        })
+    }
+
+    fn source_of(&self, wanted: &Target) -> Option<&str> {
+        self.fields.iter().find(|(target, _)| target == wanted).map(|(_, source)| source.as_str())
+    }
+
+    /// The event for one record; `origin` is recorded as
+    /// `metadata.imported_from`.
+    pub fn event(&self, record: &Value, origin: &str) -> Result<LatencyEvent> {
+        if !record.is_object() {
+            return Err(anyhow!("not a JSON object"));
+        }
+
+        let timestamp_field = self.source_of(&Target::Timestamp).unwrap_or("timestamp");
+        let timestamp = lookup(record, timestamp_field)
+            .ok_or_else(|| anyhow!("no '{}' field", timestamp_field))
+            .and_then(|value| self.timestamp(value))?;
+
+        let (unit, duration_field) = self
+            .fields
+            .iter()
+            .find_map(|(target, source)| match target {
+                Target::Duration(unit) => Some((*unit, source.as_str())),
+                _ => None,
+            })
+            .unwrap_or((DurationUnit::Milliseconds, "duration_ms"));
+        let amount = lookup(record, duration_field)
+            .and_then(number)
+            .ok_or_else(|| anyhow!("no numeric '{}' field", duration_field))?;
+        if !amount.is_finite() || amount < 0.0 {
+            return Err(anyhow!("invalid duration {}", amount));
+        }
+
+        let component_value = self.source_of(&Target::Component).and_then(|field| lookup(record, field));
+        let component = component_value.and_then(text).map_or(self.default_component, |value| self.component(&value));
+        let source = match self.source_of(&Target::Source).and_then(|field| lookup(record, field)).and_then(text) {
+            Some(value) => EventSource::parse(&value).ok_or_else(|| anyhow!("unknown event source '{}'", value))?,
+            None => EventSource::UserInteraction,
+        };
+        let kind = match self.source_of(&Target::Kind).and_then(|field| lookup(record, field)).and_then(text) {
+            Some(value) => EventKind::parse(&value).ok_or_else(|| anyhow!("unknown event kind '{}'", value))?,
+            None => EventKind::from_source(source),
+        };
+        let description = self
+            .source_of(&Target::Description)
+            .and_then(|field| lookup(record, field))
+            .and_then(text)
+            .or_else(|| component_value.and_then(text))
+            .unwrap_or_else(|| "Imported event".to_string());
+
+        let mut metadata = json!({ "imported_from": origin });
+        if self.keep_unmapped {
+            if let Some(object) = record.as_object() {
+                let mapped: Vec<&str> = self.fields.iter().map(|(_, source)| source.as_str()).collect();
+                for (key, value) in object.iter().filter(|(key, _)| !mapped.contains(&key.as_str())) {
+                    metadata[key] = value.clone();
+                }
+            }
+        }
+        for (target, source) in &self.fields {
+            if let (Target::Metadata(key), Some(value)) = (target, lookup(record, source)) {
+                metadata[key] = value.clone();
+            }
+        }
+
+        let mut event = LatencyEvent::builder(component, source)
+            .kind(kind)
+            .duration(unit.to_duration(amount)?)
+            .description(description)
+            .timestamp(timestamp)
+            .metadata(metadata)
+            .build();
+        // A content hash, so importing the same file again adds nothing
+        event.uid = None;
+        Ok(event)
+    }
+
+    fn timestamp(&self, value: &Value) -> Result<DateTime<Utc>> {
+        if let Some(epoch) = value.as_f64() {
+            return from_epoch(epoch);
+        }
+        let text = value.as_str().ok_or_else(|| anyhow!("timestamp {} is neither a string nor a number", value))?;
+        let text = text.trim();
+        if let Ok(epoch) = text.parse::<f64>() {
+            return from_epoch(epoch);
+        }
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+            return Ok(timestamp.with_timezone(&Utc));
+        }
+        if let Some(format) = &self.timestamp_format {
+            if let Ok(timestamp) = DateTime::parse_from_str(text, format) {
+                return Ok(timestamp.with_timezone(&Utc));
+            }
+            return local_time(text, format).ok_or_else(|| anyhow!("timestamp '{}' doesn't match '{}'", text, format));
+        }
+        NAIVE_FORMATS
+            .iter()
+            .find_map(|format| local_time(text, format))
+            .ok_or_else(|| anyhow!("can't read timestamp '{}' (try --timestamp-format)", text))
+    }
+
+    /// The component a value stands for: an explicit `--component-name`,
+    /// a component's own name, a telling word, or the default.
+    fn component(&self, value: &str) -> ComponentType {
+        let lower = value.trim().to_lowercase();
+        if let Some((_, component)) = self.component_names.iter().find(|(name, _)| *name == lower) {
+            return *component;
+        }
+        if let Some(component) = ComponentType::parse(value) {
+            return component;
+        }
+        COMPONENT_KEYWORDS
+            .iter()
+            .find(|(keyword, _)| lower.contains(keyword))
+            .map_or(self.default_component, |(_, component)| *component)
+    }
+}
+
+fn parse_component(value: &str) -> Result<ComponentType> {
+    ComponentType::parse(value.trim()).ok_or_else(|| {
+        let known: Vec<&str> = ComponentType::ALL.iter().map(|component| component.as_str()).collect();
+        anyhow!("Unknown component '{}' ({})", value.trim(), known.join(", "))
+    })
+}
+
+/// The value at a dotted path, or at a key that itself contains dots.
+fn lookup<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(value) = record.get(path) {
+        return Some(value);
+    }
+    path.split('.').try_fold(record, |value, key| value.get(key)).filter(|value| !value.is_null())
+}
+
+fn number(value: &Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Unix time in seconds, milliseconds, microseconds or nanoseconds, told
+/// apart by magnitude.
+fn from_epoch(epoch: f64) -> Result<DateTime<Utc>> {
+    let micros = match epoch.abs() {
+        e if e < 1e11 => epoch * 1e6,
+        e if e < 1e14 => epoch * 1e3,
+        e if e < 1e17 => epoch,
+        _ => epoch / 1e3,
+    };
+    Utc.timestamp_micros(micros as i64).single().ok_or_else(|| anyhow!("timestamp {} is out of range", epoch))
+}
+
+fn local_time(text: &str, format: &str) -> Option<DateTime<Utc>> {
+    let naive = NaiveDateTime::parse_from_str(text, format).ok()?;
+    Local.from_local_datetime(&naive).earliest().map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// What an import read and stored.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportStats {
+    pub lines: u64,
+    /// Records that became events
+    pub events: u64,
+    /// Events stored; fewer than `events` when some were imported before
+    pub inserted: u64,
+    pub skipped: u64,
+    /// The first few lines that couldn't be imported, and why
+    pub errors: Vec<String>,
+    pub components: BTreeMap<String, u64>,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+/// Import JSON lines from `reader` using `mapping`. Without `storage` the
+/// lines are only checked and counted. Bad lines are skipped, or end the
+/// import with `strict`. Cancelling `progress` stops after the current
+/// batch.
+pub async fn import_generic(
+    storage: Option<&MetricsStorage>,
+    reader: impl BufRead,
+    origin: &str,
+    mapping: &GenericMapping,
+    strict: bool,
+    progress: &Progress,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        progress.inc(line.len() as u64 + 1);
+        if line.trim().is_empty() {
+            continue;
+        }
+        stats.lines += 1;
+
+        let event = serde_json::from_str::<Value>(&line)
+            .map_err(|e| anyhow!("invalid JSON: {}", e))
+            .and_then(|record| mapping.event(&record, origin));
+        let event = match event {
+            Ok(event) => event,
+            Err(e) if strict => return Err(anyhow!("Line {}: {}", index + 1, e)),
+            Err(e) => {
+                stats.skipped += 1;
+                if stats.errors.len() < MAX_REPORTED_ERRORS {
+                    stats.errors.push(format!("line {}: {}", index + 1, e));
+                }
+                continue;
+            }
+        };
+
+        stats.events += 1;
+        *stats.components.entry(event.component_type.as_str().to_string()).or_default() += 1;
+        stats.first = Some(stats.first.map_or(event.timestamp, |first| first.min(event.timestamp)));
+        stats.last = Some(stats.last.map_or(event.timestamp, |last| last.max(event.timestamp)));
+        batch.push(event);
+
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            if let Some(storage) = storage {
+                stats.inserted += storage.import_events(&batch).await?;
+            }
+            batch.clear();
+            if progress.is_cancelled() {
+                return Ok(stats);
+            }
+        }
+    }
+
+    if let Some(storage) = storage {
+        stats.inserted += storage.import_events(&batch).await?;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(maps: &[&str]) -> GenericMapping {
+        GenericMapping::new(&GenericOptions {
+            maps: maps.iter().map(|map| map.to_string()).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn targets_parse_by_name() {
+        assert_eq!(Target::parse("timestamp").unwrap(), Target::Timestamp);
+        assert_eq!(Target::parse("duration_us").unwrap(), Target::Duration(DurationUnit::Microseconds));
+        assert_eq!(Target::parse("metadata.request.id").unwrap(), Target::Metadata("request.id".to_string()));
+        assert!(Target::parse("metadata.").is_err());
+        assert!(Target::parse("duration").is_err());
+    }
+
+    #[test]
+    fn durations_convert_from_each_unit() {
+        assert_eq!(DurationUnit::Seconds.to_duration(1.5).unwrap(), Duration::from_millis(1500));
+        assert_eq!(DurationUnit::Milliseconds.to_duration(2.5).unwrap(), Duration::from_micros(2500));
+        assert_eq!(DurationUnit::Microseconds.to_duration(750.0).unwrap(), Duration::from_micros(750));
+        assert_eq!(DurationUnit::Nanoseconds.to_duration(3000.0).unwrap(), Duration::from_micros(3));
+        for bad in [-1.0, f64::NAN, f64::INFINITY, 1e30] {
+            assert!(DurationUnit::Seconds.to_duration(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn epoch_units_are_told_apart_by_magnitude() {
+        let expected = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let seconds = expected.timestamp() as f64;
+        for epoch in [seconds, seconds * 1e3, seconds * 1e6, seconds * 1e9] {
+            assert_eq!(from_epoch(epoch).unwrap(), expected, "{}", epoch);
+        }
+        assert_eq!(from_epoch(seconds + 0.25).unwrap(), expected + chrono::Duration::milliseconds(250));
+    }
+
+    #[test]
+    fn conflicting_mappings_are_rejected() {
+        let invalid = |maps: &[&str]| {
+            GenericMapping::new(&GenericOptions {
+                maps: maps.iter().map(|map| map.to_string()).collect(),
+                ..Default::default()
+            })
+            .is_err()
+        };
+        assert!(invalid(&["duration_ms"]));
+        assert!(invalid(&["timestamp="]));
+        assert!(invalid(&["timestamp=ts", "timestamp=time"]));
+        assert!(invalid(&["duration_ms=total", "duration_s=elapsed"]));
+        assert!(invalid(&["elapsed=total"]));
+        assert!(!invalid(&["duration_s=elapsed", "metadata.a=x", "metadata.b=y"]));
+    }
+
+    #[test]
+    fn records_map_to_events() {
+        let mapping = mapping(&[
+            "timestamp=time",
+            "duration_us=timing.total",
+            "description=name",
+            "component=service",
+            "metadata.status=http.status",
+        ]);
+        let record = json!({
+            "time": "2026-03-01T12:00:00+02:00",
+            "timing": {"total": "2500"},
+            "name": "GET /completions",
+            "service": "copilot proxy",
+            "http": {"status": 200},
+            "ignored": true,
+        });
+        let event = mapping.event(&record, "proxy.log").unwrap();
+        assert_eq!(event.timestamp, Utc.with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap());
+        assert_eq!(event.duration, Duration::from_micros(2500));
+        assert_eq!(event.description, "GET /completions");
+        assert_eq!(event.component_type, ComponentType::GitHubCopilot);
+        assert_eq!(event.event_source, EventSource::UserInteraction);
+        assert_eq!(event.metadata, json!({"imported_from": "proxy.log", "status": 200}));
+        // Content hashed when stored, so re-imports are deduplicated
+        assert_eq!(event.uid, None);
+    }
+
+    #[test]
+    fn components_come_from_names_keywords_or_the_default() {
+        let mapping = GenericMapping::new(&GenericOptions {
+            component_from: Some("svc".to_string()),
+            component_names: vec!["indexer=FileSystem".to_string()],
+            component: Some("Network".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let component = |svc: Value| {
+            let record = json!({"timestamp": 1_772_366_400, "duration_ms": 5, "svc": svc});
+            mapping.event(&record, "test").unwrap().component_type
+        };
+        assert_eq!(component(json!("Indexer")), ComponentType::FileSystem);
+        assert_eq!(component(json!("language_server")), ComponentType::LanguageServer);
+        assert_eq!(component(json!("tsc build")), ComponentType::BuildTool);
+        assert_eq!(component(json!("billing")), ComponentType::Network);
+        assert_eq!(component(Value::Null), ComponentType::Network);
+    }
+
+    #[test]
+    fn bad_records_explain_what_is_wrong() {
+        let mapping = mapping(&["source=src"]);
+        let error = |record: Value| mapping.event(&record, "test").unwrap_err().to_string();
+        assert_eq!(error(json!([1])), "not a JSON object");
+        assert_eq!(error(json!({"duration_ms": 5})), "no 'timestamp' field");
+        assert_eq!(error(json!({"timestamp": 1, "duration_ms": "slow"})), "no numeric 'duration_ms' field");
+        assert_eq!(error(json!({"timestamp": 1, "duration_ms": -5})), "invalid duration -5");
+        assert_eq!(error(json!({"timestamp": 1, "duration_ms": 5, "src": "Mars"})), "unknown event source 'Mars'");
+        assert!(error(json!({"timestamp": "yesterday", "duration_ms": 5})).contains("--timestamp-format"));
+    }
+
+    #[tokio::test]
+    async fn dry_runs_count_lines_and_skip_bad_ones() {
+        let lines = concat!(
+            "{\"timestamp\": 1772366400, \"duration_ms\": 12, \"description\": \"a\"}\n",
+            "\n",
+            "not json\n",
+            "{\"timestamp\": 1772366460, \"duration_ms\": 30, \"component\": \"Terminal\"}\n",
+        );
+        let mapping = mapping(&["component=component"]);
+        let stats = import_generic(None, lines.as_bytes(), "test", &mapping, false, &Progress::hidden())
+            .await
+            .unwrap();
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.events, 2);
+        assert_eq!(stats.inserted, 0);
+        assert_eq!(stats.skipped, 1);
+        assert!(stats.errors[0].starts_with("line 3: invalid JSON"), "{:?}", stats.errors);
+        assert_eq!(stats.components.get("System"), Some(&1));
+        assert_eq!(stats.components.get("Terminal"), Some(&1));
+        assert_eq!(stats.last.unwrap() - stats.first.unwrap(), chrono::Duration::minutes(1));
+
+        let strict = import_generic(None, lines.as_bytes(), "test", &mapping, true, &Progress::hidden()).await;
+        assert!(strict.unwrap_err().to_string().starts_with("Line 3:"));
+    }
+}
@@ -0,0 +1,685 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::daemon;
+use crate::editors;
+use crate::otel::TraceContext;
+use crate::model_proxy::ModelRequestReport;
+use crate::models::{CommandLatency, ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::storage::MetricsStorage;
+use crate::suggestions::SuggestionOutcome;
+use crate::workspace::{Workspace, Workspaces};
+
+/// Local channel a companion VS Code extension pushes command timings into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpcConfig {
+    pub enabled: bool,
+    /// Unix socket path (defaults to `monitor.sock` next to the database).
+    /// On Windows this is the named pipe name.
+    pub socket_path: Option<PathBuf>,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket_path: None,
+        }
+    }
+}
+
+#[cfg(windows)]
+const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\vscode-latency-monitor";
+
+/// Longest line accepted from a client; protects against runaway writers.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// One newline-delimited JSON message from the extension.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum IpcMessage {
+    /// A completed command execution. Times are Unix epoch milliseconds as
+    /// produced by `performance.timeOrigin + performance.now()`.
+    Command {
+        command: String,
+        #[serde(default)]
+        start_ms: Option<f64>,
+        #[serde(default)]
+        end_ms: Option<f64>,
+        #[serde(default)]
+        duration_ms: Option<f64>,
+        /// Contributing extension id, e.g. `github.copilot`
+        #[serde(default)]
+        extension: Option<String>,
+        #[serde(default)]
+        success: Option<bool>,
+        #[serde(default)]
+        error: Option<String>,
+        /// Anything else the extension wants to attach
+        #[serde(default)]
+        metadata: serde_json::Value,
+        /// W3C `traceparent` of a backend request the command made
+        #[serde(default)]
+        traceparent: Option<String>,
+        /// Editor profile of the sender, for plugins of editors other than
+        /// VS Code, e.g. `neovim`
+        #[serde(default)]
+        editor: Option<String>,
+        /// Folder or `.code-workspace` file of the sender's window, as a
+        /// path or URI
+        #[serde(default)]
+        workspace: Option<String>,
+    },
+    /// The workspace an extension host's window has open, sent on
+    /// activation and when the folders change, so process samples of the
+    /// extension host and the language servers it starts are tagged with it
+    Workspace {
+        /// The extension host's process id (`process.pid`)
+        pid: u32,
+        /// Path or URI of the first folder or the `.code-workspace` file;
+        /// absent when the window has none open
+        #[serde(default)]
+        workspace: Option<String>,
+    },
+    /// A terminal command reported by the `shell-init` hooks, timed from
+    /// preexec to precmd. Times are Unix epoch milliseconds.
+    ShellCommand {
+        command: String,
+        cwd: String,
+        exit_code: i32,
+        start_ms: f64,
+        end_ms: f64,
+        #[serde(default)]
+        shell: Option<String>,
+    },
+    /// A language server request timed by `lsp-proxy`, from the client's
+    /// request to the server's response. `end_ms` is Unix epoch milliseconds.
+    LspRequest {
+        server: String,
+        method: String,
+        duration_ms: f64,
+        end_ms: f64,
+        #[serde(default)]
+        error: Option<String>,
+        /// The language server's process
+        #[serde(default)]
+        pid: Option<u32>,
+    },
+    /// A cloud model request timed by `model-proxy`
+    ModelRequest(ModelRequestReport),
+    /// An inline suggestion shown, accepted or rejected. Accepted and
+    /// rejected suggestions repeat the latency they were shown with, so the
+    /// acceptance report can band them.
+    Suggestion {
+        /// `shown`, `accepted` or `rejected`
+        outcome: String,
+        /// Time from the keystroke to the suggestion appearing; required,
+        /// since it is what every outcome is banded by
+        latency_ms: f64,
+        #[serde(default)]
+        end_ms: Option<f64>,
+        /// Extension that made the suggestion; Copilot when absent
+        #[serde(default)]
+        extension: Option<String>,
+        #[serde(default)]
+        language: Option<String>,
+        /// Ties the shown and accepted/rejected messages of a suggestion
+        #[serde(default)]
+        suggestion_id: Option<String>,
+        #[serde(default)]
+        workspace: Option<String>,
+    },
+    Ping,
+    /// Switch the connection to a stream of stored events, one JSON object
+    /// per line, for `watch`
+    Subscribe {
+        /// Only these components (all when empty)
+        #[serde(default)]
+        components: Vec<ComponentType>,
+        #[serde(default)]
+        min_duration_ms: Option<f64>,
+    },
+    /// Recent latency per component, answered from memory for `statusline`
+    Status {
+        /// How far back to look, at most `RECENT_WINDOW`
+        #[serde(default)]
+        window_secs: Option<u64>,
+    },
+}
+
+/// Latest latency per component over `RECENT_WINDOW`, kept in memory so a
+/// status request never waits on the database.
+#[derive(Default)]
+struct RecentLatency {
+    samples: HashMap<ComponentType, VecDeque<(DateTime<Utc>, u64)>>,
+}
+
+/// Longest window a status request can ask for.
+const RECENT_WINDOW: chrono::Duration = chrono::Duration::minutes(15);
+
+/// Samples kept per component; older ones are dropped first.
+const RECENT_SAMPLES: usize = 512;
+
+/// Default window of a status request.
+const DEFAULT_STATUS_WINDOW_SECS: u64 = 300;
+
+impl RecentLatency {
+    fn record(&mut self, event: &LatencyEvent) {
+        if !event.kind.carries_latency() || event.is_test_data() {
+            return;
+        }
+        let samples = self.samples.entry(event.component_type).or_default();
+        if samples.len() == RECENT_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((event.timestamp, event.duration_us()));
+        let cutoff = Utc::now() - RECENT_WINDOW;
+        while samples.front().is_some_and(|(at, _)| *at < cutoff) {
+            samples.pop_front();
+        }
+    }
+
+    fn status(&self, window: chrono::Duration) -> serde_json::Value {
+        let cutoff = Utc::now() - window.min(RECENT_WINDOW);
+        let mut components = Vec::new();
+        for component in ComponentType::ALL {
+            let Some(samples) = self.samples.get(&component) else {
+                continue;
+            };
+            let mut recent: Vec<u64> = samples.iter().filter(|(at, _)| *at >= cutoff).map(|(_, us)| *us).collect();
+            let Some(&(last_at, last_us)) = samples.back().filter(|_| !recent.is_empty()) else {
+                continue;
+            };
+            recent.sort_unstable();
+            let ms = |us: u64| us as f64 / 1000.0;
+            components.push(json!({
+                "component": component,
+                "events": recent.len(),
+                "p50_ms": ms(recent[recent.len() / 2]),
+                "p95_ms": ms(recent[(recent.len() * 95 / 100).min(recent.len() - 1)]),
+                "last_ms": ms(last_us),
+                "last_at": last_at,
+            }));
+        }
+        json!({ "ok": true, "components": components })
+    }
+}
+
+pub fn socket_path(config: &Config) -> PathBuf {
+    match &config.ipc.socket_path {
+        Some(path) => path.clone(),
+        #[cfg(windows)]
+        None => PathBuf::from(DEFAULT_PIPE_NAME),
+        #[cfg(not(windows))]
+        None => daemon::runtime_dir(config).join("monitor.sock"),
+    }
+}
+
+/// Connect to a running monitor's socket, as `watch` and the proxies do.
+#[cfg(unix)]
+pub async fn connect(socket: &Path) -> std::io::Result<impl AsyncRead + AsyncWrite> {
+    tokio::net::UnixStream::connect(socket).await
+}
+
+#[cfg(windows)]
+pub async fn connect(socket: &Path) -> std::io::Result<impl AsyncRead + AsyncWrite> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(socket)
+}
+
+/// Forward timings queued by `lsp-proxy` or `model-proxy` to the monitor,
+/// reconnecting after it restarts. Each message is acknowledged with a reply
+/// line, which is read so the monitor never blocks writing to us.
+pub async fn send_reports(socket: PathBuf, mut queue: mpsc::Receiver<serde_json::Value>) {
+    let mut connection = None;
+
+    while let Some(report) = queue.recv().await {
+        if connection.is_none() {
+            connection = connect(&socket).await.ok().map(BufReader::new);
+        }
+        let Some(stream) = connection.as_mut() else {
+            continue;
+        };
+
+        let mut reply = String::new();
+        let sent = async {
+            stream.get_mut().write_all(format!("{}\n", report).as_bytes()).await?;
+            stream.read_line(&mut reply).await
+        }
+        .await;
+        if !matches!(sent, Ok(read) if read > 0) {
+            connection = None;
+        }
+    }
+}
+
+/// Accept extension connections until the task is dropped. Workspaces
+/// extensions announce are recorded in `workspaces`.
+#[cfg(unix)]
+pub async fn serve(config: Config, storage: MetricsStorage, workspaces: Workspaces) -> Result<()> {
+    use tokio::net::{UnixListener, UnixStream};
+
+    let path = socket_path(&config);
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(anyhow!("IPC socket {} is already in use", path.display()));
+        }
+        // Left behind by a monitor that didn't shut down cleanly
+        std::fs::remove_file(&path)?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    info!("Listening for VS Code extension events on {}", path.display());
+    let recent = track_recent_latency(&storage);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_connection(stream, storage.clone(), recent.clone(), workspaces.clone()));
+    }
+}
+
+#[cfg(windows)]
+pub async fn serve(config: Config, storage: MetricsStorage, workspaces: Workspaces) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let name = socket_path(&config).display().to_string();
+    let mut server = ServerOptions::new().first_pipe_instance(true).create(&name)?;
+    info!("Listening for VS Code extension events on {}", name);
+    let recent = track_recent_latency(&storage);
+
+    loop {
+        server.connect().await?;
+        let connected = server;
+        server = ServerOptions::new().create(&name)?;
+        tokio::spawn(handle_connection(connected, storage.clone(), recent.clone(), workspaces.clone()));
+    }
+}
+
+/// Follow stored events into a [`RecentLatency`] for status requests.
+fn track_recent_latency(storage: &MetricsStorage) -> Arc<Mutex<RecentLatency>> {
+    let recent = Arc::new(Mutex::new(RecentLatency::default()));
+    let mut receiver = storage.subscribe();
+    let tracked = recent.clone();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => tracked.lock().unwrap_or_else(|e| e.into_inner()).record(&event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    recent
+}
+
+async fn handle_connection<S>(
+    stream: S,
+    storage: MetricsStorage,
+    recent: Arc<Mutex<RecentLatency>>,
+    workspaces: Workspaces,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        // Read at most one byte past the limit so an endless line can't grow
+        // `line` without bound.
+        let mut limited = (&mut lines).take(MAX_MESSAGE_BYTES as u64 + 1);
+        match limited.read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) if line.len() > MAX_MESSAGE_BYTES => {
+                warn!("Dropping oversized IPC message ({} bytes)", line.len());
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                debug!("IPC connection closed: {}", e);
+                break;
+            }
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Ok(IpcMessage::Subscribe { components, min_duration_ms }) = serde_json::from_str(trimmed) {
+            stream_events(&mut writer, &storage, &components, min_duration_ms).await;
+            break;
+        }
+
+        let status = match serde_json::from_str(trimmed) {
+            Ok(IpcMessage::Status { window_secs }) => Some(window_secs.unwrap_or(DEFAULT_STATUS_WINDOW_SECS)),
+            _ => None,
+        };
+        let reply = match status {
+            Some(window_secs) => recent
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .status(chrono::Duration::seconds(window_secs as i64)),
+            None => match handle_message(trimmed, &storage, &workspaces).await {
+                Ok(()) => json!({ "ok": true }),
+                Err(e) => {
+                    debug!("Rejected IPC message: {}", e);
+                    json!({ "ok": false, "error": e.to_string() })
+                }
+            },
+        };
+
+        if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Write events to a subscriber as they are stored, until it disconnects.
+/// A subscriber that falls behind gets `{"lagged": <skipped>}` in place of
+/// the events it missed.
+async fn stream_events<W>(
+    writer: &mut W,
+    storage: &MetricsStorage,
+    components: &[ComponentType],
+    min_duration_ms: Option<f64>,
+) where
+    W: AsyncWrite + Unpin,
+{
+    let mut receiver = storage.subscribe();
+    debug!("IPC client subscribed to events");
+
+    loop {
+        let line = match receiver.recv().await {
+            Ok(event) => {
+                let wanted = (components.is_empty() || components.contains(&event.component_type))
+                    && min_duration_ms.is_none_or(|min| event.duration_us() as f64 / 1000.0 >= min);
+                if !wanted {
+                    continue;
+                }
+                match serde_json::to_string(&event) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!("Failed to serialize event for IPC subscriber: {}", e);
+                        continue;
+                    }
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => json!({ "lagged": skipped }).to_string(),
+            Err(RecvError::Closed) => break,
+        };
+
+        if writer.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+            // Subscriber went away
+            break;
+        }
+    }
+}
+
+async fn handle_message(line: &str, storage: &MetricsStorage, workspaces: &Workspaces) -> Result<()> {
+    match serde_json::from_str::<IpcMessage>(line)? {
+        IpcMessage::Ping => Ok(()),
+        // Handled by `handle_connection`, which hands the connection over
+        // or answers from memory
+        IpcMessage::Subscribe { .. } | IpcMessage::Status { .. } => Ok(()),
+        IpcMessage::Workspace { pid, workspace } => {
+            let workspace = workspace.as_deref().and_then(Workspace::parse);
+            debug!("Extension host {} has workspace {:?} open", pid, workspace);
+            workspaces.announce(pid, workspace);
+            Ok(())
+        }
+        IpcMessage::Command {
+            command,
+            start_ms,
+            end_ms,
+            duration_ms,
+            extension,
+            success,
+            error,
+            metadata,
+            traceparent,
+            editor,
+            workspace,
+        } => {
+            let duration_ms = match (duration_ms, start_ms, end_ms) {
+                (Some(duration), _, _) => duration,
+                (None, Some(start), Some(end)) => end - start,
+                _ => return Err(anyhow!("command event needs duration_ms or start_ms/end_ms")),
+            };
+            if !duration_ms.is_finite() || duration_ms < 0.0 {
+                return Err(anyhow!("invalid duration {}", duration_ms));
+            }
+
+            let editor = editor.filter(|editor| editor != editors::VSCODE);
+            let component = if editor.is_some() {
+                ComponentType::Editor
+            } else if command.starts_with("github.copilot") {
+                ComponentType::GitHubCopilot
+            } else if extension.as_deref().is_some_and(|e| !e.starts_with("vscode.")) {
+                ComponentType::VSCodeExtension
+            } else {
+                ComponentType::VSCode
+            };
+
+            let mut event = LatencyEvent::new(
+                component,
+                EventSource::CommandExecution,
+                Duration::from_secs_f64(duration_ms / 1000.0),
+                format!("Command {}", command),
+            )
+            .with_kind(EventKind::CommandRun)
+            .with_metadata(json!({
+                "command": command,
+                "extension": extension,
+                "start_ms": start_ms,
+                "end_ms": end_ms,
+                "success": success.unwrap_or(error.is_none()),
+                "error": error,
+                "extra": metadata,
+                "editor": editor,
+                "ipc": true,
+            }));
+            if let Some(end) = end_ms.and_then(epoch_ms) {
+                event.timestamp = end;
+            }
+            if let Some(workspace) = workspace.as_deref().and_then(Workspace::parse) {
+                workspace.apply(&mut event.metadata);
+            }
+            if let Some(value) = traceparent {
+                match TraceContext::parse(&value) {
+                    Some(context) => context.apply(&mut event)?,
+                    // The event is still worth keeping without its trace
+                    None => debug!("Ignoring malformed traceparent '{}'", value),
+                }
+            }
+
+            storage.store_event(&event).await
+        }
+        IpcMessage::ShellCommand {
+            command,
+            cwd,
+            exit_code,
+            start_ms,
+            end_ms,
+            shell,
+        } => {
+            let (Some(start), Some(end)) = (epoch_ms(start_ms), epoch_ms(end_ms)) else {
+                return Err(anyhow!("invalid start_ms/end_ms"));
+            };
+            let duration_ms = end_ms - start_ms;
+            if !duration_ms.is_finite() || duration_ms < 0.0 {
+                return Err(anyhow!("invalid duration {}", duration_ms));
+            }
+
+            let latency = CommandLatency::new(command.clone(), cwd, exit_code, start, end, duration_ms.round() as u64);
+            let mut metadata = serde_json::to_value(&latency)?;
+            metadata["shell"] = json!(shell);
+            metadata["success"] = json!(exit_code == 0);
+
+            let mut event = LatencyEvent::new(
+                ComponentType::Terminal,
+                EventSource::CommandExecution,
+                Duration::from_secs_f64(duration_ms / 1000.0),
+                format!("$ {}", command),
+            )
+            .with_kind(EventKind::CommandRun)
+            .with_metadata(metadata);
+            event.timestamp = end;
+
+            storage.store_event(&event).await
+        }
+        IpcMessage::LspRequest {
+            server,
+            method,
+            duration_ms,
+            end_ms,
+            error,
+            pid,
+        } => {
+            if !duration_ms.is_finite() || duration_ms < 0.0 {
+                return Err(anyhow!("invalid duration {}", duration_ms));
+            }
+
+            let mut event = LatencyEvent::new(
+                ComponentType::LanguageServer,
+                EventSource::ExtensionHost,
+                Duration::from_secs_f64(duration_ms / 1000.0),
+                format!("{} {}", server, method),
+            )
+            .with_kind(EventKind::CommandRun)
+            .with_metadata(json!({
+                "language_server": server,
+                "lsp_method": method,
+                "pid": pid,
+                "success": error.is_none(),
+                "error": error,
+                "ipc": true,
+            }));
+            if let Some(end) = epoch_ms(end_ms) {
+                event.timestamp = end;
+            }
+
+            storage.store_event(&event).await
+        }
+        IpcMessage::ModelRequest(report) => {
+            let interaction = &report.interaction;
+            let mut metadata = serde_json::to_value(interaction)?;
+            metadata["tokens_per_second"] = json!(interaction.tokens_per_second());
+            metadata["provider"] = json!(report.provider);
+            metadata["endpoint"] = json!(report.endpoint);
+            metadata["status"] = json!(report.status);
+            metadata["streamed"] = json!(report.streamed);
+            metadata["ttfb_ms"] = json!(report.ttfb_ms);
+            metadata["chunks"] = json!(report.chunks);
+            metadata["mean_chunk_gap_ms"] = json!(report.mean_chunk_gap_ms);
+            metadata["p95_chunk_gap_ms"] = json!(report.p95_chunk_gap_ms);
+            metadata["stream_tokens_per_second"] = json!(report.stream_tokens_per_second);
+            if let Some(tokens) = &report.tokens {
+                metadata["estimated_tokens"] = json!(tokens.estimated_tokens);
+            }
+            metadata["ipc"] = json!(true);
+
+            let mut event = LatencyEvent::new(
+                ComponentType::CloudModel,
+                EventSource::NetworkRequest,
+                Duration::from_millis(interaction.duration_ms),
+                format!(
+                    "{} {} ttft {}ms",
+                    report.provider,
+                    interaction.model_type,
+                    interaction
+                        .time_to_first_token_ms
+                        .map(|t| t.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                ),
+            )
+            .with_kind(EventKind::ModelRequest)
+            .with_metadata(metadata);
+            if let Some(end) = epoch_ms(report.end_ms) {
+                event.timestamp = end;
+            }
+
+            storage.store_event(&event).await?;
+            if let Some(mut tokens) = report.tokens {
+                tokens.timestamp = event.timestamp;
+                storage.store_token_metrics(&tokens).await?;
+            }
+            Ok(())
+        }
+        IpcMessage::Suggestion {
+            outcome,
+            latency_ms,
+            end_ms,
+            extension,
+            language,
+            suggestion_id,
+            workspace,
+        } => {
+            let outcome = SuggestionOutcome::parse(&outcome)?;
+            if !latency_ms.is_finite() || latency_ms < 0.0 {
+                return Err(anyhow!("invalid latency {}", latency_ms));
+            }
+            let component = match extension.as_deref() {
+                Some(extension) if !extension.to_ascii_lowercase().contains("copilot") => {
+                    ComponentType::VSCodeExtension
+                }
+                _ => ComponentType::GitHubCopilot,
+            };
+
+            // Only showing a suggestion takes time; accepting or rejecting
+            // it is a marker the acceptance rate is counted from
+            let (kind, duration) = match outcome {
+                SuggestionOutcome::Shown => (EventKind::ModelRequest, Duration::from_secs_f64(latency_ms / 1000.0)),
+                SuggestionOutcome::Accepted | SuggestionOutcome::Rejected => {
+                    (EventKind::SuggestionOutcome, Duration::ZERO)
+                }
+            };
+            let mut event = LatencyEvent::new(
+                component,
+                EventSource::UserInteraction,
+                duration,
+                format!("Suggestion {}", outcome.as_str()),
+            )
+            .with_kind(kind)
+            .with_metadata(json!({
+                "suggestion": outcome.as_str(),
+                "suggestion_latency_ms": latency_ms,
+                "extension_id": extension,
+                "language": language,
+                "suggestion_id": suggestion_id,
+                "ipc": true,
+            }));
+            if let Some(end) = end_ms.and_then(epoch_ms) {
+                event.timestamp = end;
+            }
+            if let Some(workspace) = workspace.as_deref().and_then(Workspace::parse) {
+                workspace.apply(&mut event.metadata);
+            }
+
+            storage.store_event(&event).await
+        }
+    }
+}
+
+fn epoch_ms(ms: f64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_micros((ms * 1000.0) as i64).single()
+}
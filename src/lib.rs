@@ -0,0 +1,114 @@
+//! Latency collection and storage for VS Code, its extensions and local
+//! models, usable without the `vscode-latency-monitor` binary.
+//!
+//! The main entry points are re-exported at the crate root:
+//!
+//! - [`LatencyMonitor`] runs the collectors and stores what they measure
+//! - [`MetricsStorage`] is the SQLite event store, for writing and querying
+//! - [`LatencyEvent`] is one measurement, built with [`LatencyEvent::builder`]
+//! - [`Config`] holds every setting, with the same defaults as the CLI
+//!
+//! Recording your own measurements and reading them back:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vscode_latency_monitor::{
+//!     ComponentType, EventSource, LatencyEvent, MetricsStorage, TimeRange,
+//! };
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let storage = MetricsStorage::new("latency.db".as_ref()).await?;
+//!
+//! let event = LatencyEvent::builder(ComponentType::VSCodeExtension, EventSource::CommandExecution)
+//!     .duration(Duration::from_millis(42))
+//!     .description("editor.action.formatDocument")
+//!     .metadata(serde_json::json!({ "extension": "rust-analyzer" }))
+//!     .build();
+//! storage.store_event(&event).await?;
+//!
+//! for metrics in storage.get_performance_metrics_in(&TimeRange::default()).await? {
+//!     println!("{}: p95 {} ms", metrics.component, metrics.p95_duration_ms);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The remaining modules back the CLI (dashboard, reports, sync, alerting,
+//! ...) and are public so embedders can reuse them, but their APIs follow
+//! the binary's needs.
+
+pub mod advice;
+pub mod alerts;
+pub mod auth;
+pub mod backend;
+pub mod bench;
+pub mod calibration;
+pub mod capture;
+pub mod cargo_latency;
+pub mod cmdline;
+pub mod config;
+pub mod connectivity;
+pub mod cron;
+pub mod daemon;
+pub mod dashboard;
+pub mod demo;
+pub mod derived;
+pub mod editors;
+pub mod email;
+pub mod environment;
+pub mod event_queue;
+pub mod export;
+pub mod extensions;
+pub mod federation;
+pub mod file_io;
+pub mod filter;
+pub mod histogram;
+pub mod html_report;
+pub mod ids;
+pub mod importer;
+pub mod ipc;
+pub mod lifecycle;
+pub mod loopback;
+pub mod lsp;
+pub mod model_proxy;
+pub mod models;
+pub mod monitor;
+pub mod monitor_status;
+pub mod netprobe;
+pub mod notifications;
+pub mod object_store;
+pub mod otel;
+pub mod pipeline;
+pub mod platform;
+pub mod postgres;
+pub mod progress;
+pub mod prometheus;
+pub mod proxy;
+pub mod reload;
+pub mod report;
+pub mod scheduling;
+pub mod secrets;
+pub mod service;
+pub mod shell;
+pub mod shutdown;
+pub mod sources;
+pub mod statusline;
+pub mod stats;
+pub mod storage;
+pub mod suggestions;
+pub mod sync;
+pub mod telemetry;
+pub mod tenancy;
+pub mod timerange;
+pub mod tokens;
+pub mod tui;
+pub mod units;
+pub mod watch;
+pub mod webhook;
+pub mod workspace;
+
+pub use config::Config;
+pub use models::{ComponentType, EventKind, EventPriority, EventSource, LatencyEvent, LatencyEventBuilder, PerformanceMetrics};
+pub use monitor::LatencyMonitor;
+pub use storage::MetricsStorage;
+pub use timerange::TimeRange;
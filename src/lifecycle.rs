@@ -0,0 +1,346 @@
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use sysinfo::System;
+
+use crate::platform::{self, VSCodeRole};
+
+/// What VS Code's main process logs when an extension host exits, e.g.
+/// `2024-05-01 10:00:00.123 [info] Extension host with pid 4242 exited with
+/// code: 0, signal: unknown.`
+const EXIT_LOG_PREFIX: &str = "Extension host with pid ";
+
+/// Timestamp at the start of each VS Code log line, in local time.
+const LOG_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Kind of VS Code helper process whose lifetime we track between samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackedProcessKind {
+    ExtensionHost,
+    Renderer,
+}
+
+/// A lifecycle transition detected by comparing two process snapshots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleChange {
+    pub lifecycle: LifecycleKind,
+    pub process_kind: TrackedProcessKind,
+    pub parent_pid: Option<u32>,
+    pub old_pid: Option<u32>,
+    pub new_pid: Option<u32>,
+    pub detected_at: DateTime<Utc>,
+    /// When the old process was started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the old process exited: the time VS Code logged its exit, or
+    /// else the sample that found it gone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exited_at: Option<DateTime<Utc>>,
+    /// Exit code VS Code logged for an extension host; `None` when it was
+    /// killed by a signal or the log didn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<String>,
+    /// Restarts and crashes within the window, for crash loops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failures: Option<usize>,
+}
+
+impl LifecycleChange {
+    /// How long the old process ran, if its start is known.
+    pub fn lifetime(&self) -> Option<Duration> {
+        (self.exited_at? - self.started_at?).to_std().ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleKind {
+    /// Extension host replaced by a new process under the same parent
+    ExtensionHostRestart,
+    /// Extension host vanished while its parent window is still alive
+    ExtensionHostCrash,
+    /// Renderer replaced under the same parent (window reload)
+    RendererReload,
+    /// Restarts/crashes exceeded the configured threshold within the window
+    CrashLoop,
+}
+
+impl LifecycleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleKind::ExtensionHostRestart => "extension_host_restart",
+            LifecycleKind::ExtensionHostCrash => "extension_host_crash",
+            LifecycleKind::RendererReload => "renderer_reload",
+            LifecycleKind::CrashLoop => "crash_loop",
+        }
+    }
+}
+
+/// Aggregated lifecycle counters reported by `status`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LifecycleSummary {
+    pub extension_host_restarts: u64,
+    pub extension_host_crashes: u64,
+    pub renderer_reloads: u64,
+    pub crash_loops: u64,
+    pub last_restart: Option<DateTime<Utc>>,
+    pub last_crash: Option<DateTime<Utc>>,
+}
+
+/// A tracked process as of the previous sample.
+#[derive(Debug, Clone)]
+struct KnownProcess {
+    parent: Option<u32>,
+    started_at: Option<DateTime<Utc>>,
+    logs_dir: Option<PathBuf>,
+}
+
+/// How an extension host exited, as logged by VS Code.
+#[derive(Debug, Clone, PartialEq)]
+struct LoggedExit {
+    code: Option<i32>,
+    signal: Option<String>,
+    at: Option<DateTime<Utc>>,
+}
+
+/// Tracks extension host and renderer PIDs across monitoring ticks and
+/// reports churn (restarts, crashes, reloads) plus crash-loop detection.
+pub struct ProcessLifecycleTracker {
+    known: HashMap<(TrackedProcessKind, u32), KnownProcess>,
+    recent_failures: VecDeque<DateTime<Utc>>,
+    crash_loop_threshold: usize,
+    crash_loop_window: ChronoDuration,
+    initialized: bool,
+}
+
+impl ProcessLifecycleTracker {
+    pub fn new(crash_loop_threshold: usize, crash_loop_window_secs: u64) -> Self {
+        Self {
+            known: HashMap::new(),
+            recent_failures: VecDeque::new(),
+            crash_loop_threshold,
+            crash_loop_window: ChronoDuration::seconds(crash_loop_window_secs as i64),
+            initialized: false,
+        }
+    }
+
+    /// Compare the current process table against the previous snapshot.
+    pub fn observe(&mut self, system: &System) -> Vec<LifecycleChange> {
+        let now = Utc::now();
+        let mut current: HashMap<(TrackedProcessKind, u32), KnownProcess> = HashMap::new();
+
+        for (pid, process) in system.processes() {
+            if let Some(kind) = classify(process) {
+                let known = match self.known.get(&(kind, pid.as_u32())) {
+                    Some(known) => known.clone(),
+                    None => KnownProcess {
+                        parent: process.parent().map(|p| p.as_u32()),
+                        started_at: DateTime::from_timestamp(process.start_time() as i64, 0),
+                        logs_dir: platform::vscode_logs_dir(system, process),
+                    },
+                };
+                current.insert((kind, pid.as_u32()), known);
+            }
+        }
+
+        if !self.initialized {
+            // First sample only establishes the baseline
+            self.known = current;
+            self.initialized = true;
+            return Vec::new();
+        }
+
+        let alive: HashSet<u32> = system.processes().keys().map(|p| p.as_u32()).collect();
+
+        let mut vanished: HashMap<_, Vec<(u32, &KnownProcess)>> = HashMap::new();
+        for (&(kind, pid), known) in &self.known {
+            if !current.contains_key(&(kind, pid)) {
+                vanished.entry((kind, known.parent)).or_default().push((pid, known));
+            }
+        }
+
+        let mut appeared: HashMap<(TrackedProcessKind, Option<u32>), Vec<u32>> = HashMap::new();
+        for (&(kind, pid), known) in &current {
+            if !self.known.contains_key(&(kind, pid)) {
+                appeared.entry((kind, known.parent)).or_default().push(pid);
+            }
+        }
+
+        let mut changes = Vec::new();
+        for ((kind, parent), old_pids) in vanished {
+            let parent_alive = parent.map(|p| alive.contains(&p)).unwrap_or(false);
+            if !parent_alive {
+                // Whole window went away; not a crash
+                continue;
+            }
+
+            let mut replacements = appeared.remove(&(kind, parent)).unwrap_or_default();
+            for (old_pid, known) in old_pids {
+                let new_pid = replacements.pop();
+                let lifecycle = match (kind, new_pid) {
+                    (TrackedProcessKind::Renderer, Some(_)) => LifecycleKind::RendererReload,
+                    (TrackedProcessKind::Renderer, None) => continue,
+                    (TrackedProcessKind::ExtensionHost, Some(_)) => LifecycleKind::ExtensionHostRestart,
+                    (TrackedProcessKind::ExtensionHost, None) => LifecycleKind::ExtensionHostCrash,
+                };
+
+                let exit = match kind {
+                    TrackedProcessKind::ExtensionHost => known.logs_dir.as_deref().and_then(|dir| logged_exit(dir, old_pid)),
+                    TrackedProcessKind::Renderer => None,
+                };
+                changes.push(LifecycleChange {
+                    lifecycle,
+                    process_kind: kind,
+                    parent_pid: parent,
+                    old_pid: Some(old_pid),
+                    new_pid,
+                    detected_at: now,
+                    started_at: known.started_at,
+                    exited_at: Some(exit.as_ref().and_then(|exit| exit.at).unwrap_or(now)),
+                    exit_code: exit.as_ref().and_then(|exit| exit.code),
+                    exit_signal: exit.and_then(|exit| exit.signal),
+                    failures: None,
+                });
+            }
+        }
+
+        self.known = current;
+
+        let failures = changes
+            .iter()
+            .filter(|c| c.process_kind == TrackedProcessKind::ExtensionHost)
+            .count();
+        if failures > 0 {
+            if let Some(crash_loop) = self.record_failures(failures, now) {
+                changes.push(crash_loop);
+            }
+        }
+
+        changes
+    }
+
+    fn record_failures(&mut self, count: usize, now: DateTime<Utc>) -> Option<LifecycleChange> {
+        for _ in 0..count {
+            self.recent_failures.push_back(now);
+        }
+
+        while let Some(oldest) = self.recent_failures.front() {
+            if now - *oldest > self.crash_loop_window {
+                self.recent_failures.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.crash_loop_threshold > 0 && self.recent_failures.len() >= self.crash_loop_threshold {
+            let failures = self.recent_failures.len();
+            // Reset so a single loop is reported once per window
+            self.recent_failures.clear();
+            return Some(LifecycleChange {
+                lifecycle: LifecycleKind::CrashLoop,
+                process_kind: TrackedProcessKind::ExtensionHost,
+                parent_pid: None,
+                old_pid: None,
+                new_pid: None,
+                detected_at: now,
+                started_at: None,
+                exited_at: None,
+                exit_code: None,
+                exit_signal: None,
+                failures: Some(failures),
+            });
+        }
+
+        None
+    }
+}
+
+fn classify(process: &sysinfo::Process) -> Option<TrackedProcessKind> {
+    match platform::vscode_role(process)? {
+        VSCodeRole::ExtensionHost => Some(TrackedProcessKind::ExtensionHost),
+        VSCodeRole::Renderer => Some(TrackedProcessKind::Renderer),
+        _ => None,
+    }
+}
+
+/// How extension host `pid` exited, from the main process log of the newest
+/// session in `logs_dir`. The monitor isn't the host's parent, so it can't
+/// collect the exit status itself.
+fn logged_exit(logs_dir: &Path, pid: u32) -> Option<LoggedExit> {
+    let session = std::fs::read_dir(logs_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.path())
+        .max()?;
+    let log = std::fs::read_to_string(session.join("main.log")).ok()?;
+    log.lines().rev().find_map(|line| parse_exit_line(line, pid))
+}
+
+/// Parse one main log line, if it reports the exit of extension host `pid`.
+fn parse_exit_line(line: &str, pid: u32) -> Option<LoggedExit> {
+    let (head, rest) = line.split_once(EXIT_LOG_PREFIX)?;
+    let rest = rest.strip_prefix(&format!("{} exited with code: ", pid))?;
+    let (code, signal) = rest.split_once(", signal: ")?;
+    let signal = signal.trim_end_matches('.');
+
+    let at = head
+        .get(..23)
+        .and_then(|stamp| NaiveDateTime::parse_from_str(stamp, LOG_TIMESTAMP_FORMAT).ok())
+        .and_then(|stamp| Local.from_local_datetime(&stamp).earliest())
+        .map(|stamp| stamp.with_timezone(&Utc));
+
+    Some(LoggedExit {
+        code: code.parse().ok(),
+        signal: (!matches!(signal, "unknown" | "null" | "")).then(|| signal.to_string()),
+        at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_exit_codes_of_the_given_pid() {
+        let line = "2024-05-01 10:00:00.123 [info] Extension host with pid 4242 exited with code: 134, signal: unknown.";
+        let exit = parse_exit_line(line, 4242).unwrap();
+        assert_eq!(exit.code, Some(134));
+        assert_eq!(exit.signal, None);
+        assert!(exit.at.is_some());
+
+        assert_eq!(parse_exit_line(line, 424), None);
+    }
+
+    #[test]
+    fn parses_signals_without_an_exit_code() {
+        let line = "2024-05-01 10:00:00.123 [info] Extension host with pid 7 exited with code: null, signal: SIGKILL.";
+        let exit = parse_exit_line(line, 7).unwrap();
+        assert_eq!(exit.code, None);
+        assert_eq!(exit.signal.as_deref(), Some("SIGKILL"));
+    }
+
+    #[test]
+    fn lifetime_runs_from_start_to_exit() {
+        let started = Utc::now();
+        let change = LifecycleChange {
+            lifecycle: LifecycleKind::ExtensionHostCrash,
+            process_kind: TrackedProcessKind::ExtensionHost,
+            parent_pid: Some(1),
+            old_pid: Some(2),
+            new_pid: None,
+            detected_at: started + ChronoDuration::seconds(95),
+            started_at: Some(started),
+            exited_at: Some(started + ChronoDuration::seconds(90)),
+            exit_code: Some(1),
+            exit_signal: None,
+            failures: None,
+        };
+        assert_eq!(change.lifetime(), Some(Duration::from_secs(90)));
+    }
+}
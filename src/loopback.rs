@@ -0,0 +1,423 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+
+use crate::event_queue::EventQueue;
+use crate::filter::EventFilter;
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::storage::{self, MetricsStorage};
+use crate::timerange::TimeRange;
+
+/// `metadata.probe` of events recorded from the loopback device.
+pub const LOOPBACK_PROBE: &str = "loopback";
+
+/// A software event may finish this long outside the hardware window and
+/// still be paired with it, to absorb the device's and host's clock skew.
+const MATCH_SLACK_MS: i64 = 50;
+
+/// Most events of each kind read for one comparison.
+const MAX_COMPARED_EVENTS: u32 = 200_000;
+
+/// How long to wait before reopening a device that went away.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+/// How a loopback device reports its measurements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoopbackProtocol {
+    /// One text line per measurement: either a bare number of microseconds,
+    /// or `key=value` pairs with `latency_us` or `latency_ms` and optionally
+    /// `seq`
+    #[default]
+    Serial,
+    /// Fixed-size HID reports (Linux hidraw) carrying the latency in
+    /// microseconds and a sequence number, both little-endian `u32`
+    Hid,
+}
+
+/// Hardware latency tester measuring end-to-end input latency, from a key
+/// press it injects to the change it sees on screen (or an echo it gets
+/// back), configured as `[monitoring.loopback]`. Its measurements are the
+/// ground truth the software probes are compared against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoopbackConfig {
+    pub enabled: bool,
+    /// Serial port (`/dev/ttyACM0`, `COM3`) or hidraw device (`/dev/hidraw2`)
+    pub device: String,
+    pub protocol: LoopbackProtocol,
+    /// Written to the device every `trigger_interval_secs` to start a
+    /// measurement; leave unset for devices that measure on their own
+    pub trigger: Option<String>,
+    pub trigger_interval_secs: u64,
+    /// Size of one HID report, including the report ID byte if the device
+    /// numbers its reports
+    pub hid_report_size: usize,
+    /// Where the latency and sequence number start within a HID report
+    pub hid_offset: usize,
+    /// Software events the hardware measurements are compared against
+    pub software_filter: String,
+}
+
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: String::new(),
+            protocol: LoopbackProtocol::Serial,
+            trigger: None,
+            trigger_interval_secs: 2,
+            hid_report_size: 64,
+            hid_offset: 0,
+            software_filter: "metadata.command = 'type'".to_string(),
+        }
+    }
+}
+
+/// One measurement reported by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopbackSample {
+    pub latency_us: u64,
+    pub seq: Option<u64>,
+}
+
+/// Parse a line written by a serial tester. Blank lines and `#` comments
+/// give `None`, as do lines without a latency.
+pub fn parse_serial_line(line: &str) -> Option<LoopbackSample> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    if let Ok(latency_us) = line.parse::<u64>() {
+        return Some(LoopbackSample { latency_us, seq: None });
+    }
+
+    let mut sample = LoopbackSample { latency_us: 0, seq: None };
+    let mut found = false;
+    for pair in line.split(|c: char| c.is_whitespace() || c == ',') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "latency_us" => {
+                sample.latency_us = value.trim().parse().ok()?;
+                found = true;
+            }
+            "latency_ms" => {
+                let ms: f64 = value.trim().parse().ok()?;
+                if !ms.is_finite() || ms < 0.0 {
+                    return None;
+                }
+                sample.latency_us = (ms * 1000.0).round() as u64;
+                found = true;
+            }
+            "seq" => sample.seq = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    found.then_some(sample)
+}
+
+/// Parse a HID report; a zero latency means the report carries no
+/// measurement (e.g. a keep-alive).
+pub fn parse_hid_report(report: &[u8], offset: usize) -> Option<LoopbackSample> {
+    let field = |at: usize| -> Option<u32> {
+        let bytes = report.get(at..at + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+    let latency_us = field(offset)?;
+    if latency_us == 0 {
+        return None;
+    }
+    Some(LoopbackSample {
+        latency_us: latency_us as u64,
+        seq: field(offset + 4).map(u64::from),
+    })
+}
+
+/// Read measurements from the device until the task is dropped, reopening
+/// it if it is unplugged, and record each as a probe event.
+pub async fn run(config: LoopbackConfig, events: EventQueue) -> Result<()> {
+    if config.device.is_empty() {
+        return Err(anyhow!("monitoring.loopback.enabled is set but no device is configured"));
+    }
+    info!("Reading {:?} loopback measurements from {}", config.protocol, config.device);
+
+    spawn_triggers(&config);
+
+    loop {
+        if let Err(e) = read_device(&config, |sample| record(&config, &events, sample)).await {
+            warn!("Loopback device {} unavailable: {}", config.device, e);
+        }
+        tokio::time::sleep(REOPEN_DELAY).await;
+    }
+}
+
+/// Write the configured trigger to the device every
+/// `trigger_interval_secs` in the background, if there is one.
+pub fn spawn_triggers(config: &LoopbackConfig) {
+    if let Some(trigger) = config.trigger.clone() {
+        let device = config.device.clone();
+        let every = Duration::from_secs(config.trigger_interval_secs.max(1));
+        tokio::spawn(async move { send_triggers(&device, trigger.as_bytes(), every).await });
+    }
+}
+
+/// Separate handle from the reader, so a read blocked waiting for the
+/// device never holds up a trigger.
+async fn send_triggers(device: &str, trigger: &[u8], every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        let mut file = match tokio::fs::OpenOptions::new().write(true).open(device).await {
+            Ok(file) => file,
+            Err(e) => {
+                debug!("Can't open {} to trigger a measurement: {}", device, e);
+                tokio::time::sleep(REOPEN_DELAY).await;
+                continue;
+            }
+        };
+        loop {
+            ticker.tick().await;
+            let written = match file.write_all(trigger).await {
+                Ok(()) => file.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                debug!("Failed to trigger loopback measurement: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Pass each measurement the device reports to `on_sample` until it is
+/// closed or fails; the device is never triggered from here.
+pub async fn read_device(config: &LoopbackConfig, mut on_sample: impl FnMut(LoopbackSample)) -> Result<()> {
+    let file = tokio::fs::File::open(&config.device).await?;
+    match config.protocol {
+        LoopbackProtocol::Serial => {
+            let mut lines = BufReader::new(file).lines();
+            while let Some(line) = lines.next_line().await? {
+                match parse_serial_line(&line) {
+                    Some(sample) => on_sample(sample),
+                    None => debug!("Ignoring loopback line {:?}", line),
+                }
+            }
+        }
+        LoopbackProtocol::Hid => {
+            let mut file = file;
+            let mut report = vec![0u8; config.hid_report_size.max(config.hid_offset + 8)];
+            loop {
+                let read = file.read(&mut report).await?;
+                if read == 0 {
+                    break;
+                }
+                if let Some(sample) = parse_hid_report(&report[..read], config.hid_offset) {
+                    on_sample(sample);
+                }
+            }
+        }
+    }
+    Err(anyhow!("device closed"))
+}
+
+fn record(config: &LoopbackConfig, events: &EventQueue, sample: LoopbackSample) {
+    let event = LatencyEvent::new(
+        ComponentType::VSCode,
+        EventSource::UserInteraction,
+        Duration::from_micros(sample.latency_us),
+        format!("Loopback input latency {:.1}ms", sample.latency_us as f64 / 1000.0),
+    )
+    .with_kind(EventKind::Probe)
+    .with_metadata(json!({
+        "probe": LOOPBACK_PROBE,
+        "device": config.device,
+        "protocol": config.protocol,
+        "seq": sample.seq,
+    }));
+
+    // Ground truth is scarce, so load shedding never drops it
+    if let Err(e) = events.send_exempt(event) {
+        warn!("Failed to send loopback event: {}", e);
+    }
+}
+
+/// Percentiles of one set of latencies.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub events: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl LatencySummary {
+    fn new(sorted_us: &[u64]) -> Self {
+        let ms = |q| storage::percentile(sorted_us, q) as f64 / 1000.0;
+        Self {
+            events: sorted_us.len() as u64,
+            p50_ms: ms(0.50),
+            p95_ms: ms(0.95),
+            p99_ms: ms(0.99),
+        }
+    }
+}
+
+/// Hardware measurements set against the software probes over one window.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoopbackComparison {
+    pub range: String,
+    pub software_filter: String,
+    pub hardware: LatencySummary,
+    pub software: LatencySummary,
+    /// Hardware measurements a software event was found for
+    pub matched: u64,
+    /// Of the matched pairs, the software event took longer than the
+    /// hardware measured; usually clock skew or a mismatched pairing
+    pub software_longer: u64,
+    /// Hardware minus software latency over matched pairs: the part of
+    /// end-to-end latency the software probes don't see
+    pub unseen: LatencySummary,
+    /// Median share of the hardware latency the software probe reported
+    pub software_share_p50: Option<f64>,
+}
+
+/// Pair each hardware measurement with the first software event that
+/// finished between its key press and its end, and summarize both sides
+/// and the gap between them.
+pub async fn compare(storage: &MetricsStorage, config: &LoopbackConfig, range: &TimeRange) -> Result<LoopbackComparison> {
+    let hardware_range =
+        range.matching(EventFilter::parse(&format!("metadata.probe = '{}'", LOOPBACK_PROBE))?);
+    let software_range = range.matching(EventFilter::parse(&config.software_filter)?);
+    let hardware = storage.get_latency_samples(MAX_COMPARED_EVENTS, &hardware_range).await?;
+    let software = storage.get_latency_samples(MAX_COMPARED_EVENTS, &software_range).await?;
+
+    let slack = chrono::Duration::milliseconds(MATCH_SLACK_MS);
+    let mut next_software = 0;
+    let mut unseen_us = Vec::new();
+    let mut shares = Vec::new();
+    let mut software_longer = 0;
+    for (ended, _, latency_us) in &hardware {
+        let pressed = *ended - chrono::Duration::microseconds(*latency_us as i64);
+        while software.get(next_software).is_some_and(|(at, _, _)| *at < pressed - slack) {
+            next_software += 1;
+        }
+        let Some((at, _, software_us)) = software.get(next_software) else {
+            break;
+        };
+        if *at > *ended + slack {
+            continue;
+        }
+        next_software += 1;
+
+        if software_us > latency_us {
+            software_longer += 1;
+        }
+        unseen_us.push(latency_us.saturating_sub(*software_us));
+        if *latency_us > 0 {
+            shares.push(*software_us as f64 / *latency_us as f64);
+        }
+    }
+
+    let mut hardware_us: Vec<u64> = hardware.iter().map(|(_, _, us)| *us).collect();
+    let mut software_us: Vec<u64> = software.iter().map(|(_, _, us)| *us).collect();
+    hardware_us.sort_unstable();
+    software_us.sort_unstable();
+    unseen_us.sort_unstable();
+    shares.sort_by(f64::total_cmp);
+
+    Ok(LoopbackComparison {
+        range: range.to_string(),
+        software_filter: config.software_filter.clone(),
+        hardware: LatencySummary::new(&hardware_us),
+        software: LatencySummary::new(&software_us),
+        matched: unseen_us.len() as u64,
+        software_longer,
+        unseen: LatencySummary::new(&unseen_us),
+        software_share_p50: shares.get(shares.len() / 2).copied(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serial_lines_give_a_latency_and_optional_sequence() {
+        let sample = |latency_us, seq| Some(LoopbackSample { latency_us, seq });
+        assert_eq!(parse_serial_line("  12345\r"), sample(12_345, None));
+        assert_eq!(parse_serial_line("seq=7 latency_us=23400"), sample(23_400, Some(7)));
+        assert_eq!(parse_serial_line("latency_ms=23.45,seq=8"), sample(23_450, Some(8)));
+        assert_eq!(parse_serial_line("latency_ms=1.0 temp=31"), sample(1_000, None));
+    }
+
+    #[test]
+    fn serial_lines_without_a_usable_latency_are_ignored() {
+        for line in ["", "   ", "# tester v2 ready", "seq=9", "latency_us=fast", "latency_ms=-3", "latency_ms=NaN"] {
+            assert_eq!(parse_serial_line(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn hid_reports_carry_little_endian_fields_at_the_offset() {
+        let mut report = vec![0u8; 16];
+        report[1..5].copy_from_slice(&18_000u32.to_le_bytes());
+        report[5..9].copy_from_slice(&42u32.to_le_bytes());
+        assert_eq!(
+            parse_hid_report(&report, 1),
+            Some(LoopbackSample {
+                latency_us: 18_000,
+                seq: Some(42),
+            })
+        );
+
+        // A short report still has a latency without a sequence number
+        assert_eq!(
+            parse_hid_report(&report[..7], 1),
+            Some(LoopbackSample {
+                latency_us: 18_000,
+                seq: None,
+            })
+        );
+        assert_eq!(parse_hid_report(&report[..4], 1), None);
+    }
+
+    #[test]
+    fn zero_latency_hid_reports_are_keep_alives() {
+        let report = [0u8, 0, 0, 0, 5, 0, 0, 0];
+        assert_eq!(parse_hid_report(&report, 0), None);
+    }
+
+    #[tokio::test]
+    async fn serial_devices_are_read_line_by_line() {
+        let path = std::env::temp_dir().join(format!("vscode-latency-monitor-loopback-{}", std::process::id()));
+        std::fs::write(&path, "# ready\n15000\nseq=2 latency_ms=16.5\nnoise\n").unwrap();
+        let config = LoopbackConfig {
+            device: path.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let mut samples = Vec::new();
+        let result = read_device(&config, |sample| samples.push(sample)).await;
+        std::fs::remove_file(&path).unwrap();
+
+        // The end of a device is always reported, so the caller reopens it
+        assert!(result.is_err());
+        assert_eq!(
+            samples,
+            vec![
+                LoopbackSample {
+                    latency_us: 15_000,
+                    seq: None,
+                },
+                LoopbackSample {
+                    latency_us: 16_500,
+                    seq: Some(2),
+                },
+            ]
+        );
+    }
+}
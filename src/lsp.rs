@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::ipc;
+use crate::platform;
+
+/// Timings waiting to be sent to the monitor; beyond this they are dropped
+/// rather than holding up the editor.
+const REPORT_QUEUE: usize = 1024;
+
+/// `RequestCancelled` and `ContentModified`: the client no longer wanted
+/// the answer, so the time it took isn't a latency anyone waited for.
+const ABANDONED_ERRORS: &[i64] = &[-32800, -32801];
+
+/// A request the client sent that the server hasn't answered yet.
+struct Pending {
+    method: String,
+    sent: Instant,
+}
+
+/// Requests in flight, keyed by their JSON-RPC id.
+type PendingRequests = Arc<Mutex<HashMap<String, Pending>>>;
+
+/// Run a language server behind a proxy that times every request VS Code
+/// sends it, reporting each to the monitor over IPC. Messages pass through
+/// unchanged, and the server's exit code is returned for the proxy to exit
+/// with. The monitor not running only means timings are dropped.
+pub async fn run_proxy(socket: &Path, name: Option<String>, command: &[String]) -> Result<i32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("lsp-proxy needs the language server's command"))?;
+    let server = name
+        .or_else(|| platform::language_server_in(program, args).map(str::to_string))
+        .unwrap_or_else(|| server_name(program));
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Can't start language server {}: {}", program, e))?;
+    let (Some(server_stdin), Some(server_stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        return Err(anyhow!("Language server {} has no stdio", program));
+    };
+    let pid = child.id();
+
+    let pending = PendingRequests::default();
+    let (reports, queue) = mpsc::channel(REPORT_QUEUE);
+    tokio::spawn(ipc::send_reports(socket.to_path_buf(), queue));
+
+    // Closing stdin when the editor goes away is what stops most servers
+    let requests = pending.clone();
+    tokio::spawn(async move {
+        let _ = relay(tokio::io::stdin(), server_stdin, |message| track_request(&requests, message)).await;
+    });
+
+    // Runs until the server closes its stdout, i.e. exits
+    let _ = relay(server_stdout, tokio::io::stdout(), |message| {
+        if let Some((method, duration_ms, error)) = complete_request(&pending, message) {
+            let report = json!({
+                "type": "lsp_request",
+                "server": server,
+                "method": method,
+                "duration_ms": duration_ms,
+                "end_ms": Utc::now().timestamp_micros() as f64 / 1000.0,
+                "error": error,
+                "pid": pid,
+            });
+            let _ = reports.try_send(report);
+        }
+    })
+    .await;
+
+    let status = child.wait().await?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Copy LSP messages (`Content-Length` headers, then a JSON body) from
+/// `reader` to `writer`, showing each body to `inspect` once it has been
+/// passed on. Input that isn't LSP framing is copied through verbatim.
+async fn relay<R, W>(reader: R, mut writer: W, mut inspect: impl FnMut(&Value)) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let mut headers = Vec::new();
+        let mut length = None;
+        loop {
+            let start = headers.len();
+            if reader.read_until(b'\n', &mut headers).await? == 0 {
+                writer.write_all(&headers).await?;
+                writer.flush().await?;
+                return Ok(());
+            }
+            let line = String::from_utf8_lossy(&headers[start..]);
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let Some(length) = length else {
+            writer.write_all(&headers).await?;
+            tokio::io::copy(&mut reader, &mut writer).await?;
+            return Ok(());
+        };
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+        writer.write_all(&headers).await?;
+        writer.write_all(&body).await?;
+        writer.flush().await?;
+
+        if let Ok(message) = serde_json::from_slice::<Value>(&body) {
+            inspect(&message);
+        }
+    }
+}
+
+/// Remember a client request (it has both a `method` and an `id`;
+/// notifications have no `id`).
+fn track_request(pending: &PendingRequests, message: &Value) {
+    let (Some(method), Some(id)) = (message.get("method").and_then(Value::as_str), message.get("id")) else {
+        return;
+    };
+    let mut pending = pending.lock().unwrap_or_else(|e| e.into_inner());
+    pending.insert(
+        id.to_string(),
+        Pending {
+            method: method.to_string(),
+            sent: Instant::now(),
+        },
+    );
+}
+
+/// Method, milliseconds taken and error message of the request a server
+/// response answers; `None` for anything else and for abandoned requests.
+fn complete_request(pending: &PendingRequests, message: &Value) -> Option<(String, f64, Option<String>)> {
+    if message.get("method").is_some() {
+        return None;
+    }
+    let id = message.get("id")?.to_string();
+    let request = pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&id)?;
+    let duration_ms = request.sent.elapsed().as_secs_f64() * 1000.0;
+
+    let error = match message.get("error") {
+        Some(error) => {
+            let code = error.get("code").and_then(Value::as_i64).unwrap_or_default();
+            if ABANDONED_ERRORS.contains(&code) {
+                return None;
+            }
+            let text = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+            Some(format!("{} ({})", text, code))
+        }
+        None => None,
+    };
+    Some((request.method, duration_ms, error))
+}
+
+/// `rust-analyzer` for `/home/me/.cargo/bin/rust-analyzer(.exe)`.
+fn server_name(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| program.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn relay_passes_messages_through_and_inspects_each_body() {
+        let first = r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/hover"}"#;
+        let second = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let input = format!("{}{}", frame(first), frame(second));
+
+        let mut output = Vec::new();
+        let mut seen = Vec::new();
+        relay(input.as_bytes(), &mut output, |message| seen.push(message.clone())).await.unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0]["method"], "textDocument/hover");
+        assert_eq!(seen[1]["method"], "initialized");
+    }
+
+    #[tokio::test]
+    async fn relay_copies_input_that_isnt_lsp_framing_verbatim() {
+        let input = "not a header\r\n\r\n{\"id\":1}\r\ntrailing bytes";
+        let mut output = Vec::new();
+        let mut inspected = 0;
+        relay(input.as_bytes(), &mut output, |_| inspected += 1).await.unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+        assert_eq!(inspected, 0);
+    }
+
+    #[tokio::test]
+    async fn relay_accepts_extra_headers_in_any_case() {
+        let body = r#"{"id":"a","result":null}"#;
+        let input = format!(
+            "content-type: application/vscode-jsonrpc\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut output = Vec::new();
+        let mut seen = Vec::new();
+        relay(input.as_bytes(), &mut output, |message| seen.push(message.clone())).await.unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+        assert_eq!(seen, vec![json!({"id": "a", "result": null})]);
+    }
+
+    #[test]
+    fn responses_complete_the_request_with_the_same_id() {
+        let pending = PendingRequests::default();
+        track_request(&pending, &json!({"id": 7, "method": "textDocument/completion"}));
+        // Notifications have no id and are never answered
+        track_request(&pending, &json!({"method": "textDocument/didChange"}));
+        assert_eq!(pending.lock().unwrap().len(), 1);
+
+        // A string id isn't the same request as the numeric one
+        assert!(complete_request(&pending, &json!({"id": "7", "result": []})).is_none());
+        let (method, duration_ms, error) = complete_request(&pending, &json!({"id": 7, "result": []})).unwrap();
+        assert_eq!(method, "textDocument/completion");
+        assert!(duration_ms >= 0.0);
+        assert_eq!(error, None);
+        assert!(complete_request(&pending, &json!({"id": 7, "result": []})).is_none());
+    }
+
+    #[test]
+    fn server_requests_and_abandoned_requests_are_not_timed() {
+        let pending = PendingRequests::default();
+        track_request(&pending, &json!({"id": 1, "method": "textDocument/hover"}));
+        track_request(&pending, &json!({"id": 2, "method": "textDocument/definition"}));
+
+        // The server asking the client something shares the id space
+        assert!(complete_request(&pending, &json!({"id": 1, "method": "workspace/configuration"})).is_none());
+
+        let cancelled = json!({"id": 1, "error": {"code": -32800, "message": "cancelled"}});
+        assert!(complete_request(&pending, &cancelled).is_none());
+        assert!(pending.lock().unwrap().get("1").is_none());
+
+        let failed = json!({"id": 2, "error": {"code": -32603, "message": "internal error"}});
+        let (_, _, error) = complete_request(&pending, &failed).unwrap();
+        assert_eq!(error.as_deref(), Some("internal error (-32603)"));
+    }
+
+    #[test]
+    fn server_names_come_from_the_program_file_name() {
+        assert_eq!(server_name("/home/me/.cargo/bin/rust-analyzer"), "rust-analyzer");
+        assert_eq!(server_name("gopls"), "gopls");
+        assert_eq!(server_name("typescript-language-server.cmd"), "typescript-language-server");
+    }
+}
@@ -3,18 +3,26 @@ use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use tracing::{info, warn, error};
 
-mod monitor;
-mod models;
-mod dashboard;
-mod storage;
-mod config;
-mod telemetry;
-
-use monitor::LatencyMonitor;
-use dashboard::DashboardServer;
-use storage::MetricsStorage;
-use config::Config;
-use telemetry::TelemetryServer;
+use vscode_latency_monitor::{
+    alerts, auth, backend, bench, calibration, capture, daemon, demo, derived, file_io, ids, importer, ipc, loopback,
+    lsp, model_proxy, monitor_status, notifications, otel, pipeline, reload, report, scheduling, service, shell,
+    statusline, suggestions, sync, tenancy, timerange, tui, units, watch, webhook,
+};
+use vscode_latency_monitor::backend::StorageBackend;
+use vscode_latency_monitor::config::Config;
+use vscode_latency_monitor::connectivity::ConnectivityTracker;
+use vscode_latency_monitor::dashboard::DashboardServer;
+use vscode_latency_monitor::filter::EventFilter;
+use vscode_latency_monitor::models::{AuditEntry, ComponentType, MaintenanceWindow, Recurrence, SavedQuery};
+use vscode_latency_monitor::monitor::LatencyMonitor;
+use vscode_latency_monitor::object_store::{ObjectKind, ObjectStore};
+use vscode_latency_monitor::progress::Progress;
+use vscode_latency_monitor::secrets::Secrets;
+use vscode_latency_monitor::shutdown::Shutdown;
+use vscode_latency_monitor::storage::{run_retention, run_summaries, MetricsStorage};
+use vscode_latency_monitor::telemetry::TelemetryServer;
+use vscode_latency_monitor::timerange::TimeRange;
+use vscode_latency_monitor::workspace::Workspaces;
 
 #[derive(Parser)]
 #[command(
@@ -40,19 +48,87 @@ struct Cli {
 enum Commands {
     /// Start latency monitoring
     Start {
-        /// Component to monitor (vscode, models, terminal, all)
-        #[arg(short, long, default_value = "all")]
+        /// Component to monitor (vscode, models, terminal, lsp, disk, network, editors, all)
+        #[arg(long, default_value = "all")]
         component: String,
 
-        /// Monitoring interval in milliseconds
-        #[arg(short, long, default_value = "100")]
-        interval: u64,
+        /// Monitoring interval in milliseconds [default: monitoring.interval_ms]
+        #[arg(short, long)]
+        interval: Option<u64>,
 
         /// Run in background
-        #[arg(short, long)]
+        #[arg(long)]
         daemon: bool,
     },
 
+    /// Install a systemd user service (a launchd agent on macOS) that runs the monitor daemon
+    InstallService {
+        /// Component to monitor (vscode, models, terminal, lsp, disk, network, editors, all)
+        #[arg(long, default_value = "all")]
+        component: String,
+
+        /// Replace a service that's already installed
+        #[arg(long)]
+        force: bool,
+
+        /// Enable the service without starting it now
+        #[arg(long)]
+        no_start: bool,
+
+        /// Print the unit file instead of installing it
+        #[arg(long)]
+        print: bool,
+    },
+
+    /// Stop and remove the service installed by install-service
+    UninstallService,
+
+    /// Monitor everything at high resolution for a while, then report on it
+    #[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+    Capture {
+        #[command(subcommand)]
+        mode: Option<CaptureMode>,
+
+        /// How long to capture (30s, 10m, 1h)
+        #[arg(long, required = true)]
+        duration: Option<String>,
+
+        /// Name for the session; its events are tagged capture=<label>
+        #[arg(short, long, required = true)]
+        label: Option<String>,
+
+        /// Sampling interval in milliseconds while capturing [default: 25]
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Report format (json, csv, html)
+        #[arg(short, long, default_value = "html")]
+        format: String,
+
+        /// Report file path [default: capture-<label>-<start>.<format>]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Also write an incident bundle directory: report, SQLite snapshot of the events, config and environment
+        #[arg(long)]
+        bundle: bool,
+    },
+
+    /// Measure this machine's idle sampling noise with VS Code closed
+    Calibrate {
+        /// How long to measure (30s, 2m, 10m)
+        #[arg(long, default_value = "2m")]
+        duration: String,
+
+        /// Sampling interval in milliseconds [default: monitoring.interval_ms]
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Measure even though VS Code is running
+        #[arg(short, long)]
+        force: bool,
+    },
+
     /// Stop monitoring processes
     Stop {
         /// Force stop all processes
@@ -71,6 +147,29 @@ enum Commands {
         realtime: bool,
     },
 
+    /// Serve the dashboard on a temporary database of generated history
+    Demo {
+        /// Port to serve dashboard
+        #[arg(short, long, default_value = "3030")]
+        port: u16,
+
+        /// Days of history to generate
+        #[arg(long, default_value = "7")]
+        days: u32,
+
+        /// Random seed for the generated history
+        #[arg(long, default_value = "42")]
+        seed: u64,
+
+        /// Keep the demo database at this new path instead of a temporary one
+        #[arg(long)]
+        database: Option<PathBuf>,
+
+        /// Don't keep generating events while the dashboard runs
+        #[arg(long)]
+        no_live: bool,
+    },
+
     /// Generate performance reports
     Report {
         /// Output format (json, csv, html)
@@ -81,14 +180,65 @@ enum Commands {
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Time range (e.g., "1h", "24h", "7d")
+        /// Start of the window: a duration ago (30m, 24h, 7d, 2w), RFC 3339 or YYYY-MM-DD
         #[arg(short, long, default_value = "1h")]
         since: String,
+
+        /// End of the window, same syntax as --since (default: now)
+        #[arg(short, long)]
+        until: Option<String>,
+
+        /// Email the report to the recipients of this configured schedule
+        #[arg(long, value_name = "SCHEDULE")]
+        deliver: Option<String>,
+
+        /// Only aggregate samples inside the configured working hours
+        #[arg(long)]
+        working_hours: bool,
+
+        /// Only include events matching this filter (e.g. "pid = 4242")
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Only include events from this workspace (folder name)
+        #[arg(long)]
+        workspace: Option<String>,
+
+        /// Also count events recorded by `test`
+        #[arg(long)]
+        include_test_data: bool,
+
+        /// Idle noise handling (off, flag, subtract) [default: reports.noise]
+        #[arg(long)]
+        noise: Option<String>,
+
+        /// Embed the monitor version, platform and scrubbed configuration [default: reports.include_environment]
+        #[arg(long)]
+        include_environment: bool,
+    },
+
+    /// Copilot suggestion acceptance rate by latency band and hour
+    Acceptance {
+        /// Start of the window: a duration ago (30m, 24h, 7d, 2w), RFC 3339 or YYYY-MM-DD
+        #[arg(short, long, default_value = "7d")]
+        since: String,
+
+        /// End of the window, same syntax as --since (default: now)
+        #[arg(short, long)]
+        until: Option<String>,
+
+        /// Only suggestions of this extension (e.g. github.copilot)
+        #[arg(long)]
+        extension: Option<String>,
+
+        /// Print the report as JSON, including the hourly breakdown
+        #[arg(long)]
+        json: bool,
     },
 
     /// Export metrics data
     Export {
-        /// Export format (sqlite, json, csv)
+        /// Export format (sqlite, json, csv, parquet)
         #[arg(short, long, default_value = "json")]
         format: String,
 
@@ -96,9 +246,37 @@ enum Commands {
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Time range filter
+        /// Start of the window: a duration ago (30m, 24h, 7d, 2w), RFC 3339 or YYYY-MM-DD
         #[arg(short, long)]
         since: Option<String>,
+
+        /// End of the window, same syntax as --since (default: now)
+        #[arg(short, long)]
+        until: Option<String>,
+
+        /// Field delimiter for CSV exports (use `tab` for tab-separated)
+        #[arg(long, default_value = ",")]
+        delimiter: String,
+
+        /// Gzip the output (json and csv)
+        #[arg(long)]
+        gzip: bool,
+
+        /// Also upload the export to the configured object storage
+        #[arg(long)]
+        upload: bool,
+
+        /// Only export events matching this filter (e.g. "process_name ~ code")
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Only export events from this workspace (folder name)
+        #[arg(long)]
+        workspace: Option<String>,
+
+        /// Also export events recorded by `test`
+        #[arg(long)]
+        include_test_data: bool,
     },
 
     /// Configuration management
@@ -111,6 +289,10 @@ enum Commands {
 
         /// Configuration value (for set)
         value: Option<String>,
+
+        /// Reset without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Show system status
@@ -118,6 +300,14 @@ enum Commands {
         /// Show detailed information
         #[arg(short, long)]
         verbose: bool,
+
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Port the telemetry server was started on
+        #[arg(long, default_value = "8080")]
+        telemetry_port: u16,
     },
 
     /// Test monitoring components
@@ -130,6 +320,41 @@ enum Commands {
         iterations: usize,
     },
 
+    /// Benchmark the monitor's own hot paths and check them against a baseline
+    Bench {
+        /// Workloads to run: process-scan, db-insert, api-roundtrip [default: all]
+        #[arg(long)]
+        workload: Vec<String>,
+
+        /// Timed iterations per workload
+        #[arg(short, long, default_value = "50")]
+        iterations: usize,
+
+        /// Untimed iterations run first
+        #[arg(long, default_value = "5")]
+        warmup: usize,
+
+        /// Baseline file to compare against
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Save the results as a baseline file
+        #[arg(long)]
+        save_baseline: Option<PathBuf>,
+
+        /// Statistic compared with the baseline: mean, p50, p95, p99
+        #[arg(long, default_value = "p50")]
+        metric: String,
+
+        /// Percent slower than the baseline that counts as a regression
+        #[arg(long, default_value = "10")]
+        threshold: f64,
+
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Start LAN telemetry server
     Telemetry {
         /// Port to serve telemetry API
@@ -140,258 +365,1963 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
     },
-}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    /// Check what event filters and drop rules would do before relying on them
+    Filters {
+        #[command(subcommand)]
+        action: FiltersAction,
+    },
 
-    // Initialize tracing
-    init_tracing(cli.debug)?;
+    /// Manage event tags
+    Tags {
+        #[command(subcommand)]
+        action: TagsAction,
+    },
 
-    // Load configuration
-    let config = Config::load(cli.config)?;
+    /// Manage individual events
+    Events {
+        #[command(subcommand)]
+        action: EventsAction,
+    },
 
-    match cli.command {
-        Commands::Start { component, interval, daemon } => {
-            start_monitoring(&config, &component, interval, daemon).await?;
-        }
-        
-        Commands::Stop { force } => {
-            stop_monitoring(force).await?;
-        }
-        
-        Commands::Dashboard { port, realtime } => {
-            start_dashboard(&config, port, realtime).await?;
-        }
-        
-        Commands::Report { format, output, since } => {
-            generate_report(&config, &format, output, &since).await?;
-        }
-        
-        Commands::Export { format, output, since } => {
-            export_metrics(&config, &format, output, since).await?;
-        }
-        
-        Commands::Config { action, key, value } => {
-            handle_config(&action, key, value)?;
-        }
-        
-        Commands::Status { verbose } => {
-            show_status(&config, verbose).await?;
-        }
-        
-        Commands::Test { component, iterations } => {
-            run_tests(&config, component, iterations).await?;
-        }
-        
-        Commands::Telemetry { port, verbose } => {
-            start_telemetry(&config, port, verbose).await?;
-        }
-    }
+    /// Query events with a filter expression or a saved query
+    Query {
+        /// Filter expression (e.g. "component = GitHubCopilot and duration_ms > 2000")
+        #[arg(short, long, conflicts_with = "saved")]
+        filter: Option<String>,
 
-    Ok(())
-}
+        /// Name of a saved query to run
+        #[arg(short, long)]
+        saved: Option<String>,
 
-fn init_tracing(debug: bool) -> Result<()> {
-    let level = if debug { 
-        tracing::Level::DEBUG 
-    } else { 
-        tracing::Level::INFO 
-    };
+        /// Maximum number of events to return
+        #[arg(short, long)]
+        limit: Option<u32>,
 
-    tracing_subscriber::fmt()
-        .with_max_level(level)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(debug)
-        .with_line_number(debug)
-        .init();
+        /// Print events as JSON
+        #[arg(long)]
+        json: bool,
 
-    info!("VS Code Latency Monitor starting...");
-    Ok(())
-}
+        /// Query a restored archive (see `archive restore --attach`) instead of the live database
+        #[arg(long)]
+        archive: Option<String>,
+    },
 
-async fn start_monitoring(
-    config: &Config, 
-    component: &str, 
-    interval: u64, 
-    daemon: bool
-) -> Result<()> {
-    info!("Starting latency monitoring for component: {}", component);
-    
-    let storage = MetricsStorage::new(&config.storage.database_path).await?;
-    let mut monitor = LatencyMonitor::new(config.clone(), storage).await?;
+    /// Manage saved queries
+    Queries {
+        #[command(subcommand)]
+        action: QueriesAction,
+    },
 
-    match component {
-        "vscode" => {
-            monitor.start_vscode_monitoring(interval).await?;
-        }
-        "models" => {
-            monitor.start_model_monitoring(interval).await?;
-        }
-        "terminal" => {
-            monitor.start_terminal_monitoring(interval).await?;
-        }
-        "all" => {
-            monitor.start_all_monitoring(interval).await?;
-        }
-        _ => {
-            error!("Unknown component: {}", component);
-            return Err(anyhow::anyhow!("Invalid component specified"));
-        }
-    }
+    /// Pull new events from another monitor's database or dashboard URL
+    Sync {
+        /// Source database file or dashboard URL (e.g. http://laptop:8080)
+        #[arg(long)]
+        from: String,
 
-    if daemon {
-        info!("Running in daemon mode...");
-        monitor.run_daemon().await?;
-    } else {
-        info!("Running in foreground mode. Press Ctrl+C to stop.");
-        monitor.run_foreground().await?;
-    }
+        /// Only copy events at or after this time (e.g. 7d, RFC 3339 or YYYY-MM-DD)
+        #[arg(short, long)]
+        since: Option<String>,
+    },
 
-    Ok(())
-}
+    /// Import latency history recorded by other tools
+    Import {
+        #[command(subcommand)]
+        format: ImportFormat,
+    },
 
-async fn stop_monitoring(force: bool) -> Result<()> {
-    info!("Stopping latency monitoring processes...");
-    
-    // Implementation for graceful shutdown
-    if force {
-        warn!("Force stopping all monitoring processes");
-        // Kill all related processes
-    } else {
-        info!("Gracefully stopping monitoring processes");
-        // Send shutdown signals
-    }
+    /// Manage API tokens for the dashboard API
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
 
-    Ok(())
-}
+    /// Test webhooks and inspect the delivery log
+    Webhooks {
+        #[command(subcommand)]
+        action: WebhooksAction,
+    },
 
-async fn start_dashboard(config: &Config, port: u16, realtime: bool) -> Result<()> {
-    info!("Starting web dashboard on port {}", port);
-    
-    let storage = MetricsStorage::new(&config.storage.database_path).await?;
-    let dashboard = DashboardServer::new(config.clone(), storage, realtime).await?;
-    
-    dashboard.serve(port).await?;
-    
-    Ok(())
-}
+    /// Inspect and try out alert rules
+    Alerts {
+        #[command(subcommand)]
+        action: AlertsAction,
+    },
 
-async fn start_telemetry(config: &Config, port: u16, verbose: bool) -> Result<()> {
-    info!("🛰️ Starting LAN telemetry server on port {}", port);
-    
-    let storage = MetricsStorage::new(&config.storage.database_path).await?;
-    let telemetry_server = TelemetryServer::new(config.clone(), storage).await?;
-    
-    if verbose {
-        info!("Verbose telemetry logging enabled");
-    }
-    
-    info!("🌐 Telemetry server will be accessible across your LAN");
-    telemetry_server.serve(port).await?;
-    
-    Ok(())
-}
+    /// Manage archives in object storage
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
 
-async fn generate_report(
-    config: &Config,
-    format: &str,
-    output: Option<PathBuf>,
-    since: &str
-) -> Result<()> {
-    info!("Generating performance report in {} format", format);
-    
-    let storage = MetricsStorage::new(&config.storage.database_path).await?;
-    let report = storage.generate_report(since, format).await?;
-    
-    match output {
-        Some(path) => {
-            std::fs::write(path, report)?;
-            info!("Report saved to file");
-        }
-        None => {
-            println!("{}", report);
-        }
-    }
-    
-    Ok(())
-}
+    /// Delete old events now instead of waiting for the retention task
+    #[command(alias = "purge")]
+    Prune {
+        /// Delete events older than this (e.g. 90d, RFC 3339 or YYYY-MM-DD;
+        /// default: storage.retention_days)
+        #[arg(long, conflicts_with = "test_data")]
+        older_than: Option<String>,
 
-async fn export_metrics(
-    config: &Config,
-    format: &str,
-    output: Option<PathBuf>,
-    since: Option<String>
-) -> Result<()> {
-    info!("Exporting metrics in {} format", format);
-    
-    let storage = MetricsStorage::new(&config.storage.database_path).await?;
-    let data = storage.export_metrics(format, since).await?;
-    
-    match output {
-        Some(path) => {
-            std::fs::write(path, data)?;
-            info!("Metrics exported to file");
-        }
-        None => {
-            println!("{}", String::from_utf8_lossy(&data));
-        }
-    }
-    
-    Ok(())
-}
+        /// Delete every event recorded by `test` instead, whatever its age
+        #[arg(long)]
+        test_data: bool,
 
-fn handle_config(action: &str, key: Option<String>, value: Option<String>) -> Result<()> {
-    match action {
-        "get" => {
-            if let Some(k) = key {
-                // Get configuration value
-                info!("Getting config value for key: {}", k);
-            } else {
-                error!("Key required for get action");
-            }
-        }
-        "set" => {
-            if let (Some(k), Some(v)) = (key, value) {
-                // Set configuration value
-                info!("Setting config {}={}", k, v);
-            } else {
-                error!("Key and value required for set action");
-            }
-        }
-        "list" => {
-            // List all configuration
-            info!("Listing all configuration values");
+        /// Only report how many events would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Stream live events from the running monitor, like `tail -f`
+    Watch {
+        /// Only show these components (repeatable, e.g. -c copilot -c terminal)
+        #[arg(short, long)]
+        component: Vec<String>,
+
+        /// Only show events at least this slow
+        #[arg(long)]
+        min_ms: Option<f64>,
+
+        /// Print each event as a JSON line
+        #[arg(long)]
+        json: bool,
+
+        /// Disable colors (also off when stdout isn't a terminal)
+        #[arg(long)]
+        no_color: bool,
+    },
+
+    /// Full-screen live view of latency per component, recent events and
+    /// sampled processes, for terminals without the dashboard
+    Tui {
+        /// Only show these components (repeatable)
+        #[arg(long)]
+        component: Vec<String>,
+
+        /// Poll this dashboard instead of the local monitor (e.g.
+        /// http://server:8080); a token is read from VSCODE_LATENCY_MONITOR_TOKEN
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Screen refresh interval in milliseconds
+        #[arg(long, default_value = "500")]
+        refresh_ms: u64,
+    },
+
+    /// Print recent latency per component on one line, e.g.
+    /// `VSC 38ms | Copilot 790ms`, for tmux status bars and shell prompts
+    Statusline {
+        /// Only these components, in this order (repeatable)
+        #[arg(long)]
+        component: Vec<String>,
+
+        /// Seconds of recent events to summarize (at most 900)
+        #[arg(long, default_value = "300")]
+        window: u64,
+
+        /// Show p95 instead of the median
+        #[arg(long)]
+        p95: bool,
+
+        /// Mark slow components: none, ansi or tmux
+        #[arg(long, default_value = "none")]
+        color: String,
+
+        /// Monitor socket; skips loading the config for the fastest answer
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Print shell hooks that time every terminal command, for
+    /// `eval "$(vscode-latency-monitor shell-init zsh)"`
+    ShellInit {
+        /// bash, zsh or fish
+        shell: String,
+    },
+
+    /// Run a language server and time the requests VS Code sends it, e.g.
+    /// `lsp-proxy -- rust-analyzer` as the extension's server path
+    LspProxy {
+        /// Name recorded as `language_server` (default: detected from the command)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// The language server's command and arguments
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Proxy OpenAI and Anthropic API calls and time them, with an AI
+    /// extension's base URL pointed at e.g. `http://127.0.0.1:8898/openai/v1`
+    ModelProxy {
+        /// Address to listen on (default: model_proxy.listen)
+        #[arg(long)]
+        listen: Option<String>,
+    },
+
+    /// Check a hardware loopback latency tester and compare it with the software probes
+    Loopback {
+        #[command(subcommand)]
+        action: LoopbackAction,
+    },
+
+    /// Report one finished shell command (called by the shell-init hooks)
+    #[command(hide = true)]
+    ShellEvent {
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        #[arg(long)]
+        shell: String,
+
+        #[arg(long, allow_hyphen_values = true)]
+        command: String,
+
+        #[arg(long)]
+        cwd: String,
+
+        #[arg(long, allow_negative_numbers = true)]
+        exit: i32,
+
+        /// Seconds since the epoch, e.g. `$EPOCHREALTIME`
+        #[arg(long)]
+        start: Option<String>,
+
+        #[arg(long)]
+        end: Option<String>,
+
+        #[arg(long)]
+        duration_ms: Option<f64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WebhooksAction {
+    /// Send a signed test payload to a configured webhook
+    Test {
+        name: String,
+    },
+    /// Show recent delivery attempts
+    Log {
+        /// Only show deliveries to this webhook
+        #[arg(short, long)]
+        name: Option<String>,
+
+        #[arg(short, long, default_value = "20")]
+        limit: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportFormat {
+    /// JSON lines with any field names, mapped onto events with --map
+    Generic {
+        /// JSON-lines file to read; `-` reads standard input
+        file: PathBuf,
+
+        /// Event field and the record field it comes from, as TARGET=SOURCE (repeatable).
+        /// Targets: timestamp, duration_s|ms|us|ns, description, component, source, kind, metadata.<key>
+        #[arg(long = "map", value_name = "TARGET=SOURCE")]
+        map: Vec<String>,
+
+        /// Record field naming the component; free-form labels are matched by keyword
+        #[arg(long)]
+        component_from: Option<String>,
+
+        /// What a component value stands for, as VALUE=Component (repeatable)
+        #[arg(long = "component-name", value_name = "VALUE=COMPONENT")]
+        component_name: Vec<String>,
+
+        /// Component for records no field names one for [default: System]
+        #[arg(long)]
+        component: Option<String>,
+
+        /// chrono format of timestamps that aren't RFC 3339 or Unix time (e.g. "%d/%m/%Y %H:%M:%S")
+        #[arg(long)]
+        timestamp_format: Option<String>,
+
+        /// Keep the record's other fields as metadata
+        #[arg(long)]
+        keep_unmapped: bool,
+
+        /// Stop at the first line that can't be imported instead of skipping it
+        #[arg(long)]
+        strict: bool,
+
+        /// Check and count the records without storing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertsAction {
+    /// List configured alert rules
+    List,
+    /// Evaluate a rule against the events stored over its window
+    Test {
+        name: String,
+
+        /// Also run the rule's actions with a test notification
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Show recent firings and resolutions, including suppressed ones
+    History {
+        #[arg(short, long, default_value = "20")]
+        limit: u32,
+    },
+    /// Manage maintenance windows that silence alert notifications
+    Maintenance {
+        #[command(subcommand)]
+        action: MaintenanceAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceAction {
+    /// Add or replace a maintenance window
+    Add {
+        name: String,
+
+        /// When the window opens: an RFC 3339 timestamp, a date, or `now`
+        #[arg(long, default_value = "now")]
+        start: String,
+
+        /// When the window closes (RFC 3339 timestamp or date)
+        #[arg(long, conflicts_with = "duration", required_unless_present = "duration")]
+        end: Option<String>,
+
+        /// How long the window lasts, e.g. `2h`
+        #[arg(long)]
+        duration: Option<String>,
+
+        /// Repeat the window: once, daily or weekly
+        #[arg(long, default_value = "once")]
+        recurrence: String,
+
+        /// Only silence this rule; repeatable, all rules when omitted
+        #[arg(long = "rule")]
+        rules: Vec<String>,
+
+        /// Why notifications are silenced, e.g. "OS upgrade"
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// List maintenance windows and whether they are active now
+    List,
+    /// Delete a maintenance window
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Create a token; the secret is printed once and never stored
+    Create {
+        /// Token name, e.g. "vscode-extension"
+        name: String,
+
+        /// Granted scope (read:metrics, write:events, admin); repeatable
+        #[arg(short, long = "scope", required = true)]
+        scopes: Vec<String>,
+
+        /// Only reach this tenant's database (needs dashboard.tenancy)
+        #[arg(long)]
+        tenant: Option<String>,
+    },
+    /// List tokens and their scopes
+    List,
+    /// Revoke a token by name
+    Revoke {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArchiveAction {
+    /// Upload a local file (archive, backup or export) to object storage
+    Upload {
+        /// File to upload
+        path: PathBuf,
+
+        /// Object kind, which selects the key namespace (archive, backup, export)
+        #[arg(short, long, default_value = "archive")]
+        kind: String,
+    },
+    /// List archives in object storage, or restored archives with --local
+    List {
+        /// Object kind to list (archive, backup, export)
+        #[arg(short, long, default_value = "archive")]
+        kind: String,
+
+        /// List archives already restored and attached locally
+        #[arg(long)]
+        local: bool,
+    },
+    /// Download an archive; with --attach it becomes queryable read-only
+    Restore {
+        /// Object key as shown by `archive list`
+        key: String,
+
+        /// Attach the archive for `query --archive <NAME>`
+        #[arg(long)]
+        attach: bool,
+
+        /// Name to attach under (defaults to the archive's file stem)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Where to write the archive when not attaching
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueriesAction {
+    /// Save (or replace) a named query
+    Save {
+        /// Query name
+        name: String,
+
+        /// Filter expression
+        #[arg(short, long)]
+        filter: String,
+
+        /// Default result limit
+        #[arg(short, long)]
+        limit: Option<u32>,
+
+        /// Human-readable description
+        #[arg(long)]
+        description: Option<String>,
+    },
+
+    /// List saved queries
+    List,
+
+    /// Delete a saved query
+    Delete {
+        /// Query name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum EventsAction {
+    /// Delete one event (e.g. a corrupt or test event) by id
+    Delete {
+        /// Event id, as shown by `query --json` or the dashboard
+        id: i64,
+    },
+    /// Restore deleted events before they're purged
+    Restore {
+        /// Event ids to restore
+        ids: Vec<i64>,
+        /// Restore everything deleted at or after this time, e.g. `1h` or
+        /// `2024-05-01T09:00:00Z`
+        #[arg(long)]
+        deleted_since: Option<String>,
+    },
+    /// List deleted events awaiting purge, grouped by when they were deleted
+    Deleted {
+        /// Maximum number of deletions to list
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
+    /// Permanently remove deleted events past `storage.deleted_retention_days`
+    PurgeDeleted {
+        /// Purge every deleted event, however recently deleted
+        #[arg(long)]
+        all: bool,
+        /// Show how many events would be purged without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CaptureMode {
+    /// Capture two setups in turn (e.g. an extension on and off) and compare them
+    Ab {
+        /// What phase A is, e.g. "copilot on"
+        #[arg(long)]
+        a: String,
+
+        /// What phase B is, e.g. "copilot off"
+        #[arg(long)]
+        b: String,
+
+        /// How long to capture each phase (30s, 5m)
+        #[arg(long)]
+        duration: String,
+
+        /// Name for the session; events are tagged capture=<label> and capture_phase=a|b
+        #[arg(short, long, default_value = "ab")]
+        label: String,
+
+        /// Times to run A then B; more rounds even out drift over time
+        #[arg(long, default_value = "1")]
+        rounds: u32,
+
+        /// Sampling interval in milliseconds while capturing [default: 25]
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Print the comparison as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FiltersAction {
+    /// Show how many recent events each drop rule and filter would keep
+    Explain {
+        /// Number of most recent events to evaluate
+        #[arg(long, default_value = "1000")]
+        sample: u32,
+
+        /// Also preview a filter expression (repeatable; `tag.*` terms can't
+        /// be evaluated here)
+        #[arg(short, long)]
+        filter: Vec<String>,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LoopbackAction {
+    /// Print measurements as the device reports them, without recording them
+    Read {
+        /// Device to read (default: monitoring.loopback.device)
+        #[arg(long)]
+        device: Option<String>,
+
+        /// Stop after this many measurements
+        #[arg(short = 'n', long)]
+        count: Option<u64>,
+    },
+
+    /// Compare recorded hardware measurements with the software probes over a window
+    Compare {
+        /// Start of the window: a duration ago (30m, 24h, 7d), RFC 3339 or YYYY-MM-DD
+        #[arg(short, long, default_value = "1h")]
+        since: String,
+
+        /// End of the window, same syntax as --since (default: now)
+        #[arg(short, long)]
+        until: Option<String>,
+
+        /// Software events to compare against (default: monitoring.loopback.software_filter)
+        #[arg(long)]
+        software: Option<String>,
+
+        /// Print the comparison as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagsAction {
+    /// Retroactively tag historical events matching a filter
+    Apply {
+        /// Filter expression (e.g. "component = Terminal and description ~ 'cargo'")
+        #[arg(short, long)]
+        filter: String,
+
+        /// Tag to set, as key=value (repeatable)
+        #[arg(short, long = "set", value_name = "KEY=VALUE", required = true)]
+        set: Vec<String>,
+
+        /// Number of events tagged per transaction
+        #[arg(short, long, default_value = "1000")]
+        batch_size: u32,
+
+        /// Only report how many events would be tagged
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // The shell hooks read stdout (shell-init) or run on every prompt
+    // (shell-event), so neither may log there; neither may `watch --json` or
+    // `bench --json`, whose output is piped, or `lsp-proxy`, whose stdout is
+    // the LSP stream
+    if let Commands::ShellEvent {
+        socket: Some(socket),
+        shell,
+        command,
+        cwd,
+        exit,
+        start,
+        end,
+        duration_ms,
+    } = &cli.command
+    {
+        let command = shell::ShellCommand {
+            shell,
+            command,
+            cwd,
+            exit_code: *exit,
+            start: start.as_deref(),
+            end: end.as_deref(),
+            duration_ms: *duration_ms,
+        };
+        // The monitor may simply not be running; never disturb the prompt
+        let _ = shell::send_command(socket, &command);
+        return Ok(());
+    }
+    if let Commands::Statusline { socket: Some(socket), component, window, p95, color } = &cli.command {
+        return print_statusline(socket, component, *window, *p95, color);
+    }
+    if !matches!(
+        cli.command,
+        Commands::ShellInit { .. }
+            | Commands::ShellEvent { .. }
+            | Commands::Watch { json: true, .. }
+            | Commands::Bench { json: true, .. }
+            | Commands::Tui { .. }
+            | Commands::Statusline { .. }
+            | Commands::LspProxy { .. }
+            | Commands::InstallService { print: true, .. }
+    ) {
+        init_tracing(cli.debug)?;
+    }
+
+    // Load configuration
+    let config_path = cli.config.unwrap_or_else(Config::default_path);
+    let config = Config::load(&config_path)?;
+    ids::configure(config.storage.event_ids);
+
+    match cli.command {
+        Commands::Start { component, interval, daemon } => {
+            start_monitoring(&config, &config_path, &component, interval, daemon).await?;
+        }
+        
+        Commands::Capture { mode: Some(CaptureMode::Ab { a, b, duration, label, rounds, interval, json }), .. } => {
+            run_ab_capture(&config, (&a, &b), &duration, &label, rounds, interval, json).await?;
+        }
+
+        Commands::Capture { mode: None, duration, label, interval, format, output, bundle } => {
+            let (Some(duration), Some(label)) = (duration, label) else {
+                return Err(anyhow::anyhow!("capture needs --duration and --label"));
+            };
+            run_capture(&config, &duration, &label, interval, &format, output, bundle).await?;
+        }
+
+        Commands::Calibrate { duration, interval, force } => {
+            run_calibration(&config, &duration, interval, force).await?;
+        }
+
+        Commands::Stop { force } => {
+            stop_monitoring(&config, force).await?;
+        }
+
+        Commands::InstallService { component, force, no_start, print } => {
+            let manager = service::ServiceManager::detect()?;
+            if print {
+                print!("{}", service::render(manager, &config, &config_path, &component)?);
+                return Ok(());
+            }
+            let options = service::InstallOptions { component, force, start: !no_start };
+            let path = service::install(&config, &config_path, &options)?;
+            println!("Installed {} service {}", manager.as_str(), path.display());
+            if no_start {
+                println!("It starts at your next login");
+            } else {
+                println!("Monitor started; `status` shows it running");
+            }
+            if manager == service::ServiceManager::Systemd {
+                println!("To keep it running while you're logged out: loginctl enable-linger $USER");
+            }
+        }
+
+        Commands::UninstallService => match service::uninstall()? {
+            Some(path) => println!("Stopped the service and removed {}", path.display()),
+            None => println!("No service is installed"),
+        },
+        
+        Commands::Dashboard { port, realtime } => {
+            start_dashboard(&config, port, realtime).await?;
+        }
+
+        Commands::Demo { port, days, seed, database, no_live } => {
+            let options = demo::DemoOptions { days, seed, database, live: !no_live };
+            demo::run(&config, &options, port).await?;
+        }
+        
+        Commands::Report {
+            format,
+            output,
+            since,
+            until,
+            deliver,
+            working_hours,
+            filter,
+            workspace,
+            include_test_data,
+            noise,
+            include_environment,
+        } => {
+            let mut config = config.clone();
+            if let Some(noise) = noise {
+                config.reports.noise = calibration::NoiseHandling::parse(&noise)?;
+            }
+            config.reports.include_environment |= include_environment;
+            match deliver {
+                Some(schedule) => deliver_report(&config, &schedule).await?,
+                None => {
+                    let mut range = TimeRange::parse(Some(&since), until.as_deref())?;
+                    if working_hours {
+                        range = range.within_working_hours(config.working_hours.resolve()?);
+                    }
+                    if let Some(filter) = event_filter(filter.as_deref(), workspace.as_deref())? {
+                        range = range.matching(filter);
+                    }
+                    if include_test_data {
+                        range = range.with_test_data();
+                    }
+                    generate_report(&config, &format, output, &range).await?
+                }
+            }
+        }
+        
+        Commands::Acceptance { since, until, extension, json } => {
+            show_acceptance(&config, &since, until.as_deref(), extension.as_deref(), json).await?;
+        }
+
+        Commands::Export {
+            format,
+            output,
+            since,
+            until,
+            delimiter,
+            gzip,
+            upload,
+            filter,
+            workspace,
+            include_test_data,
+        } => {
+            let mut range = TimeRange::parse(since.as_deref(), until.as_deref())?;
+            if let Some(filter) = event_filter(filter.as_deref(), workspace.as_deref())? {
+                range = range.matching(filter);
+            }
+            if include_test_data {
+                range = range.with_test_data();
+            }
+            if format == "sqlite" {
+                if gzip {
+                    return Err(anyhow::anyhow!("--gzip is not supported for sqlite exports"));
+                }
+                export_sqlite(&config, output, &range, upload).await?;
+            } else {
+                export_metrics(&config, &format, output, &range, &delimiter, gzip, upload).await?;
+            }
+        }
+        
+        Commands::Config { action, key, value, yes } => {
+            handle_config(&config, &config_path, &action, key, value, yes).await?;
+        }
+        
+        Commands::Status { verbose, json, telemetry_port } => {
+            show_status(&config, verbose, json, telemetry_port).await?;
+        }
+        
+        Commands::Test { component, iterations } => {
+            run_tests(&config, component, iterations).await?;
+        }
+
+        Commands::Bench { workload, iterations, warmup, baseline, save_baseline, metric, threshold, json } => {
+            let options = bench::BenchOptions {
+                workloads: if workload.is_empty() {
+                    bench::Workload::ALL.to_vec()
+                } else {
+                    workload.iter().map(|name| bench::Workload::parse(name)).collect::<Result<_>>()?
+                },
+                iterations,
+                warmup,
+            };
+            let metric = bench::BenchMetric::parse(&metric)?;
+            let regressed =
+                run_bench(&config, &options, baseline.as_deref(), save_baseline.as_deref(), metric, threshold, json)
+                    .await?;
+            if regressed {
+                std::process::exit(1);
+            }
+        }
+        
+        Commands::Telemetry { port, verbose } => {
+            start_telemetry(&config, port, verbose).await?;
+        }
+
+        Commands::Events { action } => {
+            handle_events(&config, action).await?;
+        }
+
+        Commands::Filters { action } => {
+            handle_filters(&config, action).await?;
+        }
+
+        Commands::Tags { action } => {
+            handle_tags(&config, action).await?;
+        }
+
+        Commands::Query { filter, saved, limit, json, archive } => {
+            run_query(&config, filter, saved, limit, json, archive).await?;
+        }
+
+        Commands::Queries { action } => {
+            handle_queries(&config, action).await?;
+        }
+
+        Commands::Import { format } => {
+            run_import(&config, format).await?;
+        }
+
+        Commands::Sync { from, since } => {
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            let since = since
+                .as_deref()
+                .map(|s| timerange::parse_time_expr(s, chrono::Utc::now()))
+                .transpose()?;
+            let progress = Progress::rows("Syncing", None);
+            let stats = sync::sync_from(&storage, &from, since, &progress).await?;
+            progress.finish();
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "sync.run", Some(&from)).with_change(
+                        None,
+                        Some(serde_json::json!({
+                            "fetched": stats.fetched,
+                            "inserted": stats.inserted,
+                            "complete": !progress.is_cancelled(),
+                        })),
+                    ),
+                )
+                .await?;
+            println!("Synced {} new events ({} fetched) from {}", stats.inserted, stats.fetched, from);
+            if progress.is_cancelled() {
+                println!("Cancelled; run sync again to continue from here");
+            }
+        }
+
+        Commands::Webhooks { action } => {
+            handle_webhooks(&config, action).await?;
+        }
+
+        Commands::Token { action } => {
+            handle_token(&config, action).await?;
+        }
+
+        Commands::Alerts { action } => {
+            handle_alerts(&config, action).await?;
+        }
+
+        Commands::Archive { action } => {
+            handle_archive(&config, action).await?;
+        }
+
+        Commands::Prune { test_data: true, dry_run, .. } => {
+            purge_test_data(&config, dry_run).await?;
+        }
+
+        Commands::Prune { older_than, test_data: false, dry_run } => {
+            prune_events(&config, older_than.as_deref(), dry_run).await?;
+        }
+
+        Commands::Watch { component, min_ms, json, no_color } => {
+            if !config.ipc.enabled {
+                return Err(anyhow::anyhow!("watch needs the IPC socket; set ipc.enabled = true"));
+            }
+            let options = watch::WatchOptions {
+                min_duration_ms: min_ms,
+                json,
+                color: !no_color,
+                ..Default::default()
+            }
+            .with_components(&component)?;
+            watch::watch(&ipc::socket_path(&config), options).await?;
+        }
+
+        Commands::Tui { component, url, refresh_ms } => {
+            let source = match url {
+                Some(base_url) => tui::TuiSource::Api {
+                    base_url,
+                    token: std::env::var("VSCODE_LATENCY_MONITOR_TOKEN").ok(),
+                },
+                None if config.ipc.enabled => tui::TuiSource::Ipc(ipc::socket_path(&config)),
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "tui needs the IPC socket (set ipc.enabled = true) or a dashboard --url"
+                    ))
+                }
+            };
+            let options = tui::TuiOptions {
+                components: watch::WatchOptions::default().with_components(&component)?.components,
+                refresh: std::time::Duration::from_millis(refresh_ms.max(50)),
+            };
+            tui::run(source, options).await?;
+        }
+
+        Commands::LspProxy { name, command } => {
+            let code = lsp::run_proxy(&ipc::socket_path(&config), name, &command).await?;
+            std::process::exit(code);
+        }
+
+        Commands::ModelProxy { listen } => {
+            let mut proxy = config.model_proxy.clone();
+            if let Some(listen) = listen {
+                proxy.listen = listen;
+            }
+            model_proxy::run(&ipc::socket_path(&config), proxy).await?;
+        }
+
+        Commands::Loopback { action } => {
+            handle_loopback(&config, action).await?;
+        }
+
+        Commands::ShellInit { shell } => {
+            let binary = std::env::current_exe()?;
+            print!("{}", shell::init_script(&shell, &binary, &ipc::socket_path(&config))?);
+        }
+
+        Commands::Statusline { component, window, p95, color, .. } => {
+            print_statusline(&ipc::socket_path(&config), &component, window, p95, &color)?;
+        }
+
+        Commands::ShellEvent { shell, command, cwd, exit, start, end, duration_ms, .. } => {
+            let command = shell::ShellCommand {
+                shell: &shell,
+                command: &command,
+                cwd: &cwd,
+                exit_code: exit,
+                start: start.as_deref(),
+                end: end.as_deref(),
+                duration_ms,
+            };
+            let _ = shell::send_command(&ipc::socket_path(&config), &command);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_statusline(socket: &std::path::Path, component: &[String], window: u64, p95: bool, color: &str) -> Result<()> {
+    let options = statusline::StatuslineOptions {
+        components: component
+            .iter()
+            .map(|name| {
+                ComponentType::parse(name).ok_or_else(|| anyhow::anyhow!("Unknown component '{}'", name))
+            })
+            .collect::<Result<_>>()?,
+        window_secs: window,
+        p95,
+        color: statusline::StatusColor::parse(color)?,
+    };
+    // A monitor that isn't running leaves the status line empty rather
+    // than filling the prompt with errors
+    if let Ok(line) = statusline::status_line(socket, &options) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn init_tracing(debug: bool) -> Result<()> {
+    let level = if debug { 
+        tracing::Level::DEBUG 
+    } else { 
+        tracing::Level::INFO 
+    };
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_file(debug)
+        .with_line_number(debug)
+        .init();
+
+    info!("VS Code Latency Monitor starting...");
+    Ok(())
+}
+
+async fn start_monitoring(
+    config: &Config, 
+    config_path: &std::path::Path,
+    component: &str, 
+    interval: Option<u64>, 
+    daemon: bool
+) -> Result<()> {
+    if daemon && !daemon::is_daemonized() {
+        let pid = daemon::spawn_detached(config)?;
+        println!(
+            "Monitor daemon started with PID {} (log: {})",
+            pid,
+            daemon::log_file_path(config).display()
+        );
+        return Ok(());
+    }
+
+    info!("Starting latency monitoring for component: {}", component);
+    scheduling::apply(&config.monitoring.scheduling);
+    
+    // Stopped with the collectors, before the monitor's final flush
+    let shutdown = Shutdown::default();
+    let mut storage = MetricsStorage::new(&config.storage.database_path)
+        .await?
+        .with_latency_histograms(&config.histograms);
+    if config.monitoring.connectivity.enabled {
+        let tracker = ConnectivityTracker::new(&config.monitoring.connectivity);
+        shutdown.spawn(tracker.clone().run());
+        storage = storage.with_connectivity(&tracker);
+    }
+    let storage = storage.with_write_buffer(&config.storage.write_buffer);
+    shutdown.spawn(run_retention(
+        storage.clone(),
+        config.storage.retention_days,
+        config.storage.deleted_retention_days,
+    ));
+    shutdown.spawn(run_summaries(storage.clone()));
+    if config.integrations.otel.enabled {
+        shutdown.spawn(otel::run_exporter(config.integrations.otel.clone(), storage.clone()));
+    }
+    if !config.webhooks.is_empty() {
+        shutdown.spawn(webhook::run_dispatcher(config.webhooks.clone(), storage.clone()));
+    }
+    let (config_updates, _) = tokio::sync::watch::channel(config.clone());
+    shutdown.spawn(reload::run_config_reload(
+        config_path.to_path_buf(),
+        config_updates.clone(),
+        storage.clone(),
+    ));
+    let notifier = config
+        .integrations
+        .wall_notification_system
+        .then(|| notifications::Notifier::new(&config.integrations.notifications));
+    if let Some(notifier) = &notifier {
+        shutdown.spawn(notifier.clone().watch_thresholds(storage.subscribe()));
+        if config.integrations.notifications.port != 0 {
+            let notifier = notifier.clone();
+            shutdown.spawn(async move {
+                if let Err(e) = notifier.serve().await {
+                    warn!("Notification history server stopped: {}", e);
+                }
+            });
+        }
+    }
+    // Always running, so rules added by a config reload take effect
+    shutdown.spawn(alerts::run_alerts(config_updates.subscribe(), storage.clone(), notifier));
+    shutdown.spawn(report::run_schedules(config_updates.subscribe(), storage.clone()));
+    if !config.derived_metrics.expressions.is_empty() {
+        shutdown.spawn(derived::run_derived_metrics(config.derived_metrics.clone(), storage.clone()));
+    }
+    let workspaces = Workspaces::default();
+    if config.ipc.enabled {
+        let (config, storage, workspaces) = (config.clone(), storage.clone(), workspaces.clone());
+        shutdown.spawn(async move {
+            if let Err(e) = ipc::serve(config, storage, workspaces).await {
+                warn!("Extension IPC server stopped: {}", e);
+            }
+        });
+    }
+    if config.storage.backend != StorageBackend::Sqlite {
+        let central = backend::open(&config.storage, storage.clone(), &Secrets::load()?);
+        shutdown.spawn(backend::run_replication(
+            storage.clone(),
+            central,
+            std::time::Duration::from_secs(config.storage.postgres.replicate_interval_secs.max(1)),
+        ));
+    }
+    let mut monitor = LatencyMonitor::new(config.clone(), storage).await?;
+    monitor.follow_config(&config_updates);
+    monitor.follow_workspaces(&workspaces);
+    monitor.share_shutdown(&shutdown);
+
+    match component {
+        "vscode" => {
+            monitor.start_vscode_monitoring(interval).await?;
+        }
+        "models" => {
+            monitor.start_model_monitoring(interval).await?;
+        }
+        "terminal" => {
+            monitor.start_terminal_monitoring(interval).await?;
+        }
+        "lsp" => {
+            monitor.start_language_server_monitoring(interval).await?;
+        }
+        "disk" => {
+            monitor.start_disk_monitoring(interval).await?;
+        }
+        "network" => {
+            monitor.start_network_monitoring();
+        }
+        "editors" => {
+            monitor.start_editor_monitoring(interval).await?;
+        }
+        "all" => {
+            monitor.start_all_monitoring(interval).await?;
+        }
+        _ => {
+            error!("Unknown component: {}", component);
+            return Err(anyhow::anyhow!("Invalid component specified"));
+        }
+    }
+
+    monitor.start_self_monitoring();
+    monitor.start_load_watchdog();
+
+    if daemon {
+        info!("Running in daemon mode...");
+        let _pid_file = daemon::PidFile::create(config)?;
+        let summary = monitor.run_daemon().await?;
+        print!("{}", summary);
+    } else {
+        info!("Running in foreground mode. Press Ctrl+C to stop.");
+        let summary = monitor.run_foreground().await?;
+        print!("{}", summary);
+    }
+
+    Ok(())
+}
+
+async fn run_capture(
+    config: &Config,
+    duration: &str,
+    label: &str,
+    interval: Option<u64>,
+    format: &str,
+    output: Option<PathBuf>,
+    bundle: bool,
+) -> Result<()> {
+    let length = parse_capture_duration(duration)?;
+    capture::validate_label(label)?;
+
+    println!("Capturing '{}' for {}; press Ctrl+C to stop early", label, duration);
+    let interval = interval.unwrap_or(capture::CAPTURE_INTERVAL_MS).max(1);
+    let (storage, session) = capture::run(config, label, length, interval).await?;
+    // A bundled report records what it was produced with
+    let mut report_config = config.clone();
+    report_config.reports.include_environment |= bundle;
+    let report = capture::report(&storage, &report_config, &session, format).await?;
+
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.{}", session.file_stem(), format)));
+    std::fs::write(&output, &report)?;
+    println!("Captured {} events; report written to {}", session.events, output.display());
+
+    if bundle {
+        let dir = PathBuf::from(session.file_stem());
+        capture::write_bundle(&storage, config, &session, &report, format, &dir).await?;
+        println!("Incident bundle written to {}", dir.display());
+    }
+    Ok(())
+}
+
+async fn run_ab_capture(
+    config: &Config,
+    setups: (&str, &str),
+    duration: &str,
+    label: &str,
+    rounds: u32,
+    interval: Option<u64>,
+    json: bool,
+) -> Result<()> {
+    let length = parse_capture_duration(duration)?;
+    let interval = interval.unwrap_or(capture::CAPTURE_INTERVAL_MS).max(1);
+    let (_, session) = capture::run_ab(config, label, setups, length, rounds, interval).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&session)?);
+        return Ok(());
+    }
+
+    println!();
+    println!("A: {}    B: {}", session.a, session.b);
+    println!(
+        "{:<16} {:>8} {:>10} {:>8} {:>10} {:>9} {:>9}  Verdict",
+        "Component", "A events", "A p50", "B events", "B p50", "Change", "p-value"
+    );
+    for comparison in &session.comparisons {
+        println!(
+            "{:<16} {:>8} {:>10} {:>8} {:>10} {:>9} {:>9}  {}",
+            comparison.component,
+            comparison.a.events,
+            units::format_ms(comparison.a.p50_ms),
+            comparison.b.events,
+            units::format_ms(comparison.b.p50_ms),
+            comparison
+                .p50_change_percent
+                .map(|change| format!("{:+.1}%", change))
+                .unwrap_or_else(|| "-".to_string()),
+            comparison
+                .test
+                .map(|test| format!("{:.4}", test.p_value))
+                .unwrap_or_else(|| "-".to_string()),
+            comparison.verdict()
+        );
+    }
+    if session.stopped_early {
+        println!("Stopped early; only the phases captured so far are compared");
+    }
+    Ok(())
+}
+
+fn parse_capture_duration(duration: &str) -> Result<chrono::Duration> {
+    timerange::parse_duration(duration)
+        .filter(|d| *d > chrono::Duration::zero())
+        .ok_or_else(|| anyhow::anyhow!("Invalid --duration '{}', expected e.g. 30s, 10m or 1h", duration))
+}
+
+async fn run_calibration(config: &Config, duration: &str, interval: Option<u64>, force: bool) -> Result<()> {
+    let length = parse_capture_duration(duration)?;
+    let running = calibration::running_vscode_processes();
+    if running > 0 && !force {
+        return Err(anyhow::anyhow!(
+            "{} VS Code processes are running; close VS Code so only background noise is measured, or pass --force",
+            running
+        ));
+    }
+    if daemon::running_pid(config).is_some() {
+        warn!("The monitor daemon is running; its own sampling will show up in the profile");
+    }
+
+    let interval = interval.unwrap_or(config.monitoring.interval_ms);
+    println!("Measuring idle noise every {} ms for {}...", interval, duration);
+    let profile = calibration::measure(interval, length.to_std()?).await?;
+
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    storage.save_noise_profile(&profile).await?;
+    storage
+        .record_audit(
+            &AuditEntry::new(&AuditEntry::cli_actor(), "calibration.run", None)
+                .with_change(None, Some(serde_json::to_value(&profile)?)),
+        )
+        .await?;
+
+    println!("Idle noise over {} sampling passes:", profile.samples);
+    println!("  mean    {} (stddev {})", units::format_ms(profile.mean_ms), units::format_ms(profile.stddev_ms));
+    println!(
+        "  p50     {}   p95 {}   p99 {}",
+        units::format_ms(profile.p50_ms),
+        units::format_ms(profile.p95_ms),
+        units::format_ms(profile.p99_ms)
+    );
+    println!("  timer   {} late at p95", units::format_ms(profile.timer_jitter_p95_ms));
+    println!(
+        "Latency changes under {} are within noise; set reports.noise = \"flag\" or \"subtract\" to use this profile",
+        units::format_ms(profile.band_ms())
+    );
+    Ok(())
+}
+
+async fn handle_loopback(config: &Config, action: LoopbackAction) -> Result<()> {
+    let mut loopback = config.monitoring.loopback.clone();
+    match action {
+        LoopbackAction::Read { device, count } => {
+            if let Some(device) = device {
+                loopback.device = device;
+            }
+            if loopback.device.is_empty() {
+                return Err(anyhow::anyhow!("No device given; pass --device or set monitoring.loopback.device"));
+            }
+
+            println!("Reading {} (Ctrl+C to stop)", loopback.device);
+            loopback::spawn_triggers(&loopback);
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            let reader = loopback::read_device(&loopback, move |sample| {
+                let _ = tx.send(sample);
+            });
+            tokio::pin!(reader);
+
+            let mut seen = 0;
+            loop {
+                tokio::select! {
+                    result = &mut reader => return result,
+                    Some(sample) = rx.recv() => {
+                        seen += 1;
+                        let seq = sample.seq.map(|s| format!("#{} ", s)).unwrap_or_default();
+                        println!("{}{}", seq, units::format_ms(sample.latency_us as f64 / 1000.0));
+                        if count.is_some_and(|count| seen >= count) {
+                            break;
+                        }
+                    }
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+        }
+
+        LoopbackAction::Compare { since, until, software, json } => {
+            if let Some(software) = software {
+                loopback.software_filter = software;
+            }
+            let range = TimeRange::parse(Some(&since), until.as_deref())?;
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            let comparison = loopback::compare(&storage, &loopback, &range).await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+                return Ok(());
+            }
+            if comparison.hardware.events == 0 {
+                println!("No loopback measurements in {}", comparison.range);
+                return Ok(());
+            }
+
+            println!("Loopback vs software probes ({}), software: {}", comparison.range, comparison.software_filter);
+            println!("{:<10} {:>8} {:>10} {:>10} {:>10}", "", "EVENTS", "P50", "P95", "P99");
+            for (name, summary) in [
+                ("hardware", &comparison.hardware),
+                ("software", &comparison.software),
+                ("unseen", &comparison.unseen),
+            ] {
+                println!(
+                    "{:<10} {:>8} {:>10} {:>10} {:>10}",
+                    name,
+                    summary.events,
+                    units::format_ms(summary.p50_ms),
+                    units::format_ms(summary.p95_ms),
+                    units::format_ms(summary.p99_ms)
+                );
+            }
+            println!(
+                "Matched {} of {} hardware measurements to a software event",
+                comparison.matched, comparison.hardware.events
+            );
+            if let Some(share) = comparison.software_share_p50 {
+                println!("Software probes see {:.0}% of end-to-end input latency (median)", share * 100.0);
+            }
+            if comparison.software_longer > 0 {
+                println!(
+                    "{} pairs had software latency above the hardware measurement; check the device's clock and the software filter",
+                    comparison.software_longer
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn stop_monitoring(config: &Config, force: bool) -> Result<()> {
+    info!("Stopping latency monitoring processes...");
+    
+    if force {
+        warn!("Force stopping monitor daemon (pending events will be lost)");
+    } else {
+        info!("Gracefully stopping monitor daemon");
+    }
+
+    match daemon::stop_daemon(config, force).await? {
+        Some(pid) => println!("Stopped monitor daemon (PID {})", pid),
+        None => println!("No monitor daemon is running"),
+    }
+
+    Ok(())
+}
+
+async fn start_dashboard(config: &Config, port: u16, realtime: bool) -> Result<()> {
+    info!("Starting web dashboard on port {}", port);
+    
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let dashboard = DashboardServer::new(config.clone(), storage, realtime).await?;
+    
+    dashboard.serve(port).await?;
+    
+    Ok(())
+}
+
+async fn start_telemetry(config: &Config, port: u16, verbose: bool) -> Result<()> {
+    info!("🛰️ Starting LAN telemetry server on port {}", port);
+    
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let telemetry_server = TelemetryServer::new(config.clone(), storage).await?;
+    
+    if verbose {
+        info!("Verbose telemetry logging enabled");
+    }
+    
+    info!("🌐 Telemetry server will be accessible across your LAN");
+    telemetry_server.serve(port).await?;
+    
+    Ok(())
+}
+
+/// `--filter` and `--workspace` of `report` and `export`, as one filter.
+fn event_filter(filter: Option<&str>, workspace: Option<&str>) -> Result<Option<EventFilter>> {
+    let filter = filter.map(EventFilter::parse).transpose()?;
+    let workspace = workspace.map(|name| EventFilter::equals("workspace", name)).transpose()?;
+    Ok(match (filter, workspace) {
+        (Some(filter), Some(workspace)) => Some(filter.and(&workspace)),
+        (filter, workspace) => filter.or(workspace),
+    })
+}
+
+async fn generate_report(
+    config: &Config,
+    format: &str,
+    output: Option<PathBuf>,
+    range: &TimeRange,
+) -> Result<()> {
+    info!("Generating performance report in {} format", format);
+    
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let report = report::generate_report(&storage, config, range, format).await?;
+    
+    match output {
+        Some(path) => {
+            std::fs::write(path, report)?;
+            info!("Report saved to file");
+        }
+        None => {
+            println!("{}", report);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn deliver_report(config: &Config, schedule_name: &str) -> Result<()> {
+    let schedule = config
+        .schedules
+        .iter()
+        .find(|s| s.name == schedule_name)
+        .ok_or_else(|| anyhow::anyhow!("No schedule named '{}' in config", schedule_name))?;
+
+    info!("Delivering report for schedule '{}'", schedule.name);
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    report::deliver_report(&storage, config, schedule).await
+}
+
+async fn show_acceptance(
+    config: &Config,
+    since: &str,
+    until: Option<&str>,
+    extension: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let range = TimeRange::parse(Some(since), until)?;
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let report = suggestions::acceptance_report(&storage, range.since, range.until, extension).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.total.shown == 0 && report.total.accepted == 0 {
+        println!("No suggestions recorded; the companion extension reports them over IPC");
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
+
+/// JSON and CSV exports are streamed row by row to the output file, stdout,
+/// or (with `--upload` and no file) straight into a multipart upload, so
+/// memory use doesn't grow with the number of events.
+async fn export_metrics(
+    config: &Config,
+    format: &str,
+    output: Option<PathBuf>,
+    range: &TimeRange,
+    delimiter: &str,
+    gzip: bool,
+    upload: bool,
+) -> Result<()> {
+    let delimiter = match delimiter {
+        "tab" | "\\t" => b'\t',
+        d if d.len() == 1 && d.is_ascii() => d.as_bytes()[0],
+        d => return Err(anyhow::anyhow!("Delimiter must be a single ASCII character or 'tab', got '{}'", d)),
+    };
+    if !matches!(format, "json" | "csv" | "parquet") {
+        return Err(anyhow::anyhow!("Unsupported export format: {}", format));
+    }
+    if gzip && format == "parquet" {
+        return Err(anyhow::anyhow!("--gzip is not supported for parquet exports, which are compressed already"));
+    }
+
+    info!("Exporting metrics in {} format", format);
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let extension = if gzip { format!("{}.gz", format) } else { format.to_string() };
+    let progress = Progress::rows("Exporting", Some(storage.count_matching(&range.to_sql()).await?));
+
+    let rows = match &output {
+        Some(path) => {
+            let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            match write_export(&storage, format, file, range, delimiter, gzip, &progress).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    // Don't leave a truncated export looking like a finished one
+                    let _ = std::fs::remove_file(path);
+                    return Err(e);
+                }
+            }
+        }
+        None if upload => {
+            let store = ObjectStore::new(&config.object_storage, &Secrets::load()?)?;
+            let key = export_object_key(&store, &extension);
+            let mut writer = store.multipart_writer(&key, export_content_type(&extension)).await?;
+            let rows = match write_export(&storage, format, &mut writer, range, delimiter, gzip, &progress).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    writer.abort().await;
+                    return Err(e);
+                }
+            };
+            writer.complete().await?;
+            progress.finish();
+            info!("Exported {} events", rows);
+            println!("Uploaded export to {}", key);
+            return Ok(());
+        }
+        None => {
+            let stdout = std::io::BufWriter::new(std::io::stdout());
+            write_export(&storage, format, stdout, range, delimiter, gzip, &progress).await?
+        }
+    };
+    progress.finish();
+    info!("Exported {} events", rows);
+
+    if let (true, Some(path)) = (upload, output) {
+        upload_export(config, &extension, &path).await?;
+    }
+    Ok(())
+}
+
+/// Serialize `range` as `format` into `writer`, through a gzip encoder if
+/// requested, and flush it. Returns the number of rows.
+async fn write_export<W: std::io::Write + Send>(
+    storage: &MetricsStorage,
+    format: &str,
+    writer: W,
+    range: &TimeRange,
+    delimiter: u8,
+    gzip: bool,
+    progress: &Progress,
+) -> Result<u64> {
+    if gzip {
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        let rows = write_export_rows(storage, format, &mut encoder, range, delimiter, progress).await?;
+        encoder.finish()?.flush()?;
+        Ok(rows)
+    } else {
+        let mut writer = writer;
+        let rows = write_export_rows(storage, format, &mut writer, range, delimiter, progress).await?;
+        writer.flush()?;
+        Ok(rows)
+    }
+}
+
+async fn write_export_rows<W: std::io::Write + Send>(
+    storage: &MetricsStorage,
+    format: &str,
+    writer: W,
+    range: &TimeRange,
+    delimiter: u8,
+    progress: &Progress,
+) -> Result<u64> {
+    match format {
+        "json" => storage.export_json(writer, range, progress).await,
+        "csv" => storage.export_csv(writer, range, delimiter, progress).await,
+        "parquet" => storage.export_parquet(writer, range, progress).await,
+        _ => Err(anyhow::anyhow!("Unsupported export format: {}", format)),
+    }
+}
+
+/// SQLite exports are written as a snapshot file, defaulting to a
+/// timestamped name in the current directory.
+async fn export_sqlite(
+    config: &Config,
+    output: Option<PathBuf>,
+    range: &TimeRange,
+    upload: bool,
+) -> Result<()> {
+    info!("Exporting metrics in sqlite format");
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    let path = output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "latency-export-{}.db",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+        ))
+    });
+    let progress = Progress::rows("Writing snapshot", None);
+    let rows = storage.export_sqlite(&path, range, &progress).await?;
+    progress.finish();
+    println!("Exported {} events to {}", rows, path.display());
+
+    if upload {
+        upload_export(config, "sqlite", &path).await?;
+    }
+    Ok(())
+}
+
+/// Upload an export file already written to disk, streaming it in parts.
+async fn upload_export(config: &Config, extension: &str, path: &std::path::Path) -> Result<()> {
+    let store = ObjectStore::new(&config.object_storage, &Secrets::load()?)?;
+    let key = export_object_key(&store, extension);
+    let progress = Progress::bytes("Uploading", None);
+    store
+        .upload_file(&key, path, export_content_type(extension), &progress)
+        .await?;
+    progress.finish();
+    println!("Uploaded export to {}", key);
+    Ok(())
+}
+
+fn export_object_key(store: &ObjectStore, extension: &str) -> String {
+    let now = chrono::Utc::now();
+    let filename = format!("latency-export-{}.{}", now.format("%Y%m%dT%H%M%SZ"), extension);
+    store.object_key(ObjectKind::Export, &filename, now)
+}
+
+async fn handle_config(
+    config: &Config,
+    config_path: &std::path::Path,
+    action: &str,
+    key: Option<String>,
+    value: Option<String>,
+    yes: bool,
+) -> Result<()> {
+    match action {
+        "get" => {
+            let key = key.ok_or_else(|| anyhow::anyhow!("Key required for get action"))?;
+            match config.get(&key)? {
+                toml::Value::String(text) => println!("{}", text),
+                value => println!("{}", value),
+            }
+        }
+        "set" => {
+            let (Some(key), Some(value)) = (key, value) else {
+                return Err(anyhow::anyhow!("Key and value required for set action"));
+            };
+
+            let mut updated = config.clone();
+            let previous = updated.set(&key, &value)?;
+            updated.save(config_path)?;
+            let current = updated.get(&key)?;
+            println!("{} = {}", key, current);
+
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "config.set", Some(&key)).with_change(
+                        previous.map(serde_json::to_value).transpose()?,
+                        Some(serde_json::to_value(current)?),
+                    ),
+                )
+                .await?;
+        }
+        "list" => {
+            for (key, value) in config.entries()? {
+                println!("{} = {}", key, value);
+            }
         }
         "reset" => {
-            // Reset configuration to defaults
-            warn!("Resetting configuration to defaults");
+            if !yes && !confirm(&format!("Reset {} to defaults?", config_path.display()))? {
+                println!("Aborted");
+                return Ok(());
+            }
+
+            Config::default().save(config_path)?;
+            println!("Reset {} to defaults", config_path.display());
+
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            storage
+                .record_audit(&AuditEntry::new(&AuditEntry::cli_actor(), "config.reset", None).with_change(
+                    Some(serde_json::to_value(config)?),
+                    Some(serde_json::to_value(Config::default())?),
+                ))
+                .await?;
         }
         _ => {
-            error!("Unknown config action: {}", action);
+            return Err(anyhow::anyhow!("Unknown config action: {} (get, set, list, reset)", action));
         }
     }
-    
+
     Ok(())
 }
 
-async fn show_status(config: &Config, verbose: bool) -> Result<()> {
+/// Ask a yes/no question on the terminal; anything but `y`/`yes` is no.
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+async fn show_status(config: &Config, verbose: bool, json: bool, telemetry_port: u16) -> Result<()> {
     info!("Showing system status...");
     
     let storage = MetricsStorage::new(&config.storage.database_path).await?;
     let status = storage.get_system_status().await?;
+    let monitor = monitor_status::collect(config, &storage, telemetry_port).await?;
+
+    if json {
+        let mut value = serde_json::to_value(&monitor)?;
+        value["summary"] = serde_json::json!(status.summary);
+        value["total_events"] = serde_json::json!(status.total_events);
+        value["lifecycle"] = serde_json::to_value(&status.lifecycle)?;
+        value["load_shedding"] = serde_json::to_value(&status.load_shedding)?;
+        if verbose {
+            value["performance_metrics"] = serde_json::to_value(&status.performance_metrics)?;
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    if let Some(load) = &status.load_shedding {
+        eprintln!("⚠️  DEGRADED MODE: the monitor is shedding load to protect this machine");
+        eprintln!(
+            "    queue depth {}, sampling {}x slower, dropping {:?}; {} events dropped{}",
+            load.queue_depth,
+            load.interval_multiplier,
+            load.shedding,
+            load.dropped_total,
+            load.degraded_since
+                .map(|t| format!(" since {}", t.format("%Y-%m-%d %H:%M:%S UTC")))
+                .unwrap_or_default()
+        );
+    }
     
     if verbose {
         println!("Detailed System Status:\n{:#?}", status);
     } else {
         println!("System Status: {}", status.summary);
+        println!(
+            "Extension host (24h): {} restarts, {} crashes, {} renderer reloads",
+            status.lifecycle.extension_host_restarts,
+            status.lifecycle.extension_host_crashes,
+            status.lifecycle.renderer_reloads
+        );
     }
+    print_monitor_status(&monitor);
     
     Ok(())
 }
 
+fn print_monitor_status(monitor: &monitor_status::MonitorStatus) {
+    match (monitor.daemon.pid, monitor.daemon.stale_pid_file) {
+        (Some(pid), _) => println!("Daemon: running (PID {})", pid),
+        (None, true) => println!("Daemon: not running (stale PID file {})", monitor.daemon.pid_file.display()),
+        (None, false) => println!("Daemon: not running"),
+    }
+    if let Some(service) = monitor.service.as_ref().filter(|service| service.installed) {
+        println!(
+            "Service: {} {} ({}, {})",
+            service.manager.as_str(),
+            service.unit_path.display(),
+            if service.enabled { "enabled" } else { "disabled" },
+            if service.active { "active" } else { "inactive" }
+        );
+    }
+
+    if monitor.active_components.is_empty() {
+        println!("Active components (5m): none");
+    } else {
+        let active: Vec<String> = monitor
+            .active_components
+            .iter()
+            .map(|active| format!("{} ({})", active.component, active.events))
+            .collect();
+        println!("Active components (5m): {}", active.join(", "));
+    }
+    println!("Enabled monitors: {}", monitor.enabled_monitors.join(", "));
+
+    println!(
+        "Ingest ({}s): {} events, {:.2}/s{}",
+        monitor.ingest.window_secs,
+        monitor.ingest.events,
+        monitor.ingest.events_per_sec,
+        monitor
+            .ingest
+            .last_event
+            .map(|t| format!(", last at {}", t.format("%Y-%m-%d %H:%M:%S UTC")))
+            .unwrap_or_default()
+    );
+    println!(
+        "Database: {:.1} MiB ({:.1} MiB WAL) at {}",
+        monitor.database.total_bytes as f64 / (1024.0 * 1024.0),
+        monitor.database.wal_bytes as f64 / (1024.0 * 1024.0),
+        monitor.database.path.display()
+    );
+
+    for (name, server) in [("Dashboard", &monitor.dashboard), ("Telemetry", &monitor.telemetry)] {
+        if server.reachable {
+            println!("{}: reachable at {}", name, server.url);
+        } else {
+            println!("{}: unreachable at {}", name, server.url);
+        }
+    }
+}
+
+async fn run_import(config: &Config, format: ImportFormat) -> Result<()> {
+    let ImportFormat::Generic {
+        file,
+        map,
+        component_from,
+        component_name,
+        component,
+        timestamp_format,
+        keep_unmapped,
+        strict,
+        dry_run,
+    } = format;
+    let mapping = importer::GenericMapping::new(&importer::GenericOptions {
+        maps: map,
+        component_from,
+        component_names: component_name,
+        component,
+        timestamp_format,
+        keep_unmapped,
+    })?;
+
+    let storage = if dry_run { None } else { Some(MetricsStorage::new(&config.storage.database_path).await?) };
+    let origin = file.display().to_string();
+    let (stats, progress) = if file.as_os_str() == "-" {
+        let progress = Progress::bytes("Importing", None);
+        let stdin = std::io::stdin().lock();
+        (importer::import_generic(storage.as_ref(), stdin, &origin, &mapping, strict, &progress).await?, progress)
+    } else {
+        let reader = std::fs::File::open(&file).map_err(|e| anyhow::anyhow!("Can't read {}: {}", origin, e))?;
+        let progress = Progress::bytes("Importing", Some(reader.metadata()?.len()));
+        let reader = std::io::BufReader::new(reader);
+        (importer::import_generic(storage.as_ref(), reader, &origin, &mapping, strict, &progress).await?, progress)
+    };
+    progress.finish();
+
+    if let Some(storage) = &storage {
+        storage
+            .record_audit(
+                &AuditEntry::new(&AuditEntry::cli_actor(), "import.generic", Some(&origin)).with_change(
+                    None,
+                    Some(serde_json::json!({
+                        "events": stats.events,
+                        "inserted": stats.inserted,
+                        "skipped": stats.skipped,
+                        "complete": !progress.is_cancelled(),
+                    })),
+                ),
+            )
+            .await?;
+    }
+
+    match &storage {
+        Some(_) => println!(
+            "Imported {} new events ({} read, {} already present) from {}",
+            stats.inserted,
+            stats.events,
+            stats.events - stats.inserted,
+            origin
+        ),
+        None => println!("Dry run: {} of {} records would be imported from {}", stats.events, stats.lines, origin),
+    }
+    if let (Some(first), Some(last)) = (stats.first, stats.last) {
+        println!("  Time span: {} to {}", first.to_rfc3339(), last.to_rfc3339());
+    }
+    for (component, count) in &stats.components {
+        println!("  {:<16} {}", component, count);
+    }
+    if stats.skipped > 0 {
+        println!("Skipped {} lines that couldn't be imported:", stats.skipped);
+        for error in &stats.errors {
+            println!("  {}", error);
+        }
+        if stats.skipped as usize > stats.errors.len() {
+            println!("  ...");
+        }
+    }
+    if progress.is_cancelled() {
+        println!("Cancelled; importing the file again skips what is already stored");
+    }
+    Ok(())
+}
+
+/// Run the benchmarks and report them, returning whether any workload
+/// regressed against `baseline`.
+async fn run_bench(
+    config: &Config,
+    options: &bench::BenchOptions,
+    baseline: Option<&std::path::Path>,
+    save_baseline: Option<&std::path::Path>,
+    metric: bench::BenchMetric,
+    threshold: f64,
+    json: bool,
+) -> Result<bool> {
+    // Load first, so a bad path fails before the benchmarks run
+    let baseline = baseline.map(bench::Baseline::load).transpose()?;
+    let results = bench::run(config, options).await?;
+    let comparison = baseline
+        .as_ref()
+        .map(|baseline| bench::compare(baseline, &results, metric, threshold))
+        .unwrap_or_default();
+    let regressed = comparison.iter().any(|compared| compared.regressed);
+
+    if let Some(path) = save_baseline {
+        bench::Baseline::new(results.clone()).save(path)?;
+        if !json {
+            println!("Saved baseline to {}", path.display());
+        }
+    }
+
+    if json {
+        let output = serde_json::json!({
+            "results": results,
+            "comparison": comparison,
+            "threshold_percent": threshold,
+            "regressed": regressed,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(regressed);
+    }
+
+    println!(
+        "{:<14} {:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "Workload", "Iter", "Mean", "Stddev", "p50", "p95", "p99", "Max"
+    );
+    for result in &results {
+        println!(
+            "{:<14} {:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            result.workload,
+            result.iterations,
+            units::format_ms(result.mean_ms),
+            units::format_ms(result.stddev_ms),
+            units::format_ms(result.p50_ms),
+            units::format_ms(result.p95_ms),
+            units::format_ms(result.p99_ms),
+            units::format_ms(result.max_ms)
+        );
+    }
+
+    if baseline.is_some() {
+        println!("\nAgainst baseline ({}, regression over +{}%):", metric.as_str(), threshold);
+        for compared in &comparison {
+            println!(
+                "  {:<14} {} -> {} ({:+.1}%){}",
+                compared.workload,
+                units::format_ms(compared.baseline_ms),
+                units::format_ms(compared.current_ms),
+                compared.change_percent,
+                if compared.regressed { "  REGRESSED" } else { "" }
+            );
+        }
+        for result in results.iter().filter(|result| !comparison.iter().any(|c| c.workload == result.workload)) {
+            println!("  {:<14} not in baseline", result.workload);
+        }
+    }
+
+    Ok(regressed)
+}
+
 async fn run_tests(
     config: &Config,
     component: Option<String>,
@@ -424,3 +2354,730 @@ async fn run_tests(
     info!("Tests completed successfully");
     Ok(())
 }
+
+async fn handle_events(config: &Config, action: EventsAction) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    match action {
+        EventsAction::Delete { id } => {
+            let previous = storage.get_event(id).await?;
+            if storage.delete_event(id).await? {
+                storage
+                    .record_audit(
+                        &AuditEntry::new(&AuditEntry::cli_actor(), "events.delete", Some(&id.to_string()))
+                            .with_change(previous.map(|event| serde_json::json!(event)), None),
+                    )
+                    .await?;
+                println!("Deleted event {}; undo with `events restore {}`", id, id);
+            } else {
+                return Err(anyhow::anyhow!("No event with id {}", id));
+            }
+        }
+        EventsAction::Restore { ids, deleted_since } => {
+            if ids.is_empty() && deleted_since.is_none() {
+                return Err(anyhow::anyhow!("Give event ids or --deleted-since"));
+            }
+            let mut restored = storage.restore_events(&ids).await?;
+            if let Some(expr) = &deleted_since {
+                let since = timerange::parse_time_expr(expr, chrono::Utc::now())?;
+                restored += storage.restore_deleted_since(since).await?;
+            }
+            if restored > 0 {
+                let target = match &deleted_since {
+                    Some(expr) => format!("deleted since {}", expr),
+                    None => ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","),
+                };
+                storage
+                    .record_audit(
+                        &AuditEntry::new(&AuditEntry::cli_actor(), "events.restore", Some(&target))
+                            .with_change(None, Some(serde_json::json!({ "restored": restored }))),
+                    )
+                    .await?;
+            }
+            println!("Restored {} events", restored);
+        }
+        EventsAction::Deleted { limit } => {
+            let batches = storage.list_deleted_batches(limit).await?;
+            if batches.is_empty() {
+                println!("No deleted events");
+            }
+            for batch in &batches {
+                println!(
+                    "{}  {:>8} events  {} .. {}",
+                    batch.deleted_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                    batch.events,
+                    batch.first_event.format("%Y-%m-%d %H:%M"),
+                    batch.last_event.format("%Y-%m-%d %H:%M"),
+                );
+            }
+        }
+        EventsAction::PurgeDeleted { all, dry_run } => {
+            let now = chrono::Utc::now();
+            let before = if all {
+                now + chrono::Duration::seconds(1)
+            } else {
+                now - chrono::Duration::days(config.storage.deleted_retention_days as i64)
+            };
+            let count = storage.count_deleted_before(before).await?;
+            if dry_run {
+                println!("Would purge {} deleted events", count);
+                return Ok(());
+            }
+            let progress = Progress::rows("Purging", Some(count));
+            let purged = storage.purge_deleted(before, &AuditEntry::cli_actor(), &progress).await?;
+            progress.finish();
+            println!("Purged {} deleted events", purged);
+            if progress.is_cancelled() {
+                println!(
+                    "Cancelled with {} left; run events purge-deleted again to finish",
+                    count.saturating_sub(purged)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_filters(config: &Config, action: FiltersAction) -> Result<()> {
+    match action {
+        FiltersAction::Explain { sample, filter, json } => {
+            let filters = filter.iter().map(|f| EventFilter::parse(f)).collect::<Result<Vec<_>>>()?;
+            let storage = MetricsStorage::open_readonly(&config.storage.database_path).await?;
+            let events = storage.get_recent_events(sample).await?;
+            let outcomes = pipeline::explain(config, &events, &filters);
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&outcomes)?);
+                return Ok(());
+            }
+            println!("Evaluated the {} most recent events", events.len());
+            for outcome in &outcomes {
+                println!(
+                    "{:<48} keeps {:>6}  drops {:>6} ({:>5.1}%)  {}",
+                    outcome.rule,
+                    outcome.kept,
+                    outcome.dropped,
+                    outcome.dropped_percent(),
+                    outcome.effect
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_tags(config: &Config, action: TagsAction) -> Result<()> {
+    match action {
+        TagsAction::Apply { filter, set, batch_size, dry_run } => {
+            let filter = EventFilter::parse(&filter)?;
+            let sql_filter = filter.to_sql()?;
+
+            let tags = set
+                .iter()
+                .map(|pair| {
+                    pair.split_once('=')
+                        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                        .filter(|(k, _)| !k.is_empty())
+                        .ok_or_else(|| anyhow::anyhow!("Invalid tag '{}', expected key=value", pair))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            let total = storage.count_matching(&sql_filter).await?;
+            info!("{} events match filter: {}", total, filter);
+
+            if dry_run || total == 0 {
+                println!("{} events would be tagged", total);
+                return Ok(());
+            }
+
+            let progress = Progress::rows("Tagging", Some(total));
+            let tagged = storage
+                .tag_matching_events(&sql_filter, &tags, batch_size.max(1), &progress)
+                .await?;
+            progress.finish();
+            let complete = !progress.is_cancelled();
+
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "tags.apply", Some(&filter.to_string())).with_change(
+                        None,
+                        Some(serde_json::json!({ "tags": set, "events": tagged, "complete": complete })),
+                    ),
+                )
+                .await?;
+
+            if complete {
+                println!("Tagged {} events", tagged);
+            } else {
+                println!("Cancelled after tagging {} of {} events; they keep their tags", tagged, total);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_query(
+    config: &Config,
+    filter: Option<String>,
+    saved: Option<String>,
+    limit: Option<u32>,
+    json: bool,
+    archive: Option<String>,
+) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    let (filter, default_limit) = match saved {
+        Some(name) => {
+            let query = storage
+                .get_saved_query(&name)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("No saved query named '{}'", name))?;
+            (Some(query.filter), query.limit)
+        }
+        None => (filter, None),
+    };
+
+    let sql_filter = filter
+        .as_deref()
+        .map(|f| EventFilter::parse(f).and_then(|f| f.to_sql()))
+        .transpose()?;
+
+    let limit = limit.or(default_limit).unwrap_or(100);
+    let events = match archive {
+        Some(name) => {
+            validate_archive_name(&name)?;
+            let path = daemon::archives_dir(config).join(format!("{}.db", name));
+            if !path.exists() {
+                return Err(anyhow::anyhow!(
+                    "No attached archive named '{}' (see `archive list --local`)",
+                    name
+                ));
+            }
+            MetricsStorage::open_archive(&path)
+                .await?
+                .query_events(sql_filter.as_ref(), limit)
+                .await?
+        }
+        None => storage.query_events(sql_filter.as_ref(), limit).await?,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&events)?);
+    } else {
+        for event in &events {
+            println!(
+                "[{}] {} - {}ms - {}",
+                event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                event.component_type,
+                event.duration_ms(),
+                event.description
+            );
+        }
+        info!("{} events", events.len());
+    }
+
+    Ok(())
+}
+
+async fn handle_queries(config: &Config, action: QueriesAction) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    match action {
+        QueriesAction::Save { name, filter, limit, description } => {
+            // Validate before persisting so broken filters never get saved
+            EventFilter::parse(&filter)?.to_sql()?;
+
+            let query = SavedQuery {
+                name,
+                filter,
+                limit,
+                description,
+                updated_at: chrono::Utc::now(),
+            };
+            let previous = storage.get_saved_query(&query.name).await?;
+            storage.save_query(&query).await?;
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "queries.save", Some(&query.name))
+                        .with_change(previous.map(|q| serde_json::json!(q)), Some(serde_json::json!(query))),
+                )
+                .await?;
+            println!("Saved query '{}'", query.name);
+        }
+        QueriesAction::List => {
+            for query in storage.list_saved_queries().await? {
+                println!(
+                    "{:<24} {}{}",
+                    query.name,
+                    query.filter,
+                    query
+                        .description
+                        .map(|d| format!("  # {}", d))
+                        .unwrap_or_default()
+                );
+            }
+        }
+        QueriesAction::Delete { name } => {
+            let previous = storage.get_saved_query(&name).await?;
+            if storage.delete_saved_query(&name).await? {
+                storage
+                    .record_audit(
+                        &AuditEntry::new(&AuditEntry::cli_actor(), "queries.delete", Some(&name))
+                            .with_change(previous.map(|q| serde_json::json!(q)), None),
+                    )
+                    .await?;
+                println!("Deleted query '{}'", name);
+            } else {
+                warn!("No saved query named '{}'", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_webhooks(config: &Config, action: WebhooksAction) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    match action {
+        WebhooksAction::Test { name } => {
+            let webhook = config
+                .webhooks
+                .iter()
+                .find(|w| w.name == name)
+                .ok_or_else(|| anyhow::anyhow!("No webhook named '{}' in config", name))?;
+
+            let payload = serde_json::json!({ "message": "Test delivery from vscode-latency-monitor" });
+            webhook::deliver(&storage, &Secrets::load()?, webhook, "test", &payload).await?;
+            println!("Delivered test payload to '{}'", name);
+        }
+        WebhooksAction::Log { name, limit } => {
+            for delivery in storage.get_webhook_deliveries(name.as_deref(), limit).await? {
+                println!(
+                    "{} {:<16} {:<24} #{} {} {}ms {}",
+                    delivery.attempted_at.format("%Y-%m-%d %H:%M:%S"),
+                    delivery.webhook,
+                    delivery.event_type,
+                    delivery.attempt,
+                    delivery.status_code.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                    delivery.duration_ms,
+                    delivery.error.unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_alerts(config: &Config, action: AlertsAction) -> Result<()> {
+    match action {
+        AlertsAction::List => {
+            if config.alerts.is_empty() {
+                println!("No alert rules configured");
+            }
+            for rule in &config.alerts {
+                let actions = rule.actions.iter().map(|a| format!("{:?}", a).to_lowercase()).collect::<Vec<_>>();
+                println!("{:<24} {} -> {}", rule.name, rule, actions.join(", "));
+            }
+        }
+        AlertsAction::Test { name, notify } => {
+            let rule = config
+                .alerts
+                .iter()
+                .find(|r| r.name == name)
+                .ok_or_else(|| anyhow::anyhow!("No alert rule named '{}' in config", name))?;
+
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            let (value, events) = alerts::evaluate_stored(&storage, rule).await?;
+            println!("Rule:   {}", rule);
+            println!("Events: {} in the last {}s", events, rule.window_secs);
+            match value {
+                Some(value) => println!(
+                    "Value:  {:.2} ({})",
+                    value,
+                    if rule.condition.holds(value, rule.threshold) { "would fire" } else { "ok" }
+                ),
+                None => println!("Value:  n/a (needs at least {} matching events)", rule.min_events.max(1)),
+            }
+
+            if notify {
+                let notification = alerts::AlertNotification {
+                    rule: rule.name.clone(),
+                    state: alerts::AlertState::Firing,
+                    value: value.unwrap_or_default(),
+                    threshold: rule.threshold,
+                    message: format!("Test of alert '{}': {}", rule.name, rule),
+                    at: chrono::Utc::now(),
+                    digest: None,
+                };
+                alerts::notify(config, &storage, rule, &notification).await;
+                println!("Ran {} action(s)", rule.actions.len());
+            }
+        }
+        AlertsAction::History { limit } => {
+            let storage = MetricsStorage::new(&config.storage.database_path).await?;
+            for entry in storage.get_alert_history(limit).await? {
+                println!(
+                    "{} {:<24} {:<8} {:>10.2} {}",
+                    entry.at.format("%Y-%m-%d %H:%M:%S"),
+                    entry.rule,
+                    entry.state,
+                    entry.value,
+                    entry
+                        .suppressed_by
+                        .map(|w| format!("(suppressed by '{}')", w))
+                        .unwrap_or_default()
+                );
+            }
+        }
+        AlertsAction::Maintenance { action } => {
+            handle_maintenance(config, action).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_maintenance(config: &Config, action: MaintenanceAction) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let now = chrono::Utc::now();
+
+    match action {
+        MaintenanceAction::Add { name, start, end, duration, recurrence, rules, reason } => {
+            let starts_at = timerange::parse_time_expr(&start, now)?;
+            let ends_at = match (end, duration) {
+                (Some(end), _) => timerange::parse_time_expr(&end, now)?,
+                (None, Some(duration)) => {
+                    starts_at
+                        + timerange::parse_duration(&duration)
+                            .ok_or_else(|| anyhow::anyhow!("Invalid duration '{}' (expected e.g. 30m, 2h)", duration))?
+                }
+                (None, None) => unreachable!("clap requires --end or --duration"),
+            };
+            let recurrence = Recurrence::parse(&recurrence).ok_or_else(|| {
+                anyhow::anyhow!("Unknown recurrence '{}' (expected once, daily or weekly)", recurrence)
+            })?;
+            for rule in &rules {
+                if !config.alerts.iter().any(|r| &r.name == rule) {
+                    warn!("No alert rule named '{}' in config", rule);
+                }
+            }
+
+            let window = MaintenanceWindow {
+                name,
+                starts_at,
+                ends_at,
+                recurrence,
+                rules,
+                reason,
+                updated_at: now,
+            };
+            window.validate()?;
+
+            let previous = storage.get_maintenance_window(&window.name).await?;
+            storage.save_maintenance_window(&window).await?;
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "maintenance.save", Some(&window.name))
+                        .with_change(previous.map(|w| serde_json::json!(w)), Some(serde_json::json!(window))),
+                )
+                .await?;
+            println!(
+                "Saved maintenance window '{}' ({} to {}, {})",
+                window.name,
+                window.starts_at.format("%Y-%m-%d %H:%M UTC"),
+                window.ends_at.format("%Y-%m-%d %H:%M UTC"),
+                window.recurrence.as_str()
+            );
+        }
+        MaintenanceAction::List => {
+            let windows = storage.list_maintenance_windows().await?;
+            if windows.is_empty() {
+                println!("No maintenance windows");
+            }
+            for window in windows {
+                println!(
+                    "{:<20} {} -> {} {:<7} {:<8} {}{}",
+                    window.name,
+                    window.starts_at.format("%Y-%m-%d %H:%M"),
+                    window.ends_at.format("%Y-%m-%d %H:%M"),
+                    window.recurrence.as_str(),
+                    if window.is_active(now) { "active" } else { "" },
+                    if window.rules.is_empty() { "all rules".to_string() } else { window.rules.join(", ") },
+                    window.reason.map(|r| format!("  # {}", r)).unwrap_or_default()
+                );
+            }
+        }
+        MaintenanceAction::Remove { name } => {
+            let previous = storage.get_maintenance_window(&name).await?;
+            if storage.delete_maintenance_window(&name).await? {
+                storage
+                    .record_audit(
+                        &AuditEntry::new(&AuditEntry::cli_actor(), "maintenance.delete", Some(&name))
+                            .with_change(previous.map(|w| serde_json::json!(w)), None),
+                    )
+                    .await?;
+                println!("Removed maintenance window '{}'", name);
+            } else {
+                warn!("No maintenance window named '{}'", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_token(config: &Config, action: TokenAction) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    match action {
+        TokenAction::Create { name, scopes, tenant } => {
+            let scopes = scopes
+                .iter()
+                .map(|s| auth::ApiScope::parse(s))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(tenant) = &tenant {
+                tenancy::validate_tenant(tenant)?;
+                if !config.dashboard.tenancy.enabled {
+                    warn!("dashboard.tenancy is disabled; the token reaches nothing until it is enabled");
+                }
+            }
+            let token = auth::generate_token();
+
+            storage
+                .create_api_token(&name, &auth::hash_token(&token), &scopes, tenant.as_deref())
+                .await?;
+            storage
+                .record_audit(
+                    &AuditEntry::new(&AuditEntry::cli_actor(), "tokens.create", Some(&name))
+                        .with_change(None, Some(serde_json::json!({ "scopes": scopes, "tenant": tenant }))),
+                )
+                .await?;
+
+            println!(
+                "Created token '{}' with scopes: {}{}",
+                name,
+                scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", "),
+                tenant.map(|t| format!(" for tenant '{}'", t)).unwrap_or_default()
+            );
+            println!("{}", token);
+            println!("Store it now; it cannot be shown again.");
+        }
+        TokenAction::List => {
+            for token in storage.list_api_tokens().await? {
+                println!(
+                    "{:<24} {:<36} {:<16} created {}  last used {}",
+                    token.name,
+                    token.scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(","),
+                    token.tenant.as_deref().unwrap_or("-"),
+                    token.created_at.format("%Y-%m-%d"),
+                    token
+                        .last_used_at
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_else(|| "never".to_string())
+                );
+            }
+        }
+        TokenAction::Revoke { name } => {
+            if storage.revoke_api_token(&name).await? {
+                storage
+                    .record_audit(&AuditEntry::new(&AuditEntry::cli_actor(), "tokens.revoke", Some(&name)))
+                    .await?;
+                println!("Revoked token '{}'", name);
+            } else {
+                warn!("No token named '{}'", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn export_content_type(format: &str) -> &'static str {
+    match format {
+        "json" => "application/json",
+        "csv" => "text/csv",
+        "parquet" => "application/vnd.apache.parquet",
+        "sqlite" => "application/vnd.sqlite3",
+        e if e.ends_with(".gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn prune_events(config: &Config, older_than: Option<&str>, dry_run: bool) -> Result<()> {
+    let now = chrono::Utc::now();
+    let cutoff = match older_than {
+        Some(expr) => timerange::parse_time_expr(expr, now)?,
+        None => now - chrono::Duration::days(config.storage.retention_days as i64),
+    };
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+    let count = storage.count_events_before(cutoff).await?;
+
+    if dry_run {
+        println!("Would delete {} events older than {}", count, cutoff.format("%Y-%m-%d %H:%M UTC"));
+        return Ok(());
+    }
+
+    let progress = Progress::rows("Deleting", Some(count));
+    let deleted = storage
+        .prune_events_before(cutoff, &AuditEntry::cli_actor(), &progress)
+        .await?;
+    progress.finish();
+    println!("Deleted {} events older than {}", deleted, cutoff.format("%Y-%m-%d %H:%M UTC"));
+    print_restorable(config, deleted);
+    if progress.is_cancelled() {
+        println!("Cancelled with {} left; run prune again to finish", count.saturating_sub(deleted));
+    }
+    Ok(())
+}
+
+fn print_restorable(config: &Config, deleted: u64) {
+    if deleted > 0 {
+        println!(
+            "They can be restored with `events restore --deleted-since` for {} days",
+            config.storage.deleted_retention_days
+        );
+    }
+}
+
+async fn purge_test_data(config: &Config, dry_run: bool) -> Result<()> {
+    let storage = MetricsStorage::new(&config.storage.database_path).await?;
+
+    let count = storage.count_test_data().await?;
+
+    if dry_run {
+        println!("Would delete {} test events", count);
+        return Ok(());
+    }
+
+    let progress = Progress::rows("Deleting", Some(count));
+    let deleted = storage.purge_test_data(&AuditEntry::cli_actor(), &progress).await?;
+    progress.finish();
+    println!("Deleted {} test events", deleted);
+    print_restorable(config, deleted);
+    if progress.is_cancelled() {
+        println!("Cancelled with {} left; run prune --test-data again to finish", count.saturating_sub(deleted));
+    }
+    Ok(())
+}
+
+async fn handle_archive(config: &Config, action: ArchiveAction) -> Result<()> {
+    if let ArchiveAction::List { local: true, .. } = action {
+        return list_attached_archives(config);
+    }
+
+    let store = ObjectStore::new(&config.object_storage, &Secrets::load()?)?;
+
+    match action {
+        ArchiveAction::Upload { path, kind } => {
+            let kind = ObjectKind::parse(&kind)?;
+            let filename = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow::anyhow!("Invalid file name: {}", path.display()))?;
+
+            let key = store.object_key(kind, filename, chrono::Utc::now());
+            let progress = Progress::bytes("Uploading", None);
+            store
+                .upload_file(&key, &path, "application/octet-stream", &progress)
+                .await?;
+            progress.finish();
+            println!("Uploaded {} to {}", path.display(), key);
+        }
+        ArchiveAction::List { kind, .. } => {
+            let kind = ObjectKind::parse(&kind)?;
+            let retention_cutoff =
+                chrono::Utc::now() - chrono::Duration::days(config.storage.retention_days as i64);
+
+            for object in store.list_objects(Some(kind)).await? {
+                // Objects older than retention only exist in the bucket now
+                let location = match object.last_modified {
+                    Some(modified) if modified < retention_cutoff => "archive only",
+                    Some(_) => "also in hot db",
+                    None => "unknown",
+                };
+                println!(
+                    "{:<60} {:>10} {}  ({})",
+                    object.key,
+                    object.size,
+                    object
+                        .last_modified
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default(),
+                    location
+                );
+            }
+        }
+        ArchiveAction::Restore { key, attach, name, output } => {
+            let filename = key.rsplit('/').next().unwrap_or(&key).to_string();
+            let progress = Progress::bytes("Downloading", None);
+            let data = store.get_object(&key, &progress).await?;
+            progress.finish();
+
+            let path = if attach {
+                let stem = name.unwrap_or_else(|| {
+                    PathBuf::from(&filename)
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("archive")
+                        .to_string()
+                });
+                validate_archive_name(&stem)?;
+                let dir = daemon::archives_dir(config);
+                tokio::fs::create_dir_all(&dir).await?;
+                dir.join(format!("{}.db", stem))
+            } else {
+                output.unwrap_or_else(|| PathBuf::from(&filename))
+            };
+
+            file_io::write_file(&path, &data, config.storage.io_backend)?;
+
+            if attach {
+                // Opening validates the download is a usable SQLite database
+                let archive = MetricsStorage::open_archive(&path).await?;
+                let count = archive.count_events().await?;
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                println!(
+                    "Attached {} ({} events) read-only; query with `query --archive {}`",
+                    key, count, name
+                );
+            } else {
+                println!("Restored {} to {}", key, path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Attached archives are files in the archives directory named after them.
+fn validate_archive_name(name: &str) -> Result<()> {
+    capture::validate_label(name)
+        .map_err(|_| anyhow::anyhow!("Invalid archive name '{}': use letters, digits, '-', '_' and '.'", name))
+}
+
+fn list_attached_archives(config: &Config) -> Result<()> {
+    let dir = daemon::archives_dir(config);
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("db") {
+            continue;
+        }
+        println!(
+            "{:<24} {}",
+            path.file_stem().and_then(|s| s.to_str()).unwrap_or_default(),
+            path.display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,670 @@
+use anyhow::{anyhow, Result};
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use chrono::Utc;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::ipc;
+use crate::models::{ModelInteraction, ModelInteractionType, TokenMetrics};
+use crate::tokens;
+
+/// Timings waiting to be sent to the monitor; beyond this they are dropped
+/// rather than holding up responses.
+const REPORT_QUEUE: usize = 1024;
+
+/// Request headers that describe the client's connection rather than the
+/// request. `accept-encoding` is left out too, so responses arrive
+/// uncompressed and their streams can be read.
+const SKIPPED_REQUEST_HEADERS: &[&str] = &[
+    "accept-encoding",
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers that describe the upstream connection.
+const SKIPPED_RESPONSE_HEADERS: &[&str] =
+    &["connection", "content-length", "keep-alive", "trailer", "transfer-encoding", "upgrade"];
+
+/// Largest non-streamed response read for its token usage; bigger ones are
+/// passed on without it.
+const MAX_PARSED_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// `model-proxy`: a local reverse proxy in front of cloud model APIs. Each
+/// upstream is served under its name, so with the defaults an extension's
+/// base URL of `http://127.0.0.1:8898/openai/v1` reaches
+/// `https://api.openai.com/v1`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelProxyConfig {
+    pub listen: String,
+    /// Path prefix and the API base URL requests under it are sent to
+    pub upstreams: BTreeMap<String, String>,
+    pub connect_timeout_secs: u64,
+    /// How long to wait for response headers; a streamed body can take as
+    /// long as it takes after that
+    pub response_timeout_secs: u64,
+    /// Largest request body accepted, e.g. a prompt with images
+    pub max_request_bytes: usize,
+}
+
+impl Default for ModelProxyConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8898".to_string(),
+            upstreams: BTreeMap::from([
+                ("anthropic".to_string(), "https://api.anthropic.com".to_string()),
+                ("openai".to_string(), "https://api.openai.com".to_string()),
+            ]),
+            connect_timeout_secs: 10,
+            response_timeout_secs: 120,
+            max_request_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl ModelProxyConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, base) in &self.upstreams {
+            if name.is_empty() || name.contains('/') {
+                return Err(anyhow!("model_proxy.upstreams: '{}' must be one path segment", name));
+            }
+            let url = reqwest::Url::parse(base)
+                .map_err(|e| anyhow!("model_proxy.upstreams.{}: invalid URL '{}': {}", name, base, e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(anyhow!("model_proxy.upstreams.{}: '{}' isn't an HTTP(S) URL", name, base));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One proxied model request, sent to the monitor over IPC as a
+/// `model_request` message and stored as a `CloudModel` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRequestReport {
+    /// Upstream name, e.g. `openai`
+    pub provider: String,
+    /// Path below the upstream's base URL, e.g. `/v1/chat/completions`
+    pub endpoint: String,
+    /// HTTP status, absent when no response arrived
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// Whether the response was a server-sent event stream
+    #[serde(default)]
+    pub streamed: bool,
+    /// Time to the response headers
+    #[serde(default)]
+    pub ttfb_ms: Option<f64>,
+    /// Streamed content deltas, whose arrival times give the cadence
+    #[serde(default)]
+    pub chunks: u32,
+    #[serde(default)]
+    pub mean_chunk_gap_ms: Option<f64>,
+    #[serde(default)]
+    pub p95_chunk_gap_ms: Option<f64>,
+    /// Completion tokens per second from the first delta to the end
+    #[serde(default)]
+    pub stream_tokens_per_second: Option<f64>,
+    /// Unix epoch milliseconds when the response finished
+    pub end_ms: f64,
+    pub interaction: ModelInteraction,
+    /// Output throughput of a streamed response that produced text
+    #[serde(default)]
+    pub tokens: Option<TokenMetrics>,
+}
+
+struct Proxy {
+    config: ModelProxyConfig,
+    client: reqwest::Client,
+    reports: mpsc::Sender<Value>,
+}
+
+/// Serve `config.listen` until the process is stopped, reporting every
+/// model request to the monitor on `socket`. Requests and responses pass
+/// through unchanged; the monitor not running only means timings are
+/// dropped.
+pub async fn run(socket: &Path, config: ModelProxyConfig) -> Result<()> {
+    config.validate()?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs.max(1)))
+        .build()?;
+    let (reports, queue) = mpsc::channel(REPORT_QUEUE);
+    tokio::spawn(ipc::send_reports(socket.to_path_buf(), queue));
+
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .map_err(|e| anyhow!("Failed to bind model proxy on {}: {}", config.listen, e))?;
+    for (name, base) in &config.upstreams {
+        info!("Proxying http://{}/{} to {}", config.listen, name, base);
+    }
+
+    let proxy = Proxy {
+        config,
+        client,
+        reports,
+    };
+    let app = Router::new().fallback(forward).with_state(Arc::new(proxy));
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn forward(State(proxy): State<Arc<Proxy>>, request: Request) -> Response {
+    let started = Instant::now();
+    let (parts, body) = request.into_parts();
+    let path = parts.uri.path().trim_start_matches('/');
+    let (provider, rest) = path.split_once('/').unwrap_or((path, ""));
+    let Some(base) = proxy.config.upstreams.get(provider) else {
+        let known: Vec<&str> = proxy.config.upstreams.keys().map(String::as_str).collect();
+        let message = format!("No upstream '{}'; paths start with one of: {}", provider, known.join(", "));
+        return (StatusCode::NOT_FOUND, message).into_response();
+    };
+    let endpoint = format!("/{}", rest);
+    let url = match parts.uri.query() {
+        Some(query) => format!("{}{}?{}", base.trim_end_matches('/'), endpoint, query),
+        None => format!("{}{}", base.trim_end_matches('/'), endpoint),
+    };
+
+    let body = match to_bytes(body, proxy.config.max_request_bytes).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+    let request_json = serde_json::from_slice::<Value>(&body).ok();
+    let model = request_json
+        .as_ref()
+        .and_then(|request| request.get("model")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut recorder = Recorder::new(proxy.reports.clone(), started, provider, &endpoint, model);
+    recorder.prompt_length = request_json.as_ref().map(tokens::prompt_length).unwrap_or_default();
+    let mut upstream = proxy.client.request(parts.method, &url).body(body);
+    for (name, value) in &parts.headers {
+        if !SKIPPED_REQUEST_HEADERS.contains(&name.as_str()) {
+            upstream = upstream.header(name, value);
+        }
+    }
+
+    let wait = Duration::from_secs(proxy.config.response_timeout_secs.max(1));
+    let response = match tokio::time::timeout(wait, upstream.send()).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            debug!("Model request to {} failed: {}", url, e);
+            recorder.error = Some(e.to_string());
+            return (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+        }
+        Err(_) => {
+            recorder.error = Some(format!("no response within {}s", wait.as_secs()));
+            return (StatusCode::GATEWAY_TIMEOUT, "Model API didn't respond in time").into_response();
+        }
+    };
+
+    recorder.ttfb = Some(started.elapsed());
+    recorder.status = Some(response.status().as_u16());
+    recorder.streamed = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+
+    let mut builder = Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        if !SKIPPED_RESPONSE_HEADERS.contains(&name.as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+    builder
+        .body(Body::from_stream(observe(response.bytes_stream(), recorder)))
+        .unwrap_or_else(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response())
+}
+
+/// Pass `stream` on, showing every chunk to `recorder`. The recorder
+/// reports when the stream ends or the client goes away, whichever is
+/// first.
+fn observe(
+    stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    recorder: Recorder,
+) -> impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static {
+    futures_util::stream::unfold((Box::pin(stream), recorder), |(mut stream, mut recorder)| async move {
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                recorder.chunk(&chunk);
+                Some((Ok(chunk), (stream, recorder)))
+            }
+            Some(Err(e)) => {
+                recorder.error = Some(e.to_string());
+                Some((Err(e), (stream, recorder)))
+            }
+            None => {
+                recorder.mark_complete();
+                None
+            }
+        }
+    })
+}
+
+/// Timings of one request, reported when it is dropped.
+struct Recorder {
+    reports: mpsc::Sender<Value>,
+    started: Instant,
+    provider: String,
+    endpoint: String,
+    model: String,
+    status: Option<u16>,
+    streamed: bool,
+    ttfb: Option<Duration>,
+    /// Arrival of each streamed content delta
+    deltas: Vec<Duration>,
+    /// Text of the streamed deltas, for estimating tokens
+    text: String,
+    /// Characters of prompt text in the request
+    prompt_length: usize,
+    /// Unfinished line of a streamed response
+    pending_line: Vec<u8>,
+    /// Non-streamed response, kept up to `MAX_PARSED_BODY_BYTES`
+    body: Vec<u8>,
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+    error: Option<String>,
+    /// The response was passed on to the end
+    complete: bool,
+}
+
+impl Recorder {
+    fn new(reports: mpsc::Sender<Value>, started: Instant, provider: &str, endpoint: &str, model: String) -> Self {
+        Self {
+            reports,
+            started,
+            provider: provider.to_string(),
+            endpoint: endpoint.to_string(),
+            model,
+            status: None,
+            streamed: false,
+            ttfb: None,
+            deltas: Vec::new(),
+            text: String::new(),
+            prompt_length: 0,
+            pending_line: Vec::new(),
+            body: Vec::new(),
+            prompt_tokens: None,
+            completion_tokens: None,
+            error: None,
+            complete: false,
+        }
+    }
+
+    /// The whole response reached the client, so it is reported on drop.
+    fn mark_complete(&mut self) {
+        self.complete = true;
+    }
+
+    fn chunk(&mut self, chunk: &[u8]) {
+        if !self.streamed {
+            if self.body.len() + chunk.len() <= MAX_PARSED_BODY_BYTES {
+                self.body.extend_from_slice(chunk);
+            }
+            return;
+        }
+
+        let arrived = self.started.elapsed();
+        self.pending_line.extend_from_slice(chunk);
+        while let Some(newline) = self.pending_line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending_line.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(message) = serde_json::from_str::<Value>(data.trim()) else {
+                // `[DONE]` ends OpenAI streams
+                continue;
+            };
+            if is_content_delta(&message) {
+                self.deltas.push(arrived);
+                push_delta_text(&message, &mut self.text);
+            }
+            if message.get("type").and_then(Value::as_str) == Some("error") {
+                self.error = Some(error_message(&message).unwrap_or_else(|| "stream error".to_string()));
+            }
+            self.read_usage(&message);
+        }
+    }
+
+    /// Token counts from an OpenAI `usage` (also in the last chunk of a
+    /// stream with `include_usage`) or an Anthropic one, which a stream
+    /// sends in `message_start` and updates in `message_delta`.
+    fn read_usage(&mut self, message: &Value) {
+        let Some(usage) = message
+            .get("usage")
+            .filter(|usage| usage.is_object())
+            .or_else(|| message.get("message")?.get("usage"))
+        else {
+            return;
+        };
+        let count = |keys: [&str; 2]| keys.iter().find_map(|key| usage.get(*key)?.as_u64()).map(|n| n as u32);
+        if let Some(prompt) = count(["prompt_tokens", "input_tokens"]) {
+            self.prompt_tokens = Some(prompt);
+        }
+        if let Some(completion) = count(["completion_tokens", "output_tokens"]) {
+            self.completion_tokens = Some(completion);
+        }
+    }
+
+    fn report(&mut self) -> ModelRequestReport {
+        let duration = self.started.elapsed();
+        if !self.streamed && !self.body.is_empty() {
+            if let Ok(response) = serde_json::from_slice::<Value>(&self.body) {
+                self.read_usage(&response);
+                if self.error.is_none() && self.status.is_none_or(|status| status >= 400) {
+                    self.error = error_message(&response);
+                }
+            }
+        }
+        if self.error.is_none() {
+            self.error = self.status.filter(|status| *status >= 400).map(|status| format!("HTTP {}", status));
+        }
+
+        let mut interaction = ModelInteraction::new(
+            self.model.clone(),
+            interaction_type(&self.endpoint),
+            duration.as_millis() as u64,
+            self.error.is_none(),
+        );
+        interaction.prompt_tokens = self.prompt_tokens;
+        interaction.completion_tokens = self.completion_tokens;
+        interaction.total_tokens = match (self.prompt_tokens, self.completion_tokens) {
+            (Some(p), Some(c)) => Some(p + c),
+            (p, c) => p.or(c),
+        };
+        interaction.time_to_first_token_ms = self.deltas.first().map(|t| t.as_millis() as u64);
+        interaction.error_message = self.error.clone();
+
+        let mut gaps: Vec<f64> = self
+            .deltas
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).as_secs_f64() * 1000.0)
+            .collect();
+        gaps.sort_by(|a, b| a.total_cmp(b));
+        let mean_chunk_gap_ms = (!gaps.is_empty()).then(|| gaps.iter().sum::<f64>() / gaps.len() as f64);
+        let p95_chunk_gap_ms = (!gaps.is_empty()).then(|| gaps[((gaps.len() - 1) as f64 * 0.95).round() as usize]);
+        let stream_tokens_per_second = match (self.deltas.first(), self.completion_tokens) {
+            (Some(first), Some(tokens)) if duration > *first => Some(tokens as f64 / (duration - *first).as_secs_f64()),
+            _ => None,
+        };
+        let tokens = match self.deltas.first() {
+            Some(first) if self.error.is_none() && !self.text.is_empty() => {
+                let mut tokens = TokenMetrics::new(
+                    "model_proxy",
+                    &self.provider,
+                    self.model.clone(),
+                    &self.text,
+                    duration.saturating_sub(*first).as_millis() as u64,
+                    self.prompt_length,
+                );
+                tokens.reported_tokens = self.completion_tokens;
+                tokens.time_to_first_token_ms = Some(first.as_millis() as u64);
+                Some(tokens)
+            }
+            _ => None,
+        };
+
+        ModelRequestReport {
+            provider: self.provider.clone(),
+            endpoint: self.endpoint.clone(),
+            status: self.status,
+            streamed: self.streamed,
+            ttfb_ms: self.ttfb.map(|ttfb| ttfb.as_secs_f64() * 1000.0),
+            chunks: self.deltas.len() as u32,
+            mean_chunk_gap_ms,
+            p95_chunk_gap_ms,
+            stream_tokens_per_second,
+            end_ms: Utc::now().timestamp_micros() as f64 / 1000.0,
+            interaction,
+            tokens,
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // The client hung up, e.g. a completion made stale by more typing:
+        // nobody waited for the rest
+        if !self.complete && self.error.is_none() && self.status.is_some() {
+            return;
+        }
+
+        let report = self.report();
+        let mut message = match serde_json::to_value(&report) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to encode model request timing: {}", e);
+                return;
+            }
+        };
+        message["type"] = Value::from("model_request");
+        let _ = self.reports.try_send(message);
+    }
+}
+
+/// Whether a streamed event carries generated content: an OpenAI chat or
+/// completion chunk with text or a tool call, or an Anthropic
+/// `content_block_delta`.
+fn is_content_delta(message: &Value) -> bool {
+    if message.get("type").and_then(Value::as_str) == Some("content_block_delta") {
+        return true;
+    }
+    let Some(choices) = message.get("choices").and_then(Value::as_array) else {
+        return false;
+    };
+    choices.iter().any(|choice| {
+        let delta = &choice["delta"];
+        delta["content"].as_str().is_some_and(|text| !text.is_empty())
+            || delta.get("tool_calls").is_some_and(|calls| !calls.is_null())
+            || choice["text"].as_str().is_some_and(|text| !text.is_empty())
+    })
+}
+
+/// Append the generated text of a content delta to `text`: OpenAI content,
+/// completion text and tool call arguments, or an Anthropic text, thinking
+/// or tool input delta.
+fn push_delta_text(message: &Value, text: &mut String) {
+    let delta = &message["delta"];
+    for part in [&delta["text"], &delta["thinking"], &delta["partial_json"]] {
+        text.push_str(part.as_str().unwrap_or_default());
+    }
+    for choice in message["choices"].as_array().into_iter().flatten() {
+        text.push_str(choice["delta"]["content"].as_str().unwrap_or_default());
+        text.push_str(choice["text"].as_str().unwrap_or_default());
+        for call in choice["delta"]["tool_calls"].as_array().into_iter().flatten() {
+            text.push_str(call["function"]["arguments"].as_str().unwrap_or_default());
+        }
+    }
+}
+
+/// `error.message` of an OpenAI or Anthropic error body.
+fn error_message(body: &Value) -> Option<String> {
+    body.get("error")?.get("message")?.as_str().map(str::to_string)
+}
+
+fn interaction_type(endpoint: &str) -> ModelInteractionType {
+    if endpoint.contains("chat/completions") || endpoint.ends_with("/messages") || endpoint.ends_with("/responses") {
+        ModelInteractionType::ChatCompletion
+    } else if endpoint.contains("embeddings") {
+        ModelInteractionType::Embedding
+    } else if endpoint.contains("completions") {
+        ModelInteractionType::Completion
+    } else if endpoint.contains("fine_tuning") {
+        ModelInteractionType::FineTuning
+    } else {
+        ModelInteractionType::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn delta_text(messages: &[Value]) -> String {
+        let mut text = String::new();
+        for message in messages {
+            push_delta_text(message, &mut text);
+        }
+        text
+    }
+
+    #[test]
+    fn openai_content_completion_and_tool_call_deltas() {
+        let text = delta_text(&[
+            json!({ "choices": [{ "index": 0, "delta": { "role": "assistant" } }] }),
+            json!({ "choices": [{ "index": 0, "delta": { "content": "Hel" } }] }),
+            json!({ "choices": [{ "index": 0, "text": "lo" }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [{ "function": { "arguments": "{\"a\":" } }] } }] }),
+            json!({ "choices": [{ "delta": {}, "finish_reason": "stop" }], "usage": { "completion_tokens": 3 } }),
+        ]);
+        assert_eq!(text, "Hello{\"a\":");
+    }
+
+    #[test]
+    fn anthropic_text_thinking_and_input_deltas() {
+        let text = delta_text(&[
+            json!({ "type": "message_start", "message": { "usage": { "input_tokens": 5 } } }),
+            json!({ "type": "content_block_delta", "delta": { "type": "thinking_delta", "thinking": "Hmm. " } }),
+            json!({ "type": "content_block_delta", "delta": { "type": "text_delta", "text": "Hi" } }),
+            json!({ "type": "content_block_delta", "delta": { "type": "input_json_delta", "partial_json": "{}" } }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 4 } }),
+        ]);
+        assert_eq!(text, "Hmm. Hi{}");
+    }
+
+    fn start(endpoint: &str) -> (Recorder, mpsc::Receiver<Value>) {
+        let (reports, received) = mpsc::channel(4);
+        (Recorder::new(reports, Instant::now(), "openai", endpoint, "gpt-test".to_string()), received)
+    }
+
+    fn report_of(mut recorder: Recorder, received: &mut mpsc::Receiver<Value>) -> ModelRequestReport {
+        recorder.mark_complete();
+        drop(recorder);
+        serde_json::from_value(received.try_recv().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn streamed_lines_split_across_chunks_are_parsed() {
+        let (mut recorder, mut received) = start("/v1/chat/completions");
+        recorder.streamed = true;
+        recorder.status = Some(200);
+        recorder.chunk(b"data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\nda");
+        recorder.chunk(b"ta: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n: keep-alive\n");
+        recorder.chunk(b"data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":2}}\n");
+        recorder.chunk(b"data: [DONE]\n");
+
+        let report = report_of(recorder, &mut received);
+        assert_eq!(report.chunks, 2);
+        assert!(report.streamed);
+        assert_eq!(report.interaction.prompt_tokens, Some(7));
+        assert_eq!(report.interaction.completion_tokens, Some(2));
+        assert_eq!(report.interaction.total_tokens, Some(9));
+        assert_eq!(report.interaction.interaction_type, ModelInteractionType::ChatCompletion);
+        assert!(report.interaction.success);
+        assert!(report.mean_chunk_gap_ms.is_some());
+        assert_eq!(report.tokens.unwrap().reported_tokens, Some(2));
+    }
+
+    #[test]
+    fn anthropic_usage_is_updated_through_the_stream() {
+        let (mut recorder, mut received) = start("/v1/messages");
+        recorder.streamed = true;
+        recorder.status = Some(200);
+        for event in [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_delta","delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":6}}"#,
+        ] {
+            recorder.chunk(format!("event: x\ndata: {}\n\n", event).as_bytes());
+        }
+
+        let report = report_of(recorder, &mut received);
+        assert_eq!(report.chunks, 1);
+        assert_eq!(report.interaction.prompt_tokens, Some(12));
+        assert_eq!(report.interaction.completion_tokens, Some(6));
+    }
+
+    #[test]
+    fn stream_errors_fail_the_request() {
+        let (mut recorder, mut received) = start("/v1/messages");
+        recorder.streamed = true;
+        recorder.status = Some(200);
+        recorder.chunk(br#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#);
+        recorder.chunk(b"\n");
+
+        let report = report_of(recorder, &mut received);
+        assert!(!report.interaction.success);
+        assert_eq!(report.interaction.error_message.as_deref(), Some("Overloaded"));
+        assert!(report.tokens.is_none());
+    }
+
+    #[test]
+    fn whole_responses_are_read_once_finished() {
+        let (mut recorder, mut received) = start("/v1/embeddings");
+        recorder.status = Some(200);
+        recorder.chunk(br#"{"data":[],"usage":{"prompt_tokens":"#);
+        recorder.chunk(br#"4,"total_tokens":4}}"#);
+        let report = report_of(recorder, &mut received);
+        assert_eq!(report.interaction.prompt_tokens, Some(4));
+        assert_eq!(report.interaction.completion_tokens, None);
+        assert_eq!(report.interaction.total_tokens, Some(4));
+        assert_eq!(report.interaction.interaction_type, ModelInteractionType::Embedding);
+
+        let (mut recorder, mut received) = start("/v1/chat/completions");
+        recorder.status = Some(429);
+        recorder.chunk(br#"{"error":{"message":"Rate limit reached"}}"#);
+        let report = report_of(recorder, &mut received);
+        assert_eq!(report.interaction.error_message.as_deref(), Some("Rate limit reached"));
+
+        let (mut recorder, mut received) = start("/v1/chat/completions");
+        recorder.status = Some(502);
+        let report = report_of(recorder, &mut received);
+        assert_eq!(report.interaction.error_message.as_deref(), Some("HTTP 502"));
+    }
+
+    #[test]
+    fn abandoned_responses_are_not_reported() {
+        let (mut recorder, mut received) = start("/v1/completions");
+        recorder.status = Some(200);
+        drop(recorder);
+        assert!(received.try_recv().is_err());
+
+        // Without a response the request failed rather than being abandoned
+        let (mut recorder, mut received) = start("/v1/completions");
+        recorder.error = Some("connection refused".to_string());
+        drop(recorder);
+        assert!(received.try_recv().is_ok());
+    }
+
+    #[test]
+    fn endpoints_map_to_interaction_types() {
+        assert_eq!(interaction_type("/v1/chat/completions"), ModelInteractionType::ChatCompletion);
+        assert_eq!(interaction_type("/v1/messages"), ModelInteractionType::ChatCompletion);
+        assert_eq!(interaction_type("/v1/responses"), ModelInteractionType::ChatCompletion);
+        assert_eq!(interaction_type("/v1/completions"), ModelInteractionType::Completion);
+        assert_eq!(interaction_type("/v1/embeddings"), ModelInteractionType::Embedding);
+        assert_eq!(interaction_type("/v1/fine_tuning/jobs"), ModelInteractionType::FineTuning);
+        assert_eq!(interaction_type("/v1/models"), ModelInteractionType::Other);
+    }
+}
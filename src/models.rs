@@ -1,14 +1,28 @@
-use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Duration;
 use std::fmt;
 
+use crate::auth::ApiScope;
+use crate::event_queue::LoadSheddingStatus;
+use crate::ids;
+use crate::lifecycle::LifecycleSummary;
+use crate::tokens;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LatencyEvent {
+    /// Row id in the local database only
     pub id: Option<i64>,
+    /// Identifier kept across agents, exports and syncs; see `ids`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
     pub timestamp: DateTime<Utc>,
     pub component_type: ComponentType,
     pub event_source: EventSource,
+    #[serde(default)]
+    pub kind: EventKind,
     pub duration: Duration,
     pub description: String,
     pub metadata: serde_json::Value,
@@ -23,20 +37,43 @@ impl LatencyEvent {
     ) -> Self {
         Self {
             id: None,
+            uid: ids::new_event_id(),
             timestamp: Utc::now(),
             component_type,
             event_source,
+            kind: EventKind::from_source(event_source),
             duration,
             description,
             metadata: serde_json::Value::Null,
         }
     }
 
+    pub fn with_kind(mut self, kind: EventKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
         self.metadata = metadata;
         self
     }
 
+    /// Flag the event as test data, which metrics and reports leave out
+    /// and `prune --test-data` deletes.
+    pub fn as_test_data(mut self) -> Self {
+        if !self.metadata.is_object() {
+            self.metadata = serde_json::json!({});
+        }
+        self.metadata["test_data"] = serde_json::json!(true);
+        self
+    }
+
+    /// Recorded by `test`, or flagged `"test_data": true` by whoever sent it.
+    pub fn is_test_data(&self) -> bool {
+        self.event_source == EventSource::TestCommand
+            || self.metadata.get("test_data").and_then(serde_json::Value::as_bool) == Some(true)
+    }
+
     pub fn duration_ms(&self) -> u64 {
         self.duration.as_millis() as u64
     }
@@ -44,18 +81,126 @@ impl LatencyEvent {
     pub fn duration_us(&self) -> u64 {
         self.duration.as_micros() as u64
     }
+
+    /// Start building an event timestamped now, with zero duration and the
+    /// kind implied by `event_source` until set otherwise.
+    pub fn builder(component_type: ComponentType, event_source: EventSource) -> LatencyEventBuilder {
+        LatencyEventBuilder {
+            event: Self::new(component_type, event_source, Duration::ZERO, String::new()),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+/// Builder returned by [`LatencyEvent::builder`].
+#[derive(Debug, Clone)]
+pub struct LatencyEventBuilder {
+    event: LatencyEvent,
+}
+
+impl LatencyEventBuilder {
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.event.duration = duration;
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.event.description = description.into();
+        self
+    }
+
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.event.kind = kind;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.event.metadata = metadata;
+        self
+    }
+
+    /// When the measured operation happened; defaults to when the builder
+    /// was created.
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.event.timestamp = timestamp;
+        self
+    }
+
+    pub fn build(self) -> LatencyEvent {
+        self.event
+    }
+}
+
+/// An event as carried between instances by `sync`, with its tags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedEvent {
+    #[serde(flatten)]
+    pub event: LatencyEvent,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ComponentType {
     VSCode,
     VSCodeExtension,
     GitHubCopilot,
     LocalModel,
+    /// Cloud model APIs such as OpenAI's and Anthropic's, timed by
+    /// `model-proxy`
+    CloudModel,
     Terminal,
+    /// rust-analyzer, pyright, tsserver, gopls and other servers VS Code
+    /// extensions start
+    LanguageServer,
     FileSystem,
     Network,
     System,
+    BuildTool,
+    /// Editors other than VS Code, named in `metadata.editor`
+    Editor,
+}
+
+impl ComponentType {
+    pub const ALL: [ComponentType; 12] = [
+        ComponentType::VSCode,
+        ComponentType::VSCodeExtension,
+        ComponentType::GitHubCopilot,
+        ComponentType::LocalModel,
+        ComponentType::CloudModel,
+        ComponentType::Terminal,
+        ComponentType::LanguageServer,
+        ComponentType::FileSystem,
+        ComponentType::Network,
+        ComponentType::System,
+        ComponentType::BuildTool,
+        ComponentType::Editor,
+    ];
+
+    /// Column value stored in `latency_events.component_type`; the same as
+    /// the serde name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComponentType::VSCode => "VSCode",
+            ComponentType::VSCodeExtension => "VSCodeExtension",
+            ComponentType::GitHubCopilot => "GitHubCopilot",
+            ComponentType::LocalModel => "LocalModel",
+            ComponentType::CloudModel => "CloudModel",
+            ComponentType::Terminal => "Terminal",
+            ComponentType::LanguageServer => "LanguageServer",
+            ComponentType::FileSystem => "FileSystem",
+            ComponentType::Network => "Network",
+            ComponentType::System => "System",
+            ComponentType::BuildTool => "BuildTool",
+            ComponentType::Editor => "Editor",
+        }
+    }
+
+    /// Accepts the stored name as well as the display label or snake case
+    /// (`GitHubCopilot`, `GitHub Copilot`, `github_copilot`).
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = normalize_name(value);
+        Self::ALL.into_iter().find(|component| normalize_name(component.as_str()) == value)
+    }
 }
 
 impl fmt::Display for ComponentType {
@@ -65,10 +210,14 @@ impl fmt::Display for ComponentType {
             ComponentType::VSCodeExtension => write!(f, "VS Code Extension"),
             ComponentType::GitHubCopilot => write!(f, "GitHub Copilot"),
             ComponentType::LocalModel => write!(f, "Local Model"),
+            ComponentType::CloudModel => write!(f, "Cloud Model"),
             ComponentType::Terminal => write!(f, "Terminal"),
+            ComponentType::LanguageServer => write!(f, "Language Server"),
             ComponentType::FileSystem => write!(f, "File System"),
             ComponentType::Network => write!(f, "Network"),
             ComponentType::System => write!(f, "System"),
+            ComponentType::BuildTool => write!(f, "Build Tool"),
+            ComponentType::Editor => write!(f, "Editor"),
         }
     }
 }
@@ -85,6 +234,46 @@ pub enum EventSource {
     UserInteraction,
 }
 
+impl EventSource {
+    pub const ALL: [EventSource; 8] = [
+        EventSource::ProcessMonitor,
+        EventSource::ExtensionHost,
+        EventSource::ModelProcess,
+        EventSource::CommandExecution,
+        EventSource::FileOperation,
+        EventSource::NetworkRequest,
+        EventSource::TestCommand,
+        EventSource::UserInteraction,
+    ];
+
+    /// Column value stored in `latency_events.event_source`; the same as the
+    /// serde name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventSource::ProcessMonitor => "ProcessMonitor",
+            EventSource::ExtensionHost => "ExtensionHost",
+            EventSource::ModelProcess => "ModelProcess",
+            EventSource::CommandExecution => "CommandExecution",
+            EventSource::FileOperation => "FileOperation",
+            EventSource::NetworkRequest => "NetworkRequest",
+            EventSource::TestCommand => "TestCommand",
+            EventSource::UserInteraction => "UserInteraction",
+        }
+    }
+
+    /// Accepts the stored name as well as the display label or snake case.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = normalize_name(value);
+        Self::ALL.into_iter().find(|source| normalize_name(source.as_str()) == value)
+    }
+}
+
+/// Lowercase alphanumerics only, so `Process Monitor`, `process_monitor` and
+/// `ProcessMonitor` compare equal.
+fn normalize_name(value: &str) -> String {
+    value.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
 impl fmt::Display for EventSource {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -100,6 +289,111 @@ impl fmt::Display for EventSource {
     }
 }
 
+/// What an event measures, independent of which component produced it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+pub enum EventKind {
+    #[default]
+    ProcessSample,
+    ModelRequest,
+    CommandRun,
+    Probe,
+    Lifecycle,
+    Annotation,
+    /// An inline suggestion accepted or rejected; the acceptance rate is
+    /// counted from these
+    SuggestionOutcome,
+    /// CPU charged to an extension inside its host; a share, not a duration
+    Attribution,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 8] = [
+        EventKind::ProcessSample,
+        EventKind::ModelRequest,
+        EventKind::CommandRun,
+        EventKind::Probe,
+        EventKind::Lifecycle,
+        EventKind::Annotation,
+        EventKind::SuggestionOutcome,
+        EventKind::Attribution,
+    ];
+
+    /// Default kind for events that don't set one explicitly.
+    pub fn from_source(source: EventSource) -> Self {
+        match source {
+            EventSource::ProcessMonitor
+            | EventSource::ExtensionHost
+            | EventSource::ModelProcess => EventKind::ProcessSample,
+            EventSource::CommandExecution
+            | EventSource::TestCommand
+            | EventSource::FileOperation
+            | EventSource::UserInteraction => EventKind::CommandRun,
+            EventSource::NetworkRequest => EventKind::Probe,
+        }
+    }
+
+    /// Column value stored in `latency_events.event_kind`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::ProcessSample => "process_sample",
+            EventKind::ModelRequest => "model_request",
+            EventKind::CommandRun => "command_run",
+            EventKind::Probe => "probe",
+            EventKind::Lifecycle => "lifecycle",
+            EventKind::Annotation => "annotation",
+            EventKind::SuggestionOutcome => "suggestion_outcome",
+            EventKind::Attribution => "attribution",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+
+    /// Whether the event's duration is a latency worth aggregating.
+    pub fn carries_latency(&self) -> bool {
+        !matches!(
+            self,
+            EventKind::Lifecycle | EventKind::Annotation | EventKind::SuggestionOutcome | EventKind::Attribution
+        )
+    }
+
+    /// Lane the event travels in between collection and storage.
+    pub fn priority(&self) -> EventPriority {
+        match self {
+            EventKind::ModelRequest | EventKind::CommandRun | EventKind::Lifecycle => EventPriority::High,
+            EventKind::Probe | EventKind::Annotation | EventKind::SuggestionOutcome => EventPriority::Normal,
+            EventKind::ProcessSample | EventKind::Attribution => EventPriority::Low,
+        }
+    }
+}
+
+/// How urgently an event should reach storage. High-priority events are
+/// drained and flushed first and are the last to be spilled under memory
+/// pressure; alerts are evaluated on model and command latencies, so those
+/// must survive overload.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventPriority {
+    Low,
+    Normal,
+    High,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventKind::ProcessSample => write!(f, "Process Sample"),
+            EventKind::ModelRequest => write!(f, "Model Request"),
+            EventKind::CommandRun => write!(f, "Command Run"),
+            EventKind::Probe => write!(f, "Probe"),
+            EventKind::Lifecycle => write!(f, "Lifecycle"),
+            EventKind::Annotation => write!(f, "Annotation"),
+            EventKind::SuggestionOutcome => write!(f, "Suggestion Outcome"),
+            EventKind::Attribution => write!(f, "Attribution"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerformanceMetrics {
     pub component: ComponentType,
@@ -143,6 +437,11 @@ pub struct SystemStatus {
     pub uptime_seconds: u64,
     pub memory_usage_mb: u64,
     pub cpu_usage_percent: f32,
+    #[serde(default)]
+    pub lifecycle: LifecycleSummary,
+    /// Set while a recently reporting monitor is shedding load
+    #[serde(default)]
+    pub load_shedding: Option<LoadSheddingStatus>,
 }
 
 impl SystemStatus {
@@ -156,47 +455,427 @@ impl SystemStatus {
             uptime_seconds: 0,
             memory_usage_mb: 0,
             cpu_usage_percent: 0.0,
+            lifecycle: LifecycleSummary::default(),
+            load_shedding: None,
         }
     }
 }
 
+impl Default for SystemStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A named filter that can be re-run from the CLI, API and dashboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub filter: String,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A planned period (heavy build, OS upgrade) during which alerts are still
+/// evaluated and recorded but send no notifications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    #[serde(default)]
+    pub recurrence: Recurrence,
+    /// Rules silenced by the window; all of them when empty
+    #[serde(default)]
+    pub rules: Vec<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recurrence {
+    #[default]
+    Once,
+    Daily,
+    Weekly,
+}
+
+impl Recurrence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Once => "once",
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "once" => Some(Recurrence::Once),
+            "daily" => Some(Recurrence::Daily),
+            "weekly" => Some(Recurrence::Weekly),
+            _ => None,
+        }
+    }
+
+    fn period(&self) -> Option<chrono::Duration> {
+        match self {
+            Recurrence::Once => None,
+            Recurrence::Daily => Some(chrono::Duration::days(1)),
+            Recurrence::Weekly => Some(chrono::Duration::weeks(1)),
+        }
+    }
+}
+
+impl MaintenanceWindow {
+    /// Reject windows that end before they start, or recur more often than
+    /// they last.
+    pub fn validate(&self) -> Result<()> {
+        if self.ends_at <= self.starts_at {
+            return Err(anyhow!("Maintenance window '{}' must end after it starts", self.name));
+        }
+        if let Some(period) = self.recurrence.period() {
+            if self.ends_at - self.starts_at >= period {
+                return Err(anyhow!(
+                    "Maintenance window '{}' lasts longer than its {} recurrence",
+                    self.name,
+                    self.recurrence.as_str()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `at` falls in this window or, for recurring windows, in one
+    /// of its repeats since `starts_at`.
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        if at < self.starts_at {
+            return false;
+        }
+        match self.recurrence.period() {
+            None => at < self.ends_at,
+            Some(period) => {
+                let offset = (at - self.starts_at).num_seconds() % period.num_seconds();
+                offset < (self.ends_at - self.starts_at).num_seconds()
+            }
+        }
+    }
+
+    /// Whether notifications of `rule` are held back at `at`.
+    pub fn silences(&self, rule: &str, at: DateTime<Utc>) -> bool {
+        (self.rules.is_empty() || self.rules.iter().any(|r| r == rule)) && self.is_active(at)
+    }
+}
+
+/// One alert state change as kept in the alert history, whether or not a
+/// notification went out for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertHistoryEntry {
+    pub rule: String,
+    /// `firing` or `resolved`
+    pub state: String,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+    pub at: DateTime<Utc>,
+    /// Maintenance window that suppressed the notification
+    pub suppressed_by: Option<String>,
+}
+
+/// Latency of one component within one group of events, e.g. one VS Code
+/// build. `group` is `None` for events without a value for the grouping
+/// column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupedMetrics {
+    pub group: Option<String>,
+    pub component: ComponentType,
+    pub total_events: u64,
+    pub avg_duration_ms: f64,
+    pub p50_duration_ms: u64,
+    pub p95_duration_ms: u64,
+    pub max_duration_ms: u64,
+}
+
+/// CPU attributed to one extension over a window, from the samples the
+/// extension attribution collector records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionCpu {
+    pub extension_id: String,
+    pub samples: u64,
+    pub avg_cpu_percent: f64,
+    pub max_cpu_percent: f64,
+    /// `process`, `inspector`, or both, joined by commas
+    pub attribution: String,
+}
+
+/// A process seen in process samples, for the process list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessSummary {
+    pub pid: u32,
+    pub process_name: String,
+    pub component: ComponentType,
+    pub samples: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub avg_cpu_percent: f64,
+    pub max_cpu_percent: f64,
+    pub max_memory_kb: u64,
+}
+
+/// One process sample on a process timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessTimelinePoint {
+    pub event_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub process_name: String,
+    pub cpu_percent: f64,
+    pub memory_kb: u64,
+}
+
+/// A named API bearer token. The secret itself is never stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Whose database the token reaches under `dashboard.tenancy`; `None`
+    /// for the dashboard's own
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// Cumulative latency histogram for one component (Prometheus semantics:
+/// `bucket_counts[i]` counts observations `<=` `bounds_us[i]`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub component: ComponentType,
+    pub bounds_us: Vec<u64>,
+    pub bucket_counts: Vec<u64>,
+    pub count: u64,
+    pub sum_us: u64,
+}
+
+/// Latency aggregates for one component over one time bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    pub bucket_start: DateTime<Utc>,
+    pub component: ComponentType,
+    pub count: u64,
+    pub avg_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub p95_ms: f64,
+}
+
+/// Latency aggregates for one component over one UTC day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailySummary {
+    pub day: NaiveDate,
+    pub component: ComponentType,
+    pub event_count: u64,
+    pub avg_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Inline suggestion counts for one extension, latency band and UTC hour.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestionHour {
+    pub hour: DateTime<Utc>,
+    /// Extension id, empty when the suggestion didn't name one
+    pub extension: String,
+    /// Index into `suggestions::LATENCY_BANDS_MS`
+    pub band: usize,
+    pub shown: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Summed latency of the suggestions shown
+    pub latency_ms_total: f64,
+    pub accepted_latency_ms_total: f64,
+}
+
+/// Events soft-deleted together by one delete or prune, restorable until
+/// purged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletedBatch {
+    pub deleted_at: DateTime<Utc>,
+    pub events: u64,
+    pub first_event: DateTime<Utc>,
+    pub last_event: DateTime<Utc>,
+}
+
+/// A deletion or restore of one event, replayed on the central backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventDeletion {
+    pub uid: String,
+    /// When the event was deleted; `None` for a restore
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// One evaluation of a derived metric expression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedMetricSample {
+    pub name: String,
+    pub value: f64,
+    pub at: DateTime<Utc>,
+}
+
+/// An event picked to illustrate a histogram bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyExemplar {
+    pub event_id: i64,
+    /// Trace id of the event's OTLP span
+    pub trace_id: Option<String>,
+    pub component: ComponentType,
+    pub duration_us: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One attempt to deliver a webhook payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Option<i64>,
+    /// Shared by all attempts of the same payload
+    pub delivery_id: String,
+    pub webhook: String,
+    pub event_type: String,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// One administrative action (config change, purge, retention run, ...)
+/// recorded in the append-only audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Option<i64>,
+    pub timestamp: DateTime<Utc>,
+    /// Who performed the action, e.g. `cli:alice` or `api:<token>`
+    pub actor: String,
+    /// Dotted action name, e.g. `config.set` or `queries.delete`
+    pub action: String,
+    pub target: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, action: &str, target: Option<&str>) -> Self {
+        Self {
+            id: None,
+            timestamp: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.map(str::to_string),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn with_change(mut self, before: Option<serde_json::Value>, after: Option<serde_json::Value>) -> Self {
+        self.before = before;
+        self.after = after;
+        self
+    }
+
+    /// Actor for commands run from the CLI: the local user name.
+    pub fn cli_actor() -> String {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        format!("cli:{}", user)
+    }
+}
+
+/// Output throughput of one streamed model response, from `model-proxy`
+/// or the Ollama probe. Stored in `token_metrics`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenMetrics {
+    /// What observed the response: `model_proxy` or `ollama_probe`
+    pub source: String,
+    /// Provider the response came from, e.g. `openai` or `ollama`
+    pub provider: String,
     pub model_type: String,
+    /// Tokens in the generated text by `tokens::estimate_tokens`, comparable
+    /// across providers
     pub estimated_tokens: u32,
+    /// Output tokens the API itself reported, when it did
+    #[serde(default)]
+    pub reported_tokens: Option<u32>,
+    /// From the first token to the end of the response
     pub generation_time_ms: u64,
-    pub tokens_per_second: f64,
+    #[serde(default)]
+    pub time_to_first_token_ms: Option<u64>,
+    /// Estimated tokens per second of `generation_time_ms`; `None` when the
+    /// whole response arrived at once and there is no time to divide by
+    #[serde(default)]
+    pub tokens_per_second: Option<f64>,
+    /// Characters of prompt text
     pub prompt_length: usize,
+    /// Characters of generated text
     pub response_length: usize,
     pub timestamp: DateTime<Utc>,
 }
 
 impl TokenMetrics {
     pub fn new(
+        source: &str,
+        provider: &str,
         model_type: String,
-        estimated_tokens: u32,
+        response: &str,
         generation_time_ms: u64,
         prompt_length: usize,
-        response_length: usize,
     ) -> Self {
-        let tokens_per_second = if generation_time_ms > 0 {
-            (estimated_tokens as f64) / (generation_time_ms as f64 / 1000.0)
-        } else {
-            0.0
-        };
+        let estimated_tokens = tokens::estimate_tokens(response);
+        let tokens_per_second =
+            (generation_time_ms > 0).then(|| estimated_tokens as f64 / (generation_time_ms as f64 / 1000.0));
 
         Self {
+            source: source.to_string(),
+            provider: provider.to_string(),
             model_type,
             estimated_tokens,
+            reported_tokens: None,
             generation_time_ms,
+            time_to_first_token_ms: None,
             tokens_per_second,
             prompt_length,
-            response_length,
+            response_length: response.chars().count(),
             timestamp: Utc::now(),
         }
     }
 }
 
+/// Token throughput of one model from one source over a window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelThroughput {
+    pub source: String,
+    pub provider: String,
+    pub model_type: String,
+    pub responses: u64,
+    pub estimated_tokens: u64,
+    /// Sum of the reported output tokens, over the responses that had them
+    pub reported_tokens: Option<u64>,
+    /// Throughput statistics leave out responses that arrived at once; all
+    /// three are `None` when every response did
+    pub avg_tokens_per_second: Option<f64>,
+    pub p50_tokens_per_second: Option<f64>,
+    /// Slowest 5%: the throughput 95% of responses beat
+    pub p5_tokens_per_second: Option<f64>,
+    pub avg_time_to_first_token_ms: Option<f64>,
+    pub p95_time_to_first_token_ms: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandLatency {
     pub command: String,
@@ -237,6 +916,67 @@ impl CommandLatency {
     }
 }
 
+/// Metadata of process samples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessMetadata {
+    pub pid: u32,
+    pub process_name: String,
+    pub cpu_percent: f32,
+    pub memory_mb: f64,
+    /// Resident memory; older samples only have `memory_mb`
+    #[serde(default)]
+    pub memory_kb: u64,
+    /// Full command line, arguments joined by spaces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+}
+
+/// A metadata field promoted to a generated column of `latency_events`, so
+/// it can be filtered, indexed and selected like any other column.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetadataColumn {
+    pub name: &'static str,
+    pub sql_type: &'static str,
+    /// Top-level metadata key the column is extracted from
+    pub key: &'static str,
+}
+
+impl MetadataColumn {
+    pub fn is_numeric(&self) -> bool {
+        self.sql_type != "TEXT"
+    }
+}
+
+/// `ProcessMetadata` fields and the token count of model interactions.
+pub const METADATA_COLUMNS: &[MetadataColumn] = &[
+    MetadataColumn { name: "pid", sql_type: "INTEGER", key: "pid" },
+    MetadataColumn { name: "process_name", sql_type: "TEXT", key: "process_name" },
+    MetadataColumn { name: "cmdline", sql_type: "TEXT", key: "cmdline" },
+    MetadataColumn { name: "cpu_percent", sql_type: "REAL", key: "cpu_percent" },
+    MetadataColumn { name: "memory_mb", sql_type: "REAL", key: "memory_mb" },
+    MetadataColumn { name: "memory_kb", sql_type: "INTEGER", key: "memory_kb" },
+    MetadataColumn { name: "tokens", sql_type: "INTEGER", key: "estimated_tokens" },
+    MetadataColumn { name: "vscode_build", sql_type: "TEXT", key: "vscode_build" },
+    MetadataColumn { name: "vscode_profile", sql_type: "TEXT", key: "vscode_profile" },
+    MetadataColumn { name: "vscode_window", sql_type: "TEXT", key: "vscode_window" },
+    MetadataColumn { name: "language_server", sql_type: "TEXT", key: "language_server" },
+    MetadataColumn { name: "lsp_method", sql_type: "TEXT", key: "lsp_method" },
+    MetadataColumn { name: "trace_id", sql_type: "TEXT", key: "trace_id" },
+    MetadataColumn { name: "parent_span_id", sql_type: "TEXT", key: "parent_span_id" },
+    MetadataColumn { name: "test_data", sql_type: "INTEGER", key: "test_data" },
+    MetadataColumn { name: "extension_id", sql_type: "TEXT", key: "extension_id" },
+    MetadataColumn { name: "cargo_command", sql_type: "TEXT", key: "cargo_command" },
+    MetadataColumn { name: "crate_name", sql_type: "TEXT", key: "crate_name" },
+    MetadataColumn { name: "incremental", sql_type: "INTEGER", key: "incremental" },
+    MetadataColumn { name: "editor", sql_type: "TEXT", key: "editor" },
+    MetadataColumn { name: "workspace", sql_type: "TEXT", key: "workspace" },
+    MetadataColumn { name: "network_interface", sql_type: "TEXT", key: "network_interface" },
+    MetadataColumn { name: "link_type", sql_type: "TEXT", key: "link_type" },
+    MetadataColumn { name: "ssid", sql_type: "TEXT", key: "ssid" },
+    MetadataColumn { name: "signal_dbm", sql_type: "INTEGER", key: "signal_dbm" },
+    MetadataColumn { name: "suggestion", sql_type: "TEXT", key: "suggestion" },
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInteraction {
     pub model_type: String,
@@ -249,6 +989,8 @@ pub struct ModelInteraction {
     pub error_message: Option<String>,
     pub timestamp: DateTime<Utc>,
     pub context_length: Option<usize>,
+    #[serde(default)]
+    pub time_to_first_token_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -292,6 +1034,7 @@ impl ModelInteraction {
             error_message: None,
             timestamp: Utc::now(),
             context_length: None,
+            time_to_first_token_ms: None,
         }
     }
 
@@ -1,62 +1,148 @@
 use anyhow::Result;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::watch;
 use tokio::time::sleep;
 use tracing::{debug, info, warn};
-use sysinfo::System;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use sysinfo::{Pid, Process, System};
+use crossbeam_channel::TryRecvError;
 
 use crate::storage::MetricsStorage;
-use crate::config::Config;
-use crate::models::{LatencyEvent, ComponentType, EventSource};
+use crate::config::{Config, OllamaProbeConfig};
+use crate::models::{LatencyEvent, ComponentType, EventSource, EventKind, ModelInteraction, ModelInteractionType, ProcessMetadata, TokenMetrics};
+use crate::lifecycle::{LifecycleKind, ProcessLifecycleTracker, TrackedProcessKind};
+use crate::cmdline::CmdlineConfig;
+use crate::daemon;
+use crate::platform::{self, VSCodeRole};
+use crate::proxy;
+use crate::loopback;
+use crate::netprobe;
+use crate::editors;
+use crate::extensions;
+use crate::event_queue::EventQueue;
+use crate::shutdown::{SessionSummary, Shutdown};
+use crate::workspace::Workspaces;
+
+/// How often the storage task checks the channel when it is empty.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often queue depth is checked against the load-shedding thresholds.
+const LOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long collectors get to stop at shutdown before they are aborted.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// What `start --component` accepts.
+pub const COMPONENTS: &[&str] = &["vscode", "models", "terminal", "lsp", "disk", "network", "editors", "all"];
 
 pub struct LatencyMonitor {
     config: Config,
+    /// Latest config, replaced on reload; collectors read their interval and
+    /// whether they are enabled from it every round
+    settings: watch::Receiver<Config>,
     storage: MetricsStorage,
-    event_sender: Sender<LatencyEvent>,
-    event_receiver: Receiver<LatencyEvent>,
-    system: System,
+    events: EventQueue,
+    /// Workspaces extension hosts announced over IPC
+    workspaces: Workspaces,
+    /// Stops the collector tasks when the `run_*` loop exits
+    shutdown: Shutdown,
+    /// Events stored by the `run_*` loop
+    summary: Arc<Mutex<SessionSummary>>,
     running: bool,
 }
 
 impl LatencyMonitor {
     pub async fn new(config: Config, storage: MetricsStorage) -> Result<Self> {
-        let (sender, receiver) = unbounded();
-        let mut system = System::new_all();
-        system.refresh_all();
+        let budget = &config.monitoring.memory_budget;
+        let spill_path = budget
+            .spill_path
+            .clone()
+            .unwrap_or_else(|| daemon::runtime_dir(&config).join("spill.jsonl"));
+        let events = EventQueue::new(
+            budget,
+            &config.monitoring.load_shedding,
+            config.monitoring.buffer_size,
+            config.monitoring.overflow_policy,
+            spill_path,
+            config.storage.io_backend,
+        )?;
+        let (_, settings) = watch::channel(config.clone());
 
         Ok(Self {
             config,
+            settings,
             storage,
-            event_sender: sender,
-            event_receiver: receiver,
-            system,
+            events,
+            workspaces: Workspaces::default(),
+            shutdown: Shutdown::default(),
+            summary: Arc::new(Mutex::new(SessionSummary::default())),
             running: false,
         })
     }
 
-    pub async fn start_vscode_monitoring(&mut self, interval_ms: u64) -> Result<()> {
+    /// Apply configs published on `updates` (by [`crate::reload`]) to
+    /// collectors started after this call.
+    pub fn follow_config(&mut self, updates: &watch::Sender<Config>) {
+        self.settings = updates.subscribe();
+    }
+
+    /// Tag samples with the workspaces extensions announce on `workspaces`,
+    /// the registry [`crate::ipc::serve`] records them in.
+    pub fn follow_workspaces(&mut self, workspaces: &Workspaces) {
+        self.workspaces = workspaces.clone();
+    }
+
+    /// Stop the tasks spawned on `shutdown` together with the collectors,
+    /// so whatever they queue is stored by the final flush.
+    pub fn share_shutdown(&mut self, shutdown: &Shutdown) {
+        self.shutdown = shutdown.clone();
+    }
+
+    /// Sample VS Code processes every `interval_ms`, or every
+    /// `monitoring.interval_ms` of the current config when `None`.
+    pub async fn start_vscode_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
         info!("Starting VS Code process monitoring");
+
+        if self.config.monitoring.loopback.enabled {
+            let loopback = self.config.monitoring.loopback.clone();
+            let events = self.events.clone();
+            self.shutdown.spawn(async move {
+                if let Err(e) = loopback::run(loopback, events).await {
+                    warn!("Loopback latency probe stopped: {}", e);
+                }
+            });
+        }
+        if self.config.monitoring.extensions.enabled {
+            self.start_extension_attribution();
+        }
         
-        let sender = self.event_sender.clone();
-        let interval = Duration::from_millis(interval_ms);
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let workspaces = self.workspaces.clone();
+        let sampling = Sampling::new(self.settings.clone(), "vscode", interval_ms);
+        let mut lifecycle = ProcessLifecycleTracker::new(
+            self.config.monitoring.crash_loop_threshold,
+            self.config.monitoring.crash_loop_window_secs,
+        );
         
-        tokio::spawn(async move {
+        self.shutdown.spawn(async move {
             loop {
+                if !sampling.enabled() {
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
                 let start_time = Instant::now();
                 
                 // Monitor VS Code processes
                 let mut system = System::new_all();
                 system.refresh_processes();
+                workspaces.retain(|pid| system.process(Pid::from_u32(pid)).is_some());
                 
                 let vscode_processes: Vec<_> = system.processes()
                     .iter()
-                    .filter(|(_, proc)| {
-                        let name = proc.name().to_lowercase();
-                        name.contains("code") && 
-                        (name.contains("code-server") || 
-                         name.contains("code.exe") || 
-                         name == "code")
-                    })
+                    .filter(|(_, proc)| platform::is_vscode(proc))
                     .collect();
 
                 for (pid, process) in &vscode_processes {
@@ -70,7 +156,8 @@ impl LatencyMonitor {
                         start_time.elapsed(),
                         format!("Process {} - CPU: {:.1}%, Memory: {}KB", 
                                 pid, cpu_usage, memory / 1024),
-                    );
+                    )
+                    .with_metadata(vscode_metadata(&system, **pid, process, &cmdline, &workspaces));
 
                     if let Err(e) = sender.send(event) {
                         warn!("Failed to send VS Code monitoring event: {}", e);
@@ -80,10 +167,7 @@ impl LatencyMonitor {
                 // Monitor VS Code extension host processes
                 let extension_hosts: Vec<_> = system.processes()
                     .iter()
-                    .filter(|(_, proc)| {
-                        proc.name().to_lowercase().contains("extensionhost") ||
-                        proc.cmd().iter().any(|arg| arg.contains("extensionHost"))
-                    })
+                    .filter(|(_, proc)| platform::vscode_role(proc) == Some(VSCodeRole::ExtensionHost))
                     .collect();
 
                 for (pid, process) in &extension_hosts {
@@ -92,28 +176,100 @@ impl LatencyMonitor {
                         EventSource::ExtensionHost,
                         start_time.elapsed(),
                         format!("Extension Host {} - CPU: {:.1}%", pid, process.cpu_usage()),
-                    );
+                    )
+                    .with_metadata(vscode_metadata(&system, **pid, process, &cmdline, &workspaces));
 
                     if let Err(e) = sender.send(event) {
                         warn!("Failed to send extension host event: {}", e);
                     }
                 }
 
-                sleep(interval).await;
+                // Detect extension host restarts/crashes and renderer reloads
+                for change in lifecycle.observe(&system) {
+                    let description = match change.lifecycle {
+                        LifecycleKind::ExtensionHostRestart => format!(
+                            "Extension Host restarted ({:?} -> {:?})",
+                            change.old_pid, change.new_pid
+                        ),
+                        LifecycleKind::ExtensionHostCrash => match (change.exit_code, &change.exit_signal) {
+                            (Some(code), _) => format!(
+                                "Extension Host {:?} exited with code {} while window was still open",
+                                change.old_pid, code
+                            ),
+                            (None, Some(signal)) => format!(
+                                "Extension Host {:?} was killed by {} while window was still open",
+                                change.old_pid, signal
+                            ),
+                            (None, None) => format!(
+                                "Extension Host {:?} exited while window was still open",
+                                change.old_pid
+                            ),
+                        },
+                        LifecycleKind::RendererReload => format!(
+                            "Renderer reloaded ({:?} -> {:?})",
+                            change.old_pid, change.new_pid
+                        ),
+                        LifecycleKind::CrashLoop => format!(
+                            "Extension Host crash loop detected ({} restarts or crashes)",
+                            change.failures.unwrap_or_default()
+                        ),
+                    };
+
+                    let component = match change.process_kind {
+                        TrackedProcessKind::ExtensionHost => ComponentType::VSCodeExtension,
+                        TrackedProcessKind::Renderer => ComponentType::VSCode,
+                    };
+
+                    // Lifecycle events don't carry latency; their duration is
+                    // how long the old process ran
+                    let metadata = serde_json::to_value(&change).unwrap_or(serde_json::Value::Null);
+                    let event = LatencyEvent::new(
+                        component,
+                        EventSource::ExtensionHost,
+                        change.lifetime().unwrap_or(Duration::ZERO),
+                        description,
+                    )
+                    .with_kind(EventKind::Lifecycle)
+                    .with_metadata(metadata);
+
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send lifecycle event: {}", e);
+                    }
+                }
+
+                sleep(sender.sampling_interval(sampling.interval())).await;
             }
         });
 
         Ok(())
     }
 
-    pub async fn start_model_monitoring(&mut self, interval_ms: u64) -> Result<()> {
+    pub async fn start_model_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
         info!("Starting AI model interaction monitoring");
+
+        if self.config.monitoring.ollama_probe.enabled {
+            self.start_ollama_probe();
+        }
+        if self.config.monitoring.copilot_proxy.enabled {
+            let proxy = self.config.monitoring.copilot_proxy.clone();
+            let events = self.events.clone();
+            self.shutdown.spawn(async move {
+                if let Err(e) = proxy::serve(proxy, events).await {
+                    warn!("Copilot latency proxy stopped: {}", e);
+                }
+            });
+        }
         
-        let sender = self.event_sender.clone();
-        let interval = Duration::from_millis(interval_ms);
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let sampling = Sampling::new(self.settings.clone(), "models", interval_ms).slower(2);
         
-        tokio::spawn(async move {
+        self.shutdown.spawn(async move {
             loop {
+                if !sampling.enabled() {
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
                 let start_time = Instant::now();
                 
                 // Monitor GitHub Copilot processes
@@ -123,16 +279,7 @@ impl LatencyMonitor {
                 // Look for Copilot-related processes
                 let copilot_processes: Vec<_> = system.processes()
                     .iter()
-                    .filter(|(_, proc)| {
-                        let name = proc.name().to_lowercase();
-                        let cmd_line = proc.cmd()
-                            .join(" ")
-                            .to_lowercase();
-                        
-                        name.contains("copilot") || 
-                        cmd_line.contains("github.copilot") ||
-                        cmd_line.contains("copilot-agent")
-                    })
+                    .filter(|(_, proc)| platform::is_copilot(proc))
                     .collect();
 
                 for (pid, process) in &copilot_processes {
@@ -141,7 +288,8 @@ impl LatencyMonitor {
                         EventSource::ModelProcess,
                         start_time.elapsed(),
                         format!("Copilot Process {} - CPU: {:.1}%", pid, process.cpu_usage()),
-                    );
+                    )
+                    .with_metadata(process_metadata(**pid, process, &cmdline));
 
                     if let Err(e) = sender.send(event) {
                         warn!("Failed to send Copilot monitoring event: {}", e);
@@ -167,7 +315,8 @@ impl LatencyMonitor {
                             start_time.elapsed(),
                             format!("Local Model ({}) {} - CPU: {:.1}%", 
                                     pattern, pid, process.cpu_usage()),
-                        );
+                        )
+                        .with_metadata(process_metadata(**pid, process, &cmdline));
 
                         if let Err(e) = sender.send(event) {
                             warn!("Failed to send local model event: {}", e);
@@ -175,21 +324,100 @@ impl LatencyMonitor {
                     }
                 }
 
-                sleep(interval).await;
+                sleep(sender.sampling_interval(sampling.interval())).await;
             }
         });
 
         Ok(())
     }
 
-    pub async fn start_terminal_monitoring(&mut self, interval_ms: u64) -> Result<()> {
+    /// Periodically time a tiny generation against the Ollama API, recording
+    /// time-to-first-token and total duration as model requests.
+    fn start_ollama_probe(&self) {
+        let probe = self.config.monitoring.ollama_probe.clone();
+        let sender = self.events.clone();
+        let storage = self.storage.clone();
+        info!("Probing Ollama model {} at {} every {}s", probe.model, probe.url, probe.interval_secs);
+
+        self.shutdown.spawn(async move {
+            let client = match reqwest::Client::builder()
+                .timeout(Duration::from_secs(probe.timeout_secs.max(1)))
+                .build()
+            {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Failed to create Ollama probe client: {}", e);
+                    return;
+                }
+            };
+            let mut ticker = tokio::time::interval(Duration::from_secs(probe.interval_secs.max(1)));
+
+            loop {
+                ticker.tick().await;
+
+                let started = Instant::now();
+                let interaction = match probe_ollama(&client, &probe).await {
+                    Ok((interaction, tokens)) => {
+                        if let Some(tokens) = tokens {
+                            if let Err(e) = storage.store_token_metrics(&tokens).await {
+                                warn!("Failed to store Ollama token throughput: {}", e);
+                            }
+                        }
+                        interaction
+                    }
+                    Err(e) => {
+                        debug!("Ollama probe failed: {}", e);
+                        let mut failed = ModelInteraction::new(
+                            probe.model.clone(),
+                            ModelInteractionType::Completion,
+                            started.elapsed().as_millis() as u64,
+                            false,
+                        );
+                        failed.error_message = Some(e.to_string());
+                        failed
+                    }
+                };
+
+                let mut metadata = serde_json::to_value(&interaction).unwrap_or_default();
+                metadata["probe"] = serde_json::json!("ollama");
+                metadata["tokens_per_second"] = serde_json::json!(interaction.tokens_per_second());
+
+                let event = LatencyEvent::new(
+                    ComponentType::LocalModel,
+                    EventSource::NetworkRequest,
+                    Duration::from_millis(interaction.duration_ms),
+                    format!(
+                        "Ollama probe ({}) ttft {}ms",
+                        interaction.model_type,
+                        interaction
+                            .time_to_first_token_ms
+                            .map(|t| t.to_string())
+                            .unwrap_or_else(|| "-".to_string())
+                    ),
+                )
+                .with_kind(EventKind::ModelRequest)
+                .with_metadata(metadata);
+
+                if let Err(e) = sender.send(event) {
+                    warn!("Failed to send Ollama probe event: {}", e);
+                }
+            }
+        });
+    }
+
+    pub async fn start_terminal_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
         info!("Starting terminal command monitoring");
         
-        let sender = self.event_sender.clone();
-        let interval = Duration::from_millis(interval_ms);
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let sampling = Sampling::new(self.settings.clone(), "terminal", interval_ms);
         
-        tokio::spawn(async move {
+        self.shutdown.spawn(async move {
             loop {
+                if !sampling.enabled() {
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
                 let start_time = Instant::now();
                 
                 // Monitor terminal processes
@@ -198,12 +426,7 @@ impl LatencyMonitor {
                 
                 let terminal_processes: Vec<_> = system.processes()
                     .iter()
-                    .filter(|(_, proc)| {
-                        let name = proc.name().to_lowercase();
-                        name == "bash" || name == "zsh" || name == "fish" || 
-                        name == "sh" || name.contains("terminal") ||
-                        name.contains("gnome-terminal") || name.contains("konsole")
-                    })
+                    .filter(|(_, proc)| platform::is_terminal(proc))
                     .collect();
 
                 for (pid, process) in &terminal_processes {
@@ -213,7 +436,8 @@ impl LatencyMonitor {
                             EventSource::ProcessMonitor,
                             start_time.elapsed(),
                             format!("Terminal {} - CPU: {:.1}%", pid, process.cpu_usage()),
-                        );
+                        )
+                        .with_metadata(process_metadata(**pid, process, &cmdline));
 
                         if let Err(e) = sender.send(event) {
                             warn!("Failed to send terminal monitoring event: {}", e);
@@ -221,66 +445,568 @@ impl LatencyMonitor {
                     }
                 }
 
-                sleep(interval).await;
+                sleep(sender.sampling_interval(sampling.interval())).await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Sample the processes of every active editor profile other than VS
+    /// Code, and read the latencies their logs report. Profiles are re-read
+    /// every round, so a reload can switch editors.
+    pub async fn start_editor_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
+        info!("Starting editor monitoring");
+
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let settings = self.settings.clone();
+        let sampling = Sampling::new(self.settings.clone(), "editors", interval_ms);
+        let mut tailer = editors::LogTailer::default();
+
+        self.shutdown.spawn(async move {
+            loop {
+                let profiles = settings.borrow().monitoring.editors.generic_profiles();
+                if !sampling.enabled() || profiles.is_empty() {
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
+                let start_time = Instant::now();
+
+                let mut system = System::new_all();
+                system.refresh_processes();
+
+                for (pid, process) in system.processes() {
+                    let Some(profile) = profiles.iter().find(|profile| profile.matches(process)) else {
+                        continue;
+                    };
+                    let mut metadata = process_metadata(*pid, process, &cmdline);
+                    metadata["editor"] = serde_json::json!(profile.name);
+                    let event = LatencyEvent::new(
+                        ComponentType::Editor,
+                        EventSource::ProcessMonitor,
+                        start_time.elapsed(),
+                        format!("{} {} - CPU: {:.1}%", profile.label(), pid, process.cpu_usage()),
+                    )
+                    .with_metadata(metadata);
+
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send editor monitoring event: {}", e);
+                    }
+                }
+
+                // Reading logs is file I/O, so it runs on the blocking pool
+                let logs = profiles.clone();
+                let polled = tokio::task::spawn_blocking(move || {
+                    let mut latencies = Vec::new();
+                    for (index, profile) in logs.iter().enumerate() {
+                        for log in &profile.logs {
+                            latencies.extend(tailer.poll(log).into_iter().map(|latency| (index, latency)));
+                        }
+                    }
+                    (tailer, latencies)
+                })
+                .await;
+                let latencies = match polled {
+                    Ok((polled_tailer, latencies)) => {
+                        tailer = polled_tailer;
+                        latencies
+                    }
+                    Err(e) => {
+                        warn!("Reading editor logs failed: {}", e);
+                        tailer = editors::LogTailer::default();
+                        Vec::new()
+                    }
+                };
+
+                for (index, latency) in latencies {
+                    let profile = &profiles[index];
+                    let event = LatencyEvent::new(
+                        ComponentType::Editor,
+                        EventSource::UserInteraction,
+                        latency.duration,
+                        format!("{} {}", profile.label(), latency.operation.as_deref().unwrap_or("operation")),
+                    )
+                    .with_kind(EventKind::CommandRun)
+                    .with_metadata(serde_json::json!({
+                        "editor": profile.name,
+                        "operation": latency.operation,
+                        "log": latency.path,
+                        "line": latency.line,
+                    }));
+
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send editor log event: {}", e);
+                    }
+                }
+
+                sleep(sender.sampling_interval(sampling.interval())).await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Sample the CPU and memory of language servers VS Code started. Their
+    /// request latency comes from `lsp-proxy`, over IPC.
+    pub async fn start_language_server_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
+        info!("Starting language server monitoring");
+
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let workspaces = self.workspaces.clone();
+        let sampling = Sampling::new(self.settings.clone(), "lsp", interval_ms);
+
+        self.shutdown.spawn(async move {
+            loop {
+                if !sampling.enabled() {
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
+                let start_time = Instant::now();
+
+                let mut system = System::new_all();
+                system.refresh_processes();
+
+                let servers: Vec<_> = system
+                    .processes()
+                    .iter()
+                    .filter_map(|(pid, process)| Some((pid, process, platform::language_server(process)?)))
+                    .filter(|(_, process, _)| platform::spawned_by_vscode(&system, process))
+                    .collect();
+
+                for (pid, process, server) in &servers {
+                    let mut metadata = process_metadata(**pid, process, &cmdline);
+                    metadata["language_server"] = serde_json::json!(server);
+                    if let Some(workspace) = platform::vscode_workspace(&system, process, &workspaces) {
+                        workspace.apply(&mut metadata);
+                    }
+
+                    let event = LatencyEvent::new(
+                        ComponentType::LanguageServer,
+                        EventSource::ProcessMonitor,
+                        start_time.elapsed(),
+                        format!(
+                            "Language server {} ({}) - CPU: {:.1}%, Memory: {}KB",
+                            server,
+                            pid,
+                            process.cpu_usage(),
+                            process.memory() / 1024
+                        ),
+                    )
+                    .with_metadata(metadata);
+
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send language server event: {}", e);
+                    }
+                }
+
+                sleep(sender.sampling_interval(sampling.interval())).await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Sample how much VS Code's processes read and write and how long they
+    /// were blocked on disk I/O, and probe the workspace disk with a small
+    /// write + fsync. Disk stalls freeze the editor without using any CPU.
+    pub async fn start_disk_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
+        info!("Starting disk I/O monitoring");
+
+        if self.config.monitoring.disk.probe_enabled {
+            self.start_disk_probe();
+        }
+
+        let sender = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        // Byte counts are per interval, and a 100ms window is mostly empty
+        let sampling = Sampling::new(self.settings.clone(), "disk", interval_ms).slower(10);
+
+        self.shutdown.spawn(async move {
+            // Kept between passes: sysinfo reports bytes read and written
+            // since the previous refresh
+            let mut system = System::new();
+            let mut io_waits: HashMap<Pid, Duration> = HashMap::new();
+            let mut cpu_ticks = platform::cpu_iowait_ticks();
+            let mut primed = false;
+
+            loop {
+                if !sampling.enabled() {
+                    primed = false;
+                    sleep(sampling.interval()).await;
+                    continue;
+                }
+                let start_time = Instant::now();
+                system.refresh_processes();
+
+                let ticks = platform::cpu_iowait_ticks();
+                let system_iowait_percent = match (cpu_ticks, ticks) {
+                    (Some((wait_before, total_before)), Some((wait, total))) if total > total_before => {
+                        Some(wait.saturating_sub(wait_before) as f64 / (total - total_before) as f64 * 100.0)
+                    }
+                    _ => None,
+                };
+                cpu_ticks = ticks;
+
+                let mut seen = HashMap::new();
+                for (pid, process) in system.processes().iter().filter(|(_, proc)| platform::is_vscode(proc)) {
+                    let usage = process.disk_usage();
+                    let total_wait = platform::io_wait(pid.as_u32());
+                    let blocked = total_wait
+                        .zip(io_waits.get(pid))
+                        .map(|(now, before)| now.saturating_sub(*before));
+                    if let Some(total_wait) = total_wait {
+                        seen.insert(*pid, total_wait);
+                    }
+                    if !primed
+                        || (usage.read_bytes == 0 && usage.written_bytes == 0 && blocked.unwrap_or_default().is_zero())
+                    {
+                        continue;
+                    }
+
+                    let mut metadata = process_metadata(*pid, process, &cmdline);
+                    metadata["read_bytes"] = serde_json::json!(usage.read_bytes);
+                    metadata["written_bytes"] = serde_json::json!(usage.written_bytes);
+                    metadata["io_wait_ms"] = serde_json::json!(blocked.map(|b| b.as_secs_f64() * 1000.0));
+                    metadata["system_iowait_percent"] = serde_json::json!(system_iowait_percent);
+                    if platform::vscode_role(process) == Some(VSCodeRole::ExtensionHost) {
+                        metadata["extension_host"] = serde_json::json!(true);
+                    }
+
+                    // Time blocked on I/O where the kernel reports it, else
+                    // the sampling pass like other process samples
+                    let event = LatencyEvent::new(
+                        ComponentType::FileSystem,
+                        EventSource::ProcessMonitor,
+                        blocked.unwrap_or_else(|| start_time.elapsed()),
+                        format!(
+                            "Disk I/O {} ({}) - Read: {}KB, Written: {}KB",
+                            process.name(),
+                            pid,
+                            usage.read_bytes / 1024,
+                            usage.written_bytes / 1024
+                        ),
+                    )
+                    .with_metadata(metadata);
+
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send disk I/O event: {}", e);
+                    }
+                }
+                io_waits = seen;
+                primed = true;
+
+                sleep(sender.sampling_interval(sampling.interval())).await;
             }
         });
 
         Ok(())
     }
 
-    pub async fn start_all_monitoring(&mut self, interval_ms: u64) -> Result<()> {
+    /// Time DNS, TCP connect and HTTPS `HEAD` against each
+    /// `monitoring.network` target every `interval_secs`, while `network`
+    /// is in `monitoring.enabled_components`.
+    pub fn start_network_monitoring(&self) {
+        let probes = self.config.monitoring.network.clone();
+        let sender = self.events.clone();
+        let sampling = Sampling::new(self.settings.clone(), "network", None);
+        info!("Probing {} network targets every {}s", probes.targets.len(), probes.interval_secs);
+
+        self.shutdown.spawn(async move {
+            let client = match probes.client() {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Failed to create network probe client: {}", e);
+                    return;
+                }
+            };
+            let mut ticker = tokio::time::interval(Duration::from_secs(probes.interval_secs.max(1)));
+
+            loop {
+                ticker.tick().await;
+                if !sampling.enabled() {
+                    continue;
+                }
+
+                for event in netprobe::probe_all(&client, &probes).await {
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send network probe event: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Charge extension host CPU to individual extensions every
+    /// `monitoring.extensions.interval_secs`, while `vscode` is enabled.
+    fn start_extension_attribution(&self) {
+        let attribution = self.config.monitoring.extensions.clone();
+        let sender = self.events.clone();
+        let sampling = Sampling::new(self.settings.clone(), "vscode", None);
+        info!("Attributing extension host CPU to extensions every {}s", attribution.interval_secs);
+
+        self.shutdown.spawn(async move {
+            // Kept between rounds so CPU usage covers the whole interval
+            let mut system = System::new_all();
+            let mut ticker = tokio::time::interval(Duration::from_secs(attribution.interval_secs.max(1)));
+
+            loop {
+                ticker.tick().await;
+                if !sampling.enabled() {
+                    continue;
+                }
+
+                system.refresh_processes();
+                for event in extensions::attribute(&system, &attribution).await {
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send extension attribution event: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically write `probe_bytes` to a scratch file and fsync it,
+    /// recording how long the disk took as a file operation probe.
+    fn start_disk_probe(&self) {
+        let disk = self.config.monitoring.disk.clone();
+        let sender = self.events.clone();
+        let dir = disk
+            .probe_dir
+            .clone()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(std::env::temp_dir);
+        info!("Probing disk latency in {} every {}s", dir.display(), disk.probe_interval_secs);
+
+        self.shutdown.spawn(async move {
+            let path = dir.join(format!(".vscode-latency-probe-{}", std::process::id()));
+            let mut ticker = tokio::time::interval(Duration::from_secs(disk.probe_interval_secs.max(1)));
+
+            loop {
+                ticker.tick().await;
+
+                let (path, bytes) = (path.clone(), disk.probe_bytes);
+                let timings = tokio::task::spawn_blocking(move || probe_disk(&path, bytes)).await;
+                let (write, fsync) = match timings {
+                    Ok(Ok(timings)) => timings,
+                    Ok(Err(e)) => {
+                        debug!("Disk probe in {} failed: {}", dir.display(), e);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Disk probe task failed: {}", e);
+                        continue;
+                    }
+                };
+
+                let event = LatencyEvent::new(
+                    ComponentType::FileSystem,
+                    EventSource::FileOperation,
+                    write + fsync,
+                    format!(
+                        "Disk probe write+fsync {}KB - fsync {:.1}ms",
+                        disk.probe_bytes / 1024,
+                        fsync.as_secs_f64() * 1000.0
+                    ),
+                )
+                .with_kind(EventKind::Probe)
+                .with_metadata(serde_json::json!({
+                    "probe": "fsync",
+                    "dir": dir,
+                    "bytes": disk.probe_bytes,
+                    "write_us": write.as_micros() as u64,
+                    "fsync_us": fsync.as_micros() as u64,
+                }));
+
+                if let Err(e) = sender.send(event) {
+                    warn!("Failed to send disk probe event: {}", e);
+                }
+            }
+        });
+    }
+
+    pub async fn start_all_monitoring(&mut self, interval_ms: Option<u64>) -> Result<()> {
         info!("Starting comprehensive monitoring for all components");
         
-        self.start_vscode_monitoring(interval_ms).await?;
-        self.start_model_monitoring(interval_ms * 2).await?; // Models less frequently
+        // Only when VS Code is among the editors; other editors are handled
+        // by the generic editor collector
+        if self.config.monitoring.editors.is_active(editors::VSCODE) {
+            self.start_vscode_monitoring(interval_ms).await?;
+        }
+        self.start_editor_monitoring(interval_ms).await?;
+        self.start_model_monitoring(interval_ms.map(|ms| ms * 2)).await?; // Models less frequently
         self.start_terminal_monitoring(interval_ms).await?;
+        self.start_language_server_monitoring(interval_ms).await?;
+        self.start_disk_monitoring(interval_ms).await?;
+        self.start_network_monitoring();
         
         Ok(())
     }
 
-    pub async fn run_daemon(&mut self) -> Result<()> {
-        info!("Running latency monitor as daemon");
-        self.running = true;
+    /// Periodically record the monitor's own RSS, CPU and event buffer usage
+    /// as a `System` process sample tagged `metadata.self_monitor`.
+    pub fn start_self_monitoring(&self) {
+        let interval_secs = self.config.monitoring.memory_budget.self_report_interval_secs;
+        if interval_secs == 0 {
+            return;
+        }
 
-        // Start event processing task
-        let storage = self.storage.clone();
-        let receiver = self.event_receiver.clone();
-        
-        tokio::spawn(async move {
-            while let Ok(event) = receiver.recv() {
-                debug!("Processing latency event: {:?}", event);
-                
-                if let Err(e) = storage.store_event(&event).await {
-                    warn!("Failed to store event: {}", e);
+        let events = self.events.clone();
+        let cmdline = self.config.monitoring.cmdline.clone();
+        let Ok(pid) = sysinfo::get_current_pid() else {
+            warn!("Can't determine own PID; self-monitoring disabled");
+            return;
+        };
+
+        self.shutdown.spawn(async move {
+            let mut system = System::new();
+            loop {
+                let start_time = Instant::now();
+                system.refresh_process(pid);
+                let Some(process) = system.process(pid) else {
+                    break;
+                };
+
+                let stats = events.stats();
+                let mut metadata = process_metadata(pid, process, &cmdline);
+                metadata["self_monitor"] = serde_json::json!(true);
+                metadata["buffer"] = serde_json::json!(stats);
+                metadata["budget_used_percent"] =
+                    serde_json::json!(stats.buffered_bytes as f64 / stats.budget_bytes as f64 * 100.0);
+                metadata["load_shedding"] = serde_json::json!(events.load_status());
+
+                let event = LatencyEvent::new(
+                    ComponentType::System,
+                    EventSource::ProcessMonitor,
+                    start_time.elapsed(),
+                    format!(
+                        "Monitor self-check - RSS: {}MB, buffer: {}KB of {}MB, spilled: {}",
+                        process.memory() / (1024 * 1024),
+                        stats.buffered_bytes / 1024,
+                        stats.budget_bytes / (1024 * 1024),
+                        stats.spilled_total
+                    ),
+                )
+                .with_metadata(metadata);
+
+                if let Err(e) = events.send_exempt(event) {
+                    warn!("Failed to send self-monitoring event: {}", e);
                 }
+
+                sleep(Duration::from_secs(interval_secs)).await;
             }
         });
+    }
 
-        // Keep daemon running
-        while self.running {
-            sleep(Duration::from_secs(1)).await;
+    /// Check queue depth every second and enter or leave degraded mode
+    /// (slower sampling, low-priority kinds dropped) as load changes. Each
+    /// transition is recorded as a `System` lifecycle event.
+    pub fn start_load_watchdog(&self) {
+        if !self.config.monitoring.load_shedding.enabled {
+            return;
         }
 
-        Ok(())
+        let events = self.events.clone();
+        self.shutdown.spawn(async move {
+            let mut ticker = tokio::time::interval(LOAD_CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let Some(status) = events.evaluate_load() else {
+                    continue;
+                };
+
+                let description = if status.degraded {
+                    warn!(
+                        "DEGRADED MODE: event queue at {} events; sampling {}x slower, dropping {:?} ({} dropped so far)",
+                        status.queue_depth, status.interval_multiplier, status.shedding, status.dropped_total
+                    );
+                    format!(
+                        "Monitor degraded: queue depth {}, dropping {:?}",
+                        status.queue_depth, status.shedding
+                    )
+                } else {
+                    info!(
+                        "Event queue recovered ({} events); leaving degraded mode after dropping {} events",
+                        status.queue_depth, status.dropped_total
+                    );
+                    format!("Monitor recovered: queue depth {}", status.queue_depth)
+                };
+
+                let event = LatencyEvent::new(
+                    ComponentType::System,
+                    EventSource::ProcessMonitor,
+                    Duration::ZERO,
+                    description,
+                )
+                .with_kind(EventKind::Lifecycle)
+                .with_metadata(serde_json::json!({ "self_monitor": true, "load_shedding": status }));
+
+                if let Err(e) = events.send_exempt(event) {
+                    warn!("Failed to send load shedding event: {}", e);
+                }
+            }
+        });
     }
 
-    pub async fn run_foreground(&mut self) -> Result<()> {
+    pub async fn run_daemon(&mut self) -> Result<SessionSummary> {
+        info!("Running latency monitor as daemon");
+        self.run(daemon::shutdown_signal(), false).await
+    }
+
+    /// Store queued events until `shutdown` completes, then stop the
+    /// collectors and flush whatever is still pending. For embedding the
+    /// monitor in another application, which decides when to stop rather
+    /// than waiting for SIGTERM.
+    pub async fn run_until<F>(&mut self, shutdown: F) -> Result<SessionSummary>
+    where
+        F: std::future::Future<Output = Result<()>>,
+    {
+        self.run(shutdown, false).await
+    }
+
+    /// Like [`run_daemon`](Self::run_daemon), also printing every stored
+    /// event to the console.
+    pub async fn run_foreground(&mut self) -> Result<SessionSummary> {
         info!("Running latency monitor in foreground");
+        self.run(daemon::shutdown_signal(), true).await
+    }
+
+    async fn run<F>(&mut self, shutdown: F, echo: bool) -> Result<SessionSummary>
+    where
+        F: std::future::Future<Output = Result<()>>,
+    {
         self.running = true;
 
-        // Start event processing
         let storage = self.storage.clone();
-        let receiver = self.event_receiver.clone();
-        
-        let processing_task = tokio::spawn(async move {
-            while let Ok(event) = receiver.recv() {
+        let receiver = self.events.clone();
+        let stop = self.shutdown.clone();
+        let summary = self.summary.clone();
+        let mut processing_task = tokio::spawn(async move {
+            loop {
+                // Only between events, so none is lost halfway through storing
+                let event = tokio::select! {
+                    event = next_event(&receiver) => event,
+                    _ = stop.requested() => None,
+                };
+                let Some(event) = event else {
+                    break;
+                };
                 debug!("Processing latency event: {:?}", event);
-                
-                if let Err(e) = storage.store_event(&event).await {
-                    warn!("Failed to store event: {}", e);
-                } else {
+
+                let stored = storage.store_event(&event).await;
+                let mut summary = summary.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                match stored {
+                    Ok(()) => summary.record(&event),
+                    Err(e) => {
+                        summary.failed += 1;
+                        warn!("Failed to store event: {}", e);
+                        continue;
+                    }
+                }
+                if echo {
                     // Print to console for immediate feedback
-                    println!("[{}] {} - {}ms - {}", 
+                    println!("[{}] {} - {}ms - {}",
                         event.timestamp.format("%H:%M:%S"),
                         event.component_type,
                         event.duration.as_millis(),
@@ -290,20 +1016,77 @@ impl LatencyMonitor {
             }
         });
 
-        // Wait for shutdown signal (Ctrl+C)
-        tokio::select! {
-            _ = tokio::signal::ctrl_c() => {
+        // Keep running until asked to terminate
+        let mut processing_done = false;
+        let signalled = tokio::select! {
+            result = shutdown => {
                 info!("Received shutdown signal");
-                self.running = false;
+                result
             }
-            _ = processing_task => {
+            _ = &mut processing_task => {
                 info!("Event processing task completed");
+                processing_done = true;
+                Ok(())
             }
+        };
+        self.running = false;
+
+        // Collectors first, so nothing is queued after the final drain
+        let aborted = self.shutdown.stop(SHUTDOWN_GRACE).await;
+        if aborted > 0 {
+            warn!("{} monitor tasks didn't stop within {:?} and were aborted", aborted, SHUTDOWN_GRACE);
         }
+        if !processing_done {
+            let _ = processing_task.await;
+        }
+        self.flush_pending_events().await;
+        signalled?;
 
-        Ok(())
+        let mut summary = self.summary.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        summary.ended = Utc::now();
+        Ok(summary)
     }
 
+    /// Store any events still queued in the channel before exiting.
+    async fn flush_pending_events(&self) {
+        let mut flushed = 0;
+        loop {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(_) if self.events.refill().await > 0 => continue,
+                // Events on their way to the spill journal are stored too
+                Err(_) if self.events.is_spilling() => {
+                    sleep(EVENT_POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(_) => break,
+            };
+            let stored = self.storage.store_event(&event).await;
+            let mut summary = self.summary.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Err(e) = stored {
+                summary.failed += 1;
+                warn!("Failed to store event during shutdown: {}", e);
+            } else {
+                summary.record(&event);
+                summary.flushed += 1;
+                flushed += 1;
+            }
+        }
+
+        if let Err(e) = self.storage.flush().await {
+            warn!("Failed to flush buffered events: {}", e);
+        }
+
+        info!("Flushed {} pending events", flushed);
+    }
+
+    /// Queue an event recorded outside the built-in collectors; it is stored
+    /// by the `run_*` loop like any other measurement.
+    pub fn record(&self, event: LatencyEvent) -> Result<()> {
+        self.events.send(event)
+    }
+
+    /// Run `operation` and record how long it took.
     pub async fn measure_command_latency<F, Fut, T>(&self, 
         component: ComponentType,
         source: EventSource,
@@ -321,7 +1104,7 @@ impl LatencyMonitor {
         let duration = start_time.elapsed();
         let event = LatencyEvent::new(component, source, duration, description);
         
-        if let Err(e) = self.event_sender.send(event) {
+        if let Err(e) = self.events.send(event) {
             warn!("Failed to send latency measurement event: {}", e);
         }
         
@@ -343,9 +1126,10 @@ impl LatencyMonitor {
                 EventSource::TestCommand,
                 duration,
                 format!("Test VS Code command #{}", i + 1),
-            );
+            )
+            .as_test_data();
 
-            self.event_sender.send(event)?;
+            self.events.send(event)?;
         }
         
         Ok(())
@@ -366,9 +1150,11 @@ impl LatencyMonitor {
                 EventSource::TestCommand,
                 duration,
                 format!("Test model interaction #{}", i + 1),
-            );
+            )
+            .with_kind(EventKind::ModelRequest)
+            .as_test_data();
 
-            self.event_sender.send(event)?;
+            self.events.send(event)?;
         }
         
         Ok(())
@@ -389,9 +1175,10 @@ impl LatencyMonitor {
                 EventSource::TestCommand,
                 duration,
                 format!("Test terminal command #{}", i + 1),
-            );
+            )
+            .as_test_data();
 
-            self.event_sender.send(event)?;
+            self.events.send(event)?;
         }
         
         Ok(())
@@ -414,4 +1201,194 @@ impl LatencyMonitor {
         info!("Stopping latency monitor");
         self.running = false;
     }
-}
\ No newline at end of file
+}
+
+/// How often one collector samples and whether it runs at all, re-read from
+/// the current config every round so a reload applies without a restart.
+struct Sampling {
+    settings: watch::Receiver<Config>,
+    /// Name in `monitoring.enabled_components`
+    component: &'static str,
+    /// `--interval`, which takes precedence over `monitoring.interval_ms`
+    interval_ms: Option<u64>,
+    scale: u64,
+}
+
+impl Sampling {
+    fn new(settings: watch::Receiver<Config>, component: &'static str, interval_ms: Option<u64>) -> Self {
+        Self { settings, component, interval_ms, scale: 1 }
+    }
+
+    /// Sample `factor` times less often than the configured interval.
+    fn slower(mut self, factor: u64) -> Self {
+        self.scale = factor;
+        self
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings
+            .borrow()
+            .monitoring
+            .enabled_components
+            .iter()
+            .any(|name| name == self.component || name == "all")
+    }
+
+    fn interval(&self) -> Duration {
+        let interval_ms = match self.interval_ms {
+            Some(interval_ms) => interval_ms,
+            None => self.settings.borrow().monitoring.interval_ms * self.scale,
+        };
+        Duration::from_millis(interval_ms.max(1))
+    }
+}
+
+/// Await the next event without parking a runtime worker thread in a
+/// blocking `recv`, which would starve signal handling on small machines.
+async fn next_event(receiver: &EventQueue) -> Option<LatencyEvent> {
+    loop {
+        match receiver.try_recv() {
+            Ok(event) => return Some(event),
+            Err(TryRecvError::Empty) => {
+                if receiver.refill().await == 0 {
+                    sleep(EVENT_POLL_INTERVAL).await;
+                }
+            }
+            Err(TryRecvError::Disconnected) => return None,
+        }
+    }
+}
+
+/// One streamed `/api/generate` call. Time to first token is measured at the
+/// first chunk carrying response text; token counts come from the final chunk.
+/// Also returns the output throughput when any text was generated.
+async fn probe_ollama(
+    client: &reqwest::Client,
+    probe: &OllamaProbeConfig,
+) -> Result<(ModelInteraction, Option<TokenMetrics>)> {
+    let started = Instant::now();
+    let mut response = client
+        .post(format!("{}/api/generate", probe.url.trim_end_matches('/')))
+        .json(&serde_json::json!({
+            "model": probe.model,
+            "prompt": probe.prompt,
+            "stream": true,
+            "options": { "num_predict": probe.max_tokens },
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut first_token = None;
+    let mut text = String::new();
+    let mut done: Option<serde_json::Value> = None;
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let Ok(message) = serde_json::from_slice::<serde_json::Value>(&line) else {
+                continue;
+            };
+
+            if let Some(error) = message["error"].as_str() {
+                return Err(anyhow::anyhow!("Ollama error: {}", error));
+            }
+            if let Some(response) = message["response"].as_str().filter(|r| !r.is_empty()) {
+                first_token.get_or_insert_with(|| started.elapsed());
+                text.push_str(response);
+            }
+            if message["done"].as_bool() == Some(true) {
+                done = Some(message);
+            }
+        }
+    }
+
+    let done = done.ok_or_else(|| anyhow::anyhow!("Ollama stream ended without a final message"))?;
+    let mut interaction = ModelInteraction::new(
+        probe.model.clone(),
+        ModelInteractionType::Completion,
+        started.elapsed().as_millis() as u64,
+        true,
+    );
+    interaction.prompt_tokens = done["prompt_eval_count"].as_u64().map(|n| n as u32);
+    interaction.completion_tokens = done["eval_count"].as_u64().map(|n| n as u32);
+    interaction.total_tokens = match (interaction.prompt_tokens, interaction.completion_tokens) {
+        (Some(p), Some(c)) => Some(p + c),
+        (p, c) => p.or(c),
+    };
+    interaction.time_to_first_token_ms = first_token.map(|t| t.as_millis() as u64);
+
+    let tokens = first_token.map(|first| {
+        let mut tokens = TokenMetrics::new(
+            "ollama_probe",
+            "ollama",
+            probe.model.clone(),
+            &text,
+            started.elapsed().saturating_sub(first).as_millis() as u64,
+            probe.prompt.chars().count(),
+        );
+        tokens.reported_tokens = interaction.completion_tokens;
+        tokens.time_to_first_token_ms = interaction.time_to_first_token_ms;
+        tokens
+    });
+
+    Ok((interaction, tokens))
+}
+
+/// Process metadata plus the VS Code build, profile, window and workspace
+/// the process belongs to, so they can be compared.
+fn vscode_metadata(
+    system: &System,
+    pid: Pid,
+    process: &Process,
+    cmdline: &CmdlineConfig,
+    workspaces: &Workspaces,
+) -> serde_json::Value {
+    let mut metadata = process_metadata(pid, process, cmdline);
+    if let Some(build) = platform::vscode_build(process) {
+        metadata["vscode_build"] = serde_json::json!(build.as_str());
+    }
+    if let Some(profile) = platform::vscode_profile(system, process) {
+        metadata["vscode_profile"] = serde_json::json!(profile);
+    }
+    if let Some(window) = platform::vscode_window(system, process) {
+        metadata["vscode_window"] = serde_json::json!(window);
+    }
+    if let Some(workspace) = platform::vscode_workspace(system, process, workspaces) {
+        workspace.apply(&mut metadata);
+    }
+    metadata
+}
+
+/// Write `bytes` to a fresh file at `path` and fsync it, returning how long
+/// each step took. The file is removed afterwards.
+fn probe_disk(path: &std::path::Path, bytes: usize) -> std::io::Result<(Duration, Duration)> {
+    use std::io::Write;
+
+    let data = vec![0x5a; bytes];
+    let started = Instant::now();
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&data)?;
+    let written = started.elapsed();
+    let synced = file.sync_all();
+    let fsync = started.elapsed() - written;
+    drop(file);
+    let _ = std::fs::remove_file(path);
+    synced.map(|()| (written, fsync))
+}
+
+/// Resource usage attached to process samples, for filters and alert rules.
+fn process_metadata(pid: Pid, process: &Process, cmdline: &CmdlineConfig) -> serde_json::Value {
+    let metadata = ProcessMetadata {
+        pid: pid.as_u32(),
+        process_name: process.name().to_string(),
+        cpu_percent: process.cpu_usage(),
+        memory_mb: process.memory() as f64 / (1024.0 * 1024.0),
+        memory_kb: process.memory() / 1024,
+        cmdline: cmdline.render(process.cmd()),
+    };
+    serde_json::to_value(metadata).unwrap_or_default()
+}
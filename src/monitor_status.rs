@@ -0,0 +1,162 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::daemon;
+use crate::models::ComponentType;
+use crate::service::{self, ServiceState};
+use crate::storage::MetricsStorage;
+use crate::timerange::TimeRange;
+
+/// Events over this window set the ingest rate.
+const INGEST_WINDOW_SECS: i64 = 60;
+/// A component counts as monitored while it reported this recently.
+const ACTIVE_WINDOW_SECS: i64 = 300;
+/// How long `status` waits on each server before calling it unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What `status` reports about the running monitor, as opposed to the data
+/// it has stored.
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorStatus {
+    pub daemon: DaemonState,
+    /// The service `install-service` set up, on platforms that have one
+    pub service: Option<ServiceState>,
+    /// Components with events in the last five minutes
+    pub active_components: Vec<ActiveComponent>,
+    /// Collectors named in `monitoring.enabled_components`
+    pub enabled_monitors: Vec<String>,
+    pub ingest: IngestRate,
+    pub database: DatabaseSize,
+    pub dashboard: ServerState,
+    pub telemetry: ServerState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonState {
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub pid_file: PathBuf,
+    /// The PID file names a process that no longer exists
+    pub stale_pid_file: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveComponent {
+    pub component: ComponentType,
+    pub events: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestRate {
+    pub window_secs: u64,
+    pub events: u64,
+    pub events_per_sec: f64,
+    pub last_event: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseSize {
+    pub path: PathBuf,
+    /// Main file plus its write-ahead log and shared-memory index
+    pub total_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerState {
+    pub url: String,
+    pub reachable: bool,
+    pub error: Option<String>,
+}
+
+/// Gather the monitor's live state: its PID file, what it has written
+/// lately, how big the database is and whether its servers answer.
+/// `telemetry_port` is the port `telemetry` was started with.
+pub async fn collect(config: &Config, storage: &MetricsStorage, telemetry_port: u16) -> Result<MonitorStatus> {
+    let now = Utc::now();
+    let recent = |secs: i64| TimeRange {
+        since: Some(now - chrono::Duration::seconds(secs)),
+        until: None,
+        working_hours: None,
+        filter: None,
+        include_test_data: false,
+    };
+
+    let active_components: Vec<ActiveComponent> = storage
+        .get_performance_metrics_in(&recent(ACTIVE_WINDOW_SECS))
+        .await?
+        .into_iter()
+        .filter(|metrics| metrics.total_events > 0)
+        .map(|metrics| ActiveComponent { component: metrics.component, events: metrics.total_events })
+        .collect();
+    let events = storage.count_matching(&recent(INGEST_WINDOW_SECS).to_sql()).await?;
+    let last_event = storage.query_events(None, 1).await?.first().map(|event| event.timestamp);
+
+    let (dashboard, telemetry) = tokio::join!(
+        probe(format!("http://127.0.0.1:{}/health", config.dashboard.port)),
+        probe(format!("http://127.0.0.1:{}/health", telemetry_port)),
+    );
+
+    Ok(MonitorStatus {
+        daemon: daemon_state(config),
+        service: service::state(),
+        active_components,
+        enabled_monitors: config.monitoring.enabled_components.clone(),
+        ingest: IngestRate {
+            window_secs: INGEST_WINDOW_SECS as u64,
+            events,
+            events_per_sec: events as f64 / INGEST_WINDOW_SECS as f64,
+            last_event,
+        },
+        database: database_size(&config.storage.database_path),
+        dashboard,
+        telemetry,
+    })
+}
+
+fn daemon_state(config: &Config) -> DaemonState {
+    let pid_file = daemon::pid_file_path(config);
+    let pid = daemon::running_pid(config);
+    DaemonState {
+        running: pid.is_some(),
+        pid,
+        stale_pid_file: pid.is_none() && pid_file.exists(),
+        pid_file,
+    }
+}
+
+fn database_size(path: &Path) -> DatabaseSize {
+    let size = |suffix: &str| {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        std::fs::metadata(file).map(|meta| meta.len()).unwrap_or(0)
+    };
+    let wal_bytes = size("-wal");
+    DatabaseSize {
+        path: path.to_path_buf(),
+        total_bytes: size("") + wal_bytes + size("-shm"),
+        wal_bytes,
+    }
+}
+
+async fn probe(url: String) -> ServerState {
+    let result = async {
+        reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .build()?
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()
+    }
+    .await;
+    ServerState {
+        reachable: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+        url,
+    }
+}
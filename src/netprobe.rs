@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::timeout;
+
+use crate::models::{ComponentType, EventSource, LatencyEvent};
+
+/// Reachability probes against the endpoints VS Code and Copilot depend on,
+/// configured as `[monitoring.network]` and run by the `network` component.
+/// Each target is timed as a DNS lookup, a TCP connect and an HTTPS `HEAD`
+/// on a fresh connection, so slow Copilot responses can be told apart from
+/// a slow network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkProbeConfig {
+    pub targets: Vec<String>,
+    pub interval_secs: u64,
+    /// Applies to each step of each probe
+    pub timeout_secs: u64,
+}
+
+impl Default for NetworkProbeConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec![
+                "https://github.com/".to_string(),
+                "https://api.githubcopilot.com/".to_string(),
+                "https://marketplace.visualstudio.com/".to_string(),
+            ],
+            interval_secs: 30,
+            timeout_secs: 5,
+        }
+    }
+}
+
+impl NetworkProbeConfig {
+    /// Client for the HTTPS step. Idle connections aren't kept, so every
+    /// request pays for its own DNS lookup, connect and TLS handshake, as a
+    /// cold request from the editor would.
+    pub fn client(&self) -> Result<reqwest::Client> {
+        Ok(reqwest::Client::builder()
+            .timeout(self.step_timeout())
+            .pool_max_idle_per_host(0)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?)
+    }
+
+    fn step_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+}
+
+/// Probe every target in turn, returning one event per step taken. A
+/// failed DNS lookup or connect ends that target's probe.
+pub async fn probe_all(client: &reqwest::Client, config: &NetworkProbeConfig) -> Vec<LatencyEvent> {
+    let mut events = Vec::new();
+    for target in &config.targets {
+        match Url::parse(target) {
+            Ok(url) => probe(client, config, &url, &mut events).await,
+            Err(e) => events.push(failed("dns", target, "", Duration::ZERO, &anyhow!("invalid URL: {}", e))),
+        }
+    }
+    events
+}
+
+async fn probe(client: &reqwest::Client, config: &NetworkProbeConfig, url: &Url, events: &mut Vec<LatencyEvent>) {
+    let target = url.as_str();
+    let Some(host) = url.host_str() else {
+        events.push(failed("dns", target, "", Duration::ZERO, &anyhow!("URL has no host")));
+        return;
+    };
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let started = Instant::now();
+    let address = match timeout(config.step_timeout(), lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => addresses.next(),
+        Ok(Err(e)) => {
+            events.push(failed("dns", target, host, started.elapsed(), &e.into()));
+            return;
+        }
+        Err(_) => {
+            events.push(failed("dns", target, host, started.elapsed(), &anyhow!("timed out")));
+            return;
+        }
+    };
+    let Some(address) = address else {
+        events.push(failed("dns", target, host, started.elapsed(), &anyhow!("no addresses")));
+        return;
+    };
+    events.push(succeeded("dns", target, host, started.elapsed(), format!("DNS {}", host), Some(address), None));
+
+    let started = Instant::now();
+    match timeout(config.step_timeout(), TcpStream::connect(address)).await {
+        Ok(Ok(_)) => events.push(succeeded(
+            "tcp",
+            target,
+            host,
+            started.elapsed(),
+            format!("TCP connect {} ({})", host, address),
+            Some(address),
+            None,
+        )),
+        Ok(Err(e)) => {
+            events.push(failed("tcp", target, host, started.elapsed(), &e.into()));
+            return;
+        }
+        Err(_) => {
+            events.push(failed("tcp", target, host, started.elapsed(), &anyhow!("timed out")));
+            return;
+        }
+    }
+
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return;
+    }
+    let started = Instant::now();
+    match client.head(url.clone()).send().await {
+        // Any answer, even 404, means the endpoint is reachable
+        Ok(response) => {
+            let status = response.status().as_u16();
+            events.push(succeeded(
+                "https",
+                target,
+                host,
+                started.elapsed(),
+                format!("HTTPS HEAD {} {}", target, status),
+                None,
+                Some(status),
+            ))
+        }
+        Err(e) => events.push(failed("https", target, host, started.elapsed(), &e.into())),
+    }
+}
+
+fn succeeded(
+    step: &str,
+    target: &str,
+    host: &str,
+    elapsed: Duration,
+    description: String,
+    address: Option<SocketAddr>,
+    status: Option<u16>,
+) -> LatencyEvent {
+    LatencyEvent::new(ComponentType::Network, EventSource::NetworkRequest, elapsed, description).with_metadata(json!({
+        "probe": step,
+        "target": target,
+        "host": host,
+        "address": address.map(|a| a.to_string()),
+        "status": status,
+        "success": true,
+    }))
+}
+
+fn failed(step: &str, target: &str, host: &str, elapsed: Duration, error: &anyhow::Error) -> LatencyEvent {
+    LatencyEvent::new(
+        ComponentType::Network,
+        EventSource::NetworkRequest,
+        elapsed,
+        format!("{} probe of {} failed: {}", step.to_uppercase(), target, error),
+    )
+    .with_metadata(json!({
+        "probe": step,
+        "target": target,
+        "host": host,
+        "success": false,
+        "error": error.to_string(),
+    }))
+}
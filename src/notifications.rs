@@ -0,0 +1,336 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Query, State},
+    response::{Html, Json},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+use crate::alerts::{AlertAction, AlertNotification, AlertState};
+use crate::models::{ComponentType, LatencyEvent};
+use crate::report::html_escape;
+use crate::units;
+
+/// The wall notification system, configured as
+/// `[integrations.notifications]` and switched on by
+/// `integrations.wall_notification_system`. It broadcasts alerts and slow
+/// events on the channels chosen in `channels`, and can serve what it sent
+/// on a loopback port.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Where notifications go; none until chosen
+    pub channels: Vec<NotificationChannel>,
+    /// Notify when a single event takes longer than this
+    pub threshold_ms: Option<f64>,
+    /// Components `threshold_ms` applies to; all when empty
+    pub components: Vec<ComponentType>,
+    /// Minimum time between two threshold notifications for one component
+    pub cooldown_secs: u64,
+    /// Loopback port of the notification history server; 0 (the default)
+    /// turns it off
+    pub port: u16,
+    /// Notifications the history keeps, newest first
+    pub history_size: usize,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            threshold_ms: None,
+            components: Vec::new(),
+            cooldown_secs: 300,
+            port: 0,
+            history_size: 200,
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(threshold_ms) = self.threshold_ms {
+            if !threshold_ms.is_finite() || threshold_ms <= 0.0 {
+                return Err(anyhow!("integrations.notifications.threshold_ms must be a positive number"));
+            }
+        }
+        if self.history_size == 0 {
+            return Err(anyhow!("integrations.notifications.history_size must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationChannel {
+    /// Broadcast to logged-in terminals with `wall`
+    Wall,
+    /// Desktop notification (notify-send / osascript)
+    Desktop,
+}
+
+impl NotificationChannel {
+    /// The alert action that delivers on this channel.
+    pub fn action(&self) -> AlertAction {
+        match self {
+            NotificationChannel::Wall => AlertAction::Wall,
+            NotificationChannel::Desktop => AlertAction::Desktop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationSource {
+    /// An alert rule fired or resolved
+    Alert,
+    /// One event took longer than `threshold_ms`
+    Threshold,
+}
+
+/// A notification as kept in the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub at: DateTime<Utc>,
+    pub source: NotificationSource,
+    pub title: String,
+    pub message: String,
+    pub channels: Vec<NotificationChannel>,
+}
+
+/// Sends notifications and remembers them for the history server. Clones
+/// share one history.
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotificationsConfig,
+    history: Arc<Mutex<VecDeque<Notification>>>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            history: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn channels(&self) -> &[NotificationChannel] {
+        &self.config.channels
+    }
+
+    /// Add an alert delivered on `channels` by the alert engine, which
+    /// sends it itself so digests and maintenance windows apply.
+    pub fn record_alert(&self, notification: &AlertNotification, channels: Vec<NotificationChannel>) {
+        self.record(Notification {
+            at: notification.at,
+            source: NotificationSource::Alert,
+            title: alert_title(notification.state).to_string(),
+            message: notification.message.clone(),
+            channels,
+        });
+    }
+
+    /// Send `notification` on its channels and add it to the history.
+    /// Failures are logged per channel.
+    pub async fn send(&self, notification: Notification) {
+        for channel in &notification.channels {
+            let result = match channel {
+                NotificationChannel::Wall => wall(&notification.message).await,
+                NotificationChannel::Desktop => desktop(&notification.title, &notification.message).await,
+            };
+            if let Err(e) = result {
+                warn!("Notification on {:?} failed: {}", channel, e);
+            }
+        }
+        self.record(notification);
+    }
+
+    fn record(&self, notification: Notification) {
+        let mut history = self.history.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        history.push_front(notification);
+        history.truncate(self.config.history_size);
+    }
+
+    fn recent(&self, limit: usize) -> Vec<Notification> {
+        let history = self.history.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        history.iter().take(limit).cloned().collect()
+    }
+
+    /// Notify about stored events slower than `threshold_ms`, at most once
+    /// per component per cooldown. Returns at once without a threshold.
+    pub async fn watch_thresholds(self, mut events: broadcast::Receiver<LatencyEvent>) {
+        let Some(threshold_ms) = self.config.threshold_ms else {
+            return;
+        };
+        let cooldown = chrono::Duration::seconds(self.config.cooldown_secs as i64);
+        let mut last_sent: HashMap<ComponentType, DateTime<Utc>> = HashMap::new();
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Threshold notifications skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let duration_ms = event.duration.as_secs_f64() * 1000.0;
+            if duration_ms <= threshold_ms
+                || !event.kind.carries_latency()
+                || event.is_test_data()
+                || (!self.config.components.is_empty() && !self.config.components.contains(&event.component_type))
+            {
+                continue;
+            }
+            let now = Utc::now();
+            if last_sent.get(&event.component_type).is_some_and(|sent| now - *sent < cooldown) {
+                continue;
+            }
+            last_sent.insert(event.component_type, now);
+
+            let notification = Notification {
+                at: event.timestamp,
+                source: NotificationSource::Threshold,
+                title: "VS Code latency threshold exceeded".to_string(),
+                message: format!(
+                    "{} took {} (threshold {}): {}",
+                    event.component_type,
+                    units::format_ms(duration_ms),
+                    units::format_ms(threshold_ms),
+                    event.description
+                ),
+                channels: self.config.channels.clone(),
+            };
+            self.send(notification).await;
+        }
+    }
+
+    /// Serve the history on `port`. It has no authentication, so it only
+    /// listens on loopback.
+    pub async fn serve(self) -> Result<()> {
+        let port = self.config.port;
+        let app = Router::new()
+            .route("/", get(history_html))
+            .route("/api/notifications", get(api_notifications))
+            .route("/health", get(|| async { Json(json!({ "status": "healthy" })) }))
+            .with_state(self);
+
+        let addr = format!("127.0.0.1:{}", port);
+        info!("Notification history on http://{}", addr);
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| anyhow!("Can't serve notification history on {}: {}", addr, e))?;
+        axum::serve(listener, app).await?;
+        Ok(())
+    }
+}
+
+pub fn alert_title(state: AlertState) -> &'static str {
+    match state {
+        AlertState::Firing => "VS Code latency alert",
+        AlertState::Resolved => "VS Code latency alert resolved",
+    }
+}
+
+/// Broadcast `message` to every logged-in terminal.
+pub async fn wall(message: &str) -> Result<()> {
+    run_command("wall", &[message]).await
+}
+
+/// Show a desktop notification: notify-send on Linux, Notification Center
+/// on macOS.
+pub async fn desktop(title: &str, message: &str) -> Result<()> {
+    if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", message, title);
+        run_command("osascript", &["-e", &script]).await
+    } else if cfg!(target_os = "linux") {
+        run_command("notify-send", &[title, message]).await
+    } else {
+        Err(anyhow!("Desktop notifications aren't supported on this platform"))
+    }
+}
+
+async fn run_command(program: &str, args: &[&str]) -> Result<()> {
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .status()
+        .await
+        .map_err(|e| anyhow!("Failed to run {}: {}", program, e))?;
+
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    limit: Option<usize>,
+}
+
+async fn api_notifications(
+    State(notifier): State<Notifier>,
+    Query(params): Query<HistoryParams>,
+) -> Json<serde_json::Value> {
+    let notifications = notifier.recent(params.limit.unwrap_or(50));
+    Json(json!({
+        "count": notifications.len(),
+        "channels": notifier.config.channels,
+        "threshold_ms": notifier.config.threshold_ms,
+        "notifications": notifications,
+    }))
+}
+
+async fn history_html(State(notifier): State<Notifier>) -> Html<String> {
+    let rows: String = notifier
+        .recent(notifier.config.history_size)
+        .iter()
+        .map(|notification| {
+            format!(
+                "<tr><td>{}</td><td>{:?}</td><td>{}</td><td>{}</td></tr>\n",
+                notification.at.format("%Y-%m-%d %H:%M:%S UTC"),
+                notification.source,
+                html_escape(&notification.message),
+                notification.channels.iter().map(|channel| format!("{:?}", channel)).collect::<Vec<_>>().join(", ")
+            )
+        })
+        .collect();
+    let body = if rows.is_empty() {
+        "<p>No notifications since the monitor started.</p>".to_string()
+    } else {
+        format!("<table><tr><th>Time</th><th>Source</th><th>Message</th><th>Channels</th></tr>\n{}</table>", rows)
+    };
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <title>VS Code Latency Monitor - Notifications</title>
+    <meta http-equiv="refresh" content="10">
+    <style>
+        body {{ font-family: 'Monaco', 'Consolas', monospace; background: #0f0f23; color: #00ff88; padding: 20px; }}
+        h1 {{ color: #00ffff; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        th, td {{ text-align: left; padding: 6px 10px; border-bottom: 1px solid #1a1a3e; vertical-align: top; }}
+        th {{ color: #ffff00; }}
+    </style>
+</head>
+<body>
+    <h1>🏠 Wall Notifications</h1>
+    {}
+    <p><a href="/api/notifications" style="color: #00ffff">/api/notifications</a> - the same as JSON</p>
+</body>
+</html>
+"#,
+        body
+    ))
+}
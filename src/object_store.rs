@@ -0,0 +1,532 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::progress::Progress;
+use crate::secrets::Secrets;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// S3-compatible bucket used for archives, backups and exports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectStorageConfig {
+    pub enabled: bool,
+    /// Base endpoint, e.g. `https://s3.eu-central-1.amazonaws.com` or a MinIO URL
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Key prefix applied before the per-month lifecycle layout
+    pub prefix: String,
+    /// `endpoint/bucket/key` addressing (MinIO, Ceph) instead of `bucket.endpoint/key`
+    pub path_style: bool,
+    pub access_key_secret: String,
+    pub secret_key_secret: String,
+}
+
+impl Default for ObjectStorageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "https://s3.amazonaws.com".to_string(),
+            bucket: "vscode-latency-monitor".to_string(),
+            region: "us-east-1".to_string(),
+            prefix: "latency".to_string(),
+            path_style: true,
+            access_key_secret: "s3_access_key".to_string(),
+            secret_key_secret: "s3_secret_key".to_string(),
+        }
+    }
+}
+
+/// Category of uploaded object; each gets its own key namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Archive,
+    Backup,
+    Export,
+}
+
+impl ObjectKind {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "archive" | "archives" => Ok(ObjectKind::Archive),
+            "backup" | "backups" => Ok(ObjectKind::Backup),
+            "export" | "exports" => Ok(ObjectKind::Export),
+            _ => Err(anyhow!("Unknown object kind '{}' (archive, backup, export)", value)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Archive => "archives",
+            ObjectKind::Backup => "backups",
+            ObjectKind::Export => "exports",
+        }
+    }
+}
+
+/// An entry returned by `list_objects`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+pub struct ObjectStore {
+    config: ObjectStorageConfig,
+    access_key: String,
+    secret_key: String,
+    client: reqwest::Client,
+}
+
+impl ObjectStore {
+    pub fn new(config: &ObjectStorageConfig, secrets: &Secrets) -> Result<Self> {
+        if !config.enabled {
+            return Err(anyhow!("Object storage is disabled (set object_storage.enabled = true)"));
+        }
+
+        let access_key = secrets
+            .get(&config.access_key_secret)
+            .ok_or_else(|| anyhow!("Missing secret '{}'", config.access_key_secret))?;
+        let secret_key = secrets
+            .get(&config.secret_key_secret)
+            .ok_or_else(|| anyhow!("Missing secret '{}'", config.secret_key_secret))?;
+
+        Ok(Self {
+            config: config.clone(),
+            access_key,
+            secret_key,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Key for a new object: `<prefix>/<kind>/<YYYY>/<MM>/<filename>`, so
+    /// bucket lifecycle rules can expire or tier whole months at a time.
+    pub fn object_key(&self, kind: ObjectKind, filename: &str, at: DateTime<Utc>) -> String {
+        let prefix = self.config.prefix.trim_matches('/');
+        let layout = format!("{}/{}/{}", kind.as_str(), at.format("%Y/%m"), filename);
+        if prefix.is_empty() {
+            layout
+        } else {
+            format!("{}/{}", prefix, layout)
+        }
+    }
+
+    pub async fn put_object(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()> {
+        let size = body.len();
+        let response = self
+            .signed_request(reqwest::Method::PUT, key, &[], body, Some(content_type))?
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Upload of {} failed with {}: {}", key, status, text));
+        }
+
+        info!("Uploaded {} bytes to s3://{}/{}", size, self.config.bucket, key);
+        Ok(())
+    }
+
+    /// Start a multipart upload of `key`; write the object into the returned
+    /// writer and call `complete` on it.
+    pub async fn multipart_writer(&self, key: &str, content_type: &str) -> Result<MultipartWriter<'_>> {
+        let response = self
+            .signed_request(reqwest::Method::POST, key, &[("uploads", "")], Vec::new(), Some(content_type))?
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Starting upload of {} failed with {}: {}", key, status, text));
+        }
+
+        let body = response.text().await?;
+        let upload_id = xml_elements(&body, "UploadId")
+            .first()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow!("No UploadId in response for {}", key))?;
+
+        Ok(MultipartWriter {
+            store: self,
+            key: key.to_string(),
+            upload_id,
+            buffer: Vec::with_capacity(MULTIPART_PART_SIZE),
+            parts: Vec::new(),
+            uploaded: 0,
+        })
+    }
+
+    /// Upload a local file in parts, so it is never read into memory whole.
+    /// Cancelling `progress` aborts the upload, discarding the parts sent.
+    pub async fn upload_file(&self, key: &str, path: &Path, content_type: &str, progress: &Progress) -> Result<()> {
+        use std::io::{Read, Write};
+
+        let mut file = std::fs::File::open(path)?;
+        progress.set_length(Some(file.metadata()?.len()));
+        let mut writer = self.multipart_writer(key, content_type).await?;
+
+        let mut chunk = vec![0; 64 * 1024];
+        let copied = loop {
+            let read = match file.read(&mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(read) => read,
+                Err(e) => break Err(e.into()),
+            };
+            if let Err(e) = writer.write_all(&chunk[..read]) {
+                break Err(e.into());
+            }
+            if let Err(e) = progress.advance(read as u64) {
+                break Err(e);
+            }
+        };
+        match copied {
+            Ok(()) => writer.complete().await,
+            Err(e) => {
+                writer.abort().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Download `key` into memory, counting bytes on `progress` and failing
+    /// if it is cancelled.
+    pub async fn get_object(&self, key: &str, progress: &Progress) -> Result<Vec<u8>> {
+        let mut response = self
+            .signed_request(reqwest::Method::GET, key, &[], Vec::new(), None)?
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Download of {} failed with {}", key, response.status()));
+        }
+
+        progress.set_length(response.content_length());
+        let mut data = Vec::with_capacity(response.content_length().unwrap_or(0) as usize);
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            progress.advance(chunk.len() as u64)?;
+        }
+        Ok(data)
+    }
+
+    /// List objects under `<prefix>/<kind>/`, following continuation tokens.
+    pub async fn list_objects(&self, kind: Option<ObjectKind>) -> Result<Vec<ObjectInfo>> {
+        let base = self.config.prefix.trim_matches('/');
+        let prefix = match (base.is_empty(), kind) {
+            (true, Some(kind)) => format!("{}/", kind.as_str()),
+            (true, None) => String::new(),
+            (false, Some(kind)) => format!("{}/{}/", base, kind.as_str()),
+            (false, None) => format!("{}/", base),
+        };
+
+        let mut objects = Vec::new();
+        let mut continuation: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+            if let Some(token) = continuation.as_deref() {
+                query.push(("continuation-token", token));
+            }
+
+            let response = self
+                .signed_request(reqwest::Method::GET, "", &query, Vec::new(), None)?
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Listing bucket failed with {}", response.status()));
+            }
+            let body = response.text().await?;
+
+            for contents in xml_elements(&body, "Contents") {
+                let key = xml_elements(contents, "Key").first().map(|k| k.to_string());
+                let Some(key) = key else { continue };
+                objects.push(ObjectInfo {
+                    key,
+                    size: xml_elements(contents, "Size")
+                        .first()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0),
+                    last_modified: xml_elements(contents, "LastModified")
+                        .first()
+                        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                        .map(|t| t.with_timezone(&Utc)),
+                });
+            }
+
+            continuation = xml_elements(&body, "NextContinuationToken")
+                .first()
+                .map(|t| t.to_string());
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Build a SigV4-signed request for `key` (empty key addresses the bucket).
+    fn signed_request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::RequestBuilder> {
+        let endpoint = reqwest::Url::parse(&self.config.endpoint)?;
+        let endpoint_host = endpoint
+            .host_str()
+            .ok_or_else(|| anyhow!("Object storage endpoint has no host"))?;
+        let host = match endpoint.port() {
+            Some(port) => format!("{}:{}", endpoint_host, port),
+            None => endpoint_host.to_string(),
+        };
+
+        let (host, path) = if self.config.path_style {
+            if key.is_empty() {
+                (host, format!("/{}", self.config.bucket))
+            } else {
+                (host, format!("/{}/{}", self.config.bucket, key))
+            }
+        } else {
+            (format!("{}.{}", self.config.bucket, host), format!("/{}", key))
+        };
+        let canonical_uri = uri_encode(&path, false);
+
+        let mut sorted_query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        sorted_query.sort();
+        let canonical_query = sorted_query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let canonical_headers = format!(
+            "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+            host, payload_hash, amz_date
+        );
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes())?;
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes())?;
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes())?);
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut url = format!("{}://{}{}", endpoint.scheme(), host, canonical_uri);
+        if !canonical_query.is_empty() {
+            url.push('?');
+            url.push_str(&canonical_query);
+        }
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization)
+            .body(body);
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+
+        Ok(request)
+    }
+}
+
+/// Bytes buffered per multipart part; S3 requires at least 5 MiB for every
+/// part but the last.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// `Write` adapter over an S3 multipart upload that holds at most one part
+/// in memory. Full parts are sent from inside `write` by blocking the worker
+/// thread (`block_in_place`), so it needs the multi-threaded runtime.
+pub struct MultipartWriter<'a> {
+    store: &'a ObjectStore,
+    key: String,
+    upload_id: String,
+    buffer: Vec<u8>,
+    /// Part numbers and ETags, in order, for the completion request
+    parts: Vec<(u32, String)>,
+    uploaded: u64,
+}
+
+impl MultipartWriter<'_> {
+    async fn send_part(&mut self) -> Result<()> {
+        let part = std::mem::replace(&mut self.buffer, Vec::with_capacity(MULTIPART_PART_SIZE));
+        let size = part.len() as u64;
+        let number = (self.parts.len() + 1).to_string();
+        let response = self
+            .store
+            .signed_request(
+                reqwest::Method::PUT,
+                &self.key,
+                &[("partNumber", &number), ("uploadId", &self.upload_id)],
+                part,
+                None,
+            )?
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Upload of {} part {} failed with {}", self.key, number, response.status()));
+        }
+
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("No ETag for {} part {}", self.key, number))?
+            .to_string();
+        self.parts.push((self.parts.len() as u32 + 1, etag));
+        self.uploaded += size;
+        Ok(())
+    }
+
+    /// Send the final part and assemble the object.
+    pub async fn complete(mut self) -> Result<()> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            if let Err(e) = self.send_part().await {
+                self.abort().await;
+                return Err(e);
+            }
+        }
+
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (number, etag) in &self.parts {
+            body.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", number, etag));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let response = self
+            .store
+            .signed_request(
+                reqwest::Method::POST,
+                &self.key,
+                &[("uploadId", &self.upload_id)],
+                body.into_bytes(),
+                Some("application/xml"),
+            )?
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        // S3 can report a failed completion in a 200 response body
+        if !status.is_success() || !xml_elements(&text, "Error").is_empty() {
+            self.abort().await;
+            return Err(anyhow!("Completing upload of {} failed with {}: {}", self.key, status, text));
+        }
+
+        info!(
+            "Uploaded {} bytes in {} parts to s3://{}/{}",
+            self.uploaded,
+            self.parts.len(),
+            self.store.config.bucket,
+            self.key
+        );
+        Ok(())
+    }
+
+    /// Discard the upload and any parts already sent. Best effort: a bucket
+    /// lifecycle rule should clean up uploads this fails to abort.
+    pub async fn abort(&self) {
+        let request = self.store.signed_request(
+            reqwest::Method::DELETE,
+            &self.key,
+            &[("uploadId", &self.upload_id)],
+            Vec::new(),
+            None,
+        );
+        if let Ok(request) = request {
+            if let Err(e) = request.send().await {
+                warn!("Failed to abort upload of {}: {}", self.key, e);
+            }
+        }
+    }
+}
+
+impl std::io::Write for MultipartWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= MULTIPART_PART_SIZE {
+            tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(self.send_part()))
+                .map_err(std::io::Error::other)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // Parts below the minimum size can only be sent by `complete`
+        Ok(())
+    }
+}
+
+/// Inner text of every `<tag>...</tag>` in an S3 XML response. The S3 list
+/// format is flat enough that a full XML parser isn't warranted.
+fn xml_elements<'a>(body: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = body;
+
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else { break };
+        values.push(&after[..end]);
+        rest = &after[end + close.len()..];
+    }
+
+    values
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|e| anyhow!("Invalid HMAC key: {}", e))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// RFC 3986 encoding as required by SigV4; `/` is kept in paths.
+pub(crate) fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,492 @@
+use anyhow::{anyhow, Result};
+use opentelemetry_proto::tonic::{
+    collector::{
+        metrics::v1::{metrics_service_client::MetricsServiceClient, ExportMetricsServiceRequest},
+        trace::v1::{trace_service_client::TraceServiceClient, ExportTraceServiceRequest},
+    },
+    common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue},
+    metrics::v1::{metric, number_data_point, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics},
+    resource::v1::Resource,
+    trace::v1::{span::SpanKind, status::StatusCode, ResourceSpans, ScopeSpans, Span, Status},
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::transport::{Channel, ClientTlsConfig};
+use tracing::{debug, info, warn};
+
+use crate::ids;
+use crate::models::{DerivedMetricSample, LatencyEvent, PerformanceMetrics};
+use crate::storage::{self, MetricsStorage};
+
+/// OTLP export of latency events (as spans) and derived metrics (as gauges).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OtelConfig {
+    pub enabled: bool,
+    /// Collector endpoint, e.g. `http://localhost:4317` (gRPC) or `http://localhost:4318` (HTTP)
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    /// Extra request headers, e.g. authentication for a hosted backend
+    pub headers: BTreeMap<String, String>,
+    /// Spans buffered before an export is sent
+    pub batch_size: usize,
+    /// Upper bound on how long a partial batch waits
+    pub flush_interval_ms: u64,
+    /// How often per-component metrics are exported; 0 disables metrics
+    pub metrics_interval_secs: u64,
+    pub service_name: String,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_string(),
+            protocol: OtlpProtocol::Grpc,
+            headers: BTreeMap::new(),
+            batch_size: 512,
+            flush_interval_ms: 5000,
+            metrics_interval_secs: 60,
+            service_name: "vscode-latency-monitor".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    /// OTLP/gRPC
+    Grpc,
+    /// OTLP/HTTP with protobuf payloads
+    Http,
+}
+
+pub struct OtlpExporter {
+    config: OtelConfig,
+    http: reqwest::Client,
+}
+
+impl OtlpExporter {
+    pub fn new(config: &OtelConfig) -> Self {
+        Self {
+            config: config.clone(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn export_events(&self, events: &[LatencyEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let request = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(self.resource()),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(scope()),
+                    spans: events.iter().map(event_span).collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        match self.config.protocol {
+            OtlpProtocol::Grpc => {
+                let mut client = TraceServiceClient::new(self.channel().await?);
+                client.export(self.grpc_request(request)?).await?;
+            }
+            OtlpProtocol::Http => self.post("/v1/traces", request.encode_to_vec()).await?,
+        }
+
+        debug!("Exported {} spans over OTLP", events.len());
+        Ok(())
+    }
+
+    /// Export the per-component gauges plus one `vscode.derived.<name>`
+    /// gauge per derived metric.
+    pub async fn export_metrics(&self, metrics: &[PerformanceMetrics], derived: &[DerivedMetricSample]) -> Result<()> {
+        if metrics.is_empty() && derived.is_empty() {
+            return Ok(());
+        }
+
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(self.resource()),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(scope()),
+                    metrics: gauges(metrics).into_iter().chain(derived.iter().map(derived_gauge)).collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        match self.config.protocol {
+            OtlpProtocol::Grpc => {
+                let mut client = MetricsServiceClient::new(self.channel().await?);
+                client.export(self.grpc_request(request)?).await?;
+            }
+            OtlpProtocol::Http => self.post("/v1/metrics", request.encode_to_vec()).await?,
+        }
+
+        Ok(())
+    }
+
+    async fn channel(&self) -> Result<Channel> {
+        let mut endpoint = Channel::from_shared(self.config.endpoint.clone())?
+            .timeout(Duration::from_secs(10));
+        if self.config.endpoint.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+        }
+        Ok(endpoint.connect().await?)
+    }
+
+    fn grpc_request<T>(&self, message: T) -> Result<tonic::Request<T>> {
+        let mut request = tonic::Request::new(message);
+        for (name, value) in &self.config.headers {
+            let key = tonic::metadata::MetadataKey::from_bytes(name.to_lowercase().as_bytes())
+                .map_err(|_| anyhow!("Invalid OTLP header name '{}'", name))?;
+            request.metadata_mut().insert(key, value.parse()?);
+        }
+        Ok(request)
+    }
+
+    async fn post(&self, path: &str, body: Vec<u8>) -> Result<()> {
+        let mut request = self
+            .http
+            .post(format!("{}{}", self.config.endpoint.trim_end_matches('/'), path))
+            .header("content-type", "application/x-protobuf")
+            .body(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("OTLP collector returned {} for {}", response.status(), path));
+        }
+        Ok(())
+    }
+
+    fn resource(&self) -> Resource {
+        Resource {
+            attributes: vec![
+                string_attribute("service.name", &self.config.service_name),
+                string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+/// Forward stored events to the collector in batches, and periodically push
+/// per-component metrics. Export failures are logged and the batch dropped so
+/// a down collector never backs up monitoring.
+pub async fn run_exporter(config: OtelConfig, storage: MetricsStorage) {
+    let exporter = OtlpExporter::new(&config);
+    let mut events = storage.subscribe();
+    let mut batch = Vec::with_capacity(config.batch_size);
+
+    let mut flush = tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(100)));
+    let mut metrics = tokio::time::interval(Duration::from_secs(config.metrics_interval_secs.max(1)));
+
+    info!(
+        "Exporting to OTLP collector at {} ({:?})",
+        config.endpoint, config.protocol
+    );
+
+    loop {
+        tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => {
+                    batch.push(event);
+                    if batch.len() >= config.batch_size {
+                        send_batch(&exporter, &mut batch).await;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("OTLP exporter skipped {} events", skipped),
+                Err(RecvError::Closed) => break,
+            },
+            _ = flush.tick() => send_batch(&exporter, &mut batch).await,
+            _ = metrics.tick(), if config.metrics_interval_secs > 0 => {
+                let current = async {
+                    anyhow::Ok((storage.get_performance_metrics().await?, storage.get_latest_derived_metrics().await?))
+                };
+                match current.await {
+                    Ok((current, derived)) => {
+                        if let Err(e) = exporter.export_metrics(&current, &derived).await {
+                            warn!("OTLP metrics export failed: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to compute metrics for OTLP export: {}", e),
+                }
+            }
+        }
+    }
+
+    send_batch(&exporter, &mut batch).await;
+}
+
+async fn send_batch(exporter: &OtlpExporter, batch: &mut Vec<LatencyEvent>) {
+    if batch.is_empty() {
+        return;
+    }
+    if let Err(e) = exporter.export_events(batch).await {
+        warn!("OTLP export of {} spans failed: {}", batch.len(), e);
+    }
+    batch.clear();
+}
+
+/// W3C trace context of the request that caused an event, from a
+/// `traceparent` header such as
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// The caller's span, 16 lowercase hex digits
+    pub parent_span_id: String,
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` value; `None` for anything the spec says to
+    /// ignore (bad lengths, non-hex digits, all-zero ids, version `ff`).
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let [version, trace_id, parent_span_id, flags, ..] = parts[..] else {
+            return None;
+        };
+        // Version 00 has exactly four fields; later versions may append more
+        if (version == "00" && parts.len() != 4) || version == "ff" || !is_hex(version, 2) {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(parent_span_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        if trace_id.bytes().all(|b| b == b'0') || parent_span_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_span_id: parent_span_id.to_string(),
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+        })
+    }
+
+    /// Record the context in the event's metadata, where it becomes the
+    /// `trace_id` and `parent_span_id` columns. Fails if the metadata is
+    /// something other than an object.
+    pub fn apply(&self, event: &mut LatencyEvent) -> Result<()> {
+        if event.metadata.is_null() {
+            event.metadata = serde_json::json!({});
+        }
+        let metadata = event
+            .metadata
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Event metadata must be an object to carry trace context"))?;
+        metadata.insert("trace_id".to_string(), self.trace_id.clone().into());
+        metadata.insert("parent_span_id".to_string(), self.parent_span_id.clone().into());
+        metadata.insert("trace_sampled".to_string(), self.sampled.into());
+        Ok(())
+    }
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hex id stored under `key` in the event's metadata, if it decodes to
+/// `len` bytes.
+fn metadata_id(event: &LatencyEvent, key: &str, len: usize) -> Option<Vec<u8>> {
+    let value = event.metadata.get(key)?.as_str()?;
+    hex::decode(value).ok().filter(|id| id.len() == len)
+}
+
+/// One span per event, ending at the event timestamp. Span ids derive from
+/// the event uid so re-exports are idempotent in the backend. Events
+/// ingested with a `traceparent` join the caller's trace as children of its
+/// span; the rest get a trace of their own, also derived from the uid.
+fn event_span(event: &LatencyEvent) -> Span {
+    let uid = storage::event_uid(event);
+    // A ULID's leading bytes are its timestamp, shared by events created in
+    // the same millisecond, so its span id comes from the random tail
+    let (id, span_id) = match ids::ulid_bytes(&uid) {
+        Some(bytes) => (bytes.to_vec(), bytes[8..].to_vec()),
+        None => {
+            let id = hex::decode(&uid).unwrap_or_else(|_| vec![0; 16]);
+            let span_id = id[..8].to_vec();
+            (id, span_id)
+        }
+    };
+    let trace_id = metadata_id(event, "trace_id", 16).unwrap_or_else(|| id.clone());
+    let parent_span_id = metadata_id(event, "parent_span_id", 8).unwrap_or_default();
+    let end = event.timestamp.timestamp_nanos_opt().unwrap_or_default() as u64;
+    let start = end.saturating_sub(event.duration.as_nanos() as u64);
+
+    let mut attributes = vec![
+        string_attribute("vscode.component", &event.component_type.to_string()),
+        string_attribute("vscode.event_source", event.event_source.as_str()),
+        string_attribute("vscode.event_kind", event.kind.as_str()),
+        string_attribute("vscode.description", &event.description),
+        KeyValue {
+            key: "vscode.duration_us".to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(event.duration_us() as i64)),
+            }),
+        },
+    ];
+    if !event.metadata.is_null() {
+        attributes.push(string_attribute("vscode.metadata", &event.metadata.to_string()));
+    }
+
+    let failed = event.metadata.get("success").and_then(|s| s.as_bool()) == Some(false)
+        || event.metadata.get("error").is_some_and(|e| !e.is_null());
+
+    Span {
+        trace_id,
+        span_id,
+        parent_span_id,
+        name: format!("{} {}", event.component_type, event.kind),
+        kind: SpanKind::Internal as i32,
+        start_time_unix_nano: start,
+        end_time_unix_nano: end,
+        attributes,
+        status: failed.then(|| Status {
+            code: StatusCode::Error as i32,
+            message: String::new(),
+        }),
+        ..Default::default()
+    }
+}
+
+type MetricValue = fn(&PerformanceMetrics) -> f64;
+
+fn gauges(metrics: &[PerformanceMetrics]) -> Vec<Metric> {
+    let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    let series: [(&str, &str, MetricValue); 6] = [
+        ("vscode.latency.avg", "ms", |m| m.avg_duration_ms),
+        ("vscode.latency.p50", "ms", |m| m.p50_duration_ms as f64),
+        ("vscode.latency.p95", "ms", |m| m.p95_duration_ms as f64),
+        ("vscode.latency.p99", "ms", |m| m.p99_duration_ms as f64),
+        ("vscode.events.rate", "{event}/s", |m| m.events_per_second),
+        ("vscode.events.error_rate", "1", |m| m.error_rate),
+    ];
+
+    series
+        .iter()
+        .map(|(name, unit, value)| Metric {
+            name: name.to_string(),
+            unit: unit.to_string(),
+            data: Some(metric::Data::Gauge(Gauge {
+                data_points: metrics
+                    .iter()
+                    .map(|m| NumberDataPoint {
+                        attributes: vec![string_attribute("vscode.component", &m.component.to_string())],
+                        time_unix_nano: now,
+                        value: Some(number_data_point::Value::AsDouble(value(m))),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn derived_gauge(sample: &DerivedMetricSample) -> Metric {
+    Metric {
+        name: format!("vscode.derived.{}", sample.name),
+        data: Some(metric::Data::Gauge(Gauge {
+            data_points: vec![NumberDataPoint {
+                time_unix_nano: sample.at.timestamp_nanos_opt().unwrap_or_default() as u64,
+                value: Some(number_data_point::Value::AsDouble(sample.value)),
+                ..Default::default()
+            }],
+        })),
+        ..Default::default()
+    }
+}
+
+fn scope() -> InstrumentationScope {
+    InstrumentationScope {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        ..Default::default()
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ComponentType, EventSource};
+    use std::time::Duration;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parses_valid_traceparents() {
+        let context = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.parent_span_id, "00f067aa0ba902b7");
+        assert!(context.sampled);
+
+        assert!(!TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap().sampled);
+        // Later versions may append fields
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+    }
+
+    #[test]
+    fn ignores_traceparents_the_spec_rejects() {
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-zz",
+        ] {
+            assert_eq!(TraceContext::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn spans_join_the_callers_trace() {
+        let mut event = LatencyEvent::new(
+            ComponentType::LocalModel,
+            EventSource::NetworkRequest,
+            Duration::from_millis(250),
+            "completion".to_string(),
+        );
+        let own_trace = event_span(&event).trace_id;
+        assert!(event_span(&event).parent_span_id.is_empty());
+
+        TraceContext::parse(TRACEPARENT).unwrap().apply(&mut event).unwrap();
+        let span = event_span(&event);
+        assert_eq!(hex::encode(&span.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(hex::encode(&span.parent_span_id), "00f067aa0ba902b7");
+        assert_ne!(span.trace_id, own_trace);
+        assert_eq!(span.end_time_unix_nano - span.start_time_unix_nano, 250_000_000);
+
+        let mut listed = event.clone();
+        listed.metadata = serde_json::json!([1, 2]);
+        assert!(TraceContext::parse(TRACEPARENT).unwrap().apply(&mut listed).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::filter::EventFilter;
+use crate::models::{EventKind, LatencyEvent};
+
+/// What one rule that discards or hides events would do to a sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleOutcome {
+    /// e.g. `load_shedding stage 1`
+    pub rule: String,
+    /// What dropped events are lost to
+    pub effect: String,
+    pub kept: usize,
+    pub dropped: usize,
+}
+
+impl RuleOutcome {
+    fn new(rule: String, effect: &str, events: &[LatencyEvent], drops: impl Fn(&LatencyEvent) -> bool) -> Self {
+        let dropped = events.iter().filter(|event| drops(event)).count();
+        Self {
+            rule,
+            effect: effect.to_string(),
+            kept: events.len() - dropped,
+            dropped,
+        }
+    }
+
+    pub fn dropped_percent(&self) -> f64 {
+        let total = self.kept + self.dropped;
+        if total == 0 {
+            0.0
+        } else {
+            self.dropped as f64 / total as f64 * 100.0
+        }
+    }
+}
+
+/// Run every rule that can discard or hide events over `events`, in the
+/// order they apply: each load-shedding stage (cumulative, as the monitor
+/// escalates), the test-data exclusion, then `filters` as a preview of
+/// what a filter expression would keep. Rules are evaluated independently
+/// against the whole sample.
+pub fn explain(config: &Config, events: &[LatencyEvent], filters: &[EventFilter]) -> Vec<RuleOutcome> {
+    let mut outcomes = Vec::new();
+
+    let shedding = &config.monitoring.load_shedding;
+    if shedding.enabled {
+        for stage in 1..=shedding.shed_kinds.len() {
+            let shed: Vec<EventKind> = shedding.shed_kinds.iter().take(stage).copied().collect();
+            let rule = format!(
+                "load_shedding stage {} ({})",
+                stage,
+                shed.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(", ")
+            );
+            outcomes.push(RuleOutcome::new(rule, "never stored while degraded", events, |event| {
+                shed.contains(&event.kind)
+            }));
+        }
+    }
+
+    outcomes.push(RuleOutcome::new(
+        "test data".to_string(),
+        "left out of metrics and reports",
+        events,
+        LatencyEvent::is_test_data,
+    ));
+
+    for filter in filters {
+        outcomes.push(RuleOutcome::new(
+            format!("filter: {}", filter),
+            "don't match",
+            events,
+            |event| !filter.matches(event),
+        ));
+    }
+
+    outcomes
+}
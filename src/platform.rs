@@ -0,0 +1,472 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::Duration;
+use sysinfo::{Pid, Process, System};
+
+use crate::workspace::{self, Workspace, Workspaces};
+
+/// VS Code main and helper executables, as returned by [`process_stem`],
+/// including the VSCodium and Cursor forks.
+pub const VSCODE_NAMES: &[&str] = &[
+    "code", "code-insiders", "code - insiders", "code-oss", "code-server", "codium", "vscodium", "cursor",
+];
+
+/// On macOS, Electron runs each child process from a helper app named after
+/// its role, e.g. `Code Helper (Renderer)` or `Code Helper (GPU)`.
+const MACOS_HELPER_PREFIXES: &[&str] = &[
+    "code helper", "code - insiders helper", "code - oss helper", "vscodium helper", "cursor helper",
+];
+
+/// How far up the process tree to look for the main process's `--profile`,
+/// the renderer an extension host was started by, the VS Code process
+/// behind a language server, or the workspace a process works on.
+const MAX_ANCESTORS: usize = 8;
+
+/// Renderer switch naming the window it draws, `vscode:<window id>`.
+const WINDOW_CONFIG_ARG: &str = "--vscode-window-config=";
+
+/// Environment variable holding the pipe an extension host serves its
+/// window on; one per window.
+const EXTHOST_PIPE_VAR: &str = "VSCODE_IPC_HOOK_EXTHOST=";
+
+/// Shells and terminal emulators on Linux, macOS and Windows. Anything with
+/// "terminal" in its name (gnome-terminal, WindowsTerminal) also counts.
+const TERMINAL_NAMES: &[&str] = &[
+    "bash", "zsh", "fish", "sh", "konsole", "iterm2", "powershell", "pwsh", "cmd", "wt",
+];
+
+/// Command-line fragments of the Copilot agent and language server. On
+/// Windows they run as `Code.exe` or `node.exe` helpers, so the name alone
+/// doesn't identify them.
+const COPILOT_MARKERS: &[&str] = &["github.copilot", "copilot-agent", "copilot-language-server"];
+
+/// Language servers by the name recorded in `metadata.language_server` and
+/// a command-line fragment that identifies them. Most run as `node` (or as
+/// VS Code's own executable), so the script path is what gives them away.
+const LANGUAGE_SERVERS: &[(&str, &str)] = &[
+    ("rust-analyzer", "rust-analyzer"),
+    ("pyright", "pyright"),
+    ("pylance", "vscode-pylance"),
+    ("tsserver", "tsserver"),
+    ("gopls", "gopls"),
+    ("clangd", "clangd"),
+];
+
+/// This monitor's own executable, which `lsp-proxy` runs in front of a
+/// language server with the server's path on its command line.
+const MONITOR_NAME: &str = "vscode-latency-monitor";
+
+/// Lowercased executable name without a Windows `.exe` suffix, so `Code.exe`
+/// and `code` compare equal.
+pub fn process_stem(process: &Process) -> String {
+    let name = process.name().to_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+pub fn is_vscode(process: &Process) -> bool {
+    let stem = process_stem(process);
+    VSCODE_NAMES.contains(&stem.as_str())
+        || MACOS_HELPER_PREFIXES.iter().any(|prefix| stem.starts_with(prefix))
+        // Older macOS builds keep Electron's own name for the main process
+        || (stem == "electron"
+            && process
+                .exe()
+                .is_some_and(|exe| exe.to_string_lossy().contains("Visual Studio Code")))
+}
+
+/// Part of VS Code a process plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VSCodeRole {
+    Main,
+    Renderer,
+    Gpu,
+    ExtensionHost,
+    /// Any other Electron child (network service, file watcher, ...)
+    Helper,
+}
+
+/// Role of a VS Code process, from Electron's `--type=` switch or, when the
+/// command line can't be read (other users' processes on macOS), the
+/// helper app it runs from. `None` for processes that aren't VS Code's.
+pub fn vscode_role(process: &Process) -> Option<VSCodeRole> {
+    let cmd = process.cmd();
+    let stem = process_stem(process);
+    if stem.contains("extensionhost") || cmd.iter().any(|arg| arg.contains("extensionHost")) {
+        return Some(VSCodeRole::ExtensionHost);
+    }
+    if !is_vscode(process) {
+        return None;
+    }
+
+    // macOS helper apps: `Code Helper (Plugin)` hosts extensions
+    let helper = MACOS_HELPER_PREFIXES
+        .iter()
+        .find_map(|prefix| stem.strip_prefix(prefix))
+        .map(|suffix| match suffix.trim() {
+            "(renderer)" => VSCodeRole::Renderer,
+            "(gpu)" => VSCodeRole::Gpu,
+            "(plugin)" => VSCodeRole::ExtensionHost,
+            _ => VSCodeRole::Helper,
+        });
+
+    Some(match cmd.iter().find_map(|arg| arg.strip_prefix("--type=")) {
+        Some("renderer") => VSCodeRole::Renderer,
+        Some("gpu-process") => VSCodeRole::Gpu,
+        Some(_) => helper.unwrap_or(VSCodeRole::Helper),
+        None => helper.unwrap_or(VSCodeRole::Main),
+    })
+}
+
+/// Distribution of VS Code a process belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VSCodeBuild {
+    Stable,
+    Insiders,
+    /// Unbranded builds from source (`code-oss`)
+    Oss,
+    VSCodium,
+    Cursor,
+}
+
+impl VSCodeBuild {
+    /// Value stored in `metadata.vscode_build`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VSCodeBuild::Stable => "stable",
+            VSCodeBuild::Insiders => "insiders",
+            VSCodeBuild::Oss => "oss",
+            VSCodeBuild::VSCodium => "vscodium",
+            VSCodeBuild::Cursor => "cursor",
+        }
+    }
+
+    /// Directory in the platform's config directory holding this build's
+    /// user data, unless `--user-data-dir` moves it.
+    fn data_dir_name(&self) -> &'static str {
+        match self {
+            VSCodeBuild::Stable => "Code",
+            VSCodeBuild::Insiders => "Code - Insiders",
+            VSCodeBuild::Oss => "Code - OSS",
+            VSCodeBuild::VSCodium => "VSCodium",
+            VSCodeBuild::Cursor => "Cursor",
+        }
+    }
+}
+
+/// Build of a VS Code process, from its executable name and path (helpers
+/// run from inside the app's install directory, so they match too).
+/// `None` for processes that aren't VS Code's.
+pub fn vscode_build(process: &Process) -> Option<VSCodeBuild> {
+    if !is_vscode(process) && vscode_role(process).is_none() {
+        return None;
+    }
+
+    let exe = process.exe().map(|exe| exe.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let location = format!("{} {}", process_stem(process), exe);
+    Some(if location.contains("insiders") {
+        VSCodeBuild::Insiders
+    } else if location.contains("codium") {
+        VSCodeBuild::VSCodium
+    } else if location.contains("cursor") {
+        VSCodeBuild::Cursor
+    } else if location.contains("code-oss") || location.contains("code - oss") {
+        VSCodeBuild::Oss
+    } else {
+        VSCodeBuild::Stable
+    })
+}
+
+/// Profile a VS Code process was started with (`--profile <name>`). Only
+/// the main process has the switch, so renderers and extension hosts
+/// inherit it from their nearest ancestor that does. Profiles switched to
+/// inside a running window don't show up on any command line.
+pub fn vscode_profile(system: &System, process: &Process) -> Option<String> {
+    let mut current = Some(process);
+    for _ in 0..MAX_ANCESTORS {
+        let process = current?;
+        if let Some(profile) = profile_arg(process.cmd()) {
+            return Some(profile);
+        }
+        current = process.parent().and_then(|parent| system.process(parent));
+    }
+    None
+}
+
+/// Log directory of the VS Code instance a process belongs to: `logs` in
+/// the `--user-data-dir` of its nearest ancestor started with one, or in
+/// its build's default user data directory.
+pub fn vscode_logs_dir(system: &System, process: &Process) -> Option<PathBuf> {
+    let mut current = Some(process);
+    for _ in 0..MAX_ANCESTORS {
+        let Some(ancestor) = current else { break };
+        if let Some(dir) = switch_value(ancestor.cmd(), "--user-data-dir") {
+            return Some(PathBuf::from(dir).join("logs"));
+        }
+        current = ancestor.parent().and_then(|parent| system.process(parent));
+    }
+    let build = vscode_build(process)?;
+    Some(dirs::config_dir()?.join(build.data_dir_name()).join("logs"))
+}
+
+/// Window a renderer or extension host belongs to, so latency can be told
+/// apart per window. Renderers name theirs in `--vscode-window-config`, and
+/// extension hosts started by a renderer share it. Extension hosts started
+/// by the main process (newer releases) are identified by their IPC pipe
+/// instead, which is stable for the window's lifetime but differs from the
+/// renderer's id. `None` for the main process and other helpers.
+pub fn vscode_window(system: &System, process: &Process) -> Option<String> {
+    match vscode_role(process)? {
+        VSCodeRole::Renderer => window_config_arg(process.cmd()),
+        VSCodeRole::ExtensionHost => parent_renderer(system, process)
+            .and_then(|renderer| window_config_arg(renderer.cmd()))
+            .or_else(|| exthost_pipe(process)),
+        _ => None,
+    }
+}
+
+/// Workspace a VS Code process, or a language server or tool it started,
+/// works on: the one the nearest extension host among its ancestors
+/// announced over IPC, or failing that the `--folder-uri` the main process
+/// was started with. Renderers aren't descended from their extension host,
+/// so only the command line names theirs.
+pub fn vscode_workspace(system: &System, process: &Process, announced: &Workspaces) -> Option<Workspace> {
+    let mut current = Some(process);
+    for _ in 0..MAX_ANCESTORS {
+        let process = current?;
+        if let Some(workspace) = announced.get(process.pid().as_u32()) {
+            return Some(workspace);
+        }
+        current = process.parent().and_then(|parent| system.process(parent));
+    }
+
+    let mut current = Some(process);
+    for _ in 0..MAX_ANCESTORS {
+        let process = current?;
+        if let Some(workspace) = workspace::from_args(process.cmd()) {
+            return Some(workspace);
+        }
+        current = process.parent().and_then(|parent| system.process(parent));
+    }
+    None
+}
+
+fn parent_renderer<'a>(system: &'a System, process: &Process) -> Option<&'a Process> {
+    let mut current = process.parent().and_then(|parent| system.process(parent));
+    for _ in 0..MAX_ANCESTORS {
+        let ancestor = current?;
+        if vscode_role(ancestor) == Some(VSCodeRole::Renderer) {
+            return Some(ancestor);
+        }
+        current = ancestor.parent().and_then(|parent| system.process(parent));
+    }
+    None
+}
+
+fn window_config_arg(args: &[String]) -> Option<String> {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix(WINDOW_CONFIG_ARG))
+        .map(|config| config.trim_start_matches("vscode:").to_string())
+        .filter(|id| !id.is_empty())
+}
+
+/// Name of the extension host's pipe, e.g. `vscode-ipc-<uuid>` for
+/// `/run/user/1000/vscode-ipc-<uuid>.sock`. The environment of other users'
+/// processes can't be read.
+fn exthost_pipe(process: &Process) -> Option<String> {
+    let path = process.environ().iter().find_map(|var| var.strip_prefix(EXTHOST_PIPE_VAR))?;
+    let name = path.rsplit(['/', '\\']).next()?;
+    let name = name.strip_suffix(".sock").unwrap_or(name);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn profile_arg(args: &[String]) -> Option<String> {
+    switch_value(args, "--profile")
+}
+
+/// Value of `switch` given as `--switch=value` or `--switch value`.
+fn switch_value(args: &[String], switch: &str) -> Option<String> {
+    args.iter().enumerate().find_map(|(index, arg)| match arg.strip_prefix(switch) {
+        Some(value) if value.starts_with('=') => Some(value[1..].to_string()),
+        Some("") => args.get(index + 1).filter(|next| !next.starts_with('-')).cloned(),
+        _ => None,
+    })
+}
+
+pub fn is_terminal(process: &Process) -> bool {
+    let stem = process_stem(process);
+    TERMINAL_NAMES.contains(&stem.as_str()) || stem.contains("terminal")
+}
+
+pub fn is_copilot(process: &Process) -> bool {
+    if process_stem(process).contains("copilot") {
+        return true;
+    }
+    // Windows paths use backslashes and arbitrary case
+    let cmd_line = process.cmd().join(" ").to_lowercase().replace('\\', "/");
+    COPILOT_MARKERS.iter().any(|marker| cmd_line.contains(marker))
+}
+
+/// Which language server a process is, if it is one of [`LANGUAGE_SERVERS`].
+pub fn language_server(process: &Process) -> Option<&'static str> {
+    let stem = process_stem(process);
+    if stem.starts_with(MONITOR_NAME) {
+        return None;
+    }
+    language_server_in(&stem, process.cmd())
+}
+
+/// Which language server a program and its arguments start, e.g. `tsserver`
+/// for `node .../typescript/lib/tsserver.js`.
+pub fn language_server_in(program: &str, args: &[String]) -> Option<&'static str> {
+    // Windows paths use backslashes and arbitrary case
+    let cmd_line = format!("{} {}", program, args.join(" ")).to_lowercase().replace('\\', "/");
+    LANGUAGE_SERVERS
+        .iter()
+        .find(|(_, marker)| cmd_line.contains(marker))
+        .map(|(name, _)| *name)
+}
+
+/// Whether VS Code (usually an extension host) started `process`, to tell
+/// its language servers apart from ones another editor runs.
+pub fn spawned_by_vscode(system: &System, process: &Process) -> bool {
+    let mut current = process.parent().and_then(|parent| system.process(parent));
+    for _ in 0..MAX_ANCESTORS {
+        let Some(ancestor) = current else {
+            return false;
+        };
+        if vscode_role(ancestor).is_some() {
+            return true;
+        }
+        current = ancestor.parent().and_then(|parent| system.process(parent));
+    }
+    false
+}
+
+/// The extension host `process` descends from, e.g. for a language server
+/// or tool an extension started.
+pub fn extension_host_of(system: &System, process: &Process) -> Option<Pid> {
+    let mut current = process.parent().and_then(|parent| system.process(parent).map(|p| (parent, p)));
+    for _ in 0..MAX_ANCESTORS {
+        let (pid, ancestor) = current?;
+        if vscode_role(ancestor) == Some(VSCodeRole::ExtensionHost) {
+            return Some(pid);
+        }
+        current = ancestor.parent().and_then(|parent| system.process(parent).map(|p| (parent, p)));
+    }
+    None
+}
+
+/// Time a process has spent blocked waiting for disk I/O since it started,
+/// from `delayacct_blkio_ticks` in `/proc/<pid>/stat`. Linux only, and
+/// always zero unless delay accounting is on (`kernel.task_delayacct = 1`).
+#[cfg(target_os = "linux")]
+pub fn io_wait(pid: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses; the fields after
+    // its closing ')' start at field 3
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let ticks: u64 = fields.get(42 - 3)?.parse().ok()?;
+    Some(ticks_to_duration(ticks))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn io_wait(_pid: u32) -> Option<Duration> {
+    None
+}
+
+/// CPU time spent idle with I/O outstanding, and all CPU time, since boot
+/// in clock ticks, from the `cpu` line of `/proc/stat`. Linux only.
+#[cfg(target_os = "linux")]
+pub fn cpu_iowait_ticks() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    // user nice system idle iowait irq softirq steal; guest time is
+    // already counted in user
+    let ticks: Vec<u64> = line.split_whitespace().skip(1).take(8).filter_map(|v| v.parse().ok()).collect();
+    Some((*ticks.get(4)?, ticks.iter().sum()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cpu_iowait_ticks() -> Option<(u64, u64)> {
+    None
+}
+
+/// CPU time (user plus system) each thread of a process has used since it
+/// started, keyed by thread id, from `/proc/<pid>/task/<tid>/stat`. The
+/// main thread's id is the process id. Linux only.
+#[cfg(target_os = "linux")]
+pub fn thread_cpu_times(pid: u32) -> Option<HashMap<u32, Duration>> {
+    let mut times = HashMap::new();
+    for entry in std::fs::read_dir(format!("/proc/{}/task", pid)).ok()?.flatten() {
+        let Some(tid) = entry.file_name().to_str().and_then(|name| name.parse().ok()) else {
+            continue;
+        };
+        // Threads can exit between listing and reading
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        let Some((_, rest)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let ticks = |field: usize| fields.get(field - 3).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        times.insert(tid, ticks_to_duration(ticks(14) + ticks(15)));
+    }
+    Some(times)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn thread_cpu_times(_pid: u32) -> Option<HashMap<u32, Duration>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let hz = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let hz = if hz > 0 { hz as u64 } else { 100 };
+    Duration::from_micros(ticks * 1_000_000 / hz)
+}
+
+/// This machine's address on the LAN, if it has one.
+pub fn lan_ip() -> Option<IpAddr> {
+    route_lan_ip().or_else(default_interface_ip)
+}
+
+/// Address of the interface that routes to the internet, without sending
+/// anything: connecting a UDP socket only selects a route. Works the same on
+/// every platform, unlike parsing `ip route` or `ipconfig` output.
+fn route_lan_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+/// macOS fallback for LANs without a route to the internet: the address of
+/// the default route's interface, from `route get` and `ipconfig`.
+#[cfg(target_os = "macos")]
+fn default_interface_ip() -> Option<IpAddr> {
+    use std::process::Command;
+
+    let route = Command::new("route").args(["-n", "get", "default"]).output().ok()?;
+    let route = String::from_utf8(route.stdout).ok()?;
+    let interface = route
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("interface:"))?
+        .trim()
+        .to_string();
+
+    let address = Command::new("ipconfig").args(["getifaddr", &interface]).output().ok()?;
+    String::from_utf8(address.stdout).ok()?.trim().parse().ok()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn default_interface_ip() -> Option<IpAddr> {
+    None
+}
@@ -0,0 +1,311 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    postgres::{PgPool, PgPoolOptions, PgRow},
+    Row,
+};
+use tracing::info;
+
+use crate::backend::{BackendFuture, MetricsBackend, StorageBackend};
+use crate::models::{EventDeletion, EventKind, LatencyEvent, PerformanceMetrics};
+use crate::secrets::Secrets;
+use crate::storage::{event_uid, latency_kinds_sql, parse_component, parse_source};
+use crate::timerange::TimeRange;
+
+/// Shared Postgres database for `storage.backend = "postgres"`, configured
+/// as `[storage.postgres]`. The connection URL carries credentials, so it
+/// is read from a secret rather than `config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostgresConfig {
+    /// Secret holding the URL, e.g. `postgres://user:password@db:5432/latency`
+    pub url_secret: String,
+    /// Recorded with every event so developers can be told apart
+    /// (defaults to the hostname)
+    pub host: Option<String>,
+    pub max_connections: u32,
+    /// How often the monitor sends newly stored events
+    pub replicate_interval_secs: u64,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        Self {
+            url_secret: "postgres_url".to_string(),
+            host: None,
+            max_connections: 5,
+            replicate_interval_secs: 10,
+        }
+    }
+}
+
+/// Events of many monitors in one Postgres database, keyed by the host
+/// that recorded them.
+pub struct PostgresStorage {
+    pool: PgPool,
+    host: String,
+}
+
+impl PostgresStorage {
+    pub async fn connect(config: &PostgresConfig, secrets: &Secrets) -> Result<Self> {
+        let url = secrets
+            .get(&config.url_secret)
+            .ok_or_else(|| anyhow!("Missing secret '{}' for the postgres backend", config.url_secret))?;
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections.max(1))
+            .connect(&url)
+            .await?;
+
+        let host = config
+            .host
+            .clone()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "unknown".to_string());
+        let storage = Self { pool, host };
+        storage.initialize_schema().await?;
+
+        info!("Connected to the postgres backend as host '{}'", storage.host);
+        Ok(storage)
+    }
+
+    async fn initialize_schema(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS latency_events (
+                id BIGSERIAL PRIMARY KEY,
+                host TEXT NOT NULL,
+                uid TEXT NOT NULL,
+                timestamp TIMESTAMPTZ NOT NULL,
+                component_type TEXT NOT NULL,
+                event_source TEXT NOT NULL,
+                event_kind TEXT NOT NULL,
+                duration_us BIGINT NOT NULL,
+                description TEXT NOT NULL,
+                metadata JSONB,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                UNIQUE (host, uid)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Deletions replicated from each host; deleted events drop out of
+        // every read but are kept, so a restore can bring them back
+        sqlx::query("ALTER TABLE latency_events ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_events_timestamp ON latency_events(timestamp)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_events_host ON latency_events(host)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_events(&self, events: &[LatencyEvent]) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.pool.begin().await?;
+
+        for event in events {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO latency_events
+                (host, uid, timestamp, component_type, event_source, event_kind, duration_us, description, metadata)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::jsonb)
+                ON CONFLICT (host, uid) DO NOTHING
+                "#,
+            )
+            .bind(&self.host)
+            .bind(event_uid(event))
+            .bind(event.timestamp)
+            .bind(event.component_type.as_str())
+            .bind(event.event_source.as_str())
+            .bind(event.kind.as_str())
+            .bind(event.duration_us() as i64)
+            .bind(&event.description)
+            .bind(serde_json::to_string(&event.metadata)?)
+            .execute(&mut *tx)
+            .await?;
+            inserted += result.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    /// Only this host's copies change: an event synced between machines is
+    /// stored once per host that replicated it, under the same uid.
+    async fn mark_deleted(&self, deletions: &[EventDeletion]) -> Result<u64> {
+        let mut changed = 0;
+        let mut tx = self.pool.begin().await?;
+
+        for deletion in deletions {
+            let result = sqlx::query(
+                "UPDATE latency_events SET deleted_at = $1 WHERE host = $2 AND uid = $3 \
+                 AND deleted_at IS DISTINCT FROM $1",
+            )
+            .bind(deletion.deleted_at)
+            .bind(&self.host)
+            .bind(&deletion.uid)
+            .execute(&mut *tx)
+            .await?;
+            changed += result.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(changed)
+    }
+
+    async fn recent_events(&self, limit: u32) -> Result<Vec<LatencyEvent>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, host, uid, timestamp, component_type, event_source, event_kind, duration_us, description,
+                   metadata::text AS metadata
+            FROM latency_events
+            WHERE deleted_at IS NULL
+            ORDER BY timestamp DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(event_from_row).collect()
+    }
+
+    /// Per-component statistics over every host. Filters and working hours
+    /// are SQLite expressions, so only a plain time range is accepted.
+    async fn performance_metrics(&self, range: &TimeRange) -> Result<Vec<PerformanceMetrics>> {
+        if range.filter.is_some() || range.working_hours.is_some() {
+            return Err(anyhow!("The postgres backend doesn't support filters or working hours"));
+        }
+
+        let sql = format!(
+            r#"
+            SELECT
+                component_type,
+                COUNT(*) AS total_events,
+                AVG(duration_us)::float8 / 1000 AS avg_duration_ms,
+                MIN(duration_us) / 1000 AS min_duration_ms,
+                MAX(duration_us) / 1000 AS max_duration_ms,
+                percentile_disc(0.50) WITHIN GROUP (ORDER BY duration_us) / 1000 AS p50_duration_ms,
+                percentile_disc(0.95) WITHIN GROUP (ORDER BY duration_us) / 1000 AS p95_duration_ms,
+                percentile_disc(0.99) WITHIN GROUP (ORDER BY duration_us) / 1000 AS p99_duration_ms,
+                COUNT(*) FILTER (WHERE metadata->>'success' = 'false' OR metadata->>'error' IS NOT NULL) AS error_events
+            FROM latency_events
+            WHERE event_kind IN ({})
+              AND deleted_at IS NULL
+              AND ($1::timestamptz IS NULL OR timestamp >= $1)
+              AND ($2::timestamptz IS NULL OR timestamp < $2)
+              AND ($3 OR NOT (event_source = 'TestCommand' OR COALESCE((metadata->>'test_data')::boolean, false)))
+            GROUP BY component_type
+            "#,
+            latency_kinds_sql()
+        );
+        let rows = sqlx::query(&sql)
+            .bind(range.since)
+            .bind(range.until)
+            .bind(range.include_test_data)
+            .fetch_all(&self.pool)
+            .await?;
+
+        // An open-ended range spans from the first event
+        let first_event: Option<DateTime<Utc>> = match range.since {
+            Some(_) => None,
+            None => {
+                sqlx::query_scalar("SELECT MIN(timestamp) FROM latency_events WHERE deleted_at IS NULL")
+                    .fetch_one(&self.pool)
+                    .await?
+            }
+        };
+        let window_seconds = range.seconds(first_event);
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let total_events = row.get::<i64, _>("total_events") as u64;
+                let error_events = row.get::<i64, _>("error_events") as u64;
+                PerformanceMetrics {
+                    component: parse_component(&row.get::<String, _>("component_type")),
+                    total_events,
+                    avg_duration_ms: row.get("avg_duration_ms"),
+                    min_duration_ms: row.get::<i64, _>("min_duration_ms") as u64,
+                    max_duration_ms: row.get::<i64, _>("max_duration_ms") as u64,
+                    p50_duration_ms: row.get::<i64, _>("p50_duration_ms") as u64,
+                    p95_duration_ms: row.get::<i64, _>("p95_duration_ms") as u64,
+                    p99_duration_ms: row.get::<i64, _>("p99_duration_ms") as u64,
+                    events_per_second: total_events as f64 / window_seconds,
+                    error_rate: if total_events > 0 {
+                        error_events as f64 / total_events as f64
+                    } else {
+                        0.0
+                    },
+                    last_updated: Utc::now(),
+                }
+            })
+            .collect())
+    }
+
+    async fn total_events(&self) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM latency_events WHERE deleted_at IS NULL")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+}
+
+impl MetricsBackend for PostgresStorage {
+    fn name(&self) -> &'static str {
+        StorageBackend::Postgres.as_str()
+    }
+
+    fn store_events<'a>(&'a self, events: &'a [LatencyEvent]) -> BackendFuture<'a, u64> {
+        Box::pin(self.insert_events(events))
+    }
+
+    fn apply_deletions<'a>(&'a self, deletions: &'a [EventDeletion]) -> BackendFuture<'a, u64> {
+        Box::pin(self.mark_deleted(deletions))
+    }
+
+    fn get_recent_events(&self, limit: u32) -> BackendFuture<'_, Vec<LatencyEvent>> {
+        Box::pin(self.recent_events(limit))
+    }
+
+    fn get_performance_metrics_in<'a>(&'a self, range: &'a TimeRange) -> BackendFuture<'a, Vec<PerformanceMetrics>> {
+        Box::pin(self.performance_metrics(range))
+    }
+
+    fn count_events(&self) -> BackendFuture<'_, u64> {
+        Box::pin(self.total_events())
+    }
+}
+
+/// An event with the host that recorded it added to its metadata.
+fn event_from_row(row: &PgRow) -> Result<LatencyEvent> {
+    let mut metadata: serde_json::Value = row
+        .get::<Option<String>, _>("metadata")
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or(serde_json::Value::Null);
+    if let serde_json::Value::Object(fields) = &mut metadata {
+        fields.insert("host".to_string(), serde_json::json!(row.get::<String, _>("host")));
+    }
+
+    Ok(LatencyEvent {
+        id: Some(row.get("id")),
+        uid: Some(row.get("uid")),
+        timestamp: row.get("timestamp"),
+        component_type: parse_component(&row.get::<String, _>("component_type")),
+        event_source: parse_source(&row.get::<String, _>("event_source")),
+        kind: EventKind::parse(&row.get::<String, _>("event_kind")).unwrap_or_default(),
+        duration: std::time::Duration::from_micros(row.get::<i64, _>("duration_us").max(0) as u64),
+        description: row.get("description"),
+        metadata,
+    })
+}
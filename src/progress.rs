@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const ROWS_TEMPLATE: &str = "{msg} [{wide_bar}] {human_pos}/{human_len} ({per_sec}, {eta} left)";
+const ROWS_SPINNER_TEMPLATE: &str = "{spinner} {msg} {human_pos} ({per_sec}, {elapsed})";
+const BYTES_TEMPLATE: &str = "{msg} [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta} left)";
+const BYTES_SPINNER_TEMPLATE: &str = "{spinner} {msg} {bytes} ({bytes_per_sec}, {elapsed})";
+
+/// Set by the first Ctrl+C once any command has started reporting progress.
+static CTRL_C: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// How far a long-running command has got, drawn as a bar on stderr when
+/// that is a terminal, and whether Ctrl+C has asked it to stop. Operations
+/// check for cancellation between batches, so a stop never leaves half a
+/// batch written.
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+    bytes: bool,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Progress counted in rows, towards `total` when it is known.
+    pub fn rows(message: &str, total: Option<u64>) -> Self {
+        Self::start(message, total, false)
+    }
+
+    /// Progress counted in bytes, towards `total` when it is known.
+    pub fn bytes(message: &str, total: Option<u64>) -> Self {
+        Self::start(message, total, true)
+    }
+
+    /// No bar and never cancelled, for work the CLI isn't waiting on.
+    pub fn hidden() -> Self {
+        Self {
+            bar: ProgressBar::hidden(),
+            bytes: false,
+            cancelled: Arc::default(),
+        }
+    }
+
+    fn start(message: &str, total: Option<u64>, bytes: bool) -> Self {
+        let progress = Self {
+            bar: ProgressBar::new(total.unwrap_or(0)),
+            bytes,
+            cancelled: catch_ctrl_c(),
+        };
+        progress.bar.set_message(message.to_string());
+        progress.set_length(total);
+        progress
+    }
+
+    /// Switch between a bar (`Some`) and a spinner (`None`).
+    pub fn set_length(&self, total: Option<u64>) {
+        let template = match (total.is_some(), self.bytes) {
+            (true, false) => ROWS_TEMPLATE,
+            (false, false) => ROWS_SPINNER_TEMPLATE,
+            (true, true) => BYTES_TEMPLATE,
+            (false, true) => BYTES_SPINNER_TEMPLATE,
+        };
+        self.bar.set_style(
+            ProgressStyle::with_template(template)
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> "),
+        );
+        match total {
+            Some(total) => {
+                self.bar.disable_steady_tick();
+                self.bar.set_length(total);
+            }
+            // A spinner only moves when ticked, and a single long statement
+            // may not report anything for minutes
+            None => self.bar.enable_steady_tick(Duration::from_millis(120)),
+        }
+    }
+
+    /// Start counting again from zero, for operations that make more than
+    /// one pass over the same rows.
+    pub fn restart(&self, message: &str) {
+        self.bar.reset();
+        self.bar.set_message(message.to_string());
+    }
+
+    pub fn inc(&self, done: u64) {
+        self.bar.inc(done);
+    }
+
+    /// Count `done` more, then fail if Ctrl+C was pressed.
+    pub fn advance(&self, done: u64) -> Result<()> {
+        self.inc(done);
+        self.check()
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(anyhow!("Cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Remove the bar, leaving the terminal for the command's summary.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Install the Ctrl+C handler once per process. The first press only sets
+/// the flag, letting the operation stop cleanly and remove partial output;
+/// a second exits at once for when a single step is taking too long.
+fn catch_ctrl_c() -> Arc<AtomicBool> {
+    CTRL_C
+        .get_or_init(|| {
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let flag = cancelled.clone();
+            tokio::spawn(async move {
+                while tokio::signal::ctrl_c().await.is_ok() {
+                    if flag.swap(true, Ordering::SeqCst) {
+                        std::process::exit(130);
+                    }
+                    eprintln!("\nStopping after the current batch; press Ctrl+C again to quit immediately");
+                }
+            });
+            cancelled
+        })
+        .clone()
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::histogram::{self, HistogramConfig};
+use crate::models::{ComponentType, LatencyExemplar};
+use crate::storage::MetricsStorage;
+
+/// OpenMetrics content type; exemplars are only valid in this format.
+pub const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// How far back to look for exemplar events.
+const EXEMPLAR_WINDOW_MINUTES: i64 = 60;
+const EXEMPLAR_CANDIDATES: u32 = 5000;
+
+/// Render `vscode_latency_seconds` histograms in OpenMetrics text format,
+/// with each component's bucket bounds from `[histograms]`. Each bucket
+/// carries the most recent event that fell into it as an exemplar
+/// (`event_id` for `/api/events/:id`, `trace_id` matching OTLP spans).
+/// Derived metrics follow as the `vscode_derived_metric` gauge.
+pub async fn render_openmetrics(storage: &MetricsStorage, buckets: &HistogramConfig) -> Result<String> {
+    let histograms = storage.get_latency_histograms(buckets).await?;
+    let candidates = storage
+        .get_exemplar_candidates(
+            Utc::now() - chrono::Duration::minutes(EXEMPLAR_WINDOW_MINUTES),
+            EXEMPLAR_CANDIDATES,
+        )
+        .await?;
+    let exemplars = pick_exemplars(&candidates, buckets);
+
+    let mut out = String::new();
+    out.push_str("# TYPE vscode_latency_seconds histogram\n");
+    out.push_str("# UNIT vscode_latency_seconds seconds\n");
+    out.push_str("# HELP vscode_latency_seconds Latency of monitored VS Code, model and terminal events.\n");
+
+    for histogram in &histograms {
+        let component = histogram.component.as_str();
+
+        for (index, bound) in histogram.bounds_us.iter().enumerate() {
+            let _ = write!(
+                out,
+                "vscode_latency_seconds_bucket{{component=\"{}\",le=\"{}\"}} {}",
+                component,
+                seconds(*bound),
+                histogram.bucket_counts[index]
+            );
+            if let Some(exemplar) = exemplars.get(&(histogram.component, index)) {
+                out.push_str(&format_exemplar(exemplar));
+            }
+            out.push('\n');
+        }
+
+        let _ = write!(
+            out,
+            "vscode_latency_seconds_bucket{{component=\"{}\",le=\"+Inf\"}} {}",
+            component, histogram.count
+        );
+        if let Some(exemplar) = exemplars.get(&(histogram.component, histogram.bounds_us.len())) {
+            out.push_str(&format_exemplar(exemplar));
+        }
+        out.push('\n');
+
+        let _ = writeln!(
+            out,
+            "vscode_latency_seconds_count{{component=\"{}\"}} {}",
+            component, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "vscode_latency_seconds_sum{{component=\"{}\"}} {}",
+            component,
+            histogram.sum_us as f64 / 1_000_000.0
+        );
+    }
+
+    let derived = storage.get_latest_derived_metrics().await?;
+    if !derived.is_empty() {
+        out.push_str("# TYPE vscode_derived_metric gauge\n");
+        out.push_str("# HELP vscode_derived_metric Value of a [derived_metrics] expression.\n");
+        for sample in &derived {
+            let _ = writeln!(out, "vscode_derived_metric{{name=\"{}\"}} {}", sample.name, sample.value);
+        }
+    }
+
+    out.push_str("# EOF\n");
+    Ok(out)
+}
+
+/// Newest event per (component, bucket index); index `len` is the +Inf bucket.
+fn pick_exemplars<'a>(
+    candidates: &'a [LatencyExemplar],
+    buckets: &HistogramConfig,
+) -> HashMap<(ComponentType, usize), &'a LatencyExemplar> {
+    let mut exemplars = HashMap::new();
+    let bounds: HashMap<ComponentType, Vec<u64>> =
+        ComponentType::ALL.into_iter().map(|c| (c, buckets.bounds_us(c))).collect();
+
+    // Candidates arrive newest first, so the first hit per bucket wins
+    for candidate in candidates {
+        let bucket = histogram::bucket_index(&bounds[&candidate.component], candidate.duration_us);
+        exemplars.entry((candidate.component, bucket)).or_insert(candidate);
+    }
+
+    exemplars
+}
+
+fn format_exemplar(exemplar: &LatencyExemplar) -> String {
+    let trace = exemplar
+        .trace_id
+        .as_deref()
+        .map(|trace_id| format!(",trace_id=\"{}\"", trace_id))
+        .unwrap_or_default();
+
+    format!(
+        " # {{event_id=\"{}\"{}}} {} {:.3}",
+        exemplar.event_id,
+        trace,
+        seconds(exemplar.duration_us),
+        exemplar.timestamp.timestamp_millis() as f64 / 1000.0
+    )
+}
+
+fn seconds(us: u64) -> String {
+    let value = us as f64 / 1_000_000.0;
+    format!("{}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn exemplar(event_id: i64, duration_us: u64, trace_id: Option<&str>) -> LatencyExemplar {
+        LatencyExemplar {
+            event_id,
+            trace_id: trace_id.map(str::to_string),
+            component: ComponentType::Terminal,
+            duration_us,
+            timestamp: Utc.timestamp_millis_opt(1_700_000_000_250).unwrap(),
+        }
+    }
+
+    #[test]
+    fn the_newest_candidate_per_bucket_is_the_exemplar() {
+        // Newest first: 7 and 6 share the 5ms bucket, 5 is past the last bound
+        let candidates = [exemplar(7, 4_000, None), exemplar(6, 3_000, None), exemplar(5, 60_000_000, None)];
+        let exemplars = pick_exemplars(&candidates, &HistogramConfig::default());
+
+        assert_eq!(exemplars.len(), 2);
+        assert_eq!(exemplars[&(ComponentType::Terminal, 0)].event_id, 7);
+        let inf = histogram::DEFAULT_BUCKETS_MS.len();
+        assert_eq!(exemplars[&(ComponentType::Terminal, inf)].event_id, 5);
+    }
+
+    #[test]
+    fn exemplars_carry_event_and_trace_ids() {
+        assert_eq!(
+            format_exemplar(&exemplar(42, 1_500, Some("4bf92f3577b34da6a3ce929d0e0e4736"))),
+            " # {event_id=\"42\",trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"} 0.0015 1700000000.250"
+        );
+        assert_eq!(format_exemplar(&exemplar(42, 2_000_000, None)), " # {event_id=\"42\"} 2 1700000000.250");
+    }
+}
@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep_until, timeout};
+use tracing::{debug, info, warn};
+
+use crate::config::CopilotProxyConfig;
+use crate::event_queue::EventQueue;
+use crate::models::{ComponentType, EventKind, EventSource, LatencyEvent};
+
+/// Longest CONNECT request head accepted from a client.
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+
+const TUNNEL_BUFFER_BYTES: usize = 16 * 1024;
+
+/// TLS record content types of interest.
+const TLS_HANDSHAKE: u8 = 0x16;
+const TLS_APPLICATION_DATA: u8 = 0x17;
+
+/// Accept proxy connections until the task is dropped. Every CONNECT tunnel
+/// is relayed byte for byte; tunnels to configured Copilot hosts are also
+/// timed. TLS is never terminated, so timings are read from the traffic's
+/// shape: the TLS record headers for the handshake, and the alternation of
+/// client writes and server responses for each request.
+pub async fn serve(config: CopilotProxyConfig, events: EventQueue) -> Result<()> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .map_err(|e| anyhow!("Failed to bind Copilot proxy on {}: {}", config.listen, e))?;
+    info!("Copilot latency proxy listening on {}", config.listen);
+
+    let mut next_tunnel = 0u64;
+    loop {
+        let (client, peer) = listener.accept().await?;
+        next_tunnel += 1;
+        let config = config.clone();
+        let events = events.clone();
+        let tunnel_id = next_tunnel;
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(client, &config, &events, tunnel_id).await {
+                debug!("Proxy connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    mut client: TcpStream,
+    config: &CopilotProxyConfig,
+    events: &EventQueue,
+    tunnel_id: u64,
+) -> Result<()> {
+    let (target, early_data) = match read_connect_head(&mut client).await {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = client
+                .write_all(b"HTTP/1.1 405 Method Not Allowed\r\nAllow: CONNECT\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            return Err(e);
+        }
+    };
+    let host = target.rsplit_once(':').map(|(host, _)| host).unwrap_or(&target).to_string();
+
+    let connect_started = Instant::now();
+    let upstream = match timeout(
+        Duration::from_secs(config.connect_timeout_secs.max(1)),
+        TcpStream::connect(&target),
+    )
+    .await
+    {
+        Ok(Ok(upstream)) => upstream,
+        Ok(Err(e)) => {
+            let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n").await;
+            return Err(anyhow!("Failed to connect to {}: {}", target, e));
+        }
+        Err(_) => {
+            let _ = client.write_all(b"HTTP/1.1 504 Gateway Timeout\r\nContent-Length: 0\r\n\r\n").await;
+            return Err(anyhow!("Timed out connecting to {}", target));
+        }
+    };
+    let connect_ms = connect_started.elapsed().as_secs_f64() * 1000.0;
+    let _ = upstream.set_nodelay(true);
+    client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await?;
+
+    if !is_measured_host(&host, &config.hosts) {
+        return relay(client, upstream, early_data, None).await;
+    }
+
+    debug!("Measuring tunnel #{} to {}", tunnel_id, target);
+    let tap = TunnelTap::new(config, events.clone(), host, tunnel_id, connect_ms);
+    relay(client, upstream, early_data, Some(tap)).await
+}
+
+/// Read the proxy request head and return the `host:port` target plus any
+/// tunnel bytes the client sent along with it.
+async fn read_connect_head(client: &mut TcpStream) -> Result<(String, Vec<u8>)> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    let end = loop {
+        let read = client.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(anyhow!("Client closed before sending a request"));
+        }
+        head.extend_from_slice(&buffer[..read]);
+        if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_HEAD_BYTES {
+            return Err(anyhow!("Proxy request head too large"));
+        }
+    };
+
+    let request_line = head[..end].split(|b| *b == b'\n').next().unwrap_or_default();
+    let request_line = String::from_utf8_lossy(request_line);
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) if method.eq_ignore_ascii_case("CONNECT") => {
+            let target = if target.contains(':') {
+                target.to_string()
+            } else {
+                format!("{}:443", target)
+            };
+            Ok((target, head[end..].to_vec()))
+        }
+        (method, _) => Err(anyhow!("Unsupported proxy method {:?}", method.unwrap_or_default())),
+    }
+}
+
+fn is_measured_host(host: &str, hosts: &[String]) -> bool {
+    let host = host.trim_matches(|c| c == '[' || c == ']').to_ascii_lowercase();
+    hosts.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        host == pattern || host.ends_with(&format!(".{}", pattern))
+    })
+}
+
+/// Copy bytes both ways until either side closes, feeding the tap.
+async fn relay(
+    mut client: TcpStream,
+    mut upstream: TcpStream,
+    early_data: Vec<u8>,
+    mut tap: Option<TunnelTap>,
+) -> Result<()> {
+    if !early_data.is_empty() {
+        if let Some(tap) = &mut tap {
+            tap.client_data(&early_data);
+        }
+        upstream.write_all(&early_data).await?;
+    }
+
+    let (mut client_read, mut client_write) = client.split();
+    let (mut upstream_read, mut upstream_write) = upstream.split();
+    let mut from_client = vec![0u8; TUNNEL_BUFFER_BYTES];
+    let mut from_upstream = vec![0u8; TUNNEL_BUFFER_BYTES];
+
+    let result = loop {
+        let idle_deadline = tap.as_ref().and_then(TunnelTap::idle_deadline);
+        tokio::select! {
+            read = client_read.read(&mut from_client) => match read {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    if let Some(tap) = &mut tap {
+                        tap.client_data(&from_client[..n]);
+                    }
+                    if let Err(e) = upstream_write.write_all(&from_client[..n]).await {
+                        break Err(e.into());
+                    }
+                }
+                Err(e) => break Err(e.into()),
+            },
+            read = upstream_read.read(&mut from_upstream) => match read {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    if let Some(tap) = &mut tap {
+                        tap.server_data(n);
+                    }
+                    if let Err(e) = client_write.write_all(&from_upstream[..n]).await {
+                        break Err(e.into());
+                    }
+                }
+                Err(e) => break Err(e.into()),
+            },
+            _ = sleep_until(idle_deadline.unwrap_or_else(far_future).into()), if idle_deadline.is_some() => {
+                if let Some(tap) = &mut tap {
+                    tap.finish_exchange();
+                }
+            }
+        }
+    };
+
+    if let Some(tap) = &mut tap {
+        tap.finish_exchange();
+    }
+    let _ = client_write.shutdown().await;
+    let _ = upstream_write.shutdown().await;
+    result
+}
+
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86_400)
+}
+
+/// Timing state for one measured tunnel.
+struct TunnelTap {
+    events: EventQueue,
+    host: String,
+    tunnel_id: u64,
+    connect_ms: f64,
+    idle_timeout: Duration,
+    min_request_bytes: usize,
+    opened: Instant,
+    handshake: HandshakeTracker,
+    /// Handshake duration, reported with the first request only
+    pending_handshake_ms: Option<f64>,
+    exchange: Option<Exchange>,
+    exchanges: u64,
+}
+
+/// One request/response round inside the tunnel.
+struct Exchange {
+    request_started: Instant,
+    request_bytes: usize,
+    first_response: Option<Instant>,
+    last_response: Option<Instant>,
+    response_bytes: usize,
+}
+
+impl TunnelTap {
+    fn new(config: &CopilotProxyConfig, events: EventQueue, host: String, tunnel_id: u64, connect_ms: f64) -> Self {
+        Self {
+            events,
+            host,
+            tunnel_id,
+            connect_ms,
+            idle_timeout: Duration::from_millis(config.idle_timeout_ms.max(1)),
+            min_request_bytes: config.min_request_bytes,
+            opened: Instant::now(),
+            handshake: HandshakeTracker::default(),
+            pending_handshake_ms: None,
+            exchange: None,
+            exchanges: 0,
+        }
+    }
+
+    fn client_data(&mut self, data: &[u8]) {
+        let now = Instant::now();
+        if !self.handshake.done {
+            if let Some(completed) = self.handshake.feed(data, now) {
+                self.pending_handshake_ms = Some(completed.as_secs_f64() * 1000.0);
+            }
+            // The first request often shares a write with the client's Finished
+            if !self.handshake.done || data.len() < self.min_request_bytes {
+                return;
+            }
+        }
+
+        match &mut self.exchange {
+            // Still sending the request
+            Some(exchange) if exchange.first_response.is_none() => exchange.request_bytes += data.len(),
+            // Flow control, pings and acks rather than a new request
+            _ if data.len() < self.min_request_bytes => {}
+            _ => {
+                self.finish_exchange();
+                self.exchange = Some(Exchange {
+                    request_started: now,
+                    request_bytes: data.len(),
+                    first_response: None,
+                    last_response: None,
+                    response_bytes: 0,
+                });
+            }
+        }
+    }
+
+    fn server_data(&mut self, len: usize) {
+        let now = Instant::now();
+        if let Some(exchange) = &mut self.exchange {
+            exchange.first_response.get_or_insert(now);
+            exchange.last_response = Some(now);
+            exchange.response_bytes += len;
+        }
+    }
+
+    /// When a response that has gone quiet counts as complete.
+    fn idle_deadline(&self) -> Option<Instant> {
+        let last = self.exchange.as_ref()?.last_response?;
+        Some(last + self.idle_timeout)
+    }
+
+    /// Record the current exchange, if it got a response.
+    fn finish_exchange(&mut self) {
+        let Some(exchange) = self.exchange.take() else {
+            return;
+        };
+        let (Some(first), Some(last)) = (exchange.first_response, exchange.last_response) else {
+            return;
+        };
+        self.exchanges += 1;
+
+        let round_trip = first.duration_since(exchange.request_started);
+        let streaming = last.duration_since(first);
+        let mut metadata = serde_json::json!({
+            "proxy": "copilot",
+            "host": self.host,
+            "tunnel_id": self.tunnel_id,
+            "exchange": self.exchanges,
+            "round_trip_ms": round_trip.as_secs_f64() * 1000.0,
+            "streaming_ms": streaming.as_secs_f64() * 1000.0,
+            "request_bytes": exchange.request_bytes,
+            "response_bytes": exchange.response_bytes,
+            "tunnel_age_ms": exchange.request_started.duration_since(self.opened).as_millis() as u64,
+        });
+        if self.exchanges == 1 {
+            metadata["connect_ms"] = serde_json::json!(self.connect_ms);
+        }
+        if let Some(handshake_ms) = self.pending_handshake_ms.take() {
+            metadata["tls_handshake_ms"] = serde_json::json!(handshake_ms);
+        }
+
+        let event = LatencyEvent::new(
+            ComponentType::GitHubCopilot,
+            EventSource::NetworkRequest,
+            last.duration_since(exchange.request_started),
+            format!(
+                "Copilot request to {} rtt {}ms, streamed {}ms",
+                self.host,
+                round_trip.as_millis(),
+                streaming.as_millis()
+            ),
+        )
+        .with_kind(EventKind::ModelRequest)
+        .with_metadata(metadata);
+
+        if let Err(e) = self.events.send(event) {
+            warn!("Failed to send Copilot proxy event: {}", e);
+        }
+    }
+}
+
+/// Follows TLS record headers in the client-to-server direction. The
+/// handshake is taken as complete at the first application-data record the
+/// client sends: in TLS 1.3 that record carries the client's Finished, in
+/// TLS 1.2 it is the first request after the server's Finished.
+#[derive(Default)]
+struct HandshakeTracker {
+    started: Option<Instant>,
+    header: Vec<u8>,
+    /// Body bytes left in the current record
+    remaining: usize,
+    done: bool,
+}
+
+impl HandshakeTracker {
+    /// Returns the handshake duration once it completes.
+    fn feed(&mut self, mut data: &[u8], now: Instant) -> Option<Duration> {
+        while !data.is_empty() && !self.done {
+            if self.remaining > 0 {
+                let skip = self.remaining.min(data.len());
+                self.remaining -= skip;
+                data = &data[skip..];
+                continue;
+            }
+
+            let take = (5 - self.header.len()).min(data.len());
+            self.header.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.header.len() < 5 {
+                break;
+            }
+
+            let content_type = self.header[0];
+            self.remaining = u16::from_be_bytes([self.header[3], self.header[4]]) as usize;
+            self.header.clear();
+            match content_type {
+                TLS_HANDSHAKE => {
+                    self.started.get_or_insert(now);
+                }
+                TLS_APPLICATION_DATA => {
+                    self.done = true;
+                    return self.started.map(|started| now.duration_since(started));
+                }
+                // Not TLS after all; stop looking for a handshake
+                0x00..=0x13 | 0x19.. => {
+                    self.done = true;
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::models::{AuditEntry, ComponentType, EventKind, EventSource, LatencyEvent};
+use crate::storage::MetricsStorage;
+
+/// How often the config file's modification time is checked.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings the running monitor picks up from a reloaded config; any other
+/// change takes effect after a restart.
+pub const LIVE_KEYS: &[&str] = &[
+    "monitoring.interval_ms",
+    "monitoring.enabled_components",
+    "monitoring.editors",
+    "alerts",
+    "alert_digest",
+];
+
+/// Audit actor for reloads, which no user or token triggers directly.
+const RELOAD_ACTOR: &str = "system:config";
+
+/// Reload the config file when it changes on disk or, on Unix, when the
+/// process gets SIGHUP, and publish it to `updates`. A file that fails to
+/// parse or validate is reported and ignored, so the monitor keeps running
+/// with the last good config. Every applied reload is stored as a `System`
+/// annotation listing the changed keys.
+pub async fn run_config_reload(path: PathBuf, updates: watch::Sender<Config>, storage: MetricsStorage) {
+    let mut modified = modified_time(&path);
+    let mut poll = tokio::time::interval(CONFIG_POLL_INTERVAL);
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            warn!("Can't listen for SIGHUP, reloading on file changes only: {}", e);
+            None
+        }
+    };
+    info!("Watching {} for configuration changes", path.display());
+
+    loop {
+        #[cfg(unix)]
+        let trigger = tokio::select! {
+            _ = poll.tick() => "file",
+            Some(_) = async { hangup.as_mut()?.recv().await } => "sighup",
+        };
+        #[cfg(not(unix))]
+        let trigger = {
+            poll.tick().await;
+            "file"
+        };
+
+        let current = modified_time(&path);
+        if trigger == "file" && current == modified {
+            continue;
+        }
+        modified = current;
+
+        if let Err(e) = reload(&path, &updates, &storage, trigger).await {
+            warn!("Config reload from {} failed, keeping the current config: {}", path.display(), e);
+        }
+    }
+}
+
+async fn reload(path: &Path, updates: &watch::Sender<Config>, storage: &MetricsStorage, trigger: &str) -> Result<()> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let config: Config = toml::from_str(&content)?;
+    config.validate()?;
+
+    let changed = changed_keys(&toml::Value::try_from(&*updates.borrow())?, &toml::Value::try_from(&config)?);
+    if changed.is_empty() {
+        debug!("Config file {} changed but no settings did", path.display());
+        return Ok(());
+    }
+
+    let (live, restart): (Vec<_>, Vec<_>) = changed.iter().cloned().partition(|key| is_live(key));
+    updates.send_replace(config);
+    info!("Reloaded configuration ({}): {}", trigger, changed.join(", "));
+    if !restart.is_empty() {
+        warn!("Changes to {} take effect after a restart", restart.join(", "));
+    }
+
+    let details = json!({ "trigger": trigger, "applied": live, "restart_required": restart });
+    let event = LatencyEvent::builder(ComponentType::System, EventSource::UserInteraction)
+        .kind(EventKind::Annotation)
+        .description(format!("Configuration reloaded: {}", changed.join(", ")))
+        .metadata(json!({ "config_reload": details }))
+        .build();
+    storage.store_event(&event).await?;
+    storage
+        .record_audit(
+            &AuditEntry::new(RELOAD_ACTOR, "config.reload", Some(&path.display().to_string()))
+                .with_change(None, Some(details)),
+        )
+        .await
+}
+
+/// Whether `key` (or the section it is in) is applied without a restart.
+fn is_live(key: &str) -> bool {
+    LIVE_KEYS
+        .iter()
+        .any(|live| key == *live || key.starts_with(&format!("{}.", live)))
+}
+
+/// Dotted paths of the settings that differ, down to the second level
+/// (`monitoring.interval_ms`, `alerts`).
+fn changed_keys(old: &toml::Value, new: &toml::Value) -> Vec<String> {
+    let mut changed = Vec::new();
+    collect_changes(old, new, "", 2, &mut changed);
+    changed
+}
+
+fn collect_changes(old: &toml::Value, new: &toml::Value, prefix: &str, depth: usize, changed: &mut Vec<String>) {
+    if old == new {
+        return;
+    }
+    let (toml::Value::Table(old), toml::Value::Table(new), true) = (old, new, depth > 0) else {
+        changed.push(prefix.to_string());
+        return;
+    };
+
+    let mut keys: Vec<_> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let empty = toml::Value::Table(Default::default());
+    for key in keys {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        collect_changes(old.get(key).unwrap_or(&empty), new.get(key).unwrap_or(&empty), &path, depth - 1, changed);
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
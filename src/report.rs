@@ -0,0 +1,602 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::calibration::{NoiseHandling, NoiseProfile};
+use crate::config::{Config, ScheduleConfig};
+use crate::cron::CronSchedule;
+use crate::email::{self, EmailAttachment, EmailMessage};
+use crate::environment::Environment;
+use crate::secrets::Secrets;
+use crate::filter::EventFilter;
+use crate::html_report;
+use crate::webhook;
+use crate::models::{ComponentType, EventKind};
+use crate::storage::MetricsStorage;
+use crate::suggestions;
+use crate::timerange::TimeRange;
+use crate::units;
+
+/// Report composition: an ordered list of sections, each with its own
+/// parameters. Configured under `[[reports.sections]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportsConfig {
+    #[serde(default = "default_sections")]
+    pub sections: Vec<ReportSection>,
+    /// Leave out samples outside `[working_hours]`, so overnight idle
+    /// polling doesn't drag the averages down
+    #[serde(default)]
+    pub working_hours_only: bool,
+    /// Flag or subtract the idle sampling noise measured by `calibrate`
+    #[serde(default)]
+    pub noise: NoiseHandling,
+    /// Embed the monitor version, platform, compiled features and the
+    /// scrubbed configuration, so reports from different machines can be
+    /// compared and reproduced
+    #[serde(default)]
+    pub include_environment: bool,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            sections: default_sections(),
+            working_hours_only: false,
+            noise: NoiseHandling::Off,
+            include_environment: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ReportSection {
+    /// Event totals, kind breakdown and extension host lifecycle counters
+    Summary {
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// Per-component latency distribution
+    Percentiles {
+        #[serde(default)]
+        title: Option<String>,
+        /// Restrict to these components (all when empty)
+        #[serde(default)]
+        components: Vec<ComponentType>,
+    },
+    /// Slowest events, optionally narrowed by a filter or saved query
+    SlowestEvents {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default = "default_slowest_limit")]
+        limit: u32,
+        #[serde(default)]
+        filter: Option<String>,
+        #[serde(default)]
+        saved_query: Option<String>,
+    },
+    /// Copilot vs local model latency side by side
+    ModelComparison {
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// Share of events under a latency threshold vs a target
+    Slo {
+        #[serde(default)]
+        title: Option<String>,
+        objectives: Vec<SloObjective>,
+        /// Overrides `reports.working_hours_only` for this section
+        #[serde(default)]
+        working_hours_only: Option<bool>,
+    },
+    /// Annotation events recorded in the window
+    Annotations {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default = "default_annotation_limit")]
+        limit: u32,
+    },
+    /// Per-component latency split by a column, e.g. `vscode_build` or
+    /// `vscode_profile`
+    Groups {
+        #[serde(default)]
+        title: Option<String>,
+        by: String,
+    },
+    /// Extensions using the most extension host CPU, from
+    /// `[monitoring.extensions]` attribution
+    WorstExtensions {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default = "default_slowest_limit")]
+        limit: u32,
+    },
+    /// Inline suggestion acceptance rate per latency band, with the time
+    /// spent waiting for suggestions that weren't accepted
+    Acceptance {
+        #[serde(default)]
+        title: Option<String>,
+        /// Only suggestions of this extension, e.g. `github.copilot`
+        #[serde(default)]
+        extension: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloObjective {
+    pub component: ComponentType,
+    pub threshold_ms: u64,
+    /// Target percentage of events at or under the threshold
+    pub target_percent: f64,
+}
+
+fn default_sections() -> Vec<ReportSection> {
+    vec![
+        ReportSection::Summary { title: None },
+        ReportSection::Percentiles {
+            title: None,
+            components: Vec::new(),
+        },
+        ReportSection::SlowestEvents {
+            title: None,
+            limit: default_slowest_limit(),
+            filter: None,
+            saved_query: None,
+        },
+        ReportSection::ModelComparison { title: None },
+        ReportSection::WorstExtensions {
+            title: None,
+            limit: default_slowest_limit(),
+        },
+    ]
+}
+
+fn default_slowest_limit() -> u32 {
+    10
+}
+
+fn default_annotation_limit() -> u32 {
+    50
+}
+
+impl ReportSection {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ReportSection::Summary { .. } => "summary",
+            ReportSection::Percentiles { .. } => "percentiles",
+            ReportSection::SlowestEvents { .. } => "slowest-events",
+            ReportSection::ModelComparison { .. } => "model-comparison",
+            ReportSection::Slo { .. } => "slo",
+            ReportSection::Annotations { .. } => "annotations",
+            ReportSection::Groups { .. } => "groups",
+            ReportSection::WorstExtensions { .. } => "worst-extensions",
+            ReportSection::Acceptance { .. } => "acceptance",
+        }
+    }
+
+    fn title(&self) -> String {
+        let custom = match self {
+            ReportSection::Summary { title }
+            | ReportSection::Percentiles { title, .. }
+            | ReportSection::SlowestEvents { title, .. }
+            | ReportSection::ModelComparison { title }
+            | ReportSection::Slo { title, .. }
+            | ReportSection::Annotations { title, .. }
+            | ReportSection::Groups { title, .. }
+            | ReportSection::WorstExtensions { title, .. }
+            | ReportSection::Acceptance { title, .. } => title.clone(),
+        };
+
+        custom.unwrap_or_else(|| match self {
+            ReportSection::Summary { .. } => "Summary".to_string(),
+            ReportSection::Percentiles { .. } => "Latency Percentiles".to_string(),
+            ReportSection::SlowestEvents { .. } => "Slowest Events".to_string(),
+            ReportSection::ModelComparison { .. } => "Model Comparison".to_string(),
+            ReportSection::Slo { .. } => "Service Level Objectives".to_string(),
+            ReportSection::Annotations { .. } => "Annotations".to_string(),
+            ReportSection::Groups { by, .. } => format!("Latency by {}", by),
+            ReportSection::WorstExtensions { .. } => "Worst Extensions".to_string(),
+            ReportSection::Acceptance { .. } => "Copilot Acceptance".to_string(),
+        })
+    }
+}
+
+/// Samples plotted on the HTML report timeline.
+const HTML_TIMELINE_SAMPLES: u32 = 5000;
+
+/// Render a report in the requested format. Structured formats are composed
+/// from the configured sections; CSV remains a flat event listing.
+pub async fn generate_report(
+    storage: &MetricsStorage,
+    config: &Config,
+    range: &TimeRange,
+    format: &str,
+) -> Result<String> {
+    let range = &report_range(config, range)?;
+    match format {
+        "json" => {
+            let report = build_report(storage, config, range).await?;
+            Ok(serde_json::to_string_pretty(&report)?)
+        }
+        "html" => {
+            let report = build_report(storage, config, range).await?;
+            let samples = storage.get_latency_samples(HTML_TIMELINE_SAMPLES, range).await?;
+            Ok(html_report::render(&report, &samples))
+        }
+        _ => storage.generate_report(range, format).await,
+    }
+}
+
+/// `range`, limited to working hours if `reports.working_hours_only` is set.
+pub fn report_range(config: &Config, range: &TimeRange) -> Result<TimeRange> {
+    if config.reports.working_hours_only {
+        Ok(range.within_working_hours(config.working_hours.resolve()?))
+    } else {
+        Ok(range.clone())
+    }
+}
+
+pub async fn build_report(
+    storage: &MetricsStorage,
+    config: &Config,
+    range: &TimeRange,
+) -> Result<serde_json::Value> {
+    let noise = match config.reports.noise {
+        NoiseHandling::Off => None,
+        _ => {
+            let profile = storage.latest_noise_profile().await?;
+            if profile.is_none() {
+                warn!("reports.noise is set but no noise profile exists; run `calibrate` first");
+            }
+            profile
+        }
+    };
+
+    let mut sections = Vec::new();
+    for section in &config.reports.sections {
+        let data = render_section(storage, config, section, range, noise.as_ref()).await?;
+        sections.push(json!({
+            "type": section.type_name(),
+            "title": section.title(),
+            "data": data,
+        }));
+    }
+
+    let environment = config.reports.include_environment.then(|| Environment::collect(config)).transpose()?;
+
+    Ok(json!({
+        "generated_at": Utc::now(),
+        "since": range.since,
+        "until": range.until,
+        "range": range.to_string(),
+        "working_hours_only": range.working_hours.is_some(),
+        "noise": noise.as_ref().map(|profile| json!({
+            "handling": config.reports.noise,
+            "profile": profile,
+        })),
+        "environment": environment,
+        "sections": sections,
+    }))
+}
+
+async fn render_section(
+    storage: &MetricsStorage,
+    config: &Config,
+    section: &ReportSection,
+    range: &TimeRange,
+    noise: Option<&NoiseProfile>,
+) -> Result<serde_json::Value> {
+    let window = range.to_sql();
+
+    match section {
+        ReportSection::Summary { .. } => {
+            let status = storage.get_system_status().await?;
+            let kinds = storage.count_events_by_kind(range).await?;
+
+            Ok(json!({
+                "total_events": status.total_events,
+                "events_by_kind": kinds,
+                "last_event": status.last_event_timestamp,
+                "lifecycle": status.lifecycle,
+            }))
+        }
+        ReportSection::Percentiles { components, .. } => {
+            let metrics = storage
+                .get_performance_metrics_in(range)
+                .await?
+                .into_iter()
+                .filter(|m| components.is_empty() || components.contains(&m.component))
+                .map(|m| {
+                    let mut row = json!({
+                        "component": m.component,
+                        "events": m.total_events,
+                        "avg_ms": m.avg_duration_ms,
+                        "p50_ms": m.p50_duration_ms,
+                        "p95_ms": m.p95_duration_ms,
+                        "p99_ms": m.p99_duration_ms,
+                        "max_ms": m.max_duration_ms,
+                    });
+                    if let Some(profile) = noise.filter(|profile| profile.applies_to(m.component)) {
+                        row["within_noise"] = json!(profile.contains(m.p50_duration_ms as f64));
+                        if config.reports.noise == NoiseHandling::Subtract {
+                            // Max stays as measured: it is the worst case seen
+                            row["avg_ms"] = json!(profile.subtract(m.avg_duration_ms));
+                            row["p50_ms"] = json!(profile.subtract(m.p50_duration_ms as f64));
+                            row["p95_ms"] = json!(profile.subtract(m.p95_duration_ms as f64));
+                            row["p99_ms"] = json!(profile.subtract(m.p99_duration_ms as f64));
+                        }
+                    }
+                    row
+                })
+                .collect::<Vec<_>>();
+
+            Ok(json!(metrics))
+        }
+        ReportSection::SlowestEvents { limit, filter, saved_query, .. } => {
+            let expression = match saved_query {
+                Some(name) => Some(
+                    storage
+                        .get_saved_query(name)
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("Report section references unknown saved query '{}'", name))?
+                        .filter,
+                ),
+                None => filter.clone(),
+            };
+
+            let sql_filter = expression
+                .as_deref()
+                .map(|f| EventFilter::parse(f).and_then(|f| f.to_sql()))
+                .transpose()?;
+
+            let sql_filter = match sql_filter {
+                Some(filter) => window.and(&filter),
+                None => window,
+            };
+
+            let events = storage.get_slowest_events(Some(&sql_filter), *limit).await?;
+            Ok(json!(events))
+        }
+        ReportSection::ModelComparison { .. } => {
+            let models = storage
+                .get_performance_metrics_in(range)
+                .await?
+                .into_iter()
+                .filter(|m| {
+                    matches!(
+                        m.component,
+                        ComponentType::GitHubCopilot | ComponentType::LocalModel | ComponentType::CloudModel
+                    )
+                })
+                .map(|m| {
+                    json!({
+                        "component": m.component,
+                        "events": m.total_events,
+                        "avg_ms": m.avg_duration_ms,
+                        "p95_ms": m.p95_duration_ms,
+                        "max_ms": m.max_duration_ms,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Ok(json!(models))
+        }
+        ReportSection::Slo { objectives, working_hours_only, .. } => {
+            let range = match working_hours_only {
+                Some(true) => range.within_working_hours(config.working_hours.resolve()?),
+                Some(false) => TimeRange {
+                    working_hours: None,
+                    ..range.clone()
+                },
+                None => range.clone(),
+            };
+            let mut results = Vec::new();
+            for objective in objectives {
+                let (total, within) = storage
+                    .count_within_threshold(objective.component, objective.threshold_ms * 1000, &range)
+                    .await?;
+                let achieved = if total > 0 {
+                    within as f64 / total as f64 * 100.0
+                } else {
+                    100.0
+                };
+
+                results.push(json!({
+                    "component": objective.component,
+                    "threshold_ms": objective.threshold_ms,
+                    "target_percent": objective.target_percent,
+                    "achieved_percent": achieved,
+                    "events": total,
+                    "met": achieved >= objective.target_percent,
+                }));
+            }
+
+            Ok(json!(results))
+        }
+        ReportSection::Annotations { limit, .. } => {
+            let filter = EventFilter::parse(&format!("kind = {}", EventKind::Annotation.as_str()))?.to_sql()?;
+            let events = storage.query_events(Some(&filter.and(&window)), *limit).await?;
+            Ok(json!(events))
+        }
+        ReportSection::Groups { by, .. } => {
+            let groups = storage
+                .get_grouped_metrics(range, by)
+                .await?
+                .into_iter()
+                .map(|m| {
+                    json!({
+                        by.as_str(): m.group.unwrap_or_else(|| "-".to_string()),
+                        "component": m.component,
+                        "events": m.total_events,
+                        "avg_ms": m.avg_duration_ms,
+                        "p50_ms": m.p50_duration_ms,
+                        "p95_ms": m.p95_duration_ms,
+                        "max_ms": m.max_duration_ms,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Ok(json!(groups))
+        }
+        ReportSection::WorstExtensions { limit, .. } => {
+            let extensions = storage.get_extension_cpu(range, *limit).await?;
+            Ok(json!(extensions))
+        }
+        ReportSection::Acceptance { extension, .. } => {
+            let report = suggestions::acceptance_report(storage, range.since, range.until, extension.as_deref()).await?;
+            let mut rows = report
+                .by_latency
+                .iter()
+                .filter(|band| band.stats.shown + band.stats.accepted > 0)
+                .map(|band| json!(band))
+                .collect::<Vec<_>>();
+            if !rows.is_empty() {
+                rows.push(json!(suggestions::BandAcceptance {
+                    band: "all".to_string(),
+                    stats: report.total,
+                }));
+            }
+            Ok(json!(rows))
+        }
+    }
+}
+
+/// Deliver a schedule's report: emailed to its recipients with the section
+/// summary as the HTML body and raw events attached as CSV, written to its
+/// `output_dir`, and posted to its webhooks.
+pub async fn deliver_report(
+    storage: &MetricsStorage,
+    config: &Config,
+    schedule: &ScheduleConfig,
+) -> Result<()> {
+    let range = report_range(config, &TimeRange::parse(Some(&schedule.since), None)?)?;
+    let report = build_report(storage, config, &range).await?;
+    let secrets = Secrets::load()?;
+
+    if !schedule.recipients.is_empty() {
+        let csv = storage.generate_report(&range, "csv").await?;
+        let message = EmailMessage {
+            subject: format!(
+                "VS Code latency report '{}' ({})",
+                schedule.name,
+                Utc::now().format("%Y-%m-%d")
+            ),
+            html_body: render_email_html(&report),
+            attachments: vec![EmailAttachment {
+                filename: format!("latency-events-{}.csv", Utc::now().format("%Y%m%d")),
+                content_type: "text/csv".to_string(),
+                content: csv.into_bytes(),
+            }],
+        };
+        email::send_email(&config.email, &secrets, &schedule.recipients, message).await?;
+    }
+
+    if let Some(dir) = &schedule.output_dir {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}-{}.{}",
+            schedule.name,
+            Local::now().format("%Y%m%d-%H%M"),
+            schedule.format
+        ));
+        std::fs::write(&path, generate_report(storage, config, &range, &schedule.format).await?)?;
+        info!("Wrote report for schedule '{}' to {}", schedule.name, path.display());
+    }
+
+    if schedule.webhooks.is_empty() {
+        return webhook::broadcast(storage, &config.webhooks, "report", &report).await;
+    }
+    for name in &schedule.webhooks {
+        let Some(target) = config.webhooks.iter().find(|w| &w.name == name) else {
+            warn!("Schedule '{}' names unknown webhook '{}'", schedule.name, name);
+            continue;
+        };
+        if let Err(e) = webhook::deliver(storage, &secrets, target, "report", &report).await {
+            warn!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Longest sleep between checks of the wall clock. Sleeps run on a
+/// monotonic clock that stops while the machine is suspended, so a long one
+/// would deliver late after a resume.
+const SCHEDULE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Deliver every schedule with a `cron` expression when it is due, for as
+/// long as the monitor runs. Runs missed while it was stopped are skipped;
+/// one that fell due while the machine was suspended is delivered on resume.
+pub async fn run_schedules(mut settings: watch::Receiver<Config>, storage: MetricsStorage) {
+    let mut config = settings.borrow_and_update().clone();
+    let mut following = true;
+    loop {
+        let now = Local::now();
+        let next = config.schedules.iter().filter_map(|schedule| next_due(schedule, now)).min();
+        let wait = next
+            .map(|due| (due - now).to_std().unwrap_or_default())
+            .unwrap_or(SCHEDULE_CHECK_INTERVAL)
+            .min(SCHEDULE_CHECK_INTERVAL);
+
+        tokio::select! {
+            changed = settings.changed(), if following => {
+                if changed.is_err() {
+                    following = false;
+                } else {
+                    config = settings.borrow_and_update().clone();
+                }
+            }
+            _ = tokio::time::sleep(wait) => {
+                let Some(due) = next.filter(|due| *due <= Local::now()) else { continue };
+                // Every schedule due this minute, not only the first found
+                for schedule in &config.schedules {
+                    if next_due(schedule, now) != Some(due) {
+                        continue;
+                    }
+                    info!("Delivering scheduled report '{}'", schedule.name);
+                    if let Err(e) = deliver_report(&storage, &config, schedule).await {
+                        warn!("Scheduled report '{}' failed: {}", schedule.name, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// When a schedule is next due after `after`; `None` without a valid `cron`.
+fn next_due(schedule: &ScheduleConfig, after: DateTime<Local>) -> Option<DateTime<Local>> {
+    CronSchedule::parse(schedule.cron.as_deref()?).ok()?.next_after(after)
+}
+
+fn render_email_html(report: &serde_json::Value) -> String {
+    let mut html = String::from(
+        "<html><body style=\"font-family: sans-serif\"><h1>VS Code Latency Report</h1>",
+    );
+    html.push_str(&format!(
+        "<p>Generated {} covering {}</p>",
+        report["generated_at"].as_str().unwrap_or_default(),
+        html_escape(report["range"].as_str().unwrap_or_default())
+    ));
+
+    for section in report["sections"].as_array().into_iter().flatten() {
+        let mut data = section["data"].clone();
+        units::annotate(&mut data);
+        html.push_str(&format!(
+            "<h2>{}</h2><pre>{}</pre>",
+            html_escape(section["title"].as_str().unwrap_or_default()),
+            html_escape(&serde_json::to_string_pretty(&data).unwrap_or_default())
+        ));
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
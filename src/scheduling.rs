@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// Keeps the monitor from competing with the editor it measures. All
+/// settings are optional and best-effort; failures are logged, not fatal.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulingConfig {
+    /// Nice level, -20 (highest priority) to 19 (lowest); raising priority
+    /// needs CAP_SYS_NICE or root
+    pub nice: Option<i32>,
+    /// Pin the monitor to this single CPU core (Linux only)
+    pub cpu_affinity: Option<usize>,
+    /// Cap CPU time at this percentage of one core via a cgroup v2 `cpu.max`
+    /// (Linux only; the cgroup must be writable, e.g. under a delegated
+    /// systemd user slice)
+    pub cgroup_cpu_quota_percent: Option<u32>,
+    /// cgroup to create/join, relative to `/sys/fs/cgroup`
+    pub cgroup_path: Option<PathBuf>,
+}
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const DEFAULT_CGROUP: &str = "vscode-latency-monitor";
+/// `cpu.max` period in microseconds
+const CGROUP_PERIOD_US: u64 = 100_000;
+
+/// Apply the configured controls to every thread of this process. Threads
+/// created afterwards inherit priority and affinity from their creator.
+pub fn apply(config: &SchedulingConfig) {
+    if let Some(nice) = config.nice {
+        match set_nice(nice) {
+            Ok(threads) => info!("Set nice level {} on {} thread(s)", nice, threads),
+            Err(e) => warn!("Failed to set nice level {}: {}", nice, e),
+        }
+    }
+
+    if let Some(cpu) = config.cpu_affinity {
+        match set_affinity(cpu) {
+            Ok(threads) => info!("Pinned {} thread(s) to CPU {}", threads, cpu),
+            Err(e) => warn!("Failed to pin monitor to CPU {}: {}", cpu, e),
+        }
+    }
+
+    if let Some(percent) = config.cgroup_cpu_quota_percent {
+        let path = PathBuf::from(CGROUP_ROOT).join(config.cgroup_path.as_deref().unwrap_or(DEFAULT_CGROUP.as_ref()));
+        match join_cgroup(&path, percent) {
+            Ok(()) => info!("Limited monitor to {}% CPU via cgroup {}", percent, path.display()),
+            Err(e) => warn!("Failed to apply cgroup CPU quota: {}", e),
+        }
+    }
+}
+
+/// Thread ids of this process; on Linux priority and affinity are per thread.
+#[cfg(target_os = "linux")]
+fn thread_ids() -> Result<Vec<libc::pid_t>> {
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        if let Ok(id) = entry?.file_name().to_string_lossy().parse() {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) -> Result<usize> {
+    let threads = thread_ids()?;
+    for tid in &threads {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, *tid as libc::id_t, nice) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(threads.len())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn set_nice(nice: i32) -> Result<usize> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(1)
+}
+
+#[cfg(not(unix))]
+fn set_nice(_nice: i32) -> Result<usize> {
+    Err(anyhow!("nice levels aren't supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu: usize) -> Result<usize> {
+    let available = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    if cpu >= available {
+        return Err(anyhow!("CPU {} doesn't exist ({} available)", cpu, available));
+    }
+
+    let threads = thread_ids()?;
+    for tid in &threads {
+        let result = unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(cpu, &mut set);
+            libc::sched_setaffinity(*tid, std::mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(threads.len())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpu: usize) -> Result<usize> {
+    Err(anyhow!("CPU affinity is only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn join_cgroup(path: &std::path::Path, percent: u32) -> Result<()> {
+    if percent == 0 {
+        return Err(anyhow!("cgroup_cpu_quota_percent must be greater than 0"));
+    }
+
+    std::fs::create_dir_all(path)
+        .map_err(|e| anyhow!("Can't create cgroup {}: {}", path.display(), e))?;
+    let quota = CGROUP_PERIOD_US * percent as u64 / 100;
+    std::fs::write(path.join("cpu.max"), format!("{} {}", quota, CGROUP_PERIOD_US))
+        .map_err(|e| anyhow!("Can't write {}/cpu.max: {}", path.display(), e))?;
+    std::fs::write(path.join("cgroup.procs"), std::process::id().to_string())
+        .map_err(|e| anyhow!("Can't move monitor into {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn join_cgroup(_path: &std::path::Path, _percent: u32) -> Result<()> {
+    Err(anyhow!("cgroup CPU quotas are only supported on Linux"))
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Prefix for environment variables that override file-based secrets, e.g.
+/// `VSCODE_LATENCY_MONITOR_SMTP_PASSWORD` for the `smtp_password` secret.
+const ENV_PREFIX: &str = "VSCODE_LATENCY_MONITOR_";
+
+/// Credentials kept out of `config.toml`. Loaded from a flat
+/// `secrets.toml` next to the config file, with environment overrides.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    values: HashMap<String, String>,
+}
+
+impl Secrets {
+    pub fn default_path() -> PathBuf {
+        let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        home_dir.join(".config/vscode-latency-monitor/secrets.toml")
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let mut values = HashMap::new();
+
+        if path.exists() {
+            warn_if_world_readable(path);
+
+            let content = fs::read_to_string(path)?;
+            let table: toml::Table = toml::from_str(&content)?;
+            for (key, value) in table {
+                if let Some(text) = value.as_str() {
+                    values.insert(key, text.to_string());
+                }
+            }
+        }
+
+        Ok(Self { values })
+    }
+
+    /// Look up a secret, preferring the environment over the secrets file.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let env_key = format!("{}{}", ENV_PREFIX, name.to_uppercase());
+        std::env::var(env_key)
+            .ok()
+            .or_else(|| self.values.get(name).cloned())
+    }
+}
+
+#[cfg(unix)]
+fn warn_if_world_readable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            warn!("Secrets file {} is readable by other users; consider chmod 600", path.display());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_world_readable(_path: &Path) {}
@@ -0,0 +1,370 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+use crate::daemon;
+use crate::monitor;
+
+/// Name of the systemd user unit.
+pub const SYSTEMD_UNIT: &str = "vscode-latency-monitor.service";
+/// Label of the launchd agent.
+pub const LAUNCHD_LABEL: &str = "com.vscode-latency-monitor.monitor";
+
+/// The service manager that keeps the monitor running across logins and
+/// reboots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceManager {
+    /// A systemd user unit, on Linux
+    Systemd,
+    /// A launchd agent, on macOS
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The manager of this platform.
+    pub fn detect() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(ServiceManager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(ServiceManager::Systemd)
+        } else {
+            Err(anyhow!("Services are only supported with systemd (Linux) and launchd (macOS)"))
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServiceManager::Systemd => "systemd",
+            ServiceManager::Launchd => "launchd",
+        }
+    }
+
+    /// Where the unit file or property list is installed.
+    pub fn unit_path(&self) -> Result<PathBuf> {
+        match self {
+            ServiceManager::Systemd => {
+                let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("No config directory for systemd units"))?;
+                Ok(config_dir.join("systemd").join("user").join(SYSTEMD_UNIT))
+            }
+            ServiceManager::Launchd => {
+                let home = dirs::home_dir().ok_or_else(|| anyhow!("No home directory for launchd agents"))?;
+                Ok(home.join("Library").join("LaunchAgents").join(format!("{}.plist", LAUNCHD_LABEL)))
+            }
+        }
+    }
+}
+
+/// How `install-service` sets the service up.
+pub struct InstallOptions {
+    /// Component to monitor, as for `start --component`
+    pub component: String,
+    /// Replace a unit file that's already installed
+    pub force: bool,
+    /// Start the service now, not only at the next login
+    pub start: bool,
+}
+
+/// The service as `status` reports it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceState {
+    pub manager: ServiceManager,
+    pub unit_path: PathBuf,
+    pub installed: bool,
+    /// Starts by itself at login
+    pub enabled: bool,
+    /// Running under the service manager right now
+    pub active: bool,
+}
+
+/// Command line the service runs: `start --daemon` with this executable and
+/// `config_path`. The daemon environment variable keeps it in the
+/// foreground, where the service manager can supervise it, while it still
+/// writes the PID file `stop` and `status` use.
+fn program_arguments(config_path: &Path, component: &str) -> Result<Vec<String>> {
+    if !monitor::COMPONENTS.contains(&component) {
+        return Err(anyhow!("Unknown component '{}' ({})", component, monitor::COMPONENTS.join(", ")));
+    }
+    let exe = std::env::current_exe()?;
+    // Config::load has created the file if it was missing
+    let config_path = absolute(config_path)?;
+    Ok(vec![
+        exe.display().to_string(),
+        "--config".to_string(),
+        config_path.display().to_string(),
+        "start".to_string(),
+        "--component".to_string(),
+        component.to_string(),
+        "--daemon".to_string(),
+    ])
+}
+
+/// The unit file or property list `install-service` writes.
+pub fn render(manager: ServiceManager, config: &Config, config_path: &Path, component: &str) -> Result<String> {
+    let arguments = program_arguments(config_path, component)?;
+    match manager {
+        ServiceManager::Systemd => Ok(format!(
+            "[Unit]\n\
+             Description=VS Code latency monitor\n\
+             After=graphical-session.target network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart={}\n\
+             Environment={}=1\n\
+             Restart=on-failure\n\
+             RestartSec=10\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            arguments.iter().map(|argument| systemd_quote(argument)).collect::<Vec<_>>().join(" "),
+            daemon::DAEMONIZED_ENV
+        )),
+        ServiceManager::Launchd => {
+            let log = xml_escape(&daemon::log_file_path(config).display().to_string());
+            let arguments: String = arguments
+                .iter()
+                .map(|argument| format!("        <string>{}</string>\n", xml_escape(argument)))
+                .collect();
+            Ok(format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>{env}</key>
+        <string>1</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>10</integer>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+                label = LAUNCHD_LABEL,
+                arguments = arguments,
+                env = daemon::DAEMONIZED_ENV,
+                log = log,
+            ))
+        }
+    }
+}
+
+/// Write the unit for this platform, then enable it, and start it unless
+/// told not to. Returns where the unit was written.
+pub fn install(config: &Config, config_path: &Path, options: &InstallOptions) -> Result<PathBuf> {
+    let manager = ServiceManager::detect()?;
+    let path = manager.unit_path()?;
+    if path.exists() && !options.force {
+        return Err(anyhow!("{} is already installed (--force replaces it)", path.display()));
+    }
+    if options.start {
+        if let Some(pid) = daemon::running_pid(config) {
+            return Err(anyhow!(
+                "A monitor daemon is already running with PID {}; stop it first or pass --no-start",
+                pid
+            ));
+        }
+    }
+
+    let unit = render(manager, config, config_path, &options.component)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::create_dir_all(daemon::runtime_dir(config))?;
+
+    match manager {
+        ServiceManager::Systemd => {
+            fs::write(&path, unit)?;
+            systemctl(&["daemon-reload"])?;
+            if options.start {
+                systemctl(&["enable", "--now", SYSTEMD_UNIT])?;
+            } else {
+                systemctl(&["enable", SYSTEMD_UNIT])?;
+            }
+        }
+        ServiceManager::Launchd => {
+            if path.exists() {
+                // Loading over a loaded agent fails; the old one goes first
+                let _ = launchctl(&["unload", &path.display().to_string()]);
+            }
+            fs::write(&path, unit)?;
+            if options.start {
+                launchctl(&["load", "-w", &path.display().to_string()])?;
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Stop and disable the service and remove its unit. Returns the removed
+/// unit's path, or `None` if none was installed.
+pub fn uninstall() -> Result<Option<PathBuf>> {
+    let manager = ServiceManager::detect()?;
+    let path = manager.unit_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    match manager {
+        ServiceManager::Systemd => {
+            systemctl(&["disable", "--now", SYSTEMD_UNIT])?;
+            fs::remove_file(&path)?;
+            systemctl(&["daemon-reload"])?;
+        }
+        ServiceManager::Launchd => {
+            // Fails when the agent isn't loaded, which is fine
+            let _ = launchctl(&["unload", "-w", &path.display().to_string()]);
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(Some(path))
+}
+
+/// The installed service's state, or `None` on platforms without one.
+pub fn state() -> Option<ServiceState> {
+    let manager = ServiceManager::detect().ok()?;
+    let unit_path = manager.unit_path().ok()?;
+    let installed = unit_path.exists();
+    let (enabled, active) = match (manager, installed) {
+        (_, false) => (false, false),
+        (ServiceManager::Systemd, true) => (
+            query("systemctl", &["--user", "is-enabled", "--quiet", SYSTEMD_UNIT]),
+            query("systemctl", &["--user", "is-active", "--quiet", SYSTEMD_UNIT]),
+        ),
+        // RunAtLoad: a loaded agent also starts at login
+        (ServiceManager::Launchd, true) => {
+            let loaded = query("launchctl", &["list", LAUNCHD_LABEL]);
+            (loaded, loaded)
+        }
+    };
+    Some(ServiceState { manager, unit_path, installed, enabled, active })
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let mut full = vec!["--user"];
+    full.extend_from_slice(args);
+    run("systemctl", &full)
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    run("launchctl", args)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a query command exits successfully.
+fn query(program: &str, args: &[&str]) -> bool {
+    Command::new(program).args(args).output().is_ok_and(|output| output.status.success())
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+/// An `ExecStart=` word: quoted when it has spaces or quotes, with `%`
+/// and `$` doubled so systemd doesn't expand them as a specifier or an
+/// environment variable.
+fn systemd_quote(word: &str) -> String {
+    let word = word.replace('%', "%%").replace('$', "$$");
+    if word.chars().any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        word
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_start_words_are_quoted_and_escaped() {
+        assert_eq!(systemd_quote("/usr/bin/monitor"), "/usr/bin/monitor");
+        assert_eq!(systemd_quote("/home/me/My Config.toml"), "\"/home/me/My Config.toml\"");
+        assert_eq!(systemd_quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(systemd_quote(r"C:\tmp"), r#""C:\\tmp""#);
+        assert_eq!(systemd_quote("100%"), "100%%");
+        assert_eq!(systemd_quote("$HOME/it's"), "\"$$HOME/it's\"");
+    }
+
+    #[test]
+    fn xml_special_characters_are_escaped() {
+        assert_eq!(xml_escape(r#"<a href="x">&</a>"#), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn systemd_unit_runs_the_daemon_in_the_foreground() {
+        let config_path = Path::new("/home/me/my config/monitor.toml");
+        let unit = render(ServiceManager::Systemd, &Config::default(), config_path, "lsp").unwrap();
+        let exec_start = unit.lines().find_map(|line| line.strip_prefix("ExecStart=")).unwrap();
+        assert!(exec_start.ends_with(r#" --config "/home/me/my config/monitor.toml" start --component lsp --daemon"#));
+        assert!(unit.contains(&format!("\nEnvironment={}=1\n", daemon::DAEMONIZED_ENV)));
+        assert!(unit.contains("\nRestart=on-failure\n"));
+        assert!(unit.ends_with("WantedBy=default.target\n"));
+    }
+
+    #[test]
+    fn launchd_agent_lists_each_argument() {
+        let config_path = Path::new("/Users/me/R&D/monitor.toml");
+        let plist = render(ServiceManager::Launchd, &Config::default(), config_path, "all").unwrap();
+        assert!(plist.contains(&format!("<string>{}</string>", LAUNCHD_LABEL)));
+        assert!(plist.contains("        <string>/Users/me/R&amp;D/monitor.toml</string>\n"));
+        assert!(plist.contains("        <string>--daemon</string>\n    </array>"));
+        assert!(plist.contains(&format!("<key>{}</key>", daemon::DAEMONIZED_ENV)));
+    }
+
+    #[test]
+    fn unknown_components_are_rejected() {
+        let error = render(ServiceManager::Systemd, &Config::default(), Path::new("/c.toml"), "gpu").unwrap_err();
+        assert!(error.to_string().starts_with("Unknown component 'gpu'"));
+    }
+
+    #[test]
+    fn relative_config_paths_are_made_absolute() {
+        let unit = render(ServiceManager::Systemd, &Config::default(), Path::new("monitor.toml"), "all").unwrap();
+        let expected = std::env::current_dir().unwrap().join("monitor.toml");
+        assert!(unit.contains(&systemd_quote(&expected.display().to_string())));
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+
+/// Longest command line sent to the monitor; the rest is cut off.
+const MAX_COMMAND_CHARS: usize = 4096;
+
+const BASH_HOOK: &str = r#"# vscode-latency-monitor: time every command (bash 5+)
+__vlm_at_prompt=1
+__vlm_histnum() {
+    local line
+    line=$(HISTTIMEFORMAT= builtin history 1 2>/dev/null) || return
+    line=${line#"${line%%[! ]*}"}
+    printf '%s' "${line%%[!0-9]*}"
+}
+__vlm_last_hist=$(__vlm_histnum)
+__vlm_preexec() {
+    [ "$__vlm_at_prompt" = 1 ] || return
+    [ -n "$COMP_LINE" ] && return
+    __vlm_at_prompt=0
+    __vlm_start=$EPOCHREALTIME
+    __vlm_cmd=$BASH_COMMAND
+}
+__vlm_precmd() {
+    local exit=$__vlm_status end=$EPOCHREALTIME hist
+    hist=$(__vlm_histnum)
+    # An empty line or Ctrl-C adds no history entry; don't resend the last one
+    if [ -n "$__vlm_start" ] && [ "$hist" != "$__vlm_last_hist" ]; then
+        __vlm_last_hist=$hist
+        # The full line from history; BASH_COMMAND is only its first command
+        local line
+        line=$(HISTTIMEFORMAT= builtin history 1 2>/dev/null) && __vlm_cmd=$(printf '%s' "$line" | sed 's/^ *[0-9]* *//')
+        (__VLM_BIN__ shell-event --socket __VLM_SOCKET__ --shell bash --command "$__vlm_cmd" \
+            --cwd "$PWD" --exit "$exit" --start "$__vlm_start" --end "$end" >/dev/null 2>&1 &)
+    fi
+    unset __vlm_start __vlm_cmd
+    __vlm_at_prompt=1
+}
+# Run after any DEBUG trap already set, such as VS Code's shell integration
+# (a command substitution only sees it with functrace on)
+__vlm_trap=$(set -T; trap -p DEBUG)
+if [[ $__vlm_trap != *__vlm_preexec* ]]; then
+    __vlm_trap=${__vlm_trap#trap -- }
+    eval "__vlm_prev_debug=${__vlm_trap% DEBUG}"
+    trap 'eval "$__vlm_prev_debug"; __vlm_preexec' DEBUG
+    PROMPT_COMMAND="__vlm_status=\$?;${PROMPT_COMMAND:+$PROMPT_COMMAND;}__vlm_precmd"
+fi
+unset __vlm_trap
+"#;
+
+const ZSH_HOOK: &str = r#"# vscode-latency-monitor: time every command
+zmodload zsh/datetime
+__vlm_preexec() {
+    __vlm_cmd=$1
+    __vlm_start=$EPOCHREALTIME
+}
+__vlm_precmd() {
+    local exit=$? end=$EPOCHREALTIME
+    [[ -n $__vlm_start ]] || return
+    __VLM_BIN__ shell-event --socket __VLM_SOCKET__ --shell zsh --command "$__vlm_cmd" \
+        --cwd "$PWD" --exit "$exit" --start "$__vlm_start" --end "$end" &>/dev/null &!
+    unset __vlm_start __vlm_cmd
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __vlm_preexec
+add-zsh-hook precmd __vlm_precmd
+"#;
+
+const FISH_HOOK: &str = r#"# vscode-latency-monitor: time every command
+function __vlm_postexec --on-event fish_postexec
+    set -l exit $status
+    set -l duration $CMD_DURATION
+    test -n "$argv[1]"; or return
+    __VLM_BIN__ shell-event --socket __VLM_SOCKET__ --shell fish --command "$argv[1]" \
+        --cwd "$PWD" --exit $exit --duration-ms $duration >/dev/null 2>&1 &
+    disown 2>/dev/null
+end
+"#;
+
+/// Hook snippet for `shell`, to be `eval`ed from its rc file. `binary` and
+/// `socket` are baked in so the hooks don't have to search `PATH` or load
+/// the config on every prompt.
+pub fn init_script(shell: &str, binary: &Path, socket: &Path) -> Result<String> {
+    let template = match shell {
+        "bash" => BASH_HOOK,
+        "zsh" => ZSH_HOOK,
+        "fish" => FISH_HOOK,
+        other => return Err(anyhow!("Unsupported shell '{}' (expected bash, zsh or fish)", other)),
+    };
+
+    Ok(template
+        .replace("__VLM_BIN__", &quote(&binary.display().to_string()))
+        .replace("__VLM_SOCKET__", &quote(&socket.display().to_string())))
+}
+
+/// Single-quote `value` for POSIX shells and fish.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// One finished shell command as reported by the hooks.
+pub struct ShellCommand<'a> {
+    pub shell: &'a str,
+    pub command: &'a str,
+    pub cwd: &'a str,
+    pub exit_code: i32,
+    /// `$EPOCHREALTIME`-style seconds since the epoch
+    pub start: Option<&'a str>,
+    pub end: Option<&'a str>,
+    /// For shells that only report a duration (fish)
+    pub duration_ms: Option<f64>,
+}
+
+/// Send a command to the monitor's IPC socket. Fire-and-forget: nothing is
+/// printed, and a monitor that isn't running is not an error worth
+/// interrupting the prompt for, so the caller ignores failures.
+pub fn send_command(socket: &Path, command: &ShellCommand) -> Result<()> {
+    let end_ms = match command.end {
+        Some(end) => parse_epoch_seconds(end)? * 1000.0,
+        None => Utc::now().timestamp_micros() as f64 / 1000.0,
+    };
+    let start_ms = match (command.start, command.duration_ms) {
+        (Some(start), _) => parse_epoch_seconds(start)? * 1000.0,
+        (None, Some(duration)) => end_ms - duration,
+        (None, None) => return Err(anyhow!("shell-event needs --start or --duration-ms")),
+    };
+
+    let text: String = command.command.trim().chars().take(MAX_COMMAND_CHARS).collect();
+    let message = json!({
+        "type": "shell_command",
+        "shell": command.shell,
+        "command": text,
+        "cwd": command.cwd,
+        "exit_code": command.exit_code,
+        "start_ms": start_ms,
+        "end_ms": end_ms,
+    });
+
+    let mut line = serde_json::to_vec(&message)?;
+    line.push(b'\n');
+    connect(socket)?.write_all(&line)?;
+    Ok(())
+}
+
+/// Seconds since the epoch with a fractional part, accepting the decimal
+/// comma some locales put in `$EPOCHREALTIME`.
+fn parse_epoch_seconds(value: &str) -> Result<f64> {
+    value
+        .trim()
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| anyhow!("Invalid timestamp '{}'", value))
+}
+
+#[cfg(unix)]
+fn connect(socket: &Path) -> Result<std::os::unix::net::UnixStream> {
+    Ok(std::os::unix::net::UnixStream::connect(socket)?)
+}
+
+#[cfg(windows)]
+fn connect(socket: &Path) -> Result<std::fs::File> {
+    Ok(std::fs::OpenOptions::new().read(true).write(true).open(socket)?)
+}
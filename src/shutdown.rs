@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::models::{ComponentType, LatencyEvent};
+use crate::units;
+
+/// Stops a set of background tasks together: each task spawned through it
+/// is dropped at its next `.await` once shutdown is requested, so it can't
+/// queue events after the final flush. Clones share one set of tasks.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (requested, _) = watch::channel(false);
+        Self {
+            requested: Arc::new(requested),
+            tasks: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Run `task` until it completes or shutdown is requested.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut requested = self.requested.subscribe();
+        let handle = tokio::spawn(async move {
+            tokio::select! {
+                _ = requested.wait_for(|requested| *requested) => {}
+                _ = task => {}
+            }
+        });
+
+        let mut tasks = self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle);
+    }
+
+    /// Resolves once shutdown is requested, right away if it already was.
+    pub async fn requested(&self) {
+        let mut requested = self.requested.subscribe();
+        let _ = requested.wait_for(|requested| *requested).await;
+    }
+
+    /// Request shutdown and wait up to `grace` for the spawned tasks to
+    /// stop. Tasks still running then, stuck in blocking code, are aborted;
+    /// returns how many.
+    pub async fn stop(&self, grace: Duration) -> usize {
+        self.requested.send_replace(true);
+
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        let deadline = tokio::time::Instant::now() + grace;
+        let mut aborted = 0;
+        for mut task in tasks {
+            if tokio::time::timeout_at(deadline, &mut task).await.is_err() {
+                task.abort();
+                aborted += 1;
+            }
+        }
+        aborted
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ComponentTotals {
+    events: u64,
+    /// Events whose duration is a latency, which the averages cover
+    timed: u64,
+    total_us: u64,
+    max_us: u64,
+}
+
+/// What one monitor run stored, printed when it exits.
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub started: DateTime<Utc>,
+    pub ended: DateTime<Utc>,
+    /// Events stored, including `flushed`
+    pub events: u64,
+    /// Events still queued at shutdown, stored before exiting
+    pub flushed: u64,
+    /// Events that couldn't be stored
+    pub failed: u64,
+    by_component: HashMap<ComponentType, ComponentTotals>,
+}
+
+impl Default for SessionSummary {
+    fn default() -> Self {
+        let now = Utc::now();
+        Self {
+            started: now,
+            ended: now,
+            events: 0,
+            flushed: 0,
+            failed: 0,
+            by_component: HashMap::new(),
+        }
+    }
+}
+
+impl SessionSummary {
+    /// Count a stored event.
+    pub fn record(&mut self, event: &LatencyEvent) {
+        self.events += 1;
+        let totals = self.by_component.entry(event.component_type).or_default();
+        totals.events += 1;
+        if event.kind.carries_latency() {
+            let duration_us = event.duration_us();
+            totals.timed += 1;
+            totals.total_us += duration_us;
+            totals.max_us = totals.max_us.max(duration_us);
+        }
+    }
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Session summary: {} events captured in {} ({} flushed at shutdown, {} failed)",
+            self.events,
+            format_elapsed((self.ended - self.started).num_seconds().max(0)),
+            self.flushed,
+            self.failed
+        )?;
+        if self.by_component.is_empty() {
+            return Ok(());
+        }
+
+        let mut components: Vec<_> = self.by_component.iter().collect();
+        components.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        writeln!(f, "  {:<18} {:>8} {:>12} {:>12}", "Component", "Events", "Average", "Max")?;
+        for (component, totals) in components {
+            let (average, max) = match totals.total_us.checked_div(totals.timed) {
+                Some(average_us) => (
+                    units::format_duration(Duration::from_micros(average_us)),
+                    units::format_duration(Duration::from_micros(totals.max_us)),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            writeln!(f, "  {:<18} {:>8} {:>12} {:>12}", component.as_str(), totals.events, average, max)?;
+        }
+        Ok(())
+    }
+}
+
+/// `42s`, `12m 5s` or `3h 4m 5s`.
+fn format_elapsed(secs: i64) -> String {
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, secs)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{EventKind, EventSource};
+
+    fn event(component: ComponentType, kind: EventKind, duration_ms: u64) -> LatencyEvent {
+        let duration = Duration::from_millis(duration_ms);
+        LatencyEvent::new(component, EventSource::CommandExecution, duration, "run".to_string()).with_kind(kind)
+    }
+
+    #[test]
+    fn elapsed_time_drops_empty_leading_units() {
+        assert_eq!(format_elapsed(0), "0s");
+        assert_eq!(format_elapsed(42), "42s");
+        assert_eq!(format_elapsed(725), "12m 5s");
+        assert_eq!(format_elapsed(3600), "1h 0m 0s");
+        assert_eq!(format_elapsed(11_045), "3h 4m 5s");
+    }
+
+    #[test]
+    fn summary_averages_only_events_that_carry_latency() {
+        let mut summary = SessionSummary::default();
+        summary.record(&event(ComponentType::Terminal, EventKind::CommandRun, 100));
+        summary.record(&event(ComponentType::Terminal, EventKind::CommandRun, 300));
+        summary.record(&event(ComponentType::Terminal, EventKind::Annotation, 9000));
+        summary.record(&event(ComponentType::VSCode, EventKind::Lifecycle, 0));
+        summary.ended = summary.started + chrono::Duration::seconds(75);
+        summary.flushed = 2;
+
+        let text = summary.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Session summary: 4 events captured in 1m 15s (2 flushed at shutdown, 0 failed)");
+        assert!(lines[1].trim_start().starts_with("Component"));
+        // Sorted by component name
+        let terminal = format!(
+            "  {:<18} {:>8} {:>12} {:>12}",
+            "Terminal",
+            3,
+            units::format_duration(Duration::from_millis(200)),
+            units::format_duration(Duration::from_millis(300))
+        );
+        assert_eq!(lines[2], terminal);
+        assert_eq!(lines[3], format!("  {:<18} {:>8} {:>12} {:>12}", "VSCode", 1, "-", "-"));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn empty_sessions_print_one_line() {
+        let summary = SessionSummary::default();
+        assert_eq!(summary.to_string(), "Session summary: 0 events captured in 0s (0 flushed at shutdown, 0 failed)\n");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn stop_ends_spawned_tasks_and_aborts_stuck_ones() {
+        let shutdown = Shutdown::default();
+        shutdown.spawn(std::future::pending());
+        shutdown.spawn(async {});
+        // Blocking code never reaches an await where it could be dropped
+        let (started, blocking) = tokio::sync::oneshot::channel();
+        shutdown.spawn(async move {
+            let _ = started.send(());
+            std::thread::sleep(Duration::from_millis(300));
+        });
+        blocking.await.unwrap();
+
+        let waiter = shutdown.clone();
+        let requested = tokio::spawn(async move { waiter.requested().await });
+        assert!(!requested.is_finished());
+
+        assert_eq!(shutdown.stop(Duration::from_millis(50)).await, 1);
+        requested.await.unwrap();
+        // Already requested: resolves at once
+        shutdown.requested().await;
+    }
+}
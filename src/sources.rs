@@ -0,0 +1,390 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::backend::MetricsBackend;
+use crate::filter::EventFilter;
+use crate::histogram::HistogramConfig;
+use crate::models::ComponentType;
+use crate::object_store::uri_encode;
+use crate::secrets::Secrets;
+use crate::storage::MetricsStorage;
+use crate::timerange::{self, TimeRange, WorkingHoursConfig};
+
+/// Name the dashboard's own database is listed under.
+pub const LOCAL_SOURCE: &str = "local";
+
+/// Another event store the dashboard can show next to its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataSourceConfig {
+    pub name: String,
+    /// A database file, or a running monitor's dashboard (`http://host:port`)
+    pub location: String,
+    /// Secret holding the API token for a remote dashboard
+    #[serde(default)]
+    pub token_secret: Option<String>,
+}
+
+/// Most buckets one timeseries request may ask for.
+const MAX_TIMESERIES_BUCKETS: i64 = 5000;
+
+/// Parameters of `/api/timeseries`, e.g. `?component=VSCode&bucket=1m&since=6h`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeseriesQuery {
+    pub component: Option<String>,
+    /// Bucket width (`30s`, `1m`, `1h`, ...); defaults to `1m`
+    pub bucket: Option<String>,
+    /// Start of the window; defaults to `1h`
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Leave out samples outside the source's working hours
+    pub working_hours: Option<bool>,
+    /// Only samples from this workspace (folder name)
+    pub workspace: Option<String>,
+}
+
+impl TimeseriesQuery {
+    /// The window and bucket width in seconds, rejecting requests for more
+    /// than `MAX_TIMESERIES_BUCKETS` buckets.
+    pub fn resolve(&self, working_hours: &WorkingHoursConfig) -> Result<(TimeRange, i64)> {
+        let bucket = self.bucket.as_deref().unwrap_or("1m");
+        let bucket_secs = timerange::parse_duration(bucket)
+            .map(|d| d.num_seconds())
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| anyhow!("Invalid bucket '{}' (expected e.g. 30s, 1m, 1h)", bucket))?;
+
+        let mut range = TimeRange::parse(Some(self.since.as_deref().unwrap_or("1h")), self.until.as_deref())?;
+        if self.working_hours == Some(true) {
+            range = range.within_working_hours(working_hours.resolve()?);
+        }
+        if let Some(workspace) = &self.workspace {
+            range = range.matching(EventFilter::equals("workspace", workspace)?);
+        }
+        let span = range.until.unwrap_or_else(chrono::Utc::now) - range.since.unwrap_or_default();
+        if span.num_seconds() / bucket_secs > MAX_TIMESERIES_BUCKETS {
+            return Err(anyhow!(
+                "{} buckets of {} requested; at most {} are allowed",
+                span.num_seconds() / bucket_secs,
+                bucket,
+                MAX_TIMESERIES_BUCKETS
+            ));
+        }
+        Ok((range, bucket_secs))
+    }
+}
+
+/// Longest history the calendar shows, in days.
+const MAX_CALENDAR_DAYS: u32 = 731;
+
+/// Parameters of `/api/calendar`, e.g. `?component=VSCode&days=365`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CalendarQuery {
+    pub component: Option<String>,
+    /// Days of history up to and including today; defaults to 182
+    pub days: Option<u32>,
+}
+
+impl CalendarQuery {
+    /// First day shown.
+    pub fn since(&self) -> chrono::NaiveDate {
+        let days = self.days.unwrap_or(182).clamp(1, MAX_CALENDAR_DAYS);
+        chrono::Utc::now().date_naive() - chrono::Duration::days(days as i64 - 1)
+    }
+}
+
+/// Parameters of `/api/derived`, e.g. `?since=24h`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DerivedQuery {
+    /// Start of the window; defaults to `24h`
+    pub since: Option<String>,
+}
+
+impl DerivedQuery {
+    pub fn since(&self) -> Result<chrono::DateTime<chrono::Utc>> {
+        timerange::parse_time_expr(self.since.as_deref().unwrap_or("24h"), chrono::Utc::now())
+    }
+}
+
+/// Where a source's data is read from.
+pub enum DataSource {
+    Storage(MetricsStorage),
+    /// The shared database of `storage.backend`, which has events and
+    /// metrics but none of the local extras (summaries, saved queries, ...)
+    Backend(Arc<dyn MetricsBackend>),
+    Remote {
+        base_url: String,
+        client: reqwest::Client,
+        token: Option<String>,
+    },
+}
+
+/// The dashboard's sources in configured order, `local` first.
+pub struct DataSources {
+    sources: Vec<(String, Arc<DataSource>)>,
+}
+
+impl DataSources {
+    /// `local` first, then `central` (listed under its backend's name) if
+    /// events are centralized, then `configs`.
+    pub async fn open(
+        configs: &[DataSourceConfig],
+        local: MetricsStorage,
+        central: Option<Arc<dyn MetricsBackend>>,
+        secrets: &Secrets,
+    ) -> Result<Self> {
+        let mut sources = vec![(LOCAL_SOURCE.to_string(), Arc::new(DataSource::Storage(local)))];
+        if let Some(central) = central {
+            sources.push((central.name().to_string(), Arc::new(DataSource::Backend(central))));
+        }
+
+        for config in configs {
+            if sources.iter().any(|(name, _)| *name == config.name) {
+                return Err(anyhow!("Duplicate dashboard source '{}'", config.name));
+            }
+            let source = DataSource::open(config, secrets).await?;
+            info!("Dashboard source '{}' reads from {}", config.name, config.location);
+            sources.push((config.name.clone(), Arc::new(source)));
+        }
+
+        Ok(Self { sources })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DataSource> {
+        self.sources.iter().find(|(n, _)| n == name).map(|(_, source)| source.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<DataSource>)> {
+        self.sources.iter().map(|(name, source)| (name.as_str(), source))
+    }
+
+    /// Names and kinds, for the dashboard's source switcher.
+    pub fn list(&self) -> serde_json::Value {
+        json!(self
+            .sources
+            .iter()
+            .map(|(name, source)| json!({ "name": name, "kind": source.kind() }))
+            .collect::<Vec<_>>())
+    }
+}
+
+impl DataSource {
+    async fn open(config: &DataSourceConfig, secrets: &Secrets) -> Result<Self> {
+        let location = &config.location;
+        if location.starts_with("http://") || location.starts_with("https://") {
+            let token = match &config.token_secret {
+                Some(secret) => Some(
+                    secrets
+                        .get(secret)
+                        .ok_or_else(|| anyhow!("Missing secret '{}' for source '{}'", secret, config.name))?,
+                ),
+                None => None,
+            };
+            Ok(DataSource::Remote {
+                base_url: location.trim_end_matches('/').to_string(),
+                client: reqwest::Client::new(),
+                token,
+            })
+        } else {
+            let path = PathBuf::from(location);
+            if !path.exists() {
+                return Err(anyhow!("Database {} for source '{}' does not exist", path.display(), config.name));
+            }
+            Ok(DataSource::Storage(MetricsStorage::open_readonly(&path).await?))
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DataSource::Storage(_) => "database",
+            DataSource::Backend(_) => "backend",
+            DataSource::Remote { .. } => "remote",
+        }
+    }
+
+    pub async fn status(&self) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => Ok(json!(storage.get_system_status().await?)),
+            DataSource::Backend(backend) => Ok(json!({
+                "backend": backend.name(),
+                "total_events": backend.count_events().await?,
+            })),
+            DataSource::Remote { .. } => self.fetch("/api/status", &[]).await?.ok_or_else(not_found),
+        }
+    }
+
+    pub async fn recent_events(&self) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => Ok(json!(storage.get_recent_events(50).await?)),
+            DataSource::Backend(backend) => Ok(json!(backend.get_recent_events(50).await?)),
+            DataSource::Remote { .. } => self.fetch("/api/events", &[]).await?.ok_or_else(not_found),
+        }
+    }
+
+    pub async fn metrics(&self) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => Ok(json!(storage.get_performance_metrics().await?)),
+            DataSource::Backend(backend) => Ok(json!(
+                backend
+                    .get_performance_metrics_in(&TimeRange::last(chrono::Duration::hours(1)))
+                    .await?
+            )),
+            DataSource::Remote { .. } => self.fetch("/api/metrics", &[]).await?.ok_or_else(not_found),
+        }
+    }
+
+    /// Latency buckets; `working_hours` applies to databases read directly,
+    /// while remote dashboards use their own configuration.
+    pub async fn timeseries(
+        &self,
+        query: &TimeseriesQuery,
+        working_hours: &WorkingHoursConfig,
+    ) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => {
+                let (range, bucket_secs) = query.resolve(working_hours)?;
+                let points = storage
+                    .get_latency_timeseries(&range, bucket_secs, query.component.as_deref())
+                    .await?;
+                Ok(json!({
+                    "bucket_secs": bucket_secs,
+                    "since": range.since,
+                    "until": range.until,
+                    "working_hours_only": range.working_hours.is_some(),
+                    "workspace": query.workspace,
+                    "points": points,
+                }))
+            }
+            DataSource::Backend(backend) => Err(unsupported(backend.as_ref(), "timeseries")),
+            DataSource::Remote { .. } => {
+                let params = [
+                    ("component", &query.component),
+                    ("bucket", &query.bucket),
+                    ("since", &query.since),
+                    ("until", &query.until),
+                    ("workspace", &query.workspace),
+                ];
+                let mut params: Vec<_> = params
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((key, value.clone()?)))
+                    .collect();
+                if let Some(working_hours) = query.working_hours {
+                    params.push(("working_hours", working_hours.to_string()));
+                }
+                self.fetch("/api/timeseries", &params).await?.ok_or_else(not_found)
+            }
+        }
+    }
+
+    /// Daily summaries; `excluded_days` lists the holidays and vacations
+    /// in `working_hours` so the calendar can grey them out, and
+    /// `buckets_ms` the component's histogram bounds it colors days by.
+    pub async fn calendar(
+        &self,
+        query: &CalendarQuery,
+        working_hours: &WorkingHoursConfig,
+        histograms: &HistogramConfig,
+    ) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => {
+                let since = query.since();
+                let days = storage.get_daily_summaries(since, query.component.as_deref()).await?;
+                let excluded = working_hours.excluded_days(since, chrono::Utc::now().date_naive());
+                let buckets = histograms.buckets_ms(query.component.as_deref().and_then(ComponentType::parse));
+                Ok(json!({ "since": since, "days": days, "excluded_days": excluded, "buckets_ms": buckets }))
+            }
+            DataSource::Backend(backend) => Err(unsupported(backend.as_ref(), "the latency calendar")),
+            DataSource::Remote { .. } => {
+                let mut params = Vec::new();
+                if let Some(component) = &query.component {
+                    params.push(("component", component.clone()));
+                }
+                if let Some(days) = query.days {
+                    params.push(("days", days.to_string()));
+                }
+                self.fetch("/api/calendar", &params).await?.ok_or_else(not_found)
+            }
+        }
+    }
+
+    /// Derived metric values grouped by name, oldest first.
+    pub async fn derived(&self, query: &DerivedQuery) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => {
+                let since = query.since()?;
+                let mut series: std::collections::BTreeMap<String, Vec<serde_json::Value>> = Default::default();
+                for sample in storage.get_derived_metrics(since).await? {
+                    series
+                        .entry(sample.name)
+                        .or_default()
+                        .push(json!({ "at": sample.at, "value": sample.value }));
+                }
+                Ok(json!({ "since": since, "series": series }))
+            }
+            DataSource::Backend(backend) => Err(unsupported(backend.as_ref(), "derived metrics")),
+            DataSource::Remote { .. } => {
+                let params: Vec<_> = query.since.iter().map(|since| ("since", since.clone())).collect();
+                self.fetch("/api/derived", &params).await?.ok_or_else(not_found)
+            }
+        }
+    }
+
+    pub async fn saved_queries(&self) -> Result<serde_json::Value> {
+        match self {
+            DataSource::Storage(storage) => Ok(json!(storage.list_saved_queries().await?)),
+            DataSource::Backend(_) => Ok(json!([])),
+            DataSource::Remote { .. } => self.fetch("/api/queries", &[]).await?.ok_or_else(not_found),
+        }
+    }
+
+    /// Events matching a saved query of this source, or `None` if it has no
+    /// query by that name.
+    pub async fn run_query(&self, name: &str) -> Result<Option<serde_json::Value>> {
+        match self {
+            DataSource::Storage(storage) => {
+                let Some(query) = storage.get_saved_query(name).await? else {
+                    return Ok(None);
+                };
+                let filter = EventFilter::parse(&query.filter)?.to_sql()?;
+                let events = storage.query_events(Some(&filter), query.limit.unwrap_or(50)).await?;
+                Ok(Some(json!(events)))
+            }
+            DataSource::Backend(_) => Ok(None),
+            DataSource::Remote { .. } => {
+                self.fetch(&format!("/api/queries/{}/events", uri_encode(name, true)), &[]).await
+            }
+        }
+    }
+
+    /// GET a JSON document from a remote dashboard; `None` on 404.
+    async fn fetch(&self, path: &str, query: &[(&str, String)]) -> Result<Option<serde_json::Value>> {
+        let DataSource::Remote { base_url, client, token } = self else {
+            return Err(anyhow!("Not a remote source"));
+        };
+
+        let mut request = client.get(format!("{}{}", base_url, path)).query(query);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!("{} returned {}", base_url, response.status()));
+        }
+        Ok(Some(response.json().await?))
+    }
+}
+
+fn not_found() -> anyhow::Error {
+    anyhow!("Remote dashboard has no such endpoint")
+}
+
+fn unsupported(backend: &dyn MetricsBackend, what: &str) -> anyhow::Error {
+    anyhow!("The {} backend doesn't provide {}", backend.name(), what)
+}
+
@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+/// Result of a two-sided Mann-Whitney U test of whether values in `b` tend
+/// to be larger or smaller than those in `a`. Latencies are skewed and
+/// long-tailed, so a rank test is used rather than comparing means.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RankTest {
+    /// U statistic for `b`: how many (a, b) pairs have b above a, ties half
+    pub u: f64,
+    /// Probability that a random `b` value exceeds a random `a` value;
+    /// 0.5 means neither tends to be larger
+    pub b_greater_probability: f64,
+    pub p_value: f64,
+}
+
+/// Mann-Whitney U test of two ascending slices, using the normal
+/// approximation with tie correction. `None` unless both have at least
+/// `MIN_SAMPLES` values, below which the approximation isn't trustworthy.
+pub fn mann_whitney(a: &[u64], b: &[u64]) -> Option<RankTest> {
+    const MIN_SAMPLES: usize = 8;
+    if a.len() < MIN_SAMPLES || b.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    // Merge the two sorted samples, giving tied values their average rank
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let n = n_a + n_b;
+    let (mut i, mut j) = (0, 0);
+    let mut rank = 0.0;
+    let mut rank_sum_b = 0.0;
+    let mut tie_term = 0.0;
+    while i < a.len() || j < b.len() {
+        let value = match (a.get(i), b.get(j)) {
+            (Some(&x), Some(&y)) => x.min(y),
+            (Some(&x), None) => x,
+            (None, Some(&y)) => y,
+            (None, None) => break,
+        };
+        let ties_a = a[i..].iter().take_while(|&&x| x == value).count();
+        let ties_b = b[j..].iter().take_while(|&&y| y == value).count();
+        let ties = (ties_a + ties_b) as f64;
+
+        let average_rank = rank + (ties + 1.0) / 2.0;
+        rank_sum_b += average_rank * ties_b as f64;
+        tie_term += ties * ties * ties - ties;
+        rank += ties;
+        i += ties_a;
+        j += ties_b;
+    }
+
+    let u = rank_sum_b - n_b * (n_b + 1.0) / 2.0;
+    let mean = n_a * n_b / 2.0;
+    let variance = n_a * n_b / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    let p_value = if variance > 0.0 {
+        // Continuity correction towards the mean
+        let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+        (2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0)
+    } else {
+        // Every value identical: no evidence of any difference
+        1.0
+    };
+
+    Some(RankTest {
+        u,
+        b_greater_probability: u / (n_a * n_b),
+        p_value,
+    })
+}
+
+/// Pearson correlation coefficient of paired samples. `None` with fewer
+/// than three pairs or when either side is constant.
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() < 3 || xs.len() != ys.len() {
+        return None;
+    }
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let (mut covariance, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        covariance += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    (var_x > 0.0 && var_y > 0.0).then(|| covariance / (var_x * var_y).sqrt())
+}
+
+/// Standard normal CDF, via the Abramowitz and Stegun 7.1.26 approximation
+/// of erf (absolute error below 1.5e-7).
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_cdf_matches_known_values() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+        assert!((normal_cdf(-1.0) - 0.158_655).abs() < 1e-5);
+        assert!((normal_cdf(1.5) + normal_cdf(-1.5) - 1.0).abs() < 1e-7);
+    }
+
+    #[test]
+    fn rank_test_needs_enough_samples() {
+        let few: Vec<u64> = (1..8).collect();
+        let enough: Vec<u64> = (1..9).collect();
+        assert!(mann_whitney(&few, &enough).is_none());
+        assert!(mann_whitney(&enough, &few).is_none());
+        assert!(mann_whitney(&enough, &enough).is_some());
+    }
+
+    #[test]
+    fn separated_samples_are_significant() {
+        let a: Vec<u64> = (1..=10).collect();
+        let b: Vec<u64> = (11..=20).collect();
+        let test = mann_whitney(&a, &b).unwrap();
+        assert_eq!(test.u, 100.0);
+        assert_eq!(test.b_greater_probability, 1.0);
+        assert!(test.p_value < 0.001, "p = {}", test.p_value);
+
+        // Swapping the samples flips the direction, not the significance
+        let swapped = mann_whitney(&b, &a).unwrap();
+        assert_eq!(swapped.u, 0.0);
+        assert!((swapped.p_value - test.p_value).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interleaved_and_identical_samples_are_not() {
+        let a: Vec<u64> = (1..=20).step_by(2).collect();
+        let b: Vec<u64> = (2..=20).step_by(2).collect();
+        let test = mann_whitney(&a, &b).unwrap();
+        assert!(test.p_value > 0.5, "p = {}", test.p_value);
+
+        let same = vec![5; 10];
+        let test = mann_whitney(&same, &same).unwrap();
+        assert_eq!(test.u, 50.0);
+        assert_eq!(test.b_greater_probability, 0.5);
+        assert_eq!(test.p_value, 1.0);
+    }
+
+    #[test]
+    fn ties_count_half_towards_u() {
+        let a = vec![1, 1, 1, 1, 2, 2, 2, 2];
+        let b = vec![2, 2, 2, 2, 3, 3, 3, 3];
+        let test = mann_whitney(&a, &b).unwrap();
+        // 16 pairs tie at 2, every other pair has b above a
+        assert_eq!(test.u, 64.0 - 16.0 / 2.0);
+        assert!(test.b_greater_probability > 0.8);
+    }
+
+    #[test]
+    fn pearson_measures_linear_correlation() {
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let rising = [2.0, 4.0, 6.0, 8.0, 10.0];
+        let falling = [9.0, 7.0, 5.0, 3.0, 1.0];
+        assert!((pearson(&xs, &rising).unwrap() - 1.0).abs() < 1e-12);
+        assert!((pearson(&xs, &falling).unwrap() + 1.0).abs() < 1e-12);
+
+        let noisy = pearson(&xs, &[1.0, 3.0, 2.0, 5.0, 4.0]).unwrap();
+        assert!((noisy - 0.8).abs() < 1e-12, "r = {}", noisy);
+    }
+
+    #[test]
+    fn pearson_needs_three_pairs_that_vary() {
+        assert_eq!(pearson(&[1.0, 2.0], &[1.0, 2.0]), None);
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[1.0, 2.0]), None);
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[4.0, 4.0, 4.0]), None);
+        assert_eq!(pearson(&[5.0, 5.0, 5.0], &[1.0, 2.0, 3.0]), None);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::models::ComponentType;
+use crate::watch::{SLOW_MS, VERY_SLOW_MS};
+
+/// How long `statusline` waits on the monitor before printing nothing, so a
+/// stuck monitor can't stall the prompt.
+const SOCKET_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How `statusline` marks slow components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusColor {
+    None,
+    /// ANSI escapes, for shell prompts such as starship
+    Ansi,
+    /// `#[fg=...]` style markup for tmux's `status-right`
+    Tmux,
+}
+
+impl StatusColor {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "none" => Ok(StatusColor::None),
+            "ansi" => Ok(StatusColor::Ansi),
+            "tmux" => Ok(StatusColor::Tmux),
+            _ => Err(anyhow!("Unknown color mode '{}' (none, ansi, tmux)", value)),
+        }
+    }
+}
+
+pub struct StatuslineOptions {
+    /// Only these components, in this order (all with recent events when
+    /// empty)
+    pub components: Vec<ComponentType>,
+    pub window_secs: u64,
+    /// Show p95 rather than the median
+    pub p95: bool,
+    pub color: StatusColor,
+}
+
+#[derive(Deserialize)]
+struct StatusReply {
+    #[serde(default)]
+    components: Vec<ComponentStatus>,
+}
+
+#[derive(Deserialize)]
+struct ComponentStatus {
+    component: ComponentType,
+    p50_ms: f64,
+    p95_ms: f64,
+}
+
+/// Ask the monitor on `socket` for recent latency and render it as one
+/// line, e.g. `VSC 38ms | Copilot 790ms`. The monitor answers from memory,
+/// so this takes a few milliseconds.
+pub fn status_line(socket: &Path, options: &StatuslineOptions) -> Result<String> {
+    let reply = request_status(socket, options.window_secs)?;
+    let mut parts = Vec::new();
+    let ordered: Vec<&ComponentStatus> = if options.components.is_empty() {
+        reply.components.iter().collect()
+    } else {
+        options
+            .components
+            .iter()
+            .filter_map(|wanted| reply.components.iter().find(|status| status.component == *wanted))
+            .collect()
+    };
+    for status in ordered {
+        let ms = if options.p95 { status.p95_ms } else { status.p50_ms };
+        parts.push(paint(&format!("{} {}", short_name(status.component), format_ms(ms)), ms, options.color));
+    }
+    Ok(parts.join(" | "))
+}
+
+fn request_status(socket: &Path, window_secs: u64) -> Result<StatusReply> {
+    let mut stream = connect(socket)?;
+    stream.write_all(format!("{{\"type\":\"status\",\"window_secs\":{}}}\n", window_secs).as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(unix)]
+fn connect(socket: &Path) -> Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Named pipes have no timeouts; a stuck monitor stalls the prompt.
+#[cfg(windows)]
+fn connect(socket: &Path) -> Result<std::fs::File> {
+    Ok(std::fs::OpenOptions::new().read(true).write(true).open(socket)?)
+}
+
+/// Short label that keeps the line narrow enough for a prompt.
+fn short_name(component: ComponentType) -> &'static str {
+    match component {
+        ComponentType::VSCode => "VSC",
+        ComponentType::VSCodeExtension => "Ext",
+        ComponentType::GitHubCopilot => "Copilot",
+        ComponentType::LocalModel => "Model",
+        ComponentType::CloudModel => "Cloud",
+        ComponentType::Terminal => "Term",
+        ComponentType::LanguageServer => "LSP",
+        ComponentType::FileSystem => "Disk",
+        ComponentType::Network => "Net",
+        ComponentType::System => "Sys",
+        ComponentType::BuildTool => "Build",
+        ComponentType::Editor => "Editor",
+    }
+}
+
+fn format_ms(ms: f64) -> String {
+    if ms >= 10_000.0 {
+        format!("{:.0}s", ms / 1000.0)
+    } else if ms >= 1000.0 {
+        format!("{:.1}s", ms / 1000.0)
+    } else {
+        format!("{:.0}ms", ms)
+    }
+}
+
+/// Yellow over `SLOW_MS` and red over `VERY_SLOW_MS`, as in `watch`.
+fn paint(text: &str, ms: f64, color: StatusColor) -> String {
+    let level = if ms >= VERY_SLOW_MS {
+        2
+    } else if ms >= SLOW_MS {
+        1
+    } else {
+        0
+    };
+    match (color, level) {
+        (StatusColor::None, _) | (_, 0) => text.to_string(),
+        (StatusColor::Ansi, 1) => format!("\x1b[33m{}\x1b[0m", text),
+        (StatusColor::Ansi, _) => format!("\x1b[31m{}\x1b[0m", text),
+        (StatusColor::Tmux, 1) => format!("#[fg=yellow]{}#[default]", text),
+        (StatusColor::Tmux, _) => format!("#[fg=red]{}#[default]", text),
+    }
+}
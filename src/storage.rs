@@ -1,14 +1,90 @@
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use sqlx::{sqlite::SqlitePool, Row};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use sqlx::{
+    query::Query,
+    sqlite::{
+        Sqlite, SqliteArguments, SqliteConnectOptions, SqliteConnection, SqliteJournalMode,
+        SqlitePool, SqliteRow, SqliteSynchronous,
+    },
+    Row,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
 use std::path::Path;
-use tracing::{debug, info};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, info, warn};
 
-use crate::models::{LatencyEvent, SystemStatus, PerformanceMetrics, ComponentType};
+use crate::models::{LatencyEvent, SyncedEvent, SystemStatus, PerformanceMetrics, ComponentType, EventKind, EventSource, EventPriority, SavedQuery, AuditEntry, ApiToken, WebhookDelivery, LatencyHistogram, LatencyExemplar, LatencyBucket, DailySummary, DeletedBatch, EventDeletion, DerivedMetricSample, ExtensionCpu, GroupedMetrics, MaintenanceWindow, ProcessSummary, ProcessTimelinePoint, Recurrence, AlertHistoryEntry, SuggestionHour, ModelThroughput, TokenMetrics, METADATA_COLUMNS};
+use crate::alerts::AlertNotification;
+use crate::auth::ApiScope;
+use crate::calibration::NoiseProfile;
+use crate::lifecycle::{LifecycleKind, LifecycleSummary};
+use crate::filter::{FilterValue, SqlFilter};
+use crate::timerange::TimeRange;
+use crate::config::WriteBufferConfig;
+use crate::export::{collect_metadata_keys, CsvExporter, ExportRow, JsonExporter, ParquetExporter};
+use crate::event_queue::{LoadSheddingStatus, QueueStats};
+use crate::connectivity::ConnectivityTracker;
+use crate::histogram::{HdrHistogram, HistogramConfig};
+use crate::progress::Progress;
+use crate::suggestions;
 
 #[derive(Clone)]
 pub struct MetricsStorage {
     pool: SqlitePool,
+    live_events: broadcast::Sender<LatencyEvent>,
+    /// Set when writes go through the batching writer task
+    writer: Option<mpsc::Sender<WriteCommand>>,
+    /// Set when stored events are recorded into histograms
+    histograms: Option<Arc<Mutex<PendingHistograms>>>,
+    /// Set when network and model events are tagged with connectivity
+    connectivity: Option<ConnectivityTracker>,
+}
+
+/// Histograms of events stored since the last flush to `latency_histograms`,
+/// keyed by the start of the flush-interval-long period each event's
+/// timestamp falls in.
+struct PendingHistograms {
+    period: chrono::Duration,
+    by_period: HashMap<(DateTime<Utc>, ComponentType), HdrHistogram>,
+}
+
+/// Capacity of the live event broadcast; slow subscribers skip ahead.
+const LIVE_EVENT_CAPACITY: usize = 1024;
+
+/// Self-reports older than this don't count towards degraded status; a few
+/// missed self-check intervals means the monitor has likely stopped.
+const LOAD_STATUS_MAX_AGE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How long a connection waits on another process's write lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Deleting at least this many events in one prune triggers a VACUUM.
+const VACUUM_THRESHOLD: u64 = 10_000;
+
+/// Events deleted per statement by prune and purge, so neither holds the
+/// write lock for long and both can stop between batches.
+const DELETE_BATCH_SIZE: u32 = 10_000;
+
+/// How often the retention task prunes events past `retention_days`.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often completed and dirty days are (re-)summarized.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Ranges at least this long take percentiles from `latency_histograms`
+/// instead of sorting every raw duration.
+const HISTOGRAM_PERCENTILE_RANGE: chrono::Duration = chrono::Duration::hours(24);
+
+/// Maps a stored enum value to its canonical name, `None` when unknown.
+type CanonicalName = fn(&str) -> Option<&'static str>;
+
+enum WriteCommand {
+    Event(LatencyEvent),
+    Flush(oneshot::Sender<Result<()>>),
 }
 
 impl MetricsStorage {
@@ -18,16 +94,301 @@ impl MetricsStorage {
             tokio::fs::create_dir_all(parent).await?;
         }
 
-        let database_url = "sqlite::memory:".to_string();
-        let pool = SqlitePool::connect(&database_url).await?;
+        // WAL lets readers (dashboard, CLI) run alongside the monitor's writes;
+        // NORMAL sync is durable across application crashes, which is enough
+        // for metrics
+        let options = SqliteConnectOptions::new()
+            .filename(database_path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
+        let pool = SqlitePool::connect_with(options).await?;
 
-        let storage = Self { pool };
+        let (live_events, _) = broadcast::channel(LIVE_EVENT_CAPACITY);
+        let storage = Self {
+            pool,
+            live_events,
+            writer: None,
+            histograms: None,
+            connectivity: None,
+        };
         storage.initialize_schema().await?;
 
         info!("Metrics storage initialized at: {}", database_path.display());
         Ok(storage)
     }
 
+    /// Open a restored archive read-only, after bringing its schema up to
+    /// date so archives written by older versions have the columns queries
+    /// expect.
+    pub async fn open_archive(database_path: &Path) -> Result<Self> {
+        Self::new(database_path).await?.pool.close().await;
+        Self::open_readonly(database_path).await
+    }
+
+    /// Open an existing database (e.g. a restored archive) without running
+    /// migrations or allowing writes.
+    pub async fn open_readonly(database_path: &Path) -> Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(database_path)
+            .read_only(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let (live_events, _) = broadcast::channel(LIVE_EVENT_CAPACITY);
+        info!("Opened read-only database: {}", database_path.display());
+        Ok(Self {
+            pool,
+            live_events,
+            writer: None,
+            histograms: None,
+            connectivity: None,
+        })
+    }
+
+    /// Route `store_event` through a background task that commits events in
+    /// batches. Call `flush` before exiting so buffered events aren't lost.
+    pub fn with_write_buffer(mut self, config: &WriteBufferConfig) -> Self {
+        if !config.enabled {
+            return self;
+        }
+
+        let max_batch_size = config.max_batch_size.max(1);
+        let (sender, receiver) = mpsc::channel(max_batch_size * 4);
+        tokio::spawn(run_writer(
+            self.clone(),
+            receiver,
+            Duration::from_millis(config.flush_interval_ms.max(10)),
+            max_batch_size,
+        ));
+
+        self.writer = Some(sender);
+        self
+    }
+
+    /// Record every stored event into a per-component [`HdrHistogram`] and
+    /// write them to `latency_histograms` every `flush_interval_secs`. Call
+    /// before `with_write_buffer` so the writer task records too.
+    pub fn with_latency_histograms(mut self, config: &HistogramConfig) -> Self {
+        if !config.store {
+            return self;
+        }
+
+        self.histograms = Some(Arc::new(Mutex::new(PendingHistograms {
+            period: chrono::Duration::seconds(config.flush_interval_secs.max(1) as i64),
+            by_period: HashMap::new(),
+        })));
+        tokio::spawn(run_histogram_flusher(
+            self.clone(),
+            Duration::from_secs(config.flush_interval_secs.max(1)),
+        ));
+        self
+    }
+
+    /// Tag the events `store_event` stores with the tracker's latest sample
+    /// of the network context.
+    pub fn with_connectivity(mut self, tracker: &ConnectivityTracker) -> Self {
+        self.connectivity = Some(tracker.clone());
+        self
+    }
+
+    /// Write out any buffered events and recorded histograms.
+    pub async fn flush(&self) -> Result<()> {
+        if let Some(writer) = &self.writer {
+            let (ack, done) = oneshot::channel();
+            writer
+                .send(WriteCommand::Flush(ack))
+                .await
+                .map_err(|_| anyhow!("Event writer has stopped"))?;
+            done.await.map_err(|_| anyhow!("Event writer has stopped"))??;
+        }
+
+        self.flush_histograms().await
+    }
+
+    fn record_histograms<'a>(&self, events: impl IntoIterator<Item = &'a LatencyEvent>) {
+        let Some(histograms) = &self.histograms else {
+            return;
+        };
+
+        let mut pending = histograms.lock().unwrap_or_else(|e| e.into_inner());
+        let period_secs = pending.period.num_seconds();
+        for event in events
+            .into_iter()
+            .filter(|event| event.kind.carries_latency() && !event.is_test_data())
+        {
+            let timestamp = event.timestamp.timestamp();
+            let start = DateTime::from_timestamp(timestamp - timestamp.rem_euclid(period_secs), 0).unwrap_or_default();
+            pending
+                .by_period
+                .entry((start, event.component_type))
+                .or_default()
+                .record(event.duration_us());
+        }
+    }
+
+    /// Add the histograms recorded since the last flush to the stored row of
+    /// each period and component, then recount the periods that changed
+    /// underneath them.
+    async fn flush_histograms(&self) -> Result<()> {
+        let Some(histograms) = &self.histograms else {
+            return Ok(());
+        };
+
+        let (period, by_period) = {
+            let mut pending = histograms.lock().unwrap_or_else(|e| e.into_inner());
+            (pending.period, std::mem::take(&mut pending.by_period))
+        };
+        if by_period.is_empty() {
+            return self.rebuild_dirty_histograms().await;
+        }
+
+        let result = self.insert_histograms(period, &by_period).await;
+        if result.is_err() {
+            // Keep the counts for the next attempt rather than losing them
+            let mut pending = histograms.lock().unwrap_or_else(|e| e.into_inner());
+            for (key, histogram) in by_period {
+                pending.by_period.entry(key).or_default().merge(&histogram);
+            }
+            return result;
+        }
+        self.rebuild_dirty_histograms().await
+    }
+
+    /// Recount the stored histograms of hours with deleted or restored
+    /// events from the live raw events. Periods starting before the oldest
+    /// live event may have lost events to retention, so they keep their
+    /// counts.
+    async fn rebuild_dirty_histograms(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let periods = sqlx::query(
+            r#"
+            SELECT DISTINCT h.id, h.timestamp, h.period_end, h.component_type
+            FROM latency_histograms h
+            JOIN dirty_histogram_hours d ON d.component_type = h.component_type
+            WHERE substr(h.timestamp, 1, 13) <= d.hour AND substr(h.period_end, 1, 13) >= d.hour
+              AND h.timestamp >= (SELECT MIN(timestamp) FROM latency_events WHERE deleted_at IS NULL)
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let durations_sql = format!(
+            "SELECT duration_us FROM latency_events \
+             WHERE component_type = ? AND timestamp >= ? AND timestamp < ? \
+             AND event_kind IN ({}) AND NOT {} AND deleted_at IS NULL",
+            latency_kinds_sql(),
+            TEST_DATA_SQL
+        );
+        for period in &periods {
+            let durations: Vec<i64> = sqlx::query_scalar(&durations_sql)
+                .bind(period.get::<String, _>("component_type"))
+                .bind(period.get::<String, _>("timestamp"))
+                .bind(period.get::<String, _>("period_end"))
+                .fetch_all(&mut *tx)
+                .await?;
+            let mut histogram = HdrHistogram::default();
+            for duration_us in durations {
+                histogram.record(duration_us.max(0) as u64);
+            }
+
+            sqlx::query("UPDATE latency_histograms SET event_count = ?, max_us = ?, buckets = ? WHERE id = ?")
+                .bind(histogram.count() as i64)
+                .bind(histogram.max_us() as i64)
+                .bind(serde_json::to_string(histogram.buckets())?)
+                .bind(period.get::<i64, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query("DELETE FROM dirty_histogram_hours").execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        if !periods.is_empty() {
+            debug!("Recounted {} latency histograms after deletions", periods.len());
+        }
+        Ok(())
+    }
+
+    async fn insert_histograms(
+        &self,
+        period: chrono::Duration,
+        by_period: &HashMap<(DateTime<Utc>, ComponentType), HdrHistogram>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for ((start, component), histogram) in by_period {
+            let stored = sqlx::query(
+                "SELECT id, max_us, buckets FROM latency_histograms WHERE timestamp = ? AND component_type = ?",
+            )
+            .bind(start.to_rfc3339())
+            .bind(component.as_str())
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let Some(stored) = stored else {
+                sqlx::query(
+                    r#"
+                    INSERT INTO latency_histograms
+                    (timestamp, period_end, component_type, event_count, max_us, buckets)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(start.to_rfc3339())
+                .bind((*start + period).to_rfc3339())
+                .bind(component.as_str())
+                .bind(histogram.count() as i64)
+                .bind(histogram.max_us() as i64)
+                .bind(serde_json::to_string(histogram.buckets())?)
+                .execute(&mut *tx)
+                .await?;
+                continue;
+            };
+
+            let buckets: BTreeMap<u32, u64> = serde_json::from_str(&stored.get::<String, _>("buckets"))?;
+            let mut merged = HdrHistogram::from_buckets(buckets, stored.get::<i64, _>("max_us").max(0) as u64);
+            merged.merge(histogram);
+            sqlx::query("UPDATE latency_histograms SET event_count = ?, max_us = ?, buckets = ? WHERE id = ?")
+                .bind(merged.count() as i64)
+                .bind(merged.max_us() as i64)
+                .bind(serde_json::to_string(merged.buckets())?)
+                .bind(stored.get::<i64, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        debug!("Stored latency histograms for {} periods", by_period.len());
+        Ok(())
+    }
+
+    /// Merged histograms per component of the flush periods starting in
+    /// `range`. Filters and working hours can't be applied to stored
+    /// histograms, and they never hold test data, so those ranges give an
+    /// empty map.
+    async fn get_stored_histograms(&self, range: &TimeRange) -> Result<HashMap<String, HdrHistogram>> {
+        let mut merged: HashMap<String, HdrHistogram> = HashMap::new();
+        if range.filter.is_some() || range.working_hours.is_some() || range.include_test_data {
+            return Ok(merged);
+        }
+
+        // latency_histograms has only the time to filter by
+        let window = range.bounds_sql();
+        let sql = format!(
+            "SELECT component_type, max_us, buckets FROM latency_histograms WHERE {}",
+            window.clause
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        for row in rows {
+            let buckets: BTreeMap<u32, u64> = serde_json::from_str(&row.get::<String, _>("buckets"))?;
+            let histogram = HdrHistogram::from_buckets(buckets, row.get::<i64, _>("max_us").max(0) as u64);
+            merged.entry(row.get("component_type")).or_default().merge(&histogram);
+        }
+        Ok(merged)
+    }
+
     async fn initialize_schema(&self) -> Result<()> {
         // Create tables for latency events
         sqlx::query(
@@ -37,9 +398,12 @@ impl MetricsStorage {
                 timestamp TEXT NOT NULL,
                 component_type TEXT NOT NULL,
                 event_source TEXT NOT NULL,
+                event_kind TEXT,
                 duration_us INTEGER NOT NULL,
                 description TEXT NOT NULL,
                 metadata TEXT,
+                uid TEXT,
+                deleted_at TEXT,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )
             "#,
@@ -66,260 +430,3844 @@ impl MetricsStorage {
         .execute(&self.pool)
         .await?;
 
-        // Create performance metrics table
+        self.migrate_enum_names().await?;
+        self.migrate_event_kind().await?;
+        self.migrate_event_uid().await?;
+        self.migrate_deleted_at().await?;
+        self.migrate_metadata_columns().await?;
+
+        // Free-form key/value tags attached to events
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS performance_metrics (
+            CREATE TABLE IF NOT EXISTS event_tags (
+                event_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (event_id, key)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_event_tags_key_value
+            ON event_tags(key, value)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS saved_queries (
+                name TEXT PRIMARY KEY,
+                filter TEXT NOT NULL,
+                result_limit INTEGER,
+                description TEXT,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Idle sampling noise measured by `calibrate`, newest last
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS noise_profiles (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
-                component TEXT NOT NULL,
-                total_events INTEGER NOT NULL,
-                avg_duration_ms REAL NOT NULL,
-                min_duration_ms INTEGER NOT NULL,
-                max_duration_ms INTEGER NOT NULL,
-                p50_duration_ms INTEGER NOT NULL,
-                p95_duration_ms INTEGER NOT NULL,
-                p99_duration_ms INTEGER NOT NULL,
-                events_per_second REAL NOT NULL,
-                error_rate REAL NOT NULL,
-                last_updated TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                measured_at TEXT NOT NULL,
+                profile TEXT NOT NULL
             )
             "#,
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(())
-    }
+        // Periods during which alert notifications are held back
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS maintenance_windows (
+                name TEXT PRIMARY KEY,
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                recurrence TEXT NOT NULL,
+                rules TEXT NOT NULL,
+                reason TEXT,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn store_event(&self, event: &LatencyEvent) -> Result<()> {
-        let metadata_json = serde_json::to_string(&event.metadata)?;
-        
+        // Every alert state change, including suppressed ones
         sqlx::query(
             r#"
-            INSERT INTO latency_events 
-            (timestamp, component_type, event_source, duration_us, description, metadata)
-            VALUES (?, ?, ?, ?, ?, ?)
+            CREATE TABLE IF NOT EXISTS alert_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                at TEXT NOT NULL,
+                rule TEXT NOT NULL,
+                state TEXT NOT NULL,
+                value REAL NOT NULL,
+                threshold REAL NOT NULL,
+                message TEXT NOT NULL,
+                suppressed_by TEXT
+            )
             "#,
         )
-        .bind(event.timestamp.to_rfc3339())
-        .bind(format!("{:?}", event.component_type))
-        .bind(format!("{:?}", event.event_source))
-        .bind(event.duration_us() as i64)
-        .bind(&event.description)
-        .bind(metadata_json)
         .execute(&self.pool)
         .await?;
 
-        debug!("Stored latency event: {:?}", event.component_type);
-        Ok(())
-    }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_alert_history_at ON alert_history(at)")
+            .execute(&self.pool)
+            .await?;
 
-    pub async fn get_recent_events(&self, limit: u32) -> Result<Vec<LatencyEvent>> {
-        let rows = sqlx::query(
+        // Values of the `[derived_metrics]` expressions over time
+        sqlx::query(
             r#"
-            SELECT id, timestamp, component_type, event_source, duration_us, description, metadata
-            FROM latency_events 
-            ORDER BY timestamp DESC 
-            LIMIT ?
+            CREATE TABLE IF NOT EXISTS derived_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                value REAL NOT NULL,
+                at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(limit)
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await?;
 
-        let mut events = Vec::new();
-        for row in rows {
-            let timestamp_str: String = row.get("timestamp");
-            let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)?
-                .with_timezone(&Utc);
-            
-            let duration_us: i64 = row.get("duration_us");
-            let duration = std::time::Duration::from_micros(duration_us as u64);
-            
-            let component_type_str: String = row.get("component_type");
-            let _event_source_str: String = row.get("event_source");
-            let metadata_str: String = row.get("metadata");
-            
-            // Parse component type (simplified)
-            let component_type = match component_type_str.as_str() {
-                "VSCode" => ComponentType::VSCode,
-                "VSCodeExtension" => ComponentType::VSCodeExtension,
-                "GitHubCopilot" => ComponentType::GitHubCopilot,
-                "LocalModel" => ComponentType::LocalModel,
-                "Terminal" => ComponentType::Terminal,
-                "FileSystem" => ComponentType::FileSystem,
-                "Network" => ComponentType::Network,
-                _ => ComponentType::System,
-            };
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_derived_metrics_name_at ON derived_metrics(name, at)")
+            .execute(&self.pool)
+            .await?;
 
-            // Parse event source (simplified)
-            let event_source = crate::models::EventSource::ProcessMonitor; // Default
+        // Output throughput of streamed model responses
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                source TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                estimated_tokens INTEGER NOT NULL,
+                reported_tokens INTEGER,
+                generation_time_ms INTEGER NOT NULL,
+                time_to_first_token_ms INTEGER,
+                tokens_per_second REAL,
+                prompt_length INTEGER NOT NULL,
+                response_length INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            let metadata: serde_json::Value = serde_json::from_str(&metadata_str)
-                .unwrap_or(serde_json::Value::Null);
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_token_metrics_timestamp ON token_metrics(timestamp)")
+            .execute(&self.pool)
+            .await?;
 
-            let event = LatencyEvent {
-                id: Some(row.get("id")),
-                timestamp,
-                component_type,
-                event_source,
-                duration,
-                description: row.get("description"),
-                metadata,
-            };
+        // Per-source high-water marks for `sync`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_watermarks (
+                source TEXT PRIMARY KEY,
+                last_timestamp TEXT NOT NULL,
+                last_id INTEGER NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            events.push(event);
-        }
+        // Deletions and restores still to be copied to the central backend,
+        // logged only once replication has started
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS pending_deletions (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                uid TEXT NOT NULL,
+                deleted_at TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(events)
-    }
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS log_pending_deletion AFTER UPDATE OF deleted_at ON latency_events
+            WHEN NEW.uid IS NOT NULL
+             AND OLD.deleted_at IS NOT NEW.deleted_at
+             AND EXISTS (SELECT 1 FROM sync_watermarks WHERE source LIKE 'backend:%')
+            BEGIN
+                INSERT INTO pending_deletions (uid, deleted_at) VALUES (NEW.uid, NEW.deleted_at);
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_performance_metrics(&self) -> Result<Vec<PerformanceMetrics>> {
-        let rows = sqlx::query(
+        // Append-only record of administrative actions; rows are never updated
+        sqlx::query(
             r#"
-            SELECT 
-                component_type,
-                COUNT(*) as total_events,
-                AVG(duration_us) / 1000.0 as avg_duration_ms,
-                MIN(duration_us) / 1000 as min_duration_ms,
-                MAX(duration_us) / 1000 as max_duration_ms
-            FROM latency_events 
-            WHERE timestamp > datetime('now', '-1 hour')
-            GROUP BY component_type
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                target TEXT,
+                before_value TEXT,
+                after_value TEXT
+            )
             "#,
         )
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await?;
 
-        let mut metrics = Vec::new();
-        for row in rows {
-            let component_type_str: String = row.get("component_type");
-            let component_type = match component_type_str.as_str() {
-                "VSCode" => ComponentType::VSCode,
-                "VSCodeExtension" => ComponentType::VSCodeExtension,
-                "GitHubCopilot" => ComponentType::GitHubCopilot,
-                "LocalModel" => ComponentType::LocalModel,
-                "Terminal" => ComponentType::Terminal,
-                "FileSystem" => ComponentType::FileSystem,
-                "Network" => ComponentType::Network,
-                _ => ComponentType::System,
-            };
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp
+            ON audit_log(timestamp)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            let metric = PerformanceMetrics {
-                component: component_type,
-                total_events: row.get::<i64, _>("total_events") as u64,
-                avg_duration_ms: row.get("avg_duration_ms"),
-                min_duration_ms: row.get::<i64, _>("min_duration_ms") as u64,
-                max_duration_ms: row.get::<i64, _>("max_duration_ms") as u64,
-                p50_duration_ms: 0, // TODO: Calculate percentiles
-                p95_duration_ms: 0,
-                p99_duration_ms: 0,
-                events_per_second: 0.0, // TODO: Calculate
-                error_rate: 0.0,
-                last_updated: Utc::now(),
-            };
+        // API bearer tokens, stored by hash only
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                name TEXT PRIMARY KEY,
+                token_hash TEXT NOT NULL UNIQUE,
+                scopes TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                last_used_at TEXT,
+                tenant TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            metrics.push(metric);
+        // Tokens created before tenancy existed belong to this database
+        if !self.column_exists("api_tokens", "tenant").await? {
+            sqlx::query("ALTER TABLE api_tokens ADD COLUMN tenant TEXT")
+                .execute(&self.pool)
+                .await?;
         }
 
-        Ok(metrics)
-    }
-
-    pub async fn get_system_status(&self) -> Result<SystemStatus> {
-        let total_events: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM latency_events"
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                delivery_id TEXT NOT NULL,
+                webhook TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                attempt INTEGER NOT NULL,
+                status_code INTEGER,
+                error TEXT,
+                duration_ms INTEGER NOT NULL,
+                attempted_at TEXT NOT NULL
+            )
+            "#,
         )
-        .fetch_one(&self.pool)
+        .execute(&self.pool)
         .await?;
 
-        let last_event_row = sqlx::query(
-            "SELECT timestamp FROM latency_events ORDER BY timestamp DESC LIMIT 1"
+        // Per-component HDR histograms of the events whose timestamps fall
+        // in each flush-interval-long period (`timestamp` is the period
+        // start); like daily summaries they outlive retention
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS latency_histograms (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                period_end TEXT NOT NULL,
+                component_type TEXT NOT NULL,
+                event_count INTEGER NOT NULL,
+                max_us INTEGER NOT NULL,
+                buckets TEXT NOT NULL
+            )
+            "#,
         )
-        .fetch_optional(&self.pool)
+        .execute(&self.pool)
         .await?;
 
-        let last_event_timestamp = if let Some(row) = last_event_row {
-            let timestamp_str: String = row.get("timestamp");
-            Some(DateTime::parse_from_rfc3339(&timestamp_str)?.with_timezone(&Utc))
-        } else {
-            None
-        };
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_histograms_timestamp ON latency_histograms(timestamp)")
+            .execute(&self.pool)
+            .await?;
 
-        let performance_metrics = self.get_performance_metrics().await?;
+        // Per-day latency aggregates; they outlive the raw events they were
+        // computed from, so long-term views survive retention
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS daily_summaries (
+                day TEXT NOT NULL,
+                component_type TEXT NOT NULL,
+                event_count INTEGER NOT NULL,
+                avg_us REAL NOT NULL,
+                p95_us INTEGER NOT NULL,
+                max_us INTEGER NOT NULL,
+                PRIMARY KEY (day, component_type)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        let status = SystemStatus {
-            summary: "System operational".to_string(),
-            total_events: total_events as u64,
-            active_monitors: vec![
-                "VS Code Monitor".to_string(),
-                "Model Monitor".to_string(),
-                "Terminal Monitor".to_string(),
-            ],
-            performance_metrics,
-            last_event_timestamp,
-            uptime_seconds: 0, // TODO: Track uptime
-            memory_usage_mb: 0, // TODO: Get actual memory usage
-            cpu_usage_percent: 0.0, // TODO: Get actual CPU usage
-        };
+        // Hourly inline suggestion counts per extension and latency band,
+        // kept like daily_summaries so acceptance history survives retention
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS suggestion_hours (
+                hour TEXT NOT NULL,
+                extension TEXT NOT NULL,
+                band INTEGER NOT NULL,
+                shown INTEGER NOT NULL,
+                accepted INTEGER NOT NULL,
+                rejected INTEGER NOT NULL,
+                latency_us_total INTEGER NOT NULL,
+                accepted_latency_us_total INTEGER NOT NULL,
+                PRIMARY KEY (hour, extension, band)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(status)
+        // Days whose summary is stale because events were recorded, deleted
+        // or restored after the day was summarized
+        sqlx::query("CREATE TABLE IF NOT EXISTS dirty_summary_days (day TEXT PRIMARY KEY)")
+            .execute(&self.pool)
+            .await?;
+        for (name, event) in [
+            ("mark_summary_day_inserted", "INSERT ON latency_events"),
+            ("mark_summary_day_deleted", "UPDATE OF deleted_at ON latency_events"),
+        ] {
+            sqlx::query(&format!(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS {name} AFTER {event}
+                WHEN substr(NEW.timestamp, 1, 10) <= (SELECT MAX(day) FROM daily_summaries)
+                BEGIN
+                    INSERT OR IGNORE INTO dirty_summary_days (day) VALUES (substr(NEW.timestamp, 1, 10));
+                END
+                "#
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        // Hours whose stored histograms count events deleted or restored
+        // since they were flushed
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS dirty_histogram_hours \
+             (hour TEXT NOT NULL, component_type TEXT NOT NULL, PRIMARY KEY (hour, component_type))",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS mark_histogram_hour_deleted AFTER UPDATE OF deleted_at ON latency_events
+            WHEN OLD.deleted_at IS NOT NEW.deleted_at AND EXISTS (SELECT 1 FROM latency_histograms)
+            BEGIN
+                INSERT OR IGNORE INTO dirty_histogram_hours (hour, component_type)
+                VALUES (substr(NEW.timestamp, 1, 13), NEW.component_type);
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Hours whose suggestion counts are stale, marked the same way
+        sqlx::query("CREATE TABLE IF NOT EXISTS dirty_suggestion_hours (hour TEXT PRIMARY KEY)")
+            .execute(&self.pool)
+            .await?;
+        for (name, event) in [
+            ("mark_suggestion_hour_inserted", "INSERT ON latency_events"),
+            ("mark_suggestion_hour_deleted", "UPDATE OF deleted_at ON latency_events"),
+        ] {
+            sqlx::query(&format!(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS {name} AFTER {event}
+                WHEN NEW.suggestion IS NOT NULL
+                 AND substr(NEW.timestamp, 1, 13) <= (SELECT MAX(hour) FROM suggestion_hours)
+                BEGIN
+                    INSERT OR IGNORE INTO dirty_suggestion_hours (hour) VALUES (substr(NEW.timestamp, 1, 13));
+                END
+                "#
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_latency_events_suggestion ON latency_events(suggestion) \
+             WHERE suggestion IS NOT NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Accepted and rejected suggestions used to be stored as annotations
+        let migrated = sqlx::query(
+            "UPDATE latency_events SET event_kind = 'suggestion_outcome' \
+             WHERE event_kind = 'annotation' AND suggestion IS NOT NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+        if migrated.rows_affected() > 0 {
+            info!("Reclassified {} suggestion outcomes", migrated.rows_affected());
+        }
+
+        // Extension CPU shares used to be stored as process samples
+        let migrated = sqlx::query(
+            "UPDATE latency_events SET event_kind = 'attribution' \
+             WHERE event_kind = 'process_sample' AND component_type = 'VSCodeExtension' \
+             AND json_valid(metadata) AND json_extract(metadata, '$.attribution') IS NOT NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+        if migrated.rows_affected() > 0 {
+            info!("Reclassified {} extension CPU attributions", migrated.rows_affected());
+        }
+
+        // Create performance metrics table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS performance_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                component TEXT NOT NULL,
+                total_events INTEGER NOT NULL,
+                avg_duration_ms REAL NOT NULL,
+                min_duration_ms INTEGER NOT NULL,
+                max_duration_ms INTEGER NOT NULL,
+                p50_duration_ms INTEGER NOT NULL,
+                p95_duration_ms INTEGER NOT NULL,
+                p99_duration_ms INTEGER NOT NULL,
+                events_per_second REAL NOT NULL,
+                error_rate REAL NOT NULL,
+                last_updated TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
     }
 
-    pub async fn generate_report(&self, _since: &str, format: &str) -> Result<String> {
-        match format {
-            "json" => {
-                let events = self.get_recent_events(100).await?;
-                let json = serde_json::to_string_pretty(&events)?;
-                Ok(json)
+    async fn column_exists(&self, table: &str, column: &str) -> Result<bool> {
+        // table_xinfo also lists generated columns
+        let rows = sqlx::query(&format!("PRAGMA table_xinfo({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().any(|row| row.get::<String, _>("name") == column))
+    }
+
+    /// Rewrite `component_type` and `event_source` values that aren't the
+    /// canonical stored names (display labels or other spellings written by
+    /// older versions or external tools) so filters and grouping match them.
+    async fn migrate_enum_names(&self) -> Result<()> {
+        let columns: [(&str, CanonicalName); 2] = [
+            ("component_type", |value| ComponentType::parse(value).map(|c| c.as_str())),
+            ("event_source", |value| EventSource::parse(value).map(|s| s.as_str())),
+        ];
+
+        for (column, canonical) in columns {
+            let values: Vec<String> = sqlx::query_scalar(&format!("SELECT DISTINCT {} FROM latency_events", column))
+                .fetch_all(&self.pool)
+                .await?;
+
+            for value in values {
+                // Unknown values are kept; reads map them to a default
+                let Some(name) = canonical(&value).filter(|name| *name != value) else {
+                    continue;
+                };
+                let updated = sqlx::query(&format!("UPDATE latency_events SET {0} = ? WHERE {0} = ?", column))
+                    .bind(name)
+                    .bind(&value)
+                    .execute(&self.pool)
+                    .await?;
+                info!(
+                    "Normalized {} '{}' to '{}' in {} events",
+                    column,
+                    value,
+                    name,
+                    updated.rows_affected()
+                );
             }
-            "csv" => {
-                let events = self.get_recent_events(100).await?;
-                let mut csv = String::from("timestamp,component,duration_ms,description\n");
-                
+        }
+
+        Ok(())
+    }
+
+    /// Add the `event_kind` column to older databases and classify existing
+    /// rows from their source and description.
+    async fn migrate_event_kind(&self) -> Result<()> {
+        if !self.column_exists("latency_events", "event_kind").await? {
+            sqlx::query("ALTER TABLE latency_events ADD COLUMN event_kind TEXT")
+                .execute(&self.pool)
+                .await?;
+            info!("Added event_kind column to latency_events");
+        }
+
+        let migrated = sqlx::query(
+            r#"
+            UPDATE latency_events SET event_kind = CASE
+                WHEN json_valid(metadata) AND json_extract(metadata, '$.lifecycle') IS NOT NULL THEN 'lifecycle'
+                WHEN description LIKE 'Test model interaction%' THEN 'model_request'
+                WHEN event_source IN ('CommandExecution', 'TestCommand', 'FileOperation', 'UserInteraction') THEN 'command_run'
+                WHEN event_source = 'NetworkRequest' THEN 'probe'
+                ELSE 'process_sample'
+            END
+            WHERE event_kind IS NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if migrated.rows_affected() > 0 {
+            info!("Classified {} existing events by kind", migrated.rows_affected());
+        }
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_latency_events_kind
+            ON latency_events(event_kind)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Add the `deleted_at` column marking soft-deleted events, which every
+    /// query leaves out until they are restored or purged.
+    async fn migrate_deleted_at(&self) -> Result<()> {
+        if !self.column_exists("latency_events", "deleted_at").await? {
+            sqlx::query("ALTER TABLE latency_events ADD COLUMN deleted_at TEXT")
+                .execute(&self.pool)
+                .await?;
+            info!("Added deleted_at column to latency_events");
+        }
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_latency_events_deleted_at
+            ON latency_events(deleted_at) WHERE deleted_at IS NOT NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Expose `METADATA_COLUMNS` as virtual generated columns. They are
+    /// computed on read, so existing rows need no backfill.
+    async fn migrate_metadata_columns(&self) -> Result<()> {
+        for column in METADATA_COLUMNS {
+            if self.column_exists("latency_events", column.name).await? {
+                continue;
+            }
+            sqlx::query(&format!(
+                "ALTER TABLE latency_events ADD COLUMN {} {} GENERATED ALWAYS AS \
+                 (CASE WHEN json_valid(metadata) THEN json_extract(metadata, '$.{}') END) VIRTUAL",
+                column.name, column.sql_type, column.key
+            ))
+            .execute(&self.pool)
+            .await?;
+            info!("Added {} column to latency_events", column.name);
+        }
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_events_pid ON latency_events(pid)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_events_trace_id ON latency_events(trace_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_latency_events_workspace ON latency_events(workspace)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Add the `uid` column used to deduplicate synced events and backfill
+    /// it for existing rows.
+    async fn migrate_event_uid(&self) -> Result<()> {
+        if !self.column_exists("latency_events", "uid").await? {
+            sqlx::query("ALTER TABLE latency_events ADD COLUMN uid TEXT")
+                .execute(&self.pool)
+                .await?;
+            info!("Added uid column to latency_events");
+        }
+
+        let rows = sqlx::query(
+            "SELECT id, timestamp, component_type, duration_us, description FROM latency_events WHERE uid IS NULL ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        if !rows.is_empty() {
+            let mut seen = HashSet::new();
+            let mut tx = self.pool.begin().await?;
+            for row in &rows {
+                let id: i64 = row.get("id");
+                let mut uid = uid_for(
+                    row.get("timestamp"),
+                    row.get("component_type"),
+                    row.get("duration_us"),
+                    row.get("description"),
+                );
+                // Identical legacy rows keep distinct uids so the unique index holds
+                if !seen.insert(uid.clone()) {
+                    uid = format!("{}-{}", uid, id);
+                }
+                sqlx::query("UPDATE latency_events SET uid = ? WHERE id = ?")
+                    .bind(uid)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            info!("Assigned uids to {} existing events", rows.len());
+        }
+
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_latency_events_uid
+            ON latency_events(uid)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn store_event(&self, event: &LatencyEvent) -> Result<()> {
+        let tagged = self.connectivity.as_ref().and_then(|tracker| tracker.tag(event));
+        let event = tagged.as_ref().unwrap_or(event);
+        if let Some(writer) = &self.writer {
+            return writer
+                .send(WriteCommand::Event(event.clone()))
+                .await
+                .map_err(|_| anyhow!("Event writer has stopped"));
+        }
+
+        let metadata_json = serde_json::to_string(&event.metadata)?;
+        
+        let result = sqlx::query(
+            r#"
+            INSERT INTO latency_events 
+            (timestamp, component_type, event_source, event_kind, duration_us, description, metadata, uid)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event.timestamp.to_rfc3339())
+        .bind(event.component_type.as_str())
+        .bind(event.event_source.as_str())
+        .bind(event.kind.as_str())
+        .bind(event.duration_us() as i64)
+        .bind(&event.description)
+        .bind(metadata_json)
+        .bind(event_uid(event))
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Stored latency event: {:?}", event.component_type);
+
+        let mut stored = event.clone();
+        stored.id = Some(result.last_insert_rowid());
+        self.record_histograms([&stored]);
+        self.publish(stored);
+
+        Ok(())
+    }
+
+    /// Insert a batch in one transaction, then publish what was stored.
+    /// Duplicate events (same uid) are skipped. If the transaction fails the
+    /// events are retried one by one so a single bad row can't drop the rest.
+    async fn store_batch(&self, events: &[LatencyEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let stored = match self.insert_batch(events).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Batch insert of {} events failed ({}); retrying individually", events.len(), e);
+                let mut stored = Vec::new();
                 for event in events {
-                    csv.push_str(&format!(
-                        "{},{},{},{}\n",
-                        event.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                        event.component_type,
-                        event.duration_ms(),
-                        event.description.replace(',', ";")
-                    ));
+                    match self.insert_batch(std::slice::from_ref(event)).await {
+                        Ok(mut inserted) => stored.append(&mut inserted),
+                        Err(e) => warn!("Dropping event that failed to store: {}", e),
+                    }
                 }
-                
-                Ok(csv)
+                stored
             }
-            _ => Err(anyhow::anyhow!("Unsupported format: {}", format)),
+        };
+
+        debug!("Flushed {} of {} buffered events", stored.len(), events.len());
+        self.record_histograms(&stored);
+        for event in stored {
+            self.publish(event);
         }
+        Ok(())
     }
 
-    pub async fn export_metrics(&self, format: &str, _since: Option<String>) -> Result<Vec<u8>> {
-        match format {
-            "json" => {
-                let events = self.get_recent_events(1000).await?;
-                let json = serde_json::to_string(&events)?;
-                Ok(json.into_bytes())
+    async fn insert_batch(&self, events: &[LatencyEvent]) -> Result<Vec<LatencyEvent>> {
+        let mut tx = self.pool.begin().await?;
+        let mut stored = Vec::with_capacity(events.len());
+
+        for event in events {
+            let result = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO latency_events
+                (timestamp, component_type, event_source, event_kind, duration_us, description, metadata, uid)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(event.timestamp.to_rfc3339())
+            .bind(event.component_type.as_str())
+            .bind(event.event_source.as_str())
+            .bind(event.kind.as_str())
+            .bind(event.duration_us() as i64)
+            .bind(&event.description)
+            .bind(serde_json::to_string(&event.metadata)?)
+            .bind(event_uid(event))
+            .execute(&mut *tx)
+            .await?;
+
+            if result.rows_affected() > 0 {
+                let mut inserted = event.clone();
+                inserted.id = Some(result.last_insert_rowid());
+                stored.push(inserted);
+            }
+        }
+
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    /// Subscribe to events as they are stored (or published by a tailer).
+    pub fn subscribe(&self) -> broadcast::Receiver<LatencyEvent> {
+        self.live_events.subscribe()
+    }
+
+    pub fn publish(&self, event: LatencyEvent) {
+        // No subscribers is the common case; nothing to do
+        let _ = self.live_events.send(event);
+    }
+
+    /// Events with an id greater than `after_id`, oldest first. Used to tail
+    /// rows written by other processes sharing the database.
+    pub async fn get_events_after(&self, after_id: i64, limit: u32) -> Result<Vec<LatencyEvent>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, uid, timestamp, component_type, event_source, event_kind, duration_us, description, metadata
+            FROM latency_events
+            WHERE id > ? AND deleted_at IS NULL
+            ORDER BY id
+            LIMIT ?
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(event_from_row).collect()
+    }
+
+    /// Events ordered after the `(timestamp, id)` watermark, oldest first.
+    pub async fn get_events_after_watermark(
+        &self,
+        after_timestamp: &str,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<SyncedEvent>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, uid, timestamp, component_type, event_source, event_kind, duration_us, description, metadata
+            FROM latency_events
+            WHERE (timestamp > ? OR (timestamp = ? AND id > ?)) AND deleted_at IS NULL
+            ORDER BY timestamp, id
+            LIMIT ?
+            "#,
+        )
+        .bind(after_timestamp)
+        .bind(after_timestamp)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut events = rows
+            .iter()
+            .map(|row| {
+                Ok(SyncedEvent {
+                    event: event_from_row(row)?,
+                    tags: BTreeMap::new(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if events.is_empty() {
+            return Ok(events);
+        }
+
+        let index: HashMap<i64, usize> = events
+            .iter()
+            .enumerate()
+            .filter_map(|(i, synced)| synced.event.id.map(|id| (id, i)))
+            .collect();
+        let placeholders = vec!["?"; index.len()].join(", ");
+        let select_tags = format!(
+            "SELECT event_id, key, value FROM event_tags WHERE event_id IN ({})",
+            placeholders
+        );
+        let mut query = sqlx::query(&select_tags);
+        for id in index.keys() {
+            query = query.bind(id);
+        }
+        for row in query.fetch_all(&self.pool).await? {
+            let id: i64 = row.get("event_id");
+            if let Some(&i) = index.get(&id) {
+                events[i].tags.insert(row.get("key"), row.get("value"));
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Insert events from another instance, skipping any whose uid is already
+    /// present. Returns the number of rows actually inserted.
+    pub async fn import_events(&self, events: &[LatencyEvent]) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.pool.begin().await?;
+
+        for event in events {
+            if insert_imported_event(&mut tx, event).await?.is_some() {
+                inserted += 1;
             }
-            "sqlite" => {
-                // For SQLite export, we could copy the database file
-                // For now, return a simple message
-                Ok("SQLite export not yet implemented".into())
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    /// Like [`import_events`](Self::import_events), also copying the tags
+    /// of each newly inserted event.
+    pub async fn import_synced_events(&self, events: &[SyncedEvent]) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.pool.begin().await?;
+
+        for synced in events {
+            let Some(id) = insert_imported_event(&mut tx, &synced.event).await? else {
+                continue;
+            };
+            for (key, value) in &synced.tags {
+                sqlx::query("INSERT OR REPLACE INTO event_tags (event_id, key, value) VALUES (?, ?, ?)")
+                    .bind(id)
+                    .bind(key)
+                    .bind(value)
+                    .execute(&mut *tx)
+                    .await?;
             }
-            _ => Err(anyhow::anyhow!("Unsupported export format: {}", format)),
+            inserted += 1;
         }
+
+        tx.commit().await?;
+        Ok(inserted)
     }
 
-    pub async fn cleanup_old_events(&self, retention_days: u32) -> Result<()> {
-        let cutoff_date = Utc::now() - chrono::Duration::days(retention_days as i64);
-        
-        let deleted = sqlx::query(
-            "DELETE FROM latency_events WHERE timestamp < ?"
+    pub async fn get_sync_watermark(&self, source: &str) -> Result<Option<(String, i64)>> {
+        let row = sqlx::query("SELECT last_timestamp, last_id FROM sync_watermarks WHERE source = ?")
+            .bind(source)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| (row.get("last_timestamp"), row.get("last_id"))))
+    }
+
+    pub async fn set_sync_watermark(&self, source: &str, last_timestamp: &str, last_id: i64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sync_watermarks (source, last_timestamp, last_id, updated_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(source) DO UPDATE SET
+                last_timestamp = excluded.last_timestamp,
+                last_id = excluded.last_id,
+                updated_at = excluded.updated_at
+            "#,
         )
-        .bind(cutoff_date.to_rfc3339())
+        .bind(source)
+        .bind(last_timestamp)
+        .bind(last_id)
+        .bind(Utc::now().to_rfc3339())
         .execute(&self.pool)
         .await?;
 
-        info!("Cleaned up {} old events", deleted.rows_affected());
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Deletions and restores not yet copied to the central backend, oldest
+    /// first, with the sequence number to pass to `clear_pending_deletions`.
+    pub async fn get_pending_deletions(&self, limit: u32) -> Result<Vec<(i64, EventDeletion)>> {
+        let rows = sqlx::query("SELECT seq, uid, deleted_at FROM pending_deletions ORDER BY seq LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let deleted_at = row
+                    .get::<Option<String>, _>("deleted_at")
+                    .map(|value| DateTime::parse_from_rfc3339(&value).map(|at| at.with_timezone(&Utc)))
+                    .transpose()?;
+                Ok((row.get("seq"), EventDeletion { uid: row.get("uid"), deleted_at }))
+            })
+            .collect()
+    }
+
+    pub async fn clear_pending_deletions(&self, through_seq: i64) -> Result<()> {
+        sqlx::query("DELETE FROM pending_deletions WHERE seq <= ?")
+            .bind(through_seq)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Apply deletions and restores replicated from another database by
+    /// uid. Returns how many events changed.
+    pub async fn apply_deletions(&self, deletions: &[EventDeletion]) -> Result<u64> {
+        let mut changed = 0;
+        let mut tx = self.pool.begin().await?;
+        for deletion in deletions {
+            changed += sqlx::query("UPDATE latency_events SET deleted_at = ? WHERE uid = ? AND deleted_at IS NOT ?")
+                .bind(deletion.deleted_at.map(|at| at.to_rfc3339()))
+                .bind(&deletion.uid)
+                .bind(deletion.deleted_at.map(|at| at.to_rfc3339()))
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(changed)
+    }
+
+    pub async fn get_max_event_id(&self) -> Result<i64> {
+        let max_id: Option<i64> = sqlx::query_scalar("SELECT MAX(id) FROM latency_events")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(max_id.unwrap_or(0))
+    }
+
+    pub async fn get_recent_events(&self, limit: u32) -> Result<Vec<LatencyEvent>> {
+        self.query_events(None, limit).await
+    }
+
+    /// Most recent events matching an optional filter expression.
+    pub async fn query_events(&self, filter: Option<&SqlFilter>, limit: u32) -> Result<Vec<LatencyEvent>> {
+        self.select_events(filter, "timestamp DESC", limit).await
+    }
+
+    pub async fn get_event(&self, id: i64) -> Result<Option<LatencyEvent>> {
+        let filter = SqlFilter {
+            clause: "id = ?".to_string(),
+            binds: vec![FilterValue::Number(id as f64)],
+        };
+        Ok(self.select_events(Some(&filter), "id", 1).await?.into_iter().next())
+    }
+
+    /// The event stored under `uid`, whichever database it was created in.
+    pub async fn get_event_by_uid(&self, uid: &str) -> Result<Option<LatencyEvent>> {
+        let filter = SqlFilter {
+            clause: "uid = ?".to_string(),
+            binds: vec![FilterValue::Text(uid.to_string())],
+        };
+        Ok(self.select_events(Some(&filter), "id", 1).await?.into_iter().next())
+    }
+
+    /// Soft-delete one event; it keeps its tags and can be restored until
+    /// purged. Returns whether a live event was deleted.
+    pub async fn delete_event(&self, id: i64) -> Result<bool> {
+        let deleted = sqlx::query("UPDATE latency_events SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_event_tags(&self, id: i64) -> Result<BTreeMap<String, String>> {
+        let rows = sqlx::query("SELECT key, value FROM event_tags WHERE event_id = ? ORDER BY key")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("key"), row.get("value")))
+            .collect())
+    }
+
+    /// Up to `limit` events immediately before and after `event` in
+    /// (timestamp, id) order. The earlier events are returned newest first.
+    pub async fn get_neighboring_events(
+        &self,
+        event: &LatencyEvent,
+        limit: u32,
+    ) -> Result<(Vec<LatencyEvent>, Vec<LatencyEvent>)> {
+        let timestamp = event.timestamp.to_rfc3339();
+        let id = event.id.unwrap_or_default() as f64;
+        let binds = vec![
+            FilterValue::Text(timestamp.clone()),
+            FilterValue::Text(timestamp),
+            FilterValue::Number(id),
+        ];
+
+        let before = SqlFilter {
+            clause: "(timestamp < ? OR (timestamp = ? AND id < ?))".to_string(),
+            binds: binds.clone(),
+        };
+        let after = SqlFilter {
+            clause: "(timestamp > ? OR (timestamp = ? AND id > ?))".to_string(),
+            binds,
+        };
+
+        Ok((
+            self.select_events(Some(&before), "timestamp DESC, id DESC", limit).await?,
+            self.select_events(Some(&after), "timestamp, id", limit).await?,
+        ))
+    }
+
+    /// Process samples within `window` either side of `at`, oldest first.
+    pub async fn get_process_samples_near(
+        &self,
+        at: DateTime<Utc>,
+        window: chrono::Duration,
+        limit: u32,
+    ) -> Result<Vec<LatencyEvent>> {
+        let filter = SqlFilter {
+            clause: "event_kind = ?".to_string(),
+            binds: vec![FilterValue::Text(EventKind::ProcessSample.as_str().to_string())],
+        }
+        .and(
+            &TimeRange {
+                since: Some(at - window),
+                until: Some(at + window),
+                working_hours: None,
+                filter: None,
+                include_test_data: false,
+            }
+            .to_sql(),
+        );
+
+        self.select_events(Some(&filter), "timestamp, id", limit).await
+    }
+
+    /// Latency per component and per value of `group_by`, one of
+    /// [`group_by_columns`]; e.g. `vscode_build` compares stable and
+    /// Insiders side by side.
+    pub async fn get_grouped_metrics(&self, range: &TimeRange, group_by: &str) -> Result<Vec<GroupedMetrics>> {
+        if !group_by_columns().any(|column| column == group_by) {
+            return Err(anyhow!(
+                "Can't group by '{}' (expected one of: {})",
+                group_by,
+                group_by_columns().collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        let window = range.to_sql();
+        let sql = format!(
+            r#"
+            SELECT component_type, CAST({} AS TEXT) AS grp, duration_us
+            FROM latency_events
+            WHERE {} AND event_kind IN ({})
+            ORDER BY component_type, grp, duration_us
+            "#,
+            group_by,
+            window.clause,
+            latency_kinds_sql()
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut groups: BTreeMap<(String, Option<String>), Vec<u64>> = BTreeMap::new();
+        for row in &rows {
+            groups
+                .entry((row.get("component_type"), row.get("grp")))
+                .or_default()
+                .push(row.get::<i64, _>("duration_us") as u64);
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|((component, group), sorted)| GroupedMetrics {
+                group,
+                component: parse_component(&component),
+                total_events: sorted.len() as u64,
+                avg_duration_ms: sorted.iter().sum::<u64>() as f64 / sorted.len() as f64 / 1000.0,
+                p50_duration_ms: percentile(&sorted, 0.50) / 1000,
+                p95_duration_ms: percentile(&sorted, 0.95) / 1000,
+                max_duration_ms: sorted.last().copied().unwrap_or_default() / 1000,
+            })
+            .collect())
+    }
+
+    /// Extensions by the CPU attributed to them in `range`, heaviest first.
+    /// Samples are taken at a fixed interval, so the sum of their CPU
+    /// percentages ranks extensions by CPU time.
+    pub async fn get_extension_cpu(&self, range: &TimeRange, limit: u32) -> Result<Vec<ExtensionCpu>> {
+        let window = range.to_sql();
+        let sql = format!(
+            r#"
+            SELECT extension_id, COUNT(*) AS samples, AVG(cpu_percent) AS avg_cpu, MAX(cpu_percent) AS max_cpu,
+                   GROUP_CONCAT(DISTINCT json_extract(metadata, '$.attribution')) AS attribution
+            FROM latency_events
+            WHERE {} AND extension_id IS NOT NULL AND cpu_percent IS NOT NULL
+            GROUP BY extension_id
+            ORDER BY SUM(cpu_percent) DESC
+            LIMIT ?
+            "#,
+            window.clause
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ExtensionCpu {
+                extension_id: row.get("extension_id"),
+                samples: row.get::<i64, _>("samples") as u64,
+                avg_cpu_percent: row.get("avg_cpu"),
+                max_cpu_percent: row.get("max_cpu"),
+                attribution: row.get::<Option<String>, _>("attribution").unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Durations in microseconds of the latency events in `range`, sorted
+    /// ascending per component, for comparing distributions rather than
+    /// summaries.
+    pub async fn get_durations_by_component(&self, range: &TimeRange) -> Result<BTreeMap<String, Vec<u64>>> {
+        let window = range.to_sql();
+        let sql = format!(
+            "SELECT component_type, duration_us FROM latency_events WHERE {} AND event_kind IN ({}) ORDER BY component_type, duration_us",
+            window.clause,
+            latency_kinds_sql()
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut durations: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for row in &rows {
+            durations
+                .entry(row.get("component_type"))
+                .or_default()
+                .push(row.get::<i64, _>("duration_us") as u64);
+        }
+        Ok(durations)
+    }
+
+    /// Processes with samples since `since`, most recently seen first.
+    pub async fn get_processes(&self, since: DateTime<Utc>, limit: u32) -> Result<Vec<ProcessSummary>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT pid, MAX(process_name) AS process_name, MAX(component_type) AS component_type,
+                   COUNT(*) AS samples, MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen,
+                   COALESCE(AVG(cpu_percent), 0.0) AS avg_cpu, COALESCE(MAX(cpu_percent), 0.0) AS max_cpu,
+                   COALESCE(MAX({}), 0) AS max_memory_kb
+            FROM latency_events
+            WHERE event_kind = ? AND pid IS NOT NULL AND timestamp >= ? AND deleted_at IS NULL
+            GROUP BY pid
+            ORDER BY last_seen DESC
+            LIMIT ?
+            "#,
+            MEMORY_KB_SQL
+        ))
+        .bind(EventKind::ProcessSample.as_str())
+        .bind(since.to_rfc3339())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let first_seen: String = row.get("first_seen");
+                let last_seen: String = row.get("last_seen");
+                Ok(ProcessSummary {
+                    pid: row.get::<i64, _>("pid") as u32,
+                    process_name: row.get::<Option<String>, _>("process_name").unwrap_or_default(),
+                    component: parse_component(&row.get::<String, _>("component_type")),
+                    samples: row.get::<i64, _>("samples") as u64,
+                    first_seen: DateTime::parse_from_rfc3339(&first_seen)?.with_timezone(&Utc),
+                    last_seen: DateTime::parse_from_rfc3339(&last_seen)?.with_timezone(&Utc),
+                    avg_cpu_percent: row.get("avg_cpu"),
+                    max_cpu_percent: row.get("max_cpu"),
+                    max_memory_kb: row.get::<i64, _>("max_memory_kb") as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// CPU and memory of one process over time, oldest first. PIDs get
+    /// reused, so the points carry the process name to tell runs apart.
+    pub async fn get_process_timeline(
+        &self,
+        pid: u32,
+        since: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<ProcessTimelinePoint>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT id, timestamp, process_name, COALESCE(cpu_percent, 0.0) AS cpu_percent,
+                   COALESCE({}, 0) AS memory_kb
+            FROM latency_events
+            WHERE pid = ? AND event_kind = ? AND timestamp >= ? AND deleted_at IS NULL
+            ORDER BY timestamp, id
+            LIMIT ?
+            "#,
+            MEMORY_KB_SQL
+        ))
+        .bind(pid as i64)
+        .bind(EventKind::ProcessSample.as_str())
+        .bind(since.to_rfc3339())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let timestamp: String = row.get("timestamp");
+                Ok(ProcessTimelinePoint {
+                    event_id: row.get("id"),
+                    timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                    process_name: row.get::<Option<String>, _>("process_name").unwrap_or_default(),
+                    cpu_percent: row.get("cpu_percent"),
+                    memory_kb: row.get::<i64, _>("memory_kb") as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Longest-running latency events matching an optional filter.
+    pub async fn get_slowest_events(&self, filter: Option<&SqlFilter>, limit: u32) -> Result<Vec<LatencyEvent>> {
+        let latency_only = SqlFilter {
+            clause: format!("event_kind IN ({})", latency_kinds_sql()),
+            binds: Vec::new(),
+        };
+        let combined = match filter {
+            Some(filter) => latency_only.and(filter),
+            None => latency_only,
+        };
+
+        self.select_events(Some(&combined), "duration_us DESC", limit).await
+    }
+
+    async fn select_events(
+        &self,
+        filter: Option<&SqlFilter>,
+        order_by: &str,
+        limit: u32,
+    ) -> Result<Vec<LatencyEvent>> {
+        let (clause, binds) = match filter {
+            Some(filter) => (format!("WHERE deleted_at IS NULL AND {}", filter.clause), filter.binds.as_slice()),
+            None => ("WHERE deleted_at IS NULL".to_string(), &[][..]),
+        };
+
+        let sql = format!(
+            r#"
+            SELECT id, uid, timestamp, component_type, event_source, event_kind, duration_us, description, metadata
+            FROM latency_events 
+            {}
+            ORDER BY {} 
+            LIMIT ?
+            "#,
+            clause, order_by
+        );
+
+        let rows = bind_filter(sqlx::query(&sql), binds)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(event_from_row).collect()
+    }
+
+    /// Per-component metrics over the last hour.
+    pub async fn get_performance_metrics(&self) -> Result<Vec<PerformanceMetrics>> {
+        self.get_performance_metrics_in(&TimeRange::last(chrono::Duration::hours(1))).await
+    }
+
+    pub async fn get_performance_metrics_in(&self, range: &TimeRange) -> Result<Vec<PerformanceMetrics>> {
+        let window = range.to_sql();
+
+        let summary_sql = format!(
+            r#"
+            SELECT 
+                component_type,
+                COUNT(*) as total_events,
+                AVG(duration_us) / 1000.0 as avg_duration_ms,
+                MIN(duration_us) / 1000 as min_duration_ms,
+                MAX(duration_us) / 1000 as max_duration_ms,
+                SUM(CASE WHEN json_valid(metadata)
+                          AND (json_extract(metadata, '$.success') = 0
+                               OR json_extract(metadata, '$.error') IS NOT NULL)
+                    THEN 1 ELSE 0 END) as error_events
+            FROM latency_events 
+            WHERE {}
+              AND event_kind IN ({})
+            GROUP BY component_type
+            "#,
+            window.clause,
+            latency_kinds_sql()
+        );
+        let rows = bind_filter(sqlx::query(&summary_sql), &window.binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        // An open-ended range spans from the first event
+        let first_event = match range.since {
+            Some(_) => None,
+            None => self.first_event_timestamp().await?,
+        };
+        let window_seconds = range.seconds(first_event);
+
+        // Long ranges use the stored histograms for components they cover
+        // (nearly) all events of; the rest fall back to raw durations
+        let mut histograms = if window_seconds >= HISTOGRAM_PERCENTILE_RANGE.num_seconds() as f64 {
+            self.get_stored_histograms(range).await?
+        } else {
+            HashMap::new()
+        };
+        for row in &rows {
+            let total_events = row.get::<i64, _>("total_events") as u64;
+            let component: String = row.get("component_type");
+            if histograms.get(&component).is_some_and(|h| h.count() * 10 < total_events * 9) {
+                histograms.remove(&component);
+            }
+        }
+
+        // Durations per component, sorted, for the percentile columns
+        let mut durations: HashMap<String, Vec<u64>> = HashMap::new();
+        if rows.iter().any(|row| !histograms.contains_key(&row.get::<String, _>("component_type"))) {
+            let durations_sql = format!(
+                r#"
+                SELECT component_type, duration_us
+                FROM latency_events
+                WHERE {}
+                  AND event_kind IN ({})
+                ORDER BY component_type, duration_us
+                "#,
+                window.clause,
+                latency_kinds_sql()
+            );
+            let duration_rows = bind_filter(sqlx::query(&durations_sql), &window.binds)
+                .fetch_all(&self.pool)
+                .await?;
+            for row in &duration_rows {
+                durations
+                    .entry(row.get("component_type"))
+                    .or_default()
+                    .push(row.get::<i64, _>("duration_us") as u64);
+            }
+        }
+
+        let mut metrics = Vec::new();
+        for row in rows {
+            let component_type_str: String = row.get("component_type");
+            let component_type = parse_component(&component_type_str);
+
+            let total_events = row.get::<i64, _>("total_events") as u64;
+            let error_events = row.get::<i64, _>("error_events") as u64;
+            let sorted = durations.get(&component_type_str).map(Vec::as_slice).unwrap_or(&[]);
+            let histogram = histograms.get(&component_type_str);
+            let quantile = |q: f64| histogram.map_or_else(|| percentile(sorted, q), |h| h.percentile(q));
+
+            let metric = PerformanceMetrics {
+                component: component_type,
+                total_events,
+                avg_duration_ms: row.get("avg_duration_ms"),
+                min_duration_ms: row.get::<i64, _>("min_duration_ms") as u64,
+                max_duration_ms: row.get::<i64, _>("max_duration_ms") as u64,
+                p50_duration_ms: quantile(0.50) / 1000,
+                p95_duration_ms: quantile(0.95) / 1000,
+                p99_duration_ms: quantile(0.99) / 1000,
+                events_per_second: total_events as f64 / window_seconds,
+                error_rate: if total_events > 0 {
+                    error_events as f64 / total_events as f64
+                } else {
+                    0.0
+                },
+                last_updated: Utc::now(),
+            };
+
+            metrics.push(metric);
+        }
+
+        Ok(metrics)
+    }
+
+    pub async fn get_system_status(&self) -> Result<SystemStatus> {
+        let total_events: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM latency_events WHERE deleted_at IS NULL"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let last_event_row = sqlx::query(
+            "SELECT timestamp FROM latency_events WHERE deleted_at IS NULL ORDER BY timestamp DESC LIMIT 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let last_event_timestamp = if let Some(row) = last_event_row {
+            let timestamp_str: String = row.get("timestamp");
+            Some(DateTime::parse_from_rfc3339(&timestamp_str)?.with_timezone(&Utc))
+        } else {
+            None
+        };
+
+        let performance_metrics = self.get_performance_metrics().await?;
+        let lifecycle = self.get_lifecycle_summary(24).await?;
+
+        let load_shedding = self
+            .get_load_shedding_status()
+            .await?
+            .filter(|status| status.degraded);
+
+        let summary = if let Some(load) = &load_shedding {
+            format!(
+                "DEGRADED: monitor is shedding load (queue depth {}, sampling {}x slower, dropping {:?}, {} events dropped)",
+                load.queue_depth, load.interval_multiplier, load.shedding, load.dropped_total
+            )
+        } else if lifecycle.crash_loops > 0 {
+            format!(
+                "System operational (extension host crash loop: {} restarts, {} crashes in 24h)",
+                lifecycle.extension_host_restarts, lifecycle.extension_host_crashes
+            )
+        } else {
+            "System operational".to_string()
+        };
+
+        let status = SystemStatus {
+            summary,
+            total_events: total_events as u64,
+            active_monitors: vec![
+                "VS Code Monitor".to_string(),
+                "Model Monitor".to_string(),
+                "Terminal Monitor".to_string(),
+            ],
+            performance_metrics,
+            last_event_timestamp,
+            uptime_seconds: 0, // TODO: Track uptime
+            memory_usage_mb: 0, // TODO: Get actual memory usage
+            cpu_usage_percent: 0.0, // TODO: Get actual CPU usage
+            lifecycle,
+            load_shedding,
+        };
+
+        Ok(status)
+    }
+
+    /// Load-shedding state from the newest monitor self-report, ignoring
+    /// reports too old to describe a monitor that is still running.
+    async fn get_load_shedding_status(&self) -> Result<Option<LoadSheddingStatus>> {
+        let since = (Utc::now() - LOAD_STATUS_MAX_AGE).to_rfc3339();
+        let status: Option<Option<String>> = sqlx::query_scalar(
+            r#"
+            SELECT json_extract(metadata, '$.load_shedding')
+            FROM latency_events
+            WHERE timestamp > ?
+              AND json_valid(metadata)
+              AND json_extract(metadata, '$.load_shedding') IS NOT NULL
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(status.flatten().and_then(|s| serde_json::from_str(&s).ok()))
+    }
+
+    /// Event channel snapshot from the latest self-check, if recent enough
+    /// to describe a running monitor.
+    pub async fn get_event_queue_stats(&self) -> Result<Option<QueueStats>> {
+        let since = (Utc::now() - LOAD_STATUS_MAX_AGE).to_rfc3339();
+        let stats: Option<Option<String>> = sqlx::query_scalar(
+            r#"
+            SELECT json_extract(metadata, '$.buffer')
+            FROM latency_events
+            WHERE timestamp > ?
+              AND json_valid(metadata)
+              AND json_extract(metadata, '$.buffer') IS NOT NULL
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(stats.flatten().and_then(|s| serde_json::from_str(&s).ok()))
+    }
+
+    async fn first_event_timestamp(&self) -> Result<Option<DateTime<Utc>>> {
+        let sql = "SELECT MIN(timestamp) FROM latency_events WHERE deleted_at IS NULL";
+        let first: Option<String> = sqlx::query_scalar(sql)
+            .fetch_one(&self.pool)
+            .await?;
+
+        first
+            .map(|t| Ok(DateTime::parse_from_rfc3339(&t)?.with_timezone(&Utc)))
+            .transpose()
+    }
+
+    pub async fn count_events_by_kind(&self, range: &TimeRange) -> Result<BTreeMap<String, u64>> {
+        let window = range.to_sql();
+        let sql = format!(
+            "SELECT COALESCE(event_kind, 'process_sample') as kind, COUNT(*) as total FROM latency_events WHERE {} GROUP BY kind",
+            window.clause
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<String, _>("kind"), row.get::<i64, _>("total") as u64))
+            .collect())
+    }
+
+    /// Returns `(total, within)` latency events for a component, where
+    /// `within` counts events at or under `threshold_us`.
+    pub async fn count_within_threshold(
+        &self,
+        component: ComponentType,
+        threshold_us: u64,
+        range: &TimeRange,
+    ) -> Result<(u64, u64)> {
+        let window = range.to_sql();
+        let sql = format!(
+            r#"
+            SELECT COUNT(*) as total,
+                   COALESCE(SUM(CASE WHEN duration_us <= ? THEN 1 ELSE 0 END), 0) as within
+            FROM latency_events
+            WHERE component_type = ? AND event_kind IN ({}) AND {}
+            "#,
+            latency_kinds_sql(),
+            window.clause
+        );
+        let query = sqlx::query(&sql)
+            .bind(threshold_us as i64)
+            .bind(component.as_str());
+        let row = bind_filter(query, &window.binds)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok((
+            row.get::<i64, _>("total") as u64,
+            row.get::<i64, _>("within") as u64,
+        ))
+    }
+
+    pub async fn get_lifecycle_summary(&self, hours: i64) -> Result<LifecycleSummary> {
+        let since = (Utc::now() - chrono::Duration::hours(hours)).to_rfc3339();
+
+        let rows = sqlx::query(
+            r#"
+            SELECT json_extract(metadata, '$.lifecycle') as lifecycle,
+                   COUNT(*) as occurrences,
+                   MAX(timestamp) as last_seen
+            FROM latency_events
+            WHERE event_kind = 'lifecycle'
+              AND deleted_at IS NULL
+              AND json_valid(metadata)
+              AND json_extract(metadata, '$.lifecycle') IS NOT NULL
+              AND timestamp > ?
+            GROUP BY lifecycle
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut summary = LifecycleSummary::default();
+        for row in rows {
+            let lifecycle: String = row.get("lifecycle");
+            let occurrences = row.get::<i64, _>("occurrences") as u64;
+            let last_seen: String = row.get("last_seen");
+            let last_seen = DateTime::parse_from_rfc3339(&last_seen)?.with_timezone(&Utc);
+
+            if lifecycle == LifecycleKind::ExtensionHostRestart.as_str() {
+                summary.extension_host_restarts = occurrences;
+                summary.last_restart = Some(last_seen);
+            } else if lifecycle == LifecycleKind::ExtensionHostCrash.as_str() {
+                summary.extension_host_crashes = occurrences;
+                summary.last_crash = Some(last_seen);
+            } else if lifecycle == LifecycleKind::RendererReload.as_str() {
+                summary.renderer_reloads = occurrences;
+            } else if lifecycle == LifecycleKind::CrashLoop.as_str() {
+                summary.crash_loops = occurrences;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    pub async fn generate_report(&self, range: &TimeRange, format: &str) -> Result<String> {
+        let window = range.to_sql();
+        match format {
+            "json" => {
+                let events = self.query_events(Some(&window), 100).await?;
+                let json = serde_json::to_string_pretty(&events)?;
+                Ok(json)
+            }
+            "csv" => {
+                let events = self.query_events(Some(&window), 100).await?;
+                let mut csv = String::from("timestamp,component,duration_ms,description\n");
+                
+                for event in events {
+                    csv.push_str(&format!(
+                        "{},{},{},{}\n",
+                        event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                        event.component_type,
+                        event.duration_ms(),
+                        event.description.replace(',', ";")
+                    ));
+                }
+                
+                Ok(csv)
+            }
+            _ => Err(anyhow::anyhow!("Unsupported format: {}", format)),
+        }
+    }
+
+    /// `(timestamp, component, duration_us)` for the most recent latency
+    /// events in `range`, oldest first. Feeds report timelines and sparklines.
+    pub async fn get_latency_samples(
+        &self,
+        limit: u32,
+        range: &TimeRange,
+    ) -> Result<Vec<(DateTime<Utc>, ComponentType, u64)>> {
+        let window = range.to_sql();
+        let sql = format!(
+            r#"
+            SELECT timestamp, component_type, duration_us FROM (
+                SELECT id, timestamp, component_type, duration_us
+                FROM latency_events
+                WHERE event_kind IN ({}) AND {}
+                ORDER BY timestamp DESC
+                LIMIT ?
+            )
+            ORDER BY timestamp, id
+            "#,
+            latency_kinds_sql(),
+            window.clause
+        );
+        let rows = bind_filter(sqlx::query(&sql), &window.binds)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let timestamp: String = row.get("timestamp");
+                let component: String = row.get("component_type");
+                let duration_us: i64 = row.get("duration_us");
+                Ok((
+                    DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                    parse_component(&component),
+                    duration_us.max(0) as u64,
+                ))
+            })
+            .collect()
+    }
+
+    /// All-time cumulative latency histograms per component, aggregated in
+    /// SQL, each with the component's configured bucket bounds.
+    pub async fn get_latency_histograms(&self, buckets: &HistogramConfig) -> Result<Vec<LatencyHistogram>> {
+        // Count every bound any component uses, then pick each row's own
+        let all_bounds = buckets.all_bounds_us();
+        let bucket_columns = all_bounds
+            .iter()
+            .enumerate()
+            .map(|(i, bound)| format!("SUM(duration_us <= {}) AS le_{}", bound, i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            r#"
+            SELECT component_type, COUNT(*) AS total, COALESCE(SUM(duration_us), 0) AS sum_us{}{}
+            FROM latency_events
+            WHERE event_kind IN ({}) AND NOT {} AND deleted_at IS NULL
+            GROUP BY component_type
+            ORDER BY component_type
+            "#,
+            if bucket_columns.is_empty() { "" } else { ", " },
+            bucket_columns,
+            latency_kinds_sql(),
+            TEST_DATA_SQL
+        );
+
+        let rows = sqlx::query(&sql).fetch_all(&self.pool).await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let component = parse_component(&row.get::<String, _>("component_type"));
+                let bounds_us = buckets.bounds_us(component);
+                let bucket_counts = bounds_us
+                    .iter()
+                    .map(|bound| {
+                        let column = all_bounds.binary_search(bound).unwrap_or_default();
+                        row.get::<i64, _>(format!("le_{}", column).as_str()) as u64
+                    })
+                    .collect();
+                LatencyHistogram {
+                    component,
+                    bounds_us,
+                    bucket_counts,
+                    count: row.get::<i64, _>("total") as u64,
+                    sum_us: row.get::<i64, _>("sum_us") as u64,
+                }
+            })
+            .collect())
+    }
+
+    /// Per-component latency aggregates over fixed `bucket_secs` buckets
+    /// aligned to the Unix epoch, oldest first. Everything, including the
+    /// nearest-rank p95, is computed in SQL so only one row per bucket is
+    /// read back.
+    pub async fn get_latency_timeseries(
+        &self,
+        range: &TimeRange,
+        bucket_secs: i64,
+        component: Option<&str>,
+    ) -> Result<Vec<LatencyBucket>> {
+        let window = range.to_sql();
+        let sql = format!(
+            r#"
+            WITH bucketed AS (
+                SELECT component_type,
+                       CAST(strftime('%s', timestamp) AS INTEGER) / ?1 * ?1 AS bucket,
+                       duration_us
+                FROM latency_events
+                WHERE (?2 IS NULL OR component_type = ?2 COLLATE NOCASE)
+                  AND event_kind IN ({}) AND {}
+            ),
+            ranked AS (
+                SELECT component_type, bucket, duration_us,
+                       ROW_NUMBER() OVER (PARTITION BY component_type, bucket ORDER BY duration_us) AS position,
+                       COUNT(*) OVER (PARTITION BY component_type, bucket) AS total
+                FROM bucketed
+            )
+            SELECT component_type, bucket,
+                   COUNT(*) AS count,
+                   AVG(duration_us) AS avg_us,
+                   MIN(duration_us) AS min_us,
+                   MAX(duration_us) AS max_us,
+                   MIN(CASE WHEN position * 100 >= total * 95 THEN duration_us END) AS p95_us
+            FROM ranked
+            GROUP BY component_type, bucket
+            ORDER BY bucket, component_type
+            "#,
+            latency_kinds_sql(),
+            window.clause
+        );
+        // Numbered parameters come first in the text so the window's `?`
+        // placeholders continue from 3
+        let query = sqlx::query(&sql).bind(bucket_secs.max(1)).bind(component);
+        let rows = bind_filter(query, &window.binds).fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| LatencyBucket {
+                bucket_start: DateTime::from_timestamp(row.get("bucket"), 0).unwrap_or_default(),
+                component: parse_component(&row.get::<String, _>("component_type")),
+                count: row.get::<i64, _>("count") as u64,
+                avg_ms: row.get::<f64, _>("avg_us") / 1000.0,
+                min_ms: row.get::<i64, _>("min_us") as f64 / 1000.0,
+                max_ms: row.get::<i64, _>("max_us") as f64 / 1000.0,
+                p95_ms: row.get::<i64, _>("p95_us") as f64 / 1000.0,
+            })
+            .collect())
+    }
+
+    /// Summarize every completed UTC day after the last summarized one, and
+    /// re-summarize days marked dirty by late, deleted or restored events.
+    /// Returns the number of (day, component) rows written.
+    pub async fn refresh_daily_summaries(&self) -> Result<u64> {
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let mut tx = self.pool.begin().await?;
+
+        // Days at or before the oldest live event have lost raw events to
+        // retention, so their stored summary is the best there is
+        let complete_after: Option<String> = sqlx::query_scalar(
+            "SELECT substr(MIN(timestamp), 1, 10) FROM latency_events WHERE deleted_at IS NULL",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let dirty = "SELECT day FROM dirty_summary_days WHERE day > ?";
+        sqlx::query(&format!("DELETE FROM daily_summaries WHERE day IN ({})", dirty))
+            .bind(&complete_after)
+            .execute(&mut *tx)
+            .await?;
+        let sql = format!(
+            "INSERT OR REPLACE INTO daily_summaries (day, component_type, event_count, avg_us, p95_us, max_us) {}",
+            daily_summary_select(&format!(
+                "timestamp >= (SELECT MIN(day) FROM dirty_summary_days) AND substr(timestamp, 1, 10) IN ({})",
+                dirty
+            ))
+        );
+        let mut written = sqlx::query(&sql)
+            .bind(&complete_after)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM dirty_summary_days").execute(&mut *tx).await?;
+
+        let after: Option<String> = sqlx::query_scalar("SELECT MAX(day) FROM daily_summaries")
+            .fetch_one(&mut *tx)
+            .await?;
+        let sql = format!(
+            "INSERT OR REPLACE INTO daily_summaries (day, component_type, event_count, avg_us, p95_us, max_us) {}",
+            daily_summary_select("timestamp >= ? AND timestamp < ?")
+        );
+        written += sqlx::query(&sql)
+            .bind(after.map(|day| next_day(&day)).unwrap_or_default())
+            .bind(&today)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+
+        if written > 0 {
+            debug!("Summarized {} component-days before {}", written, today);
+        }
+        Ok(written)
+    }
+
+    /// Daily latency per component from `since` through today, oldest
+    /// first. Summarized days come from `daily_summaries`; later days
+    /// (including today) are aggregated from raw events on the fly. Reading
+    /// never writes; [`run_summaries`] keeps `daily_summaries` current.
+    pub async fn get_daily_summaries(&self, since: NaiveDate, component: Option<&str>) -> Result<Vec<DailySummary>> {
+        let since = since.format("%Y-%m-%d").to_string();
+
+        let mut summaries = Vec::new();
+        let live_from = match self.summarized_until().await {
+            Some(until) => {
+                let rows = sqlx::query(
+                    r#"
+                    SELECT day, component_type, event_count, avg_us, p95_us, max_us
+                    FROM daily_summaries
+                    WHERE day >= ? AND (? IS NULL OR component_type = ? COLLATE NOCASE)
+                    ORDER BY day, component_type
+                    "#,
+                )
+                .bind(&since)
+                .bind(component)
+                .bind(component)
+                .fetch_all(&self.pool)
+                .await?;
+                summaries.extend(rows.iter().map(daily_summary_from_row).collect::<Result<Vec<_>>>()?);
+                next_day(&until).max(since)
+            }
+            None => since,
+        };
+
+        let sql = format!(
+            "SELECT * FROM ({}) WHERE ? IS NULL OR component_type = ? COLLATE NOCASE ORDER BY day, component_type",
+            daily_summary_select("timestamp >= ?")
+        );
+        let rows = sqlx::query(&sql)
+            .bind(&live_from)
+            .bind(component)
+            .bind(component)
+            .fetch_all(&self.pool)
+            .await?;
+        summaries.extend(rows.iter().map(daily_summary_from_row).collect::<Result<Vec<_>>>()?);
+
+        Ok(summaries)
+    }
+
+    /// Latest day in `daily_summaries`; `None` when there are none or the
+    /// table doesn't exist (read-only databases from older versions).
+    async fn summarized_until(&self) -> Option<String> {
+        sqlx::query_scalar::<_, Option<String>>("SELECT MAX(day) FROM daily_summaries")
+            .fetch_one(&self.pool)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Summarize inline suggestions of every completed UTC hour after the
+    /// last summarized one, and re-summarize hours marked dirty by late,
+    /// deleted or restored suggestions. Returns the number of rows written.
+    pub async fn refresh_suggestion_hours(&self) -> Result<u64> {
+        let current = Utc::now().format(HOUR_FORMAT).to_string();
+        let mut tx = self.pool.begin().await?;
+
+        // As for daily summaries, hours retention has started deleting from
+        // keep their counts
+        let complete_after: Option<String> = sqlx::query_scalar(
+            "SELECT substr(MIN(timestamp), 1, 13) FROM latency_events WHERE deleted_at IS NULL",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let dirty = "SELECT hour FROM dirty_suggestion_hours WHERE hour > ?";
+        sqlx::query(&format!("DELETE FROM suggestion_hours WHERE hour IN ({})", dirty))
+            .bind(&complete_after)
+            .execute(&mut *tx)
+            .await?;
+        let sql = format!(
+            r#"INSERT OR REPLACE INTO suggestion_hours
+               (hour, extension, band, shown, accepted, rejected, latency_us_total, accepted_latency_us_total) {}"#,
+            suggestion_hour_select(&format!(
+                "timestamp >= (SELECT MIN(hour) FROM dirty_suggestion_hours) AND substr(timestamp, 1, 13) IN ({})",
+                dirty
+            ))
+        );
+        let mut written = sqlx::query(&sql)
+            .bind(&complete_after)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM dirty_suggestion_hours").execute(&mut *tx).await?;
+
+        let after: Option<String> = sqlx::query_scalar("SELECT MAX(hour) FROM suggestion_hours")
+            .fetch_one(&mut *tx)
+            .await?;
+        let sql = format!(
+            r#"INSERT OR REPLACE INTO suggestion_hours
+               (hour, extension, band, shown, accepted, rejected, latency_us_total, accepted_latency_us_total) {}"#,
+            suggestion_hour_select("timestamp >= ? AND timestamp < ?")
+        );
+        written += sqlx::query(&sql)
+            .bind(after.map(|hour| next_hour(&hour)).unwrap_or_default())
+            .bind(&current)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+
+        if written > 0 {
+            debug!("Summarized {} suggestion hour rows before {}", written, current);
+        }
+        Ok(written)
+    }
+
+    /// Inline suggestion counts per UTC hour, extension and latency band
+    /// between `since` and `until`, oldest first. Like
+    /// [`get_daily_summaries`](Self::get_daily_summaries), hours not yet
+    /// summarized are aggregated from raw events and reading never writes.
+    pub async fn get_suggestion_hours(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        extension: Option<&str>,
+    ) -> Result<Vec<SuggestionHour>> {
+        let since = since.map(|t| t.format(HOUR_FORMAT).to_string()).unwrap_or_default();
+        let until = until.map(|t| t.format(HOUR_FORMAT).to_string());
+        let filter = "(? IS NULL OR hour <= ?) AND (? IS NULL OR extension = ? COLLATE NOCASE)";
+
+        let mut hours = Vec::new();
+        let live_from = match self.suggestions_summarized_until().await {
+            Some(summarized) => {
+                let sql = format!(
+                    r#"
+                    SELECT hour, extension, band, shown, accepted, rejected, latency_us_total, accepted_latency_us_total
+                    FROM suggestion_hours
+                    WHERE hour >= ? AND {}
+                    ORDER BY hour, extension, band
+                    "#,
+                    filter
+                );
+                let rows = sqlx::query(&sql)
+                    .bind(&since)
+                    .bind(&until)
+                    .bind(&until)
+                    .bind(extension)
+                    .bind(extension)
+                    .fetch_all(&self.pool)
+                    .await?;
+                hours.extend(rows.iter().map(suggestion_hour_from_row).collect::<Result<Vec<_>>>()?);
+                next_hour(&summarized).max(since)
+            }
+            None => since,
+        };
+
+        let sql = format!(
+            "SELECT * FROM ({}) WHERE {} ORDER BY hour, extension, band",
+            suggestion_hour_select("timestamp >= ?"),
+            filter
+        );
+        let rows = sqlx::query(&sql)
+            .bind(&live_from)
+            .bind(&until)
+            .bind(&until)
+            .bind(extension)
+            .bind(extension)
+            .fetch_all(&self.pool)
+            .await?;
+        hours.extend(rows.iter().map(suggestion_hour_from_row).collect::<Result<Vec<_>>>()?);
+
+        Ok(hours)
+    }
+
+    /// Latest hour in `suggestion_hours`, as `YYYY-MM-DDTHH`.
+    async fn suggestions_summarized_until(&self) -> Option<String> {
+        sqlx::query_scalar::<_, Option<String>>("SELECT MAX(hour) FROM suggestion_hours")
+            .fetch_one(&self.pool)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Recent latency events since `since`, newest first, as exemplar candidates.
+    pub async fn get_exemplar_candidates(&self, since: DateTime<Utc>, limit: u32) -> Result<Vec<LatencyExemplar>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT id, COALESCE(trace_id, uid) AS trace_id, component_type, duration_us, timestamp
+            FROM latency_events
+            WHERE event_kind IN ({}) AND timestamp >= ? AND deleted_at IS NULL
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?
+            "#,
+            latency_kinds_sql()
+        ))
+        .bind(since.to_rfc3339())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let timestamp: String = row.get("timestamp");
+                Ok(LatencyExemplar {
+                    event_id: row.get("id"),
+                    trace_id: row.get("trace_id"),
+                    component: parse_component(&row.get::<String, _>("component_type")),
+                    duration_us: row.get::<i64, _>("duration_us").max(0) as u64,
+                    timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
+    /// Stream every event in `range` to `writer` as a JSON array, oldest
+    /// first, failing between batches if `progress` is cancelled. Returns
+    /// the number of rows.
+    pub async fn export_json<W: Write>(&self, writer: W, range: &TimeRange, progress: &Progress) -> Result<u64> {
+        let mut json = JsonExporter::new(writer)?;
+        self.for_each_export_batch(&range.to_sql(), EXPORT_SELECT, |rows| {
+            rows.iter().try_for_each(|row| json.write_row(&export_row(row)))?;
+            progress.advance(rows.len() as u64)
+        })
+        .await?;
+        json.finish()
+    }
+
+    /// Stream every event in `range` to `writer` as CSV, oldest first. Nested
+    /// metadata is flattened into `metadata.<dotted.path>` columns (arrays are
+    /// kept as JSON), so the table is read twice: once to collect the metadata
+    /// keys for the header, once to write rows; `progress` counts each pass.
+    /// Returns the number of rows.
+    pub async fn export_csv<W: Write>(
+        &self,
+        writer: W,
+        range: &TimeRange,
+        delimiter: u8,
+        progress: &Progress,
+    ) -> Result<u64> {
+        let filter = range.to_sql();
+
+        progress.restart("Collecting metadata keys");
+        let mut metadata_keys = BTreeSet::new();
+        self.for_each_export_batch(&filter, "id, metadata", |rows| {
+            for row in rows {
+                collect_metadata_keys(row.get("metadata"), &mut metadata_keys);
+            }
+            progress.advance(rows.len() as u64)
+        })
+        .await?;
+
+        progress.restart("Writing rows");
+        let mut csv = CsvExporter::new(writer, delimiter, metadata_keys)?;
+        self.for_each_export_batch(&filter, EXPORT_SELECT, |rows| {
+            rows.iter().try_for_each(|row| csv.write_row(&export_row(row)))?;
+            progress.advance(rows.len() as u64)
+        })
+        .await?;
+        csv.finish()
+    }
+
+    /// Stream every event in `range` to `writer` as Parquet, oldest first,
+    /// one record batch at a time. Returns the number of rows.
+    pub async fn export_parquet<W: Write + Send>(
+        &self,
+        writer: W,
+        range: &TimeRange,
+        progress: &Progress,
+    ) -> Result<u64> {
+        let mut parquet = ParquetExporter::new(writer)?;
+        self.for_each_export_batch(&range.to_sql(), EXPORT_SELECT, |rows| {
+            rows.iter().try_for_each(|row| parquet.write_row(&export_row(row)))?;
+            progress.advance(rows.len() as u64)
+        })
+        .await?;
+        parquet.finish()
+    }
+
+    /// Write a standalone SQLite snapshot to `path`, replacing any existing
+    /// file. An unbounded range copies the whole database with `VACUUM INTO`;
+    /// otherwise the events in `range` and their tags are copied into a
+    /// temporary database, created and migrated like any other, which is
+    /// then attached and vacuumed into `path`.
+    /// Neither step can be interrupted, so cancelling `progress` removes the
+    /// snapshot once it is written. Returns the number of events in it.
+    pub async fn export_sqlite(&self, path: &Path, range: &TimeRange, progress: &Progress) -> Result<u64> {
+        let exported = self.write_sqlite_snapshot(path, range).await?;
+        if progress.is_cancelled() {
+            tokio::fs::remove_file(path).await?;
+            return Err(anyhow!("Cancelled; removed {}", path.display()));
+        }
+        Ok(exported)
+    }
+
+    async fn write_sqlite_snapshot(&self, path: &Path, range: &TimeRange) -> Result<u64> {
+        // VACUUM INTO refuses to overwrite an existing file
+        if path.exists() {
+            tokio::fs::remove_file(path).await?;
+        }
+        let target = path.to_string_lossy().to_string();
+
+        // ATTACH is per connection, so keep every step on the same one
+        let mut conn = self.pool.acquire().await?;
+        let everything = TimeRange::default().with_test_data();
+        if *range == everything || (*range == TimeRange::default() && self.count_test_data().await? == 0) {
+            sqlx::query("VACUUM INTO ?").bind(&target).execute(&mut *conn).await?;
+            info!("Exported database snapshot to {}", path.display());
+            return self.count_events().await;
+        }
+
+        let staging = path.with_extension("partial");
+        if staging.exists() {
+            tokio::fs::remove_file(&staging).await?;
+        }
+        Self::new(&staging).await?.pool.close().await;
+        sqlx::query("ATTACH DATABASE ? AS export")
+            .bind(staging.to_string_lossy().to_string())
+            .execute(&mut *conn)
+            .await?;
+
+        let copied = copy_range_into_export(&mut conn, range, &target).await;
+
+        let detached = sqlx::query("DETACH DATABASE export").execute(&mut *conn).await;
+        let _ = tokio::fs::remove_file(&staging).await;
+        let exported = copied?;
+        detached?;
+
+        info!("Exported {} events ({}) to {}", exported, range, path.display());
+        Ok(exported)
+    }
+
+    /// Walk events matching `filter` in id order, `EXPORT_BATCH_SIZE` rows at
+    /// a time, so exports never hold the whole table in memory.
+    async fn for_each_export_batch<F>(&self, filter: &SqlFilter, columns: &str, mut on_batch: F) -> Result<()>
+    where
+        F: FnMut(&[SqliteRow]) -> Result<()>,
+    {
+        let select = format!(
+            "SELECT {} FROM latency_events WHERE id > ? AND {} ORDER BY id LIMIT ?",
+            columns, filter.clause
+        );
+
+        let mut last_id: i64 = 0;
+        loop {
+            let rows = bind_filter(sqlx::query(&select).bind(last_id), &filter.binds)
+                .bind(EXPORT_BATCH_SIZE)
+                .fetch_all(&self.pool)
+                .await?;
+
+            let Some(last) = rows.last() else {
+                return Ok(());
+            };
+            last_id = last.get("id");
+            on_batch(&rows)?;
+        }
+    }
+
+    pub async fn count_events(&self) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM latency_events WHERE deleted_at IS NULL")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    pub async fn count_matching(&self, filter: &SqlFilter) -> Result<u64> {
+        let sql = format!("SELECT COUNT(*) FROM latency_events WHERE {}", filter.clause);
+        let count: i64 = bind_filter_scalar(sqlx::query_scalar(&sql), &filter.binds)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count as u64)
+    }
+
+    /// Apply `tags` to every event matching `filter`, walking the table in id
+    /// order so each batch commits in its own transaction. Stops after the
+    /// current batch if `progress` is cancelled; returns how many events
+    /// were tagged.
+    pub async fn tag_matching_events(
+        &self,
+        filter: &SqlFilter,
+        tags: &[(String, String)],
+        batch_size: u32,
+        progress: &Progress,
+    ) -> Result<u64> {
+        let select = format!(
+            "SELECT id FROM latency_events WHERE id > ? AND {} ORDER BY id LIMIT ?",
+            filter.clause
+        );
+
+        let mut last_id: i64 = 0;
+        let mut tagged: u64 = 0;
+
+        loop {
+            let query = bind_filter(sqlx::query(&select).bind(last_id), &filter.binds);
+            let ids: Vec<i64> = query
+                .bind(batch_size)
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(|row| row.get("id"))
+                .collect();
+
+            let Some(&max_id) = ids.last() else {
+                break;
+            };
+
+            let mut tx = self.pool.begin().await?;
+            for id in &ids {
+                for (key, value) in tags {
+                    sqlx::query(
+                        "INSERT OR REPLACE INTO event_tags (event_id, key, value) VALUES (?, ?, ?)",
+                    )
+                    .bind(id)
+                    .bind(key)
+                    .bind(value)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+            tx.commit().await?;
+
+            tagged += ids.len() as u64;
+            last_id = max_id;
+            progress.inc(ids.len() as u64);
+            if progress.is_cancelled() {
+                break;
+            }
+        }
+
+        debug!("Tagged {} events", tagged);
+        Ok(tagged)
+    }
+
+    pub async fn save_query(&self, query: &SavedQuery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO saved_queries (name, filter, result_limit, description, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                filter = excluded.filter,
+                result_limit = excluded.result_limit,
+                description = excluded.description,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&query.name)
+        .bind(&query.filter)
+        .bind(query.limit)
+        .bind(&query.description)
+        .bind(query.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_saved_query(&self, name: &str) -> Result<Option<SavedQuery>> {
+        let row = sqlx::query(
+            "SELECT name, filter, result_limit, description, updated_at FROM saved_queries WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(saved_query_from_row).transpose()
+    }
+
+    pub async fn save_noise_profile(&self, profile: &NoiseProfile) -> Result<()> {
+        sqlx::query("INSERT INTO noise_profiles (measured_at, profile) VALUES (?, ?)")
+            .bind(profile.measured_at.to_rfc3339())
+            .bind(serde_json::to_string(profile)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The most recent `calibrate` result, if the machine was ever calibrated.
+    pub async fn latest_noise_profile(&self) -> Result<Option<NoiseProfile>> {
+        let profile: Option<String> =
+            sqlx::query_scalar("SELECT profile FROM noise_profiles ORDER BY measured_at DESC, id DESC LIMIT 1")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        profile.map(|p| serde_json::from_str(&p).map_err(Into::into)).transpose()
+    }
+
+    pub async fn list_saved_queries(&self) -> Result<Vec<SavedQuery>> {
+        let rows = sqlx::query(
+            "SELECT name, filter, result_limit, description, updated_at FROM saved_queries ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(saved_query_from_row).collect()
+    }
+
+    pub async fn delete_saved_query(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM saved_queries WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn save_maintenance_window(&self, window: &MaintenanceWindow) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO maintenance_windows (name, starts_at, ends_at, recurrence, rules, reason, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                starts_at = excluded.starts_at,
+                ends_at = excluded.ends_at,
+                recurrence = excluded.recurrence,
+                rules = excluded.rules,
+                reason = excluded.reason,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&window.name)
+        .bind(window.starts_at.to_rfc3339())
+        .bind(window.ends_at.to_rfc3339())
+        .bind(window.recurrence.as_str())
+        .bind(serde_json::to_string(&window.rules)?)
+        .bind(&window.reason)
+        .bind(window.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_maintenance_window(&self, name: &str) -> Result<Option<MaintenanceWindow>> {
+        let row = sqlx::query(
+            "SELECT name, starts_at, ends_at, recurrence, rules, reason, updated_at FROM maintenance_windows WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(maintenance_window_from_row).transpose()
+    }
+
+    pub async fn list_maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>> {
+        let rows = sqlx::query(
+            "SELECT name, starts_at, ends_at, recurrence, rules, reason, updated_at FROM maintenance_windows ORDER BY starts_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(maintenance_window_from_row).collect()
+    }
+
+    pub async fn delete_maintenance_window(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM maintenance_windows WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keep an alert state change, noting the maintenance window that
+    /// suppressed its notification, if any.
+    pub async fn record_alert(&self, notification: &AlertNotification, suppressed_by: Option<&str>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO alert_history (at, rule, state, value, threshold, message, suppressed_by)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(notification.at.to_rfc3339())
+        .bind(&notification.rule)
+        .bind(notification.state.as_str())
+        .bind(notification.value)
+        .bind(notification.threshold)
+        .bind(&notification.message)
+        .bind(suppressed_by)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent alert state changes, newest first.
+    pub async fn get_alert_history(&self, limit: u32) -> Result<Vec<AlertHistoryEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT at, rule, state, value, threshold, message, suppressed_by
+            FROM alert_history
+            ORDER BY at DESC, id DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(alert_history_from_row).collect()
+    }
+
+    /// State changes of one rule since `since`, oldest first.
+    pub async fn get_rule_alert_history(&self, rule: &str, since: DateTime<Utc>) -> Result<Vec<AlertHistoryEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT at, rule, state, value, threshold, message, suppressed_by
+            FROM alert_history
+            WHERE rule = ? AND at >= ?
+            ORDER BY at, id
+            "#,
+        )
+        .bind(rule)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(alert_history_from_row).collect()
+    }
+
+    pub async fn store_derived_metrics(&self, samples: &[DerivedMetricSample]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for sample in samples {
+            sqlx::query("INSERT INTO derived_metrics (name, value, at) VALUES (?, ?, ?)")
+                .bind(&sample.name)
+                .bind(sample.value)
+                .bind(sample.at.to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Most recent value of each derived metric, by name.
+    pub async fn get_latest_derived_metrics(&self) -> Result<Vec<DerivedMetricSample>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT name, value, at
+            FROM derived_metrics d
+            WHERE at = (SELECT MAX(at) FROM derived_metrics WHERE name = d.name)
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(derived_sample_from_row).collect()
+    }
+
+    /// Derived metric values since `since`, oldest first.
+    pub async fn get_derived_metrics(&self, since: DateTime<Utc>) -> Result<Vec<DerivedMetricSample>> {
+        let rows = sqlx::query("SELECT name, value, at FROM derived_metrics WHERE at >= ? ORDER BY at, name")
+            .bind(since.to_rfc3339())
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(derived_sample_from_row).collect()
+    }
+
+    pub async fn store_token_metrics(&self, metrics: &TokenMetrics) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO token_metrics (
+                timestamp, source, provider, model, estimated_tokens, reported_tokens, generation_time_ms,
+                time_to_first_token_ms, tokens_per_second, prompt_length, response_length
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(metrics.timestamp.to_rfc3339())
+        .bind(&metrics.source)
+        .bind(&metrics.provider)
+        .bind(&metrics.model_type)
+        .bind(metrics.estimated_tokens as i64)
+        .bind(metrics.reported_tokens.map(|tokens| tokens as i64))
+        .bind(metrics.generation_time_ms as i64)
+        .bind(metrics.time_to_first_token_ms.map(|ms| ms as i64))
+        .bind(metrics.tokens_per_second)
+        .bind(metrics.prompt_length as i64)
+        .bind(metrics.response_length as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Token throughput samples in the window, newest first, optionally of
+    /// one model only.
+    pub async fn get_token_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        model: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<TokenMetrics>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM token_metrics
+            WHERE (? IS NULL OR timestamp >= ?) AND (? IS NULL OR timestamp < ?)
+              AND (? IS NULL OR model = ? COLLATE NOCASE)
+            ORDER BY timestamp DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(since.map(|t| t.to_rfc3339()))
+        .bind(since.map(|t| t.to_rfc3339()))
+        .bind(until.map(|t| t.to_rfc3339()))
+        .bind(until.map(|t| t.to_rfc3339()))
+        .bind(model)
+        .bind(model)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(token_metrics_from_row).collect()
+    }
+
+    /// Token throughput per source and model over the window, fastest
+    /// median first.
+    pub async fn get_model_throughput(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        model: Option<&str>,
+    ) -> Result<Vec<ModelThroughput>> {
+        let rows = sqlx::query(
+            r#"
+            WITH samples AS (
+                SELECT * FROM token_metrics
+                WHERE (? IS NULL OR timestamp >= ?) AND (? IS NULL OR timestamp < ?)
+                  AND (? IS NULL OR model = ? COLLATE NOCASE)
+            ),
+            rates AS (
+                SELECT source, provider, model, tokens_per_second AS value,
+                       ROW_NUMBER() OVER (PARTITION BY source, provider, model ORDER BY tokens_per_second) AS position,
+                       COUNT(*) OVER (PARTITION BY source, provider, model) AS total
+                FROM samples
+                WHERE tokens_per_second IS NOT NULL
+            ),
+            ttfts AS (
+                SELECT source, provider, model, time_to_first_token_ms AS value,
+                       ROW_NUMBER() OVER (
+                           PARTITION BY source, provider, model ORDER BY time_to_first_token_ms
+                       ) AS position,
+                       COUNT(*) OVER (PARTITION BY source, provider, model) AS total
+                FROM samples
+                WHERE time_to_first_token_ms IS NOT NULL
+            ),
+            rate_percentiles AS (
+                SELECT source, provider, model,
+                       MIN(CASE WHEN position * 100 >= total * 50 THEN value END) AS p50_rate,
+                       MIN(CASE WHEN position * 100 >= total * 5 THEN value END) AS p5_rate
+                FROM rates
+                GROUP BY source, provider, model
+            ),
+            ttft_percentiles AS (
+                SELECT source, provider, model, MIN(CASE WHEN position * 100 >= total * 95 THEN value END) AS p95_ttft
+                FROM ttfts
+                GROUP BY source, provider, model
+            )
+            SELECT source, provider, model,
+                   COUNT(*) AS responses,
+                   SUM(estimated_tokens) AS estimated_tokens,
+                   SUM(reported_tokens) AS reported_tokens,
+                   AVG(tokens_per_second) AS avg_rate,
+                   AVG(time_to_first_token_ms) AS avg_ttft,
+                   p50_rate, p5_rate, p95_ttft
+            FROM samples
+            LEFT JOIN rate_percentiles USING (source, provider, model)
+            LEFT JOIN ttft_percentiles USING (source, provider, model)
+            GROUP BY source, provider, model
+            ORDER BY p50_rate IS NULL, p50_rate DESC
+            "#,
+        )
+        .bind(since.map(|t| t.to_rfc3339()))
+        .bind(since.map(|t| t.to_rfc3339()))
+        .bind(until.map(|t| t.to_rfc3339()))
+        .bind(until.map(|t| t.to_rfc3339()))
+        .bind(model)
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ModelThroughput {
+                source: row.get("source"),
+                provider: row.get("provider"),
+                model_type: row.get("model"),
+                responses: row.get::<i64, _>("responses") as u64,
+                estimated_tokens: row.get::<i64, _>("estimated_tokens") as u64,
+                reported_tokens: row.get::<Option<i64>, _>("reported_tokens").map(|tokens| tokens as u64),
+                avg_tokens_per_second: row.get("avg_rate"),
+                p50_tokens_per_second: row.get("p50_rate"),
+                p5_tokens_per_second: row.get("p5_rate"),
+                avg_time_to_first_token_ms: row.get("avg_ttft"),
+                p95_time_to_first_token_ms: row.get::<Option<i64>, _>("p95_ttft").map(|ms| ms as f64),
+            })
+            .collect())
+    }
+
+    /// Enforce `retention_days`, returning how many events were deleted.
+    pub async fn cleanup_old_events(&self, retention_days: u32) -> Result<u64> {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        self.prune_events_before(cutoff, "system:retention", &Progress::hidden()).await
+    }
+
+    /// Number of events a prune at `cutoff` would delete.
+    pub async fn count_events_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let sql = "SELECT COUNT(*) FROM latency_events WHERE timestamp < ? AND deleted_at IS NULL";
+        let count: i64 = sqlx::query_scalar(sql)
+            .bind(cutoff.to_rfc3339())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Soft-delete events older than `cutoff`; they stay restorable until
+    /// [`purge_deleted`](Self::purge_deleted) removes them. If `progress` is
+    /// cancelled the events deleted so far stay deleted.
+    pub async fn prune_events_before(&self, cutoff: DateTime<Utc>, actor: &str, progress: &Progress) -> Result<u64> {
+        // Keep the calendar's history for the days about to be deleted
+        self.refresh_daily_summaries().await?;
+        self.refresh_suggestion_hours().await?;
+
+        let older = SqlFilter {
+            clause: "timestamp < ?".to_string(),
+            binds: vec![FilterValue::Text(cutoff.to_rfc3339())],
+        };
+        let deleted = self.soft_delete_events_in_batches(&older, progress).await?;
+        let complete = !progress.is_cancelled();
+        if complete {
+            sqlx::query("DELETE FROM derived_metrics WHERE at < ?")
+                .bind(cutoff.to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("DELETE FROM token_metrics WHERE timestamp < ?")
+                .bind(cutoff.to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+        }
+
+        self.record_audit(
+            &AuditEntry::new(actor, "retention.run", None).with_change(
+                None,
+                Some(serde_json::json!({
+                    "cutoff": cutoff.to_rfc3339(),
+                    "deleted_events": deleted,
+                    "complete": complete,
+                })),
+            ),
+        )
+        .await?;
+        info!("Cleaned up {} old events", deleted);
+        Ok(deleted)
+    }
+
+    pub async fn count_test_data(&self) -> Result<u64> {
+        let sql = format!("SELECT COUNT(*) FROM latency_events WHERE {} AND deleted_at IS NULL", TEST_DATA_SQL);
+        let count: i64 = sqlx::query_scalar(&sql)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Soft-delete every event recorded as test data, whatever its age.
+    /// Cancelling `progress` stops it as for
+    /// [`prune_events_before`](Self::prune_events_before).
+    pub async fn purge_test_data(&self, actor: &str, progress: &Progress) -> Result<u64> {
+        let test_data = SqlFilter {
+            clause: TEST_DATA_SQL.to_string(),
+            binds: Vec::new(),
+        };
+        let deleted = self.soft_delete_events_in_batches(&test_data, progress).await?;
+        let complete = !progress.is_cancelled();
+
+        self.record_audit(
+            &AuditEntry::new(actor, "events.purge_test_data", None).with_change(
+                None,
+                Some(serde_json::json!({ "deleted_events": deleted, "complete": complete })),
+            ),
+        )
+        .await?;
+        info!("Purged {} test events", deleted);
+        Ok(deleted)
+    }
+
+    /// Mark live events matching `filter` deleted, `DELETE_BATCH_SIZE` per
+    /// statement, until none match or `progress` is cancelled. The whole
+    /// run shares one `deleted_at`, so it can be restored as one. Returns
+    /// how many were deleted.
+    async fn soft_delete_events_in_batches(&self, filter: &SqlFilter, progress: &Progress) -> Result<u64> {
+        let batch_end = format!(
+            "SELECT MAX(id) FROM (SELECT id FROM latency_events WHERE deleted_at IS NULL AND {} ORDER BY id LIMIT ?)",
+            filter.clause
+        );
+        let mark = format!(
+            "UPDATE latency_events SET deleted_at = ? WHERE id <= ? AND deleted_at IS NULL AND {}",
+            filter.clause
+        );
+        let deleted_at = Utc::now().to_rfc3339();
+
+        let mut deleted = 0;
+        while !progress.is_cancelled() {
+            let last_id: Option<i64> = bind_filter_scalar(sqlx::query_scalar(&batch_end), &filter.binds)
+                .bind(DELETE_BATCH_SIZE)
+                .fetch_one(&self.pool)
+                .await?;
+            let Some(last_id) = last_id else {
+                break;
+            };
+
+            let batch = bind_filter(sqlx::query(&mark).bind(&deleted_at).bind(last_id), &filter.binds)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            deleted += batch;
+            progress.inc(batch);
+        }
+        Ok(deleted)
+    }
+
+    /// Soft-deleted events, most recently deleted first, one entry per
+    /// delete or prune run.
+    pub async fn list_deleted_batches(&self, limit: u32) -> Result<Vec<DeletedBatch>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT deleted_at, COUNT(*) AS events, MIN(timestamp) AS first_event, MAX(timestamp) AS last_event
+            FROM latency_events
+            WHERE deleted_at IS NOT NULL
+            GROUP BY deleted_at
+            ORDER BY deleted_at DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let parse = |column: &str| -> Result<DateTime<Utc>> {
+                    Ok(DateTime::parse_from_rfc3339(&row.get::<String, _>(column))?.with_timezone(&Utc))
+                };
+                Ok(DeletedBatch {
+                    deleted_at: parse("deleted_at")?,
+                    events: row.get::<i64, _>("events") as u64,
+                    first_event: parse("first_event")?,
+                    last_event: parse("last_event")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Bring back soft-deleted events by id, with their tags. Returns how
+    /// many were restored.
+    pub async fn restore_events(&self, ids: &[i64]) -> Result<u64> {
+        let mut restored = 0;
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            let sql = "UPDATE latency_events SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL";
+            restored += sqlx::query(sql)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(restored)
+    }
+
+    /// Bring back every event deleted at or after `since`, e.g. to undo a
+    /// prune with the wrong cutoff. Returns how many were restored.
+    pub async fn restore_deleted_since(&self, since: DateTime<Utc>) -> Result<u64> {
+        let restored = sqlx::query("UPDATE latency_events SET deleted_at = NULL WHERE deleted_at >= ?")
+            .bind(since.to_rfc3339())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(restored)
+    }
+
+    /// Number of events soft-deleted before `before`, which a purge at
+    /// `before` would remove for good.
+    pub async fn count_deleted_before(&self, before: DateTime<Utc>) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM latency_events WHERE deleted_at < ?")
+            .bind(before.to_rfc3339())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Permanently remove events (and their tags) soft-deleted before
+    /// `before`, vacuuming the database afterwards if enough rows went to be
+    /// worth the rewrite. If `progress` is cancelled the events removed so
+    /// far stay removed and the vacuum is skipped.
+    pub async fn purge_deleted(&self, before: DateTime<Utc>, actor: &str, progress: &Progress) -> Result<u64> {
+        let expired = SqlFilter {
+            clause: "deleted_at < ?".to_string(),
+            binds: vec![FilterValue::Text(before.to_rfc3339())],
+        };
+        let purged = self.delete_events_in_batches(&expired, progress).await?;
+        let complete = !progress.is_cancelled();
+        if purged == 0 {
+            return Ok(0);
+        }
+
+        self.record_audit(
+            &AuditEntry::new(actor, "events.purge_deleted", None).with_change(
+                None,
+                Some(serde_json::json!({
+                    "deleted_before": before.to_rfc3339(),
+                    "purged_events": purged,
+                    "complete": complete,
+                })),
+            ),
+        )
+        .await?;
+        info!("Purged {} deleted events", purged);
+
+        if complete && purged >= VACUUM_THRESHOLD {
+            // Another process holding the database only delays reclaiming
+            // space until the next large purge
+            if let Err(e) = self.vacuum().await {
+                warn!("VACUUM after purging {} events failed: {}", purged, e);
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Delete events matching `filter` and their tags, oldest id first,
+    /// `DELETE_BATCH_SIZE` per transaction, until none match or `progress`
+    /// is cancelled. Returns how many were deleted.
+    async fn delete_events_in_batches(&self, filter: &SqlFilter, progress: &Progress) -> Result<u64> {
+        let batch_end = format!(
+            "SELECT MAX(id) FROM (SELECT id FROM latency_events WHERE {} ORDER BY id LIMIT ?)",
+            filter.clause
+        );
+        let delete_tags = format!(
+            "DELETE FROM event_tags WHERE event_id IN (SELECT id FROM latency_events WHERE id <= ? AND {})",
+            filter.clause
+        );
+        let delete_events = format!("DELETE FROM latency_events WHERE id <= ? AND {}", filter.clause);
+
+        let mut deleted = 0;
+        while !progress.is_cancelled() {
+            let last_id: Option<i64> = bind_filter_scalar(sqlx::query_scalar(&batch_end), &filter.binds)
+                .bind(DELETE_BATCH_SIZE)
+                .fetch_one(&self.pool)
+                .await?;
+            let Some(last_id) = last_id else {
+                break;
+            };
+
+            let mut tx = self.pool.begin().await?;
+            bind_filter(sqlx::query(&delete_tags).bind(last_id), &filter.binds)
+                .execute(&mut *tx)
+                .await?;
+            let batch = bind_filter(sqlx::query(&delete_events).bind(last_id), &filter.binds)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+
+            deleted += batch;
+            progress.inc(batch);
+        }
+        Ok(deleted)
+    }
+
+    /// Rewrite the database file to return freed pages to the filesystem.
+    pub async fn vacuum(&self) -> Result<()> {
+        let started = std::time::Instant::now();
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        info!("Vacuumed database in {:.1}s", started.elapsed().as_secs_f64());
+        Ok(())
+    }
+
+    /// Store a token's hash. A `tenant` token only reaches that tenant's
+    /// database when `dashboard.tenancy` is enabled.
+    pub async fn create_api_token(
+        &self,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        tenant: Option<&str>,
+    ) -> Result<()> {
+        let scopes = scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",");
+        sqlx::query(
+            "INSERT INTO api_tokens (name, token_hash, scopes, created_at, tenant) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(token_hash)
+        .bind(scopes)
+        .bind(Utc::now().to_rfc3339())
+        .bind(tenant)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                anyhow::anyhow!("A token named '{}' already exists", name)
+            }
+            other => other.into(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Resolve a presented token by hash, recording its use.
+    pub async fn authenticate_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        let row = sqlx::query(
+            "SELECT name, scopes, created_at, last_used_at, tenant FROM api_tokens WHERE token_hash = ?",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else { return Ok(None) };
+        let token = api_token_from_row(&row)?;
+
+        sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE name = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(&token.name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Some(token))
+    }
+
+    pub async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        let rows = sqlx::query("SELECT name, scopes, created_at, last_used_at, tenant FROM api_tokens ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(api_token_from_row).collect()
+    }
+
+    /// Tenant of a presented token without recording its use: `None` for an
+    /// unknown token, `Some(None)` for one of this database's own.
+    pub async fn api_token_tenant(&self, token_hash: &str) -> Result<Option<Option<String>>> {
+        let tenant = sqlx::query_scalar("SELECT tenant FROM api_tokens WHERE token_hash = ?")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(tenant)
+    }
+
+    pub async fn count_api_tokens(&self) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    pub async fn revoke_api_token(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries
+            (delivery_id, webhook, event_type, attempt, status_code, error, duration_ms, attempted_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&delivery.delivery_id)
+        .bind(&delivery.webhook)
+        .bind(&delivery.event_type)
+        .bind(delivery.attempt)
+        .bind(delivery.status_code)
+        .bind(&delivery.error)
+        .bind(delivery.duration_ms as i64)
+        .bind(delivery.attempted_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_webhook_deliveries(&self, webhook: Option<&str>, limit: u32) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, delivery_id, webhook, event_type, attempt, status_code, error, duration_ms, attempted_at
+            FROM webhook_deliveries
+            WHERE ?1 IS NULL OR webhook = ?1
+            ORDER BY id DESC
+            LIMIT ?2
+            "#,
+        )
+        .bind(webhook)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let attempted_at: String = row.get("attempted_at");
+                Ok(WebhookDelivery {
+                    id: Some(row.get("id")),
+                    delivery_id: row.get("delivery_id"),
+                    webhook: row.get("webhook"),
+                    event_type: row.get("event_type"),
+                    attempt: row.get::<i64, _>("attempt") as u32,
+                    status_code: row.get::<Option<i64>, _>("status_code").map(|s| s as u16),
+                    error: row.get("error"),
+                    duration_ms: row.get::<i64, _>("duration_ms") as u64,
+                    attempted_at: DateTime::parse_from_rfc3339(&attempted_at)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
+    pub async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (timestamp, actor, action, target, before_value, after_value)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(entry.timestamp.to_rfc3339())
+        .bind(&entry.actor)
+        .bind(&entry.action)
+        .bind(&entry.target)
+        .bind(entry.before.as_ref().map(|v| v.to_string()))
+        .bind(entry.after.as_ref().map(|v| v.to_string()))
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Audit: {} {} {:?}", entry.actor, entry.action, entry.target);
+        Ok(())
+    }
+
+    /// Most recent audit entries, optionally narrowed to an action prefix
+    /// (`config` matches `config.set` and `config.reset`).
+    pub async fn get_audit_log(&self, action: Option<&str>, limit: u32) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, timestamp, actor, action, target, before_value, after_value
+            FROM audit_log
+            WHERE ?1 IS NULL OR action = ?1 OR action LIKE ?1 || '.%'
+            ORDER BY id DESC
+            LIMIT ?2
+            "#,
+        )
+        .bind(action)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(audit_entry_from_row).collect()
+    }
+}
+
+/// Columns `get_grouped_metrics` accepts: the event source and kind, and
+/// every promoted metadata column.
+pub fn group_by_columns() -> impl Iterator<Item = &'static str> {
+    ["event_source", "event_kind"]
+        .into_iter()
+        .chain(METADATA_COLUMNS.iter().map(|column| column.name))
+}
+
+/// Memory of a process sample in KB; samples from before `memory_kb` was
+/// recorded only have `memory_mb`.
+const MEMORY_KB_SQL: &str = "COALESCE(memory_kb, CAST(memory_mb * 1024 AS INTEGER))";
+
+/// Events from `test`, or flagged as test data by whoever recorded them; the
+/// SQL counterpart of [`LatencyEvent::is_test_data`].
+pub(crate) const TEST_DATA_SQL: &str = "(event_source = 'TestCommand' OR COALESCE(test_data, 0) != 0)";
+
+const EXPORT_BATCH_SIZE: u32 = 5000;
+
+/// Columns read for each exported event; see `export_row`.
+const EXPORT_SELECT: &str =
+    "id, uid, timestamp, component_type, event_source, event_kind, duration_us, description, metadata, created_at";
+
+/// Borrow an `EXPORT_SELECT` row as an export record.
+fn export_row(row: &SqliteRow) -> ExportRow<'_> {
+    ExportRow {
+        id: row.get("id"),
+        uid: row.get("uid"),
+        timestamp: row.get("timestamp"),
+        component_type: row.get("component_type"),
+        event_source: row.get("event_source"),
+        event_kind: row.get("event_kind"),
+        duration_us: row.get("duration_us"),
+        description: row.get("description"),
+        metadata: row.get("metadata"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn alert_history_from_row(row: &SqliteRow) -> Result<AlertHistoryEntry> {
+    let at: String = row.get("at");
+    Ok(AlertHistoryEntry {
+        rule: row.get("rule"),
+        state: row.get("state"),
+        value: row.get("value"),
+        threshold: row.get("threshold"),
+        message: row.get("message"),
+        at: DateTime::parse_from_rfc3339(&at)?.with_timezone(&Utc),
+        suppressed_by: row.get("suppressed_by"),
+    })
+}
+
+fn event_from_row(row: &SqliteRow) -> Result<LatencyEvent> {
+    let timestamp_str: String = row.get("timestamp");
+    let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)?
+        .with_timezone(&Utc);
+    
+    let duration_us: i64 = row.get("duration_us");
+    let duration = std::time::Duration::from_micros(duration_us as u64);
+    
+    let component_type = parse_component(&row.get::<String, _>("component_type"));
+    let event_source = parse_source(&row.get::<String, _>("event_source"));
+    let kind = row
+        .get::<Option<String>, _>("event_kind")
+        .and_then(|k| EventKind::parse(&k))
+        .unwrap_or_default();
+    let metadata_str: String = row.get("metadata");
+
+    let metadata: serde_json::Value = serde_json::from_str(&metadata_str)
+        .unwrap_or(serde_json::Value::Null);
+
+    Ok(LatencyEvent {
+        id: Some(row.get("id")),
+        uid: row.get("uid"),
+        timestamp,
+        component_type,
+        event_source,
+        kind,
+        duration,
+        description: row.get("description"),
+        metadata,
+    })
+}
+
+/// Identifier shared by every copy of an event, so the same sample synced
+/// through several instances is stored once: the id it was created with, or
+/// for events without one (`storage.event_ids = "hash"`, older agents) a
+/// hash of its content.
+pub fn event_uid(event: &LatencyEvent) -> String {
+    if let Some(uid) = &event.uid {
+        return uid.clone();
+    }
+    uid_for(
+        event.timestamp.to_rfc3339(),
+        event.component_type.as_str().to_string(),
+        event.duration_us() as i64,
+        event.description.clone(),
+    )
+}
+
+fn uid_for(timestamp: String, component: String, duration_us: i64, description: String) -> String {
+    let mut hasher = Sha256::new();
+    for part in [timestamp, component, duration_us.to_string(), description] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
+
+fn api_token_from_row(row: &SqliteRow) -> Result<ApiToken> {
+    let created_at: String = row.get("created_at");
+    let last_used_at: Option<String> = row.get("last_used_at");
+    let scopes: String = row.get("scopes");
+
+    Ok(ApiToken {
+        name: row.get("name"),
+        scopes: scopes
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(ApiScope::parse)
+            .collect::<Result<_>>()?,
+        created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        last_used_at: last_used_at
+            .map(|t| DateTime::parse_from_rfc3339(&t).map(|t| t.with_timezone(&Utc)))
+            .transpose()?,
+        tenant: row.get("tenant"),
+    })
+}
+
+fn audit_entry_from_row(row: &SqliteRow) -> Result<AuditEntry> {
+    let timestamp: String = row.get("timestamp");
+    let parse = |column: &str| {
+        row.get::<Option<String>, _>(column)
+            .map(|v| serde_json::from_str(&v).unwrap_or(serde_json::Value::String(v)))
+    };
+
+    Ok(AuditEntry {
+        id: Some(row.get("id")),
+        timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+        actor: row.get("actor"),
+        action: row.get("action"),
+        target: row.get("target"),
+        before: parse("before_value"),
+        after: parse("after_value"),
+    })
+}
+
+/// Stored component name; unknown values (written by newer versions) read
+/// as `System`.
+pub(crate) fn parse_component(name: &str) -> ComponentType {
+    ComponentType::parse(name).unwrap_or(ComponentType::System)
+}
+
+/// Stored event source; unknown values read as `ProcessMonitor`.
+pub(crate) fn parse_source(name: &str) -> EventSource {
+    EventSource::parse(name).unwrap_or(EventSource::ProcessMonitor)
+}
+
+fn saved_query_from_row(row: &SqliteRow) -> Result<SavedQuery> {
+    let updated_at: String = row.get("updated_at");
+
+    Ok(SavedQuery {
+        name: row.get("name"),
+        filter: row.get("filter"),
+        limit: row.get::<Option<i64>, _>("result_limit").map(|l| l as u32),
+        description: row.get("description"),
+        updated_at: DateTime::parse_from_rfc3339(&updated_at)?.with_timezone(&Utc),
+    })
+}
+
+fn derived_sample_from_row(row: &SqliteRow) -> Result<DerivedMetricSample> {
+    let at: String = row.get("at");
+
+    Ok(DerivedMetricSample {
+        name: row.get("name"),
+        value: row.get("value"),
+        at: DateTime::parse_from_rfc3339(&at)?.with_timezone(&Utc),
+    })
+}
+
+fn maintenance_window_from_row(row: &SqliteRow) -> Result<MaintenanceWindow> {
+    let starts_at: String = row.get("starts_at");
+    let ends_at: String = row.get("ends_at");
+    let recurrence: String = row.get("recurrence");
+    let rules: String = row.get("rules");
+    let updated_at: String = row.get("updated_at");
+
+    Ok(MaintenanceWindow {
+        name: row.get("name"),
+        starts_at: DateTime::parse_from_rfc3339(&starts_at)?.with_timezone(&Utc),
+        ends_at: DateTime::parse_from_rfc3339(&ends_at)?.with_timezone(&Utc),
+        recurrence: Recurrence::parse(&recurrence)
+            .ok_or_else(|| anyhow!("Unknown maintenance recurrence '{}'", recurrence))?,
+        rules: serde_json::from_str(&rules)?,
+        reason: row.get("reason"),
+        updated_at: DateTime::parse_from_rfc3339(&updated_at)?.with_timezone(&Utc),
+    })
+}
+
+/// Copy the events in `range` and their tags into the attached `export`
+/// database, which already has the current schema, then vacuum it into
+/// `target`.
+async fn copy_range_into_export(conn: &mut SqliteConnection, range: &TimeRange, target: &str) -> Result<u64> {
+    // Generated columns can't be inserted into; table_info leaves them out
+    let columns = sqlx::query("PRAGMA main.table_info(latency_events)")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get::<String, _>("name"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let window = range.to_sql();
+    let sql = format!(
+        "INSERT INTO export.latency_events ({0}) SELECT {0} FROM main.latency_events WHERE {1}",
+        columns, window.clause
+    );
+    let exported = bind_filter(sqlx::query(&sql), &window.binds)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+    sqlx::query(
+        "INSERT INTO export.event_tags SELECT * FROM main.event_tags WHERE event_id IN (SELECT id FROM export.latency_events)",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("VACUUM export INTO ?").bind(target).execute(&mut *conn).await?;
+    Ok(exported)
+}
+
+/// Prune events past `retention_days`, and purge events deleted more than
+/// `deleted_retention_days` ago, now and then every `RETENTION_INTERVAL`
+/// until the task is dropped.
+pub async fn run_retention(storage: MetricsStorage, retention_days: u32, deleted_retention_days: u32) {
+    let mut ticker = tokio::time::interval(RETENTION_INTERVAL);
+    info!(
+        "Keeping {} days of events, and deleted events for {} days",
+        retention_days, deleted_retention_days
+    );
+
+    loop {
+        ticker.tick().await;
+        if let Err(e) = storage.cleanup_old_events(retention_days).await {
+            warn!("Retention cleanup failed: {}", e);
+        }
+        let before = Utc::now() - chrono::Duration::days(deleted_retention_days as i64);
+        if let Err(e) = storage.purge_deleted(before, "system:retention", &Progress::hidden()).await {
+            warn!("Purging deleted events failed: {}", e);
+        }
+    }
+}
+
+/// Summarize completed days and suggestion hours, and re-summarize those
+/// that received late or deleted events, now and then every
+/// `SUMMARY_INTERVAL`.
+pub async fn run_summaries(storage: MetricsStorage) {
+    let mut ticker = tokio::time::interval(SUMMARY_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        if let Err(e) = storage.refresh_daily_summaries().await {
+            warn!("Refreshing daily summaries failed: {}", e);
+        }
+        if let Err(e) = storage.refresh_suggestion_hours().await {
+            warn!("Refreshing suggestion hours failed: {}", e);
+        }
+    }
+}
+
+/// Insert one imported event unless its uid is already present, returning
+/// the new row id.
+async fn insert_imported_event(conn: &mut SqliteConnection, event: &LatencyEvent) -> Result<Option<i64>> {
+    let result = sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO latency_events
+        (timestamp, component_type, event_source, event_kind, duration_us, description, metadata, uid)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(event.timestamp.to_rfc3339())
+    .bind(event.component_type.as_str())
+    .bind(event.event_source.as_str())
+    .bind(event.kind.as_str())
+    .bind(event.duration_us() as i64)
+    .bind(&event.description)
+    .bind(serde_json::to_string(&event.metadata)?)
+    .bind(event_uid(event))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok((result.rows_affected() > 0).then(|| result.last_insert_rowid()))
+}
+
+/// Write recorded histograms to the database every `interval`.
+async fn run_histogram_flusher(storage: MetricsStorage, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick is immediate and there is nothing recorded yet
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        if let Err(e) = storage.flush_histograms().await {
+            warn!("Failed to store latency histograms: {}", e);
+        }
+    }
+}
+
+/// Buffer events from `store_event` and commit them when the batch fills,
+/// the flush interval elapses, or a flush is requested.
+async fn run_writer(
+    storage: MetricsStorage,
+    mut commands: mpsc::Receiver<WriteCommand>,
+    flush_interval: Duration,
+    max_batch_size: usize,
+) {
+    let mut batch = Vec::with_capacity(max_batch_size);
+    let mut ticker = tokio::time::interval(flush_interval);
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(WriteCommand::Event(event)) => {
+                    // High-priority events don't wait for the next tick
+                    let urgent = event.kind.priority() == EventPriority::High;
+                    batch.push(event);
+                    if urgent || batch.len() >= max_batch_size {
+                        let _ = storage.store_batch(&std::mem::take(&mut batch)).await;
+                    }
+                }
+                Some(WriteCommand::Flush(ack)) => {
+                    let _ = ack.send(storage.store_batch(&std::mem::take(&mut batch)).await);
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                let _ = storage.store_batch(&std::mem::take(&mut batch)).await;
+            }
+        }
+    }
+
+    let _ = storage.store_batch(&batch).await;
+}
+
+fn bind_filter<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    binds: &'q [FilterValue],
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    for value in binds {
+        query = match value {
+            FilterValue::Text(text) => query.bind(text),
+            FilterValue::Number(number) => query.bind(number),
+        };
+    }
+    query
+}
+
+fn bind_filter_scalar<'q, T>(
+    mut query: sqlx::query::QueryScalar<'q, Sqlite, T, SqliteArguments<'q>>,
+    binds: &'q [FilterValue],
+) -> sqlx::query::QueryScalar<'q, Sqlite, T, SqliteArguments<'q>> {
+    for value in binds {
+        query = match value {
+            FilterValue::Text(text) => query.bind(text),
+            FilterValue::Number(number) => query.bind(number),
+        };
+    }
+    query
+}
+
+/// Nearest-rank percentile of an ascending slice (`q` in 0.0..=1.0).
+pub fn percentile(sorted: &[u64], q: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Per-(UTC day, component) aggregates of latency events matching
+/// `window`, which compares `timestamp` against `YYYY-MM-DD` binds.
+fn daily_summary_select(window: &str) -> String {
+    format!(
+        r#"
+        WITH ranked AS (
+            SELECT substr(timestamp, 1, 10) AS day, component_type, duration_us,
+                   ROW_NUMBER() OVER (PARTITION BY substr(timestamp, 1, 10), component_type ORDER BY duration_us) AS position,
+                   COUNT(*) OVER (PARTITION BY substr(timestamp, 1, 10), component_type) AS total
+            FROM latency_events
+            WHERE event_kind IN ({}) AND NOT {} AND deleted_at IS NULL AND {}
+        )
+        SELECT day, component_type,
+               COUNT(*) AS event_count,
+               AVG(duration_us) AS avg_us,
+               MIN(CASE WHEN position * 100 >= total * 95 THEN duration_us END) AS p95_us,
+               MAX(duration_us) AS max_us
+        FROM ranked
+        GROUP BY day, component_type
+        "#,
+        latency_kinds_sql(),
+        TEST_DATA_SQL,
+        window
+    )
+}
+
+fn token_metrics_from_row(row: &SqliteRow) -> Result<TokenMetrics> {
+    Ok(TokenMetrics {
+        source: row.get("source"),
+        provider: row.get("provider"),
+        model_type: row.get("model"),
+        estimated_tokens: row.get::<i64, _>("estimated_tokens") as u32,
+        reported_tokens: row.get::<Option<i64>, _>("reported_tokens").map(|tokens| tokens as u32),
+        generation_time_ms: row.get::<i64, _>("generation_time_ms") as u64,
+        time_to_first_token_ms: row.get::<Option<i64>, _>("time_to_first_token_ms").map(|ms| ms as u64),
+        tokens_per_second: row.get("tokens_per_second"),
+        prompt_length: row.get::<i64, _>("prompt_length") as usize,
+        response_length: row.get::<i64, _>("response_length") as usize,
+        timestamp: DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))?.with_timezone(&Utc),
+    })
+}
+
+fn daily_summary_from_row(row: &SqliteRow) -> Result<DailySummary> {
+    Ok(DailySummary {
+        day: NaiveDate::parse_from_str(&row.get::<String, _>("day"), "%Y-%m-%d")?,
+        component: parse_component(&row.get::<String, _>("component_type")),
+        event_count: row.get::<i64, _>("event_count") as u64,
+        avg_ms: row.get::<f64, _>("avg_us") / 1000.0,
+        p95_ms: row.get::<i64, _>("p95_us") as f64 / 1000.0,
+        max_ms: row.get::<i64, _>("max_us") as f64 / 1000.0,
+    })
+}
+
+/// The `YYYY-MM-DD` day after `day`.
+fn next_day(day: &str) -> String {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.succ_opt())
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| day.to_string())
+}
+
+/// Per-(UTC hour, extension, latency band) counts of inline suggestions
+/// matching `window`, which compares `timestamp` against `YYYY-MM-DDTHH`
+/// binds. Accepted and rejected suggestions fall in the band of the latency
+/// they were shown with.
+fn suggestion_hour_select(window: &str) -> String {
+    format!(
+        r#"
+        SELECT hour, extension, {} AS band,
+               SUM(outcome = 'shown') AS shown,
+               SUM(outcome = 'accepted') AS accepted,
+               SUM(outcome = 'rejected') AS rejected,
+               SUM(CASE WHEN outcome = 'shown' THEN latency_us ELSE 0 END) AS latency_us_total,
+               SUM(CASE WHEN outcome = 'accepted' THEN latency_us ELSE 0 END) AS accepted_latency_us_total
+        FROM (
+            SELECT substr(timestamp, 1, 13) AS hour,
+                   COALESCE(extension_id, '') AS extension,
+                   suggestion AS outcome,
+                   CAST(json_extract(metadata, '$.suggestion_latency_ms') * 1000 AS INTEGER) AS latency_us
+            FROM latency_events
+            WHERE suggestion IS NOT NULL
+              AND NOT {} AND deleted_at IS NULL AND {}
+        )
+        WHERE latency_us IS NOT NULL
+        GROUP BY hour, extension, band
+        "#,
+        suggestions::band_sql("latency_us"),
+        TEST_DATA_SQL,
+        window
+    )
+}
+
+fn suggestion_hour_from_row(row: &SqliteRow) -> Result<SuggestionHour> {
+    let hour = row.get::<String, _>("hour");
+    Ok(SuggestionHour {
+        hour: NaiveDateTime::parse_from_str(&format!("{}:00", hour), "%Y-%m-%dT%H:%M")?.and_utc(),
+        extension: row.get("extension"),
+        band: row.get::<i64, _>("band") as usize,
+        shown: row.get::<i64, _>("shown") as u64,
+        accepted: row.get::<i64, _>("accepted") as u64,
+        rejected: row.get::<i64, _>("rejected") as u64,
+        latency_ms_total: row.get::<i64, _>("latency_us_total") as f64 / 1000.0,
+        accepted_latency_ms_total: row.get::<i64, _>("accepted_latency_us_total") as f64 / 1000.0,
+    })
+}
+
+/// UTC hours as stored in `suggestion_hours`, a prefix of event timestamps.
+const HOUR_FORMAT: &str = "%Y-%m-%dT%H";
+
+/// The `YYYY-MM-DDTHH` hour after `hour`.
+fn next_hour(hour: &str) -> String {
+    NaiveDateTime::parse_from_str(&format!("{}:00", hour), "%Y-%m-%dT%H:%M")
+        .map(|h| (h + chrono::Duration::hours(1)).format(HOUR_FORMAT).to_string())
+        .unwrap_or_else(|_| hour.to_string())
+}
+
+/// SQL list of event kinds whose durations are latencies, for `IN (...)` filters.
+pub(crate) fn latency_kinds_sql() -> String {
+    EventKind::ALL
+        .iter()
+        .filter(|kind| kind.carries_latency())
+        .map(|kind| format!("'{}'", kind.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ComponentType, EventSource};
+
+    fn event_days_ago(days: i64, duration_ms: u64) -> LatencyEvent {
+        let mut event = LatencyEvent::new(
+            ComponentType::VSCode,
+            EventSource::CommandExecution,
+            Duration::from_millis(duration_ms),
+            "command".to_string(),
+        );
+        event.timestamp = Utc::now() - chrono::Duration::days(days);
+        event
+    }
+
+    async fn summarized_count(storage: &MetricsStorage, days: i64) -> Option<i64> {
+        let day = (Utc::now() - chrono::Duration::days(days)).format("%Y-%m-%d").to_string();
+        sqlx::query_scalar("SELECT event_count FROM daily_summaries WHERE day = ?")
+            .bind(day)
+            .fetch_optional(&storage.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn late_and_deleted_events_resummarize_their_day() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-summaries-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db")).await.unwrap();
+
+        storage
+            .import_events(&[event_days_ago(4, 10), event_days_ago(2, 20)])
+            .await
+            .unwrap();
+        storage.refresh_daily_summaries().await.unwrap();
+        assert_eq!(summarized_count(&storage, 2).await, Some(1));
+
+        // Reading never summarizes
+        storage.import_events(&[event_days_ago(2, 30)]).await.unwrap();
+        storage.get_daily_summaries(Utc::now().date_naive(), None).await.unwrap();
+        assert_eq!(summarized_count(&storage, 2).await, Some(1));
+
+        storage.refresh_daily_summaries().await.unwrap();
+        assert_eq!(summarized_count(&storage, 2).await, Some(2));
+
+        let late: i64 = sqlx::query_scalar("SELECT MAX(id) FROM latency_events")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert!(storage.delete_event(late).await.unwrap());
+        storage.refresh_daily_summaries().await.unwrap();
+        assert_eq!(summarized_count(&storage, 2).await, Some(1));
+
+        // The oldest day may have lost events to retention, so it keeps its summary
+        let oldest: i64 = sqlx::query_scalar("SELECT MIN(id) FROM latency_events")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert!(storage.delete_event(oldest).await.unwrap());
+        storage.refresh_daily_summaries().await.unwrap();
+        assert_eq!(summarized_count(&storage, 4).await, Some(1));
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletions_are_logged_once_replication_starts() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-deletions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db")).await.unwrap();
+
+        storage
+            .import_events(&[event_days_ago(1, 10), event_days_ago(1, 20)])
+            .await
+            .unwrap();
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM latency_events ORDER BY id")
+            .fetch_all(&storage.pool)
+            .await
+            .unwrap();
+
+        // Nothing to replicate to yet
+        assert!(storage.delete_event(ids[0]).await.unwrap());
+        assert!(storage.get_pending_deletions(10).await.unwrap().is_empty());
+
+        storage.set_sync_watermark("backend:postgres", "", ids[1]).await.unwrap();
+        assert!(storage.delete_event(ids[1]).await.unwrap());
+        assert_eq!(storage.restore_events(&[ids[0]]).await.unwrap(), 1);
+        let pending = storage.get_pending_deletions(10).await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert!(pending[0].1.deleted_at.is_some());
+        assert!(pending[1].1.deleted_at.is_none());
+
+        storage.clear_pending_deletions(pending[1].0).await.unwrap();
+        assert!(storage.get_pending_deletions(10).await.unwrap().is_empty());
+
+        // Replaying the same changes is a no-op
+        let deletions: Vec<EventDeletion> = pending.into_iter().map(|(_, deletion)| deletion).collect();
+        assert_eq!(storage.apply_deletions(&deletions).await.unwrap(), 0);
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleted_events_leave_stored_histograms() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-histograms-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db"))
+            .await
+            .unwrap()
+            .with_latency_histograms(&HistogramConfig::default());
+
+        // Periods before the oldest live event keep their counts
+        storage.import_events(&[event_days_ago(1, 5)]).await.unwrap();
+        storage.store_event(&event_days_ago(0, 10)).await.unwrap();
+        storage.store_event(&event_days_ago(0, 20)).await.unwrap();
+        storage.flush().await.unwrap();
+        let stored = || async {
+            sqlx::query_scalar::<_, i64>("SELECT SUM(event_count) FROM latency_histograms")
+                .fetch_one(&storage.pool)
+                .await
+                .unwrap()
+        };
+        assert_eq!(stored().await, 2);
+
+        let first: i64 = sqlx::query_scalar("SELECT MIN(id) FROM latency_events WHERE duration_us = 10000")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert!(storage.delete_event(first).await.unwrap());
+        storage.flush().await.unwrap();
+        assert_eq!(stored().await, 1);
+
+        assert_eq!(storage.restore_events(&[first]).await.unwrap(), 1);
+        storage.flush().await.unwrap();
+        assert_eq!(stored().await, 2);
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn histograms_count_events_in_the_period_they_happened() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-periods-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db"))
+            .await
+            .unwrap()
+            .with_latency_histograms(&HistogramConfig::default());
+
+        let late = event_days_ago(2, 10);
+        storage.store_event(&late).await.unwrap();
+        let recent = event_days_ago(0, 20);
+        let mut same_period = event_days_ago(0, 30);
+        same_period.timestamp = recent.timestamp;
+        storage.store_event(&recent).await.unwrap();
+        storage.store_event(&same_period).await.unwrap();
+        storage.flush().await.unwrap();
+
+        let periods: Vec<(String, String, i64)> =
+            sqlx::query_as("SELECT timestamp, period_end, event_count FROM latency_histograms ORDER BY timestamp")
+                .fetch_all(&storage.pool)
+                .await
+                .unwrap();
+        assert_eq!(periods.len(), 2);
+        let (start, end, count) = &periods[0];
+        assert!(*start <= late.timestamp.to_rfc3339() && late.timestamp.to_rfc3339() < *end);
+        assert_eq!(*count, 1);
+        assert_eq!(periods[1].2, 2);
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn suggestion_hours_ago(hours: i64, outcome: &str, latency_ms: Option<f64>) -> LatencyEvent {
+        let mut event = LatencyEvent::new(
+            ComponentType::GitHubCopilot,
+            EventSource::UserInteraction,
+            Duration::ZERO,
+            format!("Suggestion {}", outcome),
+        )
+        .with_kind(EventKind::SuggestionOutcome)
+        .with_metadata(serde_json::json!({ "suggestion": outcome, "suggestion_latency_ms": latency_ms }));
+        event.timestamp = Utc::now() - chrono::Duration::hours(hours);
+        event
+    }
+
+    #[tokio::test]
+    async fn late_suggestions_recount_their_hour() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-suggestions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db")).await.unwrap();
+
+        storage
+            .import_events(&[
+                suggestion_hours_ago(5, "shown", Some(80.0)),
+                suggestion_hours_ago(3, "shown", Some(300.0)),
+            ])
+            .await
+            .unwrap();
+        storage.refresh_suggestion_hours().await.unwrap();
+
+        // An accept without the latency it was shown with can't be banded
+        storage
+            .import_events(&[
+                suggestion_hours_ago(3, "accepted", Some(300.0)),
+                suggestion_hours_ago(3, "accepted", None),
+            ])
+            .await
+            .unwrap();
+        storage.refresh_suggestion_hours().await.unwrap();
+
+        let since = Utc::now() - chrono::Duration::hours(4);
+        let hours = storage.get_suggestion_hours(Some(since), None, None).await.unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!((hours[0].band, hours[0].shown, hours[0].accepted), (2, 1, 1));
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn throughput_leaves_out_responses_without_generation_time() {
+        let dir = std::env::temp_dir().join(format!("vscode-latency-monitor-throughput-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = MetricsStorage::new(&dir.join("metrics.db")).await.unwrap();
+
+        let response = "word ".repeat(40);
+        for generation_time_ms in [0, 1000, 2000] {
+            let metrics =
+                TokenMetrics::new("model_proxy", "openai", "gpt".to_string(), &response, generation_time_ms, 10);
+            storage.store_token_metrics(&metrics).await.unwrap();
+        }
+
+        let throughput = storage.get_model_throughput(None, None, None).await.unwrap();
+        assert_eq!(throughput.len(), 1);
+        assert_eq!(throughput[0].responses, 3);
+        assert_eq!(throughput[0].p50_tokens_per_second, Some(20.0));
+        assert_eq!(throughput[0].p5_tokens_per_second, Some(20.0));
+        assert_eq!(throughput[0].avg_tokens_per_second, Some(30.0));
+
+        storage.pool.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
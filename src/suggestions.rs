@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::models::SuggestionHour;
+use crate::stats::pearson;
+use crate::storage::MetricsStorage;
+use crate::units;
+
+/// Upper bounds of the latency bands suggestions are counted in; the last
+/// band holds everything slower.
+pub const LATENCY_BANDS_MS: &[u64] = &[100, 250, 500, 1000, 2000];
+
+/// Hours with fewer suggestions shown are left out of the correlation,
+/// their acceptance rate being mostly noise.
+const MIN_SHOWN_FOR_CORRELATION: u64 = 5;
+
+/// What became of an inline suggestion, as reported by the companion
+/// extension and stored in `metadata.suggestion`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionOutcome {
+    /// Displayed as ghost text; its latency is the time from the keystroke
+    Shown,
+    /// Taken with Tab or a partial accept
+    Accepted,
+    /// Dismissed, or typed over
+    Rejected,
+}
+
+impl SuggestionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuggestionOutcome::Shown => "shown",
+            SuggestionOutcome::Accepted => "accepted",
+            SuggestionOutcome::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "shown" => Ok(SuggestionOutcome::Shown),
+            "accepted" => Ok(SuggestionOutcome::Accepted),
+            "rejected" => Ok(SuggestionOutcome::Rejected),
+            _ => Err(anyhow!("unknown suggestion outcome '{}' (shown, accepted, rejected)", value)),
+        }
+    }
+}
+
+/// `< 100 ms`, `100-250 ms`, ... `>= 2000 ms`.
+pub fn band_label(band: usize) -> String {
+    match (band.checked_sub(1).and_then(|lower| LATENCY_BANDS_MS.get(lower)), LATENCY_BANDS_MS.get(band)) {
+        (None, Some(upper)) => format!("< {} ms", upper),
+        (Some(lower), Some(upper)) => format!("{}-{} ms", lower, upper),
+        (Some(lower), None) => format!(">= {} ms", lower),
+        (None, None) => "all".to_string(),
+    }
+}
+
+/// SQL expression of the band index of a latency in microseconds.
+pub(crate) fn band_sql(latency_us: &str) -> String {
+    let cases: String = LATENCY_BANDS_MS
+        .iter()
+        .enumerate()
+        .map(|(band, upper)| format!("WHEN {} < {} THEN {} ", latency_us, upper * 1000, band))
+        .collect();
+    format!("CASE {}ELSE {} END", cases, LATENCY_BANDS_MS.len())
+}
+
+/// Suggestion counts with the acceptance rate and latencies derived from
+/// them.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct AcceptanceStats {
+    pub shown: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Share of the suggestions shown that were accepted
+    pub acceptance_percent: Option<f64>,
+    /// Mean latency of the suggestions shown
+    pub avg_latency_ms: Option<f64>,
+    pub avg_accepted_latency_ms: Option<f64>,
+    /// Time spent waiting for suggestions that weren't accepted
+    pub unaccepted_wait_ms: f64,
+    #[serde(skip)]
+    latency_ms_total: f64,
+    #[serde(skip)]
+    accepted_latency_ms_total: f64,
+}
+
+impl AcceptanceStats {
+    fn add(&mut self, hour: &SuggestionHour) {
+        self.shown += hour.shown;
+        self.accepted += hour.accepted;
+        self.rejected += hour.rejected;
+        self.latency_ms_total += hour.latency_ms_total;
+        self.accepted_latency_ms_total += hour.accepted_latency_ms_total;
+    }
+
+    fn finish(mut self) -> Self {
+        let mean = |total: f64, count: u64| (count > 0).then(|| total / count as f64);
+        self.acceptance_percent =
+            (self.shown > 0).then(|| (self.accepted as f64 / self.shown as f64 * 100.0).min(100.0));
+        self.avg_latency_ms = mean(self.latency_ms_total, self.shown);
+        self.avg_accepted_latency_ms = mean(self.accepted_latency_ms_total, self.accepted);
+        self.unaccepted_wait_ms = (self.latency_ms_total - self.accepted_latency_ms_total).max(0.0);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BandAcceptance {
+    pub band: String,
+    #[serde(flatten)]
+    pub stats: AcceptanceStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HourAcceptance {
+    pub hour: DateTime<Utc>,
+    #[serde(flatten)]
+    pub stats: AcceptanceStats,
+}
+
+/// How often inline suggestions were accepted, overall, per latency band and
+/// per hour, from the hourly aggregates in `suggestion_hours`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AcceptanceReport {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only suggestions of this extension, e.g. `github.copilot`
+    pub extension: Option<String>,
+    pub total: AcceptanceStats,
+    pub by_latency: Vec<BandAcceptance>,
+    pub by_hour: Vec<HourAcceptance>,
+    /// Pearson correlation of each hour's mean latency with its acceptance
+    /// rate; negative when slower hours see fewer suggestions accepted
+    pub latency_correlation: Option<f64>,
+}
+
+pub async fn acceptance_report(
+    storage: &MetricsStorage,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    extension: Option<&str>,
+) -> Result<AcceptanceReport> {
+    let hours = storage.get_suggestion_hours(since, until, extension).await?;
+
+    let mut total = AcceptanceStats::default();
+    let mut bands = vec![AcceptanceStats::default(); LATENCY_BANDS_MS.len() + 1];
+    let mut by_hour: BTreeMap<DateTime<Utc>, AcceptanceStats> = BTreeMap::new();
+    for hour in &hours {
+        total.add(hour);
+        if let Some(band) = bands.get_mut(hour.band) {
+            band.add(hour);
+        }
+        by_hour.entry(hour.hour).or_default().add(hour);
+    }
+
+    let by_hour: Vec<HourAcceptance> = by_hour
+        .into_iter()
+        .map(|(hour, stats)| HourAcceptance {
+            hour,
+            stats: stats.finish(),
+        })
+        .collect();
+    let (latencies, rates): (Vec<f64>, Vec<f64>) = by_hour
+        .iter()
+        .filter(|hour| hour.stats.shown >= MIN_SHOWN_FOR_CORRELATION)
+        .filter_map(|hour| Some((hour.stats.avg_latency_ms?, hour.stats.acceptance_percent?)))
+        .unzip();
+
+    Ok(AcceptanceReport {
+        since,
+        until,
+        extension: extension.map(str::to_string),
+        total: total.finish(),
+        by_latency: bands
+            .into_iter()
+            .enumerate()
+            .map(|(band, stats)| BandAcceptance {
+                band: band_label(band),
+                stats: stats.finish(),
+            })
+            .collect(),
+        by_hour,
+        latency_correlation: pearson(&latencies, &rates),
+    })
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map(units::format_percent).unwrap_or_else(|| "-".to_string())
+}
+
+fn latency(value: Option<f64>) -> String {
+    value.map(units::format_ms).unwrap_or_else(|| "-".to_string())
+}
+
+impl fmt::Display for AcceptanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = &self.total;
+        writeln!(
+            f,
+            "Suggestions: {} shown, {} accepted, {} rejected ({} accepted)",
+            total.shown,
+            total.accepted,
+            total.rejected,
+            percent(total.acceptance_percent)
+        )?;
+        if total.shown == 0 {
+            return Ok(());
+        }
+        writeln!(
+            f,
+            "Latency: {} on average, {} for accepted suggestions; {} spent waiting for suggestions not accepted",
+            latency(total.avg_latency_ms),
+            latency(total.avg_accepted_latency_ms),
+            units::format_ms(total.unaccepted_wait_ms)
+        )?;
+        match self.latency_correlation {
+            Some(r) => writeln!(f, "Hourly latency vs acceptance rate: r = {:.2}", r)?,
+            None => writeln!(f, "Hourly latency vs acceptance rate: not enough busy hours")?,
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>9} {:>9} {:>10} {:>14}",
+            "Latency", "Shown", "Accepted", "Rejected", "Accepted %", "Unaccepted wait"
+        )?;
+        for band in self.by_latency.iter().filter(|band| band.stats.shown + band.stats.accepted > 0) {
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>9} {:>9} {:>10} {:>14}",
+                band.band,
+                band.stats.shown,
+                band.stats.accepted,
+                band.stats.rejected,
+                percent(band.stats.acceptance_percent),
+                units::format_ms(band.stats.unaccepted_wait_ms)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePool;
+
+    fn hour(band: usize, shown: u64, accepted: u64, latency_ms: f64, accepted_latency_ms: f64) -> SuggestionHour {
+        SuggestionHour {
+            hour: Utc::now(),
+            extension: "github.copilot".to_string(),
+            band,
+            shown,
+            accepted,
+            rejected: shown - accepted,
+            latency_ms_total: latency_ms,
+            accepted_latency_ms_total: accepted_latency_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn band_sql_agrees_with_the_band_bounds() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        for (latency_us, band) in [(0, 0), (99_999, 0), (100_000, 1), (499_999, 2), (1_500_000, 4), (2_000_000, 5)] {
+            let sql = format!("SELECT {}", band_sql(&latency_us.to_string()));
+            let found: i64 = sqlx::query_scalar(&sql).fetch_one(&pool).await.unwrap();
+            assert_eq!(found, band, "{}us", latency_us);
+        }
+        pool.close().await;
+    }
+
+    #[test]
+    fn bands_are_labelled_by_their_bounds() {
+        assert_eq!(band_label(0), "< 100 ms");
+        assert_eq!(band_label(1), "100-250 ms");
+        assert_eq!(band_label(LATENCY_BANDS_MS.len()), ">= 2000 ms");
+        assert_eq!(band_label(LATENCY_BANDS_MS.len() + 1), "all");
+    }
+
+    #[test]
+    fn stats_are_derived_from_summed_hours() {
+        let mut stats = AcceptanceStats::default();
+        stats.add(&hour(0, 10, 4, 800.0, 200.0));
+        stats.add(&hour(3, 10, 1, 7200.0, 600.0));
+        let stats = stats.finish();
+
+        assert_eq!((stats.shown, stats.accepted, stats.rejected), (20, 5, 15));
+        assert_eq!(stats.acceptance_percent, Some(25.0));
+        assert_eq!(stats.avg_latency_ms, Some(400.0));
+        assert_eq!(stats.avg_accepted_latency_ms, Some(160.0));
+        assert_eq!(stats.unaccepted_wait_ms, 7200.0);
+
+        let empty = AcceptanceStats::default().finish();
+        assert_eq!(empty.acceptance_percent, None);
+        assert_eq!(empty.avg_latency_ms, None);
+        assert_eq!(empty.unaccepted_wait_ms, 0.0);
+    }
+
+    #[test]
+    fn acceptance_never_exceeds_every_suggestion_shown() {
+        // Accepts reported without their shown event, e.g. across a restart
+        let mut stats = AcceptanceStats::default();
+        stats.add(&SuggestionHour {
+            accepted: 3,
+            rejected: 0,
+            ..hour(0, 2, 0, 0.0, 0.0)
+        });
+        assert_eq!(stats.finish().acceptance_percent, Some(100.0));
+    }
+
+    #[test]
+    fn outcomes_parse_without_case() {
+        assert_eq!(SuggestionOutcome::parse("Accepted").unwrap(), SuggestionOutcome::Accepted);
+        for outcome in [SuggestionOutcome::Shown, SuggestionOutcome::Accepted, SuggestionOutcome::Rejected] {
+            assert_eq!(SuggestionOutcome::parse(outcome.as_str()).unwrap(), outcome);
+        }
+        assert!(SuggestionOutcome::parse("ignored").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use tracing::info;
+
+use crate::models::SyncedEvent;
+use crate::progress::Progress;
+use crate::storage::MetricsStorage;
+
+/// Events fetched per round trip.
+const SYNC_BATCH_SIZE: u32 = 1000;
+
+#[derive(Debug, Default)]
+pub struct SyncStats {
+    pub fetched: u64,
+    pub inserted: u64,
+}
+
+/// Where `sync --from` reads events: another monitor's database file, or a
+/// running monitor's dashboard (`http://host:port`).
+enum SyncSource {
+    Database(MetricsStorage),
+    Remote { base_url: String, client: reqwest::Client },
+}
+
+impl SyncSource {
+    async fn open(from: &str) -> Result<Self> {
+        if from.starts_with("http://") || from.starts_with("https://") {
+            Ok(SyncSource::Remote {
+                base_url: from.trim_end_matches('/').to_string(),
+                client: reqwest::Client::new(),
+            })
+        } else {
+            let path = Path::new(from);
+            if !path.exists() {
+                return Err(anyhow!("Sync source {} does not exist", path.display()));
+            }
+            Ok(SyncSource::Database(MetricsStorage::open_readonly(path).await?))
+        }
+    }
+
+    async fn fetch(&self, after_timestamp: &str, after_id: i64) -> Result<Vec<SyncedEvent>> {
+        match self {
+            SyncSource::Database(storage) => {
+                storage
+                    .get_events_after_watermark(after_timestamp, after_id, SYNC_BATCH_SIZE)
+                    .await
+            }
+            SyncSource::Remote { base_url, client } => {
+                let response = client
+                    .get(format!("{}/api/sync/events", base_url))
+                    .query(&[
+                        ("after_timestamp", after_timestamp.to_string()),
+                        ("after_id", after_id.to_string()),
+                        ("limit", SYNC_BATCH_SIZE.to_string()),
+                    ])
+                    .send()
+                    .await?;
+
+                if !response.status().is_success() {
+                    return Err(anyhow!("{} returned {}", base_url, response.status()));
+                }
+                Ok(response.json().await?)
+            }
+        }
+    }
+}
+
+/// Copy events newer than the stored watermark for `from` (or `since`, if
+/// later) into `storage`, advancing the watermark after every batch so an
+/// interrupted sync resumes where it stopped. Cancelling `progress` stops
+/// it after the current batch, returning what was copied so far.
+pub async fn sync_from(
+    storage: &MetricsStorage,
+    from: &str,
+    since: Option<DateTime<Utc>>,
+    progress: &Progress,
+) -> Result<SyncStats> {
+    let source = SyncSource::open(from).await?;
+    let source_key = source_key(from);
+
+    let (mut after_timestamp, mut after_id) = storage
+        .get_sync_watermark(&source_key)
+        .await?
+        .unwrap_or_else(|| (String::new(), 0));
+
+    if let Some(since) = since {
+        let since = since.to_rfc3339();
+        if since > after_timestamp {
+            after_timestamp = since;
+            after_id = 0;
+        }
+    }
+
+    let mut stats = SyncStats::default();
+    loop {
+        let events = source.fetch(&after_timestamp, after_id).await?;
+        let Some(last) = events.last() else { break };
+
+        after_timestamp = last.event.timestamp.to_rfc3339();
+        after_id = last.event.id.unwrap_or(after_id);
+
+        stats.fetched += events.len() as u64;
+        stats.inserted += storage.import_synced_events(&events).await?;
+        storage
+            .set_sync_watermark(&source_key, &after_timestamp, after_id)
+            .await?;
+        progress.inc(events.len() as u64);
+
+        if (events.len() as u32) < SYNC_BATCH_SIZE || progress.is_cancelled() {
+            break;
+        }
+    }
+
+    info!(
+        "Synced from {}: {} fetched, {} new",
+        from, stats.fetched, stats.inserted
+    );
+    Ok(stats)
+}
+
+/// Watermarks are keyed by canonical path so `./a.db` and `/abs/a.db` match.
+fn source_key(from: &str) -> String {
+    if from.starts_with("http://") || from.starts_with("https://") {
+        from.trim_end_matches('/').to_string()
+    } else {
+        std::fs::canonicalize(from)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| from.to_string())
+    }
+}
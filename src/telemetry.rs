@@ -2,6 +2,7 @@ use anyhow::Result;
 use axum::{
     extract::State,
     http::StatusCode,
+    middleware,
     response::Json,
     routing::get,
     Router,
@@ -13,7 +14,9 @@ use tower_http::cors::CorsLayer;
 use tracing::info;
 
 use crate::config::Config;
+use crate::platform;
 use crate::storage::MetricsStorage;
+use crate::units;
 
 #[derive(Clone)]
 pub struct TelemetryServer {
@@ -31,7 +34,10 @@ struct TelemetryState {
 
 impl TelemetryServer {
     pub async fn new(config: Config, storage: MetricsStorage) -> Result<Self> {
-        let lan_ip = get_lan_ip().await?;
+        // Fall back to localhost if no route to the LAN can be found
+        let lan_ip = platform::lan_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
         info!("Detected LAN IP: {}", lan_ip);
         
         Ok(Self {
@@ -56,6 +62,7 @@ impl TelemetryServer {
             .route("/api/system/resources", get(api_system_resources))
             .route("/api/monitoring/status", get(api_monitoring_status))
             .route("/health", get(telemetry_health))
+            .layer(middleware::from_fn(units::formatted_units))
             .layer(CorsLayer::permissive())
             .with_state(state);
 
@@ -73,29 +80,19 @@ impl TelemetryServer {
     }
 }
 
-async fn get_lan_ip() -> Result<String> {
-    // Try to get the LAN IP using a simple approach
-    use std::process::Command;
-    
-    let output = Command::new("ip")
-        .args(&["route", "get", "8.8.8.8"])
-        .output()?;
-    
-    let output_str = String::from_utf8(output.stdout)?;
-    for line in output_str.lines() {
-        if let Some(src_pos) = line.find("src ") {
-            let ip_start = src_pos + 4;
-            if let Some(ip_end) = line[ip_start..].find(' ') {
-                return Ok(line[ip_start..ip_start + ip_end].to_string());
-            }
-        }
-    }
-    
-    // Fallback to localhost if we can't detect LAN IP
-    Ok("127.0.0.1".to_string())
-}
-
 async fn telemetry_home(State(state): State<TelemetryState>) -> axum::response::Html<String> {
+    // Served by the running monitor; see `notifications`
+    let integrations = &state.config.integrations;
+    let notifications_link = if integrations.wall_notification_system && integrations.notifications.port != 0 {
+        format!(
+            r#"<div class="endpoint">
+                    <a href="http://localhost:{}">🏠 Wall Notifications (Port {}, this machine only)</a>
+                </div>"#,
+            integrations.notifications.port, integrations.notifications.port
+        )
+    } else {
+        String::new()
+    };
     let html = format!(r#"
 <!DOCTYPE html>
 <html>
@@ -253,9 +250,7 @@ async fn telemetry_home(State(state): State<TelemetryState>) -> axum::response::
                 <div class="endpoint">
                     <a href="http://{}:3030">🎛️ Main Dashboard (Port 3030)</a>
                 </div>
-                <div class="endpoint">
-                    <a href="http://{}:8888">🏠 Wall Notifications (Port 8888)</a>
-                </div>
+                {}
                 <div class="info">
                     <strong>Integration:</strong> This telemetry service provides machine-readable data for external monitoring systems, dashboards, and automation tools.
                 </div>
@@ -302,7 +297,7 @@ async fn telemetry_home(State(state): State<TelemetryState>) -> axum::response::
         state.lan_ip,    // LAN Access code
         state.lan_ip,    // Local IP
         state.lan_ip,    // Dashboard link  
-        state.lan_ip,    // Wall notifications link
+        notifications_link,
         chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")  // Timestamp
     );
 
@@ -319,6 +314,9 @@ async fn api_telemetry(State(state): State<TelemetryState>) -> Result<Json<serde
     let performance_metrics = state.storage.get_performance_metrics().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let derived_metrics = state.storage.get_latest_derived_metrics().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(Json(json!({
         "service": "vscode-latency-monitor-telemetry",
         "version": "1.0.0",
@@ -327,6 +325,7 @@ async fn api_telemetry(State(state): State<TelemetryState>) -> Result<Json<serde
         "system_status": system_status,
         "recent_events": recent_events,
         "performance_metrics": performance_metrics,
+        "derived_metrics": derived_metrics,
         "telemetry_metadata": {
             "collection_interval": "real-time",
             "data_retention": "persistent",
@@ -406,9 +405,12 @@ async fn api_system_resources(State(_state): State<TelemetryState>) -> Result<Js
 async fn api_monitoring_status(State(state): State<TelemetryState>) -> Result<Json<serde_json::Value>, StatusCode> {
     let status = state.storage.get_system_status().await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let event_queue = state.storage.get_event_queue_stats().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(json!({
         "monitoring_status": status,
+        "event_queue": event_queue,
         "telemetry_info": {
             "lan_accessible": true,
             "lan_ip": state.lan_ip,
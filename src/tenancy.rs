@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::config::StorageConfig;
+use crate::storage::{run_retention, run_summaries, MetricsStorage};
+
+/// Serving several teams from one dashboard, configured as
+/// `[dashboard.tenancy]`. Each tenant's events live in a database of their
+/// own, and API tokens created with `--tenant` only ever see that database.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenancyConfig {
+    pub enabled: bool,
+    /// Where tenant databases are kept, one `<tenant>.db` each (default:
+    /// `tenants/` next to `storage.database_path`)
+    pub data_dir: Option<PathBuf>,
+}
+
+/// Tenant names become file names, so only `[a-z0-9_-]` are allowed.
+pub fn validate_tenant(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "Invalid tenant '{}': use 1-64 lowercase letters, digits, '-' and '_'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Tenant databases, opened on first use and kept open.
+pub struct Tenants {
+    data_dir: PathBuf,
+    retention_days: u32,
+    deleted_retention_days: u32,
+    open: Mutex<HashMap<String, MetricsStorage>>,
+}
+
+impl Tenants {
+    pub fn new(config: &TenancyConfig, storage: &StorageConfig) -> Self {
+        let data_dir = config.data_dir.clone().unwrap_or_else(|| {
+            storage
+                .database_path
+                .parent()
+                .map(|dir| dir.join("tenants"))
+                .unwrap_or_else(|| PathBuf::from("tenants"))
+        });
+        Self {
+            data_dir,
+            retention_days: storage.retention_days,
+            deleted_retention_days: storage.deleted_retention_days,
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn database_path(&self, tenant: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.db", tenant))
+    }
+
+    /// The tenant's database, created on first use. Nothing else prunes or
+    /// summarizes it, so retention and summaries run alongside it for as
+    /// long as the server does.
+    pub async fn storage(&self, tenant: &str) -> Result<MetricsStorage> {
+        validate_tenant(tenant)?;
+        let mut open = self.open.lock().await;
+        if let Some(storage) = open.get(tenant) {
+            return Ok(storage.clone());
+        }
+
+        std::fs::create_dir_all(&self.data_dir)?;
+        let path = self.database_path(tenant);
+        let storage = MetricsStorage::new(&path).await?;
+        tokio::spawn(run_retention(storage.clone(), self.retention_days, self.deleted_retention_days));
+        tokio::spawn(run_summaries(storage.clone()));
+        info!("Opened database for tenant '{}' at {}", tenant, path.display());
+
+        open.insert(tenant.to_string(), storage.clone());
+        Ok(storage)
+    }
+}
@@ -0,0 +1,482 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::filter::{EventFilter, FilterValue, SqlFilter};
+use crate::storage::TEST_DATA_SQL;
+
+/// A `[since, until)` window over event timestamps; either end may be open.
+/// Working hours and a filter can narrow it further to the events that count.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only count events inside these hours
+    pub working_hours: Option<WorkingHours>,
+    /// Only count events matching this filter
+    pub filter: Option<EventFilter>,
+    /// Count events recorded by `test` too, which are left out by default
+    pub include_test_data: bool,
+}
+
+impl TimeRange {
+    /// Parse `--since`/`--until` expressions relative to now.
+    pub fn parse(since: Option<&str>, until: Option<&str>) -> Result<Self> {
+        let now = Utc::now();
+        let range = Self {
+            since: since.map(|s| parse_time_expr(s, now)).transpose()?,
+            until: until.map(|u| parse_time_expr(u, now)).transpose()?,
+            working_hours: None,
+            filter: None,
+            include_test_data: false,
+        };
+
+        if let (Some(since), Some(until)) = (range.since, range.until) {
+            if since >= until {
+                return Err(anyhow!("--since ({}) must be before --until ({})", since, until));
+            }
+        }
+        Ok(range)
+    }
+
+    /// The last `duration` up to now; longer than chrono can represent
+    /// means all time.
+    pub fn last(duration: Duration) -> Self {
+        let now = Utc::now();
+        Self {
+            since: Some(now.checked_sub_signed(duration).unwrap_or(DateTime::<Utc>::MIN_UTC)),
+            until: Some(now),
+            working_hours: None,
+            filter: None,
+            include_test_data: false,
+        }
+    }
+
+    /// The same window without events outside `hours`.
+    pub fn within_working_hours(&self, hours: WorkingHours) -> Self {
+        Self {
+            working_hours: Some(hours),
+            ..self.clone()
+        }
+    }
+
+    /// The same window without events that don't match `filter`.
+    pub fn matching(&self, filter: EventFilter) -> Self {
+        Self {
+            filter: Some(filter),
+            ..self.clone()
+        }
+    }
+
+    /// The same window with test data counted.
+    pub fn with_test_data(&self) -> Self {
+        Self {
+            include_test_data: true,
+            ..self.clone()
+        }
+    }
+
+    /// SQL predicate over `latency_events`, always leaving out soft-deleted
+    /// events.
+    pub fn to_sql(&self) -> SqlFilter {
+        let (mut clauses, mut binds) = self.bounds();
+        if let Some(hours) = &self.working_hours {
+            let filter = hours.to_sql();
+            clauses.push(filter.clause);
+            binds.extend(filter.binds);
+        }
+        // Filters are compiled once when parsed, so this can't fail here
+        if let Some(Ok(filter)) = self.filter.as_ref().map(EventFilter::to_sql) {
+            clauses.push(filter.clause);
+            binds.extend(filter.binds);
+        }
+        if !self.include_test_data {
+            clauses.push(format!("NOT {}", TEST_DATA_SQL));
+        }
+        // Soft-deleted events are out of every range until restored
+        clauses.push("deleted_at IS NULL".to_string());
+
+        SqlFilter {
+            clause: clauses.join(" AND "),
+            binds,
+        }
+    }
+
+    /// Only the `since`/`until` bounds on `timestamp`, for tables other than
+    /// `latency_events`.
+    pub fn bounds_sql(&self) -> SqlFilter {
+        let (clauses, binds) = self.bounds();
+        SqlFilter {
+            clause: if clauses.is_empty() {
+                "1 = 1".to_string()
+            } else {
+                clauses.join(" AND ")
+            },
+            binds,
+        }
+    }
+
+    fn bounds(&self) -> (Vec<String>, Vec<FilterValue>) {
+        let mut clauses = Vec::new();
+        let mut binds = Vec::new();
+        if let Some(since) = self.since {
+            clauses.push("timestamp >= ?".to_string());
+            binds.push(FilterValue::Text(since.to_rfc3339()));
+        }
+        if let Some(until) = self.until {
+            clauses.push("timestamp < ?".to_string());
+            binds.push(FilterValue::Text(until.to_rfc3339()));
+        }
+        (clauses, binds)
+    }
+
+    /// Length of the window, with open ends clamped to `first_event` and now.
+    pub fn seconds(&self, first_event: Option<DateTime<Utc>>) -> f64 {
+        let until = self.until.unwrap_or_else(Utc::now);
+        let since = self.since.or(first_event).unwrap_or(until);
+        (until - since).num_milliseconds().max(1000) as f64 / 1000.0
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |t: DateTime<Utc>| t.format("%Y-%m-%d %H:%M UTC").to_string();
+        match (self.since, self.until) {
+            (Some(since), Some(until)) => write!(f, "{} to {}", format(since), format(until)),
+            (Some(since), None) => write!(f, "since {}", format(since)),
+            (None, Some(until)) => write!(f, "until {}", format(until)),
+            (None, None) => write!(f, "all time"),
+        }?;
+        if self.working_hours.is_some() {
+            write!(f, " (working hours only)")?;
+        }
+        if let Some(filter) = &self.filter {
+            write!(f, " where {}", filter)?;
+        }
+        if self.include_test_data {
+            write!(f, " (including test data)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a point in time: a duration ago (`30m`, `24h`, `7d`, `2w`), `now`,
+/// an RFC 3339 timestamp, or a `YYYY-MM-DD` date (midnight UTC).
+pub fn parse_time_expr(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("now") {
+        return Ok(now);
+    }
+    if let Some(duration) = parse_duration(value) {
+        return now
+            .checked_sub_signed(duration)
+            .ok_or_else(|| anyhow!("Time '{}' is too far in the past", value));
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| {
+            anyhow!(
+                "Invalid time '{}' (expected e.g. 30m, 24h, 7d, 2w, RFC 3339 or YYYY-MM-DD)",
+                value
+            )
+        })
+}
+
+/// `<number><unit>` with unit s, m, h, d or w.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    if amount < 0 {
+        return None;
+    }
+
+    match unit {
+        's' => Duration::try_seconds(amount),
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Which local hours count as the working day, so aggregations can leave
+/// out overnight and weekend samples (mostly idle polling). Configured
+/// under `[working_hours]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkingHoursConfig {
+    /// Start of the working day, `HH:MM` local time
+    pub start: String,
+    /// End of the working day (exclusive); before `start` for shifts that
+    /// run past midnight
+    pub end: String,
+    /// Working days, e.g. `["Mon", "Tue"]`
+    pub days: Vec<Weekday>,
+    /// Minutes east of UTC; defaults to this machine's time zone
+    pub utc_offset_minutes: Option<i32>,
+    /// Holidays and vacations skipped entirely, e.g.
+    /// `["2024-12-25", { from = "2024-08-05", to = "2024-08-16" }]`
+    pub exclusions: Vec<Exclusion>,
+}
+
+/// A day or an inclusive range of days off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Exclusion {
+    Day(NaiveDate),
+    Range {
+        from: NaiveDate,
+        to: NaiveDate,
+        /// Free-form label, e.g. "Summer vacation"
+        #[serde(default)]
+        name: Option<String>,
+    },
+}
+
+impl Exclusion {
+    fn days(&self) -> (NaiveDate, NaiveDate) {
+        match self {
+            Exclusion::Day(day) => (*day, *day),
+            Exclusion::Range { from, to, .. } => (*from, *to),
+        }
+    }
+}
+
+impl Default for WorkingHoursConfig {
+    fn default() -> Self {
+        Self {
+            start: "09:00".to_string(),
+            end: "18:00".to_string(),
+            days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+            utc_offset_minutes: None,
+            exclusions: Vec::new(),
+        }
+    }
+}
+
+impl WorkingHoursConfig {
+    pub fn resolve(&self) -> Result<WorkingHours> {
+        let parse = |value: &str| {
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .map_err(|_| anyhow!("Invalid working hours time '{}' (expected HH:MM)", value))
+        };
+        let (start, end) = (parse(&self.start)?, parse(&self.end)?);
+        if start == end {
+            return Err(anyhow!("Working hours start and end are both {}", self.start));
+        }
+        if self.days.is_empty() {
+            return Err(anyhow!("No working days configured"));
+        }
+        if let Some((from, to)) = self.exclusions.iter().map(Exclusion::days).find(|(from, to)| from > to) {
+            return Err(anyhow!("Working hours exclusion from {} ends before it starts ({})", from, to));
+        }
+
+        Ok(WorkingHours {
+            start,
+            end,
+            days: self
+                .days
+                .iter()
+                .fold(0, |days, day| days | 1 << day.num_days_from_sunday()),
+            utc_offset_minutes: self.utc_offset_minutes,
+            excluded: self.exclusions.iter().map(Exclusion::days).collect(),
+        })
+    }
+
+    /// Excluded days between `since` and `until` inclusive, in order.
+    pub fn excluded_days(&self, since: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let mut days: Vec<_> = self
+            .exclusions
+            .iter()
+            .map(Exclusion::days)
+            .flat_map(|(from, to)| from.max(since).iter_days().take_while(move |day| *day <= to.min(until)))
+            .collect();
+        days.sort();
+        days.dedup();
+        days
+    }
+}
+
+/// A validated `WorkingHoursConfig`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    /// Bit n is weekday n counted from Sunday, as in SQLite's `%w`
+    days: u8,
+    utc_offset_minutes: Option<i32>,
+    /// Inclusive ranges of local dates to skip
+    excluded: Vec<(NaiveDate, NaiveDate)>,
+}
+
+impl WorkingHours {
+    fn to_sql(&self) -> SqlFilter {
+        let modifier = match self.utc_offset_minutes {
+            Some(minutes) => format!("{:+} minutes", minutes),
+            None => "localtime".to_string(),
+        };
+        let days = (0..7)
+            .filter(|day| self.days & (1 << day) != 0)
+            .map(|day| day.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        // A shift past midnight is the hours after start or before end
+        let join = if self.start < self.end { "AND" } else { "OR" };
+
+        let mut clause = format!(
+            "(CAST(strftime('%w', timestamp, ?) AS INTEGER) IN ({}) \
+             AND (strftime('%H:%M:%S', timestamp, ?) >= ? {} strftime('%H:%M:%S', timestamp, ?) < ?))",
+            days, join
+        );
+        let mut binds = vec![
+            FilterValue::Text(modifier.clone()),
+            FilterValue::Text(modifier.clone()),
+            FilterValue::Text(self.start.format("%H:%M:%S").to_string()),
+            FilterValue::Text(modifier.clone()),
+            FilterValue::Text(self.end.format("%H:%M:%S").to_string()),
+        ];
+        for (from, to) in &self.excluded {
+            clause.push_str(" AND date(timestamp, ?) NOT BETWEEN ? AND ?");
+            binds.push(FilterValue::Text(modifier.clone()));
+            binds.push(FilterValue::Text(from.to_string()));
+            binds.push(FilterValue::Text(to.to_string()));
+        }
+
+        SqlFilter {
+            clause: format!("({})", clause),
+            binds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_past_the_representable_range_are_errors() {
+        let now = Utc::now();
+        assert!(parse_time_expr("999999999d", now).is_err());
+        assert!(parse_time_expr("99999999d", now).is_err());
+        assert_eq!(TimeRange::last(Duration::MAX).since, Some(DateTime::<Utc>::MIN_UTC));
+    }
+
+    #[test]
+    fn parses_relative_and_absolute_times() {
+        let now = DateTime::parse_from_rfc3339("2024-06-10T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(parse_time_expr("now", now).unwrap(), now);
+        assert_eq!(parse_time_expr(" 30m ", now).unwrap(), now - Duration::minutes(30));
+        assert_eq!(parse_time_expr("2w", now).unwrap(), now - Duration::weeks(2));
+        assert_eq!(
+            parse_time_expr("2024-06-01T08:30:00+02:00", now).unwrap().to_rfc3339(),
+            "2024-06-01T06:30:00+00:00"
+        );
+        assert_eq!(parse_time_expr("2024-06-01", now).unwrap().to_rfc3339(), "2024-06-01T00:00:00+00:00");
+        assert!(parse_time_expr("yesterday", now).is_err());
+        assert!(parse_time_expr("-5m", now).is_err());
+
+        assert_eq!(parse_duration("45s"), Some(Duration::seconds(45)));
+        assert_eq!(parse_duration("3x"), None);
+        assert_eq!(parse_duration("h"), None);
+    }
+
+    #[test]
+    fn ranges_must_run_forwards() {
+        let range = TimeRange::parse(Some("2d"), Some("1d")).unwrap();
+        assert!(range.since < range.until);
+        assert!(TimeRange::parse(Some("1d"), Some("2d")).is_err());
+        assert_eq!(TimeRange::parse(None, None).unwrap(), TimeRange::default());
+    }
+
+    #[test]
+    fn sql_always_drops_deleted_events_and_test_data_unless_asked() {
+        let range = TimeRange::parse(Some("2024-06-01"), None).unwrap();
+        let sql = range.to_sql();
+        assert_eq!(
+            sql.clause,
+            format!("timestamp >= ? AND NOT {} AND deleted_at IS NULL", TEST_DATA_SQL)
+        );
+        assert_eq!(sql.binds, vec![FilterValue::Text("2024-06-01T00:00:00+00:00".to_string())]);
+
+        let sql = range.with_test_data().to_sql();
+        assert_eq!(sql.clause, "timestamp >= ? AND deleted_at IS NULL");
+        assert_eq!(TimeRange::default().bounds_sql().clause, "1 = 1");
+    }
+
+    #[test]
+    fn open_ends_are_clamped_when_measuring_the_window() {
+        let until = Utc::now();
+        let range = TimeRange {
+            until: Some(until),
+            ..TimeRange::default()
+        };
+        assert_eq!(range.seconds(Some(until - Duration::minutes(2))), 120.0);
+        // Never shorter than a second, so rates stay finite
+        assert_eq!(range.seconds(None), 1.0);
+    }
+
+    #[test]
+    fn working_hours_validate_their_settings() {
+        let config = WorkingHoursConfig::default();
+        assert!(config.resolve().is_ok());
+        let invalid = [
+            WorkingHoursConfig {
+                start: "9am".to_string(),
+                ..WorkingHoursConfig::default()
+            },
+            WorkingHoursConfig {
+                end: "09:00".to_string(),
+                ..WorkingHoursConfig::default()
+            },
+            WorkingHoursConfig {
+                days: Vec::new(),
+                ..WorkingHoursConfig::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.resolve().is_err());
+        }
+    }
+
+    #[test]
+    fn excluded_days_are_clipped_to_the_range() {
+        let day = |value: &str| NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap();
+        let config = WorkingHoursConfig {
+            exclusions: vec![
+                Exclusion::Day(day("2024-12-25")),
+                Exclusion::Range {
+                    from: day("2024-12-24"),
+                    to: day("2025-01-02"),
+                    name: None,
+                },
+            ],
+            ..WorkingHoursConfig::default()
+        };
+        assert_eq!(
+            config.excluded_days(day("2024-12-30"), day("2025-01-01")),
+            vec![day("2024-12-30"), day("2024-12-31"), day("2025-01-01")]
+        );
+        assert_eq!(config.excluded_days(day("2024-12-25"), day("2024-12-25")), vec![day("2024-12-25")]);
+    }
+
+    #[test]
+    fn shifts_past_midnight_join_their_hours_with_or() {
+        let config = WorkingHoursConfig {
+            start: "22:00".to_string(),
+            end: "06:00".to_string(),
+            utc_offset_minutes: Some(60),
+            ..WorkingHoursConfig::default()
+        };
+        let sql = config.resolve().unwrap().to_sql();
+        assert!(sql.clause.contains("IN (1, 2, 3, 4, 5)"));
+        assert!(sql.clause.contains(">= ? OR strftime"));
+        assert_eq!(sql.binds[0], FilterValue::Text("+60 minutes".to_string()));
+        assert_eq!(sql.binds[2], FilterValue::Text("22:00:00".to_string()));
+    }
+}
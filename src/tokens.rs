@@ -0,0 +1,114 @@
+/// Estimated token count of model output, for responses whose API reports
+/// none and to compare providers on the same scale.
+///
+/// Mimics how BPE vocabularies split text: common words are one token and
+/// long ones a token per ~4 letters, digits go in groups of up to three,
+/// each punctuation mark is its own token and a space is folded into the
+/// word after it. Letters outside ASCII (CJK, mostly) count one each.
+pub fn estimate_tokens(text: &str) -> u32 {
+    let mut tokens = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_alphabetic() {
+            let mut letters: u32 = 1;
+            while chars.next_if(char::is_ascii_alphabetic).is_some() {
+                letters += 1;
+            }
+            tokens += letters.div_ceil(4).max(1);
+        } else if c.is_ascii_digit() {
+            let mut digits: u32 = 1;
+            while chars.next_if(char::is_ascii_digit).is_some() {
+                digits += 1;
+            }
+            tokens += digits.div_ceil(3);
+        } else if c == '\n' {
+            // A run of blank lines is one token
+            while chars.next_if(|&next| next == '\n').is_some() {}
+            tokens += 1;
+        } else if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens
+}
+
+/// Characters of prompt text in an OpenAI or Anthropic request body:
+/// `messages` content, `system`, `prompt` and `input`, leaving out images
+/// and other non-text parts.
+pub fn prompt_length(request: &serde_json::Value) -> usize {
+    fn text(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::String(text) => text.chars().count(),
+            serde_json::Value::Array(items) => items.iter().map(text).sum(),
+            serde_json::Value::Object(fields) => ["content", "text"]
+                .iter()
+                .filter_map(|key| fields.get(*key))
+                .map(text)
+                .sum(),
+            _ => 0,
+        }
+    }
+    ["messages", "system", "prompt", "input"]
+        .iter()
+        .filter_map(|key| request.get(*key))
+        .map(text)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn words_count_a_token_per_four_letters() {
+        assert_eq!(estimate_tokens("the cat sat"), 3);
+        assert_eq!(estimate_tokens("hello world"), 4);
+        assert_eq!(estimate_tokens("internationalization"), 5);
+        assert_eq!(estimate_tokens("a"), 1);
+        assert_eq!(estimate_tokens(""), 0);
+    }
+
+    #[test]
+    fn digits_newlines_and_punctuation() {
+        assert_eq!(estimate_tokens("12345"), 2);
+        assert_eq!(estimate_tokens("a\n\n\nb"), 3);
+        assert_eq!(estimate_tokens("Hi, there!"), 5);
+        assert_eq!(estimate_tokens("x == 1;"), 5);
+    }
+
+    #[test]
+    fn cjk_counts_one_token_per_character() {
+        assert_eq!(estimate_tokens("你好世界"), 4);
+        assert_eq!(estimate_tokens("東京 tower"), 4);
+    }
+
+    #[test]
+    fn prompt_length_of_openai_requests() {
+        let chat = json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "user", "content": "Hello" },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "abc" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } }
+                ] }
+            ]
+        });
+        assert_eq!(prompt_length(&chat), 8);
+        assert_eq!(prompt_length(&json!({ "prompt": "def main" })), 8);
+        assert_eq!(prompt_length(&json!({ "input": ["one", "two"] })), 6);
+    }
+
+    #[test]
+    fn prompt_length_of_anthropic_requests() {
+        let request = json!({
+            "model": "claude",
+            "max_tokens": 100,
+            "system": "Be brief",
+            "messages": [{ "role": "user", "content": [{ "type": "text", "text": "Hi" }] }]
+        });
+        assert_eq!(prompt_length(&request), 10);
+        assert_eq!(prompt_length(&json!({ "model": "claude" })), 0);
+    }
+}
@@ -0,0 +1,535 @@
+use anyhow::{anyhow, Result};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Sparkline, Table};
+use ratatui::{Frame, Terminal};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Stdout;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::ipc;
+use crate::models::{ComponentType, LatencyEvent};
+use crate::storage;
+use crate::units;
+use crate::watch::{self, SLOW_MS, VERY_SLOW_MS};
+
+/// Latencies per component the percentiles are computed over.
+const WINDOW_EVENTS: usize = 1000;
+/// Events kept for the log pane.
+const LOG_EVENTS: usize = 500;
+/// Seconds shown in each component's trend and the selected sparkline.
+const HISTORY_SECS: usize = 300;
+/// Seconds of history in the table's inline trend column.
+const TREND_SECS: usize = 30;
+/// A process not sampled for this long leaves the process table.
+const PROCESS_TTL: Duration = Duration::from_secs(60);
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Where `tui` gets its events from.
+pub enum TuiSource {
+    /// The running monitor's IPC socket, as `watch` uses
+    Ipc(std::path::PathBuf),
+    /// A dashboard's `/api/events`, polled every refresh
+    Api { base_url: String, token: Option<String> },
+}
+
+impl TuiSource {
+    fn describe(&self) -> String {
+        match self {
+            TuiSource::Ipc(socket) => format!("ipc {}", socket.display()),
+            TuiSource::Api { base_url, .. } => base_url.clone(),
+        }
+    }
+}
+
+/// What `tui` shows.
+#[derive(Debug, Clone)]
+pub struct TuiOptions {
+    /// Only these components (all when empty)
+    pub components: Vec<ComponentType>,
+    pub refresh: Duration,
+}
+
+/// What the feed task passes to the screen.
+enum Feed {
+    Event(Box<LatencyEvent>),
+    /// Events the source dropped because the screen fell behind
+    Lagged(u64),
+    Error(String),
+}
+
+/// Full-screen live view until the user quits: latency per component with
+/// sparkline histories, a scrolling event log and the processes the
+/// monitor samples.
+pub async fn run(source: TuiSource, options: TuiOptions) -> Result<()> {
+    let (sender, mut feed) = mpsc::channel(4096);
+    match &source {
+        TuiSource::Ipc(socket) => {
+            // Connect before taking over the screen, so a monitor that isn't
+            // running is reported like `watch` does
+            let stream = connect(socket).await?;
+            let components = options.components.clone();
+            tokio::spawn(async move {
+                if let Err(e) = feed_from_ipc(stream, &components, &sender).await {
+                    let _ = sender.send(Feed::Error(e.to_string())).await;
+                }
+            });
+        }
+        TuiSource::Api { base_url, token } => {
+            let base_url = base_url.trim_end_matches('/').to_string();
+            let (token, refresh) = (token.clone(), options.refresh);
+            tokio::spawn(async move { feed_from_api(&base_url, token.as_deref(), refresh, &sender).await });
+        }
+    }
+
+    let mut terminal = Screen::enter()?;
+    let mut app = App::new(source.describe(), options.components.clone());
+    let mut ticker = tokio::time::interval(options.refresh);
+    loop {
+        tokio::select! {
+            Some(update) = feed.recv() => app.apply(update),
+            _ = ticker.tick() => {
+                if !app.handle_keys()? {
+                    break;
+                }
+                app.advance();
+                terminal.0.draw(|frame| app.draw(frame))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn connect(socket: &Path) -> Result<impl AsyncRead + AsyncWrite> {
+    ipc::connect(socket).await.map_err(|e| {
+        anyhow!(
+            "Can't connect to the monitor at {} ({}); is it running with IPC enabled?",
+            socket.display(),
+            e
+        )
+    })
+}
+
+async fn feed_from_ipc(
+    stream: impl AsyncRead + AsyncWrite,
+    components: &[ComponentType],
+    sender: &mpsc::Sender<Feed>,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let subscribe = json!({ "type": "subscribe", "components": components });
+    writer.write_all(format!("{}\n", subscribe).as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        let update = match value.get("lagged") {
+            Some(skipped) => Feed::Lagged(skipped.as_u64().unwrap_or_default()),
+            None => Feed::Event(Box::new(serde_json::from_value(value)?)),
+        };
+        if sender.send(update).await.is_err() {
+            return Ok(());
+        }
+    }
+    Err(anyhow!("Monitor closed the connection"))
+}
+
+/// Poll the dashboard's recent events, passing on those newer than the last
+/// poll. Only the 50 most recent are returned, so a burst between two polls
+/// may be partly missed.
+async fn feed_from_api(base_url: &str, token: Option<&str>, refresh: Duration, sender: &mpsc::Sender<Feed>) {
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval(refresh.max(Duration::from_millis(250)));
+    let mut last_id = None;
+    loop {
+        ticker.tick().await;
+        let mut request = client.get(format!("{}/api/events", base_url));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let polled = async {
+            let response = request.send().await?.error_for_status()?;
+            anyhow::Ok(response.json::<Vec<LatencyEvent>>().await?)
+        };
+        let update = match polled.await {
+            Ok(mut recent) => {
+                let after = last_id.unwrap_or(i64::MIN);
+                recent.retain(|event| event.id.is_some_and(|id| id > after));
+                recent.sort_by_key(|event| event.id);
+                for event in recent {
+                    last_id = event.id;
+                    if sender.send(Feed::Event(Box::new(event))).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+            Err(e) => Feed::Error(format!("{}: {}", base_url, e)),
+        };
+        if sender.send(update).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped,
+/// including when the TUI fails.
+struct Screen(Terminal<CrosstermBackend<Stdout>>);
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        let mut stdout = std::io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        Ok(Self(Terminal::new(CrosstermBackend::new(stdout))?))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(self.0.backend_mut(), LeaveAlternateScreen);
+        let _ = self.0.show_cursor();
+    }
+}
+
+#[derive(Default)]
+struct ComponentStats {
+    events: u64,
+    last_us: u64,
+    max_us: u64,
+    /// Most recent latencies, for percentiles
+    window_us: VecDeque<u64>,
+    /// Slowest latency per second, oldest first, ending with the current
+    /// second
+    history_us: VecDeque<u64>,
+}
+
+struct ProcessRow {
+    name: String,
+    component: ComponentType,
+    cpu_percent: Option<f64>,
+    memory_mb: Option<f64>,
+    seen: Instant,
+}
+
+struct App {
+    source: String,
+    components: Vec<ComponentType>,
+    stats: BTreeMap<&'static str, (ComponentType, ComponentStats)>,
+    log: VecDeque<LatencyEvent>,
+    processes: HashMap<u64, ProcessRow>,
+    selected: usize,
+    paused: bool,
+    started: Instant,
+    elapsed_secs: u64,
+    total: u64,
+    skipped: u64,
+    error: Option<String>,
+}
+
+impl App {
+    fn new(source: String, components: Vec<ComponentType>) -> Self {
+        Self {
+            source,
+            components,
+            stats: BTreeMap::new(),
+            log: VecDeque::new(),
+            processes: HashMap::new(),
+            selected: 0,
+            paused: false,
+            started: Instant::now(),
+            elapsed_secs: 0,
+            total: 0,
+            skipped: 0,
+            error: None,
+        }
+    }
+
+    fn apply(&mut self, update: Feed) {
+        match update {
+            Feed::Event(event) => self.record(*event),
+            Feed::Lagged(skipped) => self.skipped += skipped,
+            Feed::Error(error) => self.error = Some(error),
+        }
+    }
+
+    fn record(&mut self, event: LatencyEvent) {
+        if !self.components.is_empty() && !self.components.contains(&event.component_type) {
+            return;
+        }
+        self.total += 1;
+        self.error = None;
+
+        let duration_us = event.duration_us();
+        let (_, stats) = self
+            .stats
+            .entry(event.component_type.as_str())
+            .or_insert_with(|| (event.component_type, ComponentStats::default()));
+        stats.events += 1;
+        stats.last_us = duration_us;
+        stats.max_us = stats.max_us.max(duration_us);
+        stats.window_us.push_back(duration_us);
+        if stats.window_us.len() > WINDOW_EVENTS {
+            stats.window_us.pop_front();
+        }
+        if stats.history_us.is_empty() {
+            stats.history_us.push_back(0);
+        }
+        if let Some(current) = stats.history_us.back_mut() {
+            *current = (*current).max(duration_us);
+        }
+
+        if let Some(pid) = event.metadata.get("pid").and_then(|pid| pid.as_u64()) {
+            let number = |key: &str| event.metadata.get(key).and_then(|value| value.as_f64());
+            self.processes.insert(
+                pid,
+                ProcessRow {
+                    name: event
+                        .metadata
+                        .get("process_name")
+                        .and_then(|name| name.as_str())
+                        .unwrap_or("?")
+                        .to_string(),
+                    component: event.component_type,
+                    cpu_percent: number("cpu_percent"),
+                    memory_mb: number("memory_mb"),
+                    seen: Instant::now(),
+                },
+            );
+        }
+
+        if !self.paused {
+            self.log.push_back(event);
+            if self.log.len() > LOG_EVENTS {
+                self.log.pop_front();
+            }
+        }
+    }
+
+    /// Start a new second in every history for each second gone by, and
+    /// forget processes that stopped being sampled.
+    fn advance(&mut self) {
+        let now = self.started.elapsed().as_secs();
+        while self.elapsed_secs < now {
+            self.elapsed_secs += 1;
+            for (_, stats) in self.stats.values_mut() {
+                stats.history_us.push_back(0);
+                if stats.history_us.len() > HISTORY_SECS {
+                    stats.history_us.pop_front();
+                }
+            }
+        }
+        self.processes.retain(|_, process| process.seen.elapsed() < PROCESS_TTL);
+    }
+
+    /// Apply pending key presses; `false` once the user quits.
+    fn handle_keys(&mut self) -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let rows = self.stats.len().max(1);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+                KeyCode::Tab | KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1) % rows,
+                KeyCode::BackTab | KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected = (self.selected + rows - 1) % rows
+                }
+                KeyCode::Char('p') | KeyCode::Char(' ') => self.paused = !self.paused,
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [table, sparkline, panes, status] = Layout::vertical([
+            Constraint::Length(self.stats.len().max(1) as u16 + 3),
+            Constraint::Length(7),
+            Constraint::Min(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [log, processes] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(panes);
+
+        self.draw_stats(frame, table);
+        self.draw_sparkline(frame, sparkline);
+        self.draw_log(frame, log);
+        self.draw_processes(frame, processes);
+        self.draw_status(frame, status);
+    }
+
+    fn draw_stats(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["Component", "Events", "Last", "p50", "p95", "p99", "Max", "Last 30s"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.stats.values().enumerate().map(|(index, (component, stats))| {
+            let mut sorted: Vec<u64> = stats.window_us.iter().copied().collect();
+            sorted.sort_unstable();
+            let percentile = |q| latency_cell(storage::percentile(&sorted, q));
+            let row = Row::new([
+                Cell::from(component.as_str()).style(Style::default().fg(component_color(*component))),
+                Cell::from(stats.events.to_string()),
+                latency_cell(stats.last_us),
+                percentile(0.50),
+                percentile(0.95),
+                percentile(0.99),
+                latency_cell(stats.max_us),
+                Cell::from(trend(&stats.history_us, TREND_SECS)),
+            ]);
+            if index == self.selected {
+                row.style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                row
+            }
+        });
+        let widths = [
+            Constraint::Length(16),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Min(TREND_SECS as u16),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(" Latency by component (recent events) "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_sparkline(&self, frame: &mut Frame, area: Rect) {
+        let Some((component, stats)) = self.stats.values().nth(self.selected) else {
+            frame.render_widget(Block::default().borders(Borders::ALL).title(" Waiting for events "), area);
+            return;
+        };
+        // Newest on the right, as many seconds as fit
+        let width = area.width.saturating_sub(2) as usize;
+        let skip = stats.history_us.len().saturating_sub(width);
+        let history: Vec<u64> = stats.history_us.iter().skip(skip).copied().collect();
+        let peak = history.iter().copied().max().unwrap_or_default();
+        let title = format!(
+            " {} slowest per second, last {}s (peak {}) ",
+            component.as_str(),
+            history.len(),
+            units::format_duration(Duration::from_micros(peak))
+        );
+        let sparkline = Sparkline::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .data(&history)
+            .style(Style::default().fg(component_color(*component)));
+        frame.render_widget(sparkline, area);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(visible))
+            .map(|event| {
+                let time = event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S%.3f");
+                ListItem::new(Line::from(vec![
+                    Span::styled(time.to_string(), Style::default().add_modifier(Modifier::DIM)),
+                    Span::raw("  "),
+                    Span::styled(
+                        format!("{:<15}", event.component_type.as_str()),
+                        Style::default().fg(component_color(event.component_type)),
+                    ),
+                    Span::raw("  "),
+                    Span::styled(
+                        format!("{:>9}", units::format_duration(event.duration)),
+                        latency_style(event.duration_us()),
+                    ),
+                    Span::raw("  "),
+                    Span::raw(event.description.clone()),
+                ]))
+            })
+            .collect();
+        let title = if self.paused { " Events (paused) " } else { " Events " };
+        frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title(title)), area);
+    }
+
+    fn draw_processes(&self, frame: &mut Frame, area: Rect) {
+        let mut processes: Vec<(&u64, &ProcessRow)> = self.processes.iter().collect();
+        processes.sort_by(|a, b| b.1.cpu_percent.unwrap_or_default().total_cmp(&a.1.cpu_percent.unwrap_or_default()));
+        let rows = processes.into_iter().map(|(pid, process)| {
+            Row::new([
+                Cell::from(pid.to_string()),
+                Cell::from(process.name.clone()).style(Style::default().fg(component_color(process.component))),
+                Cell::from(process.cpu_percent.map(units::format_percent).unwrap_or_default()),
+                Cell::from(process.memory_mb.map(|mb| format!("{:.0} MB", mb)).unwrap_or_default()),
+            ])
+        });
+        let widths = [Constraint::Length(8), Constraint::Min(12), Constraint::Length(7), Constraint::Length(9)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["PID", "Process", "CPU", "Memory"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(Block::default().borders(Borders::ALL).title(" Processes "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let mut spans = vec![Span::raw(format!(" {}  {} events", self.source, self.total))];
+        if self.skipped > 0 {
+            spans.push(Span::styled(format!("  {} skipped", self.skipped), Style::default().fg(Color::Yellow)));
+        }
+        if let Some(error) = &self.error {
+            spans.push(Span::styled(format!("  {}", error), Style::default().fg(Color::Red)));
+        }
+        spans.push(Span::styled(
+            "  q quit  tab/↑↓ component  p pause log",
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+}
+
+fn latency_cell(us: u64) -> Cell<'static> {
+    Cell::from(units::format_duration(Duration::from_micros(us))).style(latency_style(us))
+}
+
+/// Yellow and red at the same thresholds `watch` uses.
+fn latency_style(us: u64) -> Style {
+    let ms = us as f64 / 1000.0;
+    if ms >= VERY_SLOW_MS {
+        Style::default().fg(Color::Red)
+    } else if ms >= SLOW_MS {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    }
+}
+
+/// `watch`'s ANSI color for the component, as a palette color.
+fn component_color(component: ComponentType) -> Color {
+    match watch::component_color(component) {
+        code @ 30..=37 => Color::Indexed(code - 30),
+        code @ 90..=97 => Color::Indexed(code - 82),
+        _ => Color::Reset,
+    }
+}
+
+/// The last `seconds` of a history as block characters scaled to its peak.
+fn trend(history_us: &VecDeque<u64>, seconds: usize) -> String {
+    let recent: Vec<u64> = history_us.iter().skip(history_us.len().saturating_sub(seconds)).copied().collect();
+    let peak = recent.iter().copied().max().unwrap_or_default().max(1);
+    recent
+        .iter()
+        .map(|&us| if us == 0 { ' ' } else { BARS[((us * 7) / peak) as usize] })
+        .collect()
+}
@@ -0,0 +1,126 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Query, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tracing::warn;
+
+/// `?units=true` on an API request: add a human-readable string next to
+/// every duration and percentage in the response.
+#[derive(Debug, Default, Deserialize)]
+pub struct UnitsQuery {
+    #[serde(default)]
+    pub units: bool,
+}
+
+/// Milliseconds scaled to the most readable unit: `850 µs`, `42.5 ms`,
+/// `1.25 s`.
+pub fn format_ms(ms: f64) -> String {
+    if ms.abs() < 1.0 && ms != 0.0 {
+        format!("{:.0} µs", ms * 1000.0)
+    } else if ms.abs() < 1000.0 {
+        format!("{:.1} ms", ms)
+    } else {
+        format!("{:.2} s", ms / 1000.0)
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    format_ms(duration.as_secs_f64() * 1000.0)
+}
+
+pub fn format_percent(percent: f64) -> String {
+    format!("{:.1}%", percent)
+}
+
+/// Add a `<name>_formatted` string beside each value with a unit, anywhere
+/// in `value`:
+///
+/// - `<name>_ms` numbers, e.g. `p95_duration_ms` gets `p95_duration_formatted`
+/// - `<name>_percent` numbers
+/// - `duration` objects (`{secs, nanos}`), which get `duration_formatted`
+///
+/// Event `metadata` is left as it was recorded.
+pub fn annotate(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(annotate),
+        Value::Object(object) => annotate_object(object),
+        _ => {}
+    }
+}
+
+fn annotate_object(object: &mut Map<String, Value>) {
+    let mut formatted = Vec::new();
+    for (key, value) in object.iter_mut() {
+        if key == "metadata" {
+            continue;
+        }
+        if let Some(text) = format_field(key, value) {
+            let name = key.strip_suffix("_ms").or_else(|| key.strip_suffix("_percent")).unwrap_or(key);
+            formatted.push((format!("{}_formatted", name), Value::String(text)));
+        } else {
+            annotate(value);
+        }
+    }
+    object.extend(formatted);
+}
+
+fn format_field(key: &str, value: &Value) -> Option<String> {
+    if key == "duration" {
+        let secs = value.get("secs")?.as_u64()?;
+        let nanos = value.get("nanos")?.as_u64()?;
+        return Some(format_duration(Duration::new(secs, nanos as u32)));
+    }
+    let number = value.as_f64()?;
+    if key.ends_with("_ms") {
+        Some(format_ms(number))
+    } else if key.ends_with("_percent") {
+        Some(format_percent(number))
+    } else {
+        None
+    }
+}
+
+/// Middleware for JSON APIs: when the request has `?units=true`, annotate
+/// the response body with [`annotate`]. Raw values are always kept, so
+/// clients can opt in without changing how they read numbers.
+pub async fn formatted_units(request: Request, next: Next) -> Response {
+    let requested = Query::<UnitsQuery>::try_from_uri(request.uri()).is_ok_and(|Query(query)| query.units);
+    if !requested {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to buffer response for unit formatting: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(mut value) => {
+            annotate(&mut value);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            serde_json::to_vec(&value).map(Body::from).unwrap_or_else(|_| Body::from(bytes))
+        }
+        Err(_) => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::io::IsTerminal;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::ipc;
+use crate::models::{ComponentType, LatencyEvent};
+use crate::units;
+
+/// Durations at or above these are printed yellow and red.
+pub(crate) const SLOW_MS: f64 = 100.0;
+pub(crate) const VERY_SLOW_MS: f64 = 1000.0;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+
+/// What `watch` prints.
+#[derive(Debug, Clone, Default)]
+pub struct WatchOptions {
+    /// Only these components (all when empty)
+    pub components: Vec<ComponentType>,
+    pub min_duration_ms: Option<f64>,
+    /// Print each event as a JSON line instead of a formatted row
+    pub json: bool,
+    /// Colorize rows; off when stdout isn't a terminal
+    pub color: bool,
+}
+
+impl WatchOptions {
+    /// Parse `--component` values, accepting any spelling
+    /// [`ComponentType::parse`] does.
+    pub fn with_components(mut self, names: &[String]) -> Result<Self> {
+        for name in names {
+            let component = ComponentType::parse(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown component '{}' (expected one of: {})",
+                    name,
+                    ComponentType::ALL.map(|c| c.as_str()).join(", ")
+                )
+            })?;
+            self.components.push(component);
+        }
+        Ok(self)
+    }
+}
+
+/// Stream events stored by the running monitor to stdout until it stops or
+/// the user interrupts, like `tail -f`. Connects to the monitor's IPC socket,
+/// so it works against a daemon without opening the dashboard.
+pub async fn watch(socket: &Path, mut options: WatchOptions) -> Result<()> {
+    options.color &= std::io::stdout().is_terminal();
+    let stream = ipc::connect(socket).await.map_err(|e| {
+        anyhow!(
+            "Can't connect to the monitor at {} ({}); is it running with IPC enabled?",
+            socket.display(),
+            e
+        )
+    })?;
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let subscribe = json!({
+        "type": "subscribe",
+        "components": options.components,
+        "min_duration_ms": options.min_duration_ms,
+    });
+    writer.write_all(format!("{}\n", subscribe).as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if options.json {
+            println!("{}", line);
+            continue;
+        }
+
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        if let Some(skipped) = value.get("lagged") {
+            eprintln!("... {} events skipped (watch fell behind)", skipped);
+            continue;
+        }
+        println!("{}", format_event(&serde_json::from_value(value)?, options.color));
+    }
+
+    eprintln!("Monitor closed the connection");
+    Ok(())
+}
+
+/// One terminal row: local time, component, duration and description.
+pub fn format_event(event: &LatencyEvent, color: bool) -> String {
+    let time = event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S%.3f");
+    let duration_ms = event.duration.as_secs_f64() * 1000.0;
+    let duration = format!("{:>9}", units::format_duration(event.duration));
+
+    if !color {
+        return format!("{}  {:<15}  {}  {}", time, event.component_type.as_str(), duration, event.description);
+    }
+
+    let duration_color = if duration_ms >= VERY_SLOW_MS {
+        RED
+    } else if duration_ms >= SLOW_MS {
+        YELLOW
+    } else {
+        ""
+    };
+    format!(
+        "{DIM}{}{RESET}  \x1b[{}m{:<15}{RESET}  {}{}{RESET}  {}",
+        time,
+        component_color(event.component_type),
+        event.component_type.as_str(),
+        duration_color,
+        duration,
+        event.description
+    )
+}
+
+/// ANSI foreground color code per component.
+pub(crate) fn component_color(component: ComponentType) -> u8 {
+    match component {
+        ComponentType::VSCode => 34,
+        ComponentType::VSCodeExtension => 36,
+        ComponentType::GitHubCopilot => 35,
+        ComponentType::LocalModel => 95,
+        ComponentType::CloudModel => 91,
+        ComponentType::Terminal => 32,
+        ComponentType::LanguageServer => 96,
+        ComponentType::FileSystem => 33,
+        ComponentType::Network => 94,
+        ComponentType::System => 90,
+        ComponentType::BuildTool => 93,
+        ComponentType::Editor => 92,
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+use crate::models::{EventKind, WebhookDelivery};
+use crate::secrets::Secrets;
+use crate::storage::MetricsStorage;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-Latency-Monitor-Signature";
+pub const EVENT_HEADER: &str = "X-Latency-Monitor-Event";
+pub const DELIVERY_HEADER: &str = "X-Latency-Monitor-Delivery";
+
+/// Longest wait between attempts, however many retries are configured.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// An HTTP endpoint notified about monitor events. Configured as
+/// `[[webhooks]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    /// Name of the secret (see `secrets.toml`) used to sign payloads; unsigned when unset
+    #[serde(default)]
+    pub signing_secret: Option<String>,
+    /// Event types to send, e.g. `lifecycle.crash_loop` or `report`; all when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on each further attempt
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl WebhookConfig {
+    pub fn subscribes_to(&self, event_type: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event_type)
+    }
+}
+
+/// `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`. Receivers should
+/// recompute the MAC and reject stale timestamps to prevent replays.
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> Result<String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| anyhow!("Invalid webhook secret: {}", e))?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    Ok(format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes())))
+}
+
+/// Deliver one payload, retrying transport errors, 429 and 5xx responses with
+/// exponential backoff. Every attempt is written to the delivery log.
+pub async fn deliver(
+    storage: &MetricsStorage,
+    secrets: &Secrets,
+    webhook: &WebhookConfig,
+    event_type: &str,
+    payload: &serde_json::Value,
+) -> Result<()> {
+    let secret = match &webhook.signing_secret {
+        Some(name) => Some(
+            secrets
+                .get(name)
+                .ok_or_else(|| anyhow!("Missing secret '{}' for webhook '{}'", name, webhook.name))?,
+        ),
+        None => None,
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(webhook.timeout_secs.max(1)))
+        .build()?;
+    let delivery_id = delivery_id();
+    let body = serde_json::to_vec(&json!({
+        "id": delivery_id,
+        "type": event_type,
+        "created_at": Utc::now(),
+        "data": payload,
+    }))?;
+
+    let mut backoff = Duration::from_millis(webhook.initial_backoff_ms);
+    let attempts = webhook.max_attempts.max(1);
+
+    for attempt in 1..=attempts {
+        let mut request = client
+            .post(&webhook.url)
+            .header("content-type", "application/json")
+            .header(EVENT_HEADER, event_type)
+            .header(DELIVERY_HEADER, &delivery_id)
+            .body(body.clone());
+        // Signed per attempt so the timestamp reflects when it was sent
+        if let Some(secret) = &secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, Utc::now().timestamp(), &body)?);
+        }
+
+        let started = Instant::now();
+        let result = request.send().await;
+        let elapsed = started.elapsed();
+
+        let (status, error, retryable) = match &result {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None, false),
+            Ok(response) => {
+                let status = response.status();
+                let retryable = status.is_server_error() || status.as_u16() == 429;
+                (Some(status.as_u16()), Some(format!("HTTP {}", status)), retryable)
+            }
+            Err(e) => (None, Some(e.to_string()), true),
+        };
+
+        storage
+            .record_webhook_delivery(&WebhookDelivery {
+                id: None,
+                delivery_id: delivery_id.clone(),
+                webhook: webhook.name.clone(),
+                event_type: event_type.to_string(),
+                attempt,
+                status_code: status,
+                error: error.clone(),
+                duration_ms: elapsed.as_millis() as u64,
+                attempted_at: Utc::now(),
+            })
+            .await?;
+
+        match error {
+            None => {
+                debug!("Delivered {} to webhook '{}' (attempt {})", event_type, webhook.name, attempt);
+                return Ok(());
+            }
+            Some(error) if !retryable || attempt == attempts => {
+                return Err(anyhow!(
+                    "Webhook '{}' delivery failed after {} attempt(s): {}",
+                    webhook.name,
+                    attempt,
+                    error
+                ));
+            }
+            Some(error) => {
+                warn!(
+                    "Webhook '{}' attempt {} failed ({}); retrying in {:?}",
+                    webhook.name, attempt, error, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a payload to every webhook subscribed to `event_type`. Failures are
+/// logged; one bad endpoint doesn't stop delivery to the others.
+pub async fn broadcast(
+    storage: &MetricsStorage,
+    webhooks: &[WebhookConfig],
+    event_type: &str,
+    payload: &serde_json::Value,
+) -> Result<()> {
+    let targets: Vec<_> = webhooks.iter().filter(|w| w.subscribes_to(event_type)).collect();
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    let secrets = Secrets::load()?;
+    for webhook in targets {
+        if let Err(e) = deliver(storage, &secrets, webhook, event_type, payload).await {
+            warn!("{}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Forward extension host lifecycle events (`lifecycle.<kind>`) as they are
+/// stored.
+pub async fn run_dispatcher(webhooks: Vec<WebhookConfig>, storage: MetricsStorage) {
+    let mut events = storage.subscribe();
+    info!("Dispatching lifecycle events to {} webhook(s)", webhooks.len());
+
+    loop {
+        match events.recv().await {
+            Ok(event) if event.kind == EventKind::Lifecycle => {
+                let Some(lifecycle) = event.metadata["lifecycle"].as_str() else {
+                    continue;
+                };
+                let event_type = format!("lifecycle.{}", lifecycle);
+
+                let (storage, webhooks) = (storage.clone(), webhooks.clone());
+                // Retries can take minutes; don't hold up later events
+                tokio::spawn(async move {
+                    if let Err(e) = broadcast(&storage, &webhooks, &event_type, &json!(event)).await {
+                        warn!("Webhook dispatch failed: {}", e);
+                    }
+                });
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => warn!("Webhook dispatcher skipped {} events", skipped),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+fn delivery_id() -> String {
+    let mut bytes = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
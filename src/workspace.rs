@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use sysinfo::{Pid, System};
+
+/// Switches that open VS Code on a folder or `.code-workspace` file by URI,
+/// as remote folders and launchers do.
+const WORKSPACE_ARGS: &[&str] = &["--folder-uri", "--file-uri"];
+
+/// Extension of multi-root workspace files, left out of their name.
+const WORKSPACE_FILE_EXTENSION: &str = ".code-workspace";
+
+/// The folder or workspace file a VS Code window has open, stored as
+/// `metadata.workspace` (the `workspace` column) and
+/// `metadata.workspace_path`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workspace {
+    /// Last path segment, e.g. `vscode-latency-monitor`; what reports,
+    /// exports and the dashboard filter and group by
+    pub name: String,
+    /// Local path, or the URI of a remote folder
+    pub path: String,
+}
+
+impl Workspace {
+    /// A workspace from a `file://` URI, a remote URI such as
+    /// `vscode-remote://ssh-remote+host/srv/repo`, or a plain path.
+    pub fn parse(location: &str) -> Option<Self> {
+        let location = location.trim();
+        let path = match location.strip_prefix("file://") {
+            // file:///home/me/repo, or file:///c%3A/src/repo on Windows
+            Some(rest) => {
+                let decoded = percent_decode(rest);
+                match decoded.strip_prefix('/') {
+                    Some(drive) if drive.get(1..2) == Some(":") => drive.to_string(),
+                    _ => decoded,
+                }
+            }
+            None => location.to_string(),
+        };
+        let trimmed = path.trim_end_matches(['/', '\\']);
+        let last = trimmed.rsplit(['/', '\\']).next().unwrap_or(trimmed);
+        let name = percent_decode(last.strip_suffix(WORKSPACE_FILE_EXTENSION).unwrap_or(last));
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name,
+            path: trimmed.to_string(),
+        })
+    }
+
+    /// Record the workspace in an event's metadata.
+    pub fn apply(&self, metadata: &mut serde_json::Value) {
+        if !metadata.is_object() {
+            *metadata = serde_json::json!({});
+        }
+        metadata["workspace"] = serde_json::json!(self.name);
+        metadata["workspace_path"] = serde_json::json!(self.path);
+    }
+}
+
+/// Workspace a VS Code process was started on, from `--folder-uri` or
+/// `--file-uri`. Only the main process has them, and only for the window it
+/// was started with; windows opened later are named by their extension
+/// host over IPC instead (see [`Workspaces`]).
+pub fn from_args(args: &[String]) -> Option<Workspace> {
+    args.iter().enumerate().find_map(|(index, arg)| {
+        WORKSPACE_ARGS.iter().find_map(|switch| match arg.strip_prefix(switch) {
+            Some(value) if value.starts_with('=') => Workspace::parse(&value[1..]),
+            Some("") => args
+                .get(index + 1)
+                .filter(|next| !next.starts_with('-'))
+                .and_then(|next| Workspace::parse(next)),
+            _ => None,
+        })
+    })
+}
+
+/// Workspaces companion extensions have announced over IPC, by the process
+/// id of the extension host they run in. Clones share one registry.
+#[derive(Debug, Clone, Default)]
+pub struct Workspaces {
+    by_pid: Arc<Mutex<HashMap<u32, Workspace>>>,
+}
+
+impl Workspaces {
+    /// Record the workspace the extension host `pid` has open, or forget it
+    /// when the window closed its folder. Hosts that have exited are
+    /// forgotten first, since the VS Code collector that also prunes them
+    /// may not be running.
+    pub fn announce(&self, pid: u32, workspace: Option<Workspace>) {
+        let mut by_pid = self.by_pid.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match workspace {
+            Some(workspace) => {
+                let mut system = System::new();
+                by_pid.retain(|pid, _| system.refresh_process(Pid::from_u32(*pid)));
+                by_pid.insert(pid, workspace);
+            }
+            None => {
+                by_pid.remove(&pid);
+            }
+        }
+    }
+
+    pub fn get(&self, pid: u32) -> Option<Workspace> {
+        self.by_pid.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(&pid).cloned()
+    }
+
+    /// Forget extension hosts that have exited, before their ids are reused.
+    pub fn retain(&self, alive: impl Fn(u32) -> bool) {
+        self.by_pid.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).retain(|pid, _| alive(*pid));
+    }
+}
+
+/// Decode `%XX` escapes; malformed ones are kept as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| text.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str, path: &str) -> Option<Workspace> {
+        Some(Workspace {
+            name: name.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    #[test]
+    fn workspaces_are_named_by_their_last_path_segment() {
+        assert_eq!(Workspace::parse("/home/me/repo/"), workspace("repo", "/home/me/repo"));
+        assert_eq!(Workspace::parse("file:///home/me/my%20repo"), workspace("my repo", "/home/me/my repo"));
+        assert_eq!(Workspace::parse("file:///c%3A/src/repo"), workspace("repo", "c:/src/repo"));
+        assert_eq!(Workspace::parse(r"C:\src\app.code-workspace"), workspace("app", r"C:\src\app.code-workspace"));
+        assert_eq!(
+            Workspace::parse("vscode-remote://ssh-remote%2Bhost/srv/api"),
+            workspace("api", "vscode-remote://ssh-remote%2Bhost/srv/api")
+        );
+        assert_eq!(Workspace::parse("  "), None);
+        assert_eq!(Workspace::parse("file:///"), None);
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(percent_decode("100%25"), "100%");
+        assert_eq!(percent_decode("50%"), "50%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+
+    #[test]
+    fn workspace_comes_from_either_form_of_the_switch() {
+        let args = |line: &str| line.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(from_args(&args("code --folder-uri=file:///srv/web")), workspace("web", "/srv/web"));
+        assert_eq!(
+            from_args(&args("code --file-uri file:///srv/all.code-workspace")),
+            workspace("all", "/srv/all.code-workspace")
+        );
+        assert_eq!(from_args(&args("code --folder-uri --new-window")), None);
+        assert_eq!(from_args(&args("code --folder-urix=/srv/web /srv/other")), None);
+    }
+
+    #[test]
+    fn applying_records_both_fields() {
+        let mut metadata = serde_json::Value::Null;
+        workspace("api", "/srv/api").unwrap().apply(&mut metadata);
+        assert_eq!(metadata, serde_json::json!({"workspace": "api", "workspace_path": "/srv/api"}));
+    }
+
+    #[test]
+    fn announcements_are_kept_per_extension_host() {
+        let workspaces = Workspaces::default();
+        let pid = std::process::id();
+        workspaces.announce(pid, workspace("api", "/srv/api"));
+        assert_eq!(workspaces.clone().get(pid), workspace("api", "/srv/api"));
+
+        workspaces.announce(pid, None);
+        assert_eq!(workspaces.get(pid), None);
+
+        workspaces.announce(pid, workspace("web", "/srv/web"));
+        workspaces.retain(|alive| alive != pid);
+        assert_eq!(workspaces.get(pid), None);
+    }
+}